@@ -32,17 +32,18 @@ fn read_write_bench(
         for i in 0..file_num {
             let file = {
                 let path = format!("tmp.data.{}", i).to_string();
-                let flags = libc::O_RDWR | libc::O_CREAT | libc::O_TRUNC;
+                let flags = libc::O_RDWR | libc::O_CREAT;
                 let mode = libc::S_IRUSR | libc::S_IWUSR;
                 AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
             };
+            file.set_len(0).await.unwrap();
 
             let vec = vec![0; 4096];
             let buf = vec.into_boxed_slice();
 
             let mut offset = 0;
             while offset < file_size {
-                let retval = file.write_at(offset, &buf[..]).await.unwrap();
+                let retval = file.write_exact_at(offset, &buf[..]).await.unwrap();
                 offset += retval;
             }
 
@@ -83,11 +84,13 @@ fn read_write_bench(
                             let mut offset = 0;
                             while offset < file_size {
                                 if is_read {
-                                    let nbytes = file.read_at(offset, &mut buf[..]).await.unwrap();
+                                    let nbytes =
+                                        file.read_exact_at(offset, &mut buf[..]).await.unwrap();
                                     assert!(nbytes > 0);
                                     offset += nbytes as usize;
                                 } else {
-                                    let nbytes = file.write_at(offset, &buf[..]).await.unwrap();
+                                    let nbytes =
+                                        file.write_exact_at(offset, &buf[..]).await.unwrap();
                                     assert!(nbytes > 0);
                                     assert!(nbytes == file_block_size);
                                     offset += nbytes;
@@ -100,11 +103,13 @@ fn read_write_bench(
                                 let offset = (get_random() as usize % block_num) * file_block_size;
 
                                 if is_read {
-                                    let nbytes = file.read_at(offset, &mut buf[..]).await.unwrap();
+                                    let nbytes =
+                                        file.read_exact_at(offset, &mut buf[..]).await.unwrap();
                                     assert!(nbytes > 0);
                                     cnt += nbytes as usize;
                                 } else {
-                                    let nbytes = file.write_at(offset, &buf[..]).await.unwrap();
+                                    let nbytes =
+                                        file.write_exact_at(offset, &buf[..]).await.unwrap();
                                     assert!(nbytes > 0);
                                     cnt += nbytes;
                                 }
@@ -208,7 +213,7 @@ fn init_async_rt() {
         ring.start_enter_syscall_thread();
     }
     let callback = move || {
-        ring.trigger_callbacks();
+        ring.poll_completions();
     };
     async_rt::config::set_sched_callback(callback);
 }
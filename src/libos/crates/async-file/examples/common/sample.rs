@@ -9,17 +9,18 @@ fn read_write_sample() {
 
         let file = {
             let path = format!("tmp.data").to_string();
-            let flags = libc::O_RDWR | libc::O_CREAT | libc::O_TRUNC;
+            let flags = libc::O_RDWR | libc::O_CREAT;
             let mode = libc::S_IRUSR | libc::S_IWUSR;
             AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
         };
+        file.set_len(0).await.unwrap();
 
         let vec = vec![0; block_size];
         let mut buf = vec.into_boxed_slice();
         let mut offset = 0;
         let start = Instant::now();
         while offset < file_size {
-            let nbytes = file.write_at(offset, &buf[..]).await.unwrap();
+            let nbytes = file.write_exact_at(offset, &buf[..]).await.unwrap();
             offset += nbytes;
         }
         let duration = start.elapsed();
@@ -28,7 +29,7 @@ fn read_write_sample() {
         offset = 0;
         let start = Instant::now();
         while offset < file_size {
-            let nbytes = file.read_at(offset, &mut buf[..]).await.unwrap();
+            let nbytes = file.read_exact_at(offset, &mut buf[..]).await.unwrap();
             offset += nbytes as usize;
         }
         let duration = start.elapsed();
@@ -109,7 +110,7 @@ fn init_async_rt() {
         ring.start_enter_syscall_thread();
     }
     let callback = move || {
-        ring.trigger_callbacks();
+        ring.poll_completions();
     };
     async_rt::config::set_sched_callback(callback);
-}
\ No newline at end of file
+}
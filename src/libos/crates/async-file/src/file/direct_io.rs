@@ -0,0 +1,190 @@
+#[cfg(not(feature = "sgx"))]
+use std::alloc::{alloc, dealloc, Layout};
+use std::marker::PhantomData;
+#[cfg(feature = "sgx")]
+use std::prelude::v1::*;
+use std::sync::Arc;
+
+use async_io::prelude::{Result, *};
+use io_uring_callback::Fd;
+#[cfg(feature = "sgx")]
+use sgx_untrusted_alloc::UntrustedAllocator;
+
+use super::{close_fd, libc_errno, AsyncFileRt};
+
+/// The alignment of the offsets, lengths and buffers of direct I/O.
+///
+/// This is the logical block size of most block devices. Just like Linux,
+/// `AsyncFile` fails misaligned direct I/O with `EINVAL`.
+pub const DIRECT_IO_ALIGN: usize = 512;
+/// The maximum number of bytes of a direct I/O, which limits the size of the
+/// bounce buffers. A larger I/O is done partially.
+pub(crate) const MAX_DIRECT_IO_SIZE: usize = 1024 * 1024;
+
+/// The direct I/O of an open file, which bypasses the page cache.
+///
+/// The I/O is done with a host fd that is opened with `O_DIRECT` and owned by
+/// the open. As the host cannot access the buffers of the users under SGX, the
+/// data are copied through aligned bounce buffers in untrusted memory.
+pub(crate) struct DirectIo<Rt: AsyncFileRt + ?Sized> {
+    fd: i32,
+    phantom_data: PhantomData<Rt>,
+}
+
+impl<Rt: AsyncFileRt + ?Sized> DirectIo<Rt> {
+    /// Open a host file for direct I/O.
+    ///
+    /// The file must have been opened, thus created or truncated if needed, with
+    /// the same flags.
+    pub fn open(path: &str, flags: i32) -> Result<Self> {
+        let flags = (flags & !(libc::O_CREAT | libc::O_EXCL | libc::O_TRUNC)) | libc::O_DIRECT;
+        let fd = unsafe {
+            let c_path = std::ffi::CString::new(path).unwrap();
+            let c_path_ptr = c_path.as_bytes_with_nul().as_ptr() as _;
+            #[cfg(not(feature = "sgx"))]
+            let fd = libc::open(c_path_ptr, flags);
+            #[cfg(feature = "sgx")]
+            let fd = libc::ocall::open64(c_path_ptr, flags, 0);
+            fd
+        };
+        if fd < 0 {
+            return_errno!(Errno::from(libc_errno() as u32), "libc::open error");
+        }
+        Ok(Self {
+            fd,
+            phantom_data: PhantomData,
+        })
+    }
+
+    /// Check that the offset, the length and the address of a buffer are all
+    /// aligned for direct I/O.
+    pub fn check_alignment(offset: usize, buf_addr: usize, buf_len: usize) -> Result<()> {
+        if offset % DIRECT_IO_ALIGN != 0
+            || buf_addr % DIRECT_IO_ALIGN != 0
+            || buf_len % DIRECT_IO_ALIGN != 0
+        {
+            return_errno!(EINVAL, "misaligned direct I/O");
+        }
+        Ok(())
+    }
+
+    /// Read from the host file at a given offset, returning the number of bytes
+    /// read, which may be less than the length of the buffer.
+    pub async fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let len = buf.len().min(MAX_DIRECT_IO_SIZE);
+        let bounce_buf = Arc::new(BounceBuf::new(len));
+        let complete_fn = {
+            let bounce_buf = bounce_buf.clone();
+            // The buffer must outlive the I/O, even if the read is cancelled
+            move |_retval: i32| drop(bounce_buf)
+        };
+        let io_uring = Rt::io_uring();
+        let handle = unsafe {
+            io_uring.read(
+                Fd(self.fd),
+                bounce_buf.as_mut_ptr(),
+                len as u32,
+                offset as i64,
+                0,
+                complete_fn,
+            )
+        };
+        let retval = handle.await;
+        if retval < 0 {
+            return_errno!(Errno::from(-retval as u32), "direct read error");
+        }
+
+        let read_nbytes = (retval as usize).min(len);
+        buf[..read_nbytes].copy_from_slice(&bounce_buf.as_slice()[..read_nbytes]);
+        Ok(read_nbytes)
+    }
+
+    /// Write to the host file at a given offset, returning the number of bytes
+    /// written, which may be less than the length of the buffer.
+    pub async fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        let len = buf.len().min(MAX_DIRECT_IO_SIZE);
+        let mut bounce_buf = BounceBuf::new(len);
+        bounce_buf.as_slice_mut().copy_from_slice(&buf[..len]);
+        let buf_ptr = bounce_buf.as_mut_ptr() as *const u8;
+        let complete_fn = move |_retval: i32| drop(bounce_buf);
+        let io_uring = Rt::io_uring();
+        let handle = unsafe {
+            io_uring.write(
+                Fd(self.fd),
+                buf_ptr,
+                len as u32,
+                offset as i64,
+                0,
+                complete_fn,
+            )
+        };
+        let retval = handle.await;
+        if retval < 0 {
+            return_errno!(Errno::from(-retval as u32), "direct write error");
+        }
+        Ok((retval as usize).min(len))
+    }
+}
+
+impl<Rt: AsyncFileRt + ?Sized> Drop for DirectIo<Rt> {
+    fn drop(&mut self) {
+        close_fd(self.fd);
+    }
+}
+
+// A buffer in untrusted memory that is aligned for direct I/O.
+struct BounceBuf {
+    ptr: *mut u8,
+    len: usize,
+    #[cfg(feature = "sgx")]
+    allocator: UntrustedAllocator,
+}
+
+unsafe impl Send for BounceBuf {}
+unsafe impl Sync for BounceBuf {}
+
+impl BounceBuf {
+    fn new(len: usize) -> Self {
+        debug_assert!(len > 0 && len % DIRECT_IO_ALIGN == 0);
+        #[cfg(not(feature = "sgx"))]
+        let ptr = unsafe {
+            let ptr = alloc(Layout::from_size_align_unchecked(len, DIRECT_IO_ALIGN));
+            assert!(!ptr.is_null());
+            ptr
+        };
+        #[cfg(feature = "sgx")]
+        let allocator = UntrustedAllocator::new(len, DIRECT_IO_ALIGN).unwrap();
+        #[cfg(feature = "sgx")]
+        let ptr = allocator.as_mut_ptr();
+        Self {
+            ptr,
+            len,
+            #[cfg(feature = "sgx")]
+            allocator,
+        }
+    }
+
+    fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    fn as_slice_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl Drop for BounceBuf {
+    fn drop(&mut self) {
+        #[cfg(not(feature = "sgx"))]
+        unsafe {
+            dealloc(
+                self.ptr,
+                Layout::from_size_align_unchecked(self.len, DIRECT_IO_ALIGN),
+            );
+        }
+    }
+}
@@ -1,40 +1,218 @@
 use std::marker::PhantomData;
 #[cfg(feature = "sgx")]
 use std::prelude::v1::*;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_io::event::{Events, Pollee};
+use async_io::prelude::*;
+use async_rt::wait::WaiterQueue;
+use async_rt::waiter_loop;
 use futures::future::BoxFuture;
-use io_uring_callback::Fd;
+use io_uring_callback::{Fd, TimeoutFlags, Timespec};
 use itertools::Itertools;
 #[cfg(feature = "sgx")]
 use sgx_untrusted_alloc::UntrustedAllocator;
+use sgx_untrusted_alloc::{MaybeUntrusted, UntrustedBox};
+use spin::RwLock;
 
+use crate::file::inode::Inode;
+use crate::file::writeback::{WritebackConfig, WritebackStats};
 use crate::file::AsyncFileRt;
-use crate::page_cache::{Page, PageHandle, PageState};
+use crate::page_cache::{FileId, Page, PageHandle, PageState};
 
 /// Flush dirty pages in a page cache.
+///
+/// The flusher also runs a writeback daemon, which is started by the first
+/// call to `wake_daemon`. The daemon writes back dirty pages in the background
+/// according to a `WritebackConfig`, either because there are too many dirty
+/// pages, or because they have been dirty for too long. The daemon is woken up
+/// when pages become dirty or writes are throttled, and, while there are dirty
+/// pages, by an io_uring timeout every `writeback_interval` so that the expired
+/// dirty pages are written back even when there are no new writes.
 pub struct Flusher<Rt: AsyncFileRt + ?Sized> {
     pollee: Arc<Pollee>,
+    config: RwLock<WritebackConfig>,
+    daemon_waiters: WaiterQueue,
+    is_daemon_started: AtomicBool,
+    is_expiry_timer_armed: AtomicBool,
+    // The time of the last check for expired dirty pages, in nanoseconds
+    last_expire_check: AtomicU64,
+    background_pages: AtomicU64,
+    expired_pages: AtomicU64,
+    requested_pages: AtomicU64,
+    throttled_writes: AtomicU64,
     phantom_data: PhantomData<Rt>,
 }
 
 impl<Rt: AsyncFileRt + ?Sized> Flusher<Rt> {
     pub fn new() -> Self {
+        Self::with_config(WritebackConfig::default())
+    }
+
+    pub fn with_config(config: WritebackConfig) -> Self {
         Self {
             pollee: Arc::new(Pollee::new(Events::IN | Events::OUT)),
+            config: RwLock::new(config),
+            daemon_waiters: WaiterQueue::new(),
+            is_daemon_started: AtomicBool::new(false),
+            is_expiry_timer_armed: AtomicBool::new(false),
+            last_expire_check: AtomicU64::new(0),
+            background_pages: AtomicU64::new(0),
+            expired_pages: AtomicU64::new(0),
+            requested_pages: AtomicU64::new(0),
+            throttled_writes: AtomicU64::new(0),
             phantom_data: PhantomData,
         }
     }
 
-    pub async fn flush_by_fd(&self, fd: i32, max_pages: usize) -> usize {
-        let dirty_pages = Rt::page_cache().evict_dirty_pages_by_fd(fd, max_pages);
-        self.do_flush(dirty_pages).await
+    pub fn config(&self) -> WritebackConfig {
+        *self.config.read()
+    }
+
+    pub fn set_config(&self, config: WritebackConfig) {
+        *self.config.write() = config;
+    }
+
+    pub fn stats(&self) -> WritebackStats {
+        WritebackStats {
+            num_dirty_pages: Rt::page_cache().num_dirty_pages(),
+            background_pages: self.background_pages.load(Ordering::Relaxed),
+            expired_pages: self.expired_pages.load(Ordering::Relaxed),
+            requested_pages: self.requested_pages.load(Ordering::Relaxed),
+            throttled_writes: self.throttled_writes.load(Ordering::Relaxed),
+        }
+    }
+
+    pub async fn flush_by_id(&self, id: FileId, max_pages: usize) -> usize {
+        let dirty_pages = Rt::page_cache().evict_dirty_pages_by_id(id, max_pages);
+        let num_flushed = self.do_flush(dirty_pages).await;
+        self.requested_pages
+            .fetch_add(num_flushed as u64, Ordering::Relaxed);
+        num_flushed
     }
 
     pub async fn flush(&self, max_pages: usize) -> usize {
         let dirty_pages = Rt::page_cache().evict_dirty_pages(max_pages);
-        self.do_flush(dirty_pages).await
+        let num_flushed = self.do_flush(dirty_pages).await;
+        self.requested_pages
+            .fetch_add(num_flushed as u64, Ordering::Relaxed);
+        num_flushed
+    }
+
+    /// Wake up the writeback daemon if there are dirty pages to write back,
+    /// starting the daemon on the first call.
+    pub fn wake_daemon(&'static self) {
+        if !self.is_daemon_started.swap(true, Ordering::Relaxed) {
+            async_rt::task::spawn(self.run_daemon());
+        }
+        if self.is_over_background_limit() || self.is_time_to_check_expiry() {
+            self.daemon_waiters.wake_all();
+        }
+        self.arm_expiry_timer();
+    }
+
+    // Arm a timer that wakes up the daemon after the writeback interval if
+    // there are dirty pages and no timer is armed yet.
+    fn arm_expiry_timer(&'static self) {
+        if Rt::page_cache().num_dirty_pages() == 0
+            || self.is_expiry_timer_armed.swap(true, Ordering::Relaxed)
+        {
+            return;
+        }
+        let interval = self.config().writeback_interval;
+        let timespec = TimespecBox(UntrustedBox::new(KernelTimespec(Timespec {
+            tv_sec: interval.as_secs() as _,
+            tv_nsec: interval.subsec_nanos() as _,
+        })));
+        let timespec_ptr = &timespec.0 .0 as *const Timespec;
+        let callback = move |_retval: i32| {
+            drop(timespec);
+            self.is_expiry_timer_armed.store(false, Ordering::Relaxed);
+            self.wake_daemon();
+        };
+        unsafe {
+            Rt::io_uring().timeout(timespec_ptr, 0, TimeoutFlags::empty(), callback);
+        }
+    }
+
+    /// Returns whether a write should be throttled, i.e., the dirty pages
+    /// exceed the limit of `WritebackConfig::dirty_bytes`.
+    ///
+    /// The writeback daemon is woken up if so.
+    pub(crate) fn should_throttle(&'static self) -> bool {
+        let num_dirty_bytes = Rt::page_cache().num_dirty_pages() * Page::size();
+        if num_dirty_bytes <= self.config().dirty_bytes {
+            return false;
+        }
+        self.throttled_writes.fetch_add(1, Ordering::Relaxed);
+        self.wake_daemon();
+        true
+    }
+
+    async fn run_daemon(&'static self) {
+        waiter_loop!(&self.daemon_waiters, {
+            self.write_back_over_background_limit().await;
+            self.write_back_expired_pages().await;
+        });
+    }
+
+    async fn write_back_over_background_limit(&self) {
+        if !self.is_over_background_limit() {
+            return;
+        }
+        let config = self.config();
+        let page_cache = Rt::page_cache();
+        while page_cache.num_dirty_pages() * Page::size() > config.dirty_background_bytes / 2 {
+            let dirty_pages = page_cache.evict_dirty_pages(config.max_pages_per_flush);
+            let num_flushed = self.do_flush(dirty_pages).await;
+            if num_flushed == 0 {
+                break;
+            }
+            self.background_pages
+                .fetch_add(num_flushed as u64, Ordering::Relaxed);
+        }
+    }
+
+    async fn write_back_expired_pages(&self) {
+        if !self.is_time_to_check_expiry() {
+            return;
+        }
+        let now = Rt::now();
+        self.last_expire_check
+            .store(now.as_nanos() as u64, Ordering::Relaxed);
+        let config = self.config();
+        let expire_time = match now.checked_sub(config.dirty_expire) {
+            Some(expire_time) => expire_time,
+            None => return,
+        };
+        let page_cache = Rt::page_cache();
+        loop {
+            let dirty_pages =
+                page_cache.evict_expired_dirty_pages(expire_time, config.max_pages_per_flush);
+            let num_flushed = self.do_flush(dirty_pages).await;
+            if num_flushed == 0 {
+                break;
+            }
+            self.expired_pages
+                .fetch_add(num_flushed as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn is_over_background_limit(&self) -> bool {
+        let num_dirty_bytes = Rt::page_cache().num_dirty_pages() * Page::size();
+        num_dirty_bytes > self.config().dirty_background_bytes
+    }
+
+    fn is_time_to_check_expiry(&self) -> bool {
+        if Rt::page_cache().num_dirty_pages() == 0 {
+            return false;
+        }
+        let last_check = Duration::from_nanos(self.last_expire_check.load(Ordering::Relaxed));
+        Rt::now()
+            .checked_sub(last_check)
+            .map_or(false, |elapsed| elapsed >= self.config().writeback_interval)
     }
 
     async fn do_flush(&self, mut dirty_pages: Vec<PageHandle>) -> usize {
@@ -65,27 +243,52 @@ impl<Rt: AsyncFileRt + ?Sized> Flusher<Rt> {
         // Sort the pages so that we can easily merge small writes into larger ones
         dirty_pages.sort_by_key(|page| page.key());
 
-        // Flush the dirty pages one fd at a time
+        // Flush the dirty pages one file at a time
         let mut futures: Vec<BoxFuture<'static, i32>> = Vec::new();
+        let mut protected_files = Vec::new();
         dirty_pages
             .into_iter()
-            .group_by(|page| page.fd())
+            .group_by(|page| page.file_id())
             .into_iter()
-            .for_each(|(fd, dirty_pages_of_a_fd)| {
-                self.flush_dirty_pages_of_a_fd(fd, dirty_pages_of_a_fd, &mut futures);
+            .for_each(|(_, dirty_pages_of_a_file)| {
+                let mut dirty_pages_of_a_file = dirty_pages_of_a_file.peekable();
+                let file = Self::file_of(dirty_pages_of_a_file.peek().unwrap());
+                self.flush_dirty_pages_of_a_file(&file, dirty_pages_of_a_file, &mut futures);
+                if file.protection().is_some() {
+                    protected_files.push(file);
+                }
             });
         for future in futures {
             future.await;
         }
+
+        // The metadata of a protected file is persisted after its pages
+        for file in protected_files {
+            if let Err(e) = file.persist_metadata().await {
+                file.set_writeback_error(e.errno());
+            }
+        }
         num_dirty_pages
     }
 
-    fn flush_dirty_pages_of_a_fd(
+    fn file_of(page: &PageHandle) -> Arc<Inode<Rt>> {
+        page.file()
+            .clone()
+            .downcast::<Inode<Rt>>()
+            .expect("the pages of the page cache must belong to inodes")
+    }
+
+    fn flush_dirty_pages_of_a_file(
         &self,
-        fd: i32,
+        file: &Arc<Inode<Rt>>,
         mut iter: impl Iterator<Item = PageHandle>,
         futures: &mut Vec<BoxFuture<'static, i32>>,
     ) {
+        let fd = file.fd();
+        // The length of the file must be read after the pages become flushing
+        let len = file.len();
+        // The pages to be merged into one write must be consecutive in the host file
+        let host_offset = |offset: usize| file.host_offset(offset);
         let mut first_page_opt = iter.next();
         // Scan the dirty pages to group them into consecutive pages
         loop {
@@ -100,7 +303,7 @@ impl<Rt: AsyncFileRt + ?Sized> Flusher<Rt> {
 
             // Collet the remaining consecutive pages
             let mut consecutive_pages = vec![first_page];
-            let mut next_offset = first_offset + Page::size();
+            let mut next_offset = host_offset(first_offset) + Page::size();
             loop {
                 let next_page = match iter.next() {
                     Some(next_page) => next_page,
@@ -109,7 +312,7 @@ impl<Rt: AsyncFileRt + ?Sized> Flusher<Rt> {
                         break;
                     }
                 };
-                if next_page.offset() != next_offset {
+                if host_offset(next_page.offset()) != next_offset {
                     first_page_opt = Some(next_page);
                     break;
                 }
@@ -118,28 +321,42 @@ impl<Rt: AsyncFileRt + ?Sized> Flusher<Rt> {
                 next_offset += Page::size();
             }
 
-            let future =
-                self.flush_consecutive_dirty_pages_of_a_fd(fd, first_offset, consecutive_pages);
+            let future = if file.is_io_bounced() {
+                self.flush_bounced_pages(fd, file, len, consecutive_pages)
+            } else {
+                self.flush_consecutive_dirty_pages_of_a_fd(
+                    file,
+                    fd,
+                    first_offset,
+                    len,
+                    consecutive_pages,
+                )
+            };
             futures.push(future);
         }
     }
 
     fn flush_consecutive_dirty_pages_of_a_fd(
         &self,
+        file: &Arc<Inode<Rt>>,
         fd: i32,
         offset: usize,
+        file_len: usize,
         mut consecutive_pages: Vec<PageHandle>,
     ) -> BoxFuture<'static, i32> {
+        // The bytes beyond the end of the file are not written so that the host
+        // file is not padded to a multiple of pages.
         // TODO: I don't think this Box is necessary (at least for non-SGX build)
         let iovecs: Box<Vec<libc::iovec>> = Box::new(
             consecutive_pages
                 .iter()
                 .map(|page| libc::iovec {
                     iov_base: page.page().as_mut_ptr() as _,
-                    iov_len: Page::size(),
+                    iov_len: file_len.saturating_sub(page.offset()).min(Page::size()),
                 })
                 .collect(),
         );
+        let write_len = iovecs.iter().map(|iovec| iovec.iov_len).sum::<usize>();
         #[cfg(not(feature = "sgx"))]
         let (iovecs_ptr, iovecs_len) = ((*iovecs).as_ptr(), (*iovecs).len());
         #[cfg(feature = "sgx")]
@@ -160,24 +377,10 @@ impl<Rt: AsyncFileRt + ?Sized> Flusher<Rt> {
         let iovecs_box = IovecsBox(iovecs);
 
         let complete_fn = {
-            let page_cache = Rt::page_cache();
+            let file = file.clone();
             let flusher_pollee = self.pollee.clone();
             move |retval: i32| {
-                // TODO: handle partial writes or error
-                assert!(retval as usize == consecutive_pages.len() * Page::size());
-
-                for page in consecutive_pages {
-                    let mut state = page.state();
-                    match *state {
-                        PageState::Flushing => {
-                            *state = PageState::UpToDate;
-                        }
-                        _ => unreachable!(),
-                    };
-                    drop(state);
-                    page_cache.release(page);
-                }
-                flusher_pollee.add_events(Events::IN | Events::OUT);
+                Self::complete_flush(&file, consecutive_pages, retval, write_len, &flusher_pollee);
 
                 #[cfg(feature = "sgx")]
                 drop(allocator);
@@ -199,7 +402,117 @@ impl<Rt: AsyncFileRt + ?Sized> Flusher<Rt> {
         Box::pin(handle)
     }
 
+    // Flush pages that are consecutive in the host file through a bounce buffer,
+    // encrypting them if the file is protected.
+    fn flush_bounced_pages(
+        &self,
+        fd: i32,
+        file: &Arc<Inode<Rt>>,
+        file_len: usize,
+        consecutive_pages: Vec<PageHandle>,
+    ) -> BoxFuture<'static, i32> {
+        let host_offset = file.host_offset(consecutive_pages[0].offset());
+        let buf_len = consecutive_pages.len() * Page::size();
+        let mut buf: UntrustedBox<[u8]> = UntrustedBox::new_uninit_slice(buf_len);
+        let mut page_entries = Vec::new();
+        for (page, dst_buf) in consecutive_pages.iter().zip(buf.chunks_mut(Page::size())) {
+            let src_buf = unsafe { page.page().as_slice() };
+            match file.protection() {
+                Some(protection) => {
+                    let entry = protection.encrypt_page(page.offset(), src_buf, dst_buf);
+                    page_entries.push((page.offset(), entry));
+                }
+                None => dst_buf.copy_from_slice(src_buf),
+            }
+        }
+        let buf_ptr = buf.as_ptr() as *const u8;
+        // The pages of a protected file are written as a whole since they are
+        // encrypted, while those of other files are clamped to the end of the file.
+        let write_len = match file.protection() {
+            Some(_) => buf_len,
+            None => file_len.saturating_sub(host_offset).min(buf_len),
+        };
+
+        let complete_fn = {
+            let file = file.clone();
+            let flusher_pollee = self.pollee.clone();
+            move |retval: i32| {
+                // The MACs are committed only after the pages reach the host, and
+                // before the pages are no longer being flushed. Upon a failure, the
+                // old MACs are kept, which still match the pages on the host unless
+                // the write has partially reached it.
+                if retval >= 0 && retval as usize == write_len {
+                    if let Some(protection) = file.protection() {
+                        for (offset, entry) in page_entries {
+                            protection.commit_page(offset, entry);
+                        }
+                    }
+                }
+                Self::complete_flush(&file, consecutive_pages, retval, write_len, &flusher_pollee);
+
+                drop(buf);
+            }
+        };
+        let io_uring = Rt::io_uring();
+        let handle = unsafe {
+            io_uring.write(
+                Fd(fd),
+                buf_ptr,
+                write_len as u32,
+                host_offset as i64,
+                0,
+                complete_fn,
+            )
+        };
+        Box::pin(handle)
+    }
+
+    fn complete_flush(
+        file: &Inode<Rt>,
+        consecutive_pages: Vec<PageHandle>,
+        retval: i32,
+        write_len: usize,
+        flusher_pollee: &Pollee,
+    ) {
+        // Just like Linux, the pages are up to date even if the write fails or
+        // is short, i.e., the data are not written again. Instead, the error is
+        // recorded in the file, which is reported by the next flush of the file.
+        if retval < 0 {
+            // TODO: guard against Iago attack through errno
+            file.set_writeback_error(Errno::from(-retval as u32));
+        } else if retval as usize != write_len {
+            file.set_writeback_error(EIO);
+        }
+
+        let page_cache = Rt::page_cache();
+        for page in consecutive_pages {
+            let mut state = page.state();
+            match *state {
+                PageState::Flushing => {
+                    *state = PageState::UpToDate;
+                }
+                _ => unreachable!(),
+            };
+            drop(state);
+            page_cache.release(page);
+        }
+        flusher_pollee.add_events(Events::IN | Events::OUT);
+    }
+
     pub(crate) fn pollee(&self) -> &Pollee {
         &self.pollee
     }
 }
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct KernelTimespec(Timespec);
+
+// Safety. KernelTimespec is a C-style struct.
+unsafe impl MaybeUntrusted for KernelTimespec {}
+
+struct TimespecBox(UntrustedBox<KernelTimespec>);
+
+// Safety. `UntrustedBox` does not implement `Send`. But since the timespec in it
+// is only read by io_uring, it is ok for `TimespecBox` to move between threads.
+unsafe impl Send for TimespecBox {}
@@ -0,0 +1,878 @@
+use spin::{Mutex, RwLock};
+use std::marker::PhantomData;
+use std::ops::Range;
+#[cfg(feature = "sgx")]
+use std::prelude::v1::*;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, Weak};
+
+use async_io::event::{Events, Pollee, Poller};
+use async_io::prelude::{Result, *};
+
+use super::protection::Protection;
+use super::{close_fd, ftruncate_fd, AsyncFileRt, FALLOC_FL_KEEP_SIZE, FALLOC_FL_PUNCH_HOLE};
+use crate::page_cache::{AsFileId, FileId, Page, PageHandle, PageState};
+use crate::util::crypto::Key;
+use crate::util::{align_down, align_up};
+
+use io_uring_callback::{Fd, IoHandle};
+#[cfg(feature = "sgx")]
+use sgx_untrusted_alloc::UntrustedAllocator;
+use sgx_untrusted_alloc::UntrustedBox;
+
+/// The state of a host file that is shared by all its opened instances.
+///
+/// An inode is registered in the page cache so that all opens of the same host
+/// file share the same inode, thus the same cached pages. When the last open is
+/// closed, the inode is retired along with its cached pages, unless some pages
+/// are still dirty or an error of writing back the pages is yet to be reported.
+/// In the latter case, the inode stays registered so that the dirty pages can be
+/// flushed, and a later open sees the unflushed data or the error.
+pub(crate) struct Inode<Rt: AsyncFileRt + ?Sized> {
+    id: FileId,
+    // The number of opens, or `None` if the inode has been retired.
+    num_opens: Mutex<Option<usize>>,
+    // The host fd for I/O, which is writable if the file has ever been opened
+    // for write.
+    fd: AtomicI32,
+    is_fd_writable: AtomicBool,
+    // The fds that have been replaced by a writable one. They are kept open since
+    // they may still be used by in-flight I/O.
+    replaced_fds: Mutex<Vec<i32>>,
+    len: RwLock<usize>,
+    // The first error of writing back the pages since it was last reported
+    writeback_error: Mutex<Option<Errno>>,
+    // The first error of fetching the pages since it was last reported
+    fetch_error: Mutex<Option<Errno>>,
+    pollee: Pollee,
+    protection: Option<Protection>,
+    // Whether the I/O between the page cache and the host goes through bounce
+    // buffers, which is the case if the pages are encrypted on the host or the
+    // pages in the page cache are not accessible to the host.
+    is_io_bounced: bool,
+    phantom_data: PhantomData<Rt>,
+    weak_self: Weak<Self>,
+}
+
+impl<Rt: AsyncFileRt + ?Sized> Inode<Rt> {
+    /// Returns the inode of a newly-opened host fd, taking the ownership of the fd.
+    ///
+    /// If the file is already open, the existing inode is returned, which may
+    /// adopt the fd if the fd is writable but the inode's is not.
+    pub fn open(id: FileId, fd: i32, is_fd_writable: bool, key: Option<Key>) -> Result<Arc<Self>> {
+        let page_cache = Rt::page_cache();
+        let mut fd = fd;
+        loop {
+            if let Some(inode) = page_cache.file::<Self>(id) {
+                if inode.add_open() {
+                    return inode.reopen(fd, is_fd_writable, key);
+                }
+                // The inode has just been retired and unregistered
+            }
+
+            let new_inode = Self::new(id, fd, is_fd_writable, key)?;
+            let inode = page_cache.register_file(new_inode.clone());
+            if Arc::ptr_eq(&inode, &new_inode) {
+                return Ok(inode);
+            }
+            // The file has been opened concurrently. Try again with the fd.
+            fd = new_inode.fd.swap(-1, Ordering::Relaxed);
+        }
+    }
+
+    /// Close an open of the inode.
+    pub fn close(&self) {
+        let mut num_opens = self.num_opens.lock();
+        let count = num_opens.as_mut().unwrap();
+        *count -= 1;
+        if *count > 0 {
+            return;
+        }
+
+        // Retire the inode if all its pages can be discarded, so that a later
+        // open reloads the file from the host.
+        if self.writeback_error.lock().is_some() {
+            return;
+        }
+        let page_cache = Rt::page_cache();
+        if page_cache.discard_clean_pages_of(self.id) {
+            *num_opens = None;
+            page_cache.unregister_file(self);
+        }
+    }
+
+    fn add_open(&self) -> bool {
+        match self.num_opens.lock().as_mut() {
+            Some(count) => {
+                *count += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn new(id: FileId, fd: i32, is_fd_writable: bool, key: Option<Key>) -> Result<Arc<Self>> {
+        #[cfg(not(feature = "sgx"))]
+        let len = unsafe { libc::lseek(fd, 0, libc::SEEK_END) };
+        #[cfg(feature = "sgx")]
+        let len = unsafe { libc::ocall::lseek(fd, 0, libc::SEEK_END) };
+        if len < 0 {
+            let errno = super::libc_errno();
+            close_fd(fd);
+            return_errno!(Errno::from(errno as u32), "libc::lseek error");
+        }
+
+        let (protection, len) = match key {
+            Some(key) => match Protection::load(key, fd, len as usize) {
+                Ok((protection, len)) => (Some(protection), len),
+                Err(e) => {
+                    close_fd(fd);
+                    return Err(e);
+                }
+            },
+            None => (None, len as usize),
+        };
+        let is_io_bounced =
+            protection.is_some() || (Rt::page_cache().is_trusted() && cfg!(feature = "sgx"));
+
+        let new_self = (Self {
+            id,
+            num_opens: Mutex::new(Some(1)),
+            fd: AtomicI32::new(fd),
+            is_fd_writable: AtomicBool::new(is_fd_writable),
+            replaced_fds: Mutex::new(Vec::new()),
+            len: RwLock::new(len),
+            writeback_error: Mutex::new(None),
+            fetch_error: Mutex::new(None),
+            pollee: Pollee::new(Events::IN | Events::OUT),
+            protection,
+            is_io_bounced,
+            phantom_data: PhantomData,
+            weak_self: Weak::default(),
+        })
+        .wrap();
+        Ok(new_self)
+    }
+
+    fn wrap(self) -> Arc<Self> {
+        // Create an Arc, make a Weak from it, then put it into the struct.
+        // It's a little tricky.
+        let arc = Arc::new(self);
+        let weak = Arc::downgrade(&arc);
+        let ptr = Arc::into_raw(arc) as *mut Self;
+        unsafe {
+            (*ptr).weak_self = weak;
+            Arc::from_raw(ptr)
+        }
+    }
+
+    fn reopen(
+        self: Arc<Self>,
+        fd: i32,
+        is_fd_writable: bool,
+        key: Option<Key>,
+    ) -> Result<Arc<Self>> {
+        let is_key_matched = match (&self.protection, &key) {
+            (Some(protection), Some(key)) => protection.key() == key,
+            (None, None) => true,
+            _ => false,
+        };
+        if !is_key_matched {
+            close_fd(fd);
+            self.close();
+            return_errno!(EINVAL, "the file is already open with another key");
+        }
+
+        {
+            let mut replaced_fds = self.replaced_fds.lock();
+            if is_fd_writable && !self.is_fd_writable.load(Ordering::Relaxed) {
+                let old_fd = self.fd.swap(fd, Ordering::Relaxed);
+                self.is_fd_writable.store(true, Ordering::Relaxed);
+                replaced_fds.push(old_fd);
+            } else {
+                close_fd(fd);
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn id(&self) -> FileId {
+        self.id
+    }
+
+    pub fn fd(&self) -> i32 {
+        self.fd.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        *self.len.read()
+    }
+
+    pub fn pollee(&self) -> &Pollee {
+        &self.pollee
+    }
+
+    pub fn protection(&self) -> Option<&Protection> {
+        self.protection.as_ref()
+    }
+
+    pub fn is_io_bounced(&self) -> bool {
+        self.is_io_bounced
+    }
+
+    /// Returns the offset in the host file of the page at a given offset.
+    pub fn host_offset(&self, offset: usize) -> usize {
+        match &self.protection {
+            Some(_) => Protection::host_offset(offset),
+            None => offset,
+        }
+    }
+
+    pub fn check_integrity(&self) -> Result<()> {
+        match &self.protection {
+            Some(protection) if protection.is_corrupted() => {
+                return_errno!(EIO, "the protected file is corrupted")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Record an error of writing back the pages of the file.
+    ///
+    /// Only the first error is kept until it is reported.
+    pub fn set_writeback_error(&self, errno: Errno) {
+        let mut writeback_error = self.writeback_error.lock();
+        if writeback_error.is_none() {
+            *writeback_error = Some(errno);
+        }
+    }
+
+    /// Returns the error of writing back the pages of the file, if any.
+    pub fn check_writeback_error(&self) -> Result<()> {
+        match *self.writeback_error.lock() {
+            Some(errno) => return_errno!(errno, "failed to write back the pages"),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the error of writing back the pages of the file, if any, which
+    /// is then considered as reported.
+    pub fn take_writeback_error(&self) -> Result<()> {
+        match self.writeback_error.lock().take() {
+            Some(errno) => return_errno!(errno, "failed to write back the pages"),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the error of fetching the pages of the file, if any, which is
+    /// then considered as reported.
+    pub fn take_fetch_error(&self) -> Result<()> {
+        match self.fetch_error.lock().take() {
+            Some(errno) => return_errno!(errno, "failed to fetch the pages"),
+            None => Ok(()),
+        }
+    }
+
+    // Record an error of fetching the pages, which are left uninitialized to be
+    // fetched again by the next read.
+    fn fail_fetch(&self, pages: Vec<PageHandle>, retval: i32) {
+        // TODO: guard against Iago attack through errno
+        let errno = Errno::from(-retval as u32);
+        let mut fetch_error = self.fetch_error.lock();
+        if fetch_error.is_none() {
+            *fetch_error = Some(errno);
+        }
+        drop(fetch_error);
+
+        let page_cache = Rt::page_cache();
+        for page in pages {
+            {
+                let mut state = page.state();
+                debug_assert!(*state == PageState::Fetching);
+                *state = PageState::Uninit;
+            }
+            page_cache.release(page);
+        }
+        self.pollee.add_events(Events::IN | Events::OUT);
+    }
+
+    /// Persist the metadata of a protected file after its pages are flushed.
+    pub async fn persist_metadata(&self) -> Result<()> {
+        match &self.protection {
+            Some(protection) => {
+                protection
+                    .persist(self.fd(), self.len(), Rt::io_uring())
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Truncate or extend the file to a given length.
+    ///
+    /// The cached pages beyond the new length are discarded, after the ones
+    /// being flushed reach the host so that the truncation is not undone.
+    pub async fn resize(&self, new_len: usize) -> Result<()> {
+        let page_cache = Rt::page_cache();
+        let discard_range = align_up(new_len, Page::size())..usize::MAX;
+        loop {
+            let flushing_pages = {
+                let mut len = self.len.write();
+                let flushing_pages = page_cache.discard_pages(self.id, discard_range.clone());
+                if flushing_pages.is_empty() {
+                    match &self.protection {
+                        Some(protection) => protection.truncate(new_len),
+                        None => ftruncate_fd(self.fd(), new_len)?,
+                    }
+                    *len = new_len;
+                    return Ok(());
+                }
+                flushing_pages
+            };
+            Self::wait_for_flushes(flushing_pages).await;
+        }
+    }
+
+    /// Allocate the host storage for a range of the file, extending the file
+    /// unless `FALLOC_FL_KEEP_SIZE` is given.
+    ///
+    /// The storage of a protected file is not preallocated since its layout on
+    /// the host differs from that of the file.
+    pub async fn allocate(&self, mode: i32, offset: usize, len: usize) -> Result<()> {
+        if self.protection.is_none() {
+            self.fallocate_host(mode, offset, len).await?;
+        }
+        if mode & FALLOC_FL_KEEP_SIZE == 0 {
+            let mut file_len = self.len.write();
+            if offset + len > *file_len {
+                *file_len = offset + len;
+            }
+        }
+        Ok(())
+    }
+
+    /// Extend the length of the file after the host file is written directly.
+    pub fn extend_len(&self, new_len: usize) {
+        let mut len = self.len.write();
+        if new_len > *len {
+            *len = new_len;
+        }
+    }
+
+    /// Deallocate the pages within a range of offsets, which then read as zeros.
+    pub async fn punch_hole(&self, range: Range<usize>) -> Result<()> {
+        debug_assert!(range.start % Page::size() == 0 && range.end % Page::size() == 0);
+        self.discard_pages(range.clone()).await;
+        match &self.protection {
+            Some(protection) => protection.clear_pages(range.clone()),
+            None => {
+                let mode = FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE;
+                self.fallocate_host(mode, range.start, range.len()).await?
+            }
+        }
+        // The pages fetched during the punching may hold the old data
+        self.discard_pages(range).await;
+        Ok(())
+    }
+
+    async fn fallocate_host(&self, mode: i32, offset: usize, len: usize) -> Result<()> {
+        let complete_fn = move |_retval: i32| {};
+        let io_uring = Rt::io_uring();
+        let handle = unsafe {
+            io_uring.fallocate(
+                Fd(self.fd()),
+                offset as libc::off_t,
+                len as libc::off_t,
+                mode,
+                complete_fn,
+            )
+        };
+        let retval = handle.await;
+        if retval < 0 {
+            return_errno!(Errno::from(-retval as u32), "fallocate error");
+        }
+        Ok(())
+    }
+
+    /// Write back the dirty pages of the file and wait for the pages within a
+    /// range of offsets that are being flushed, after which the host file is
+    /// up to date in the range.
+    pub async fn write_back_pages(&self, range: Range<usize>) {
+        const FLUSH_BATCH_SIZE: usize = 64;
+        let flusher = Rt::flusher();
+        while flusher.flush_by_id(self.id, FLUSH_BATCH_SIZE).await > 0 {}
+        let flushing_pages = Rt::page_cache().flushing_pages(self.id, range);
+        Self::wait_for_flushes(flushing_pages).await;
+    }
+
+    /// Discard the cached pages within a range of offsets, waiting for the ones
+    /// being flushed.
+    pub async fn discard_pages(&self, range: Range<usize>) {
+        let flushing_pages = Rt::page_cache().discard_pages(self.id, range);
+        Self::wait_for_flushes(flushing_pages).await;
+    }
+
+    async fn wait_for_flushes(pages: Vec<PageHandle>) {
+        if !pages.is_empty() {
+            let mut poller = Poller::new();
+            Rt::flusher().pollee().poll(Events::OUT, Some(&mut poller));
+            while pages
+                .iter()
+                .any(|page| *page.state() == PageState::Flushing)
+            {
+                poller.wait().await;
+            }
+        }
+
+        let page_cache = Rt::page_cache();
+        for page in pages {
+            page_cache.release(page);
+        }
+    }
+
+    // Fetch and prefetch pages.
+    //
+    // The first pages in the fetch range [offset, offset + len) that are ready to read are passed
+    // to a closure so that the caller can access the data in these pages. Note that the state of the
+    // page is locked while the closure is being executed.
+    //
+    // The pages that are within the range [offset, offset + len + prefetch_len] will be fetched into
+    // the page cache, if they are not present in the page cache.
+    //
+    // The procedure works in two phases. The first phase is fetching, in which we iterate
+    // the first pages that are ready to read. These pages are passed to the access closure
+    // one-by-one. Upon reaching the first page that cannot be read or beyond the fetching
+    // range [offset, offset + len), we transit to the second phase: prefetching. In this
+    // phase, we will try out our best to bring the pages into the page cache,
+    // issueing async reads if needed.
+    //
+    // Returns whether the first page is missing from the page cache, i.e., its data has to be
+    // fetched from the file.
+    pub fn fetch_pages(
+        &self,
+        offset: usize,
+        len: usize,
+        prefetch_len: usize,
+        mut access_fn: impl FnMut(&PageHandle),
+    ) -> bool {
+        // If the first stage, the value is true; if the second stage, false.
+        let mut should_call_access_fn = true;
+        let mut is_first_page_missing = true;
+        // Prepare for async read that fetches multiple consecutive pages
+        let mut consecutive_pages = Vec::new();
+
+        // Enter the loop that fetches and prefetches pages.
+        let arc_self = self.clone_arc();
+        let page_cache = Rt::page_cache();
+        let page_begin = align_down(offset, Page::size());
+        let page_end = align_up(offset + len + prefetch_len, Page::size());
+        let fetch_end = align_up(offset + len, Page::size());
+        for page_offset in (page_begin..page_end).step_by(Page::size()) {
+            if should_call_access_fn && page_offset >= fetch_end {
+                should_call_access_fn = false;
+            }
+
+            let page = match page_cache.acquire(&arc_self, page_offset) {
+                Some(page) => page,
+                None => break,
+            };
+            let mut state = page.state();
+            if page_offset == page_begin {
+                is_first_page_missing = *state == PageState::Uninit;
+            }
+            if should_call_access_fn {
+                // The fetching phase
+                match *state {
+                    PageState::UpToDate | PageState::Dirty | PageState::Flushing => {
+                        // Invoke the access function
+                        (access_fn)(&page);
+
+                        drop(state);
+                        page_cache.release(page);
+                    }
+                    PageState::Uninit => {
+                        // Start prefetching
+                        *state = PageState::Fetching;
+                        drop(state);
+                        consecutive_pages.push(page);
+
+                        // Transit to the prefetching phase
+                        should_call_access_fn = false;
+                    }
+                    PageState::Fetching => {
+                        // We do nothing here
+                        drop(state);
+                        page_cache.release(page);
+
+                        // Transit to the prefetching phase
+                        should_call_access_fn = false;
+                    }
+                }
+            } else {
+                // The prefetching phase
+                match *state {
+                    PageState::Uninit => {
+                        // Add one more page to prefetch
+                        *state = PageState::Fetching;
+                        drop(state);
+                        consecutive_pages.push(page);
+                    }
+                    PageState::UpToDate
+                    | PageState::Dirty
+                    | PageState::Flushing
+                    | PageState::Fetching => {
+                        drop(state);
+                        page_cache.release(page);
+
+                        // When reaching the end of consecutive pages, start the I/O
+                        if consecutive_pages.len() > 0 {
+                            self.fetch_consecutive_pages(consecutive_pages);
+                            consecutive_pages = Vec::new();
+                        }
+                    }
+                }
+            }
+        }
+        // When reaching the end of consecutive pages, start the I/O
+        if consecutive_pages.len() > 0 {
+            self.fetch_consecutive_pages(consecutive_pages);
+        }
+        is_first_page_missing
+    }
+
+    // Bring the pages within the range [offset, offset + len) into the page cache without
+    // accessing them.
+    pub fn prefetch_pages(&self, offset: usize, len: usize) {
+        self.fetch_pages(offset, 0, len, |_| {});
+    }
+
+    fn fetch_consecutive_pages(&self, consecutive_pages: Vec<PageHandle>) {
+        debug_assert!(!consecutive_pages.is_empty());
+        debug_assert!(consecutive_pages.windows(2).all(|two_pages| {
+            let (p0, p1) = (&two_pages[0], &two_pages[1]);
+            p0.offset() + Page::size() == p1.offset()
+        }));
+        debug_assert!(consecutive_pages
+            .iter()
+            .all(|page| { *page.state() == PageState::Fetching }));
+
+        if self.is_io_bounced {
+            // The pages that are consecutive in the file may not be so in the host file
+            let mut host_runs: Vec<Vec<PageHandle>> = Vec::new();
+            for page in consecutive_pages {
+                match host_runs.last_mut() {
+                    Some(run)
+                        if self.host_offset(run.last().unwrap().offset()) + Page::size()
+                            == self.host_offset(page.offset()) =>
+                    {
+                        run.push(page)
+                    }
+                    _ => host_runs.push(vec![page]),
+                }
+            }
+            for run in host_runs {
+                self.fetch_bounced_pages(run);
+            }
+            return;
+        }
+
+        let first_offset = consecutive_pages[0].offset();
+        let self_ = self.clone_arc();
+        let iovecs = Box::new(
+            consecutive_pages
+                .iter()
+                .map(|page_handle| libc::iovec {
+                    iov_base: page_handle.page().as_mut_ptr() as _,
+                    iov_len: Page::size(),
+                })
+                .collect::<Vec<libc::iovec>>(),
+        );
+        #[cfg(not(feature = "sgx"))]
+        let (iovecs_ptr, iovecs_len) = ((*iovecs).as_ptr(), (*iovecs).len());
+        #[cfg(feature = "sgx")]
+        let (iovecs_ptr, iovecs_len, allocator, iovecs_ptr_u64, t_iovecs_ptr_u64) = {
+            let iovecs_len = (*iovecs).len();
+            let t_iovecs_ptr = (*iovecs).as_ptr();
+            let iovecs_size = iovecs_len * core::mem::size_of::<libc::iovec>();
+            let allocator = UntrustedAllocator::new(iovecs_size, 8).unwrap();
+            let iovecs_ptr = allocator.as_mut_ptr() as *mut libc::iovec;
+            unsafe {
+                std::ptr::copy_nonoverlapping(t_iovecs_ptr, iovecs_ptr, iovecs_len);
+            }
+            (
+                iovecs_ptr,
+                iovecs_len,
+                allocator,
+                iovecs_ptr as u64,
+                t_iovecs_ptr as u64,
+            )
+        };
+
+        struct IovecsBox(Box<Vec<libc::iovec>>);
+        unsafe impl Send for IovecsBox {}
+        let iovecs_box = IovecsBox(iovecs);
+
+        let handle_store: Arc<Mutex<Option<IoHandle>>> = Arc::new(Mutex::new(None));
+        let handle_store2 = handle_store.clone();
+
+        let callback = move |retval| {
+            // The captured buffers are dropped as the callback returns
+            if retval < 0 {
+                self_.fail_fetch(consecutive_pages, retval);
+                return;
+            }
+            let page_cache = Rt::page_cache();
+            let read_nbytes = retval as usize;
+            for page in consecutive_pages {
+                let page_offset = page.offset();
+                debug_assert!(page_offset >= first_offset);
+
+                // For a partial read, fill zeros or in the remaining part of the page.
+                // TODO: are there partial reads that should not fill zeros?
+                let page_valid_nbytes = if first_offset + read_nbytes > page_offset {
+                    (first_offset + read_nbytes - page_offset).min(Page::size())
+                } else {
+                    0
+                };
+                if page_valid_nbytes < Page::size() {
+                    let page_slice = unsafe { page.page().as_slice_mut() };
+                    page_slice[page_valid_nbytes..].fill(0);
+                }
+
+                // Update page state
+                {
+                    let mut state = page.state();
+                    debug_assert!(*state == PageState::Fetching);
+                    *state = PageState::UpToDate;
+                }
+                page_cache.release(page);
+            }
+            self_.pollee.add_events(Events::IN | Events::OUT);
+
+            #[cfg(feature = "sgx")]
+            drop(allocator);
+            drop(iovecs_box);
+            drop(handle_store);
+        };
+        let io_uring = Rt::io_uring();
+        let handle = unsafe {
+            io_uring.readv(
+                Fd(self.fd()),
+                iovecs_ptr,
+                iovecs_len as u32,
+                first_offset as i64,
+                0,
+                callback,
+            )
+        };
+        let mut guard = handle_store2.lock();
+        guard.replace(handle);
+    }
+
+    // Fetch pages that are consecutive in the host file through a bounce buffer,
+    // decrypting them if the file is protected.
+    fn fetch_bounced_pages(&self, pages: Vec<PageHandle>) {
+        let host_offset = self.host_offset(pages[0].offset());
+        let buf_len = pages.len() * Page::size();
+        let buf: UntrustedBox<[u8]> = UntrustedBox::new_uninit_slice(buf_len);
+        let buf_ptr = buf.as_mut_ptr() as *mut u8;
+
+        let handle_store: Arc<Mutex<Option<IoHandle>>> = Arc::new(Mutex::new(None));
+        let handle_store2 = handle_store.clone();
+
+        let self_ = self.clone_arc();
+        let callback = move |retval: i32| {
+            // The captured buffers are dropped as the callback returns
+            if retval < 0 {
+                self_.fail_fetch(pages, retval);
+                return;
+            }
+            let page_cache = Rt::page_cache();
+            let read_nbytes = retval as usize;
+            for (i, page) in pages.into_iter().enumerate() {
+                // Copy the page into the page cache before decryption so that
+                // the host cannot alter it after it is verified. For a partial
+                // read, fill zeros in the remaining part of the page.
+                let buf_offset = i * Page::size();
+                let page_valid_nbytes = read_nbytes.saturating_sub(buf_offset).min(Page::size());
+                let src_buf = &buf[buf_offset..buf_offset + page_valid_nbytes];
+                let page_slice = unsafe { page.page().as_slice_mut() };
+                page_slice[..page_valid_nbytes].copy_from_slice(src_buf);
+                page_slice[page_valid_nbytes..].fill(0);
+
+                let is_verified = match &self_.protection {
+                    Some(protection) => protection.decrypt_page(page.offset(), page_slice).is_ok(),
+                    None => true,
+                };
+
+                // Update page state. A page that fails the verification is
+                // dropped from the page cache.
+                {
+                    let mut state = page.state();
+                    debug_assert!(*state == PageState::Fetching);
+                    *state = if is_verified {
+                        PageState::UpToDate
+                    } else {
+                        PageState::Uninit
+                    };
+                }
+                page_cache.release(page);
+            }
+            self_.pollee.add_events(Events::IN | Events::OUT);
+
+            drop(buf);
+            drop(handle_store);
+        };
+        let io_uring = Rt::io_uring();
+        let handle = unsafe {
+            io_uring.read(
+                Fd(self.fd()),
+                buf_ptr,
+                buf_len as u32,
+                host_offset as i64,
+                0,
+                callback,
+            )
+        };
+        let mut guard = handle_store2.lock();
+        guard.replace(handle);
+    }
+
+    /// Write the bytes of a buffer into the cached pages at a given offset,
+    /// returning the number of bytes written.
+    ///
+    /// The write stops at the first page that is not ready to be written,
+    /// whose fetching is started if needed.
+    pub fn write_pages(&self, offset: usize, buf: &[u8]) -> usize {
+        let mut new_dirty_pages = false;
+        let mut write_nbytes = 0;
+        // Writes are serialized by the length lock, so that the flusher, which
+        // clamps its writes to the length of the file, never sees a page that
+        // is dirtied beyond the length.
+        let mut file_len = self.len.write();
+        let arc_self = self.clone_arc();
+        let page_cache = Rt::page_cache();
+        let page_begin = align_down(offset, Page::size());
+        let page_end = align_up(offset + buf.len(), Page::size());
+        for page_offset in (page_begin..page_end).step_by(Page::size()) {
+            let page_handle = match page_cache.acquire(&arc_self, page_offset) {
+                Some(page_handle) => page_handle,
+                None => break,
+            };
+            let inner_offset = offset + write_nbytes - page_offset;
+
+            let copy_size = {
+                let page_remain = Page::size() - inner_offset;
+                let buf_remain = buf.len() - write_nbytes;
+                buf_remain.min(page_remain)
+            };
+            let to_write_full_page = copy_size == Page::size();
+
+            let mut do_write = || {
+                let page_slice = unsafe { page_handle.page().as_slice_mut() };
+
+                let src_buf = &buf[write_nbytes..write_nbytes + copy_size];
+                let dst_buf = &mut page_slice[inner_offset..inner_offset + copy_size];
+                dst_buf.copy_from_slice(src_buf);
+                // A write into the middle of a page, e.g., an append to the
+                // last page, is not counted as a new access of the page.
+                if inner_offset == 0 {
+                    page_cache.mark_accessed(&page_handle);
+                }
+
+                write_nbytes += copy_size;
+            };
+
+            let mut state = page_handle.state();
+            match *state {
+                PageState::UpToDate => {
+                    (do_write)();
+
+                    *state = PageState::Dirty;
+                    page_handle.set_dirty_since(Rt::now());
+                    drop(state);
+                    page_cache.release(page_handle);
+
+                    new_dirty_pages = true;
+                }
+                PageState::Dirty => {
+                    (do_write)();
+
+                    drop(state);
+                    page_cache.release(page_handle);
+                }
+                PageState::Uninit if to_write_full_page => {
+                    (do_write)();
+
+                    *state = PageState::Dirty;
+                    page_handle.set_dirty_since(Rt::now());
+                    drop(state);
+                    page_cache.release(page_handle);
+
+                    new_dirty_pages = true;
+                }
+                PageState::Uninit => {
+                    *state = PageState::Fetching;
+                    drop(state);
+
+                    self.fetch_consecutive_pages(vec![page_handle]);
+                    break;
+                }
+                PageState::Fetching | PageState::Flushing => {
+                    // We do nothing here
+                    drop(state);
+                    page_cache.release(page_handle);
+
+                    break;
+                }
+            }
+        }
+
+        // Update file length if necessary
+        if write_nbytes > 0 && offset + write_nbytes > *file_len {
+            *file_len = offset + write_nbytes;
+        }
+        drop(file_len);
+
+        if new_dirty_pages {
+            Rt::auto_flush();
+        }
+        write_nbytes
+    }
+
+    fn clone_arc(&self) -> Arc<Self> {
+        self.weak_self.upgrade().unwrap()
+    }
+}
+
+impl<Rt: AsyncFileRt + ?Sized> AsFileId for Inode<Rt> {
+    fn file_id(&self) -> FileId {
+        self.id
+    }
+}
+
+impl<Rt: AsyncFileRt + ?Sized> Drop for Inode<Rt> {
+    fn drop(&mut self) {
+        let fd = self.fd();
+        if fd >= 0 {
+            close_fd(fd);
+        }
+        for &fd in self.replaced_fds.lock().iter() {
+            close_fd(fd);
+        }
+        Rt::page_cache().unregister_file(self);
+    }
+}
+
+impl<Rt: AsyncFileRt + ?Sized> std::fmt::Debug for Inode<Rt> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Inode")
+            .field("id", &self.id)
+            .field("num_opens", &*self.num_opens.lock())
+            .field("fd", &self.fd())
+            .field("len", &self.len())
+            .field("protection", &self.protection)
+            .field("pollee", &self.pollee)
+            .finish()
+    }
+}
@@ -1,40 +1,64 @@
-use spin::{Mutex, RwLock};
-use std::any::Any;
-use std::marker::PhantomData;
+use spin::Mutex;
 #[cfg(feature = "sgx")]
 use std::prelude::v1::*;
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 
-use async_io::event::{Events, Pollee, Poller};
-use async_io::file::{Async, File, SeekFrom};
+use async_io::event::{Events, Poller};
+use async_io::file::{AccessMode, File};
+use async_io::fs::SeekFrom;
 use async_io::prelude::{Result, *};
-use futures::future::BoxFuture;
-use futures::prelude::*;
 
-use crate::file::tracker::SeqRdTracker;
-use crate::page_cache::{AsFd, Page, PageCache, PageHandle, PageState};
+use crate::file::tracker::{Pattern, SeqRdTracker};
+use crate::page_cache::{FileId, Page, PageCache, PageHandle};
+use crate::util::crypto::Key;
 use crate::util::{align_down, align_up};
 
+use self::direct_io::{DirectIo, MAX_DIRECT_IO_SIZE};
 pub use self::flusher::Flusher;
+use self::inode::Inode;
 
-use io_uring_callback::{Fd, Handle, IoUring};
-#[cfg(feature = "sgx")]
-use sgx_untrusted_alloc::UntrustedAllocator;
+use io_uring_callback::{Fd, IoUring};
 
+mod direct_io;
 mod flusher;
+mod inode;
+mod protection;
 mod tracker;
+mod writeback;
+
+pub use self::direct_io::DIRECT_IO_ALIGN;
+pub use self::tracker::Advice;
+pub use self::writeback::{WritebackConfig, WritebackStats};
+
+/// The mode of `AsyncFile::fallocate` that keeps the length of the file.
+pub const FALLOC_FL_KEEP_SIZE: i32 = 0x01;
+/// The mode of `AsyncFile::fallocate` that deallocates a range of the file. It
+/// must be used together with `FALLOC_FL_KEEP_SIZE`.
+pub const FALLOC_FL_PUNCH_HOLE: i32 = 0x02;
 
 /// An instance of file with async APIs.
+///
+/// Each open of a host file creates an `AsyncFile`, which has its own file
+/// offset and access mode, while the state of the host file, including its
+/// length and cached pages, is shared among all opens of the file.
+///
+/// # Direct I/O
+///
+/// A file opened with `O_DIRECT` bypasses the page cache: its reads and writes
+/// go straight to the host file. The offsets, lengths and buffers of direct I/O
+/// must be aligned to `DIRECT_IO_ALIGN`, or the I/O fails with `EINVAL`. The
+/// cached pages of the file, which other opens may use, are kept coherent with
+/// the direct I/O. As direct I/O always has to wait for the host, it can only be
+/// done with the async methods, e.g., `read_exact_at` and `write_exact_at`.
 pub struct AsyncFile<Rt: AsyncFileRt + ?Sized> {
-    fd: i32,
+    inode: Arc<Inode<Rt>>,
+    direct_io: Option<DirectIo<Rt>>,
     pos: Mutex<usize>,
-    len: RwLock<usize>,
     can_read: bool,
     can_write: bool,
     seq_rd_tracker: Mutex<SeqRdTracker>,
-    pollee: Pollee,
     fixed_events: Events,
-    phantom_data: PhantomData<Rt>,
     weak_self: Weak<Self>,
 }
 
@@ -49,7 +73,28 @@ pub trait AsyncFileRt: Send + Sync + 'static {
     fn io_uring() -> &'static IoUring;
     fn page_cache() -> &'static PageCache;
     fn flusher() -> &'static Flusher<Self>;
-    fn auto_flush();
+
+    /// Returns the time elapsed since an arbitrary but fixed point in the past,
+    /// which must never go backward.
+    ///
+    /// It is used to find out the dirty pages that have expired.
+    fn now() -> Duration;
+
+    /// Notify the runtime of new dirty pages.
+    ///
+    /// By default, the writeback daemon of the flusher is woken up.
+    fn auto_flush() {
+        Self::flusher().wake_daemon();
+    }
+
+    /// Returns the key of the file at a given path if the file is to be protected.
+    ///
+    /// The pages of a protected file are encrypted and authenticated on the host.
+    /// Under SGX, protected files require the page cache to be created with
+    /// `PageCache::with_capacity_trusted`. By default, no file is protected.
+    fn file_key(_path: &str) -> Option<Key> {
+        None
+    }
 }
 
 impl<Rt: AsyncFileRt + ?Sized> File for AsyncFile<Rt> {
@@ -71,83 +116,54 @@ impl<Rt: AsyncFileRt + ?Sized> File for AsyncFile<Rt> {
         res
     }
 
-    fn seek(&self, seek_pos: SeekFrom) -> Result<usize> {
-        let mut pos = self.pos.lock();
-        match seek_pos {
-            SeekFrom::Start(offset) => {
-                *pos = offset;
-            }
-            SeekFrom::End(offset) => {
-                let len = self.len.read();
-                *pos = len
-                    .checked_add(offset)
-                    .ok_or_else(|| errno!(EOVERFLOW, "offset overflow"))?;
-            }
-            SeekFrom::Current(offset) => {
-                let new_pos = if offset >= 0 {
-                    pos.checked_add(offset as usize)
-                        .ok_or_else(|| errno!(EOVERFLOW, "offset overflow"))?
-                } else {
-                    pos.checked_sub(-offset as usize)
-                        .ok_or_else(|| errno!(EINVAL, "offset underflow"))?
-                };
-                *pos = new_pos;
-            }
-        }
-        Ok(*pos)
-    }
-
-    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
-        self.do_read_at(offset, buf)
-    }
-
-    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
-        self.do_write_at(offset, buf)
-    }
-
-    fn flush(&self) -> BoxFuture<'_, Result<()>> {
-        let fd = self.fd;
-        (async move {
-            loop {
-                const FLUSH_BATCH_SIZE: usize = 64;
-                let num_flushed = Rt::flusher().flush_by_fd(fd, FLUSH_BATCH_SIZE).await;
-                if num_flushed == 0 {
-                    break;
-                }
-            }
-
-            let complete_fn = move |retval: i32| assert!(retval == 0);
-            let io_uring = Rt::io_uring();
-            let handle = unsafe { io_uring.fsync(Fd(fd), false, complete_fn) };
-            handle.await;
-
-            return Ok(());
-        })
-        .boxed()
-    }
-
-    fn poll_by(&self, mask: Events, mut poller: Option<&mut Poller>) -> Events {
+    fn poll(&self, mask: Events, mut poller: Option<&mut Poller>) -> Events {
         // Both the file's and flusher's pollee affects the readiness of
         // reads and writes on this file.
         let reborrowed_poller = poller.as_mut().map(|p| &mut **p);
-        self.pollee.poll_by(mask, reborrowed_poller);
+        self.inode.pollee().poll(mask, reborrowed_poller);
 
         let flusher = Rt::flusher();
-        flusher.pollee().poll_by(mask, poller);
+        flusher.pollee().poll(mask, poller);
 
-        self.fixed_events
+        self.fixed_events & mask
     }
 
-    fn as_any(&self) -> &dyn Any {
-        self as &dyn Any
+    fn access_mode(&self) -> AccessMode {
+        match (self.can_read, self.can_write) {
+            (true, true) => AccessMode::O_RDWR,
+            (false, true) => AccessMode::O_WRONLY,
+            _ => AccessMode::O_RDONLY,
+        }
     }
 }
 
 impl<Rt: AsyncFileRt + ?Sized> AsyncFile<Rt> {
     /// Open a file at a given path.
     ///
-    /// The three arguments have the same meaning as the open syscall.
-    pub fn open(mut path: String, flags: i32, mode: u32) -> Result<Async<Arc<Self>>> {
+    /// The three arguments have the same meaning as the open syscall. All opens
+    /// of the same host file share the cached pages of the file.
+    ///
+    /// `O_TRUNC` is not supported, since truncating a file that is already open
+    /// has to wait for its pages being flushed. Use `set_len` instead.
+    pub fn open(path: String, flags: i32, mode: u32) -> Result<Arc<Self>> {
+        if flags & libc::O_TRUNC != 0 {
+            return_errno!(EINVAL, "O_TRUNC is not supported; use set_len instead");
+        }
+        let key = Rt::file_key(&path);
+        // Under SGX, the plaintext of a protected file must stay in the enclave
+        let is_cache_trusted = !cfg!(feature = "sgx") || Rt::page_cache().is_trusted();
+        if key.is_some() && !is_cache_trusted {
+            return_errno!(EINVAL, "protected files require a trusted page cache");
+        }
+        // The pages of a protected file must be decrypted in the page cache
+        let is_direct = flags & libc::O_DIRECT != 0;
+        if key.is_some() && is_direct {
+            return_errno!(EINVAL, "protected files do not support O_DIRECT");
+        }
+        // The host fd of the inode is used for the I/O of the page cache, which
+        // may not be aligned for direct I/O.
+        let flags = flags & !libc::O_DIRECT;
+
         let (can_read, can_write) = if flags & libc::O_WRONLY != 0 {
             (false, true)
         } else if flags & libc::O_RDWR != 0 {
@@ -158,7 +174,7 @@ impl<Rt: AsyncFileRt + ?Sized> AsyncFile<Rt> {
         };
 
         let fd = unsafe {
-            let c_path = std::ffi::CString::new(path).unwrap();
+            let c_path = std::ffi::CString::new(path.as_str()).unwrap();
             let c_path_ptr = c_path.as_bytes_with_nul().as_ptr() as _;
             let flags = if flags & libc::O_WRONLY != 0 {
                 (flags & !libc::O_WRONLY) | libc::O_RDWR
@@ -175,13 +191,25 @@ impl<Rt: AsyncFileRt + ?Sized> AsyncFile<Rt> {
             return_errno!(Errno::from(libc_errno() as u32), "libc::open error");
         }
 
-        #[cfg(not(feature = "sgx"))]
-        let len = unsafe { libc::lseek(fd, 0, libc::SEEK_END) };
-        #[cfg(feature = "sgx")]
-        let len = unsafe { libc::ocall::lseek(fd, 0, libc::SEEK_END) };
-        if len < 0 {
-            return_errno!(Errno::from(libc_errno() as u32), "libc::lseek error");
-        }
+        let direct_io = if is_direct {
+            match DirectIo::open(&path, flags) {
+                Ok(direct_io) => Some(direct_io),
+                Err(e) => {
+                    close_fd(fd);
+                    return Err(e);
+                }
+            }
+        } else {
+            None
+        };
+        let id = match file_id_of(fd) {
+            Ok(id) => id,
+            Err(e) => {
+                close_fd(fd);
+                return Err(e);
+            }
+        };
+        let inode = Inode::open(id, fd, can_write, key)?;
 
         // A regular file is always readable (or writable) if it is open for
         // read or write.
@@ -197,22 +225,20 @@ impl<Rt: AsyncFileRt + ?Sized> AsyncFile<Rt> {
         };
 
         let new_self = (Self {
-            fd,
+            inode,
+            direct_io,
             pos: Mutex::new(0),
-            len: RwLock::new(len as usize),
             can_read,
             can_write,
             seq_rd_tracker: Mutex::new(SeqRdTracker::new()),
-            pollee: Pollee::new(fixed_events),
             fixed_events,
-            phantom_data: PhantomData,
             weak_self: Weak::default(),
         })
         .wrap();
         Ok(new_self)
     }
 
-    fn wrap(self) -> Async<Arc<Self>> {
+    fn wrap(self) -> Arc<Self> {
         // Create an Arc, make a Weak from it, then put it into the struct.
         // It's a little tricky.
         let arc = Arc::new(self);
@@ -220,20 +246,305 @@ impl<Rt: AsyncFileRt + ?Sized> AsyncFile<Rt> {
         let ptr = Arc::into_raw(arc) as *mut Self;
         unsafe {
             (*ptr).weak_self = weak;
-            Async::new(Arc::from_raw(ptr))
+            Arc::from_raw(ptr)
+        }
+    }
+
+    /// Read some bytes at a given offset.
+    ///
+    /// The method never blocks. If no bytes are ready in the page cache, it
+    /// returns `EAGAIN` and starts fetching them, after which the file will be
+    /// notified with `Events::IN`. It fails with `EINVAL` if the file is open
+    /// for direct I/O.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        self.do_read_at(offset, buf)
+    }
+
+    /// Write some bytes at a given offset.
+    ///
+    /// The method never blocks. If no bytes can be written into the page cache,
+    /// it returns `EAGAIN`. It fails with `EINVAL` if the file is open for
+    /// direct I/O.
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        self.do_write_at(offset, buf)
+    }
+
+    /// Read bytes at a given offset until the buffer is full or the end of
+    /// the file is reached.
+    pub async fn read_exact_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        if let Some(direct_io) = &self.direct_io {
+            return self.read_direct_at(direct_io, offset, buf).await;
+        }
+        let mut poller = None;
+        let mut read_nbytes = 0;
+        while read_nbytes < buf.len() {
+            match self.do_read_at(offset + read_nbytes, &mut buf[read_nbytes..]) {
+                Ok(0) => break,
+                Ok(nbytes) => read_nbytes += nbytes,
+                Err(e) if e.errno() == EAGAIN => self.wait_events(Events::IN, &mut poller).await,
+                Err(_) if read_nbytes > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(read_nbytes)
+    }
+
+    /// Write all bytes of the buffer at a given offset.
+    pub async fn write_exact_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        if let Some(direct_io) = &self.direct_io {
+            return self.write_direct_at(direct_io, offset, buf).await;
+        }
+        let mut poller = None;
+        let mut write_nbytes = 0;
+        while write_nbytes < buf.len() {
+            match self.do_write_at(offset + write_nbytes, &buf[write_nbytes..]) {
+                Ok(nbytes) => write_nbytes += nbytes,
+                Err(e) if e.errno() == EAGAIN => {
+                    // The page cache may be full of dirty pages. So the writer
+                    // helps flush its own dirty pages before waiting.
+                    if self.flush_dirty_pages_once().await > 0 {
+                        continue;
+                    }
+                    self.wait_events(Events::OUT, &mut poller).await;
+                }
+                Err(_) if write_nbytes > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(write_nbytes)
+    }
+
+    /// Change the offset for the next `read` or `write`.
+    pub fn seek(&self, seek_pos: SeekFrom) -> Result<usize> {
+        let mut pos = self.pos.lock();
+        match seek_pos {
+            SeekFrom::Start(offset) => {
+                *pos = offset as usize;
+            }
+            SeekFrom::End(offset) => {
+                let len = self.inode.len() as i64;
+                let new_pos = len
+                    .checked_add(offset)
+                    .ok_or_else(|| errno!(EOVERFLOW, "offset overflow"))?;
+                if new_pos < 0 {
+                    return_errno!(EINVAL, "offset underflow");
+                }
+                *pos = new_pos as usize;
+            }
+            SeekFrom::Current(offset) => {
+                let new_pos = if offset >= 0 {
+                    pos.checked_add(offset as usize)
+                        .ok_or_else(|| errno!(EOVERFLOW, "offset overflow"))?
+                } else {
+                    pos.checked_sub(-offset as usize)
+                        .ok_or_else(|| errno!(EINVAL, "offset underflow"))?
+                };
+                *pos = new_pos;
+            }
+        }
+        Ok(*pos)
+    }
+
+    /// Returns whether the file is open for direct I/O, i.e., with `O_DIRECT`.
+    pub fn is_direct(&self) -> bool {
+        self.direct_io.is_some()
+    }
+
+    /// Returns the length of the file, including the bytes that are written
+    /// into the page cache but not yet flushed.
+    pub fn len(&self) -> usize {
+        self.inode.len()
+    }
+
+    /// Truncate or extend the file to a given length.
+    ///
+    /// The extended part of the file reads as zeros.
+    pub async fn set_len(&self, len: usize) -> Result<()> {
+        if !self.can_write {
+            return_errno!(EINVAL, "not open for write");
+        }
+        self.inode.check_integrity()?;
+        if len > i64::max_value() as usize {
+            return_errno!(EFBIG, "len is too large");
+        }
+
+        // The bytes beyond the new length in the last page are zeroed, which
+        // would otherwise show up again once the file is extended.
+        let zero_end = align_up(len, Page::size()).min(self.inode.len());
+        if len < zero_end {
+            self.write_zeros(len, zero_end - len).await?;
+        }
+        self.inode.resize(len).await
+    }
+
+    /// Manipulate the allocated space of the file.
+    ///
+    /// The supported modes are 0, which allocates the space and extends the
+    /// file if needed, `FALLOC_FL_KEEP_SIZE`, which allocates the space only,
+    /// and `FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE`, which deallocates the
+    /// space so that the range reads as zeros.
+    pub async fn fallocate(&self, mode: i32, offset: usize, len: usize) -> Result<()> {
+        if !self.can_write {
+            return_errno!(EBADF, "not open for write");
+        }
+        self.inode.check_integrity()?;
+        if len == 0 {
+            return_errno!(EINVAL, "len must be positive");
+        }
+        let end = offset
+            .checked_add(len)
+            .filter(|&end| end <= i64::max_value() as usize)
+            .ok_or_else(|| errno!(EFBIG, "offset or len is too large"))?;
+
+        match mode {
+            0 | FALLOC_FL_KEEP_SIZE => self.inode.allocate(mode, offset, len).await,
+            _ if mode == FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE => {
+                self.punch_hole(offset, end).await
+            }
+            _ => return_errno!(EOPNOTSUPP, "unsupported mode"),
+        }
+    }
+
+    async fn punch_hole(&self, offset: usize, end: usize) -> Result<()> {
+        let end = end.min(self.inode.len());
+        if offset >= end {
+            return Ok(());
+        }
+
+        // The partial pages at both ends are zeroed, while the whole pages in
+        // between are deallocated.
+        let hole_begin = align_up(offset, Page::size());
+        let hole_end = align_down(end, Page::size());
+        if hole_begin >= hole_end {
+            return self.write_zeros(offset, end - offset).await;
+        }
+        self.write_zeros(offset, hole_begin - offset).await?;
+        self.write_zeros(hole_end, end - hole_end).await?;
+        self.inode.punch_hole(hole_begin..hole_end).await
+    }
+
+    async fn write_zeros(&self, offset: usize, len: usize) -> Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        let zeros = vec![0; len];
+        let write_nbytes = self.write_exact_at(offset, &zeros).await?;
+        if write_nbytes < len {
+            return_errno!(EIO, "failed to write zeros");
+        }
+        Ok(())
+    }
+
+    /// Give advice on how the file is to be accessed through this open.
+    ///
+    /// `Normal`, `Random` and `Sequential` apply to the whole file, regardless
+    /// of the range, by tuning the prefetch of later reads. `WillNeed` starts
+    /// fetching the range into the page cache without waiting for it. A `len`
+    /// of zero means to the end of the file. `DontNeed` and `NoReuse` are
+    /// accepted but ignored.
+    pub fn fadvise(&self, advice: Advice, offset: usize, len: usize) -> Result<()> {
+        match advice {
+            Advice::Normal | Advice::Random | Advice::Sequential => {
+                self.seq_rd_tracker.lock().set_advice(advice);
+            }
+            Advice::WillNeed => {
+                let file_len = self.inode.len();
+                if offset < file_len {
+                    let max_len = file_len - offset;
+                    let len = if len == 0 { max_len } else { len.min(max_len) };
+                    self.inode.prefetch_pages(offset, len);
+                }
+            }
+            Advice::DontNeed | Advice::NoReuse => {}
+        }
+        Ok(())
+    }
+
+    /// Flush all dirty pages of the file to the host.
+    ///
+    /// Unlike `flush`, this method does not wait for the data to reach the disk.
+    /// It fails if writing back any pages of the file has failed, and the error
+    /// is yet to be reported by `flush` or `sync_data`.
+    pub async fn flush_dirty_pages(&self) -> Result<()> {
+        while self.flush_dirty_pages_once().await > 0 {}
+        self.inode.check_writeback_error()
+    }
+
+    /// Flush all dirty pages of the file and then sync the file to the disk.
+    ///
+    /// Just like Linux, it fails if writing back any pages of the file has failed
+    /// since the last `flush` or `sync_data`, even if the pages are written back
+    /// by the writeback daemon or for another open of the file.
+    pub async fn flush(&self) -> Result<()> {
+        while self.flush_dirty_pages_once().await > 0 {}
+        self.inode.take_writeback_error()?;
+        self.inode.persist_metadata().await?;
+
+        let complete_fn = move |_retval: i32| {};
+        let io_uring = Rt::io_uring();
+        let handle = unsafe { io_uring.fsync(Fd(self.inode.fd()), false, complete_fn) };
+        let retval = handle.await;
+        if retval < 0 {
+            return_errno!(Errno::from(-retval as u32), "fsync error");
+        }
+        Ok(())
+    }
+
+    /// Flush all dirty pages of the file and then sync the data of the file
+    /// to the disk, without the metadata that is not needed to read the data.
+    pub async fn sync_data(&self) -> Result<()> {
+        while self.flush_dirty_pages_once().await > 0 {}
+        self.inode.take_writeback_error()?;
+        // The metadata of a protected file is part of its data
+        self.inode.persist_metadata().await?;
+
+        let complete_fn = move |_retval: i32| {};
+        let io_uring = Rt::io_uring();
+        let handle = unsafe { io_uring.fsync(Fd(self.inode.fd()), true, complete_fn) };
+        let retval = handle.await;
+        if retval < 0 {
+            return_errno!(Errno::from(-retval as u32), "fdatasync error");
+        }
+        Ok(())
+    }
+
+    // Wait for the events that may make a retry of I/O succeed.
+    //
+    // The first call only starts polling without waiting so that the caller
+    // retries the I/O before any event could be missed.
+    async fn wait_events(&self, mask: Events, poller: &mut Option<Poller>) {
+        match poller {
+            Some(poller) => poller.wait().await,
+            None => {
+                let mut new_poller = Poller::new();
+                self.poll(mask, Some(&mut new_poller));
+                *poller = Some(new_poller);
+            }
         }
     }
 
+    async fn flush_dirty_pages_once(&self) -> usize {
+        const FLUSH_BATCH_SIZE: usize = 64;
+        Rt::flusher()
+            .flush_by_id(self.inode.id(), FLUSH_BATCH_SIZE)
+            .await
+    }
+
     fn do_read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
         if !self.can_read {
             return_errno!(EBADF, "not open for read");
         }
+        if self.direct_io.is_some() {
+            return_errno!(EINVAL, "direct I/O cannot be done without waiting");
+        }
+        self.inode.check_integrity()?;
+        self.inode.take_fetch_error()?;
         if buf.len() == 0 {
             return Ok(0);
         }
         self.check_args(offset, buf.len())?;
 
-        let file_len = *self.len.read();
+        let file_len = self.inode.len();
 
         // For reads beyond the end of the file
         if offset >= file_len {
@@ -246,243 +557,150 @@ impl<Rt: AsyncFileRt + ?Sized> AsyncFile<Rt> {
         let buf_len = buf.len().min(file_remaining);
         let buf = &mut buf[..buf_len];
 
-        // Use tracker to determine if the new read is sequential and how much data to prefetch.
+        // Use tracker to determine the access pattern of the new read and what to prefetch.
         let mut tracker = self.seq_rd_tracker.try_lock();
         let new_read = tracker
             .as_mut()
             .map(|tracker| tracker.track(offset, buf.len()));
-        let prefetch_len = {
-            let prefetch_len = new_read.as_ref().map_or(0, |seq_rd| seq_rd.prefetch_size());
-            let max_prefetch_len = file_remaining - buf.len();
-            prefetch_len.min(max_prefetch_len)
+        // The prefetch that immediately follows the read is merged into the fetch
+        // of the read, while the others are issued after the fetch.
+        let mut prefetch_len = 0;
+        let mut prefetch_ranges = Vec::new();
+        if let Some(new_read) = &new_read {
+            for range in new_read.prefetch_ranges() {
+                let range = range.start..range.end.min(file_len);
+                if range.start >= range.end {
+                    continue;
+                }
+                if range.start == offset + buf_len {
+                    prefetch_len = range.len();
+                } else {
+                    prefetch_ranges.push(range);
+                }
+            }
+        }
+
+        // A sequential read that continues in the page where the last read ends
+        // is not counted as a new access of the page.
+        let continued_page = match &new_read {
+            Some(new_read)
+                if new_read.pattern() == Some(Pattern::Sequential)
+                    && offset % Page::size() != 0 =>
+            {
+                Some(align_down(offset, Page::size()))
+            }
+            _ => None,
         };
+        let page_cache = Rt::page_cache();
 
         // Fetch the data to the page cache and copy the data of the first ready pages
         // in the page cache to the output buffer.
         let mut read_nbytes = 0;
-        self.fetch_pages(offset, buf_len, prefetch_len, |page_handle: &PageHandle| {
-            let page_slice = unsafe { page_handle.page().as_slice() };
-            let inner_offset = offset + read_nbytes - page_handle.offset();
-            let page_remain = Page::size() - inner_offset;
+        let is_first_page_missing =
+            self.inode
+                .fetch_pages(offset, buf_len, prefetch_len, |page_handle: &PageHandle| {
+                    if Some(page_handle.offset()) != continued_page {
+                        page_cache.mark_accessed(page_handle);
+                    }
 
-            let buf_remain = buf_len - read_nbytes;
-            let copy_size = buf_remain.min(page_remain);
-            let src_buf = &page_slice[inner_offset..inner_offset + copy_size];
-            let target_buf = &mut buf[read_nbytes..read_nbytes + copy_size];
-            target_buf.copy_from_slice(src_buf);
+                    let page_slice = unsafe { page_handle.page().as_slice() };
+                    let inner_offset = offset + read_nbytes - page_handle.offset();
+                    let page_remain = Page::size() - inner_offset;
 
-            read_nbytes += copy_size;
-        });
+                    let buf_remain = buf_len - read_nbytes;
+                    let copy_size = buf_remain.min(page_remain);
+                    let src_buf = &page_slice[inner_offset..inner_offset + copy_size];
+                    let target_buf = &mut buf[read_nbytes..read_nbytes + copy_size];
+                    target_buf.copy_from_slice(src_buf);
 
+                    read_nbytes += copy_size;
+                });
+        for range in prefetch_ranges {
+            self.inode.prefetch_pages(range.start, range.len());
+        }
+
+        if let Some(new_read) = new_read {
+            new_read.complete(read_nbytes, is_first_page_missing);
+        }
         if read_nbytes > 0 {
-            new_read.map(|new_read| new_read.complete(read_nbytes));
             Ok(read_nbytes)
         } else {
             return_errno!(EAGAIN, "try again later");
         }
     }
 
-    // Fetch and prefetch pages.
-    //
-    // The first pages in the fetch range [offset, offset + len) that are ready to read are passed
-    // to a closure so that the caller can access the data in these pages. Note that the state of the
-    // page is locked while the closure is being executed.
-    //
-    // The pages that are within the range [offset, offset + len + prefetch_len] will be fetched into
-    // the page cache, if they are not present in the page cache.
-    //
-    // The procedure works in two phases. The first phase is fetching, in which we iterate
-    // the first pages that are ready to read. These pages are passed to the access closure
-    // one-by-one. Upon reaching the first page that cannot be read or beyond the fetching
-    // range [offset, offset + len), we transit to the second phase: prefetching. In this
-    // phase, we will try out our best to bring the pages into the page cache,
-    // issueing async reads if needed.
-    fn fetch_pages(
+    fn do_write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        if !self.can_write {
+            return_errno!(EBADF, "not open for write");
+        }
+        if self.direct_io.is_some() {
+            return_errno!(EINVAL, "direct I/O cannot be done without waiting");
+        }
+        self.inode.check_integrity()?;
+        if buf.len() == 0 {
+            return Ok(0);
+        }
+        self.check_args(offset, buf.len())?;
+        // Writers wait for the writeback to catch up if there are too many
+        // dirty pages
+        if Rt::flusher().should_throttle() {
+            return_errno!(EAGAIN, "writes are throttled");
+        }
+
+        let write_nbytes = self.inode.write_pages(offset, buf);
+        if write_nbytes > 0 {
+            Ok(write_nbytes)
+        } else {
+            return_errno!(EAGAIN, "try again later");
+        }
+    }
+
+    async fn read_direct_at(
         &self,
+        direct_io: &DirectIo<Rt>,
         offset: usize,
-        len: usize,
-        prefetch_len: usize,
-        mut access_fn: impl FnMut(&PageHandle),
-    ) {
-        // If the first stage, the value is true; if the second stage, false.
-        let mut should_call_access_fn = true;
-        // Prepare for async read that fetches multiple consecutive pages
-        let mut consecutive_pages = Vec::new();
-
-        // Enter the loop that fetches and prefetches pages.
-        let arc_self = self.clone_arc();
-        let page_cache = Rt::page_cache();
-        let page_begin = align_down(offset, Page::size());
-        let page_end = align_up(offset + len + prefetch_len, Page::size());
-        let fetch_end = align_up(offset + len, Page::size());
-        for page_offset in (page_begin..page_end).step_by(Page::size()) {
-            if should_call_access_fn && page_offset >= fetch_end {
-                should_call_access_fn = false;
-            }
+        buf: &mut [u8],
+    ) -> Result<usize> {
+        if !self.can_read {
+            return_errno!(EBADF, "not open for read");
+        }
+        if buf.len() == 0 {
+            return Ok(0);
+        }
+        self.check_args(offset, buf.len())?;
+        DirectIo::<Rt>::check_alignment(offset, buf.as_ptr() as usize, buf.len())?;
 
-            let page = match page_cache.acquire(&arc_self, page_offset) {
-                Some(page) => page,
-                None => break,
-            };
-            let mut state = page.state();
-            if should_call_access_fn {
-                // The fetching phase
-                match *state {
-                    PageState::UpToDate | PageState::Dirty | PageState::Flushing => {
-                        // Invoke the access function
-                        (access_fn)(&page);
-
-                        drop(state);
-                        page_cache.release(page);
-                    }
-                    PageState::Uninit => {
-                        // Start prefetching
-                        *state = PageState::Fetching;
-                        drop(state);
-                        consecutive_pages.push(page);
-
-                        // Transit to the prefetching phase
-                        should_call_access_fn = false;
-                    }
-                    PageState::Fetching => {
-                        // We do nothing here
-                        drop(state);
-                        page_cache.release(page);
+        // The dirty pages must reach the host before the host file is read
+        let page_range =
+            align_down(offset, Page::size())..align_up(offset + buf.len(), Page::size());
+        self.inode.write_back_pages(page_range).await;
 
-                        // Transit to the prefetching phase
-                        should_call_access_fn = false;
-                    }
-                }
-            } else {
-                // The prefetching phase
-                match *state {
-                    PageState::Uninit => {
-                        // Add one more page to prefetch
-                        *state = PageState::Fetching;
-                        drop(state);
-                        consecutive_pages.push(page);
-                    }
-                    PageState::UpToDate
-                    | PageState::Dirty
-                    | PageState::Flushing
-                    | PageState::Fetching => {
-                        drop(state);
-                        page_cache.release(page);
-
-                        // When reaching the end of consecutive pages, start the I/O
-                        if consecutive_pages.len() > 0 {
-                            self.fetch_consecutive_pages(consecutive_pages);
-                            consecutive_pages = Vec::new();
-                        }
+        let mut read_nbytes = 0;
+        while read_nbytes < buf.len() {
+            let buf = &mut buf[read_nbytes..];
+            let buf_len = buf.len();
+            match direct_io.read_at(offset + read_nbytes, buf).await {
+                Ok(nbytes) => {
+                    read_nbytes += nbytes;
+                    // A short read hits the end of the file, after which the
+                    // offset is no longer aligned.
+                    if nbytes < buf_len.min(MAX_DIRECT_IO_SIZE) {
+                        break;
                     }
                 }
+                Err(_) if read_nbytes > 0 => break,
+                Err(e) => return Err(e),
             }
         }
-        // When reaching the end of consecutive pages, start the I/O
-        if consecutive_pages.len() > 0 {
-            self.fetch_consecutive_pages(consecutive_pages);
-        }
-    }
-
-    fn fetch_consecutive_pages(&self, consecutive_pages: Vec<PageHandle>) {
-        debug_assert!(!consecutive_pages.is_empty());
-        debug_assert!(consecutive_pages.windows(2).all(|two_pages| {
-            let (p0, p1) = (&two_pages[0], &two_pages[1]);
-            p0.offset() + Page::size() == p1.offset()
-        }));
-        debug_assert!(consecutive_pages
-            .iter()
-            .all(|page| { *page.state() == PageState::Fetching }));
-
-        let first_offset = consecutive_pages[0].offset();
-        let self_ = self.clone_arc();
-        let iovecs = Box::new(
-            consecutive_pages
-                .iter()
-                .map(|page_handle| libc::iovec {
-                    iov_base: page_handle.page().as_mut_ptr() as _,
-                    iov_len: Page::size(),
-                })
-                .collect::<Vec<libc::iovec>>(),
-        );
-        #[cfg(not(feature = "sgx"))]
-        let (iovecs_ptr, iovecs_len) = ((*iovecs).as_ptr(), (*iovecs).len());
-        #[cfg(feature = "sgx")]
-        let (iovecs_ptr, iovecs_len, allocator, iovecs_ptr_u64, t_iovecs_ptr_u64) = {
-            let iovecs_len = (*iovecs).len();
-            let t_iovecs_ptr = (*iovecs).as_ptr();
-            let iovecs_size = iovecs_len * core::mem::size_of::<libc::iovec>();
-            let allocator = UntrustedAllocator::new(iovecs_size, 8).unwrap();
-            let iovecs_ptr = allocator.as_mut_ptr() as *mut libc::iovec;
-            unsafe {
-                std::ptr::copy_nonoverlapping(t_iovecs_ptr, iovecs_ptr, iovecs_len);
-            }
-            (
-                iovecs_ptr,
-                iovecs_len,
-                allocator,
-                iovecs_ptr as u64,
-                t_iovecs_ptr as u64,
-            )
-        };
-
-        struct IovecsBox(Box<Vec<libc::iovec>>);
-        unsafe impl Send for IovecsBox {}
-        let iovecs_box = IovecsBox(iovecs);
-
-        let handle_store: Arc<Mutex<Option<Handle>>> = Arc::new(Mutex::new(None));
-        let handle_store2 = handle_store.clone();
-
-        let callback = move |retval| {
-            let page_cache = Rt::page_cache();
-            let read_nbytes = if retval >= 0 { retval } else { 0 } as usize;
-            for page in consecutive_pages {
-                let page_offset = page.offset();
-                debug_assert!(page_offset >= first_offset);
-
-                // For a partial read, fill zeros or in the remaining part of the page.
-                // TODO: are there partial reads that should not fill zeros?
-                let page_valid_nbytes = if first_offset + read_nbytes > page_offset {
-                    (first_offset + read_nbytes - page_offset).min(Page::size())
-                } else {
-                    0
-                };
-                if page_valid_nbytes < Page::size() {
-                    let page_slice = unsafe { page.page().as_slice_mut() };
-                    page_slice[page_valid_nbytes..].fill(0);
-                }
-
-                // Update page state
-                {
-                    let mut state = page.state();
-                    debug_assert!(*state == PageState::Fetching);
-                    *state = PageState::UpToDate;
-                }
-                page_cache.release(page);
-            }
-            self_.pollee.add_events(Events::IN | Events::OUT);
-
-            #[cfg(feature = "sgx")]
-            drop(allocator);
-            drop(iovecs_box);
-            drop(handle_store);
-        };
-        let io_uring = Rt::io_uring();
-        let handle = unsafe {
-            io_uring.readv(
-                Fd(self.fd),
-                iovecs_ptr,
-                iovecs_len as u32,
-                first_offset as i64,
-                0,
-                callback,
-            )
-        };
-        let mut guard = handle_store2.lock();
-        guard.replace(handle);
+        Ok(read_nbytes)
     }
 
-    fn do_write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+    async fn write_direct_at(
+        &self,
+        direct_io: &DirectIo<Rt>,
+        offset: usize,
+        buf: &[u8],
+    ) -> Result<usize> {
         if !self.can_write {
             return_errno!(EBADF, "not open for write");
         }
@@ -490,95 +708,37 @@ impl<Rt: AsyncFileRt + ?Sized> AsyncFile<Rt> {
             return Ok(0);
         }
         self.check_args(offset, buf.len())?;
+        DirectIo::<Rt>::check_alignment(offset, buf.as_ptr() as usize, buf.len())?;
 
-        let mut new_dirty_pages = false;
-        let mut write_nbytes = 0;
-        let arc_self = self.clone_arc();
-        let page_cache = Rt::page_cache();
-        let page_begin = align_down(offset, Page::size());
-        let page_end = align_up(offset + buf.len(), Page::size());
-        for page_offset in (page_begin..page_end).step_by(Page::size()) {
-            let page_handle = match page_cache.acquire(&arc_self, page_offset) {
-                Some(page_handle) => page_handle,
-                None => break,
-            };
-            let inner_offset = offset + write_nbytes - page_offset;
-
-            let copy_size = {
-                let page_remain = Page::size() - inner_offset;
-                let buf_remain = buf.len() - write_nbytes;
-                buf_remain.min(page_remain)
-            };
-            let to_write_full_page = copy_size == Page::size();
-
-            let mut do_write = || {
-                let page_slice = unsafe { page_handle.page().as_slice_mut() };
+        // The dirty pages that are partially overwritten must reach the host
+        // first. Otherwise, the rest of their data would be lost once the pages
+        // are discarded.
+        let page_range =
+            align_down(offset, Page::size())..align_up(offset + buf.len(), Page::size());
+        self.inode.write_back_pages(page_range.clone()).await;
 
-                let src_buf = &buf[write_nbytes..write_nbytes + copy_size];
-                let dst_buf = &mut page_slice[inner_offset..inner_offset + copy_size];
-                dst_buf.copy_from_slice(src_buf);
-
-                write_nbytes += copy_size;
-            };
-
-            let mut state = page_handle.state();
-            match *state {
-                PageState::UpToDate => {
-                    (do_write)();
-
-                    *state = PageState::Dirty;
-                    drop(state);
-                    page_cache.release(page_handle);
-
-                    new_dirty_pages = true;
-                }
-                PageState::Dirty => {
-                    (do_write)();
-
-                    drop(state);
-                    page_cache.release(page_handle);
-                }
-                PageState::Uninit if to_write_full_page => {
-                    (do_write)();
-
-                    *state = PageState::Dirty;
-                    drop(state);
-                    page_cache.release(page_handle);
-
-                    new_dirty_pages = true;
-                }
-                PageState::Uninit => {
-                    *state = PageState::Fetching;
-                    drop(state);
-
-                    self.fetch_consecutive_pages(vec![page_handle]);
-                    break;
-                }
-                PageState::Fetching | PageState::Flushing => {
-                    // We do nothing here
-                    drop(state);
-                    page_cache.release(page_handle);
-
-                    break;
+        let mut write_nbytes = 0;
+        while write_nbytes < buf.len() {
+            let buf = &buf[write_nbytes..];
+            match direct_io.write_at(offset + write_nbytes, buf).await {
+                Ok(nbytes) => {
+                    write_nbytes += nbytes;
+                    if nbytes < buf.len().min(MAX_DIRECT_IO_SIZE) {
+                        break;
+                    }
                 }
+                Err(_) if write_nbytes > 0 => break,
+                Err(e) => return Err(e),
             }
         }
-
-        if new_dirty_pages {
-            Rt::auto_flush();
-        }
-
         if write_nbytes > 0 {
-            // Update file length if necessary
-            let mut file_len = self.len.write();
-            if offset + write_nbytes > *file_len {
-                *file_len = offset + write_nbytes;
-            }
-
-            Ok(write_nbytes)
-        } else {
-            return_errno!(EAGAIN, "try again later");
+            self.inode.extend_len(offset + write_nbytes);
         }
+
+        // The cached pages, including the ones fetched during the write, may
+        // hold the old data
+        self.inode.discard_pages(page_range).await;
+        Ok(write_nbytes)
     }
 
     fn check_args(&self, offset: usize, buf_len: usize) -> Result<()> {
@@ -596,38 +756,63 @@ impl<Rt: AsyncFileRt + ?Sized> AsyncFile<Rt> {
     pub fn clone_arc(&self) -> Arc<Self> {
         self.weak_self.upgrade().unwrap()
     }
-
-    pub(crate) fn pollee(&self) -> &Pollee {
-        &self.pollee
-    }
-}
-
-impl<Rt: AsyncFileRt + ?Sized> AsFd for AsyncFile<Rt> {
-    fn as_fd(&self) -> i32 {
-        self.fd
-    }
 }
 
 impl<Rt: AsyncFileRt + ?Sized> Drop for AsyncFile<Rt> {
     fn drop(&mut self) {
-        unsafe {
-            #[cfg(not(feature = "sgx"))]
-            libc::close(self.fd);
-            #[cfg(feature = "sgx")]
-            libc::ocall::close(self.fd);
-        }
+        self.inode.close();
     }
 }
 
 impl<Rt: AsyncFileRt + ?Sized> std::fmt::Debug for AsyncFile<Rt> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AsyncFile")
-            .field("fd", &self.fd)
-            .field("pollee", self.pollee())
+            .field("inode", &self.inode)
+            .field("pos", &*self.pos.lock())
             .finish()
     }
 }
 
+// Returns the identity of the host file of an fd.
+fn file_id_of(fd: i32) -> Result<FileId> {
+    #[cfg(not(feature = "sgx"))]
+    let (ret, stat) = unsafe {
+        let mut stat: libc::stat = std::mem::zeroed();
+        let ret = libc::fstat(fd, &mut stat);
+        (ret, stat)
+    };
+    #[cfg(feature = "sgx")]
+    let (ret, stat) = unsafe {
+        let mut stat: libc::stat64 = std::mem::zeroed();
+        let ret = libc::ocall::fstat64(fd, &mut stat);
+        (ret, stat)
+    };
+    if ret < 0 {
+        return_errno!(Errno::from(libc_errno() as u32), "libc::fstat error");
+    }
+    Ok(FileId::new(stat.st_dev as u64, stat.st_ino as u64))
+}
+
+fn ftruncate_fd(fd: i32, len: usize) -> Result<()> {
+    #[cfg(not(feature = "sgx"))]
+    let ret = unsafe { libc::ftruncate(fd, len as libc::off_t) };
+    #[cfg(feature = "sgx")]
+    let ret = unsafe { libc::ocall::ftruncate64(fd, len as libc::off64_t) };
+    if ret < 0 {
+        return_errno!(Errno::from(libc_errno() as u32), "libc::ftruncate error");
+    }
+    Ok(())
+}
+
+fn close_fd(fd: i32) {
+    unsafe {
+        #[cfg(not(feature = "sgx"))]
+        libc::close(fd);
+        #[cfg(feature = "sgx")]
+        libc::ocall::close(fd);
+    }
+}
+
 #[cfg(not(feature = "sgx"))]
 fn libc_errno() -> i32 {
     unsafe {
@@ -653,7 +838,7 @@ mod tests {
         let path = "write_first_page.data";
         let file = {
             let path = path.to_string();
-            let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC;
+            let flags = libc::O_WRONLY | libc::O_CREAT;
             let mode = libc::S_IRUSR | libc::S_IWUSR;
             AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
         };
@@ -661,7 +846,7 @@ mod tests {
         input_buf.resize(input_buf.capacity(), 0);
 
         b.iter(|| {
-            let nbytes = file.file().write_at(0, &input_buf).unwrap();
+            let nbytes = file.write_at(0, &input_buf).unwrap();
             assert!(nbytes == input_buf.len());
         })
     }
@@ -694,16 +879,16 @@ mod tests {
         let path = "read_first_page.data";
         let file = {
             let path = path.to_string();
-            let flags = libc::O_RDWR | libc::O_CREAT | libc::O_TRUNC;
+            let flags = libc::O_RDWR | libc::O_CREAT;
             let mode = libc::S_IRUSR | libc::S_IWUSR;
             AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
         };
         let mut buf = Vec::with_capacity(4096);
         buf.resize(buf.capacity(), 0);
-        file.file().write_at(0, &buf).unwrap();
+        file.write_at(0, &buf).unwrap();
 
         b.iter(|| {
-            let nbytes = file.file().read_at(0, &mut buf).unwrap();
+            let nbytes = file.read_at(0, &mut buf).unwrap();
             assert!(nbytes == buf.len());
         })
     }
@@ -0,0 +1,502 @@
+//! The protection of files with encryption and authentication.
+//!
+//! The pages of a protected file are encrypted with AES-GCM before being written
+//! to the host, and are decrypted and verified after being read from the host.
+//! The nonces and MACs of the pages are organized into a MAC tree, whose root is
+//! kept in the header of the file. So not only the tampering of a page, but also
+//! the rollback of some pages to their older versions, can be detected.
+//!
+//! # Layout
+//!
+//! A protected file on the host begins with a header page, which is followed by
+//! groups of pages. A group consists of a node page and up to 128 data pages.
+//!
+//! ```text
+//! | header | node 0 | data 0 ... data 127 | node 1 | data 128 ... data 255 | ...
+//! ```
+//!
+//! A node page holds the nonces and MACs of the data pages in its group. The
+//! header holds the length of the file, as well as the root MAC, which is the
+//! CMAC of the header and all node pages. A data page whose nonce and MAC are all
+//! zeros has never been written and is read as zeros.
+//!
+//! # Limitations
+//!
+//! All node pages are loaded and verified when a file is opened, and stay in
+//! memory until the file is closed. They take 1/128 of the size of the file.
+//!
+//! The update of a protected file is not atomic. If the host crashes in the
+//! middle of a flush, the file may fail the verification when it is opened again.
+//! And since the key of a file does not change, the rollback of a whole file to
+//! an older version cannot be detected.
+
+use spin::Mutex;
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+use std::ops::Range;
+#[cfg(feature = "sgx")]
+use std::prelude::v1::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use async_io::prelude::{Result, *};
+use async_rt::wait::WaiterQueue;
+use async_rt::waiter_loop;
+use io_uring_callback::{Fd, IoUring};
+use sgx_untrusted_alloc::UntrustedBox;
+
+use super::libc_errno;
+use crate::page_cache::Page;
+use crate::util::align_up;
+use crate::util::crypto::{self, Key, Mac, Nonce};
+
+/// The number of data pages in a group.
+const PAGES_PER_GROUP: usize = Page::size() / Entry::SIZE;
+const MAGIC: [u8; 8] = *b"AFILEPF1";
+
+pub(crate) struct Protection {
+    key: Key,
+    tree: Mutex<MacTree>,
+    is_corrupted: AtomicBool,
+    // Persisting the metadata is serialized so that an older version of the
+    // metadata never overwrites a newer one on the host.
+    is_persisting: AtomicBool,
+    persist_waiters: WaiterQueue,
+}
+
+impl Protection {
+    /// Load and verify the metadata of a protected file, returning the protection
+    /// and the length of the file.
+    ///
+    /// An empty host file is considered as a new protected file.
+    pub fn load(key: Key, fd: i32, host_len: usize) -> Result<(Self, usize)> {
+        let (tree, len) = if host_len == 0 {
+            (MacTree::new(), 0)
+        } else {
+            MacTree::load(&key, fd, host_len)?
+        };
+        let new_self = Self {
+            key,
+            tree: Mutex::new(tree),
+            is_corrupted: AtomicBool::new(false),
+            is_persisting: AtomicBool::new(false),
+            persist_waiters: WaiterQueue::new(),
+        };
+        Ok((new_self, len))
+    }
+
+    /// Returns the offset in the host file of the page at a given offset.
+    pub fn host_offset(offset: usize) -> usize {
+        debug_assert!(offset % Page::size() == 0);
+        let page_idx = offset / Page::size();
+        let (node_idx, slot) = (page_idx / PAGES_PER_GROUP, page_idx % PAGES_PER_GROUP);
+        node_host_offset(node_idx) + (1 + slot) * Page::size()
+    }
+
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    /// Drop the metadata of the pages beyond a given length, which read as
+    /// zeros if the file is extended later.
+    ///
+    /// The host file is left as is since its pages beyond the length are never
+    /// read again.
+    pub fn truncate(&self, len: usize) {
+        let num_pages = align_up(len, Page::size()) / Page::size();
+        self.tree.lock().truncate(num_pages);
+    }
+
+    /// Clear the metadata of the pages within a range of offsets, which then
+    /// read as zeros.
+    pub fn clear_pages(&self, range: Range<usize>) {
+        debug_assert!(range.start % Page::size() == 0 && range.end % Page::size() == 0);
+        let page_range = range.start / Page::size()..range.end / Page::size();
+        self.tree.lock().clear_entries(page_range);
+    }
+
+    /// Returns whether a page of the file has ever failed the verification.
+    pub fn is_corrupted(&self) -> bool {
+        self.is_corrupted.load(Ordering::Acquire)
+    }
+
+    /// Encrypt the page at a given offset, which is about to be written to the host.
+    ///
+    /// The returned nonce and MAC of the page must be committed by `commit_page`
+    /// after the page is written to the host.
+    pub fn encrypt_page(&self, offset: usize, src: &[u8], dst: &mut [u8]) -> Entry {
+        let page_idx = offset / Page::size();
+        let mut nonce = Nonce::default();
+        crypto::fill_random(&mut nonce);
+        let mac = crypto::encrypt(&self.key, &nonce, &page_aad(page_idx), src, dst);
+        Entry { nonce, mac }
+    }
+
+    /// Commit the nonce and MAC of the page at a given offset, which has been
+    /// written to the host.
+    pub fn commit_page(&self, offset: usize, entry: Entry) {
+        let page_idx = offset / Page::size();
+        self.tree.lock().set_entry(page_idx, entry);
+    }
+
+    /// Decrypt and verify the page at a given offset, which is read from the host.
+    ///
+    /// The buffer must be in trusted memory. Upon a failure, the whole file is
+    /// considered as corrupted.
+    pub fn decrypt_page(&self, offset: usize, buf: &mut [u8]) -> Result<()> {
+        let page_idx = offset / Page::size();
+        let entry = self.tree.lock().entry(page_idx);
+        if entry.is_empty() {
+            buf.fill(0);
+            return Ok(());
+        }
+
+        crypto::decrypt(
+            &self.key,
+            &entry.nonce,
+            &page_aad(page_idx),
+            &entry.mac,
+            buf,
+        )
+        .map_err(|e| {
+            self.is_corrupted.store(true, Ordering::Release);
+            e
+        })
+    }
+
+    /// Persist the metadata that has been updated since the last persisting.
+    ///
+    /// The metadata includes the node pages and the header, the latter of which
+    /// records the given length of the file.
+    pub async fn persist(&self, fd: i32, len: usize, io_uring: &IoUring) -> Result<()> {
+        let _guard = self.lock_persist().await;
+
+        let (writes, dirty_nodes) = match self.prepare_persist(len) {
+            Some(writes) => writes,
+            None => return Ok(()),
+        };
+        let handles: Vec<_> = writes
+            .into_iter()
+            .map(|(offset, buf)| {
+                let buf_ptr = buf.as_ptr() as *const u8;
+                let callback = move |_retval: i32| {
+                    drop(buf);
+                };
+                unsafe {
+                    io_uring.write(
+                        Fd(fd),
+                        buf_ptr,
+                        Page::size() as u32,
+                        offset as i64,
+                        0,
+                        callback,
+                    )
+                }
+            })
+            .collect();
+
+        let mut res = Ok(());
+        for handle in handles {
+            let retval = handle.await;
+            if res.is_err() {
+                continue;
+            }
+            if retval < 0 {
+                res = Err(errno!(
+                    Errno::from(-retval as u32),
+                    "failed to write metadata"
+                ));
+            } else if retval as usize != Page::size() {
+                res = Err(errno!(EIO, "short write of metadata"));
+            }
+        }
+        if res.is_err() {
+            // Try again in the next persisting
+            let mut tree = self.tree.lock();
+            tree.dirty_nodes.extend(dirty_nodes);
+            tree.header_len = None;
+        }
+        res
+    }
+
+    // Serialize the metadata to be persisted into bounce buffers.
+    fn prepare_persist(
+        &self,
+        len: usize,
+    ) -> Option<(Vec<(usize, UntrustedBox<[u8]>)>, BTreeSet<usize>)> {
+        let mut tree = self.tree.lock();
+        tree.resize(num_nodes(len));
+        if tree.dirty_nodes.is_empty() && tree.header_len == Some(len) {
+            return None;
+        }
+
+        let dirty_nodes = std::mem::take(&mut tree.dirty_nodes);
+        let mut writes = Vec::with_capacity(dirty_nodes.len() + 1);
+        let mut page = vec![0; Page::size()];
+        for &node_idx in &dirty_nodes {
+            tree.node_to_bytes(node_idx, &mut page);
+            tree.node_macs[node_idx] = node_mac(&self.key, node_idx, &page);
+            writes.push((node_host_offset(node_idx), UntrustedBox::new_slice(&page)));
+        }
+        tree.header_to_bytes(&self.key, len, &mut page);
+        writes.push((0, UntrustedBox::new_slice(&page)));
+        tree.header_len = Some(len);
+        Some((writes, dirty_nodes))
+    }
+
+    async fn lock_persist(&self) -> PersistGuard<'_> {
+        waiter_loop!(&self.persist_waiters, {
+            if self
+                .is_persisting
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                break;
+            }
+        });
+        PersistGuard(self)
+    }
+}
+
+impl std::fmt::Debug for Protection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tree = self.tree.lock();
+        f.debug_struct("Protection")
+            .field("num_nodes", &tree.nodes.len())
+            .field("num_dirty_nodes", &tree.dirty_nodes.len())
+            .field("is_corrupted", &self.is_corrupted())
+            .finish()
+    }
+}
+
+struct PersistGuard<'a>(&'a Protection);
+
+impl<'a> Drop for PersistGuard<'a> {
+    fn drop(&mut self) {
+        self.0.is_persisting.store(false, Ordering::Release);
+        self.0.persist_waiters.wake_all();
+    }
+}
+
+/// The nonce and MAC of a data page.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub(crate) struct Entry {
+    nonce: Nonce,
+    mac: Mac,
+}
+
+impl Entry {
+    /// The size of an entry in a node page, which is padded to a power of two.
+    const SIZE: usize = 32;
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let nonce_end = std::mem::size_of::<Nonce>();
+        let mac_end = nonce_end + std::mem::size_of::<Mac>();
+        Self {
+            nonce: bytes[..nonce_end].try_into().unwrap(),
+            mac: bytes[nonce_end..mac_end].try_into().unwrap(),
+        }
+    }
+
+    fn to_bytes(&self, bytes: &mut [u8]) {
+        let nonce_end = std::mem::size_of::<Nonce>();
+        let mac_end = nonce_end + std::mem::size_of::<Mac>();
+        bytes[..nonce_end].copy_from_slice(&self.nonce);
+        bytes[nonce_end..mac_end].copy_from_slice(&self.mac);
+        bytes[mac_end..Self::SIZE].fill(0);
+    }
+}
+
+type Node = Box<[Entry; PAGES_PER_GROUP]>;
+
+/// The in-memory MAC tree of a protected file.
+struct MacTree {
+    nodes: Vec<Node>,
+    // The MACs of the nodes as they were loaded or last persisted
+    node_macs: Vec<Mac>,
+    dirty_nodes: BTreeSet<usize>,
+    // The length of the file recorded in the header on the host, if known
+    header_len: Option<usize>,
+}
+
+impl MacTree {
+    fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            node_macs: Vec::new(),
+            dirty_nodes: BTreeSet::new(),
+            header_len: None,
+        }
+    }
+
+    fn load(key: &Key, fd: i32, host_len: usize) -> Result<(Self, usize)> {
+        let mut page = vec![0; Page::size()];
+        read_page(fd, 0, &mut page)?;
+        if page[..8] != MAGIC {
+            return_errno!(EINVAL, "not a protected file");
+        }
+        // The length in the header is untrusted until the root MAC is verified.
+        // So it is bounded by the host file before the nodes are read.
+        let len = u64::from_le_bytes(page[8..16].try_into().unwrap());
+        if len > max_len(host_len) as u64 {
+            return_errno!(EIO, "the protected file is corrupted");
+        }
+        let len = len as usize;
+        let root_mac: Mac = page[16..32].try_into().unwrap();
+
+        let mut tree = Self::new();
+        for node_idx in 0..num_nodes(len) {
+            read_page(fd, node_host_offset(node_idx), &mut page)?;
+            let mut node = Box::new([Entry::default(); PAGES_PER_GROUP]);
+            for (entry, bytes) in node.iter_mut().zip(page.chunks(Entry::SIZE)) {
+                *entry = Entry::from_bytes(bytes);
+            }
+            tree.nodes.push(node);
+            tree.node_macs.push(node_mac(key, node_idx, &page));
+        }
+        if tree.root_mac(key, len) != root_mac {
+            return_errno!(EIO, "the protected file is corrupted");
+        }
+        tree.header_len = Some(len);
+        Ok((tree, len))
+    }
+
+    fn entry(&self, page_idx: usize) -> Entry {
+        self.nodes
+            .get(page_idx / PAGES_PER_GROUP)
+            .map_or(Entry::default(), |node| node[page_idx % PAGES_PER_GROUP])
+    }
+
+    fn set_entry(&mut self, page_idx: usize, entry: Entry) {
+        let node_idx = page_idx / PAGES_PER_GROUP;
+        if node_idx >= self.nodes.len() {
+            self.resize(node_idx + 1);
+        }
+        self.nodes[node_idx][page_idx % PAGES_PER_GROUP] = entry;
+        self.dirty_nodes.insert(node_idx);
+    }
+
+    fn clear_entries(&mut self, page_range: Range<usize>) {
+        for page_idx in page_range {
+            let node_idx = page_idx / PAGES_PER_GROUP;
+            if node_idx >= self.nodes.len() {
+                break;
+            }
+            let entry = &mut self.nodes[node_idx][page_idx % PAGES_PER_GROUP];
+            if !entry.is_empty() {
+                *entry = Entry::default();
+                self.dirty_nodes.insert(node_idx);
+            }
+        }
+    }
+
+    fn truncate(&mut self, num_pages: usize) {
+        let num_nodes = align_up(num_pages, PAGES_PER_GROUP) / PAGES_PER_GROUP;
+        if num_nodes < self.nodes.len() {
+            self.nodes.truncate(num_nodes);
+            self.node_macs.truncate(num_nodes);
+            self.dirty_nodes.split_off(&num_nodes);
+        }
+        self.clear_entries(num_pages..num_nodes * PAGES_PER_GROUP);
+    }
+
+    fn resize(&mut self, num_nodes: usize) {
+        let old_num_nodes = self.nodes.len();
+        if num_nodes <= old_num_nodes {
+            return;
+        }
+        self.nodes
+            .resize_with(num_nodes, || Box::new([Entry::default(); PAGES_PER_GROUP]));
+        self.node_macs.resize(num_nodes, Mac::default());
+        self.dirty_nodes.extend(old_num_nodes..num_nodes);
+    }
+
+    fn node_to_bytes(&self, node_idx: usize, page: &mut [u8]) {
+        for (entry, bytes) in self.nodes[node_idx]
+            .iter()
+            .zip(page.chunks_mut(Entry::SIZE))
+        {
+            entry.to_bytes(bytes);
+        }
+    }
+
+    fn header_to_bytes(&self, key: &Key, len: usize, page: &mut [u8]) {
+        page.fill(0);
+        page[..16].copy_from_slice(&header_fields(len));
+        page[16..32].copy_from_slice(&self.root_mac(key, len));
+    }
+
+    fn root_mac(&self, key: &Key, len: usize) -> Mac {
+        let header_fields = header_fields(len);
+        let parts: Vec<&[u8]> = std::iter::once(&header_fields[..])
+            .chain(self.node_macs.iter().map(|mac| &mac[..]))
+            .collect();
+        crypto::cmac(key, &parts)
+    }
+}
+
+fn header_fields(len: usize) -> [u8; 16] {
+    let mut fields = [0; 16];
+    fields[..8].copy_from_slice(&MAGIC);
+    fields[8..].copy_from_slice(&(len as u64).to_le_bytes());
+    fields
+}
+
+fn node_mac(key: &Key, node_idx: usize, page: &[u8]) -> Mac {
+    crypto::cmac(key, &[&(node_idx as u64).to_le_bytes(), page])
+}
+
+// The additional authenticated data of a data page, which binds the page to its
+// position in the file.
+fn page_aad(page_idx: usize) -> [u8; 8] {
+    (page_idx as u64).to_le_bytes()
+}
+
+fn node_host_offset(node_idx: usize) -> usize {
+    (1 + node_idx * (1 + PAGES_PER_GROUP)) * Page::size()
+}
+
+// Returns the max length of a file whose nodes fit in a host file of a given length.
+fn max_len(host_len: usize) -> usize {
+    // The header is followed by the groups of pages, each of which is led by its node
+    let group_host_len = (1 + PAGES_PER_GROUP) * Page::size();
+    let num_groups =
+        align_up(host_len.saturating_sub(Page::size()), group_host_len) / group_host_len;
+    num_groups * PAGES_PER_GROUP * Page::size()
+}
+
+// Returns the number of nodes needed by a file of a given length.
+fn num_nodes(len: usize) -> usize {
+    let num_pages = align_up(len, Page::size()) / Page::size();
+    align_up(num_pages, PAGES_PER_GROUP) / PAGES_PER_GROUP
+}
+
+// Read a whole page from the host synchronously, which is only done when a
+// protected file is opened.
+fn read_page(fd: i32, offset: usize, buf: &mut [u8]) -> Result<()> {
+    let mut read_nbytes = 0;
+    while read_nbytes < buf.len() {
+        let ptr = buf[read_nbytes..].as_mut_ptr() as *mut libc::c_void;
+        let len = buf.len() - read_nbytes;
+        let offset = (offset + read_nbytes) as i64;
+        #[cfg(not(feature = "sgx"))]
+        let ret = unsafe { libc::pread(fd, ptr, len, offset) };
+        #[cfg(feature = "sgx")]
+        let ret = unsafe { libc::ocall::pread64(fd, ptr, len, offset) };
+        if ret < 0 {
+            let errno = libc_errno();
+            if errno == libc::EINTR {
+                continue;
+            }
+            return_errno!(Errno::from(errno as u32), "failed to read metadata");
+        }
+        if ret == 0 {
+            return_errno!(EIO, "the protected file is truncated");
+        }
+        read_nbytes += ret as usize;
+    }
+    Ok(())
+}
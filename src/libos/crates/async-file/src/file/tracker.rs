@@ -1,166 +1,476 @@
+use std::ops::Range;
 #[cfg(feature = "sgx")]
 use std::prelude::v1::*;
 
 use crate::page_cache::Page;
+use crate::util::align_up;
 
 /// A few tuning knobs for the sequential read tracker.
 pub const MIN_PREFETCH_SIZE: usize = Page::size();
 pub const MAX_PREFETCH_SIZE: usize = 64 * Page::size();
+/// The maximum number of streams that are tracked at the same time.
+pub const MAX_STREAMS: usize = 8;
+/// The maximum distance between the starts of two reads of a strided stream.
+pub const MAX_STRIDE: usize = 256 * Page::size();
 
-/// A read tracker that can determine whether a new read is sequential or not.
+/// The advice on how a file is to be accessed, as given by `posix_fadvise`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Advice {
+    /// No advice is given, which is the default.
+    Normal,
+    /// The file is to be read in random order, so nothing is prefetched.
+    Random,
+    /// The file is to be read sequentially, so the prefetch is more aggressive.
+    Sequential,
+    /// A range of the file is to be accessed in the near future.
+    WillNeed,
+    /// A range of the file is not to be accessed in the near future.
+    DontNeed,
+    /// A range of the file is to be accessed only once.
+    NoReuse,
+}
+
+/// The access pattern of a stream of reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    /// Each read starts where the last one ends.
+    Sequential,
+    /// Each read ends where the last one starts.
+    Backward,
+    /// Each read starts at a fixed distance, which may be negative, from
+    /// where the last one starts.
+    Strided(isize),
+}
+
+/// A read tracker that can determine the access patterns of new reads.
+///
+/// The tracker follows several streams of reads at the same time, so that
+/// the readers that interleave their reads on different regions of a file do
+/// not defeat the prefetch of each other. Each stream has its own prefetch
+/// window, which grows when the prefetched pages are read and shrinks when
+/// they are evicted before being read.
 pub struct SeqRdTracker {
-    // A new read is considered sequential if and only if the new read starts at
-    // this position.
-    last_rd_end: usize,
-    // The end of the region that we have issued prefetch
-    prefetch_end: usize,
-    // The size of the next prefetch, increasing with the number of consecutive
-    // sequential reads. If the size is 0, then the last read received by the
-    // tracker is not sequential.
-    prefetch_size: usize,
+    // The streams, from the least recently used to the most recently used
+    streams: Vec<Stream>,
+    advice: Advice,
+}
+
+struct Stream {
+    // The range of the last read
+    last_offset: usize,
+    last_end: usize,
+    // The distance from the start of the read before the last one to that of
+    // the last one, which is the candidate stride of the stream
+    last_distance: isize,
+    // The pattern of the stream, or None if it is not recognized yet
+    pattern: Option<Pattern>,
+    // How far the prefetch of the stream goes: the end of the prefetched
+    // region for a sequential stream, the start of it for a backward stream,
+    // and the start of the farthest prefetched read for a strided stream
+    prefetch_pos: usize,
+    // The size of the next prefetch
+    window: usize,
+    // Whether a prefetched page has been evicted before being read since the
+    // last read completed
+    has_thrashed: bool,
 }
 
 impl SeqRdTracker {
     pub fn new() -> Self {
         Self {
-            last_rd_end: 0,
-            prefetch_end: 0,
-            prefetch_size: 0,
+            streams: Vec::new(),
+            advice: Advice::Normal,
         }
     }
 
+    /// Set the advice on the access pattern of the file.
+    ///
+    /// Only `Normal`, `Random` and `Sequential` affect the tracker.
+    pub fn set_advice(&mut self, advice: Advice) {
+        self.advice = advice;
+    }
+
     pub fn track<'a>(&'a mut self, offset: usize, len: usize) -> NewRead<'a> {
-        // Handle the cases when the new read is NOT considered sequential
-        if offset != self.last_rd_end {
-            self.prefetch_size = 0;
-            return NewRead::Random {
-                tracker: self,
-                offset,
-            };
-        }
+        debug_assert!(len > 0);
+        let advice = self.advice;
+        let matched_idx = self
+            .streams
+            .iter()
+            .rposition(|stream| stream.classify(offset, len).is_some());
+        let (stream_idx, pattern) = match matched_idx {
+            Some(idx) => (Some(idx), self.streams[idx].classify(offset, len)),
+            // A read that matches no stream starts a sequential one if so advised
+            None if advice == Advice::Sequential => (None, Some(Pattern::Sequential)),
+            // Otherwise, the read may be the second one of a strided stream,
+            // which is recognized by the next read
+            None => {
+                let idx = self.streams.iter().rposition(|stream| {
+                    stream.pattern.is_none()
+                        && distance(stream.last_offset, offset).abs() as usize <= MAX_STRIDE
+                });
+                (idx, None)
+            }
+        };
 
-        // If this new read is the first sequential read
-        if self.prefetch_size == 0 {
-            self.prefetch_end = offset;
-            self.prefetch_size = MIN_PREFETCH_SIZE;
+        let mut is_prefetched = false;
+        let mut prefetch_ranges = Vec::new();
+        if let Some(pattern) = pattern {
+            match stream_idx {
+                Some(idx) => {
+                    let stream = &mut self.streams[idx];
+                    if stream.pattern == Some(pattern) {
+                        is_prefetched = stream.is_prefetched(offset);
+                    } else {
+                        stream.start_pattern(pattern, offset, len, advice);
+                    }
+                    prefetch_ranges = stream.prefetch(offset, len);
+                }
+                None => {
+                    let end = offset + len;
+                    prefetch_ranges = vec![end..end + initial_window(advice)];
+                }
+            }
         }
-        NewRead::Sequential {
+        if advice == Advice::Random {
+            prefetch_ranges.clear();
+        }
+
+        NewRead {
             tracker: self,
+            stream_idx,
+            pattern,
             offset,
             len,
+            is_prefetched,
+            prefetch_ranges,
         }
     }
-}
 
-pub enum NewRead<'a> {
-    Sequential {
-        tracker: &'a mut SeqRdTracker,
-        offset: usize,
-        len: usize,
-    },
-    Random {
-        tracker: &'a mut SeqRdTracker,
-        offset: usize,
-    },
+    fn add_stream(&mut self, stream: Stream) {
+        if self.streams.len() == MAX_STREAMS {
+            self.streams.remove(0);
+        }
+        self.streams.push(stream);
+    }
 }
 
-impl<'a> NewRead<'a> {
-    pub fn prefetch_size(&self) -> usize {
-        match self {
-            Self::Sequential {
-                tracker,
-                offset,
-                len,
-            } => {
-                // If the new read attempts to read the range beyond the end of the
-                // prefetched region, we need to issue new prefetch
-                if *offset + *len >= tracker.prefetch_end {
-                    tracker.prefetch_size
+impl Stream {
+    // Create a stream with a read that matches no other streams.
+    fn new(offset: usize, read_nbytes: usize, len: usize, advice: Advice) -> Self {
+        let mut stream = Self {
+            last_offset: offset,
+            last_end: offset + read_nbytes,
+            last_distance: 0,
+            pattern: None,
+            prefetch_pos: 0,
+            window: initial_window(advice),
+            has_thrashed: false,
+        };
+        if advice == Advice::Sequential {
+            stream.pattern = Some(Pattern::Sequential);
+            stream.prefetch_pos = offset + len + stream.window;
+        }
+        stream
+    }
+
+    fn classify(&self, offset: usize, len: usize) -> Option<Pattern> {
+        if offset == self.last_end {
+            return Some(Pattern::Sequential);
+        }
+        if offset + len == self.last_offset {
+            return Some(Pattern::Backward);
+        }
+        let distance = distance(self.last_offset, offset);
+        if distance != 0 && distance == self.last_distance {
+            return Some(Pattern::Strided(distance));
+        }
+        None
+    }
+
+    fn start_pattern(&mut self, pattern: Pattern, offset: usize, len: usize, advice: Advice) {
+        self.pattern = Some(pattern);
+        self.prefetch_pos = match pattern {
+            Pattern::Sequential => offset + len,
+            Pattern::Backward | Pattern::Strided(_) => offset,
+        };
+        self.window = initial_window(advice);
+        self.has_thrashed = false;
+    }
+
+    // Returns whether the first page of a new read, which follows the pattern
+    // of the stream, has been prefetched.
+    fn is_prefetched(&self, offset: usize) -> bool {
+        match self.pattern.unwrap() {
+            Pattern::Sequential => offset < self.prefetch_pos,
+            Pattern::Backward => offset >= self.prefetch_pos,
+            Pattern::Strided(stride) if stride > 0 => offset <= self.prefetch_pos,
+            Pattern::Strided(_) => offset >= self.prefetch_pos,
+        }
+    }
+
+    // Returns the ranges to prefetch for a new read, which follows the pattern
+    // of the stream. The prefetch is issued ahead of time, i.e., when less than
+    // half of the window that has been prefetched remains unread.
+    fn prefetch(&mut self, offset: usize, len: usize) -> Vec<Range<usize>> {
+        let window = self.window;
+        match self.pattern.unwrap() {
+            Pattern::Sequential => {
+                let end = offset + len;
+                if self.prefetch_pos >= end + window / 2 {
+                    return Vec::new();
+                }
+                let range = self.prefetch_pos.max(end)..end + window;
+                self.prefetch_pos = range.end;
+                vec![range]
+            }
+            Pattern::Backward => {
+                if offset >= self.prefetch_pos + window / 2 {
+                    return Vec::new();
+                }
+                let range = offset.saturating_sub(window)..self.prefetch_pos.min(offset);
+                self.prefetch_pos = range.start;
+                if range.start < range.end {
+                    vec![range]
                 } else {
-                    0
+                    Vec::new()
+                }
+            }
+            Pattern::Strided(stride) => {
+                // Prefetch as many of the next reads as the window can hold
+                let num_reads = (window / align_up(len, Page::size())).max(1) as isize;
+                let num_ahead = (distance(offset, self.prefetch_pos) / stride).max(0);
+                if num_ahead > num_reads / 2 {
+                    return Vec::new();
+                }
+                let mut ranges = Vec::new();
+                for i in num_ahead + 1..=num_reads {
+                    let start = offset as isize + i * stride;
+                    if start < 0 {
+                        break;
+                    }
+                    ranges.push(start as usize..start as usize + len);
+                    self.prefetch_pos = start as usize;
                 }
+                ranges
             }
-            Self::Random { .. } => 0,
         }
     }
+}
 
-    pub fn complete(self, read_nbytes: usize) {
-        match self {
-            Self::Sequential { tracker, len, .. } => {
-                tracker.last_rd_end += read_nbytes;
-                tracker.prefetch_end += len + tracker.prefetch_size;
-                tracker.prefetch_size = MAX_PREFETCH_SIZE.min(tracker.prefetch_size * 2);
-            }
-            Self::Random { tracker, offset } => {
-                tracker.last_rd_end = offset + read_nbytes;
+fn initial_window(advice: Advice) -> usize {
+    match advice {
+        Advice::Sequential => MAX_PREFETCH_SIZE,
+        _ => MIN_PREFETCH_SIZE,
+    }
+}
+
+fn distance(from: usize, to: usize) -> isize {
+    to as isize - from as isize
+}
+
+/// A new read that is being tracked.
+pub struct NewRead<'a> {
+    tracker: &'a mut SeqRdTracker,
+    stream_idx: Option<usize>,
+    pattern: Option<Pattern>,
+    offset: usize,
+    len: usize,
+    is_prefetched: bool,
+    prefetch_ranges: Vec<Range<usize>>,
+}
+
+impl<'a> NewRead<'a> {
+    /// Returns the access pattern of the read, or None if the read is random.
+    pub fn pattern(&self) -> Option<Pattern> {
+        self.pattern
+    }
+
+    /// Returns the ranges of the file to prefetch, which may exceed the end
+    /// of the file.
+    pub fn prefetch_ranges(&self) -> &[Range<usize>] {
+        &self.prefetch_ranges
+    }
+
+    /// Complete the read with the number of bytes read, which is zero if the
+    /// data is not ready, and whether the first page of the read was missing
+    /// from the page cache.
+    pub fn complete(self, read_nbytes: usize, is_first_page_missing: bool) {
+        let Self {
+            tracker,
+            stream_idx,
+            offset,
+            len,
+            is_prefetched,
+            ..
+        } = self;
+        let advice = tracker.advice;
+        let idx = match stream_idx {
+            Some(idx) => idx,
+            None => {
+                if read_nbytes > 0 {
+                    tracker.add_stream(Stream::new(offset, read_nbytes, len, advice));
+                }
+                return;
             }
+        };
+
+        let stream = &mut tracker.streams[idx];
+        // A prefetched page that is missing must have been evicted
+        if is_prefetched && is_first_page_missing {
+            stream.has_thrashed = true;
+        }
+        if read_nbytes == 0 {
+            return;
+        }
+        if is_prefetched {
+            stream.window = if stream.has_thrashed {
+                (stream.window / 2).max(MIN_PREFETCH_SIZE)
+            } else {
+                (stream.window * 2).min(MAX_PREFETCH_SIZE)
+            };
+            stream.has_thrashed = false;
         }
+        stream.last_distance = distance(stream.last_offset, offset);
+        stream.last_offset = offset;
+        stream.last_end = offset + read_nbytes;
+
+        let stream = tracker.streams.remove(idx);
+        tracker.streams.push(stream);
     }
 }
 
-/*
 #[cfg(test)]
 mod test {
-    use self::helper::SeqRds;
+    use self::helper::{track_rds, Rds};
     use super::*;
 
     #[test]
     fn read_seq_one_thread() {
-        let tracker = SeqRdTracker::new();
-
-        let mut seq_rds = SeqRds::new(0, 4096, 10);
-        seq_rds.for_each(|(offset, buf_size)| {
-            let seq_rd = tracker.accept(offset, buf_size);
-            assert!(seq_rd.is_some());
-            let seq_rd = seq_rd.unwrap();
-            //println!("prefetch size = {}", seq_rd.prefetch_size());
-            seq_rd.complete(buf_size);
-        });
+        let mut tracker = SeqRdTracker::new();
+        let prefetch_sizes = track_rds(&mut tracker, Rds::new(0, 4096, 10));
+        assert!(prefetch_sizes[0] == 0);
+        assert!(prefetch_sizes[1] == MIN_PREFETCH_SIZE);
+        assert!(prefetch_sizes.iter().sum::<usize>() >= MAX_PREFETCH_SIZE);
     }
 
     #[test]
     fn read_seq_multi_threads() {
-        let tracker = SeqRdTracker::new();
+        let mut tracker = SeqRdTracker::new();
+        let mixed_rds = Rds::new(0, 4096, 10).interleave(Rds::new(12345678, 1024, 8));
+        let num_rand_rds = mixed_rds
+            .filter(|(offset, buf_size)| {
+                let new_read = tracker.track(*offset, *buf_size);
+                let is_rand = new_read.pattern().is_none();
+                new_read.complete(*buf_size, false);
+                is_rand
+            })
+            .count();
+        assert!(num_rand_rds == 2);
+    }
 
-        let mixed_seq_rds = {
-            let seq_rds0 = SeqRds::new(0, 4096, 10);
-            let seq_rds1 = SeqRds::new(12345678, 1024, 8);
-            seq_rds0.interleave(seq_rds1)
-        };
+    #[test]
+    fn read_rand() {
+        let mut tracker = SeqRdTracker::new();
+        let random_rds = [(8, 16), (123456789, 16), (1024, 4)];
+        random_rds.iter().for_each(|(offset, buf_size)| {
+            let new_read = tracker.track(*offset, *buf_size);
+            assert!(new_read.pattern().is_none());
+            assert!(new_read.prefetch_ranges().is_empty());
+            new_read.complete(*buf_size, false);
+        });
+    }
 
-        let mut num_non_seq_rds = 0;
-        mixed_seq_rds.for_each(|(offset, buf_size)| {
-            if let Some(seq_rd) = tracker.accept(offset, buf_size) {
-                //println!("offset = {}, buf_size = {}: prefetch_size = {}", offset, buf_size, seq_rd.prefetch_size());
-                seq_rd.complete(buf_size);
-            } else {
-                //println!("offset = {}, buf_size = {}: non-sequential", offset, buf_size);
-                num_non_seq_rds += 1;
+    #[test]
+    fn read_backward() {
+        let mut tracker = SeqRdTracker::new();
+        let buf_size = 4096;
+        for i in 0..10 {
+            let offset = (100 - i) * buf_size;
+            let new_read = tracker.track(offset, buf_size);
+            if i > 0 {
+                assert!(new_read.pattern() == Some(Pattern::Backward));
+                assert!(new_read.prefetch_ranges().iter().all(|r| r.end <= offset));
             }
-        });
-        assert!(num_non_seq_rds <= 1);
+            new_read.complete(buf_size, false);
+        }
     }
 
     #[test]
-    fn read_rand() {
-        let tracker = SeqRdTracker::new();
-        let random_rds = [(8, 16), (128, 16), (1024, 4)];
-        random_rds.into_iter().for_each(|(offset, buf_size)| {
-            let seq_rd = tracker.accept(*offset, *buf_size);
-            assert!(seq_rd.is_none());
-        });
+    fn read_strided() {
+        let mut tracker = SeqRdTracker::new();
+        let (buf_size, stride) = (512, 3 * 4096);
+        for i in 0..10 {
+            let offset = i * stride;
+            let new_read = tracker.track(offset, buf_size);
+            if i > 1 {
+                assert!(new_read.pattern() == Some(Pattern::Strided(stride as isize)));
+                assert!(new_read
+                    .prefetch_ranges()
+                    .iter()
+                    .all(|r| (r.start - offset) % stride == 0 && r.len() == buf_size));
+            }
+            new_read.complete(buf_size, false);
+        }
+    }
+
+    #[test]
+    fn ramp_window_up_and_down() {
+        let mut tracker = SeqRdTracker::new();
+        let buf_size = 4096;
+        let mut prefetch_end = 0;
+        for i in 0..100 {
+            let new_read = tracker.track(i * buf_size, buf_size);
+            if let Some(range) = new_read.prefetch_ranges().last() {
+                prefetch_end = range.end;
+            }
+            new_read.complete(buf_size, false);
+        }
+        // The window has grown to the maximum
+        assert!(prefetch_end - 100 * buf_size >= MAX_PREFETCH_SIZE / 2);
+
+        // The prefetched pages are evicted before being read
+        let offset = 100 * buf_size;
+        let new_read = tracker.track(offset, buf_size);
+        new_read.complete(0, true);
+        let new_read = tracker.track(offset, buf_size);
+        new_read.complete(buf_size, false);
+        assert!(tracker.streams.last().unwrap().window == MAX_PREFETCH_SIZE / 2);
+    }
+
+    #[test]
+    fn follow_advice() {
+        let mut tracker = SeqRdTracker::new();
+        tracker.set_advice(Advice::Sequential);
+        let new_read = tracker.track(4096, 100);
+        assert!(new_read.prefetch_ranges() == &[4196..4196 + MAX_PREFETCH_SIZE]);
+        new_read.complete(100, false);
+
+        tracker.set_advice(Advice::Random);
+        let prefetch_sizes = track_rds(&mut tracker, Rds::new(0, 4096, 10));
+        assert!(prefetch_sizes.iter().all(|size| *size == 0));
     }
 
     mod helper {
         use super::*;
 
-        pub struct SeqRds {
+        // Track the reads, returning the size of prefetch of each read.
+        pub fn track_rds(tracker: &mut SeqRdTracker, rds: Rds) -> Vec<usize> {
+            rds.map(|(offset, buf_size)| {
+                let new_read = tracker.track(offset, buf_size);
+                let prefetch_size = new_read.prefetch_ranges().iter().map(|r| r.len()).sum();
+                new_read.complete(buf_size, false);
+                prefetch_size
+            })
+            .collect()
+        }
+
+        pub struct Rds {
             offset: usize,
             buf_size: usize,
             nrepeats_remain: usize,
         }
 
-        impl SeqRds {
+        impl Rds {
             pub fn new(offset: usize, buf_size: usize, nrepeats: usize) -> Self {
                 Self {
                     offset,
@@ -168,9 +478,24 @@ mod test {
                     nrepeats_remain: nrepeats,
                 }
             }
+
+            pub fn interleave(self, other: Self) -> impl Iterator<Item = (usize, usize)> {
+                let mut this = self.peekable();
+                let mut other = other.peekable();
+                let mut take_this = true;
+                std::iter::from_fn(move || {
+                    let next = if take_this && this.peek().is_some() || other.peek().is_none() {
+                        this.next()
+                    } else {
+                        other.next()
+                    };
+                    take_this = !take_this;
+                    next
+                })
+            }
         }
 
-        impl Iterator for SeqRds {
+        impl Iterator for Rds {
             type Item = (usize /* offset */, usize /* buf_size */);
 
             fn next(&mut self) -> Option<Self::Item> {
@@ -186,4 +511,3 @@ mod test {
         }
     }
 }
-*/
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+/// The policy of writing back dirty pages, which resembles the `vm.dirty_*`
+/// sysctls of Linux.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WritebackConfig {
+    /// The writeback daemon starts writing back dirty pages once their total
+    /// size exceeds this limit, and stops once it falls below half the limit.
+    pub dirty_background_bytes: usize,
+    /// Writers are throttled once the total size of dirty pages exceeds this
+    /// limit, until the writeback catches up.
+    pub dirty_bytes: usize,
+    /// The pages that have been dirty for longer than this are written back by
+    /// the writeback daemon.
+    pub dirty_expire: Duration,
+    /// The minimal interval between two checks for expired dirty pages.
+    pub writeback_interval: Duration,
+    /// The maximum number of pages that are written back in a batch.
+    pub max_pages_per_flush: usize,
+}
+
+impl Default for WritebackConfig {
+    fn default() -> Self {
+        Self {
+            dirty_background_bytes: 16 * 1024 * 1024,
+            dirty_bytes: 32 * 1024 * 1024,
+            dirty_expire: Duration::from_secs(30),
+            writeback_interval: Duration::from_secs(5),
+            max_pages_per_flush: 256,
+        }
+    }
+}
+
+/// The statistics of writeback.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WritebackStats {
+    pub num_dirty_pages: usize,
+    /// The number of pages written back as the dirty pages exceed the
+    /// background limit.
+    pub background_pages: u64,
+    /// The number of pages written back as they have been dirty for too long.
+    pub expired_pages: u64,
+    /// The number of pages written back on request, e.g., by `AsyncFile::flush`
+    /// or throttled writers.
+    pub requested_pages: u64,
+    /// The number of writes that are throttled.
+    pub throttled_writes: u64,
+}
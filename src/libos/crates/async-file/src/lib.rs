@@ -15,6 +15,8 @@ extern crate lazy_static;
 #[cfg(feature = "sgx")]
 extern crate sgx_libc as libc;
 #[cfg(feature = "sgx")]
+extern crate sgx_tcrypto;
+#[cfg(feature = "sgx")]
 extern crate sgx_trts;
 #[cfg(feature = "sgx")]
 extern crate sgx_untrusted_alloc;
@@ -26,15 +28,18 @@ mod file;
 mod page_cache;
 mod util;
 
-pub use crate::file::{AsyncFile, AsyncFileRt, Flusher};
-pub use crate::page_cache::{AsFd, Page, PageCache, PageHandle, PageState};
-
-// TODO
-// - [ ] Use inode number instead of fd to differentiate AsyncFile
+pub use crate::file::{
+    Advice, AsyncFile, AsyncFileRt, Flusher, WritebackConfig, WritebackStats, DIRECT_IO_ALIGN,
+    FALLOC_FL_KEEP_SIZE, FALLOC_FL_PUNCH_HOLE,
+};
+pub use crate::page_cache::{
+    AsFileId, FileId, Page, PageCache, PageCacheStats, PageHandle, PageState,
+};
+pub use crate::util::crypto::Key;
 
 #[cfg(test)]
 mod tests {
-    use async_io::file::{Async, File};
+    use async_io::prelude::Errno;
     use io_uring_callback::{Builder, IoUring};
     use lazy_static::lazy_static;
 
@@ -47,10 +52,11 @@ mod tests {
             let path = "tmp.data.hello_world";
             let file = {
                 let path = path.to_string();
-                let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC;
+                let flags = libc::O_WRONLY | libc::O_CREAT;
                 let mode = libc::S_IRUSR | libc::S_IWUSR;
                 AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
             };
+            file.set_len(0).await.unwrap();
             // The size of this file is considered _small_ given the size of
             // the page cache.
             let input_buf = "hello world\n".to_string().into_bytes().into_boxed_slice();
@@ -74,16 +80,259 @@ mod tests {
         });
     }
 
+    #[test]
+    fn share_pages_between_opens() {
+        async_rt::task::block_on(async {
+            let path = "tmp.data.share_pages";
+            let open = |flags| {
+                let mode = libc::S_IRUSR | libc::S_IWUSR;
+                AsyncFile::<Runtime>::open(path.to_string(), flags, mode).unwrap()
+            };
+            let writer = open(libc::O_WRONLY | libc::O_CREAT);
+            writer.set_len(0).await.unwrap();
+            let reader = open(libc::O_RDONLY);
+
+            // The data written through one open is visible to the other before
+            // it is flushed.
+            let input_buf = vec![0xcd; Page::size() + 100];
+            writer.write_exact_at(0, &input_buf).await.unwrap();
+            assert!(reader.len() == input_buf.len());
+            let mut output_buf = vec![0; input_buf.len()];
+            reader.read_exact_at(0, &mut output_buf).await.unwrap();
+            assert!(output_buf == input_buf);
+
+            // The dirty pages survive the close of the writer
+            drop(writer);
+            let reader2 = open(libc::O_RDONLY);
+            assert!(reader2.len() == input_buf.len());
+            reader2.flush().await.unwrap();
+            drop(reader);
+            drop(reader2);
+            assert!(std::fs::read(path).unwrap() == input_buf);
+        });
+    }
+
+    #[test]
+    fn truncate_and_extend_file() {
+        async_rt::task::block_on(async {
+            let path = "tmp.data.set_len";
+            let file = {
+                let path = path.to_string();
+                let flags = libc::O_RDWR | libc::O_CREAT;
+                let mode = libc::S_IRUSR | libc::S_IWUSR;
+                AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
+            };
+            file.set_len(0).await.unwrap();
+            let input_buf = vec![0xef; 3 * Page::size()];
+            file.write_exact_at(0, &input_buf).await.unwrap();
+            file.flush().await.unwrap();
+
+            // Truncate the file in the middle of a page
+            let new_len = Page::size() + 100;
+            file.set_len(new_len).await.unwrap();
+            assert!(file.len() == new_len);
+            file.flush().await.unwrap();
+            assert!(std::fs::metadata(path).unwrap().len() == new_len as u64);
+
+            // The truncated bytes read as zeros after the file is extended
+            file.set_len(input_buf.len()).await.unwrap();
+            let mut output_buf = vec![0; input_buf.len()];
+            file.read_exact_at(0, &mut output_buf).await.unwrap();
+            assert!(output_buf[..new_len] == input_buf[..new_len]);
+            assert!(output_buf[new_len..].iter().all(|&b| b == 0));
+            file.flush().await.unwrap();
+            assert!(std::fs::read(path).unwrap() == output_buf);
+        });
+    }
+
+    #[test]
+    fn punch_hole_in_file() {
+        async_rt::task::block_on(async {
+            let path = "tmp.data.punch_hole";
+            let file = {
+                let path = path.to_string();
+                let flags = libc::O_RDWR | libc::O_CREAT;
+                let mode = libc::S_IRUSR | libc::S_IWUSR;
+                AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
+            };
+            file.set_len(0).await.unwrap();
+            let input_buf = vec![0x5a; 4 * Page::size()];
+            file.write_exact_at(0, &input_buf).await.unwrap();
+
+            // The hole covers two whole pages and two partial ones
+            let (offset, len) = (Page::size() - 10, 2 * Page::size() + 20);
+            let mode = FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE;
+            file.fallocate(mode, offset, len).await.unwrap();
+            assert!(file.len() == input_buf.len());
+
+            let mut expected_buf = input_buf.clone();
+            expected_buf[offset..offset + len].fill(0);
+            let mut output_buf = vec![0; input_buf.len()];
+            file.read_exact_at(0, &mut output_buf).await.unwrap();
+            assert!(output_buf == expected_buf);
+            file.flush().await.unwrap();
+            assert!(std::fs::read(path).unwrap() == expected_buf);
+
+            // Allocating the space beyond the end extends the file unless the
+            // size is kept
+            let end = input_buf.len() + Page::size();
+            file.fallocate(FALLOC_FL_KEEP_SIZE, 0, end).await.unwrap();
+            assert!(file.len() == input_buf.len());
+            file.fallocate(0, 0, end).await.unwrap();
+            assert!(file.len() == end);
+            let res = file.fallocate(FALLOC_FL_PUNCH_HOLE, 0, end).await;
+            assert!(res.unwrap_err().errno() == Errno::EOPNOTSUPP);
+        });
+    }
+
+    #[test]
+    fn read_file_with_advice() {
+        async_rt::task::block_on(async {
+            let path = "tmp.data.read_with_advice";
+            let file = {
+                let path = path.to_string();
+                let flags = libc::O_RDWR | libc::O_CREAT;
+                let mode = libc::S_IRUSR | libc::S_IWUSR;
+                AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
+            };
+            file.set_len(0).await.unwrap();
+            let input_buf: Vec<u8> = (0..64 * Page::size()).map(|i| (i % 251) as u8).collect();
+            file.write_exact_at(0, &input_buf).await.unwrap();
+            file.flush().await.unwrap();
+
+            file.fadvise(Advice::WillNeed, 0, 0).unwrap();
+            // Read the file backward and then with a stride
+            let buf_size = 1000;
+            let mut output_buf = vec![0; buf_size];
+            for offset in (0..input_buf.len() - buf_size).step_by(buf_size).rev() {
+                file.read_exact_at(offset, &mut output_buf).await.unwrap();
+                assert!(output_buf[..] == input_buf[offset..offset + buf_size]);
+            }
+            file.fadvise(Advice::Random, 0, 0).unwrap();
+            for offset in (0..input_buf.len() - buf_size).step_by(3 * Page::size()) {
+                file.read_exact_at(offset, &mut output_buf).await.unwrap();
+                assert!(output_buf[..] == input_buf[offset..offset + buf_size]);
+            }
+        });
+    }
+
+    #[test]
+    fn write_file_with_writeback() {
+        async_rt::task::block_on(async {
+            let path = "tmp.data.write_with_writeback";
+            let file = {
+                let path = path.to_string();
+                let flags = libc::O_RDWR | libc::O_CREAT;
+                let mode = libc::S_IRUSR | libc::S_IWUSR;
+                AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
+            };
+            file.set_len(0).await.unwrap();
+            let old_stats = Runtime::flusher().stats();
+
+            // Write much more than the dirty limits allow
+            let num_pages = 4 * runtime::PAGE_CACHE_SIZE;
+            let input_buf: Vec<u8> = (0..num_pages * Page::size())
+                .map(|i| (i % 253) as u8)
+                .collect();
+            file.write_exact_at(0, &input_buf).await.unwrap();
+            file.sync_data().await.unwrap();
+
+            // Every page must have been written back once, either in the
+            // background or on request
+            let new_stats = Runtime::flusher().stats();
+            let num_written_back = (new_stats.background_pages - old_stats.background_pages)
+                + (new_stats.requested_pages - old_stats.requested_pages)
+                + (new_stats.expired_pages - old_stats.expired_pages);
+            assert!(num_written_back >= num_pages as u64);
+            drop(file);
+
+            let file = {
+                let path = path.to_string();
+                let flags = libc::O_RDONLY;
+                AsyncFile::<Runtime>::open(path, flags, 0).unwrap()
+            };
+            let mut output_buf = vec![0; input_buf.len()];
+            file.read_exact_at(0, &mut output_buf).await.unwrap();
+            assert!(output_buf == input_buf);
+        });
+    }
+
+    #[test]
+    fn read_write_direct_file() {
+        async_rt::task::block_on(async {
+            let path = "tmp.data.read_write_direct";
+            let file = {
+                let path = path.to_string();
+                let flags = libc::O_RDWR | libc::O_CREAT;
+                let mode = libc::S_IRUSR | libc::S_IWUSR;
+                AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
+            };
+            file.set_len(0).await.unwrap();
+            let direct_file = {
+                let path = path.to_string();
+                let flags = libc::O_RDWR | libc::O_DIRECT;
+                AsyncFile::<Runtime>::open(path, flags, 0).unwrap()
+            };
+            assert!(direct_file.is_direct());
+
+            // The buffers of direct I/O must be aligned
+            let buf_len = 4 * Page::size();
+            let mut storage = vec![0_u8; buf_len + DIRECT_IO_ALIGN];
+            let buf_start = storage.as_ptr().align_offset(DIRECT_IO_ALIGN);
+            let buf = &mut storage[buf_start..buf_start + buf_len];
+
+            // A direct read sees the data in the dirty pages
+            let input_buf: Vec<u8> = (0..buf_len).map(|i| (i % 241) as u8).collect();
+            file.write_exact_at(0, &input_buf).await.unwrap();
+            assert!(direct_file.read_exact_at(0, buf).await.unwrap() == buf_len);
+            assert!(buf[..] == input_buf[..]);
+
+            // A cached read sees the data of a direct write
+            buf.fill(0xab);
+            let offset = DIRECT_IO_ALIGN;
+            let len = 2 * DIRECT_IO_ALIGN;
+            assert!(
+                direct_file
+                    .write_exact_at(offset, &buf[..len])
+                    .await
+                    .unwrap()
+                    == len
+            );
+            let mut output_buf = vec![0; buf_len];
+            file.read_exact_at(0, &mut output_buf).await.unwrap();
+            assert!(output_buf[..offset] == input_buf[..offset]);
+            assert!(output_buf[offset..offset + len].iter().all(|b| *b == 0xab));
+            assert!(output_buf[offset + len..] == input_buf[offset + len..]);
+
+            // Misaligned direct I/O fails
+            let errno = direct_file.read_exact_at(1, buf).await.unwrap_err().errno();
+            assert!(errno == Errno::EINVAL);
+            let errno = direct_file
+                .write_exact_at(0, &buf[..100])
+                .await
+                .unwrap_err()
+                .errno();
+            assert!(errno == Errno::EINVAL);
+            let errno = direct_file
+                .write_exact_at(0, &buf[1..513])
+                .await
+                .unwrap_err()
+                .errno();
+            assert!(errno == Errno::EINVAL);
+        });
+    }
+
     #[test]
     fn write_read_large_file() {
         async_rt::task::block_on(async {
             let path = "tmp.data.test_seq_write_read";
             let file = {
                 let path = path.to_string();
-                let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC;
+                let flags = libc::O_WRONLY | libc::O_CREAT;
                 let mode = libc::S_IRUSR | libc::S_IWUSR;
                 AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
             };
+            file.set_len(0).await.unwrap();
 
             // The size of this file is considered _large_ given the size of
             // the page cache.
@@ -115,6 +364,89 @@ mod tests {
         });
     }
 
+    #[test]
+    fn write_read_protected_file() {
+        async_rt::task::block_on(async {
+            let path = "tmp.data.protected.write_read";
+            // A file left by a previous run may have been tampered with
+            let _ = std::fs::remove_file(path);
+            let file = {
+                let path = path.to_string();
+                let flags = libc::O_RDWR | libc::O_CREAT;
+                let mode = libc::S_IRUSR | libc::S_IWUSR;
+                AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
+            };
+            // The data spans multiple groups of pages and ends in the middle of
+            // a page.
+            let data_len = 1024 * 1024 + 100;
+            let input_buf: Vec<u8> = (0..data_len).map(|i| (i % 251) as u8).collect();
+            file.write_exact_at(0, &input_buf).await.unwrap();
+            file.flush().await.unwrap();
+            drop(file);
+
+            // The host file never contains the plaintext
+            let host_data = std::fs::read(path).unwrap();
+            assert!(host_data.len() > data_len);
+            assert!(!host_data
+                .windows(251)
+                .any(|window| window == &input_buf[..251]));
+
+            let file = {
+                let path = path.to_string();
+                let flags = libc::O_RDONLY;
+                let mode = 0;
+                AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
+            };
+            assert!(file.len() == data_len);
+            let mut output_buf = vec![0; data_len];
+            file.read_exact_at(0, &mut output_buf).await.unwrap();
+            assert!(output_buf == input_buf);
+        });
+    }
+
+    #[test]
+    fn detect_tampered_protected_file() {
+        async_rt::task::block_on(async {
+            let path = "tmp.data.protected.tamper";
+            // A file left by a previous run may have been tampered with
+            let _ = std::fs::remove_file(path);
+            let file = {
+                let path = path.to_string();
+                let flags = libc::O_RDWR | libc::O_CREAT;
+                let mode = libc::S_IRUSR | libc::S_IWUSR;
+                AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
+            };
+            let input_buf = vec![0xab; 4 * Page::size()];
+            file.write_exact_at(0, &input_buf).await.unwrap();
+            file.flush().await.unwrap();
+            drop(file);
+
+            // Flip a byte in the third data page, after the header and the node
+            let mut host_data = std::fs::read(path).unwrap();
+            host_data[4 * Page::size() + 10] ^= 1;
+            std::fs::write(path, &host_data).unwrap();
+
+            let file = {
+                let path = path.to_string();
+                let flags = libc::O_RDONLY;
+                let mode = 0;
+                AsyncFile::<Runtime>::open(path.clone(), flags, mode).unwrap()
+            };
+            let mut output_buf = vec![0; input_buf.len()];
+            let res = file.read_exact_at(0, &mut output_buf).await;
+            assert!(res.is_err() || res.unwrap() < input_buf.len());
+            let res = file.read_exact_at(0, &mut output_buf).await;
+            assert!(res.unwrap_err().errno() == Errno::EIO);
+            drop(file);
+
+            // Tampering with the metadata is detected upon opening
+            host_data[Page::size() + 10] ^= 1;
+            std::fs::write(path, &host_data).unwrap();
+            let res = AsyncFile::<Runtime>::open(path.to_string(), libc::O_RDONLY, 0);
+            assert!(res.unwrap_err().errno() == Errno::EIO);
+        });
+    }
+
     // #[test]
     // fn bench_random() {
     //     use std::time::{Duration, Instant};
@@ -185,22 +517,25 @@ mod tests {
     // }
 
     mod runtime {
-        use async_rt::{wait::WaiterQueue, waiter_loop};
-
         use super::*;
-        use std::sync::Once;
+        use std::time::{Duration, Instant};
 
         pub struct Runtime;
 
         pub const PAGE_CACHE_SIZE: usize = 10; // 10 * 4KB
-        pub const DIRTY_LOW_MARK: usize = 3;
-        pub const DIRTY_HIGH_MARK: usize = 6;
+        pub const DIRTY_BACKGROUND_PAGES: usize = 6;
+        pub const DIRTY_PAGES: usize = 8;
         pub const MAX_DIRTY_PAGES_PER_FLUSH: usize = 10;
 
         lazy_static! {
             static ref PAGE_CACHE: PageCache = PageCache::with_capacity(PAGE_CACHE_SIZE);
-            static ref FLUSHER: Flusher<Runtime> = Flusher::new();
-            static ref WAITER_QUEUE: WaiterQueue = WaiterQueue::new();
+            static ref FLUSHER: Flusher<Runtime> = Flusher::with_config(WritebackConfig {
+                dirty_background_bytes: DIRTY_BACKGROUND_PAGES * Page::size(),
+                dirty_bytes: DIRTY_PAGES * Page::size(),
+                max_pages_per_flush: MAX_DIRTY_PAGES_PER_FLUSH,
+                ..WritebackConfig::default()
+            });
+            static ref START_TIME: Instant = Instant::now();
             pub static ref RING: IoUring = Builder::new().build(1024).unwrap();
         }
 
@@ -216,29 +551,15 @@ mod tests {
                 &FLUSHER
             }
 
-            fn auto_flush() {
-                static INIT: Once = Once::new();
-                INIT.call_once(|| {
-                    async_rt::task::spawn(async {
-                        let page_cache = &PAGE_CACHE;
-                        let flusher = &FLUSHER;
-                        let waiter_queue = &WAITER_QUEUE;
-                        waiter_loop!(waiter_queue, {
-                            // Start flushing when the # of dirty pages rises above the high watermark
-                            if page_cache.num_dirty_pages() < DIRTY_HIGH_MARK {
-                                continue;
-                            }
-
-                            // Stop flushing until the # of dirty pages falls below the low watermark
-                            while page_cache.num_dirty_pages() > DIRTY_LOW_MARK {
-                                flusher.flush(MAX_DIRTY_PAGES_PER_FLUSH).await;
-                            }
-                        });
-                    });
-                });
-
-                if PAGE_CACHE.num_dirty_pages() >= DIRTY_HIGH_MARK {
-                    WAITER_QUEUE.wake_all();
+            fn now() -> Duration {
+                START_TIME.elapsed()
+            }
+
+            fn file_key(path: &str) -> Option<Key> {
+                if path.contains(".protected.") {
+                    Some([0x42; 16])
+                } else {
+                    None
                 }
             }
         }
@@ -254,7 +575,7 @@ mod tests {
         }
 
         let callback = move || {
-            ring.trigger_callbacks();
+            ring.poll_completions();
         };
         async_rt::config::set_sched_callback(callback);
     }
@@ -0,0 +1,20 @@
+/// The identity of a host file, i.e., its device and inode numbers.
+///
+/// Unlike fds, the identity of a file is stable: all opens of the same file
+/// share it, and it is never reused as long as the file is open.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct FileId {
+    pub dev: u64,
+    pub ino: u64,
+}
+
+impl FileId {
+    pub const fn new(dev: u64, ino: u64) -> Self {
+        Self { dev, ino }
+    }
+}
+
+/// The files whose pages can be cached in a page cache.
+pub trait AsFileId {
+    fn file_id(&self) -> FileId;
+}
@@ -1,34 +1,61 @@
 use spin::{Mutex, MutexGuard};
+use std::any::Any;
 use std::collections::HashMap;
+use std::ops::Range;
 #[cfg(feature = "sgx")]
 use std::prelude::v1::*;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 
+mod file_id;
 mod page;
 mod page_entry;
 mod page_handle;
 mod page_lru_list;
+mod page_map;
 mod page_state;
+mod stats;
 
+pub use self::file_id::{AsFileId, FileId};
 pub use self::page::Page;
 pub use self::page_handle::PageHandle;
 pub use self::page_state::PageState;
+pub use self::stats::PageCacheStats;
 
 use self::page_entry::{PageEntry, PageEntryInner};
 use self::page_lru_list::PageLruList;
+use self::page_map::PageMap;
 
 /// Page cache.
+///
+/// # Replacement policy
+///
+/// The clean pages that are not in use are kept in two LRU lists, similar to
+/// those of Linux. A page that is brought into the page cache starts in the
+/// inactive list, and moves to the active list once it is accessed again. Pages
+/// are evicted from the inactive list first, while the active list is kept no
+/// larger than `ACTIVE_RATIO` times the inactive list by deactivating its least
+/// recently used pages. Thus, a large scan, which accesses each page only once,
+/// can only evict the pages in the inactive list, leaving the working set in the
+/// active list intact.
 pub struct PageCache {
-    capacity: usize,
+    capacity: AtomicUsize,
+    is_trusted: bool,
     num_allocated: AtomicUsize,
-    map: Mutex<HashMap<(i32, usize), PageEntry>>,
-    lru_lists: [Mutex<PageLruList>; 3],
+    map: Mutex<PageMap>,
+    lru_lists: [Mutex<PageLruList>; 4],
+    // The files whose pages may be in the page cache. All users of a file
+    // must share the same file object so that their views of the file are
+    // coherent.
+    files: Mutex<HashMap<FileId, Weak<dyn Any + Send + Sync>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
 }
 
-pub trait AsFd {
-    fn as_fd(&self) -> i32;
-}
+/// The maximum ratio of the number of active pages to that of inactive pages.
+const ACTIVE_RATIO: usize = 3;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[repr(u8)]
@@ -36,82 +63,152 @@ enum LruListName {
     // For any entry : &PageEntry in &lru_lists[LruListName::Unused], we have
     //      entry.state == PageState::Uninit && PageEntry::refcnt(entry) == 1
     Unused = 0,
-    // For any entry : &PageEntry in &lru_lists[LruListName::Evictable], we have
+    // For any entry : &PageEntry in &lru_lists[LruListName::Inactive], we have
+    //      entry.state == PageState::UpToDate && PageEntry::refcnt(entry) == 2
+    Inactive = 1,
+    // For any entry : &PageEntry in &lru_lists[LruListName::Active], we have
     //      entry.state == PageState::UpToDate && PageEntry::refcnt(entry) == 2
-    Evictable = 1,
+    Active = 2,
     // For any entry : &PageEntry in &lru_lists[LruListName::Dirty], we have
     //      entry.state == PageState::Dirty (most likely, but not always)
     //   && PageEntry::refcnt(entry) > 2
-    Dirty = 2,
+    Dirty = 3,
 }
 
 impl PageCache {
     /// Create a page cache that can contain an specified number of pages at most.
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::new(capacity, false)
+    }
+
+    /// Create a page cache whose pages reside in trusted memory.
+    ///
+    /// Such a page cache is required by protected files under SGX, whose plaintext
+    /// must never be seen by the host. The I/O of the pages then goes through
+    /// bounce buffers in untrusted memory.
+    pub fn with_capacity_trusted(capacity: usize) -> Self {
+        Self::new(capacity, true)
+    }
+
+    fn new(capacity: usize, is_trusted: bool) -> Self {
         assert!(capacity > 0);
         let num_allocated = AtomicUsize::new(0);
-        let map = Mutex::new(HashMap::new());
+        let map = Mutex::new(PageMap::new());
         let lru_lists = array_init::array_init(|_| Mutex::new(PageLruList::new()));
+        let files = Mutex::new(HashMap::new());
         Self {
-            capacity,
+            capacity: AtomicUsize::new(capacity),
+            is_trusted,
             num_allocated,
             map,
             lru_lists,
+            files,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
         }
     }
 
-    /// Acquire a page handle for the given fd and offset.
+    /// Returns the registered file of a given id if it is still alive.
+    pub fn file<F>(&self, id: FileId) -> Option<Arc<F>>
+    where
+        F: AsFileId + Send + Sync + 'static,
+    {
+        // Upgrade the file without holding the lock, since dropping it may
+        // unregister the file.
+        let file = self.files.lock().get(&id)?.upgrade()?;
+        file.downcast::<F>().ok()
+    }
+
+    /// Register a file unless another live file of the same id has been
+    /// registered, returning the registered file.
+    ///
+    /// The page cache only keeps a weak reference to the file.
+    pub fn register_file<F>(&self, file: Arc<F>) -> Arc<F>
+    where
+        F: AsFileId + Send + Sync + 'static,
+    {
+        let id = file.file_id();
+        let mut files = self.files.lock();
+        let registered = files.get(&id).and_then(|weak| weak.upgrade());
+        match registered {
+            Some(registered) => {
+                drop(files);
+                registered
+                    .downcast::<F>()
+                    .expect("the files of the same id must be of the same type")
+            }
+            None => {
+                let weak = Arc::downgrade(&file) as Weak<dyn Any + Send + Sync>;
+                files.insert(id, weak);
+                file
+            }
+        }
+    }
+
+    /// Unregister a file if it is the registered one of its id.
+    pub fn unregister_file<F: AsFileId>(&self, file: &F) {
+        let id = file.file_id();
+        let mut files = self.files.lock();
+        let is_registered = files.get(&id).map_or(false, |weak| {
+            weak.as_ptr() as *const u8 == file as *const F as *const u8
+        });
+        if is_registered {
+            files.remove(&id);
+        }
+    }
+
+    /// Acquire a page handle for the given file and offset.
     ///
     /// The returned page handle may be fetched from the cache or newly created.
     pub fn acquire<F>(&self, file: &Arc<F>, offset: usize) -> Option<PageHandle>
     where
-        F: AsFd + Send + Sync + 'static,
+        F: AsFileId + Send + Sync + 'static,
     {
         debug_assert!(offset % Page::size() == 0);
 
-        let key = (file.as_fd(), offset);
+        let key = (file.file_id(), offset);
         let mut map = self.map.lock();
 
         // Try to get an existing entry in the map.
         if let Some(existing_entry) = map.get(&key) {
-            self.touch_lru_list(existing_entry);
-            return Some(PageHandle::wrap(existing_entry.clone()));
+            let existing_entry = existing_entry.clone();
+            self.touch_entry(&existing_entry);
+            return Some(PageHandle::wrap(existing_entry));
         }
 
         // Try to create an new entry
-        // First attempt: reuse an entry that is previously allocated, but currently not in use.
-        let reusable_entry_opt = self.evict_from_lru_list(LruListName::Unused);
-        let new_entry = if let Some(mut reusable_entry) = reusable_entry_opt {
+        // First attempt: reuse a page of the file itself if the file has used up its quota
+        let new_entry = if map.is_quota_exhausted(key.0) {
+            let mut evicted_entry = self.evict_clean_entry(&mut map, Some(key.0))?;
+            unsafe {
+                evicted_entry.reset(file.clone(), offset);
+            }
+            evicted_entry
+        }
+        // Second attempt: reuse an entry that is previously allocated, but currently not in use.
+        else if let Some(mut reusable_entry) = self.evict_from_lru_list(LruListName::Unused) {
             unsafe {
                 debug_assert!(PageEntry::refcnt(&reusable_entry) == 1);
                 reusable_entry.reset(file.clone(), offset);
             }
             reusable_entry
         }
-        // Second attempt: allocate a new entry if the capacity won't be exceeded
-        else if self.num_allocated.load(Ordering::Relaxed) < self.capacity {
+        // Third attempt: allocate a new entry if the capacity won't be exceeded
+        else if self.num_allocated.load(Ordering::Relaxed) < self.capacity() {
             self.num_allocated.fetch_add(1, Ordering::Relaxed);
-            PageEntry::new(file.clone(), offset)
+            PageEntry::new(file.clone(), offset, self.is_trusted)
         }
-        // Last attempt: evict an entry from the evictable LRU list
+        // Last attempt: evict a clean entry
         else {
-            let evicted_entry_opt = self.evict_from_lru_list(LruListName::Evictable);
-            let mut evicted_entry = match evicted_entry_opt {
-                Some(evicted_entry) => evicted_entry,
-                None => {
-                    return None;
-                }
-            };
-            map.remove(&evicted_entry.key());
-
+            let mut evicted_entry = self.evict_clean_entry(&mut map, None)?;
             unsafe {
-                debug_assert!(PageEntry::refcnt(&evicted_entry) == 1);
                 evicted_entry.reset(file.clone(), offset);
-                *evicted_entry.state() = PageState::Uninit;
             }
             evicted_entry
         };
-        map.insert(key, new_entry.clone()).unwrap_none();
+        map.insert(key, new_entry.clone());
+        self.misses.fetch_add(1, Ordering::Relaxed);
 
         Some(PageHandle::wrap(new_entry))
     }
@@ -125,6 +222,92 @@ impl PageCache {
         self.do_release(handle, true)
     }
 
+    /// Mark a page as accessed by a user, e.g., a read or write of the page.
+    ///
+    /// A page accessed twice is considered as part of the working set, which
+    /// is the last to be evicted. Note that acquiring a page, e.g., for the
+    /// sake of prefetching, does not count as an access.
+    pub fn mark_accessed(&self, handle: &PageHandle) {
+        handle.entry().mark_accessed();
+    }
+
+    /// Free up to a given number of pages that are neither in use nor dirty,
+    /// returning the number of pages freed.
+    ///
+    /// This is for the users to reclaim the memory of the page cache, e.g.,
+    /// under memory pressure. The unused pages are freed first, and then the
+    /// clean pages, starting from the inactive ones.
+    pub fn shrink(&self, max_pages: usize) -> usize {
+        let mut map = self.map.lock();
+        let mut num_freed = 0;
+        while num_freed < max_pages {
+            let entry = match self.evict_from_lru_list(LruListName::Unused) {
+                Some(entry) => entry,
+                None => match self.evict_clean_entry(&mut map, None) {
+                    Some(entry) => entry,
+                    None => break,
+                },
+            };
+            debug_assert!(PageEntry::refcnt(&entry) == 1);
+            drop(entry);
+            self.num_allocated.fetch_sub(1, Ordering::Relaxed);
+            num_freed += 1;
+        }
+        num_freed
+    }
+
+    /// Returns the maximum number of pages.
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Change the maximum number of pages, shrinking the page cache if needed.
+    ///
+    /// The pages that are in use or dirty cannot be freed immediately. So the
+    /// page cache may exceed the new capacity for a while, during which the
+    /// pages are freed as soon as they become unused.
+    pub fn set_capacity(&self, capacity: usize) {
+        assert!(capacity > 0);
+        self.capacity.store(capacity, Ordering::Relaxed);
+        let num_allocated = self.num_allocated.load(Ordering::Relaxed);
+        if num_allocated > capacity {
+            self.shrink(num_allocated - capacity);
+        }
+    }
+
+    /// Set the maximum number of pages that a file can occupy, or `None` to
+    /// follow the default quota.
+    ///
+    /// A file that has used up its quota can only bring in new pages by evicting
+    /// its own clean pages.
+    pub fn set_quota(&self, id: FileId, max_pages: Option<usize>) {
+        self.map.lock().set_quota(id, max_pages);
+    }
+
+    /// Set the maximum number of pages that a file can occupy unless the file
+    /// has its own quota, or `None` for no limit, which is the default.
+    pub fn set_default_quota(&self, max_pages: Option<usize>) {
+        self.map.lock().set_default_quota(max_pages);
+    }
+
+    /// Returns the number of pages of a file in the page cache.
+    pub fn num_pages_of(&self, id: FileId) -> usize {
+        self.map.lock().num_pages_of(id)
+    }
+
+    pub fn stats(&self) -> PageCacheStats {
+        PageCacheStats {
+            capacity: self.capacity(),
+            num_pages: self.num_allocated.load(Ordering::Relaxed),
+            num_active_pages: self.acquire_lru_list(LruListName::Active).len(),
+            num_inactive_pages: self.acquire_lru_list(LruListName::Inactive).len(),
+            num_dirty_pages: self.num_dirty_pages(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
     /// Evict some LRU dirty pages.
     ///
     /// Note that the results may contain false positives.
@@ -144,17 +327,35 @@ impl PageCache {
     /// Evict some LRU dirty pages of a file.
     ///
     /// Note that the results may contain false positives.
-    pub fn evict_dirty_pages_by_file<F: AsFd>(
+    pub fn evict_dirty_pages_by_file<F: AsFileId>(
         &self,
         file: &F,
         max_count: usize,
     ) -> Vec<PageHandle> {
-        self.evict_dirty_pages_by_fd(file.as_fd(), max_count)
+        self.evict_dirty_pages_by_id(file.file_id(), max_count)
+    }
+
+    pub fn evict_dirty_pages_by_id(&self, id: FileId, max_count: usize) -> Vec<PageHandle> {
+        let mut lru_dirty_list = self.acquire_lru_list(LruListName::Dirty);
+        let cond = |entry: &PageEntryInner| entry.file_id() == id;
+        let evicted: Vec<PageEntry> = lru_dirty_list.evict_nr_with(max_count, cond);
+        for entry in &evicted {
+            entry.set_list_name(None);
+        }
+        unsafe {
+            // This transmute is ok because PageEntry and PageHandle have
+            // exactly the same memory layout.
+            std::mem::transmute(evicted)
+        }
     }
 
-    pub fn evict_dirty_pages_by_fd(&self, fd: i32, max_count: usize) -> Vec<PageHandle> {
+    /// Evict some LRU dirty pages that have been dirty since a given time or
+    /// earlier, as recorded by `PageHandle::set_dirty_since`.
+    ///
+    /// Note that the results may contain false positives.
+    pub fn evict_expired_dirty_pages(&self, time: Duration, max_count: usize) -> Vec<PageHandle> {
         let mut lru_dirty_list = self.acquire_lru_list(LruListName::Dirty);
-        let cond = |entry: &PageEntryInner| entry.fd() == fd;
+        let cond = |entry: &PageEntryInner| entry.dirty_since() <= time;
         let evicted: Vec<PageEntry> = lru_dirty_list.evict_nr_with(max_count, cond);
         for entry in &evicted {
             entry.set_list_name(None);
@@ -166,6 +367,57 @@ impl PageCache {
         }
     }
 
+    /// Discard the pages of a file within a range of offsets, dirty or not.
+    ///
+    /// The pages that are in use are detached from the page cache, so that they
+    /// can no longer be acquired, and are recycled once released. The pages under
+    /// I/O are left as is. So the pages being flushed, which may still reach the
+    /// host, are returned for the caller to wait for and then release.
+    pub fn discard_pages(&self, id: FileId, range: Range<usize>) -> Vec<PageHandle> {
+        debug_assert!(range.start % Page::size() == 0);
+
+        let mut map = self.map.lock();
+        let keys: Vec<(FileId, usize)> = map
+            .keys()
+            .filter(|key| key.0 == id && range.contains(&key.1))
+            .cloned()
+            .collect();
+        self.detach_entries(&mut map, keys)
+    }
+
+    /// Discard all pages of a file unless some of them are dirty or being flushed,
+    /// returning whether the pages are discarded.
+    pub fn discard_clean_pages_of(&self, id: FileId) -> bool {
+        let mut map = self.map.lock();
+        let mut keys = Vec::new();
+        for (key, entry) in map.iter().filter(|(key, _)| key.0 == id) {
+            match *entry.state() {
+                PageState::Dirty | PageState::Flushing => return false,
+                _ => keys.push(*key),
+            }
+        }
+        let flushing_pages = self.detach_entries(&mut map, keys);
+        debug_assert!(flushing_pages.is_empty());
+        true
+    }
+
+    /// Returns the pages of a file within a range of offsets that are being
+    /// flushed, which the caller may wait for and then must release.
+    pub fn flushing_pages(&self, id: FileId, range: Range<usize>) -> Vec<PageHandle> {
+        let map = self.map.lock();
+        map.iter()
+            .filter(|(key, entry)| {
+                key.0 == id && range.contains(&key.1) && *entry.state() == PageState::Flushing
+            })
+            .map(|(_, entry)| PageHandle::wrap(entry.clone()))
+            .collect()
+    }
+
+    /// Returns whether the pages reside in trusted memory.
+    pub fn is_trusted(&self) -> bool {
+        self.is_trusted
+    }
+
     pub fn num_dirty_pages(&self) -> usize {
         let lru_dirty_list = self.acquire_lru_list(LruListName::Dirty);
         lru_dirty_list.len()
@@ -176,11 +428,10 @@ impl PageCache {
         let mut map = self.map.lock();
 
         let are_users_still_holding_handles = |entry: &PageEntry| {
-            let internal_refcnt = if entry.list_name().is_some() {
-                2 // 1 for lru_list + 1 for map
-            } else {
-                1 // 1 for lru_list
-            };
+            // 1 for lru_list + 1 for map, the latter of which does not hold
+            // detached entries
+            let internal_refcnt =
+                entry.list_name().map_or(0, |_| 1) + if entry.is_detached() { 0 } else { 1 };
             let user_refcnt = PageEntry::refcnt(entry) - internal_refcnt;
             user_refcnt > 1
         };
@@ -194,24 +445,61 @@ impl PageCache {
                 }
             } else {
                 // This is the right timing to "free" a page cache
-                if is_discard {
+                if is_discard || entry.is_detached() {
                     *state = PageState::Uninit;
                 }
-                if *state == PageState::Uninit {
+                if *state == PageState::Uninit && !entry.is_detached() {
                     map.remove(&entry.key());
                 }
 
                 match *state {
                     PageState::Uninit => Some(LruListName::Unused),
-                    PageState::UpToDate => Some(LruListName::Evictable),
+                    PageState::UpToDate if entry.is_active() => Some(LruListName::Active),
+                    PageState::UpToDate => Some(LruListName::Inactive),
                     PageState::Dirty => Some(LruListName::Dirty),
                     _ => None,
                 }
             }
         };
+
+        // Free the unused page if the page cache has been shrunk below its size
+        if dst_list_name == Some(LruListName::Unused)
+            && self.num_allocated.load(Ordering::Relaxed) > self.capacity()
+        {
+            self.reinsert_to_lru_list(entry, None);
+            self.num_allocated.fetch_sub(1, Ordering::Relaxed);
+            return;
+        }
         self.reinsert_to_lru_list(entry, dst_list_name);
     }
 
+    fn detach_entries(&self, map: &mut PageMap, keys: Vec<(FileId, usize)>) -> Vec<PageHandle> {
+        let mut flushing_pages = Vec::new();
+        for key in keys {
+            let entry = map.remove(&key).unwrap();
+            entry.set_detached();
+            {
+                let mut state = entry.state();
+                match *state {
+                    PageState::Fetching => {}
+                    PageState::Flushing => flushing_pages.push(PageHandle::wrap(entry.clone())),
+                    _ => *state = PageState::Uninit,
+                }
+            }
+
+            // 1 for lru_list + 1 for this reference
+            let internal_refcnt = entry.list_name().map_or(0, |_| 1) + 1;
+            let dst_list_name = if PageEntry::refcnt(&entry) == internal_refcnt {
+                Some(LruListName::Unused)
+            } else {
+                // The entry will be recycled when the last user releases it
+                None
+            };
+            self.reinsert_to_lru_list(entry, dst_list_name);
+        }
+        flushing_pages
+    }
+
     fn reinsert_to_lru_list(&self, entry: PageEntry, dst_list_name: Option<LruListName>) {
         let src_list_name = entry.list_name();
 
@@ -245,15 +533,59 @@ impl PageCache {
         }
     }
 
-    fn touch_lru_list(&self, entry: &PageEntry) {
-        let lru_list_name = match entry.list_name() {
-            Some(lru_list_name) => lru_list_name,
-            None => {
-                return;
+    // Touch an existing entry that is acquired by a user.
+    fn touch_entry(&self, entry: &PageEntry) {
+        match *entry.state() {
+            PageState::UpToDate | PageState::Dirty | PageState::Flushing => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
             }
-        };
-        let mut lru_list = self.acquire_lru_list(lru_list_name);
-        lru_list.touch(entry);
+            PageState::Uninit | PageState::Fetching => {}
+        }
+
+        match entry.list_name() {
+            // A clean entry in use must not be evicted. It is put back to the
+            // clean lists when released.
+            Some(LruListName::Inactive) | Some(LruListName::Active) => {
+                self.reinsert_to_lru_list(entry.clone(), None);
+            }
+            Some(lru_list_name) => {
+                let mut lru_list = self.acquire_lru_list(lru_list_name);
+                lru_list.touch(entry);
+            }
+            None => {}
+        }
+    }
+
+    // Evict a clean entry, optionally of a given file, removing it from the map.
+    fn evict_clean_entry(&self, map: &mut PageMap, id: Option<FileId>) -> Option<PageEntry> {
+        self.balance_lru_lists();
+
+        let cond = |entry: &PageEntryInner| id.map_or(true, |id| entry.file_id() == id);
+        let evicted_entry = [LruListName::Inactive, LruListName::Active]
+            .iter()
+            .find_map(|&name| self.acquire_lru_list(name).evict_nr_with(1, cond).pop())?;
+        debug_assert!(
+            PageEntry::refcnt(&evicted_entry) == 2 && *evicted_entry.state() == PageState::UpToDate
+        );
+        evicted_entry.set_list_name(None);
+
+        map.remove(&evicted_entry.key());
+        *evicted_entry.state() = PageState::Uninit;
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+        Some(evicted_entry)
+    }
+
+    // Deactivate the least recently used active entries if the active list
+    // grows too large.
+    fn balance_lru_lists(&self) {
+        let mut active_list = self.acquire_lru_list(LruListName::Active);
+        let mut inactive_list = self.acquire_lru_list(LruListName::Inactive);
+        while active_list.len() > ACTIVE_RATIO * inactive_list.len() {
+            let entry = active_list.evict().unwrap();
+            entry.deactivate();
+            entry.set_list_name(Some(LruListName::Inactive));
+            inactive_list.insert(entry);
+        }
     }
 
     fn evict_from_lru_list(&self, name: LruListName) -> Option<PageEntry> {
@@ -271,7 +603,7 @@ impl PageCache {
                 PageEntry::refcnt(&evicted_entry) == 1
                     && *evicted_entry.state() == PageState::Uninit
             }
-            LruListName::Evictable => {
+            LruListName::Inactive | LruListName::Active => {
                 PageEntry::refcnt(&evicted_entry) == 2
                     && *evicted_entry.state() == PageState::UpToDate
             }
@@ -292,7 +624,8 @@ impl PageCache {
 impl std::fmt::Debug for PageCache {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("PageCache")
-            .field("capacity", &self.capacity)
+            .field("capacity", &self.capacity())
+            .field("is_trusted", &self.is_trusted)
             .field("num_allocated", &self.num_allocated.load(Ordering::Relaxed))
             .field("map", &self.map.lock())
             .field(
@@ -311,7 +644,7 @@ impl std::fmt::Debug for PageCache {
 mod tests {
     use test::Bencher;
 
-    use self::helper::{release_pages, visit_page, File};
+    use self::helper::{access_page, release_pages, visit_page, File};
     use super::*;
 
     // Create a dummy file object.
@@ -392,6 +725,28 @@ mod tests {
         assert!(page_cache.num_dirty_pages() == 0);
     }
 
+    #[test]
+    fn evict_expired_dirty_pages() {
+        let page_cache = PageCache::with_capacity(4);
+        let file = file!(0);
+        // Dirty pages 0, 1, 2 and 3 at time 0, 10, 20 and 30 seconds
+        for i in 0..4 {
+            let page_handle = page_cache.acquire(&file, i * Page::size()).unwrap();
+            *page_handle.state() = PageState::Dirty;
+            page_handle.set_dirty_since(Duration::from_secs(i as u64 * 10));
+            page_cache.release(page_handle);
+        }
+
+        let expired_pages = page_cache.evict_expired_dirty_pages(Duration::from_secs(15), 4);
+        let offsets: Vec<usize> = expired_pages.iter().map(|page| page.offset()).collect();
+        assert_eq!(offsets, vec![0, Page::size()]);
+        for page_handle in expired_pages {
+            *page_handle.state() = PageState::UpToDate;
+            page_cache.release(page_handle);
+        }
+        assert!(page_cache.num_dirty_pages() == 2);
+    }
+
     #[test]
     fn evict_lru_pages() {
         // The cache can contain at most two pages
@@ -434,13 +789,13 @@ mod tests {
         let evicted_pages0 = page_cache.evict_dirty_pages(1);
         assert!(page_cache.num_dirty_pages() == 2);
         assert!(evicted_pages0.len() == 1);
-        assert!(evicted_pages0[0].key() == (0, 0));
+        assert!(evicted_pages0[0].key() == (FileId::new(0, 0), 0));
 
         let evicted_pages1 = page_cache.evict_dirty_pages(2);
         assert!(page_cache.num_dirty_pages() == 0);
         assert!(evicted_pages1.len() == 2);
-        assert!(evicted_pages1[0].key() == (1, 0));
-        assert!(evicted_pages1[1].key() == (2, 0));
+        assert!(evicted_pages1[0].key() == (FileId::new(0, 1), 0));
+        assert!(evicted_pages1[1].key() == (FileId::new(0, 2), 0));
 
         release_pages(&page_cache, evicted_pages0.into_iter());
         release_pages(&page_cache, evicted_pages1.into_iter());
@@ -485,6 +840,152 @@ mod tests {
         assert!(page_cache.num_dirty_pages() == 0);
     }
 
+    #[test]
+    fn discard_pages_of_file() {
+        let page_cache = PageCache::with_capacity(3);
+        let file = file!(0);
+        let page_keys = [
+            (file.clone(), 0),
+            (file.clone(), Page::size()),
+            (file.clone(), 2 * Page::size()),
+        ];
+        for page_key in &page_keys {
+            visit_page(&page_cache, page_key, |state, _page_slice| {
+                **state = PageState::Dirty;
+            });
+        }
+        assert!(page_cache.num_dirty_pages() == 3);
+
+        // Page 2 is in use while being discarded
+        let page_handle = page_cache.acquire(&file, 2 * Page::size()).unwrap();
+        let flushing_pages = page_cache.discard_pages(file.file_id(), Page::size()..usize::MAX);
+        assert!(flushing_pages.is_empty());
+        assert!(page_cache.num_dirty_pages() == 1);
+        assert!(*page_handle.state() == PageState::Uninit);
+        // A discarded page in use is never returned again
+        let another_page_handle = page_cache.acquire(&file, 2 * Page::size()).unwrap();
+        assert!(*another_page_handle.state() == PageState::Uninit);
+        page_cache.release(page_handle);
+        page_cache.release(another_page_handle);
+
+        // Page 0 is intact
+        visit_page(&page_cache, &page_keys[0], |state, _page_slice| {
+            assert!(**state == PageState::Dirty);
+        });
+        // The discarded pages are reusable
+        let other_file = file!(1);
+        let handles = (0..2)
+            .map(|i| page_cache.acquire(&other_file, i * Page::size()).unwrap())
+            .collect::<Vec<PageHandle>>();
+        release_pages(&page_cache, handles.into_iter());
+    }
+
+    #[test]
+    fn keep_working_set_during_scan() {
+        let page_cache = PageCache::with_capacity(8);
+        let file = file!(0);
+        let working_set = [(file.clone(), 0), (file.clone(), Page::size())];
+        for _ in 0..2 {
+            for page_key in &working_set {
+                access_page(&page_cache, page_key);
+            }
+        }
+
+        // Scan many pages, each of which is accessed only once
+        let other_file = file!(1);
+        for i in 0..100 {
+            access_page(&page_cache, &(other_file.clone(), i * Page::size()));
+        }
+        for page_key in &working_set {
+            visit_page(&page_cache, page_key, |state, _page_slice| {
+                assert!(**state == PageState::UpToDate);
+            });
+        }
+
+        let stats = page_cache.stats();
+        assert!(stats.num_pages == 8);
+        assert!(stats.num_active_pages == 2);
+        assert!(stats.misses == 102);
+        assert!(stats.hits == 4);
+        assert!(stats.evictions == 94);
+    }
+
+    #[test]
+    fn limit_pages_by_quota() {
+        let page_cache = PageCache::with_capacity(8);
+        let file = file!(0);
+        page_cache.set_quota(file.file_id(), Some(2));
+        for i in 0..5 {
+            access_page(&page_cache, &(file.clone(), i * Page::size()));
+        }
+        assert!(page_cache.num_pages_of(file.file_id()) == 2);
+
+        // Other files are not limited
+        let other_file = file!(1);
+        for i in 0..5 {
+            access_page(&page_cache, &(other_file.clone(), i * Page::size()));
+        }
+        assert!(page_cache.num_pages_of(other_file.file_id()) == 5);
+
+        // A file that has used up its quota with pages in use cannot get more
+        let handles = (0..2)
+            .map(|i| page_cache.acquire(&file, i * Page::size()).unwrap())
+            .collect::<Vec<PageHandle>>();
+        assert!(page_cache.acquire(&file, 2 * Page::size()).is_none());
+        release_pages(&page_cache, handles.into_iter());
+
+        page_cache.set_default_quota(Some(1));
+        page_cache.set_quota(file.file_id(), None);
+        access_page(&page_cache, &(file.clone(), 10 * Page::size()));
+        assert!(page_cache.num_pages_of(file.file_id()) == 1);
+    }
+
+    #[test]
+    fn shrink_page_cache() {
+        let page_cache = PageCache::with_capacity(4);
+        let file = file!(0);
+        for i in 0..3 {
+            access_page(&page_cache, &(file.clone(), i * Page::size()));
+        }
+        // Page 3 is dirty and page 2 is in use
+        visit_page(
+            &page_cache,
+            &(file.clone(), 3 * Page::size()),
+            |state, _| {
+                **state = PageState::Dirty;
+            },
+        );
+        let page_handle = page_cache.acquire(&file, 2 * Page::size()).unwrap();
+
+        assert!(page_cache.shrink(usize::MAX) == 2);
+        assert!(page_cache.stats().num_pages == 2);
+        assert!(page_cache.num_pages_of(file.file_id()) == 2);
+
+        // The page in use is freed once released if the capacity is exceeded
+        page_cache.set_capacity(1);
+        page_cache.discard(page_handle);
+        assert!(page_cache.stats().num_pages == 1);
+        assert!(page_cache.num_dirty_pages() == 1);
+    }
+
+    #[test]
+    fn register_files() {
+        let page_cache = PageCache::with_capacity(1);
+        let file = page_cache.register_file(file!(1));
+        let same_file = page_cache.register_file(file!(1));
+        assert!(Arc::ptr_eq(&file, &same_file));
+        assert!(page_cache.file::<File>(file.file_id()).is_some());
+
+        // Only the registered file can unregister itself
+        page_cache.unregister_file(&*file!(1));
+        assert!(page_cache.file::<File>(file.file_id()).is_some());
+        page_cache.unregister_file(&*file);
+        assert!(page_cache.file::<File>(file.file_id()).is_none());
+
+        let another_file = page_cache.register_file(file!(1));
+        assert!(!Arc::ptr_eq(&file, &another_file));
+    }
+
     #[test]
     fn downcast_file() {
         let page_cache = PageCache::with_capacity(1);
@@ -514,9 +1015,9 @@ mod tests {
         #[derive(Debug, PartialEq, Eq)]
         pub struct File(pub i32);
 
-        impl AsFd for File {
-            fn as_fd(&self) -> i32 {
-                self.0
+        impl AsFileId for File {
+            fn file_id(&self) -> FileId {
+                FileId::new(0, self.0 as u64)
             }
         }
 
@@ -534,6 +1035,18 @@ mod tests {
             page_cache.release(page_handle);
         }
 
+        // Access a page as a read would do, bringing it up to date if needed.
+        pub fn access_page(page_cache: &PageCache, page_key: &(Arc<File>, usize)) {
+            let page_handle = page_cache.acquire(&page_key.0, page_key.1).unwrap();
+            let mut state = page_handle.state();
+            if *state == PageState::Uninit {
+                *state = PageState::UpToDate;
+            }
+            drop(state);
+            page_cache.mark_accessed(&page_handle);
+            page_cache.release(page_handle);
+        }
+
         pub fn release_pages(page_cache: &PageCache, iter: impl Iterator<Item = PageHandle>) {
             iter.for_each(|page_handle| page_cache.release(page_handle));
         }
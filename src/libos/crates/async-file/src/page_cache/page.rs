@@ -2,7 +2,9 @@
 use lazy_static::lazy_static;
 #[cfg(feature = "sgx")]
 use sgx_untrusted_alloc::UntrustedAllocator;
-use std::alloc::{alloc, Layout};
+#[cfg(feature = "sgx")]
+use spin::Mutex;
+use std::alloc::{alloc, dealloc, Layout};
 use std::cell::UnsafeCell;
 #[cfg(feature = "sgx")]
 use std::prelude::v1::*;
@@ -11,27 +13,54 @@ use std::prelude::v1::*;
 lazy_static! {
     static ref U_ALLOC: UntrustedAllocator =
         UntrustedAllocator::new(1024 * 1024 * 512, 4096).unwrap();
+    // The untrusted pages that have been freed, which are reused before
+    // allocating new ones from `U_ALLOC`.
+    static ref FREE_U_PAGES: Mutex<Vec<usize>> = Mutex::new(Vec::new());
 }
 
 pub struct Page {
-    // TODO: for SGX, this buffer needs to be allocated from a different source.
     buf: UnsafeCell<*mut u8>,
+    is_trusted: bool,
 }
 
 unsafe impl Send for Page {}
 unsafe impl Sync for Page {}
 
 impl Page {
+    /// Allocate a page that the host can access directly.
     pub fn new() -> Self {
         #[cfg(not(feature = "sgx"))]
-        let ptr = unsafe { alloc(Layout::from_size_align_unchecked(Page::size(), 4096)) };
+        let ptr = Self::alloc_trusted();
         #[cfg(feature = "sgx")]
-        let ptr = U_ALLOC
-            .new_slice_mut_align(Page::size(), 4096)
-            .unwrap()
-            .as_mut_ptr();
+        let ptr = match FREE_U_PAGES.lock().pop() {
+            Some(addr) => addr as *mut u8,
+            None => U_ALLOC
+                .new_slice_mut_align(Page::size(), 4096)
+                .unwrap()
+                .as_mut_ptr(),
+        };
         let buf = UnsafeCell::new(ptr);
-        Self { buf }
+        Self {
+            buf,
+            is_trusted: !cfg!(feature = "sgx"),
+        }
+    }
+
+    /// Allocate a page in trusted memory, which the host cannot access.
+    ///
+    /// Without SGX, this is the same as `Page::new`.
+    pub fn new_trusted() -> Self {
+        let buf = UnsafeCell::new(Self::alloc_trusted());
+        Self {
+            buf,
+            is_trusted: true,
+        }
+    }
+
+    fn alloc_trusted() -> *mut u8 {
+        let ptr = unsafe { alloc(Layout::from_size_align_unchecked(Page::size(), 4096)) };
+        assert!(!ptr.is_null());
+        ptr
     }
 
     pub unsafe fn as_slice(&self) -> &[u8] {
@@ -55,6 +84,18 @@ impl Page {
     }
 }
 
+impl Drop for Page {
+    fn drop(&mut self) {
+        let ptr = self.as_mut_ptr();
+        if self.is_trusted {
+            unsafe { dealloc(ptr, Layout::from_size_align_unchecked(Page::size(), 4096)) };
+            return;
+        }
+        #[cfg(feature = "sgx")]
+        FREE_U_PAGES.lock().push(ptr as usize);
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
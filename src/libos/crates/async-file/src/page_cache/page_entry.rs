@@ -3,10 +3,12 @@ use spin::{Mutex, MutexGuard};
 use std::any::Any;
 #[cfg(feature = "sgx")]
 use std::prelude::v1::*;
+use std::sync::atomic::{AtomicBool, AtomicU64};
 use std::sync::Arc;
+use std::time::Duration;
 
 use super::LruListName;
-use crate::page_cache::{AsFd, Page, PageState};
+use crate::page_cache::{AsFileId, FileId, Page, PageState};
 use crate::util::lru_list::LruEntry;
 
 /// A page entry represents a cache page in the page cache.
@@ -18,21 +20,29 @@ pub struct PageEntry(Arc<LruEntry<PageEntryInner>>);
 
 pub struct PageEntryInner {
     file: Arc<dyn Any + Send + Sync>,
-    fd: i32,
+    file_id: FileId,
     offset: usize,
     state: Mutex<PageState>,
     list_name: Atomic<Option<LruListName>>,
+    // Whether the entry has been removed from the map of the page cache
+    is_detached: AtomicBool,
+    // Whether the page has been accessed since it was brought in or deactivated
+    is_referenced: AtomicBool,
+    // Whether the page goes to the active list once it is clean and not in use
+    is_active: AtomicBool,
+    // The time since which the page has been dirty, in nanoseconds
+    dirty_since: AtomicU64,
     page: Page,
 }
 
 // Implementationn for PageEntry
 
 impl PageEntry {
-    pub fn new<F>(file: Arc<F>, offset: usize) -> Self
+    pub fn new<F>(file: Arc<F>, offset: usize, is_trusted: bool) -> Self
     where
-        F: AsFd + Send + Sync + 'static,
+        F: AsFileId + Send + Sync + 'static,
     {
-        let inner = PageEntryInner::new(file, offset);
+        let inner = PageEntryInner::new(file, offset, is_trusted);
         let new_self = Self(Arc::new(LruEntry::new(inner)));
         new_self
     }
@@ -46,29 +56,33 @@ impl PageEntry {
         inner
     }
 
-    pub fn key(&self) -> (i32, usize) {
-        (self.fd(), self.offset())
+    pub fn key(&self) -> (FileId, usize) {
+        (self.file_id(), self.offset())
     }
 
     pub unsafe fn reset<F>(&mut self, file: Arc<F>, offset: usize)
     where
-        F: AsFd + Send + Sync + 'static,
+        F: AsFileId + Send + Sync + 'static,
     {
         debug_assert!(Arc::strong_count(&self.0) == 1);
         debug_assert!(Arc::weak_count(&self.0) == 0);
 
         let inner_mut = Arc::get_mut_unchecked(&mut self.0);
-        inner_mut.inner_mut().fd = file.as_fd();
+        inner_mut.inner_mut().file_id = file.file_id();
         inner_mut.inner_mut().file = file as Arc<dyn Any + Send + Sync>;
         inner_mut.inner_mut().offset = offset;
+        inner_mut.inner_mut().is_detached = AtomicBool::new(false);
+        inner_mut.inner_mut().is_referenced = AtomicBool::new(false);
+        inner_mut.inner_mut().is_active = AtomicBool::new(false);
+        inner_mut.inner_mut().dirty_since = AtomicU64::new(0);
     }
 
     pub fn file(&self) -> &Arc<dyn Any + Send + Sync> {
         &self.0.inner().file
     }
 
-    pub fn fd(&self) -> i32 {
-        self.0.inner().fd
+    pub fn file_id(&self) -> FileId {
+        self.0.inner().file_id
     }
 
     pub fn offset(&self) -> usize {
@@ -94,6 +108,44 @@ impl PageEntry {
             .store(new_list_name, Ordering::Relaxed)
     }
 
+    pub(super) fn is_detached(&self) -> bool {
+        self.0.inner().is_detached.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn set_detached(&self) {
+        self.0.inner().is_detached.store(true, Ordering::Relaxed)
+    }
+
+    /// Mark the page as accessed, which activates the page upon its second
+    /// access.
+    pub(super) fn mark_accessed(&self) {
+        let inner = self.0.inner();
+        if inner.is_referenced.swap(true, Ordering::Relaxed) {
+            inner.is_active.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub(super) fn is_active(&self) -> bool {
+        self.0.inner().is_active.load(Ordering::Relaxed)
+    }
+
+    pub fn dirty_since(&self) -> Duration {
+        self.0.inner().dirty_since()
+    }
+
+    pub fn set_dirty_since(&self, time: Duration) {
+        self.0
+            .inner()
+            .dirty_since
+            .store(time.as_nanos() as u64, Ordering::Relaxed)
+    }
+
+    pub(super) fn deactivate(&self) {
+        let inner = self.0.inner();
+        inner.is_active.store(false, Ordering::Relaxed);
+        inner.is_referenced.store(false, Ordering::Relaxed);
+    }
+
     pub fn refcnt(this: &Self) -> usize {
         Arc::strong_count(&this.0)
     }
@@ -112,10 +164,12 @@ impl Clone for PageEntry {
 impl std::fmt::Debug for PageEntry {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("PageEntry")
-            .field("fd", &self.fd())
+            .field("file_id", &self.file_id())
             .field("offset", &self.offset())
             .field("state", &*self.state())
             .field("list_name", &self.list_name())
+            .field("is_detached", &self.is_detached())
+            .field("is_active", &self.is_active())
             .finish()
     }
 }
@@ -123,32 +177,45 @@ impl std::fmt::Debug for PageEntry {
 // Implementationn for PageEntryInner
 
 impl PageEntryInner {
-    pub fn new<F>(file: Arc<F>, offset: usize) -> Self
+    pub fn new<F>(file: Arc<F>, offset: usize, is_trusted: bool) -> Self
     where
-        F: AsFd + Send + Sync + 'static,
+        F: AsFileId + Send + Sync + 'static,
     {
         debug_assert!(offset % Page::size() == 0);
-        let fd = file.as_fd();
+        let file_id = file.file_id();
         let file = file as Arc<dyn Any + Send + Sync>;
+        let page = if is_trusted {
+            Page::new_trusted()
+        } else {
+            Page::new()
+        };
         Self {
             file,
-            fd,
+            file_id,
             offset,
             state: Mutex::new(PageState::Uninit),
             list_name: Atomic::new(None),
-            page: Page::new(),
+            is_detached: AtomicBool::new(false),
+            is_referenced: AtomicBool::new(false),
+            is_active: AtomicBool::new(false),
+            dirty_since: AtomicU64::new(0),
+            page,
         }
     }
 
-    pub fn fd(&self) -> i32 {
-        self.fd
+    pub fn file_id(&self) -> FileId {
+        self.file_id
+    }
+
+    pub fn dirty_since(&self) -> Duration {
+        Duration::from_nanos(self.dirty_since.load(Ordering::Relaxed))
     }
 }
 
 impl std::fmt::Debug for PageEntryInner {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("PageEntryInner")
-            .field("fd", &self.fd)
+            .field("file_id", &self.file_id)
             .field("offset", &self.offset)
             .field("state", &*self.state.lock())
             .field("list_name", &self.list_name.load(Ordering::Relaxed))
@@ -3,8 +3,9 @@ use std::any::Any;
 #[cfg(feature = "sgx")]
 use std::prelude::v1::*;
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::page_cache::{Page, PageEntry, PageState};
+use crate::page_cache::{FileId, Page, PageEntry, PageState};
 
 /// Page handle is the user's view of page entry.
 ///
@@ -28,15 +29,15 @@ impl PageHandle {
         self.0.file()
     }
 
-    pub fn fd(&self) -> i32 {
-        self.0.fd()
+    pub fn file_id(&self) -> FileId {
+        self.0.file_id()
     }
 
     pub fn offset(&self) -> usize {
         self.0.offset()
     }
 
-    pub fn key(&self) -> (i32, usize) {
+    pub fn key(&self) -> (FileId, usize) {
         self.0.key()
     }
 
@@ -47,6 +48,20 @@ impl PageHandle {
     pub fn page(&self) -> &Page {
         self.0.page()
     }
+
+    /// Returns the time since which the page has been dirty.
+    pub fn dirty_since(&self) -> Duration {
+        self.0.dirty_since()
+    }
+
+    /// Record the time at which the page becomes dirty.
+    pub fn set_dirty_since(&self, time: Duration) {
+        self.0.set_dirty_since(time)
+    }
+
+    pub(super) fn entry(&self) -> &PageEntry {
+        &self.0
+    }
 }
 
 impl Clone for PageHandle {
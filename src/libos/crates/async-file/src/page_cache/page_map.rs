@@ -0,0 +1,91 @@
+use std::collections::hash_map::{Iter, Keys};
+use std::collections::HashMap;
+#[cfg(feature = "sgx")]
+use std::prelude::v1::*;
+
+use super::page_entry::PageEntry;
+use super::FileId;
+
+/// The map from the keys to the page entries in a page cache.
+///
+/// The map also keeps track of the number of pages of each file, so that the
+/// number can be checked against the quota of the file.
+pub struct PageMap {
+    entries: HashMap<(FileId, usize), PageEntry>,
+    num_pages: HashMap<FileId, usize>,
+    quotas: HashMap<FileId, usize>,
+    default_quota: Option<usize>,
+}
+
+impl PageMap {
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            num_pages: HashMap::new(),
+            quotas: HashMap::new(),
+            default_quota: None,
+        }
+    }
+
+    pub fn get(&self, key: &(FileId, usize)) -> Option<&PageEntry> {
+        self.entries.get(key)
+    }
+
+    pub fn insert(&mut self, key: (FileId, usize), entry: PageEntry) {
+        self.entries.insert(key, entry).unwrap_none();
+        *self.num_pages.entry(key.0).or_insert(0) += 1;
+    }
+
+    pub fn remove(&mut self, key: &(FileId, usize)) -> Option<PageEntry> {
+        let entry = self.entries.remove(key)?;
+        let num_pages = self.num_pages.get_mut(&key.0).unwrap();
+        *num_pages -= 1;
+        if *num_pages == 0 {
+            self.num_pages.remove(&key.0);
+        }
+        Some(entry)
+    }
+
+    pub fn keys(&self) -> Keys<'_, (FileId, usize), PageEntry> {
+        self.entries.keys()
+    }
+
+    pub fn iter(&self) -> Iter<'_, (FileId, usize), PageEntry> {
+        self.entries.iter()
+    }
+
+    /// Returns the number of pages of a file in the map.
+    pub fn num_pages_of(&self, id: FileId) -> usize {
+        self.num_pages.get(&id).cloned().unwrap_or(0)
+    }
+
+    /// Returns whether a file has used up its quota.
+    pub fn is_quota_exhausted(&self, id: FileId) -> bool {
+        match self.quotas.get(&id).cloned().or(self.default_quota) {
+            Some(quota) => self.num_pages_of(id) >= quota,
+            None => false,
+        }
+    }
+
+    pub fn set_quota(&mut self, id: FileId, quota: Option<usize>) {
+        match quota {
+            Some(quota) => self.quotas.insert(id, quota),
+            None => self.quotas.remove(&id),
+        };
+    }
+
+    pub fn set_default_quota(&mut self, quota: Option<usize>) {
+        self.default_quota = quota;
+    }
+}
+
+impl std::fmt::Debug for PageMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PageMap")
+            .field("entries", &self.entries)
+            .field("num_pages", &self.num_pages)
+            .field("quotas", &self.quotas)
+            .field("default_quota", &self.default_quota)
+            .finish()
+    }
+}
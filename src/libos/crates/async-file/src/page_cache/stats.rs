@@ -0,0 +1,31 @@
+/// The statistics of a page cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PageCacheStats {
+    /// The maximum number of pages.
+    pub capacity: usize,
+    /// The number of pages that are allocated, whether in use or not.
+    pub num_pages: usize,
+    /// The number of clean pages that have been accessed more than once recently.
+    pub num_active_pages: usize,
+    /// The number of clean pages that are the first to be evicted.
+    pub num_inactive_pages: usize,
+    pub num_dirty_pages: usize,
+    /// The number of lookups that find the data of the page in the page cache.
+    pub hits: u64,
+    /// The number of lookups that have to bring a new page into the page cache.
+    pub misses: u64,
+    /// The number of clean pages whose data is dropped to make room for other
+    /// pages or to shrink the page cache.
+    pub evictions: u64,
+}
+
+impl PageCacheStats {
+    /// Returns the ratio of hits to all lookups, or zero if there are none.
+    pub fn hit_ratio(&self) -> f64 {
+        let num_lookups = self.hits + self.misses;
+        if num_lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / num_lookups as f64
+    }
+}
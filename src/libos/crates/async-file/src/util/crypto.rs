@@ -0,0 +1,139 @@
+//! The cryptographic primitives for protected files.
+//!
+//! With SGX, the primitives are provided by the crypto library of the SGX SDK;
+//! otherwise, by the RustCrypto crates.
+
+#[cfg(feature = "sgx")]
+use std::prelude::v1::*;
+
+use async_io::prelude::{Result, *};
+
+/// A 128-bit AES key.
+pub type Key = [u8; 16];
+/// A 96-bit nonce of AES-GCM.
+pub type Nonce = [u8; 12];
+/// A 128-bit MAC, which is either the tag of AES-GCM or the output of AES-CMAC.
+pub type Mac = [u8; 16];
+
+/// Encrypt the bytes of `src` into `dst` with AES-GCM, returning the MAC.
+pub fn encrypt(key: &Key, nonce: &Nonce, aad: &[u8], src: &[u8], dst: &mut [u8]) -> Mac {
+    debug_assert!(src.len() == dst.len());
+    imp::encrypt(key, nonce, aad, src, dst)
+}
+
+/// Decrypt the bytes of a buffer in place with AES-GCM.
+///
+/// The buffer must be in trusted memory, so that the host cannot alter the
+/// ciphertext after it is authenticated. If the MAC does not match, the content
+/// of the buffer is unspecified and `EIO` is returned.
+pub fn decrypt(key: &Key, nonce: &Nonce, aad: &[u8], mac: &Mac, buf: &mut [u8]) -> Result<()> {
+    if !imp::decrypt(key, nonce, aad, mac, buf) {
+        return_errno!(EIO, "MAC mismatch");
+    }
+    Ok(())
+}
+
+/// Compute the AES-CMAC of the concatenation of some byte slices.
+pub fn cmac(key: &Key, parts: &[&[u8]]) -> Mac {
+    imp::cmac(key, parts)
+}
+
+/// Fill a buffer with random bytes.
+pub fn fill_random(buf: &mut [u8]) {
+    imp::fill_random(buf)
+}
+
+#[cfg(feature = "sgx")]
+mod imp {
+    use sgx_tcrypto::{rsgx_rijndael128GCM_decrypt, rsgx_rijndael128GCM_encrypt, SgxCmacHandle};
+    use sgx_trts::trts::rsgx_read_rand;
+
+    use super::*;
+
+    pub fn encrypt(key: &Key, nonce: &Nonce, aad: &[u8], src: &[u8], dst: &mut [u8]) -> Mac {
+        let mut mac = Mac::default();
+        rsgx_rijndael128GCM_encrypt(key, src, nonce, aad, dst, &mut mac)
+            .expect("AES-GCM encryption never fails with valid arguments");
+        mac
+    }
+
+    pub fn decrypt(key: &Key, nonce: &Nonce, aad: &[u8], mac: &Mac, buf: &mut [u8]) -> bool {
+        // The SDK does not decrypt in place
+        let src = buf.to_vec();
+        rsgx_rijndael128GCM_decrypt(key, &src, nonce, aad, mac, buf).is_ok()
+    }
+
+    pub fn cmac(key: &Key, parts: &[&[u8]]) -> Mac {
+        let handle = SgxCmacHandle::new();
+        handle.init(key).unwrap();
+        for part in parts {
+            handle.update_slice(part).unwrap();
+        }
+        let mac = handle.get_hash().unwrap();
+        handle.close().unwrap();
+        mac
+    }
+
+    pub fn fill_random(buf: &mut [u8]) {
+        rsgx_read_rand(buf).expect("failed to generate random bytes");
+    }
+}
+
+#[cfg(not(feature = "sgx"))]
+mod imp {
+    use aes::Aes128;
+    use aes_gcm::aead::generic_array::GenericArray;
+    use aes_gcm::aead::{AeadInPlace, NewAead};
+    use aes_gcm::Aes128Gcm;
+    use cmac::{Cmac, Mac as _, NewMac};
+
+    use super::*;
+
+    pub fn encrypt(key: &Key, nonce: &Nonce, aad: &[u8], src: &[u8], dst: &mut [u8]) -> Mac {
+        let cipher = Aes128Gcm::new(GenericArray::from_slice(key));
+        dst.copy_from_slice(src);
+        let tag = cipher
+            .encrypt_in_place_detached(GenericArray::from_slice(nonce), aad, dst)
+            .expect("AES-GCM encryption never fails with valid arguments");
+        tag.into()
+    }
+
+    pub fn decrypt(key: &Key, nonce: &Nonce, aad: &[u8], mac: &Mac, buf: &mut [u8]) -> bool {
+        let cipher = Aes128Gcm::new(GenericArray::from_slice(key));
+        cipher
+            .decrypt_in_place_detached(
+                GenericArray::from_slice(nonce),
+                aad,
+                buf,
+                GenericArray::from_slice(mac),
+            )
+            .is_ok()
+    }
+
+    pub fn cmac(key: &Key, parts: &[&[u8]]) -> Mac {
+        let mut cmac = Cmac::<Aes128>::new(GenericArray::from_slice(key));
+        for part in parts {
+            cmac.update(part);
+        }
+        cmac.finalize().into_bytes().into()
+    }
+
+    pub fn fill_random(buf: &mut [u8]) {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let ret = unsafe {
+                libc::getrandom(
+                    buf[filled..].as_mut_ptr() as *mut libc::c_void,
+                    buf.len() - filled,
+                    0,
+                )
+            };
+            if ret < 0 {
+                let errno = unsafe { *libc::__errno_location() };
+                assert!(errno == libc::EINTR, "failed to generate random bytes");
+                continue;
+            }
+            filled += ret as usize;
+        }
+    }
+}
@@ -1,3 +1,4 @@
+pub mod crypto;
 pub mod lru_list;
 pub mod object_id;
 
@@ -4,19 +4,24 @@ use std::mem::transmute;
 use std::ops::CoerceUnsized;
 use std::ops::Deref;
 
+use downcast_rs::{impl_downcast, Downcast};
 use futures::future::{self, BoxFuture};
 use futures::prelude::*;
 use inherit_methods_macro::inherit_methods;
 
 use crate::event::{Events, Pollee, Poller};
 use crate::file::{AccessMode, StatusFlags};
+use crate::ioctl::IoctlCmd;
 use crate::prelude::*;
 
 /// An abstract for file APIs.
 ///
 /// An implementation for this trait should make sure all read and write APIs
 /// are non-blocking.
-pub trait File: Debug + Sync + Send {
+///
+/// A trait object of `File` can be downcasted to the concrete type with the
+/// `downcast_ref` method.
+pub trait File: Downcast + Debug + Sync + Send {
     fn read(&self, _buf: &mut [u8]) -> Result<usize> {
         return_errno!(EBADF, "not support read");
     }
@@ -47,11 +52,9 @@ pub trait File: Debug + Sync + Send {
         Events::empty()
     }
 
-    /*
-        fn ioctl(&self, cmd: &mut IoctlCmd) -> Result<i32> {
-            return_op_unsupported_error!("ioctl")
-        }
-    */
+    fn ioctl(&self, _cmd: &mut dyn IoctlCmd) -> Result<()> {
+        return_errno!(ENOTTY, "not support ioctl");
+    }
 
     fn access_mode(&self) -> AccessMode {
         AccessMode::O_RDWR
@@ -65,6 +68,7 @@ pub trait File: Debug + Sync + Send {
         return_errno!(ENOSYS, "not support setting status flags");
     }
 }
+impl_downcast!(File);
 
 /// A wrapper type that makes a `T: File`'s I/O methods _async_.
 #[repr(transparent)]
@@ -82,6 +86,11 @@ impl<F> Async<F> {
 }
 
 impl<F: File + ?Sized> Async<F> {
+    /// Returns the inner file.
+    pub fn file(&self) -> &F {
+        &self.0
+    }
+
     pub async fn read(&self, buf: &mut [u8]) -> Result<usize> {
         let is_nonblocking = self.is_nonblocking();
 
@@ -198,6 +207,7 @@ impl<F: File + ?Sized> Async<F> {
 #[rustfmt::skip]
 impl<F: File + ?Sized> Async<F> {
     pub fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events;
+    pub fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()>;
     pub fn status_flags(&self) -> StatusFlags;
     pub fn set_status_flags(&self, new_status: StatusFlags) -> Result<()>;
     pub fn access_mode(&self) -> AccessMode;
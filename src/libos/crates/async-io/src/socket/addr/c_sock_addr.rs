@@ -2,9 +2,11 @@ use std::mem::{size_of, size_of_val, MaybeUninit};
 
 /// A trait for all C version of C socket addresses.
 ///
-/// There are four types that implement this trait:
+/// There are six types that implement this trait:
 /// * `libc::sockaddr_in`
 /// * `(libc::sockaddr_in, usize)`
+/// * `libc::sockaddr_in6`
+/// * `(libc::sockaddr_in6, usize)`
 /// * `(libc::sockaddr_un, usize)`
 /// * `(libc::sockaddr_storage, usize)`.
 pub trait CSockAddr {
@@ -65,6 +67,34 @@ impl CSockAddr for (libc::sockaddr_in, usize) {
     }
 }
 
+impl CSockAddr for libc::sockaddr_in6 {
+    fn c_family(&self) -> libc::sa_family_t {
+        libc::AF_INET6 as _
+    }
+
+    fn c_addr(&self) -> &[u8] {
+        // Safety. The slice is part of self.
+        unsafe {
+            let addr_ptr = (self as *const _ as *const u8).add(size_of_val(&self.sin6_family));
+            std::slice::from_raw_parts(
+                addr_ptr,
+                size_of::<libc::sockaddr_in6>() - size_of_val(&self.sin6_family),
+            )
+        }
+    }
+}
+
+impl CSockAddr for (libc::sockaddr_in6, usize) {
+    fn c_family(&self) -> libc::sa_family_t {
+        self.0.c_family()
+    }
+
+    fn c_addr(&self) -> &[u8] {
+        assert!(self.1 == size_of::<libc::sockaddr_in6>());
+        self.0.c_addr()
+    }
+}
+
 impl CSockAddr for (libc::sockaddr_un, usize) {
     fn c_family(&self) -> libc::sa_family_t {
         libc::AF_UNIX as _
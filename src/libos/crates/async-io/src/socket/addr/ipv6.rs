@@ -0,0 +1,217 @@
+use std::fmt::{self, Debug};
+use std::mem::size_of;
+
+use super::{Addr, CSockAddr, Domain, Ipv4Addr};
+use crate::prelude::*;
+
+/// An IPv6 socket address, consisting of an IPv6 address, a port, a flow info,
+/// and a scope ID.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Ipv6SocketAddr {
+    ip: Ipv6Addr,
+    port: u16,
+    flowinfo: u32,
+    scope_id: u32,
+}
+
+/// The minimal length of a valid `libc::sockaddr_in6`.
+///
+/// The `sin6_scope_id` field was not part of the original definition in RFC 2133.
+/// So, just like Linux, we accept addresses without the field.
+const SIN6_LEN_RFC2133: usize = 24;
+
+impl Addr for Ipv6SocketAddr {
+    fn domain() -> Domain {
+        Domain::Ipv6
+    }
+
+    fn from_c_storage(c_addr: &libc::sockaddr_storage, c_addr_len: usize) -> Result<Self> {
+        if c_addr_len > std::mem::size_of::<libc::sockaddr_storage>() {
+            return_errno!(EINVAL, "address length is too large");
+        }
+        if c_addr_len < SIN6_LEN_RFC2133 {
+            return_errno!(EINVAL, "address length is too small");
+        }
+        // Safe to convert from sockaddr_storage to sockaddr_in6
+        let c_addr: &libc::sockaddr_in6 = unsafe { std::mem::transmute(c_addr) };
+        let mut new_self = Self::from_c(c_addr)?;
+        if c_addr_len < size_of::<libc::sockaddr_in6>() {
+            new_self.scope_id = 0;
+        }
+        Ok(new_self)
+    }
+
+    fn to_c_storage(&self) -> (libc::sockaddr_storage, usize) {
+        let c_in6_addr = self.to_c();
+        c_in6_addr.to_c_storage()
+    }
+}
+
+impl Ipv6SocketAddr {
+    pub fn new(ip: Ipv6Addr, port: u16) -> Self {
+        Self::with_flowinfo_and_scope_id(ip, port, 0, 0)
+    }
+
+    pub fn with_flowinfo_and_scope_id(
+        ip: Ipv6Addr,
+        port: u16,
+        flowinfo: u32,
+        scope_id: u32,
+    ) -> Self {
+        Self {
+            ip,
+            port,
+            flowinfo,
+            scope_id,
+        }
+    }
+
+    pub fn from_c(c_addr: &libc::sockaddr_in6) -> Result<Self> {
+        if c_addr.sin6_family != libc::AF_INET6 as libc::sa_family_t {
+            return_errno!(EINVAL, "an ipv6 address is expected");
+        }
+        Ok(Self {
+            port: u16::from_be(c_addr.sin6_port),
+            ip: Ipv6Addr::from_c(&c_addr.sin6_addr),
+            flowinfo: u32::from_be(c_addr.sin6_flowinfo),
+            scope_id: c_addr.sin6_scope_id,
+        })
+    }
+
+    pub fn to_c(&self) -> libc::sockaddr_in6 {
+        libc::sockaddr_in6 {
+            sin6_family: libc::AF_INET6 as _,
+            sin6_port: self.port.to_be(),
+            sin6_flowinfo: self.flowinfo.to_be(),
+            sin6_addr: self.ip.to_c(),
+            sin6_scope_id: self.scope_id,
+        }
+    }
+
+    pub fn ip(&self) -> &Ipv6Addr {
+        &self.ip
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns the flow info, in host byte order.
+    pub fn flowinfo(&self) -> u32 {
+        self.flowinfo
+    }
+
+    pub fn scope_id(&self) -> u32 {
+        self.scope_id
+    }
+
+    pub fn set_ip(&mut self, new_ip: Ipv6Addr) {
+        self.ip = new_ip;
+    }
+
+    pub fn set_port(&mut self, new_port: u16) {
+        self.port = new_port;
+    }
+
+    pub fn set_flowinfo(&mut self, new_flowinfo: u32) {
+        self.flowinfo = new_flowinfo;
+    }
+
+    pub fn set_scope_id(&mut self, new_scope_id: u32) {
+        self.scope_id = new_scope_id;
+    }
+}
+
+/// An IPv6 address.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Ipv6Addr([u8; 16] /* big endian */);
+
+impl Ipv6Addr {
+    /// The unspecified address `::`, which is used to bind to all interfaces.
+    pub const UNSPECIFIED: Self = Self([0; 16]);
+
+    /// The loopback address `::1`.
+    pub const LOCALHOST: Self = Self([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+    /// Creates a new IPv6 address from eight 16-bit segments.
+    pub fn new(a: u16, b: u16, c: u16, d: u16, e: u16, f: u16, g: u16, h: u16) -> Self {
+        let mut octets = [0u8; 16];
+        for (i, segment) in [a, b, c, d, e, f, g, h].iter().enumerate() {
+            octets[2 * i..2 * i + 2].copy_from_slice(&segment.to_be_bytes());
+        }
+        Self(octets)
+    }
+
+    /// Creates a new IPv6 address from sixteen octets.
+    pub fn from_octets(octets: [u8; 16]) -> Self {
+        Self(octets)
+    }
+
+    /// Creates a new IPv6 address from its C counterpart.
+    pub fn from_c(c_addr: &libc::in6_addr) -> Self {
+        Self(c_addr.s6_addr)
+    }
+
+    /// Return the C counterpart.
+    pub fn to_c(&self) -> libc::in6_addr {
+        libc::in6_addr { s6_addr: self.0 }
+    }
+
+    /// Return the sixteen octets that make up the address.
+    pub fn octets(&self) -> &[u8; 16] {
+        &self.0
+    }
+
+    /// Return the eight 16-bit segments that make up the address.
+    pub fn segments(&self) -> [u16; 8] {
+        let mut segments = [0u16; 8];
+        for (i, segment) in segments.iter_mut().enumerate() {
+            *segment = u16::from_be_bytes([self.0[2 * i], self.0[2 * i + 1]]);
+        }
+        segments
+    }
+
+    /// Creates an IPv4-mapped IPv6 address, i.e., `::ffff:a.b.c.d`.
+    ///
+    /// This is how IPv4 peers appear to a dual-stack IPv6 socket.
+    pub fn from_ipv4_mapped(ipv4: &Ipv4Addr) -> Self {
+        let mut octets = [0u8; 16];
+        octets[10] = 0xff;
+        octets[11] = 0xff;
+        octets[12..].copy_from_slice(ipv4.octets());
+        Self(octets)
+    }
+
+    /// Returns the IPv4 address if this address is an IPv4-mapped IPv6 address.
+    pub fn to_ipv4_mapped(&self) -> Option<Ipv4Addr> {
+        match self.0 {
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, a, b, c, d] => {
+                Some(Ipv4Addr::new(a, b, c, d))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_unspecified(&self) -> bool {
+        *self == Self::UNSPECIFIED
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        *self == Self::LOCALHOST
+    }
+}
+
+impl fmt::Debug for Ipv6Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ipv4) = self.to_ipv4_mapped() {
+            let [a, b, c, d] = *ipv4.octets();
+            return write!(f, "Ipv6Addr (::ffff:{}.{}.{}.{})", &a, &b, &c, &d);
+        }
+        let [a, b, c, d, e, g, h, i] = self.segments();
+        write!(
+            f,
+            "Ipv6Addr ({:x}:{:x}:{:x}:{:x}:{:x}:{:x}:{:x}:{:x})",
+            a, b, c, d, e, g, h, i
+        )
+    }
+}
@@ -5,6 +5,8 @@ use crate::prelude::*;
 
 mod c_sock_addr;
 mod ipv4;
+mod ipv6;
+mod netlink;
 mod unix;
 
 /// A trait for network addresses.
@@ -30,6 +32,8 @@ pub trait Addr: Clone + Debug + PartialEq + Send + Sync {
 
 pub use self::c_sock_addr::CSockAddr;
 pub use self::ipv4::{Ipv4Addr, Ipv4SocketAddr};
+pub use self::ipv6::{Ipv6Addr, Ipv6SocketAddr};
+pub use self::netlink::NetlinkSocketAddr;
 pub use self::unix::UnixAddr;
 
 #[cfg(test)]
@@ -60,6 +64,38 @@ mod tests {
         check_to_and_from_c(&c_addr, &addr);
     }
 
+    #[test]
+    fn ipv6_to_and_from_c() {
+        let addr = [0x20u8, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        let port = 8888u16;
+        let flowinfo = 0x12345;
+        let scope_id = 3;
+
+        let c_addr = libc::sockaddr_in6 {
+            sin6_family: libc::AF_INET6 as _,
+            sin6_port: port.to_be(),
+            sin6_flowinfo: flowinfo.to_be(),
+            sin6_addr: libc::in6_addr { s6_addr: addr },
+            sin6_scope_id: scope_id,
+        };
+
+        let addr = {
+            let addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+            Ipv6SocketAddr::with_flowinfo_and_scope_id(addr, port, flowinfo, scope_id)
+        };
+
+        check_to_and_from_c(&c_addr, &addr);
+    }
+
+    #[test]
+    fn ipv6_ipv4_mapped() {
+        let ipv4_addr = Ipv4Addr::new(192, 168, 0, 1);
+        let ipv6_addr = Ipv6Addr::from_ipv4_mapped(&ipv4_addr);
+        assert!(ipv6_addr.segments() == [0, 0, 0, 0, 0, 0xffff, 0xc0a8, 0x0001]);
+        assert!(ipv6_addr.to_ipv4_mapped() == Some(ipv4_addr));
+        assert!(Ipv6Addr::LOCALHOST.to_ipv4_mapped().is_none());
+    }
+
     #[test]
     fn unix_unnamed_to_and_from_c() {
         let c_addr = libc::sockaddr_un {
@@ -121,6 +157,15 @@ mod tests {
         check_to_and_from_c(&(c_addr, c_addr_len), &addr);
     }
 
+    #[test]
+    fn netlink_to_and_from_c() {
+        let addr = NetlinkSocketAddr::new(1234, 0x5);
+        let (c_addr, c_addr_len) = addr.to_c_storage();
+        assert!(c_addr.ss_family == libc::AF_NETLINK as libc::sa_family_t);
+        assert!(c_addr_len == size_of::<libc::sockaddr_nl>());
+        assert!(NetlinkSocketAddr::from_c_storage(&c_addr, c_addr_len).unwrap() == addr);
+    }
+
     fn check_to_and_from_c<T: CSockAddr, U: Addr>(c_addr: &T, addr: &U) {
         let c_addr_storage = c_addr.to_c_storage();
 
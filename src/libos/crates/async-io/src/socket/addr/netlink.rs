@@ -0,0 +1,74 @@
+use std::mem::{size_of, MaybeUninit};
+
+use super::{Addr, Domain};
+use crate::prelude::*;
+
+/// A netlink socket address, consisting of a port ID and a multicast group mask.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct NetlinkSocketAddr {
+    port_id: u32,
+    groups: u32,
+}
+
+impl Addr for NetlinkSocketAddr {
+    fn domain() -> Domain {
+        Domain::Netlink
+    }
+
+    fn from_c_storage(c_addr: &libc::sockaddr_storage, c_addr_len: usize) -> Result<Self> {
+        if c_addr_len > size_of::<libc::sockaddr_storage>() {
+            return_errno!(EINVAL, "address length is too large");
+        }
+        if c_addr_len < size_of::<libc::sockaddr_nl>() {
+            return_errno!(EINVAL, "address length is too small");
+        }
+        // Safe to convert from sockaddr_storage to sockaddr_nl
+        let c_addr = unsafe { std::mem::transmute(c_addr) };
+        Self::from_c(c_addr)
+    }
+
+    fn to_c_storage(&self) -> (libc::sockaddr_storage, usize) {
+        let mut c_storage =
+            unsafe { MaybeUninit::<libc::sockaddr_storage>::zeroed().assume_init() };
+        // Safe to convert from sockaddr_storage to sockaddr_nl
+        let c_nl_addr: &mut libc::sockaddr_nl = unsafe { std::mem::transmute(&mut c_storage) };
+        *c_nl_addr = self.to_c();
+        (c_storage, size_of::<libc::sockaddr_nl>())
+    }
+}
+
+impl NetlinkSocketAddr {
+    /// The address of the kernel.
+    pub const KERNEL: Self = Self::new(0, 0);
+
+    pub const fn new(port_id: u32, groups: u32) -> Self {
+        Self { port_id, groups }
+    }
+
+    pub fn from_c(c_addr: &libc::sockaddr_nl) -> Result<Self> {
+        if c_addr.nl_family != libc::AF_NETLINK as libc::sa_family_t {
+            return_errno!(EINVAL, "a netlink address is expected");
+        }
+        Ok(Self {
+            port_id: c_addr.nl_pid,
+            groups: c_addr.nl_groups,
+        })
+    }
+
+    pub fn to_c(&self) -> libc::sockaddr_nl {
+        // Safety. All fields of sockaddr_nl, including the padding, can be zeros.
+        let mut c_addr: libc::sockaddr_nl = unsafe { MaybeUninit::zeroed().assume_init() };
+        c_addr.nl_family = libc::AF_NETLINK as _;
+        c_addr.nl_pid = self.port_id;
+        c_addr.nl_groups = self.groups;
+        c_addr
+    }
+
+    pub fn port_id(&self) -> u32 {
+        self.port_id
+    }
+
+    pub fn groups(&self) -> u32 {
+        self.groups
+    }
+}
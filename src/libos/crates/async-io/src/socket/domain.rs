@@ -5,7 +5,9 @@ use num_enum::{IntoPrimitive, TryFromPrimitive};
 #[repr(i32)]
 pub enum Domain {
     Ipv4 = libc::AF_INET,
+    Ipv6 = libc::AF_INET6,
     Unix = libc::AF_LOCAL,
+    Netlink = libc::AF_NETLINK,
 }
 
 #[cfg(test)]
@@ -17,7 +19,9 @@ mod tests {
     fn from_i32() {
         // Positive cases
         assert!(Domain::try_from(libc::AF_INET).unwrap() == Domain::Ipv4);
+        assert!(Domain::try_from(libc::AF_INET6).unwrap() == Domain::Ipv6);
         assert!(Domain::try_from(libc::AF_LOCAL).unwrap() == Domain::Unix);
+        assert!(Domain::try_from(libc::AF_NETLINK).unwrap() == Domain::Netlink);
 
         // Negative cases
         assert!(Domain::try_from(-1).is_err());
@@ -1,7 +1,13 @@
 mod addr;
 mod domain;
+mod flags;
 mod shutdown;
+pub mod sockopt;
 
-pub use self::addr::{Addr, CSockAddr, Ipv4Addr, Ipv4SocketAddr, UnixAddr};
+pub use self::addr::{
+    Addr, CSockAddr, Ipv4Addr, Ipv4SocketAddr, Ipv6Addr, Ipv6SocketAddr, NetlinkSocketAddr,
+    UnixAddr,
+};
 pub use self::domain::Domain;
+pub use self::flags::{MsgFlags, RecvFlags, SendFlags};
 pub use self::shutdown::Shutdown;
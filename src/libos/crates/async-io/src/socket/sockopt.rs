@@ -0,0 +1,216 @@
+//! Socket options.
+//!
+//! Each socket option is represented by a pair of ioctl-style commands: one for
+//! `setsockopt` and the other for `getsockopt`. Socket implementations handle the
+//! commands that are meaningful to them in their `ioctl` methods and reject the rest.
+//! See the `ioctl` module for more info about ioctl-style commands.
+
+use std::time::Duration;
+
+use crate::impl_ioctl_cmd;
+use crate::prelude::*;
+
+impl_ioctl_cmd! {
+    /// = setsockopt SO_REUSEADDR
+    pub struct SetReuseAddr<Input=bool, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt SO_REUSEADDR
+    pub struct GetReuseAddr<Input=(), Output=bool> {}
+}
+
+impl_ioctl_cmd! {
+    /// = setsockopt SO_REUSEPORT
+    pub struct SetReusePort<Input=bool, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt SO_REUSEPORT
+    pub struct GetReusePort<Input=(), Output=bool> {}
+}
+
+impl_ioctl_cmd! {
+    /// = setsockopt SO_KEEPALIVE
+    pub struct SetKeepAlive<Input=bool, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt SO_KEEPALIVE
+    pub struct GetKeepAlive<Input=(), Output=bool> {}
+}
+
+impl_ioctl_cmd! {
+    /// = setsockopt SO_LINGER
+    pub struct SetLinger<Input=Linger, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt SO_LINGER
+    pub struct GetLinger<Input=(), Output=Linger> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt SO_ERROR
+    ///
+    /// Getting the pending error of a socket also clears the error.
+    pub struct GetSockError<Input=(), Output=Option<Errno>> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt SO_PEERCRED
+    pub struct GetPeerCred<Input=(), Output=PeerCred> {}
+}
+
+impl_ioctl_cmd! {
+    /// = setsockopt SO_RCVBUF
+    pub struct SetRecvBufSize<Input=usize, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt SO_RCVBUF
+    pub struct GetRecvBufSize<Input=(), Output=usize> {}
+}
+
+impl_ioctl_cmd! {
+    /// = setsockopt SO_SNDBUF
+    pub struct SetSendBufSize<Input=usize, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt SO_SNDBUF
+    pub struct GetSendBufSize<Input=(), Output=usize> {}
+}
+
+impl_ioctl_cmd! {
+    /// = setsockopt SO_RCVTIMEO (`None` means no timeout)
+    pub struct SetRecvTimeout<Input=Option<Duration>, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt SO_RCVTIMEO (`None` means no timeout)
+    pub struct GetRecvTimeout<Input=(), Output=Option<Duration>> {}
+}
+
+impl_ioctl_cmd! {
+    /// = setsockopt SO_SNDTIMEO (`None` means no timeout)
+    pub struct SetSendTimeout<Input=Option<Duration>, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt SO_SNDTIMEO (`None` means no timeout)
+    pub struct GetSendTimeout<Input=(), Output=Option<Duration>> {}
+}
+
+impl_ioctl_cmd! {
+    /// = setsockopt IPV6_V6ONLY
+    pub struct SetIpv6Only<Input=bool, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt IPV6_V6ONLY
+    pub struct GetIpv6Only<Input=(), Output=bool> {}
+}
+
+impl_ioctl_cmd! {
+    /// = setsockopt TCP_NODELAY
+    pub struct SetTcpNoDelay<Input=bool, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt TCP_NODELAY
+    pub struct GetTcpNoDelay<Input=(), Output=bool> {}
+}
+
+impl_ioctl_cmd! {
+    /// = setsockopt TCP_CORK
+    pub struct SetTcpCork<Input=bool, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt TCP_CORK
+    pub struct GetTcpCork<Input=(), Output=bool> {}
+}
+
+impl_ioctl_cmd! {
+    /// = setsockopt TCP_KEEPIDLE (in seconds)
+    pub struct SetTcpKeepIdle<Input=u32, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt TCP_KEEPIDLE (in seconds)
+    pub struct GetTcpKeepIdle<Input=(), Output=u32> {}
+}
+
+impl_ioctl_cmd! {
+    /// = setsockopt TCP_KEEPINTVL (in seconds)
+    pub struct SetTcpKeepIntvl<Input=u32, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt TCP_KEEPINTVL (in seconds)
+    pub struct GetTcpKeepIntvl<Input=(), Output=u32> {}
+}
+
+impl_ioctl_cmd! {
+    /// = setsockopt TCP_KEEPCNT
+    pub struct SetTcpKeepCnt<Input=u32, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// = getsockopt TCP_KEEPCNT
+    pub struct GetTcpKeepCnt<Input=(), Output=u32> {}
+}
+
+/// The value of the `SO_LINGER` option.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Linger {
+    /// Whether lingering is enabled.
+    pub enabled: bool,
+    /// How many seconds to linger for.
+    pub secs: u32,
+}
+
+impl Linger {
+    pub fn from_c(c_linger: &libc::linger) -> Result<Self> {
+        if c_linger.l_linger < 0 {
+            return_errno!(EINVAL, "the linger time must not be negative");
+        }
+        Ok(Self {
+            enabled: c_linger.l_onoff != 0,
+            secs: c_linger.l_linger as u32,
+        })
+    }
+
+    pub fn to_c(&self) -> libc::linger {
+        libc::linger {
+            l_onoff: self.enabled as _,
+            l_linger: self.secs as _,
+        }
+    }
+}
+
+/// The value of the `SO_PEERCRED` option, i.e., the credentials of the peer
+/// process at the time of connecting, listening or creating a socket pair.
+///
+/// This type has the same memory layout as `struct ucred`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PeerCred {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl PeerCred {
+    /// The credentials of a socket that has no peer, just like Linux.
+    pub const fn invalid() -> Self {
+        // Linux uses the overflow UID and GID for unknown credentials
+        const OVERFLOW_ID: u32 = 65534;
+        Self {
+            pid: 0,
+            uid: OVERFLOW_ID,
+            gid: OVERFLOW_ID,
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use atomic::Atomic;
 use ringbuf::{Consumer as RbConsumer, Producer as RbProducer, RingBuffer};
@@ -27,6 +27,7 @@ pub struct Consumer {
 
 #[derive(Debug)]
 struct Common {
+    id: usize,
     producer: EndPoint<RbProducer<u8>>,
     consumer: EndPoint<RbConsumer<u8>>,
     event_lock: Mutex<()>,
@@ -83,7 +84,11 @@ impl Common {
 
         let event_lock = Mutex::new(());
 
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
         Ok(Self {
+            id,
             producer,
             consumer,
             event_lock,
@@ -133,6 +138,12 @@ impl<T> std::fmt::Debug for EndPoint<T> {
 }
 
 impl Producer {
+    /// Returns the ID of the channel, which is unique among all channels and
+    /// shared by both ends.
+    pub fn channel_id(&self) -> usize {
+        self.common.id
+    }
+
     fn this_end(&self) -> &EndPoint<RbProducer<u8>> {
         &self.common.producer
     }
@@ -158,24 +169,62 @@ impl Producer {
             peer_end.pollee().add_events(Events::IN);
         }
     }
+
+    /// Shutdown the producer.
+    ///
+    /// After the shutdown, any attempt to write to the channel fails with EPIPE,
+    /// while the consumer gets EOF after consuming all the remaining data.
+    pub fn shutdown(&self) {
+        self.this_end().shutdown();
+
+        // Wake up the blocked writers so that they can observe the shutdown
+        self.this_end().pollee().add_events(Events::OUT);
+        self.peer_end()
+            .pollee()
+            .add_events(Events::IN | Events::HUP);
+    }
+
+    /// Returns whether either end of the channel has been shut down.
+    pub fn is_shutdown(&self) -> bool {
+        self.this_end().is_shutdown() || self.peer_end().is_shutdown()
+    }
+
+    /// Returns the number of bytes that can be written without blocking.
+    pub fn free_space(&self) -> usize {
+        self.this_end().ringbuf().remaining()
+    }
+
+    /// Returns the capacity of the channel.
+    pub fn capacity(&self) -> usize {
+        self.this_end().ringbuf().capacity()
+    }
 }
 
 impl File for Producer {
     fn write(&self, buf: &[u8]) -> Result<usize> {
-        let this_end = self.this_end();
-        let peer_end = self.peer_end();
+        self.writev(&[buf])
+    }
 
-        if this_end.is_shutdown() || peer_end.is_shutdown() {
-            return_errno!(EPIPE, "");
+    fn writev(&self, bufs: &[&[u8]]) -> Result<usize> {
+        if self.is_shutdown() {
+            return_errno!(EPIPE, "the channel has been shut down");
         }
 
-        if buf.len() == 0 {
+        let total_len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        if total_len == 0 {
             return Ok(0);
         }
 
         let nbytes = {
-            let mut rb = this_end.ringbuf();
-            let nbytes = rb.push_slice(buf);
+            let mut rb = self.this_end().ringbuf();
+            let mut nbytes = 0;
+            for buf in bufs {
+                let this_len = rb.push_slice(buf);
+                nbytes += this_len;
+                if this_len < buf.len() {
+                    break;
+                }
+            }
             nbytes
         };
 
@@ -188,8 +237,6 @@ impl File for Producer {
         }
     }
 
-    // TODO: implement writev
-
     fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
         self.this_end().pollee().poll(mask, poller)
     }
@@ -207,13 +254,17 @@ impl File for Producer {
 
 impl Drop for Producer {
     fn drop(&mut self) {
-        self.peer_end()
-            .pollee()
-            .add_events(Events::IN | Events::HUP);
+        self.shutdown();
     }
 }
 
 impl Consumer {
+    /// Returns the ID of the channel, which is unique among all channels and
+    /// shared by both ends.
+    pub fn channel_id(&self) -> usize {
+        self.common.id
+    }
+
     fn this_end(&self) -> &EndPoint<RbConsumer<u8>> {
         &self.common.consumer
     }
@@ -232,45 +283,125 @@ impl Consumer {
         let event_lock = self.common.lock_event();
 
         let rb = this_end.ringbuf();
-        if rb.is_empty() {
+        if rb.is_empty() && !peer_end.is_shutdown() {
             this_end.pollee().del_events(Events::IN);
         }
         if !rb.is_full() {
             peer_end.pollee().add_events(Events::OUT);
         }
     }
+
+    /// Shutdown the consumer.
+    ///
+    /// After the shutdown, any attempt to read from the channel returns EOF,
+    /// while the producer gets EPIPE.
+    pub fn shutdown(&self) {
+        self.this_end().shutdown();
+
+        // Wake up the blocked readers so that they can observe the shutdown
+        self.this_end().pollee().add_events(Events::IN);
+        self.peer_end()
+            .pollee()
+            .add_events(Events::OUT | Events::HUP);
+    }
+
+    /// Returns whether either end of the channel has been shut down.
+    pub fn is_shutdown(&self) -> bool {
+        self.this_end().is_shutdown() || self.peer_end().is_shutdown()
+    }
+
+    /// Copy the data at the front of the channel into a set of buffers without consuming them.
+    ///
+    /// Unlike `readv`, EOF is indicated by a return value of zero only if the producer has
+    /// been shut down; otherwise, EAGAIN is returned if there is no data.
+    pub fn peekv(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        if self.this_end().is_shutdown() {
+            return Ok(0);
+        }
+        // Check the shutdown of the producer before accessing the data so that no data
+        // written before the shutdown can be missed.
+        let is_peer_shutdown = self.peer_end().is_shutdown();
+
+        let nbytes = {
+            let rb = self.this_end().ringbuf();
+            let mut nbytes = 0;
+            rb.access(|first, second| {
+                let mut src = first.iter().chain(second.iter());
+                for buf in bufs.iter_mut() {
+                    for (dst_byte, src_byte) in buf.iter_mut().zip(&mut src) {
+                        *dst_byte = *src_byte;
+                        nbytes += 1;
+                    }
+                }
+            });
+            nbytes
+        };
+
+        if nbytes > 0 || is_peer_shutdown {
+            Ok(nbytes)
+        } else {
+            return_errno!(EAGAIN, "try peek later");
+        }
+    }
+
+    /// Discard at most `nbytes` bytes at the front of the channel, returning the number
+    /// of bytes discarded.
+    pub fn skip(&self, nbytes: usize) -> usize {
+        let nbytes = {
+            let mut rb = self.this_end().ringbuf();
+            rb.discard(nbytes)
+        };
+        self.update_pollee();
+        nbytes
+    }
+
+    /// Returns the number of bytes that can be read without blocking.
+    pub fn ready_len(&self) -> usize {
+        self.this_end().ringbuf().len()
+    }
 }
 
 impl File for Consumer {
     fn read(&self, buf: &mut [u8]) -> Result<usize> {
-        let this_end = self.this_end();
-        let peer_end = self.peer_end();
+        self.readv(&mut [buf])
+    }
 
-        if this_end.is_shutdown() || peer_end.is_shutdown() {
-            return_errno!(EPIPE, "");
+    fn readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        if self.this_end().is_shutdown() {
+            return Ok(0);
         }
 
-        if buf.len() == 0 {
+        let total_len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        if total_len == 0 {
             return Ok(0);
         }
+        // Check the shutdown of the producer before consuming the data so that no data
+        // written before the shutdown can be missed.
+        let is_peer_shutdown = self.peer_end().is_shutdown();
 
         let nbytes = {
-            let mut rb = this_end.ringbuf();
-            let nbytes = rb.pop_slice(buf);
+            let mut rb = self.this_end().ringbuf();
+            let mut nbytes = 0;
+            for buf in bufs.iter_mut() {
+                let this_len = rb.pop_slice(buf);
+                nbytes += this_len;
+                if this_len < buf.len() {
+                    break;
+                }
+            }
             nbytes
         };
 
         self.update_pollee();
 
-        if nbytes > 0 {
+        // An empty channel whose producer has been shut down means EOF
+        if nbytes > 0 || is_peer_shutdown {
             Ok(nbytes)
         } else {
             return_errno!(EAGAIN, "try read later");
         }
     }
 
-    // TODO: implement read
-
     fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
         self.this_end().pollee().poll(mask, poller)
     }
@@ -288,9 +419,7 @@ impl File for Consumer {
 
 impl Drop for Consumer {
     fn drop(&mut self) {
-        self.peer_end()
-            .pollee()
-            .add_events(Events::OUT | Events::HUP);
+        self.shutdown();
     }
 }
 
@@ -398,6 +527,49 @@ mod tests {
         assert!(producer.poll(mask, None) == Events::empty());
         assert!(consumer.poll(mask, None) == Events::IN);
     }
+
+    #[test]
+    fn peek_and_skip() {
+        let channel = Channel::with_capacity(16).unwrap();
+        let (producer, consumer) = channel.split();
+
+        let mut buf = [0u8; 4];
+        assert!(consumer.peekv(&mut [&mut buf]).has_errno(EAGAIN));
+
+        producer.write(b"hello").unwrap();
+        assert!(consumer.peekv(&mut [&mut buf]).unwrap() == 4);
+        assert!(&buf == b"hell");
+        assert!(consumer.ready_len() == 5);
+
+        assert!(consumer.skip(1) == 1);
+        let (mut buf0, mut buf1) = ([0u8; 2], [0u8; 8]);
+        assert!(consumer.readv(&mut [&mut buf0, &mut buf1]).unwrap() == 4);
+        assert!(&buf0 == b"el" && &buf1[..2] == b"lo");
+        assert!(consumer.ready_len() == 0);
+    }
+
+    #[test]
+    fn shutdown() {
+        let mask = Events::all();
+        let mut buf = [0u8; 8];
+
+        // Shutting down the producer results in EOF after the remaining data are consumed
+        let channel = Channel::with_capacity(16).unwrap();
+        let (producer, consumer) = channel.split();
+        producer.write(b"bye").unwrap();
+        producer.shutdown();
+        assert!(producer.write(b"bye").has_errno(EPIPE));
+        assert!(consumer.poll(mask, None) == Events::IN | Events::HUP);
+        assert!(consumer.read(&mut buf).unwrap() == 3);
+        assert!(consumer.read(&mut buf).unwrap() == 0);
+
+        // Dropping the consumer results in EPIPE
+        let channel = Channel::with_capacity(16).unwrap();
+        let (producer, consumer) = channel.split();
+        drop(consumer);
+        assert!(producer.poll(mask, None) == Events::OUT | Events::HUP);
+        assert!(producer.write(b"hello").has_errno(EPIPE));
+    }
 }
 
 fn check_status_flags(flags: StatusFlags) -> Result<()> {
@@ -1,3 +1,5 @@
+use core::cell::Cell;
+
 use flume::{Receiver, Sender};
 use futures::task::waker_ref;
 
@@ -20,6 +22,20 @@ pub fn shutdown() {
     EXECUTOR.shutdown()
 }
 
+/// Returns the ID of the executor thread, i.e., the vCPU, that runs the current task.
+///
+/// Returns `None` if the current thread is not an executor thread.
+pub fn current_thread_id() -> Option<u32> {
+    let thread_id = CURRENT_THREAD_ID.get();
+    if thread_id == u32::MAX {
+        return None;
+    }
+    Some(thread_id)
+}
+
+#[thread_local]
+static CURRENT_THREAD_ID: Cell<u32> = Cell::new(u32::MAX);
+
 lazy_static! {
     pub(crate) static ref EXECUTOR: Executor = {
         let parallelism = CONFIG.parallelism();
@@ -77,6 +93,7 @@ impl Executor {
     pub fn run_tasks(&self) {
         let run_queue_id = self.next_run_queue_id.fetch_add(1, Ordering::Relaxed);
         assert!(run_queue_id < self.parallelism);
+        CURRENT_THREAD_ID.set(run_queue_id);
         let run_queue = &self.run_queues[run_queue_id as usize];
         loop {
             let task = {
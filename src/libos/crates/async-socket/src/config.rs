@@ -0,0 +1,36 @@
+//! The global configurations of async sockets.
+//!
+//! Unlike host-socket, whose buffers grow and shrink on demand, the send and
+//! receive buffers of an async socket are fixed-sized. But the sizes can be
+//! configured instead of being hard-coded.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{DEFAULT_RECV_BUF_SIZE, DEFAULT_SEND_BUF_SIZE};
+
+static SEND_BUF_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_SEND_BUF_SIZE);
+static RECV_BUF_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_RECV_BUF_SIZE);
+
+/// Set the size of the send buffer of a connected socket.
+///
+/// The new size only applies to the sockets connected afterwards.
+pub fn set_send_buf_size(size: usize) {
+    assert!(size > 1);
+    SEND_BUF_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// Set the size of the receive buffer of a connected socket.
+///
+/// The new size only applies to the sockets connected afterwards.
+pub fn set_recv_buf_size(size: usize) {
+    assert!(size > 1);
+    RECV_BUF_SIZE.store(size, Ordering::Relaxed);
+}
+
+pub(crate) fn send_buf_size() -> usize {
+    SEND_BUF_SIZE.load(Ordering::Relaxed)
+}
+
+pub(crate) fn recv_buf_size() -> usize {
+    RECV_BUF_SIZE.load(Ordering::Relaxed)
+}
@@ -21,6 +21,7 @@ extern crate sgx_untrusted_alloc;
 #[cfg(feature = "sgx")]
 use std::prelude::v1::*;
 
+pub mod config;
 mod io;
 mod poll;
 mod util;
@@ -64,6 +65,8 @@ enum State<P: IoUringProvider> {
 
 // Implementation for Socket
 
+/// The default sizes of the send and receive buffers, which can be changed via
+/// the `config` module.
 pub const DEFAULT_SEND_BUF_SIZE: usize = 65 * 1024;
 pub const DEFAULT_RECV_BUF_SIZE: usize = 65 * 1024;
 
@@ -149,8 +152,8 @@ impl<P: IoUringProvider> Socket<P> {
             return retval;
         }
         *state = {
-            let sender = Sender::new(self.common.clone(), DEFAULT_SEND_BUF_SIZE);
-            let receiver = Receiver::new(self.common.clone(), DEFAULT_RECV_BUF_SIZE);
+            let sender = Sender::new(self.common.clone(), config::send_buf_size());
+            let receiver = Receiver::new(self.common.clone(), config::recv_buf_size());
             State::Connected { sender, receiver }
         };
 
@@ -210,8 +213,8 @@ impl<P: IoUringProvider> Socket<P> {
 
         let common = Arc::new(Common::new_with_fd(retval));
         let state = RwLock::new({
-            let sender = Sender::new(common.clone(), DEFAULT_SEND_BUF_SIZE);
-            let receiver = Receiver::new(common.clone(), DEFAULT_RECV_BUF_SIZE);
+            let sender = Sender::new(common.clone(), config::send_buf_size());
+            let receiver = Receiver::new(common.clone(), config::recv_buf_size());
             State::Connected { sender, receiver }
         });
         Ok(Self { common, state })
@@ -0,0 +1,51 @@
+//! The global configurations of host sockets.
+//!
+//! The send and receive buffers of a connected socket reside in untrusted memory.
+//! A buffer starts small and grows on demand up to its limit, which is either given
+//! by `SO_SNDBUF`/`SO_RCVBUF` or the default one, and shrinks back when it is
+//! underused. The total size of all buffers is bounded by a global budget.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static MIN_BUF_SIZE: AtomicUsize = AtomicUsize::new(4 * 1024);
+static DEFAULT_MAX_BUF_SIZE: AtomicUsize = AtomicUsize::new(256 * 1024);
+static BUF_BUDGET: AtomicUsize = AtomicUsize::new(128 * 1024 * 1024);
+
+/// Set the min size of a send or receive buffer.
+///
+/// A buffer is created with the min size and never shrinks below it.
+///
+/// The new size only applies to the sockets created afterwards.
+pub fn set_min_buf_size(size: usize) {
+    assert!(size > 1);
+    MIN_BUF_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// Set the max size of a send or receive buffer whose size is not set via
+/// `SO_SNDBUF`/`SO_RCVBUF`.
+///
+/// The new size only applies to the sockets created afterwards.
+pub fn set_max_buf_size(size: usize) {
+    assert!(size > 1);
+    DEFAULT_MAX_BUF_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// Set the max total size of the buffers of all sockets.
+///
+/// The budget only limits the growth of buffers. A buffer of the min size is
+/// always granted so that the creation of a socket never fails for the budget.
+pub fn set_buf_budget(budget: usize) {
+    BUF_BUDGET.store(budget, Ordering::Relaxed);
+}
+
+pub(crate) fn min_buf_size() -> usize {
+    MIN_BUF_SIZE.load(Ordering::Relaxed)
+}
+
+pub(crate) fn default_max_buf_size() -> usize {
+    DEFAULT_MAX_BUF_SIZE.load(Ordering::Relaxed)
+}
+
+pub(crate) fn buf_budget() -> usize {
+    BUF_BUDGET.load(Ordering::Relaxed)
+}
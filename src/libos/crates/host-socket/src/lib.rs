@@ -13,9 +13,11 @@ extern crate sgx_tstd as std;
 
 #[macro_use]
 mod prelude;
+pub mod config;
 mod runtime;
 mod stream;
 mod util;
 
 pub use self::runtime::Runtime;
-pub use self::stream::StreamSocket;
+pub use self::stream::{SendStats, StreamSocket};
+pub use self::util::Timer;
@@ -3,8 +3,20 @@ use io_uring_callback::IoUring;
 /// The runtime support for HostSocket.
 ///
 /// This trait provides a common interface for user-implemented runtimes
-/// that support HostSocket. Currently, the only dependency is a singleton
-/// of IoUring instance.
+/// that support HostSocket. The main dependency is a singleton of IoUring
+/// instance. Optionally, a runtime may tell the vCPUs that run async tasks,
+/// which helps listener sockets spread accepted connections across vCPUs.
 pub trait Runtime: Send + Sync + 'static {
     fn io_uring() -> &'static IoUring;
+
+    /// Returns the number of vCPUs, i.e., the threads that run async tasks.
+    fn num_vcpus() -> usize {
+        1
+    }
+
+    /// Returns the ID of the vCPU that runs the current task, which is less than
+    /// the number of vCPUs.
+    fn vcpu_id() -> usize {
+        0
+    }
 }
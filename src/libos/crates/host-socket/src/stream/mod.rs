@@ -1,6 +1,17 @@
 mod states;
 
-use self::states::{Common, ConnectedStream, ConnectingStream, InitStream, ListenerStream};
+use async_io::file::StatusFlags;
+use async_io::ioctl::IoctlCmd;
+use async_io::match_ioctl_cmd_mut;
+use async_io::socket::sockopt::{
+    GetRecvBufSize, GetSendBufSize, SetRecvBufSize, SetSendBufSize, SetTcpCork, SetTcpNoDelay,
+};
+use async_io::socket::{MsgFlags, RecvFlags, SendFlags, Shutdown};
+
+pub use self::states::SendStats;
+use self::states::{
+    adjust_buf_size, Common, ConnectedStream, ConnectingStream, InitStream, ListenerStream,
+};
 use crate::prelude::*;
 use crate::runtime::Runtime;
 
@@ -63,19 +74,17 @@ impl<A: Addr, R: Runtime> StreamSocket<A, R> {
     }
 
     pub async fn connect(&self, peer_addr: &A) -> Result<()> {
-        // Create the new intermediate state of connecting and save the
+        self.try_complete_connect();
+
+        // Create the new intermediate state of connecting, which keeps the
         // old state of init in case of failure to connect.
-        let (init_stream, connecting_stream) = {
+        let connecting_stream = {
             let mut state = self.state.write().unwrap();
             match &*state {
                 State::Init(init_stream) => {
-                    let connecting_stream = {
-                        let common = init_stream.common().clone();
-                        ConnectingStream::new(peer_addr, common)?
-                    };
-                    let init_stream = init_stream.clone();
+                    let connecting_stream = ConnectingStream::new(peer_addr, init_stream.clone())?;
                     *state = State::Connect(connecting_stream.clone());
-                    (init_stream, connecting_stream)
+                    connecting_stream
                 }
                 State::Connect(_) => {
                     return_errno!(EALREADY, "the socket is already connecting");
@@ -89,27 +98,64 @@ impl<A: Addr, R: Runtime> StreamSocket<A, R> {
             }
         };
 
+        // For a non-blocking socket, the connect completes asynchronously. The user
+        // may wait for the socket to become writable and then check SO_ERROR.
+        if connecting_stream.common().is_nonblocking() {
+            connecting_stream.start_connect();
+            return_errno!(EINPROGRESS, "the connect is in progress");
+        }
+
         let res = connecting_stream.connect().await;
+        // The connect timed out, yet it goes on in the background. Its result is
+        // reported through SO_ERROR, just as that of a non-blocking connect.
+        if res.has_errno(EINPROGRESS) {
+            return res;
+        }
+        self.complete_connect(&connecting_stream, &res);
+        res
+    }
+
+    /// Transit the state from connecting to either connected or init if the
+    /// async connect started by a non-blocking socket has completed.
+    ///
+    /// The result of such a connect is reported through SO_ERROR.
+    fn try_complete_connect(&self) {
+        let connecting_stream = {
+            let state = self.state.read().unwrap();
+            match &*state {
+                State::Connect(connecting_stream) => connecting_stream.clone(),
+                _ => return,
+            }
+        };
+        let res = match connecting_stream.connect_result() {
+            Some(res) => res,
+            None => return,
+        };
+        if let Err(e) = &res {
+            connecting_stream.common().set_errno(e.errno());
+        }
+        self.complete_connect(&connecting_stream, &res);
+    }
+
+    fn complete_connect(&self, connecting_stream: &Arc<ConnectingStream<A, R>>, res: &Result<()>) {
+        let mut state = self.state.write().unwrap();
+        // The state may have been transited by another thread
+        match &*state {
+            State::Connect(current) if Arc::ptr_eq(current, connecting_stream) => (),
+            _ => return,
+        }
 
         // If success, then the state transits to connected; otherwise,
         // the state is restored to the init state.
-        match &res {
+        *state = match res {
             Ok(()) => {
-                let connected_stream = {
-                    let common = init_stream.common().clone();
-                    common.set_peer_addr(peer_addr);
-                    ConnectedStream::new(common)
-                };
-
-                let mut state = self.state.write().unwrap();
-                *state = State::Connected(connected_stream);
-            }
-            Err(e) => {
-                let mut state = self.state.write().unwrap();
-                *state = State::Init(init_stream);
+                let common = connecting_stream.common().clone();
+                common.set_peer_addr(connecting_stream.peer_addr());
+                let connected_stream = ConnectedStream::new(common);
+                State::Connected(connected_stream)
             }
-        }
-        res
+            Err(_) => State::Init(connecting_stream.init_stream().clone()),
+        };
     }
 
     pub async fn accept(&self) -> Result<Self> {
@@ -133,18 +179,42 @@ impl<A: Addr, R: Runtime> StreamSocket<A, R> {
         self.readv(&mut [buf]).await
     }
 
-    pub async fn readv(&self, buf: &mut [&mut [u8]]) -> Result<usize> {
+    pub async fn readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        let (nbytes, _) = self.recvmsg(bufs, RecvFlags::empty()).await?;
+        Ok(nbytes)
+    }
+
+    /// Receive data into the bufs with the given flags.
+    ///
+    /// Besides the number of bytes received, the flags of the received message
+    /// are returned, which are always empty for stream sockets.
+    pub async fn recvmsg(
+        &self,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+    ) -> Result<(usize, MsgFlags)> {
+        self.try_complete_connect();
+
         let connected_stream = {
             let state = self.state.read().unwrap();
             match &*state {
                 State::Connected(connected_stream) => connected_stream.clone(),
+                State::Connect(connecting_stream) => {
+                    if connecting_stream.common().is_nonblocking()
+                        || flags.contains(RecvFlags::MSG_DONTWAIT)
+                    {
+                        return_errno!(EAGAIN, "the socket is connecting");
+                    }
+                    return_errno!(ENOTCONN, "the socket is not connected");
+                }
                 _ => {
-                    return_errno!(EINVAL, "the socket is not connected");
+                    return_errno!(ENOTCONN, "the socket is not connected");
                 }
             }
         };
 
-        connected_stream.readv(buf).await
+        let nbytes = connected_stream.recvmsg(bufs, flags).await?;
+        Ok((nbytes, MsgFlags::empty()))
     }
 
     pub async fn write(&self, buf: &[u8]) -> Result<usize> {
@@ -152,74 +222,162 @@ impl<A: Addr, R: Runtime> StreamSocket<A, R> {
     }
 
     pub async fn writev(&self, bufs: &[&[u8]]) -> Result<usize> {
+        self.sendmsg(bufs, SendFlags::empty()).await
+    }
+
+    /// Send data in the bufs with the given flags.
+    pub async fn sendmsg(&self, bufs: &[&[u8]], flags: SendFlags) -> Result<usize> {
+        self.try_complete_connect();
+
         let connected_stream = {
             let state = self.state.read().unwrap();
             match &*state {
                 State::Connected(connected_stream) => connected_stream.clone(),
+                State::Connect(connecting_stream) => {
+                    if connecting_stream.common().is_nonblocking()
+                        || flags.contains(SendFlags::MSG_DONTWAIT)
+                    {
+                        return_errno!(EAGAIN, "the socket is connecting");
+                    }
+                    return_errno!(ENOTCONN, "the socket is not connected");
+                }
                 _ => {
                     return_errno!(ENOTCONN, "the socket is not connected");
                 }
             }
         };
 
-        connected_stream.writev(bufs).await
+        connected_stream.sendmsg(bufs, flags).await
     }
 
     pub fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
+        self.try_complete_connect();
+
         let state = self.state.read().unwrap();
         let pollee = state.common().pollee();
         pollee.poll(mask, poller)
     }
 
-    /*
-        pub async fn shutdown(&self, shutdown: Shutdown) -> Result<()> {
-            let connected_stream = {
-                let state = self.state.read();
-                match *state {
-                    Connected(connected_stream) => connected_stream.clone(),
-                    _ => {
-                        return_errno!(ENOTCONN, "the socket is not connected");
-                    }
-                }
-            };
-
-            connected_stream.shutdown(shutdown)
+    pub fn status_flags(&self) -> StatusFlags {
+        let state = self.state.read().unwrap();
+        if state.common().is_nonblocking() {
+            StatusFlags::O_NONBLOCK
+        } else {
+            StatusFlags::empty()
         }
+    }
 
-        pub fn ioctl(&self, ioctl: &mut dyn IoctlCmd) -> Result<()> {
-            return_errno!(EINVAL, "")
-        }
+    pub fn set_status_flags(&self, new_flags: StatusFlags) -> Result<()> {
+        // Other status flags are meaningless to sockets and thus ignored
+        let is_nonblocking = new_flags.contains(StatusFlags::O_NONBLOCK);
+        let state = self.state.read().unwrap();
+        state.common().set_nonblocking(is_nonblocking);
+        Ok(())
+    }
 
-        pub fn poll_by(&self, mask: Events, mut poller: Option<&mut Poller>) -> Events {
-            let state = self.state.read();
-            match *state {
-                Init(init_stream) => init_stream.poll_by(mask, poller),
-                Connect(connect_stream) => connect_stream.poll_by(mask, poller),
-                Connected(connected_stream) = connected_stream.poll_by(mask, poller),
-                Listen(listener_stream) = listener_stream.poll_by(mask, poller),
-            }
-        }
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        self.try_complete_connect();
 
-        pub fn addr(&self) -> Option<A> {
+        let connected_stream = {
             let state = self.state.read().unwrap();
             match &*state {
-                Init(init_stream) => init_stream.addr(),
-                Connect(connecting_stream) => connecting_stream.addr(),
-                Connected(connected_stream) = connected_stream.addr(),
-                Listen(listener_stream) = listener_stream.addr(),
+                State::Connected(connected_stream) => connected_stream.clone(),
+                _ => {
+                    return_errno!(ENOTCONN, "the socket is not connected");
+                }
+            }
+        };
+
+        connected_stream.shutdown(how)
+    }
+
+    /// Returns the address that the socket is bound to.
+    pub fn addr(&self) -> Result<A> {
+        let state = self.state.read().unwrap();
+        state.common().host_addr()
+    }
+
+    /// Returns the address of the peer that the socket is connected to.
+    pub fn peer_addr(&self) -> Result<A> {
+        self.try_complete_connect();
+
+        let state = self.state.read().unwrap();
+        match &*state {
+            State::Connected(connected_stream) => connected_stream
+                .common()
+                .peer_addr()
+                .ok_or_else(|| errno!(ENOTCONN, "the peer address is unknown")),
+            _ => {
+                return_errno!(ENOTCONN, "the socket is not connected");
             }
         }
+    }
 
-        pub fn peer_addr(&self) -> Option<A> {
-            let state = self.state.read();
-            match *state {
-                Connected(connected_stream) = connected_stream.peer_addr(),
-                _ => {
-                    return_errno!(EINVAL, "")
+    /// Returns the statistics of the data sent through the host socket.
+    ///
+    /// The statistics are all zeros if the socket is not connected.
+    pub fn send_stats(&self) -> SendStats {
+        let state = self.state.read().unwrap();
+        match &*state {
+            State::Connected(connected_stream) => connected_stream.send_stats(),
+            _ => SendStats::default(),
+        }
+    }
+
+    /// Handle ioctl-style commands, e.g., getting or setting socket options.
+    pub fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        self.try_complete_connect();
+
+        let state = self.state.read().unwrap();
+        match_ioctl_cmd_mut!(&mut *cmd, {
+            cmd: SetSendBufSize => {
+                let buf_size = adjust_buf_size(*cmd.input());
+                state.common().set_send_buf_size(buf_size);
+                if let State::Connected(connected_stream) = &*state {
+                    connected_stream.set_send_buf_size(buf_size);
                 }
+                Ok(())
+            },
+            cmd: GetSendBufSize => {
+                cmd.set_output(state.common().send_buf_size());
+                Ok(())
+            },
+            cmd: SetRecvBufSize => {
+                let buf_size = adjust_buf_size(*cmd.input());
+                state.common().set_recv_buf_size(buf_size);
+                if let State::Connected(connected_stream) = &*state {
+                    connected_stream.set_recv_buf_size(buf_size);
+                }
+                Ok(())
+            },
+            cmd: GetRecvBufSize => {
+                cmd.set_output(state.common().recv_buf_size());
+                Ok(())
+            },
+            cmd: SetTcpCork => {
+                let is_corked = *cmd.input();
+                state.common().set_tcp_corked(is_corked)?;
+                if let State::Connected(connected_stream) = &*state {
+                    connected_stream.set_corked(is_corked);
+                }
+                Ok(())
+            },
+            cmd: SetTcpNoDelay => {
+                let is_nodelay = *cmd.input();
+                state.common().ioctl(cmd)?;
+                // Just like Linux, enabling TCP_NODELAY pushes out the pending data
+                if let State::Connected(connected_stream) = &*state {
+                    if is_nodelay {
+                        connected_stream.push();
+                    }
+                }
+                Ok(())
+            },
+            _ => {
+                state.common().ioctl(cmd)
             }
-        }
-    */
+        })
+    }
 }
 
 impl<A: Addr + 'static, R: Runtime> std::fmt::Debug for State<A, R> {
@@ -1,14 +1,30 @@
 use std::marker::PhantomData;
+use std::mem::{size_of, MaybeUninit};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
+use async_io::ioctl::IoctlCmd;
+use async_io::match_ioctl_cmd_mut;
+use async_io::socket::sockopt::*;
+use async_io::socket::Shutdown;
 use io_uring_callback::IoUring;
 cfg_if::cfg_if! {
     if #[cfg(feature = "sgx")] {
+        use libc::ocall::getsockname as do_getsockname;
+        use libc::ocall::getsockopt as do_getsockopt;
+        use libc::ocall::setsockopt as do_setsockopt;
+        use libc::ocall::shutdown as do_shutdown;
         use libc::ocall::socket as do_socket;
     } else {
+        use libc::getsockname as do_getsockname;
+        use libc::getsockopt as do_getsockopt;
+        use libc::setsockopt as do_setsockopt;
+        use libc::shutdown as do_shutdown;
         use libc::socket as do_socket;
     }
 }
 
+use crate::config;
 use crate::prelude::*;
 use crate::runtime::Runtime;
 
@@ -16,6 +32,7 @@ use crate::runtime::Runtime;
 pub struct Common<A: Addr + 'static, R: Runtime> {
     host_fd: HostFd,
     pollee: Pollee,
+    is_nonblocking: AtomicBool,
     inner: Mutex<Inner<A>>,
     phantom_data: PhantomData<(A, R)>,
 }
@@ -28,11 +45,15 @@ impl<A: Addr + 'static, R: Runtime> Common<A, R> {
             assert!(retval >= 0);
             retval as HostFd
         };
+        if A::domain() == Domain::Ipv6 {
+            Self::enable_dual_stack(host_fd);
+        }
         let pollee = Pollee::new(Events::empty());
         let inner = Mutex::new(Inner::new());
         Self {
             host_fd,
             pollee,
+            is_nonblocking: AtomicBool::new(false),
             inner,
             phantom_data: PhantomData,
         }
@@ -44,12 +65,27 @@ impl<A: Addr + 'static, R: Runtime> Common<A, R> {
         Self {
             host_fd,
             pollee,
+            is_nonblocking: AtomicBool::new(false),
             inner,
             phantom_data: PhantomData,
         }
     }
 
-    pub fn io_uring(&self) -> &IoUring {
+    /// Make an IPv6 socket accept IPv4 traffic, too.
+    ///
+    /// Whether an IPv6 socket is dual-stack by default depends on the host's
+    /// `net.ipv6.bindv6only` sysctl. We do not want the behavior of LibOS sockets
+    /// to vary with the host configuration, so `IPV6_V6ONLY` is cleared explicitly.
+    fn enable_dual_stack(host_fd: HostFd) {
+        let v6only: libc::c_int = 0;
+        if let Err(e) =
+            Self::do_set_host_opt(host_fd, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, &v6only)
+        {
+            log::warn!("failed to make the IPv6 socket dual-stack: {}", e);
+        }
+    }
+
+    pub fn io_uring(&self) -> &'static IoUring {
         R::io_uring()
     }
 
@@ -61,6 +97,14 @@ impl<A: Addr + 'static, R: Runtime> Common<A, R> {
         &self.pollee
     }
 
+    pub fn is_nonblocking(&self) -> bool {
+        self.is_nonblocking.load(Ordering::Relaxed)
+    }
+
+    pub fn set_nonblocking(&self, is_nonblocking: bool) {
+        self.is_nonblocking.store(is_nonblocking, Ordering::Relaxed);
+    }
+
     pub fn addr(&self) -> Option<A> {
         let inner = self.inner.lock().unwrap();
         inner.addr.clone()
@@ -80,6 +124,285 @@ impl<A: Addr + 'static, R: Runtime> Common<A, R> {
         let mut inner = self.inner.lock().unwrap();
         inner.peer_addr = Some(peer_addr.clone())
     }
+
+    /// Returns the address that the host socket is bound to.
+    ///
+    /// The address is queried from the host since the host may have assigned
+    /// an address to the socket implicitly, e.g., binding to port 0 or
+    /// connecting without binding first.
+    pub fn host_addr(&self) -> Result<A> {
+        let mut c_addr_storage: libc::sockaddr_storage =
+            unsafe { MaybeUninit::zeroed().assume_init() };
+        let mut c_addr_len = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        let retval = unsafe {
+            do_getsockname(
+                self.host_fd as _,
+                &mut c_addr_storage as *mut _ as *mut libc::sockaddr,
+                &mut c_addr_len,
+            )
+        };
+        if retval < 0 {
+            let errno = Errno::from(-retval as u32);
+            return_errno!(errno, "getsockname failed");
+        }
+        // Guard against Iago attack
+        let c_addr_len = (c_addr_len as usize).min(size_of::<libc::sockaddr_storage>());
+        A::from_c_storage(&c_addr_storage, c_addr_len)
+    }
+
+    /// Shutdown the read side, the write side, or both sides of the host socket.
+    pub fn host_shutdown(&self, how: Shutdown) -> Result<()> {
+        let c_how = match how {
+            Shutdown::Read => libc::SHUT_RD,
+            Shutdown::Write => libc::SHUT_WR,
+            Shutdown::Both => libc::SHUT_RDWR,
+        };
+        let retval = unsafe { do_shutdown(self.host_fd as _, c_how) };
+        if retval < 0 {
+            let errno = Errno::from(-retval as u32);
+            return_errno!(errno, "shutdown failed");
+        }
+        Ok(())
+    }
+
+    /// Record an error, which can be retrieved later with `SO_ERROR`.
+    pub fn set_errno(&self, errno: Errno) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.errno = Some(errno);
+    }
+
+    /// Retrieve and clear the pending error.
+    pub fn take_errno(&self) -> Option<Errno> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.errno.take()
+    }
+
+    pub fn send_buf_size(&self) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner.send_buf_size
+    }
+
+    pub fn set_send_buf_size(&self, buf_size: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.send_buf_size = buf_size;
+    }
+
+    pub fn recv_buf_size(&self) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner.recv_buf_size
+    }
+
+    pub fn set_recv_buf_size(&self, buf_size: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.recv_buf_size = buf_size;
+    }
+
+    /// Returns the timeout of receiving, i.e., `SO_RCVTIMEO`, which also applies to
+    /// accepting.
+    pub fn recv_timeout(&self) -> Option<Duration> {
+        let inner = self.inner.lock().unwrap();
+        inner.recv_timeout
+    }
+
+    /// Returns the timeout of sending, i.e., `SO_SNDTIMEO`, which also applies to
+    /// connecting.
+    pub fn send_timeout(&self) -> Option<Duration> {
+        let inner = self.inner.lock().unwrap();
+        inner.send_timeout
+    }
+
+    pub fn is_tcp_corked(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.is_tcp_corked
+    }
+
+    /// Set or clear `TCP_CORK`.
+    ///
+    /// The option is not passed to the host socket. Instead, it is the sender of
+    /// a connected socket that holds back the corked data.
+    pub fn set_tcp_corked(&self, is_corked: bool) -> Result<()> {
+        self.check_tcp()?;
+        let mut inner = self.inner.lock().unwrap();
+        inner.is_tcp_corked = is_corked;
+        Ok(())
+    }
+
+    /// Handle the socket options that are common to stream sockets in all states.
+    ///
+    /// Most of these options are simply applied to or retrieved from the host socket.
+    pub fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        match_ioctl_cmd_mut!(&mut *cmd, {
+            cmd: SetReuseAddr => {
+                self.set_host_opt(libc::SOL_SOCKET, libc::SO_REUSEADDR, *cmd.input() as libc::c_int)
+            },
+            cmd: GetReuseAddr => {
+                let val: libc::c_int = self.get_host_opt(libc::SOL_SOCKET, libc::SO_REUSEADDR)?;
+                cmd.set_output(val != 0);
+                Ok(())
+            },
+            cmd: SetReusePort => {
+                self.set_host_opt(libc::SOL_SOCKET, libc::SO_REUSEPORT, *cmd.input() as libc::c_int)
+            },
+            cmd: GetReusePort => {
+                let val: libc::c_int = self.get_host_opt(libc::SOL_SOCKET, libc::SO_REUSEPORT)?;
+                cmd.set_output(val != 0);
+                Ok(())
+            },
+            cmd: SetKeepAlive => {
+                self.set_host_opt(libc::SOL_SOCKET, libc::SO_KEEPALIVE, *cmd.input() as libc::c_int)
+            },
+            cmd: GetKeepAlive => {
+                let val: libc::c_int = self.get_host_opt(libc::SOL_SOCKET, libc::SO_KEEPALIVE)?;
+                cmd.set_output(val != 0);
+                Ok(())
+            },
+            cmd: SetLinger => {
+                self.set_host_opt(libc::SOL_SOCKET, libc::SO_LINGER, cmd.input().to_c())
+            },
+            cmd: GetLinger => {
+                let c_linger: libc::linger = self.get_host_opt(libc::SOL_SOCKET, libc::SO_LINGER)?;
+                cmd.set_output(Linger::from_c(&c_linger)?);
+                Ok(())
+            },
+            cmd: SetRecvTimeout => {
+                let mut inner = self.inner.lock().unwrap();
+                inner.recv_timeout = *cmd.input();
+                Ok(())
+            },
+            cmd: GetRecvTimeout => {
+                cmd.set_output(self.recv_timeout());
+                Ok(())
+            },
+            cmd: SetSendTimeout => {
+                let mut inner = self.inner.lock().unwrap();
+                inner.send_timeout = *cmd.input();
+                Ok(())
+            },
+            cmd: GetSendTimeout => {
+                cmd.set_output(self.send_timeout());
+                Ok(())
+            },
+            cmd: GetSockError => {
+                cmd.set_output(self.take_errno());
+                Ok(())
+            },
+            cmd: SetIpv6Only => {
+                if A::domain() != Domain::Ipv6 {
+                    return_errno!(ENOPROTOOPT, "not an IPv6 socket");
+                }
+                self.set_host_opt(libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, *cmd.input() as libc::c_int)
+            },
+            cmd: GetIpv6Only => {
+                if A::domain() != Domain::Ipv6 {
+                    return_errno!(ENOPROTOOPT, "not an IPv6 socket");
+                }
+                let val: libc::c_int = self.get_host_opt(libc::IPPROTO_IPV6, libc::IPV6_V6ONLY)?;
+                cmd.set_output(val != 0);
+                Ok(())
+            },
+            cmd: SetTcpNoDelay => {
+                self.check_tcp()?;
+                self.set_host_opt(libc::IPPROTO_TCP, libc::TCP_NODELAY, *cmd.input() as libc::c_int)
+            },
+            cmd: GetTcpNoDelay => {
+                self.check_tcp()?;
+                let val: libc::c_int = self.get_host_opt(libc::IPPROTO_TCP, libc::TCP_NODELAY)?;
+                cmd.set_output(val != 0);
+                Ok(())
+            },
+            cmd: GetTcpCork => {
+                self.check_tcp()?;
+                cmd.set_output(self.is_tcp_corked());
+                Ok(())
+            },
+            cmd: SetTcpKeepIdle => {
+                self.check_tcp()?;
+                self.set_host_opt(libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, *cmd.input() as libc::c_int)
+            },
+            cmd: GetTcpKeepIdle => {
+                self.check_tcp()?;
+                let val: libc::c_int = self.get_host_opt(libc::IPPROTO_TCP, libc::TCP_KEEPIDLE)?;
+                cmd.set_output(val as u32);
+                Ok(())
+            },
+            cmd: SetTcpKeepIntvl => {
+                self.check_tcp()?;
+                self.set_host_opt(libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, *cmd.input() as libc::c_int)
+            },
+            cmd: GetTcpKeepIntvl => {
+                self.check_tcp()?;
+                let val: libc::c_int = self.get_host_opt(libc::IPPROTO_TCP, libc::TCP_KEEPINTVL)?;
+                cmd.set_output(val as u32);
+                Ok(())
+            },
+            cmd: SetTcpKeepCnt => {
+                self.check_tcp()?;
+                self.set_host_opt(libc::IPPROTO_TCP, libc::TCP_KEEPCNT, *cmd.input() as libc::c_int)
+            },
+            cmd: GetTcpKeepCnt => {
+                self.check_tcp()?;
+                let val: libc::c_int = self.get_host_opt(libc::IPPROTO_TCP, libc::TCP_KEEPCNT)?;
+                cmd.set_output(val as u32);
+                Ok(())
+            },
+            _ => {
+                return_errno!(ENOPROTOOPT, "the socket option is not supported");
+            }
+        })
+    }
+
+    fn check_tcp(&self) -> Result<()> {
+        match A::domain() {
+            Domain::Ipv4 | Domain::Ipv6 => Ok(()),
+            _ => return_errno!(EOPNOTSUPP, "not a TCP socket"),
+        }
+    }
+
+    fn set_host_opt<T>(&self, level: libc::c_int, name: libc::c_int, val: T) -> Result<()> {
+        Self::do_set_host_opt(self.host_fd, level, name, &val)
+    }
+
+    fn do_set_host_opt<T>(
+        host_fd: HostFd,
+        level: libc::c_int,
+        name: libc::c_int,
+        val: &T,
+    ) -> Result<()> {
+        let retval = unsafe {
+            do_setsockopt(
+                host_fd as _,
+                level,
+                name,
+                val as *const T as *const libc::c_void,
+                size_of::<T>() as _,
+            )
+        };
+        if retval < 0 {
+            let errno = Errno::from(-retval as u32);
+            return_errno!(errno, "setsockopt failed");
+        }
+        Ok(())
+    }
+
+    fn get_host_opt<T: Copy>(&self, level: libc::c_int, name: libc::c_int) -> Result<T> {
+        // Safety. All option values are C-style types, for which zeros are valid.
+        let mut val: T = unsafe { MaybeUninit::zeroed().assume_init() };
+        let mut val_len = size_of::<T>() as libc::socklen_t;
+        let retval = unsafe {
+            do_getsockopt(
+                self.host_fd as _,
+                level,
+                name,
+                &mut val as *mut T as *mut libc::c_void,
+                &mut val_len,
+            )
+        };
+        if retval < 0 {
+            let errno = Errno::from(-retval as u32);
+            return_errno!(errno, "getsockopt failed");
+        }
+        Ok(val)
+    }
 }
 
 impl<A: Addr + 'static, R: Runtime> std::fmt::Debug for Common<A, R> {
@@ -87,6 +410,7 @@ impl<A: Addr + 'static, R: Runtime> std::fmt::Debug for Common<A, R> {
         f.debug_struct("Common")
             .field("host_fd", &self.host_fd)
             .field("pollee", &self.pollee)
+            .field("is_nonblocking", &self.is_nonblocking())
             .field("inner", &self.inner.lock().unwrap())
             .finish()
     }
@@ -94,7 +418,20 @@ impl<A: Addr + 'static, R: Runtime> std::fmt::Debug for Common<A, R> {
 
 impl<A: Addr + 'static, R: Runtime> Drop for Common<A, R> {
     fn drop(&mut self) {
-        // TODO: close host_fd
+        // The host socket is shared by all the states of a stream, and the
+        // pending I/O on it keeps the stream alive. So it can be closed now.
+        close_host_fd(self.host_fd);
+    }
+}
+
+pub(super) fn close_host_fd(host_fd: HostFd) {
+    let host_fd = host_fd as i32;
+    #[cfg(not(feature = "sgx"))]
+    let retval = unsafe { libc::close(host_fd) };
+    #[cfg(feature = "sgx")]
+    let retval = unsafe { libc::ocall::close(host_fd) };
+    if retval < 0 {
+        log::warn!("failed to close the host fd {}", host_fd);
     }
 }
 
@@ -102,6 +439,12 @@ impl<A: Addr + 'static, R: Runtime> Drop for Common<A, R> {
 struct Inner<A: Addr + 'static> {
     addr: Option<A>,
     peer_addr: Option<A>,
+    errno: Option<Errno>,
+    send_buf_size: usize,
+    recv_buf_size: usize,
+    is_tcp_corked: bool,
+    recv_timeout: Option<Duration>,
+    send_timeout: Option<Duration>,
 }
 
 impl<A: Addr + 'static> Inner<A> {
@@ -109,6 +452,12 @@ impl<A: Addr + 'static> Inner<A> {
         Self {
             addr: None,
             peer_addr: None,
+            errno: None,
+            send_buf_size: config::default_max_buf_size(),
+            recv_buf_size: config::default_max_buf_size(),
+            is_tcp_corked: false,
+            recv_timeout: None,
+            send_timeout: None,
         }
     }
 }
@@ -3,13 +3,15 @@ use std::marker::PhantomData;
 use io_uring_callback::{Fd, IoHandle};
 use sgx_untrusted_alloc::{MaybeUntrusted, UntrustedBox};
 
-use super::Common;
+use super::{Common, InitStream};
 use crate::prelude::*;
 use crate::runtime::Runtime;
+use crate::util::Timer;
 
 /// A stream socket that is in its connecting state.
 pub struct ConnectingStream<A: Addr + 'static, R: Runtime> {
     common: Arc<Common<A, R>>,
+    init_stream: Arc<InitStream<A, R>>,
     peer_addr: A,
     req: Mutex<ConnectReq<A>>,
 }
@@ -18,15 +20,18 @@ struct ConnectReq<A: Addr> {
     io_handle: Option<IoHandle>,
     c_addr: UntrustedBox<libc::sockaddr_storage>,
     c_addr_len: usize,
+    is_done: bool,
     errno: Option<Errno>,
     phantom_data: PhantomData<A>,
 }
 
 impl<A: Addr + 'static, R: Runtime> ConnectingStream<A, R> {
-    pub fn new(peer_addr: &A, common: Arc<Common<A, R>>) -> Result<Arc<Self>> {
+    pub fn new(peer_addr: &A, init_stream: Arc<InitStream<A, R>>) -> Result<Arc<Self>> {
+        let common = init_stream.common().clone();
         let req = Mutex::new(ConnectReq::new(peer_addr));
         let new_self = Self {
             common,
+            init_stream,
             peer_addr: peer_addr.clone(),
             req,
         };
@@ -34,28 +39,58 @@ impl<A: Addr + 'static, R: Runtime> ConnectingStream<A, R> {
     }
 
     /// Connect to the peer address.
+    ///
+    /// Just like Linux, the connect is bounded by `SO_SNDTIMEO` and fails with
+    /// `EINPROGRESS` if it times out, while the async connect is left to complete
+    /// in the background, just as the one started by a non-blocking socket.
     pub async fn connect(self: &Arc<Self>) -> Result<()> {
-        let pollee = self.common.pollee();
-        pollee.reset_events();
-
-        self.initiate_async_connect();
+        self.start_connect();
 
         // Wait for the async connect to complete
+        let pollee = self.common.pollee();
         let mut poller = Poller::new();
+        let mut timer = None;
         loop {
             let events = pollee.poll(Events::OUT, Some(&mut poller));
             if !events.is_empty() {
                 break;
             }
+            if let Some(timeout) = self.common.send_timeout() {
+                let timer =
+                    timer.get_or_insert_with(|| Timer::start(self.common.io_uring(), timeout));
+                if timer.poll(Some(&mut poller)) {
+                    return_errno!(EINPROGRESS, "the connect timed out");
+                }
+            }
             poller.wait().await;
         }
 
         // Finish the async connect
+        self.connect_result().unwrap()
+    }
+
+    /// Start connecting to the peer address, without waiting for the completion.
+    ///
+    /// Once the async connect completes, the socket becomes writable on success
+    /// or gets an error event on failure. The result can then be retrieved with
+    /// `connect_result`.
+    pub fn start_connect(self: &Arc<Self>) {
+        self.common.pollee().reset_events();
+
+        self.initiate_async_connect();
+    }
+
+    /// Returns the result of the async connect if it has completed.
+    pub fn connect_result(&self) -> Option<Result<()>> {
         let req = self.req.lock().unwrap();
-        if let Some(e) = req.errno {
-            return_errno!(e, "connect failed");
+        if !req.is_done {
+            return None;
         }
-        Ok(())
+        let res = match req.errno {
+            Some(errno) => Err(errno!(errno, "connect failed")),
+            None => Ok(()),
+        };
+        Some(res)
     }
 
     fn initiate_async_connect(self: &Arc<Self>) {
@@ -64,13 +99,15 @@ impl<A: Addr + 'static, R: Runtime> ConnectingStream<A, R> {
             // Guard against Igao attack
             assert!(retval <= 0);
 
+            let mut req = arc_self.req.lock().unwrap();
+            req.is_done = true;
             if retval == 0 {
+                drop(req);
+
                 arc_self.common.pollee().add_events(Events::OUT);
             } else {
                 // Store the errno
-                let mut req = arc_self.req.lock().unwrap();
-                let errno = Errno::from(-retval as u32);
-                req.errno = Some(errno);
+                req.errno = Some(Errno::from(-retval as u32));
                 drop(req);
 
                 arc_self.common.pollee().add_events(Events::ERR);
@@ -100,6 +137,11 @@ impl<A: Addr + 'static, R: Runtime> ConnectingStream<A, R> {
     pub fn common(&self) -> &Arc<Common<A, R>> {
         &self.common
     }
+
+    /// Returns the init stream, to which the socket is restored if the connect fails.
+    pub fn init_stream(&self) -> &Arc<InitStream<A, R>> {
+        &self.init_stream
+    }
 }
 
 impl<A: Addr> ConnectReq<A> {
@@ -109,6 +151,7 @@ impl<A: Addr> ConnectReq<A> {
             io_handle: None,
             c_addr: UntrustedBox::new(c_addr_storage),
             c_addr_len,
+            is_done: false,
             errno: None,
             phantom_data: PhantomData,
         }
@@ -129,6 +172,7 @@ impl<A: Addr> std::fmt::Debug for ConnectReq<A> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ConnectReq")
             .field("io_handle", &self.io_handle)
+            .field("is_done", &self.is_done)
             .field("errno", &self.errno)
             .finish()
     }
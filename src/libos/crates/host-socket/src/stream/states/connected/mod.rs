@@ -1,4 +1,7 @@
+use async_io::socket::Shutdown;
+
 use self::recv::Receiver;
+pub use self::send::SendStats;
 use self::send::Sender;
 use super::Common;
 use crate::prelude::*;
@@ -7,8 +10,10 @@ use crate::runtime::Runtime;
 mod recv;
 mod send;
 
-pub const SEND_BUF_SIZE: usize = 32 * 1024;
-pub const RECV_BUF_SIZE: usize = 32 * 1024;
+/// The lower bound of the buffer sizes that can be set via `SO_SNDBUF` or `SO_RCVBUF`.
+pub const MIN_BUF_SIZE: usize = 4 * 1024;
+/// The upper bound of the buffer sizes that can be set via `SO_SNDBUF` or `SO_RCVBUF`.
+pub const MAX_BUF_SIZE: usize = 4 * 1024 * 1024;
 
 pub struct ConnectedStream<A: Addr + 'static, R: Runtime> {
     common: Arc<Common<A, R>>,
@@ -18,8 +23,8 @@ pub struct ConnectedStream<A: Addr + 'static, R: Runtime> {
 
 impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
     pub fn new(common: Arc<Common<A, R>>) -> Arc<Self> {
-        let sender = Sender::new();
-        let receiver = Receiver::new();
+        let sender = Sender::new(common.send_buf_size(), common.is_tcp_corked());
+        let receiver = Receiver::new(common.recv_buf_size());
         let new_self = Self {
             common,
             sender,
@@ -32,7 +37,18 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
         &self.common
     }
 
-    // TODO: implement other methods
+    pub fn shutdown(self: &Arc<Self>, how: Shutdown) -> Result<()> {
+        if how.should_shut_read() {
+            self.shutdown_read()?;
+        }
+        if how.should_shut_write() {
+            self.shutdown_write()?;
+        }
+        if how == Shutdown::Both {
+            self.common.pollee().add_events(Events::HUP);
+        }
+        Ok(())
+    }
 
     // Other methods are implemented in the send and receive modules
 }
@@ -47,6 +63,33 @@ impl<A: Addr + 'static, R: Runtime> std::fmt::Debug for ConnectedStream<A, R> {
     }
 }
 
+/// Adjust a buffer size specified by the user to the one that is actually used.
+///
+/// Just like Linux, the size is doubled to make room for bookkeeping overhead
+/// and then clamped to the allowed range. And the resulting value is what
+/// `SO_SNDBUF` or `SO_RCVBUF` reports back to the user.
+pub fn adjust_buf_size(user_size: usize) -> usize {
+    user_size
+        .saturating_mul(2)
+        .max(MIN_BUF_SIZE)
+        .min(MAX_BUF_SIZE)
+}
+
+/// Returns the remaining parts of the bufs after skipping the first `nbytes` bytes.
+fn skip_bufs<'a>(bufs: &'a mut [&mut [u8]], mut nbytes: usize) -> Vec<&'a mut [u8]> {
+    bufs.iter_mut()
+        .filter_map(|buf| {
+            if nbytes >= buf.len() {
+                nbytes -= buf.len();
+                return None;
+            }
+            let remain_buf = &mut buf[nbytes..];
+            nbytes = 0;
+            Some(remain_buf)
+        })
+        .collect()
+}
+
 fn new_msghdr(iovecs_ptr: *mut libc::iovec, iovecs_len: usize) -> libc::msghdr {
     use std::mem::MaybeUninit;
     // Safety. Setting all fields to zeros is a valid state for msghdr.
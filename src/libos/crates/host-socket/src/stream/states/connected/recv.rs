@@ -2,29 +2,68 @@ use std::marker::PhantomData;
 use std::mem::MaybeUninit;
 use std::ptr::{self};
 
+use async_io::socket::{RecvFlags, Shutdown};
 use io_uring_callback::{Fd, IoHandle};
 use memoffset::offset_of;
 use sgx_untrusted_alloc::{MaybeUntrusted, UntrustedBox};
 
-use super::ConnectedStream;
+use super::{skip_bufs, ConnectedStream};
 use crate::prelude::*;
 use crate::runtime::Runtime;
-use crate::util::UntrustedCircularBuf;
+use crate::util::{BufSizer, Timer, UntrustedCircularBuf};
 
 impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
-    pub async fn readv(self: &Arc<Self>, bufs: &mut [&mut [u8]]) -> Result<usize> {
+    pub async fn recvmsg(
+        self: &Arc<Self>,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+    ) -> Result<usize> {
         let total_len: usize = bufs.iter().map(|buf| buf.len()).sum();
         if total_len == 0 {
             return Ok(0);
         }
+        if flags.intersects(RecvFlags::MSG_OOB | RecvFlags::MSG_ERRQUEUE) {
+            return_errno!(
+                EOPNOTSUPP,
+                "out-of-band data or error queues are not supported"
+            );
+        }
+
+        let is_nonblocking =
+            flags.contains(RecvFlags::MSG_DONTWAIT) || self.common.is_nonblocking();
+        // Peeking always returns whatever is available, just like reads without
+        // MSG_WAITALL.
+        let wait_all =
+            flags.contains(RecvFlags::MSG_WAITALL) && !flags.contains(RecvFlags::MSG_PEEK);
 
-        // Initialize the poller only when needed
+        // Initialize the poller and the timer only when needed
         let mut poller = None;
+        let mut timer = None;
+        let mut total_recv = 0;
         loop {
-            // Attempt to reade
-            let res = self.try_readv(bufs);
-            if !res.has_errno(EAGAIN) {
-                return res;
+            // Attempt to read
+            let res = {
+                let mut remain_bufs = skip_bufs(bufs, total_recv);
+                self.try_recvmsg(&mut remain_bufs, flags)
+            };
+            match res {
+                // End of file
+                Ok(0) => return Ok(total_recv),
+                Ok(nbytes) => {
+                    total_recv += nbytes;
+                    if !wait_all || total_recv == total_len {
+                        return Ok(total_recv);
+                    }
+                }
+                Err(e) if e.errno() == EAGAIN && !is_nonblocking => (),
+                Err(e) => {
+                    // Just like Linux, the bytes already received are returned
+                    // in spite of the error.
+                    if total_recv > 0 {
+                        return Ok(total_recv);
+                    }
+                    return Err(e);
+                }
             }
 
             // Wait for interesting events by polling
@@ -34,16 +73,44 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
             let mask = Events::IN;
             let events = self.common.pollee().poll(mask, poller.as_mut());
             if events.is_empty() {
+                if let Some(timeout) = self.common.recv_timeout() {
+                    let timer =
+                        timer.get_or_insert_with(|| Timer::start(self.common.io_uring(), timeout));
+                    if timer.poll(poller.as_mut()) {
+                        // The async recv is not left pending on the host
+                        self.cancel_recv();
+                        // Just like Linux, the bytes already received are returned
+                        if total_recv > 0 {
+                            return Ok(total_recv);
+                        }
+                        return_errno!(EAGAIN, "the receive timed out");
+                    }
+                }
                 poller.as_ref().unwrap().wait().await;
             }
         }
     }
 
-    fn try_readv(self: &Arc<Self>, bufs: &mut [&mut [u8]]) -> Result<usize> {
+    fn try_recvmsg(self: &Arc<Self>, bufs: &mut [&mut [u8]], flags: RecvFlags) -> Result<usize> {
         let mut inner = self.receiver.inner.lock().unwrap();
 
         // Copy data from the recv buffer to the bufs
-        let nbytes = {
+        let nbytes = if flags.contains(RecvFlags::MSG_PEEK) {
+            let mut total_peeked = 0;
+            for buf in bufs {
+                let this_peeked = inner.recv_buf.peek_at(total_peeked, buf);
+                if this_peeked == 0 {
+                    break;
+                }
+                total_peeked += this_peeked;
+            }
+            total_peeked
+        } else if flags.contains(RecvFlags::MSG_TRUNC) {
+            // Just like Linux, MSG_TRUNC on a stream socket means discarding
+            // the received data instead of copying them to the bufs.
+            let total_len: usize = bufs.iter().map(|buf| buf.len()).sum();
+            inner.recv_buf.consume_without_copy(total_len)
+        } else {
             let mut total_consumed = 0;
             for buf in bufs {
                 let this_consumed = inner.recv_buf.consume(buf);
@@ -54,7 +121,6 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
             }
             total_consumed
         };
-
         if inner.end_of_file {
             return Ok(nbytes);
         }
@@ -72,9 +138,10 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
         // Only when there are no data available in the recv buffer, shall we check
         // the following error conditions.
         //
-        // Case 1: If the read side of the connection has been shutdown...
+        // Case 1: If the read side of the connection has been shutdown, then,
+        // just like Linux, reads return end-of-file.
         if inner.is_shutdown {
-            return_errno!(EPIPE, "read side is shutdown");
+            return Ok(0);
         }
         // Case 2: If the connenction has been broken...
         if let Some(errno) = inner.fatal {
@@ -86,11 +153,14 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
     }
 
     fn do_recv(self: &Arc<Self>, inner: &mut MutexGuard<Inner>) {
-        if inner.recv_buf.is_full()
-            || inner.is_shutdown
-            || inner.io_handle.is_some()
-            || inner.end_of_file
-        {
+        if inner.is_shutdown || inner.io_handle.is_some() || inner.end_of_file {
+            return;
+        }
+
+        // The recv buffer can only grow or shrink when there are no async recv
+        // referring to the buffer.
+        inner.adjust_recv_buf();
+        if inner.recv_buf.is_full() {
             return;
         }
 
@@ -107,20 +177,31 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
                 // TODO: guard against Iago attack through errno
                 // TODO: should we ignore EINTR and try again?
                 let errno = Errno::from(-retval as u32);
+                // The async recv was cancelled as a receive timed out. Wake up
+                // the other readers, if any, to start another async recv.
+                if errno == ECANCELED {
+                    stream.common.pollee().add_events(Events::IN);
+                    return;
+                }
                 inner.fatal = Some(errno);
+                stream.common.set_errno(errno);
                 stream.common.pollee().add_events(Events::ERR);
                 return;
             }
             // Handle end of file
             else if retval == 0 {
                 inner.end_of_file = true;
-                stream.common.pollee().add_events(Events::IN);
+                stream
+                    .common
+                    .pollee()
+                    .add_events(Events::IN | Events::RDHUP);
                 return;
             }
 
             // Handle the normal case of a successful read
             let nbytes = retval as usize;
             inner.recv_buf.produce_without_copy(nbytes);
+            inner.record_recv_buf_usage();
 
             // Now that we have produced non-zero bytes, the buf must become
             // ready to read.
@@ -138,6 +219,44 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
         let handle = unsafe { io_uring.recvmsg(host_fd, msghdr_ptr, 0, complete_fn) };
         inner.io_handle.replace(handle);
     }
+
+    /// Cancel the pending async recv, if any.
+    ///
+    /// The data in the recv buffer are kept. A new async recv is started by the
+    /// next attempt to read.
+    fn cancel_recv(&self) {
+        let inner = self.receiver.inner.lock().unwrap();
+        if let Some(io_handle) = &inner.io_handle {
+            unsafe { self.common.io_uring().cancel(io_handle) };
+        }
+    }
+
+    /// Set the max size of the recv buffer.
+    ///
+    /// If the buffer is larger than the new size, it shrinks once the host is done
+    /// with it and the data in it fit.
+    pub fn set_recv_buf_size(self: &Arc<Self>, buf_size: usize) {
+        let mut inner = self.receiver.inner.lock().unwrap();
+        inner.buf_sizer.set_max_size(buf_size);
+        self.do_recv(&mut inner);
+    }
+
+    pub(super) fn shutdown_read(self: &Arc<Self>) -> Result<()> {
+        let mut inner = self.receiver.inner.lock().unwrap();
+        if inner.is_shutdown {
+            return Ok(());
+        }
+        inner.is_shutdown = true;
+        drop(inner);
+
+        // Any pending async recv will complete with end-of-file once the
+        // read side of the host socket is shutdown.
+        self.common.host_shutdown(Shutdown::Read)?;
+
+        // Wake up the readers, who will observe end-of-file.
+        self.common.pollee().add_events(Events::IN | Events::RDHUP);
+        Ok(())
+    }
 }
 
 pub struct Receiver {
@@ -145,16 +264,10 @@ pub struct Receiver {
 }
 
 impl Receiver {
-    pub fn new() -> Self {
-        let inner = Mutex::new(Inner::new());
+    pub fn new(buf_size: usize) -> Self {
+        let inner = Mutex::new(Inner::new(buf_size));
         Self { inner }
     }
-
-    pub fn shutdown(&self) {
-        let mut inner = self.inner.lock().unwrap();
-        inner.is_shutdown = true;
-        // TODO: update pollee?
-    }
 }
 
 impl std::fmt::Debug for Receiver {
@@ -172,6 +285,7 @@ struct Inner {
     is_shutdown: bool,
     end_of_file: bool,
     fatal: Option<Errno>,
+    buf_sizer: BufSizer,
 }
 
 // Safety. `RecvReq` does not implement `Send`. But since all pointers in `RecvReq`
@@ -181,17 +295,27 @@ struct Inner {
 unsafe impl Send for Inner {}
 
 impl Inner {
-    pub fn new() -> Self {
+    pub fn new(buf_size: usize) -> Self {
+        let buf_sizer = BufSizer::new(buf_size);
         Self {
-            recv_buf: UntrustedCircularBuf::with_capacity(super::RECV_BUF_SIZE),
+            recv_buf: UntrustedCircularBuf::with_capacity(buf_sizer.init_size()),
             recv_req: UntrustedBox::new_uninit(),
             io_handle: None,
             is_shutdown: false,
             end_of_file: false,
             fatal: None,
+            buf_sizer,
         }
     }
 
+    pub fn adjust_recv_buf(&mut self) {
+        self.buf_sizer.adjust(&mut self.recv_buf);
+    }
+
+    pub fn record_recv_buf_usage(&mut self) {
+        self.buf_sizer.record_usage(&self.recv_buf);
+    }
+
     /// Constructs a new recv request according to the receiver's internal state.
     ///
     /// The new `RecvReq` will be put into `self.recv_req`, which is a location that is
@@ -254,6 +378,7 @@ impl std::fmt::Debug for Inner {
             .field("is_shutdown", &self.is_shutdown)
             .field("end_of_file", &self.end_of_file)
             .field("fatal", &self.fatal)
+            .field("buf_sizer", &self.buf_sizer)
             .finish()
     }
 }
@@ -2,6 +2,7 @@ use std::marker::PhantomData;
 use std::mem::MaybeUninit;
 use std::ptr::{self};
 
+use async_io::socket::{SendFlags, Shutdown};
 use io_uring_callback::{Fd, IoHandle};
 use memoffset::offset_of;
 use sgx_untrusted_alloc::{MaybeUntrusted, UntrustedBox};
@@ -9,21 +10,28 @@ use sgx_untrusted_alloc::{MaybeUntrusted, UntrustedBox};
 use super::ConnectedStream;
 use crate::prelude::*;
 use crate::runtime::Runtime;
-use crate::util::UntrustedCircularBuf;
+use crate::util::{BufSizer, Timer, UntrustedCircularBuf};
 
 impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
-    pub async fn writev(self: &Arc<Self>, bufs: &[&[u8]]) -> Result<usize> {
+    pub async fn sendmsg(self: &Arc<Self>, bufs: &[&[u8]], flags: SendFlags) -> Result<usize> {
         let total_len: usize = bufs.iter().map(|buf| buf.len()).sum();
         if total_len == 0 {
             return Ok(0);
         }
+        if flags.contains(SendFlags::MSG_OOB) {
+            return_errno!(EOPNOTSUPP, "out-of-band data are not supported");
+        }
+
+        let is_nonblocking =
+            flags.contains(SendFlags::MSG_DONTWAIT) || self.common.is_nonblocking();
 
-        // Initialize the poller only when needed
+        // Initialize the poller and the timer only when needed
         let mut poller = None;
+        let mut timer = None;
         loop {
             // Attempt to write
-            let res = self.try_writev(bufs);
-            if !res.has_errno(EAGAIN) {
+            let res = self.try_sendmsg(bufs, flags);
+            if !res.has_errno(EAGAIN) || is_nonblocking {
                 return res;
             }
 
@@ -34,12 +42,21 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
             let mask = Events::OUT;
             let events = self.common.pollee().poll(mask, poller.as_mut());
             if events.is_empty() {
+                if let Some(timeout) = self.common.send_timeout() {
+                    let timer =
+                        timer.get_or_insert_with(|| Timer::start(self.common.io_uring(), timeout));
+                    if timer.poll(poller.as_mut()) {
+                        // The async send is not left pending on the host
+                        self.cancel_send();
+                        return_errno!(EAGAIN, "the send timed out");
+                    }
+                }
                 poller.as_ref().unwrap().wait().await;
             }
         }
     }
 
-    fn try_writev(self: &Arc<Self>, bufs: &[&[u8]]) -> Result<usize> {
+    fn try_sendmsg(self: &Arc<Self>, bufs: &[&[u8]], flags: SendFlags) -> Result<usize> {
         let mut inner = self.sender.inner.lock().unwrap();
 
         // Check for error condition before write.
@@ -53,6 +70,8 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
             return_errno!(errno, "write failed");
         }
 
+        inner.try_adjust_send_buf();
+
         // Copy data from the bufs to the send buffer
         let nbytes = {
             let mut total_produced = 0;
@@ -65,14 +84,22 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
             }
             total_produced
         };
+        inner.record_send_buf_usage();
+
+        // More data are coming, so the data can be held back to be coalesced with
+        // the data of the following writes. A write that appends nothing leaves the
+        // data queued earlier as they are.
+        if nbytes > 0 {
+            inner.is_more = flags.contains(SendFlags::MSG_MORE);
+        }
 
         if inner.send_buf.is_full() {
             // Mark the socket as non-writable
             self.common.pollee().del_events(Events::OUT);
         }
 
-        // Since the send buffer is not empty, we can try to flush the buffer
-        if inner.io_handle.is_none() {
+        // The data written while a send is in flight are coalesced into the next send
+        if inner.io_handle.is_none() && inner.should_flush() {
             self.do_send(&mut inner);
         }
 
@@ -85,7 +112,6 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
 
     fn do_send(self: &Arc<Self>, inner: &mut MutexGuard<Inner>) {
         debug_assert!(!inner.send_buf.is_empty());
-        debug_assert!(!inner.is_shutdown);
         debug_assert!(inner.io_handle.is_none());
 
         // Init the callback invoked upon the completion of the async send
@@ -101,7 +127,17 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
                 // TODO: guard against Iago attack through errno
                 // TODO: should we ignore EINTR and try again?
                 let errno = Errno::from(-retval as u32);
+                // The async send was cancelled as a send timed out. The data stay
+                // in the send buffer and are still to be sent, since the timeout
+                // only bounds how long the caller blocks.
+                if errno == ECANCELED {
+                    if !inner.send_buf.is_empty() {
+                        stream.do_send(&mut inner);
+                    }
+                    return;
+                }
                 inner.fatal = Some(errno);
+                stream.common.set_errno(errno);
                 stream.common.pollee().add_events(Events::ERR);
                 return;
             }
@@ -110,14 +146,33 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
             // Handle the normal case of a successful write
             let nbytes = retval as usize;
             inner.send_buf.consume_without_copy(nbytes);
+            inner.stats.num_bytes += nbytes as u64;
+
+            // The buf may grow or shrink now that the host is done with it
+            inner.try_adjust_send_buf();
 
             // Now that we have consume non-zero bytes, the buf must become
             // ready to write.
             stream.common.pollee().add_events(Events::OUT);
 
             // Attempt to send again if there are available data in the buf.
-            if !inner.send_buf.is_empty() {
+            if inner.should_flush() {
                 stream.do_send(&mut inner);
+                return;
+            }
+            // The remaining data, if any, are held back until more data come or
+            // the cork is removed.
+            if !inner.send_buf.is_empty() {
+                return;
+            }
+            inner.is_pushing = false;
+
+            // Now that all data in the buf have been sent...
+            // ...the deferred shutdown of the write side can be done.
+            if inner.is_shutdown {
+                if let Err(e) = stream.common.host_shutdown(Shutdown::Write) {
+                    log::error!("deferred shutdown failed: {}", e);
+                }
             }
         };
 
@@ -127,8 +182,92 @@ impl<A: Addr + 'static, R: Runtime> ConnectedStream<A, R> {
         // Submit the async send to io_uring
         let io_uring = self.common.io_uring();
         let host_fd = Fd(self.common.host_fd() as _);
-        let handle = unsafe { io_uring.sendmsg(host_fd, msghdr_ptr, 0, complete_fn) };
+        // SIGPIPE, if any, is up to the LibOS, not the host, to raise
+        let mut host_flags = libc::MSG_NOSIGNAL;
+        if inner.is_held_back() {
+            // The host is told that more data are coming, so that it can coalesce
+            // the data into fewer packets, too.
+            host_flags |= libc::MSG_MORE;
+        }
+        let handle = unsafe { io_uring.sendmsg(host_fd, msghdr_ptr, host_flags as _, complete_fn) };
         inner.io_handle.replace(handle);
+        inner.stats.num_submissions += 1;
+    }
+
+    /// Cancel the pending async send, if any.
+    ///
+    /// The data in the send buffer are kept. Once the cancellation completes, a
+    /// new async send is submitted for them right away, so the data accepted by
+    /// the previous sends are delivered, timeout or not.
+    fn cancel_send(&self) {
+        let inner = self.sender.inner.lock().unwrap();
+        if let Some(io_handle) = &inner.io_handle {
+            unsafe { self.common.io_uring().cancel(io_handle) };
+        }
+    }
+
+    /// Set or clear `TCP_CORK`, which makes the sender hold back partial batches
+    /// of data.
+    ///
+    /// Removing the cork flushes the held data. Unlike Linux, there is no 200ms
+    /// ceiling on how long the data can be corked.
+    pub fn set_corked(self: &Arc<Self>, is_corked: bool) {
+        let mut inner = self.sender.inner.lock().unwrap();
+        inner.is_corked = is_corked;
+        if inner.io_handle.is_none() && inner.should_flush() {
+            self.do_send(&mut inner);
+        }
+    }
+
+    /// Push out all the data in the send buffer, including the ones held back
+    /// for `MSG_MORE` or `TCP_CORK`.
+    pub fn push(self: &Arc<Self>) {
+        let mut inner = self.sender.inner.lock().unwrap();
+        if inner.send_buf.is_empty() {
+            return;
+        }
+        inner.is_pushing = true;
+        if inner.io_handle.is_none() {
+            self.do_send(&mut inner);
+        }
+    }
+
+    pub fn send_stats(&self) -> SendStats {
+        let inner = self.sender.inner.lock().unwrap();
+        inner.stats
+    }
+
+    /// Set the max size of the send buffer.
+    ///
+    /// If the buffer is larger than the new size, it shrinks once the host is done
+    /// with it and the data in it fit.
+    pub fn set_send_buf_size(self: &Arc<Self>, buf_size: usize) {
+        let mut inner = self.sender.inner.lock().unwrap();
+        inner.buf_sizer.set_max_size(buf_size);
+        inner.try_adjust_send_buf();
+    }
+
+    pub(super) fn shutdown_write(self: &Arc<Self>) -> Result<()> {
+        let mut inner = self.sender.inner.lock().unwrap();
+        if inner.is_shutdown {
+            return Ok(());
+        }
+        inner.is_shutdown = true;
+
+        // Wake up the writers, who will observe that the write side is shutdown.
+        self.common.pollee().add_events(Events::OUT);
+
+        // If there are still data in the send buffer, the shutdown of the write side
+        // of the host socket is deferred until all the data are sent.
+        if inner.io_handle.is_none() && !inner.send_buf.is_empty() {
+            // The data held back are flushed now
+            self.do_send(&mut inner);
+        }
+        if inner.io_handle.is_some() {
+            return Ok(());
+        }
+        drop(inner);
+        self.common.host_shutdown(Shutdown::Write)
     }
 }
 
@@ -137,16 +276,10 @@ pub struct Sender {
 }
 
 impl Sender {
-    pub fn new() -> Self {
-        let inner = Mutex::new(Inner::new());
+    pub fn new(buf_size: usize, is_corked: bool) -> Self {
+        let inner = Mutex::new(Inner::new(buf_size, is_corked));
         Self { inner }
     }
-
-    pub fn shutdown(&self) {
-        let mut inner = self.inner.lock().unwrap();
-        inner.is_shutdown = true;
-        // TODO: update pollee?
-    }
 }
 
 impl std::fmt::Debug for Sender {
@@ -162,7 +295,23 @@ struct Inner {
     send_req: UntrustedBox<SendReq>,
     io_handle: Option<IoHandle>,
     is_shutdown: bool,
+    is_more: bool,
+    is_corked: bool,
+    is_pushing: bool,
     fatal: Option<Errno>,
+    buf_sizer: BufSizer,
+    stats: SendStats,
+}
+
+/// The statistics of the data sent through the host socket.
+///
+/// Comparing the two numbers tells how well the small writes are coalesced.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct SendStats {
+    /// The number of async sends submitted to io_uring.
+    pub num_submissions: u64,
+    /// The number of bytes sent.
+    pub num_bytes: u64,
 }
 
 // Safety. `SendReq` does not implement `Send`. But since all pointers in `SengReq`
@@ -172,16 +321,52 @@ struct Inner {
 unsafe impl Send for Inner {}
 
 impl Inner {
-    pub fn new() -> Self {
+    pub fn new(buf_size: usize, is_corked: bool) -> Self {
+        let buf_sizer = BufSizer::new(buf_size);
         Self {
-            send_buf: UntrustedCircularBuf::with_capacity(super::SEND_BUF_SIZE),
+            send_buf: UntrustedCircularBuf::with_capacity(buf_sizer.init_size()),
             send_req: UntrustedBox::new_uninit(),
             io_handle: None,
             is_shutdown: false,
+            is_more: false,
+            is_corked,
+            is_pushing: false,
             fatal: None,
+            buf_sizer,
+            stats: SendStats::default(),
         }
     }
 
+    /// Whether the data in the send buffer should be sent now, if no send is in flight.
+    ///
+    /// The data are held back if more data are coming, i.e., `MSG_MORE` or `TCP_CORK`,
+    /// until they make a full batch, which is half of the send buffer. Otherwise,
+    /// the data are sent as soon as possible.
+    pub fn should_flush(&self) -> bool {
+        if self.send_buf.is_empty() {
+            return false;
+        }
+        let is_batch_full = self.send_buf.consumable() >= self.send_buf.capacity() / 2;
+        !self.is_held_back() || is_batch_full || self.is_shutdown
+    }
+
+    fn is_held_back(&self) -> bool {
+        (self.is_more || self.is_corked) && !self.is_pushing
+    }
+
+    /// Grow or shrink the send buffer if needed and it is safe to do so, i.e., there
+    /// are no async send referring to the buffer.
+    pub fn try_adjust_send_buf(&mut self) {
+        if self.io_handle.is_some() {
+            return;
+        }
+        self.buf_sizer.adjust(&mut self.send_buf);
+    }
+
+    pub fn record_send_buf_usage(&mut self) {
+        self.buf_sizer.record_usage(&self.send_buf);
+    }
+
     /// Constructs a new send request according to the sender's internal state.
     ///
     /// The new `SendReq` will be put into `self.send_req`, which is a location that is
@@ -242,7 +427,12 @@ impl std::fmt::Debug for Inner {
             .field("send_buf", &self.send_buf)
             .field("io_handle", &self.io_handle)
             .field("is_shutdown", &self.is_shutdown)
+            .field("is_more", &self.is_more)
+            .field("is_corked", &self.is_corked)
+            .field("is_pushing", &self.is_pushing)
             .field("fatal", &self.fatal)
+            .field("buf_sizer", &self.buf_sizer)
+            .field("stats", &self.stats)
             .finish()
     }
 }
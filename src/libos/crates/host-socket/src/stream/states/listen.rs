@@ -1,19 +1,34 @@
 use std::collections::VecDeque;
 use std::marker::PhantomData;
 use std::mem::{size_of, MaybeUninit};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use io_uring_callback::{Fd, IoHandle};
 use memoffset::offset_of;
 use sgx_untrusted_alloc::{MaybeUntrusted, UntrustedBox};
 
+use super::common::close_host_fd;
 use super::{Common, ConnectedStream};
 use crate::prelude::*;
 use crate::runtime::Runtime;
+use crate::util::Timer;
 
 /// A listener stream, ready to accept incoming connections.
+///
+/// The accepted connections are put into per-vCPU queues so that the server
+/// threads running on different vCPUs accept connections without contending
+/// for a single lock or waking up each other.
 pub struct ListenerStream<A: Addr + 'static, R: Runtime> {
     common: Arc<Common<A, R>>,
     inner: Mutex<Inner<A>>,
+    ready_queues: Box<[ReadyQueue<A>]>,
+    // The total number of accepted connections in the ready queues
+    num_ready: AtomicUsize,
+    // The ready queue to try first when dispatching a new connection
+    next_queue: AtomicUsize,
+    // Whether some free entries of the backlog are left idle as there are
+    // too many connections in the ready queues
+    is_starved: AtomicBool,
 }
 
 impl<A: Addr + 'static, R: Runtime> ListenerStream<A, R> {
@@ -23,14 +38,22 @@ impl<A: Addr + 'static, R: Runtime> ListenerStream<A, R> {
 
         Self::do_listen(common.host_fd(), backlog)?;
 
+        let ready_queues = (0..R::num_vcpus().max(1))
+            .map(|_| ReadyQueue::new())
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
         let new_self = Arc::new(Self {
             common,
             inner: Mutex::new(inner),
+            ready_queues,
+            num_ready: AtomicUsize::new(0),
+            next_queue: AtomicUsize::new(0),
+            is_starved: AtomicBool::new(false),
         });
 
         // Start async accept requests right as early as possible to improve performance
         {
-            let mut inner = new_self.inner.lock().unwrap();
+            let inner = new_self.inner.lock().unwrap();
             new_self.initiate_async_accepts(inner);
         }
 
@@ -51,12 +74,17 @@ impl<A: Addr + 'static, R: Runtime> ListenerStream<A, R> {
     }
 
     pub async fn accept(self: &Arc<Self>) -> Result<Arc<ConnectedStream<A, R>>> {
-        // Init the poller only when needed
+        // Wait on the ready queue of the current vCPU
+        let queue_idx = R::vcpu_id() % self.ready_queues.len();
+        let ready_queue = &self.ready_queues[queue_idx];
+
+        // Init the poller and the timer only when needed
         let mut poller = None;
+        let mut timer = None;
         loop {
             // Attempt to accept
-            let res = self.try_accept();
-            if !res.has_errno(EAGAIN) {
+            let res = self.try_accept_from(queue_idx);
+            if !res.has_errno(EAGAIN) || self.common.is_nonblocking() {
                 return res;
             }
 
@@ -64,35 +92,66 @@ impl<A: Addr + 'static, R: Runtime> ListenerStream<A, R> {
             if poller.is_none() {
                 poller = Some(Poller::new());
             }
-            // Wait for interesting events by polling
-            let mask = Events::IN;
-            let events = self.common.pollee().poll(mask, poller.as_mut());
-            if events.is_empty() {
+            // Wait for interesting events by polling. Announce the waiter and check
+            // the other queues with the lock of `inner` held, under which new
+            // connections are dispatched. So either a new connection is dispatched
+            // to a queue with waiters after the announcement, or it is seen here.
+            let has_ready = {
+                let _inner = self.inner.lock().unwrap();
+                ready_queue.num_waiters.fetch_add(1, Ordering::SeqCst);
+                let mask = Events::IN;
+                let events = ready_queue.pollee.poll(mask, poller.as_mut());
+                !events.is_empty() || self.num_ready.load(Ordering::SeqCst) > 0
+            };
+            if !has_ready {
+                // Just like Linux, SO_RCVTIMEO applies to accepting
+                let is_timed_out = self.common.recv_timeout().map_or(false, |timeout| {
+                    let timer =
+                        timer.get_or_insert_with(|| Timer::start(self.common.io_uring(), timeout));
+                    timer.poll(poller.as_mut())
+                });
+                if is_timed_out {
+                    ready_queue.num_waiters.fetch_sub(1, Ordering::SeqCst);
+                    return_errno!(EAGAIN, "the accept timed out");
+                }
                 poller.as_ref().unwrap().wait().await;
             }
+            ready_queue.num_waiters.fetch_sub(1, Ordering::SeqCst);
         }
     }
 
     pub fn try_accept(self: &Arc<Self>) -> Result<Arc<ConnectedStream<A, R>>> {
-        let mut inner = self.inner.lock().unwrap();
-
-        if let Some(errno) = inner.fatal {
-            return_errno!(errno, "accept failed");
-        }
+        let queue_idx = R::vcpu_id() % self.ready_queues.len();
+        self.try_accept_from(queue_idx)
+    }
 
-        let (accepted_fd, accepted_addr) = inner
-            .backlog
-            .pop_completed_req()
-            .ok_or_else(|| errno!(EAGAIN, "try accept again"))?;
+    /// Try to accept a connection from the given ready queue, or from the other
+    /// queues if the given one is empty.
+    fn try_accept_from(self: &Arc<Self>, queue_idx: usize) -> Result<Arc<ConnectedStream<A, R>>> {
+        let (accepted_fd, accepted_addr) = match self.pop_ready(queue_idx) {
+            Some(ready) => ready,
+            None => {
+                let inner = self.inner.lock().unwrap();
+                if let Some(errno) = inner.fatal {
+                    return_errno!(errno, "accept failed");
+                }
+                return_errno!(EAGAIN, "try accept again");
+            }
+        };
 
-        if !inner.backlog.has_completed_reqs() {
-            self.common.pollee().del_events(Events::IN);
+        // Resume the async accepts that were held back by the full ready queues
+        if self.is_starved.load(Ordering::SeqCst) {
+            let inner = self.inner.lock().unwrap();
+            self.initiate_async_accepts(inner);
         }
 
-        self.initiate_async_accepts(inner);
-
         let common = {
-            let mut common = Arc::new(Common::with_host_fd(accepted_fd));
+            let common = Arc::new(Common::with_host_fd(accepted_fd));
+            common.set_peer_addr(&accepted_addr);
+            // Just like Linux, the accepted socket inherits the buffer sizes
+            // of the listener socket.
+            common.set_send_buf_size(self.common.send_buf_size());
+            common.set_recv_buf_size(self.common.recv_buf_size());
             common.pollee().add_events(Events::OUT);
             common
         };
@@ -100,11 +159,58 @@ impl<A: Addr + 'static, R: Runtime> ListenerStream<A, R> {
         Ok(accepted_stream)
     }
 
+    /// Dispatch a newly-accepted connection to a ready queue.
+    ///
+    /// A queue that has blocked acceptors is preferred so that the connection
+    /// wakes up one of them, instead of waiting for the acceptors of a busy vCPU.
+    ///
+    /// The lock of `inner` must be held so that the acceptors about to block
+    /// are seen, which is ensured by taking its guard.
+    fn push_ready(&self, _inner: &MutexGuard<Inner<A>>, host_fd: HostFd, addr: A) {
+        let num_queues = self.ready_queues.len();
+        let start_idx = self.next_queue.fetch_add(1, Ordering::Relaxed) % num_queues;
+        let queue_idx = (0..num_queues)
+            .map(|offset| (start_idx + offset) % num_queues)
+            .find(|idx| self.ready_queues[*idx].num_waiters.load(Ordering::SeqCst) > 0)
+            .unwrap_or(start_idx);
+
+        self.num_ready.fetch_add(1, Ordering::SeqCst);
+        self.ready_queues[queue_idx].push(host_fd, addr);
+        self.common.pollee().add_events(Events::IN);
+    }
+
+    /// Pop a ready connection, preferring the given queue and stealing from the
+    /// others if the given one is empty.
+    fn pop_ready(&self, queue_idx: usize) -> Option<(HostFd, A)> {
+        let num_queues = self.ready_queues.len();
+        let ready = (0..num_queues)
+            .map(|offset| (queue_idx + offset) % num_queues)
+            .find_map(|idx| self.ready_queues[idx].pop())?;
+
+        if self.num_ready.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.common.pollee().del_events(Events::IN);
+            // A connection may have been pushed before the events are deleted
+            if self.num_ready.load(Ordering::SeqCst) > 0 {
+                self.common.pollee().add_events(Events::IN);
+            }
+        }
+        Some(ready)
+    }
+
     fn initiate_async_accepts(self: &Arc<Self>, mut inner: MutexGuard<Inner<A>>) {
+        // The pending requests and the ready connections are no more than the
+        // capacity of the backlog, which bounds the connections accepted from the
+        // host but not yet by the user.
         let backlog = &mut inner.backlog;
+        let mut is_starved = false;
         while backlog.has_free_entries() {
+            if self.num_ready.load(Ordering::SeqCst) >= backlog.num_free {
+                is_starved = true;
+                break;
+            }
             backlog.start_new_req(self);
         }
+        self.is_starved.store(is_starved, Ordering::SeqCst);
     }
 
     pub fn common(&self) -> &Arc<Common<A, R>> {
@@ -117,6 +223,7 @@ impl<A: Addr + 'static, R: Runtime> std::fmt::Debug for ListenerStream<A, R> {
         f.debug_struct("ListenerStream")
             .field("common", &self.common)
             .field("inner", &self.inner.lock().unwrap())
+            .field("num_ready", &self.num_ready.load(Ordering::Relaxed))
             .finish()
     }
 }
@@ -145,6 +252,49 @@ impl<A: Addr + 'static> std::fmt::Debug for Inner<A> {
     }
 }
 
+/// A queue of the accepted connections that are ready to be returned by `accept`.
+struct ReadyQueue<A: Addr> {
+    conns: Mutex<VecDeque<(HostFd, A)>>,
+    pollee: Pollee,
+    // The number of acceptors that are waiting on the queue
+    num_waiters: AtomicUsize,
+}
+
+impl<A: Addr> ReadyQueue<A> {
+    fn new() -> Self {
+        Self {
+            conns: Mutex::new(VecDeque::new()),
+            pollee: Pollee::new(Events::empty()),
+            num_waiters: AtomicUsize::new(0),
+        }
+    }
+
+    fn push(&self, host_fd: HostFd, addr: A) {
+        let mut conns = self.conns.lock().unwrap();
+        conns.push_back((host_fd, addr));
+        self.pollee.add_events(Events::IN);
+    }
+
+    fn pop(&self) -> Option<(HostFd, A)> {
+        let mut conns = self.conns.lock().unwrap();
+        let conn = conns.pop_front()?;
+        if conns.is_empty() {
+            self.pollee.del_events(Events::IN);
+        }
+        Some(conn)
+    }
+}
+
+impl<A: Addr> Drop for ReadyQueue<A> {
+    fn drop(&mut self) {
+        // The connections accepted from the host but not by the user are closed
+        let mut conns = self.conns.lock().unwrap();
+        for (host_fd, _) in conns.drain(..) {
+            close_host_fd(host_fd);
+        }
+    }
+}
+
 /// An entry in the backlog.
 #[derive(Debug)]
 enum Entry {
@@ -152,8 +302,6 @@ enum Entry {
     Free,
     /// The entry is a pending accept request.
     Pending { io_handle: IoHandle },
-    /// The entry is a completed accept request.
-    Completed { host_fd: HostFd },
 }
 
 impl Default for Entry {
@@ -175,15 +323,14 @@ unsafe impl MaybeUntrusted for AcceptReq {}
 
 /// A backlog of incoming connections of a listener stream.
 ///
-/// With backlog, we can start async accept requests, keep track of the pending requests,
-/// and maintain the ones that have completed.
+/// With backlog, we can start async accept requests and keep track of the pending
+/// requests. Once a request completes, its entry becomes free again and the accepted
+/// connection goes to a ready queue of the listener stream.
 struct Backlog<A: Addr> {
     // The entries in the backlog.
     entries: Box<[Entry]>,
     // Arguments of the io_uring requests submitted for the entries in the backlog.
     reqs: UntrustedBox<[AcceptReq]>,
-    // The indexes of completed entries.
-    completed: VecDeque<usize>,
     // The number of free entries.
     num_free: usize,
     phantom_data: PhantomData<A>,
@@ -200,12 +347,10 @@ impl<A: Addr> Backlog<A> {
             .collect::<Vec<Entry>>()
             .into_boxed_slice();
         let reqs = UntrustedBox::new_uninit_slice(capacity);
-        let completed = VecDeque::new();
         let num_free = capacity;
         let new_self = Self {
             entries,
             reqs,
-            completed,
             num_free,
             phantom_data: PhantomData,
         };
@@ -240,6 +385,7 @@ impl<A: Addr> Backlog<A> {
             move |retval: i32| {
                 let mut inner = stream.inner.lock().unwrap();
 
+                let accepted_addr = inner.backlog.complete_req(entry_idx);
                 if retval < 0 {
                     // Since most errors that may result from the accept syscall are _not fatal_,
                     // we simply ignore the errno code and try again.
@@ -254,15 +400,16 @@ impl<A: Addr> Backlog<A> {
                     log::error!("Accept error: errno = {}", errno);
                     //inner.fatal = Some(errno);
                     //stream.common.pollee().add_events(Events::ERR);
-
-                    inner.backlog.entries[entry_idx] = Entry::Free;
-                    inner.backlog.num_free += 1;
                 } else {
                     let host_fd = retval as HostFd;
-                    inner.backlog.entries[entry_idx] = Entry::Completed { host_fd };
-                    inner.backlog.completed.push_back(entry_idx);
-
-                    stream.common.pollee().add_events(Events::IN);
+                    match accepted_addr {
+                        Some(addr) => stream.push_ready(&inner, host_fd, addr),
+                        None => {
+                            // The connection cannot be used without a valid address
+                            log::error!("Accept error: invalid address of the accepted socket");
+                            close_host_fd(host_fd);
+                        }
+                    }
                 }
 
                 stream.initiate_async_accepts(inner);
@@ -277,28 +424,16 @@ impl<A: Addr> Backlog<A> {
         self.num_free -= 1;
     }
 
-    pub fn has_completed_reqs(&self) -> bool {
-        self.completed.len() > 0
-    }
-
-    /// Pop a completed async accept request, turing a completed entry into a free one.
-    pub fn pop_completed_req(&mut self) -> Option<(HostFd, A)> {
-        let completed_idx = self.completed.pop_front()?;
-        let accepted_addr = {
-            let AcceptReq { c_addr, c_addr_len } = self.reqs[completed_idx].clone();
-            A::from_c_storage(&c_addr, c_addr_len as _).unwrap()
-        };
-        let accepted_fd = {
-            let entry = &mut self.entries[completed_idx];
-            let accepted_fd = match entry {
-                Entry::Completed { host_fd } => *host_fd,
-                _ => unreachable!("the entry should have been completed"),
-            };
-            self.num_free += 1;
-            *entry = Entry::Free;
-            accepted_fd
-        };
-        Some((accepted_fd, accepted_addr))
+    /// Complete an async accept request, turning a pending entry into a free one.
+    ///
+    /// The address of the accepted connection is returned if it is valid.
+    pub fn complete_req(&mut self, entry_idx: usize) -> Option<A> {
+        let AcceptReq { c_addr, c_addr_len } = self.reqs[entry_idx].clone();
+        let accepted_addr = A::from_c_storage(&c_addr, c_addr_len as _).ok();
+        debug_assert!(matches!(self.entries[entry_idx], Entry::Pending { .. }));
+        self.entries[entry_idx] = Entry::Free;
+        self.num_free += 1;
+        accepted_addr
     }
 }
 
@@ -306,14 +441,7 @@ impl<A: Addr + 'static> std::fmt::Debug for Backlog<A> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Backlog")
             .field("entries", &self.entries)
-            .field("completed", &self.completed)
             .field("num_free", &self.num_free)
             .finish()
     }
 }
-
-impl<A: Addr> Drop for Backlog<A> {
-    fn drop(&mut self) {
-        // TODO: close the accepted fds
-    }
-}
@@ -6,6 +6,6 @@ mod listen;
 
 pub use self::common::Common;
 pub use self::connect::ConnectingStream;
-pub use self::connected::ConnectedStream;
+pub use self::connected::{adjust_buf_size, ConnectedStream, SendStats};
 pub use self::init::InitStream;
 pub use self::listen::ListenerStream;
@@ -0,0 +1,34 @@
+//! The accounting of the untrusted memory used by the buffers of all sockets.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::config;
+
+static USED: AtomicUsize = AtomicUsize::new(0);
+
+/// Charge the budget for some bytes, even if the budget would be exceeded.
+pub fn charge(size: usize) {
+    USED.fetch_add(size, Ordering::Relaxed);
+}
+
+/// Charge the budget for some bytes, failing if the budget would be exceeded.
+pub fn try_charge(size: usize) -> bool {
+    let budget = config::buf_budget();
+    let mut used = USED.load(Ordering::Relaxed);
+    loop {
+        let new_used = match used.checked_add(size) {
+            Some(new_used) if new_used <= budget => new_used,
+            _ => return false,
+        };
+        match USED.compare_exchange_weak(used, new_used, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return true,
+            Err(actual) => used = actual,
+        }
+    }
+}
+
+/// Return some bytes to the budget.
+pub fn uncharge(size: usize) {
+    let old_used = USED.fetch_sub(size, Ordering::Relaxed);
+    debug_assert!(old_used >= size);
+}
@@ -0,0 +1,100 @@
+use super::UntrustedCircularBuf;
+use crate::config;
+
+/// A sizer decides when and how a send or receive buffer grows or shrinks.
+///
+/// A buffer grows, by doubling its size, if it is ever filled up, until it hits
+/// the max size. And it shrinks, by halving its size, if its usage stays below a
+/// quarter of its size during a window of I/O operations, until it hits the min
+/// size. The size of a buffer is in the same sense as `UntrustedCircularBuf::with_capacity`.
+pub struct BufSizer {
+    min_size: usize,
+    max_size: usize,
+    // The peak usage of the buffer in the current window
+    peak_usage: usize,
+    // Whether the buffer has been full in the current window
+    is_pressured: bool,
+    // The number of operations in the current window
+    num_ops: usize,
+}
+
+impl BufSizer {
+    /// The number of I/O operations in a window.
+    const WINDOW_LEN: usize = 64;
+
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            min_size: config::min_buf_size().min(max_size),
+            max_size,
+            peak_usage: 0,
+            is_pressured: false,
+            num_ops: 0,
+        }
+    }
+
+    /// Returns the size of a newly-created buffer.
+    pub fn init_size(&self) -> usize {
+        self.min_size
+    }
+
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.min_size = config::min_buf_size().min(max_size);
+    }
+
+    /// Record the usage of the buffer after some bytes are put into the buffer.
+    pub fn record_usage(&mut self, buf: &UntrustedCircularBuf) {
+        self.peak_usage = self.peak_usage.max(buf.consumable());
+        self.is_pressured |= buf.is_full();
+        self.num_ops += 1;
+    }
+
+    /// Grow or shrink the buffer if needed.
+    ///
+    /// The caller must ensure that no one (e.g., the host) is accessing the buffer.
+    pub fn adjust(&mut self, buf: &mut UntrustedCircularBuf) {
+        // The size of a buffer is its capacity plus one
+        let size = buf.capacity() + 1;
+        let new_size = if size > self.max_size {
+            self.max_size
+        } else if self.is_pressured && size < self.max_size {
+            (size * 2).min(self.max_size)
+        } else if self.num_ops >= Self::WINDOW_LEN {
+            let is_underused = self.peak_usage < size / 4;
+            self.new_window(buf);
+            if !is_underused || size <= self.min_size {
+                return;
+            }
+            (size / 2).max(self.min_size)
+        } else {
+            return;
+        };
+        // The bytes in the buffer must be kept. If they do not fit, try again later
+        // when more bytes are consumed.
+        if new_size <= buf.consumable() {
+            return;
+        }
+
+        // Growing the buffer may fail for the budget, which is fine
+        if buf.try_resize(new_size) {
+            self.new_window(buf);
+        }
+    }
+
+    fn new_window(&mut self, buf: &UntrustedCircularBuf) {
+        self.peak_usage = buf.consumable();
+        self.is_pressured = false;
+        self.num_ops = 0;
+    }
+}
+
+impl std::fmt::Debug for BufSizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufSizer")
+            .field("min_size", &self.min_size)
+            .field("max_size", &self.max_size)
+            .field("peak_usage", &self.peak_usage)
+            .field("is_pressured", &self.is_pressured)
+            .finish()
+    }
+}
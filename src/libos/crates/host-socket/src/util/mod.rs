@@ -1,3 +1,8 @@
+mod buf_budget;
+mod buf_sizer;
+mod timer;
 mod untrusted_circular_buf;
 
+pub use self::buf_sizer::BufSizer;
+pub use self::timer::Timer;
 pub use self::untrusted_circular_buf::UntrustedCircularBuf;
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use io_uring_callback::{IoHandle, IoUring, TimeoutFlags, Timespec};
+use sgx_untrusted_alloc::{MaybeUntrusted, UntrustedBox};
+
+use crate::prelude::*;
+
+/// A one-shot timer backed by an io_uring timeout request.
+///
+/// The timer is meant to bound the time of waiting on pollees: once it expires,
+/// its own pollee gets an event, which wakes up the pollers that poll the timer.
+/// Dropping a timer that has not expired cancels the timeout request.
+pub struct Timer {
+    inner: Arc<Inner>,
+    io_uring: &'static IoUring,
+}
+
+struct Inner {
+    pollee: Pollee,
+    req: Mutex<TimeoutReq>,
+}
+
+struct TimeoutReq {
+    io_handle: Option<IoHandle>,
+    timespec: UntrustedBox<KernelTimespec>,
+    is_expired: bool,
+}
+
+// Safety. `UntrustedBox` does not implement `Send`. But since the timespec in it
+// is only read by io_uring, it is ok for `TimeoutReq` to move between threads.
+unsafe impl Send for TimeoutReq {}
+
+impl Timer {
+    /// Start a timer that expires after the given duration.
+    pub fn start(io_uring: &'static IoUring, duration: Duration) -> Self {
+        let timespec = KernelTimespec(Timespec {
+            tv_sec: duration.as_secs() as _,
+            tv_nsec: duration.subsec_nanos() as _,
+        });
+        let req = TimeoutReq {
+            io_handle: None,
+            timespec: UntrustedBox::new(timespec),
+            is_expired: false,
+        };
+        let inner = Arc::new(Inner {
+            pollee: Pollee::new(Events::empty()),
+            req: Mutex::new(req),
+        });
+
+        let callback = {
+            let inner = inner.clone();
+            move |retval: i32| {
+                let mut req = inner.req.lock().unwrap();
+                req.io_handle.take();
+                // A timeout request that is not cancelled completes with ETIME
+                if retval == -(ETIME as i32) {
+                    req.is_expired = true;
+                    drop(req);
+                    inner.pollee.add_events(Events::IN);
+                }
+            }
+        };
+        let mut req = inner.req.lock().unwrap();
+        let timespec_ptr = &req.timespec.0 as *const Timespec;
+        let io_handle =
+            unsafe { io_uring.timeout(timespec_ptr, 0, TimeoutFlags::empty(), callback) };
+        req.io_handle = Some(io_handle);
+        drop(req);
+
+        Self { inner, io_uring }
+    }
+
+    /// Returns whether the timer has expired.
+    ///
+    /// If the timer has not expired, the poller, if given, will be notified upon
+    /// the expiration.
+    pub fn poll(&self, poller: Option<&mut Poller>) -> bool {
+        !self.inner.pollee.poll(Events::IN, poller).is_empty()
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let req = self.inner.req.lock().unwrap();
+        if let Some(io_handle) = &req.io_handle {
+            // The request is released upon the completion of the cancellation
+            unsafe { self.io_uring.cancel(io_handle) };
+        }
+    }
+}
+
+impl std::fmt::Debug for Timer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let req = self.inner.req.lock().unwrap();
+        f.debug_struct("Timer")
+            .field("io_handle", &req.io_handle)
+            .field("is_expired", &req.is_expired)
+            .finish()
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct KernelTimespec(Timespec);
+
+// Safety. KernelTimespec is a C-style struct.
+unsafe impl MaybeUntrusted for KernelTimespec {}
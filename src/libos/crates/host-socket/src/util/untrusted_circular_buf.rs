@@ -4,9 +4,12 @@ use std::slice::{self};
 
 use sgx_untrusted_alloc::{MaybeUntrusted, UntrustedBox};
 
+use super::buf_budget;
 use crate::prelude::*;
 
 /// A circular buffer in untrusted memory.
+///
+/// The memory of all circular buffers is charged to the budget of socket buffers.
 pub struct UntrustedCircularBuf {
     // The underlying storage of the buffer.
     //
@@ -27,6 +30,7 @@ impl UntrustedCircularBuf {
     /// Construct a circular buffer.
     pub fn with_capacity(capacity: usize) -> Self {
         debug_assert!(capacity > 0);
+        buf_budget::charge(capacity);
         Self {
             buf: UntrustedBox::new_uninit_slice(capacity),
             head: 0,
@@ -34,6 +38,32 @@ impl UntrustedCircularBuf {
         }
     }
 
+    /// Resize the buffer, keeping the consumable bytes.
+    ///
+    /// The new capacity, in the same sense as that of `with_capacity`, must be large
+    /// enough to hold the consumable bytes. Growing the buffer fails if the budget
+    /// of socket buffers is exhausted.
+    ///
+    /// The caller must ensure that no one (e.g., the host) is accessing the buffer.
+    pub fn try_resize(&mut self, new_capacity: usize) -> bool {
+        let old_capacity = self.buf.len();
+        debug_assert!(new_capacity > self.consumable());
+        if new_capacity > old_capacity && !buf_budget::try_charge(new_capacity - old_capacity) {
+            return false;
+        }
+
+        let mut new_buf = UntrustedBox::<[u8]>::new_uninit_slice(new_capacity);
+        let len = self.consume(&mut new_buf[..]);
+        self.buf = new_buf;
+        self.head = 0;
+        self.tail = len;
+
+        if new_capacity < old_capacity {
+            buf_budget::uncharge(old_capacity - new_capacity);
+        }
+        true
+    }
+
     /// Produce some bytes.
     pub fn produce(&mut self, buf: &[u8]) -> usize {
         self.with_producer_view(|part0, part1| {
@@ -141,6 +171,39 @@ impl UntrustedCircularBuf {
     }
 
     pub fn with_consumer_view(&mut self, f: impl FnOnce(&[u8], &[u8]) -> usize) -> usize {
+        let (part0, part1) = self.consumer_parts();
+
+        let bytes_consumed = f(part0, part1);
+        assert!(bytes_consumed <= self.consumable());
+
+        self.head = (self.head + bytes_consumed) % self.buf.len();
+        bytes_consumed
+    }
+
+    /// Copy some bytes without consuming them.
+    ///
+    /// The bytes are copied starting from the `offset`-th consumable byte.
+    pub fn peek_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let (part0, part1) = self.consumer_parts();
+
+        let mut offset = offset;
+        let mut bytes_copied = 0;
+        for part in [part0, part1].iter() {
+            if offset >= part.len() {
+                offset -= part.len();
+                continue;
+            }
+            let part = &part[offset..];
+            offset = 0;
+
+            let copy_len = part.len().min(buf.len() - bytes_copied);
+            buf[bytes_copied..bytes_copied + copy_len].copy_from_slice(&part[..copy_len]);
+            bytes_copied += copy_len;
+        }
+        bytes_copied
+    }
+
+    fn consumer_parts(&self) -> (&[u8], &[u8]) {
         let head = self.head;
         let tail = self.tail;
         let len = self.buf.len();
@@ -150,15 +213,7 @@ impl UntrustedCircularBuf {
         } else {
             (head..len, 0..tail)
         };
-
-        let part0 = &self.buf[range0];
-        let part1 = &self.buf[range1];
-
-        let bytes_consumed = f(part0, part1);
-        assert!(bytes_consumed <= self.consumable());
-
-        self.head = (head + bytes_consumed) % len;
-        bytes_consumed
+        (&self.buf[range0], &self.buf[range1])
     }
 
     pub fn consumable(&self) -> usize {
@@ -196,6 +251,12 @@ impl UntrustedCircularBuf {
     }
 }
 
+impl Drop for UntrustedCircularBuf {
+    fn drop(&mut self) {
+        buf_budget::uncharge(self.buf.len());
+    }
+}
+
 impl std::fmt::Debug for UntrustedCircularBuf {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("UntrustedCircularBuf")
@@ -290,6 +351,44 @@ mod tests {
         assert_eq!(cbuf.consumable(), 0);
     }
 
+    #[test]
+    fn test_resize() {
+        let mut cbuf = UntrustedCircularBuf::with_capacity(8);
+        // Make the consumable bytes wrap around the end of the buffer
+        assert_eq!(cbuf.produce(&[0; 5]), 5);
+        assert_eq!(cbuf.consume(&mut [0; 5]), 5);
+        assert_eq!(cbuf.produce(&[1, 2, 3, 4, 5, 6]), 6);
+
+        assert!(cbuf.try_resize(16));
+        assert_eq!(cbuf.capacity(), 15);
+        assert_eq!(cbuf.consumable(), 6);
+        assert_eq!(cbuf.produce(&[7, 8]), 2);
+
+        assert!(cbuf.try_resize(9));
+        assert_eq!(cbuf.capacity(), 8);
+        let mut buf = [0u8; 8];
+        assert_eq!(cbuf.consume(&mut buf), 8);
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_peek() {
+        let mut cbuf = UntrustedCircularBuf::with_capacity(8);
+        // Make the consumable bytes wrap around the end of the buffer
+        assert_eq!(cbuf.produce(&[0; 5]), 5);
+        assert_eq!(cbuf.consume(&mut [0; 5]), 5);
+        assert_eq!(cbuf.produce(&[1, 2, 3, 4, 5, 6]), 6);
+
+        let mut buf = [0u8; 4];
+        assert_eq!(cbuf.peek_at(0, &mut buf), 4);
+        assert_eq!(buf, [1, 2, 3, 4]);
+        assert_eq!(cbuf.peek_at(4, &mut buf), 2);
+        assert_eq!(&buf[..2], &[5, 6]);
+        assert_eq!(cbuf.peek_at(6, &mut buf), 0);
+        // Peeking does not consume any bytes
+        assert_eq!(cbuf.consumable(), 6);
+    }
+
     #[test]
     fn test_buf_full() {
         let capacity = 1024;
@@ -1,6 +1,8 @@
 //! Test sockets with an echo server.
 
-use async_io::socket::{Addr, Ipv4Addr, Ipv4SocketAddr, UnixAddr};
+use std::sync::Arc;
+
+use async_io::socket::{Addr, Ipv4Addr, Ipv4SocketAddr, Ipv6Addr, Ipv6SocketAddr, UnixAddr};
 
 #[test]
 fn ipv4() {
@@ -15,6 +17,19 @@ fn ipv4() {
     run_echo_server_and_clients(server_addr, num_clients, total_data, buf_size);
 }
 
+#[test]
+fn ipv6() {
+    let server_addr = {
+        let ipv6_addr = Ipv6Addr::LOCALHOST;
+        let port = 9998;
+        Ipv6SocketAddr::new(ipv6_addr, port)
+    };
+    let num_clients = 8;
+    let total_data = 8 * 1024 * 1024;
+    let buf_size = 4 * 1024;
+    run_echo_server_and_clients(server_addr, num_clients, total_data, buf_size);
+}
+
 #[test]
 fn unix() {
     let server_addr = {
@@ -28,6 +43,289 @@ fn unix() {
     run_echo_server_and_clients(server_addr, num_clients, total_data, buf_size);
 }
 
+#[test]
+fn nonblocking() {
+    use async_io::event::{Events, Poller};
+    use async_io::file::StatusFlags;
+    use async_io::socket::sockopt::GetSockError;
+    use errno::prelude::*;
+    use host_socket::StreamSocket;
+
+    use self::runtime::SocketRuntime;
+
+    async fn wait_for<A: Addr + 'static>(socket: &StreamSocket<A, SocketRuntime>, mask: Events) {
+        let mut poller = Poller::new();
+        while socket.poll(mask, Some(&mut poller)).is_empty() {
+            poller.wait().await;
+        }
+    }
+
+    runtime::SocketRuntime::init(2);
+
+    let server_addr = Ipv4SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1), 9997);
+    async_rt::task::block_on(async move {
+        let listener: StreamSocket<Ipv4SocketAddr, SocketRuntime> = StreamSocket::new().unwrap();
+        listener.bind(&server_addr).unwrap();
+        listener.listen(2).unwrap();
+        listener.set_status_flags(StatusFlags::O_NONBLOCK).unwrap();
+        assert!(listener.accept().await.has_errno(EAGAIN));
+
+        // A non-blocking connect completes asynchronously
+        let client: StreamSocket<Ipv4SocketAddr, SocketRuntime> = StreamSocket::new().unwrap();
+        client.set_status_flags(StatusFlags::O_NONBLOCK).unwrap();
+        assert!(client.connect(&server_addr).await.has_errno(EINPROGRESS));
+        wait_for(&client, Events::OUT).await;
+        let mut get_sock_error = GetSockError::new(());
+        client.ioctl(&mut get_sock_error).unwrap();
+        assert_eq!(get_sock_error.output(), Some(&None));
+        assert!(client.connect(&server_addr).await.has_errno(EISCONN));
+
+        wait_for(&listener, Events::IN).await;
+        let server = listener.accept().await.unwrap();
+        // The accepted socket does not inherit the non-blocking flag
+        assert!(server.status_flags().is_empty());
+
+        // Reads return EAGAIN until there are data available
+        let mut buf = [0u8; 5];
+        assert!(client.read(&mut buf).await.has_errno(EAGAIN));
+        server.write(b"hello").await.unwrap();
+        wait_for(&client, Events::IN).await;
+        assert_eq!(client.read(&mut buf).await.unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+    });
+}
+
+#[test]
+fn cork() {
+    use async_io::socket::sockopt::SetTcpCork;
+    use host_socket::StreamSocket;
+
+    use self::runtime::SocketRuntime;
+    use self::stream_socket_ext::StreamSocketExt;
+
+    runtime::SocketRuntime::init(2);
+
+    let server_addr = Ipv4SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1), 9996);
+    async_rt::task::block_on(async move {
+        let listener: StreamSocket<Ipv4SocketAddr, SocketRuntime> = StreamSocket::new().unwrap();
+        listener.bind(&server_addr).unwrap();
+        listener.listen(2).unwrap();
+
+        let client: StreamSocket<Ipv4SocketAddr, SocketRuntime> = StreamSocket::new().unwrap();
+        client.connect(&server_addr).await.unwrap();
+        let server = listener.accept().await.unwrap();
+
+        // Many small writes to a corked socket are coalesced into a few sends
+        let num_writes = 1000;
+        let msg = b"0123456789";
+        client.ioctl(&mut SetTcpCork::new(true)).unwrap();
+        for _ in 0..num_writes {
+            client.write_exact(msg).await;
+        }
+        client.ioctl(&mut SetTcpCork::new(false)).unwrap();
+
+        let mut buf = vec![0u8; num_writes * msg.len()];
+        server.read_exact(&mut buf).await;
+        assert!(buf.chunks(msg.len()).all(|chunk| chunk == msg));
+
+        let stats = client.send_stats();
+        assert_eq!(stats.num_bytes, buf.len() as u64);
+        assert!(stats.num_submissions < 10);
+    });
+}
+
+#[test]
+fn timeout() {
+    use std::time::{Duration, Instant};
+
+    use async_io::socket::sockopt::{GetRecvTimeout, SetRecvTimeout};
+    use errno::prelude::*;
+    use host_socket::StreamSocket;
+
+    use self::runtime::SocketRuntime;
+
+    runtime::SocketRuntime::init(2);
+
+    let server_addr = Ipv4SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1), 9995);
+    async_rt::task::block_on(async move {
+        let listener: StreamSocket<Ipv4SocketAddr, SocketRuntime> = StreamSocket::new().unwrap();
+        listener.bind(&server_addr).unwrap();
+        listener.listen(2).unwrap();
+
+        // Accepts time out, too
+        let timeout = Duration::from_millis(100);
+        listener
+            .ioctl(&mut SetRecvTimeout::new(Some(timeout)))
+            .unwrap();
+        let start = Instant::now();
+        assert!(listener.accept().await.has_errno(EAGAIN));
+        assert!(start.elapsed() >= timeout);
+
+        let client: StreamSocket<Ipv4SocketAddr, SocketRuntime> = StreamSocket::new().unwrap();
+        client.connect(&server_addr).await.unwrap();
+        let server = listener.accept().await.unwrap();
+
+        client
+            .ioctl(&mut SetRecvTimeout::new(Some(timeout)))
+            .unwrap();
+        let mut get_recv_timeout = GetRecvTimeout::new(());
+        client.ioctl(&mut get_recv_timeout).unwrap();
+        assert_eq!(get_recv_timeout.output(), Some(&Some(timeout)));
+
+        // A read times out if no data arrive in time
+        let mut buf = [0u8; 5];
+        let start = Instant::now();
+        assert!(client.read(&mut buf).await.has_errno(EAGAIN));
+        assert!(start.elapsed() >= timeout);
+
+        // But not if the data arrive in time
+        server.write(b"hello").await.unwrap();
+        assert_eq!(client.read(&mut buf).await.unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+    });
+}
+
+#[test]
+fn send_timeout() {
+    use std::time::Duration;
+
+    use async_io::socket::sockopt::{SetSendBufSize, SetSendTimeout};
+    use errno::prelude::*;
+    use host_socket::StreamSocket;
+
+    use self::runtime::SocketRuntime;
+    use self::stream_socket_ext::StreamSocketExt;
+
+    runtime::SocketRuntime::init(2);
+
+    let server_addr = Ipv4SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1), 9992);
+    async_rt::task::block_on(async move {
+        let listener: StreamSocket<Ipv4SocketAddr, SocketRuntime> = StreamSocket::new().unwrap();
+        listener.bind(&server_addr).unwrap();
+        listener.listen(2).unwrap();
+
+        let client: StreamSocket<Ipv4SocketAddr, SocketRuntime> = StreamSocket::new().unwrap();
+        client.connect(&server_addr).await.unwrap();
+        let server = listener.accept().await.unwrap();
+
+        // Keep writing until the send times out as the server does not read
+        client.ioctl(&mut SetSendBufSize::new(4096)).unwrap();
+        client
+            .ioctl(&mut SetSendTimeout::new(Some(Duration::from_millis(100))))
+            .unwrap();
+        let msg = [b'x'; 4096];
+        let mut total_sent = 0;
+        loop {
+            match client.write(&msg).await {
+                Ok(nbytes) => total_sent += nbytes,
+                Err(e) => {
+                    assert_eq!(e.errno(), EAGAIN);
+                    break;
+                }
+            }
+        }
+
+        // The data accepted before the async send was cancelled are not lost,
+        // but sent along with the following data
+        client.ioctl(&mut SetSendTimeout::new(None)).unwrap();
+        let server = async_rt::task::spawn(async move {
+            let mut buf = vec![0u8; total_sent + 5];
+            server.read_exact(&mut buf).await;
+            assert!(buf[..total_sent].iter().all(|b| *b == b'x'));
+            assert_eq!(&buf[total_sent..], b"hello");
+        });
+        client.write_exact(b"hello").await;
+        server.await;
+    });
+}
+
+#[test]
+fn accept() {
+    use async_io::socket::sockopt::SetRecvTimeout;
+    use errno::prelude::*;
+    use host_socket::StreamSocket;
+    use std::time::Duration;
+
+    use self::runtime::SocketRuntime;
+
+    runtime::SocketRuntime::init(2);
+
+    let server_addr = Ipv4SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1), 9994);
+    async_rt::task::block_on(async move {
+        let listener: StreamSocket<Ipv4SocketAddr, SocketRuntime> = StreamSocket::new().unwrap();
+        listener.bind(&server_addr).unwrap();
+        let backlog = 2;
+        listener.listen(backlog).unwrap();
+
+        // The connections within the backlog complete before being accepted
+        let mut clients = Vec::new();
+        for _ in 0..backlog {
+            let client: StreamSocket<Ipv4SocketAddr, SocketRuntime> = StreamSocket::new().unwrap();
+            client.connect(&server_addr).await.unwrap();
+            clients.push(client);
+        }
+
+        // Each accepted socket is connected with one of the clients
+        let mut client_addrs: Vec<_> = clients.iter().map(|c| c.addr().unwrap()).collect();
+        for _ in 0..backlog {
+            let server = listener.accept().await.unwrap();
+            let peer_addr = server.peer_addr().unwrap();
+            let pos = client_addrs.iter().position(|addr| *addr == peer_addr);
+            client_addrs.swap_remove(pos.expect("the peer is not a client"));
+        }
+
+        // No more connections to accept
+        listener
+            .ioctl(&mut SetRecvTimeout::new(Some(Duration::from_millis(100))))
+            .unwrap();
+        assert!(listener.accept().await.has_errno(EAGAIN));
+    });
+}
+
+#[test]
+fn concurrent_accepters() {
+    use async_rt::task::JoinHandle;
+    use host_socket::StreamSocket;
+
+    use self::runtime::SocketRuntime;
+
+    runtime::SocketRuntime::init(2);
+
+    let server_addr = Ipv4SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1), 9993);
+    async_rt::task::block_on(async move {
+        let listener: Arc<StreamSocket<Ipv4SocketAddr, SocketRuntime>> =
+            Arc::new(StreamSocket::new().unwrap());
+        listener.bind(&server_addr).unwrap();
+        listener.listen(16).unwrap();
+
+        // The accepters block on the ready queues of different vCPUs before the
+        // connections arrive. Each of them must be woken up to accept one.
+        let num_accepters = 16;
+        let accepters: Vec<JoinHandle<()>> = (0..num_accepters)
+            .map(|_| {
+                let listener = listener.clone();
+                async_rt::task::spawn(async move {
+                    let server = listener.accept().await.unwrap();
+                    let mut buf = [0u8; 5];
+                    server.read(&mut buf).await.unwrap();
+                    assert_eq!(&buf, b"hello");
+                })
+            })
+            .collect();
+
+        let mut clients = Vec::new();
+        for _ in 0..num_accepters {
+            let client: StreamSocket<Ipv4SocketAddr, SocketRuntime> = StreamSocket::new().unwrap();
+            client.connect(&server_addr).await.unwrap();
+            client.write(b"hello").await.unwrap();
+            clients.push(client);
+        }
+        for accepter in accepters {
+            accepter.await;
+        }
+    });
+}
+
 fn run_echo_server_and_clients<A: Addr + 'static>(
     // The server address
     server_addr: A,
@@ -116,6 +414,14 @@ mod runtime {
         fn io_uring() -> &'static IoUring {
             &*IO_URING
         }
+
+        fn num_vcpus() -> usize {
+            async_rt::executor::parallelism() as usize
+        }
+
+        fn vcpu_id() -> usize {
+            async_rt::executor::current_thread_id().unwrap_or(0) as usize
+        }
     }
 }
 
@@ -264,6 +570,7 @@ mod client {
             let random_base64 = RandomBase64::new();
             let client = Client {
                 addr,
+                total_data: remain_data,
                 remain_data,
                 buf_size,
                 socket,
@@ -275,6 +582,7 @@ mod client {
 
     pub struct Client<A: Addr + 'static> {
         addr: A,
+        total_data: usize,
         remain_data: usize,
         buf_size: usize,
         socket: StreamSocket<A, SocketRuntime>,
@@ -305,6 +613,10 @@ mod client {
 
                 self.remain_data -= msg_len;
             }
+
+            // The echoed data have been read, so all data must have been sent
+            let stats = self.socket.send_stats();
+            assert_eq!(stats.num_bytes, self.total_data as u64);
             Ok(())
         }
 
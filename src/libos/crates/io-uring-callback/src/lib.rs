@@ -377,6 +377,26 @@ impl IoUring {
         self.push_entry(entry, callback)
     }
 
+    /// Push a fallocate request into the submission queue of the io_uring.
+    ///
+    /// # Safety
+    ///
+    /// See the safety section of the `IoUring`.
+    pub unsafe fn fallocate(
+        &self,
+        fd: Fd,
+        offset: libc::off_t,
+        len: libc::off_t,
+        mode: i32,
+        callback: impl FnOnce(i32) + Send + 'static,
+    ) -> IoHandle {
+        let entry = opcode::Fallocate::new(fd, len)
+            .offset(offset)
+            .mode(mode)
+            .build();
+        self.push_entry(entry, callback)
+    }
+
     /// Push a timeout request into the submission queue of the io_uring.
     ///
     /// # Safety
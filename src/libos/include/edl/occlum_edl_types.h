@@ -21,4 +21,20 @@ typedef struct {
     unsigned long fds_bits[FD_SETSIZE / 8 / sizeof(long)];
 } fd_set;
 
+/*
+ * An address of a network interface, or the interface itself if the family is
+ * AF_PACKET.
+ */
+#define OCCLUM_IFNAMSIZ 16
+struct occlum_ifaddr {
+    char        name[OCCLUM_IFNAMSIZ];
+    uint32_t    index;
+    uint32_t    flags;          /* IFF_* */
+    uint32_t    mtu;
+    uint8_t     hw_addr[6];     /* only valid if the family is AF_PACKET */
+    uint16_t    family;         /* AF_PACKET, AF_INET or AF_INET6 */
+    uint8_t     addr[16];       /* only valid if the family is AF_INET or AF_INET6 */
+    uint32_t    prefix_len;
+};
+
 #endif /* __OCCLUM_EDL_TYPES_H__ */
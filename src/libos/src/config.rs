@@ -1,6 +1,7 @@
 use std::collections::HashSet;
 use std::ffi::{CStr, CString};
 use std::io::Read;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use std::sgxfs::SgxFile;
 use std::untrusted::path::PathEx;
@@ -70,7 +71,7 @@ fn conf_get_hardcoded_file_mac() -> sgx_aes_gcm_128bit_tag_t {
     mac
 }
 
-fn parse_mac(mac_str: &str) -> Result<sgx_aes_gcm_128bit_tag_t> {
+pub fn parse_mac(mac_str: &str) -> Result<sgx_aes_gcm_128bit_tag_t> {
     let bytes_str_vec = {
         let bytes_str_vec: Vec<&str> = mac_str.split("-").collect();
         if bytes_str_vec.len() != 16 {
@@ -93,6 +94,8 @@ pub struct Config {
     pub env: ConfigEnv,
     pub entry_points: Vec<PathBuf>,
     pub mount: Vec<ConfigMount>,
+    pub mount_policy: ConfigMountPolicy,
+    pub network: ConfigNetwork,
 }
 
 #[derive(Debug)]
@@ -138,6 +141,44 @@ pub struct ConfigMountOptions {
     pub mac: Option<sgx_aes_gcm_128bit_tag_t>,
     pub layers: Option<Vec<ConfigMount>>,
     pub temporary: bool,
+    /// The devices of DevFS. The default devices are available if not given.
+    pub devices: Option<Vec<String>>,
+}
+
+/// The policy of the mounts at runtime, i.e., via the mount system call.
+///
+/// A file system can be mounted at runtime only if its target is under one of
+/// the targets and its source on the host, if any, is under one of the sources.
+/// So no mount is allowed at runtime by default.
+#[derive(Debug)]
+pub struct ConfigMountPolicy {
+    pub targets: Vec<PathBuf>,
+    pub sources: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct ConfigNetwork {
+    /// The synthetic network interfaces reported to the LibOS processes, which
+    /// are used instead of the host interfaces if given.
+    pub interfaces: Option<Vec<ConfigNetworkInterface>>,
+    /// The min size of the send or receive buffer of a connected socket.
+    pub socket_buf_min_size: Option<usize>,
+    /// The max size of the send or receive buffer of a connected socket, unless
+    /// set via `SO_SNDBUF` or `SO_RCVBUF`.
+    pub socket_buf_max_size: Option<usize>,
+    /// The max total size of the socket buffers, which reside in untrusted memory.
+    pub socket_buf_budget: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct ConfigNetworkInterface {
+    pub name: String,
+    pub index: u32,
+    pub mtu: u32,
+    pub mac: [u8; 6],
+    pub loopback: bool,
+    /// The IP addresses along with their prefix lengths.
+    pub addrs: Vec<(IpAddr, u8)>,
 }
 
 impl Config {
@@ -163,12 +204,16 @@ impl Config {
             }
             mount
         };
+        let mount_policy = ConfigMountPolicy::from_input(&input.mount_policy)?;
+        let network = ConfigNetwork::from_input(&input.network)?;
         Ok(Config {
             resource_limits,
             process,
             env,
             entry_points,
             mount,
+            mount_policy,
+            network,
         })
     }
 }
@@ -265,10 +310,157 @@ impl ConfigMountOptions {
             mac,
             layers,
             temporary: input.temporary,
+            devices: input.devices.clone(),
         })
     }
 }
 
+impl ConfigMountPolicy {
+    fn from_input(input: &InputConfigMountPolicy) -> Result<ConfigMountPolicy> {
+        let mut targets = Vec::new();
+        for target in &input.targets {
+            let target = PathBuf::from(target);
+            if !target.is_absolute() {
+                return_errno!(EINVAL, "Target must be an absolute path");
+            }
+            targets.push(target);
+        }
+        // Just like the sources of mounts, the sources are relative to the instance dir
+        let sources = input
+            .sources
+            .iter()
+            .filter_map(|source| {
+                let path = unsafe { PathBuf::from(&INSTANCE_DIR) };
+                path.join(source).canonicalize().ok()
+            })
+            .collect();
+        Ok(ConfigMountPolicy { targets, sources })
+    }
+}
+
+impl ConfigNetwork {
+    fn from_input(input: &InputConfigNetwork) -> Result<ConfigNetwork> {
+        let interfaces = match &input.interfaces {
+            Some(input_interfaces) => {
+                let mut interfaces = Vec::new();
+                for (i, input_interface) in input_interfaces.iter().enumerate() {
+                    // Interface indexes start from 1
+                    let default_index = i as u32 + 1;
+                    interfaces.push(ConfigNetworkInterface::from_input(
+                        input_interface,
+                        default_index,
+                    )?);
+                }
+                Some(interfaces)
+            }
+            None => None,
+        };
+        let parse_size = |size_str: &Option<String>| -> Result<Option<usize>> {
+            size_str.as_deref().map(parse_memory_size).transpose()
+        };
+        let socket_buf_min_size = parse_size(&input.socket_buf_min_size)?;
+        let socket_buf_max_size = parse_size(&input.socket_buf_max_size)?;
+        let socket_buf_budget = parse_size(&input.socket_buf_budget)?;
+        if socket_buf_min_size.map_or(false, |size| size < 2)
+            || socket_buf_max_size.map_or(false, |size| size < 2)
+        {
+            return_errno!(EINVAL, "socket buffer sizes are too small");
+        }
+        if let (Some(min_size), Some(max_size)) = (socket_buf_min_size, socket_buf_max_size) {
+            if min_size > max_size {
+                return_errno!(EINVAL, "the min socket buffer size exceeds the max one");
+            }
+        }
+        Ok(ConfigNetwork {
+            interfaces,
+            socket_buf_min_size,
+            socket_buf_max_size,
+            socket_buf_budget,
+        })
+    }
+}
+
+impl ConfigNetworkInterface {
+    fn from_input(
+        input: &InputConfigNetworkInterface,
+        default_index: u32,
+    ) -> Result<ConfigNetworkInterface> {
+        // The same as IFNAMSIZ of Linux, including the trailing null
+        const MAX_NAME_LEN: usize = 16 - 1;
+        if input.name.is_empty() || input.name.len() > MAX_NAME_LEN {
+            return_errno!(EINVAL, "invalid interface name");
+        }
+        let index = input.index.unwrap_or(default_index);
+        if index == 0 {
+            return_errno!(EINVAL, "interface index must be positive");
+        }
+        let mtu = input
+            .mtu
+            .unwrap_or(if input.loopback { 65536 } else { 1500 });
+        let mac = match &input.mac {
+            Some(mac_str) => parse_hw_addr(mac_str)?,
+            None => [0; 6],
+        };
+        let addrs = {
+            let mut addrs = Vec::new();
+            for addr_str in &input.addrs {
+                addrs.push(parse_ip_prefix(addr_str)?);
+            }
+            addrs
+        };
+        Ok(ConfigNetworkInterface {
+            name: input.name.clone(),
+            index,
+            mtu,
+            mac,
+            loopback: input.loopback,
+            addrs,
+        })
+    }
+}
+
+/// Parse a hardware address like `02:42:ac:11:00:02`.
+fn parse_hw_addr(hw_addr_str: &str) -> Result<[u8; 6]> {
+    let bytes_str_vec: Vec<&str> = hw_addr_str.split(":").collect();
+    if bytes_str_vec.len() != 6 {
+        return_errno!(
+            EINVAL,
+            "The length or format of hardware address is invalid"
+        );
+    }
+
+    let mut hw_addr = [0u8; 6];
+    for (byte_i, byte_str) in bytes_str_vec.iter().enumerate() {
+        hw_addr[byte_i] = u8::from_str_radix(byte_str, 16).map_err(|e| errno!(e))?;
+    }
+    Ok(hw_addr)
+}
+
+/// Parse an IP address with a prefix length like `192.168.0.2/24` or `fe80::1/64`.
+///
+/// If the prefix length is omitted, the address is considered a host address.
+fn parse_ip_prefix(prefix_str: &str) -> Result<(IpAddr, u8)> {
+    let mut split = prefix_str.splitn(2, '/');
+    let ip_addr: IpAddr = split
+        .next()
+        .unwrap()
+        .trim()
+        .parse()
+        .map_err(|_| errno!(EINVAL, "invalid IP address"))?;
+    let max_prefix_len = if ip_addr.is_ipv4() { 32 } else { 128 };
+    let prefix_len = match split.next() {
+        Some(len_str) => len_str
+            .trim()
+            .parse::<u8>()
+            .map_err(|_| errno!(EINVAL, "invalid prefix length"))?,
+        None => max_prefix_len,
+    };
+    if prefix_len > max_prefix_len {
+        return_errno!(EINVAL, "the prefix length is too large");
+    }
+    Ok((ip_addr, prefix_len))
+}
+
 fn parse_memory_size(mem_str: &str) -> Result<usize> {
     const UNIT2FACTOR: [(&str, usize); 5] = [
         ("KB", 1024),
@@ -307,6 +499,10 @@ struct InputConfig {
     pub entry_points: Vec<String>,
     #[serde(default)]
     pub mount: Vec<InputConfigMount>,
+    #[serde(default)]
+    pub mount_policy: InputConfigMountPolicy,
+    #[serde(default)]
+    pub network: InputConfigNetwork,
 }
 
 #[derive(Deserialize, Debug)]
@@ -403,4 +599,44 @@ struct InputConfigMountOptions {
     pub layers: Option<Vec<InputConfigMount>>,
     #[serde(default)]
     pub temporary: bool,
+    #[serde(default)]
+    pub devices: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct InputConfigMountPolicy {
+    #[serde(default)]
+    pub targets: Vec<String>,
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct InputConfigNetwork {
+    #[serde(default)]
+    pub interfaces: Option<Vec<InputConfigNetworkInterface>>,
+    #[serde(default)]
+    pub socket_buf_min_size: Option<String>,
+    #[serde(default)]
+    pub socket_buf_max_size: Option<String>,
+    #[serde(default)]
+    pub socket_buf_budget: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct InputConfigNetworkInterface {
+    pub name: String,
+    #[serde(default)]
+    pub index: Option<u32>,
+    #[serde(default)]
+    pub mtu: Option<u32>,
+    #[serde(default)]
+    pub mac: Option<String>,
+    #[serde(default)]
+    pub loopback: bool,
+    #[serde(default)]
+    pub addrs: Vec<String>,
 }
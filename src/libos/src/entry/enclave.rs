@@ -81,6 +81,8 @@ pub extern "C" fn occlum_ecall_init(
 
         super::interrupt::init();
 
+        crate::net::init();
+
         info!("num_vcpus = {:?}", num_vcpus);
         async_rt::config::set_parallelism(num_vcpus);
         async_rt::task::spawn(async {
@@ -254,6 +256,8 @@ fn do_new_process(
 ) -> Result<pid_t> {
     validate_program_path(program_path)?;
 
+    crate::fs::set_host_tty_fds(host_stdio_fds);
+
     let file_actions = Vec::new();
     let current = &process::IDLE;
     let program_path_str = program_path.to_str().unwrap();
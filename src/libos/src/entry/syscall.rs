@@ -9,12 +9,13 @@ use std::ptr;
 
 use crate::fs::{
     do_access, do_chdir, do_chmod, do_chown, do_close, do_dup, do_dup2, do_dup3, do_eventfd,
-    do_eventfd2, do_faccessat, do_fchmod, do_fchmodat, do_fchown, do_fchownat, do_fdatasync,
-    do_fstat, do_fstatat, do_fsync, do_ftruncate, do_getcwd, do_lchown, do_link, do_linkat,
-    do_lseek, do_lstat, do_mkdir, do_mkdirat, do_open, do_openat, do_pipe, do_pipe2, do_pread,
+    do_eventfd2, do_faccessat, do_fadvise64, do_fallocate, do_fchmod, do_fchmodat, do_fchown,
+    do_fchownat, do_fcntl, do_fdatasync, do_flock, do_fstat, do_fstatat, do_fsync, do_ftruncate,
+    do_getcwd, do_getdents, do_getdents64, do_ioctl, do_lchown, do_link, do_linkat, do_lseek,
+    do_lstat, do_mkdir, do_mkdirat, do_mount, do_open, do_openat, do_pipe, do_pipe2, do_pread,
     do_pwrite, do_read, do_readlink, do_readlinkat, do_readv, do_rename, do_renameat, do_rmdir,
-    do_stat, do_symlink, do_symlinkat, do_sync, do_truncate, do_unlink, do_unlinkat, do_write,
-    do_writev, iovec_t, FileDesc, FileRef, StatBuf,
+    do_sendfile, do_stat, do_symlink, do_symlinkat, do_sync, do_truncate, do_umount2, do_unlink,
+    do_unlinkat, do_write, do_writev, iovec_t, FileDesc, FileRef, StatBuf,
 };
 /*
 use crate::fs::{
@@ -29,7 +30,11 @@ use crate::fs::{
 };
 */
 use crate::misc::{resource_t, rlimit_t, sysinfo_t, utsname_t};
-use crate::net::{do_accept, do_accept4, do_bind, do_connect, do_listen, do_socket};
+use crate::net::{
+    do_accept, do_accept4, do_bind, do_connect, do_getpeername, do_getsockname, do_getsockopt,
+    do_listen, do_recvfrom, do_recvmmsg, do_recvmsg, do_sendmmsg, do_sendmsg, do_sendto,
+    do_setsockopt, do_shutdown, do_socket, do_socketpair, mmsghdr, msghdr, msghdr_mut,
+};
 /*
 use crate::net::{
     do_accept, do_accept4, do_bind, do_connect, do_epoll_create, do_epoll_create1, do_epoll_ctl,
@@ -176,7 +181,12 @@ macro_rules! process_syscall_table_with_callback {
             (Readv = 19) => do_readv(fd: FileDesc, iov: *mut iovec_t, count: i32),
             (Pread64 = 17) => do_pread(fd: FileDesc, buf: *mut u8, size: usize, offset: off_t),
             (Lseek = 8) => do_lseek(fd: FileDesc, offset: off_t, whence: i32),
-            //(Ioctl = 16) => do_ioctl(fd: FileDesc, cmd: u32, argp: *mut u8),
+            (Ioctl = 16) => do_ioctl(fd: FileDesc, cmd: u32, argp: *mut u8),
+            (Sendfile = 40) => do_sendfile(out_fd: FileDesc, in_fd: FileDesc, offset_ptr: *mut off_t, count: usize),
+            (Fcntl = 72) => do_fcntl(fd: FileDesc, cmd: u32, arg: u64),
+            (Flock = 73) => do_flock(fd: FileDesc, operation: u32),
+            (Getdents = 78) => do_getdents(fd: FileDesc, buf: *mut u8, buf_size: usize),
+            (Getdents64 = 217) => do_getdents64(fd: FileDesc, buf: *mut u8, buf_size: usize),
 
             (Rmdir = 84) => do_rmdir(path: *const i8),
             (Link = 86) => do_link(oldpath: *const i8, newpath: *const i8),
@@ -205,6 +215,8 @@ macro_rules! process_syscall_table_with_callback {
             (Renameat = 264) => do_renameat(olddirfd: i32, oldpath: *const i8, newdirfd: i32, newpath: *const i8),
             (Truncate = 76) => do_truncate(path: *const i8, len: usize),
             (Ftruncate = 77) => do_ftruncate(fd: FileDesc, len: usize),
+            (Fallocate = 285) => do_fallocate(fd: FileDesc, mode: i32, offset: off_t, len: off_t),
+            (Fadvise64 = 221) => do_fadvise64(fd: FileDesc, offset: off_t, len: off_t, advice: i32),
             (Chown = 92) => do_chown(path: *const i8, uid: u32, gid: u32),
             (Fchown = 93) => do_fchown(fd: FileDesc, uid: u32, gid: u32),
             (Lchown = 94) => do_lchown(path: *const i8, uid: u32, gid: u32),
@@ -214,6 +226,8 @@ macro_rules! process_syscall_table_with_callback {
             (Symlink = 88) => do_symlink(target: *const i8, link_path: *const i8),
             (Symlinkat = 266) => do_symlinkat(target: *const i8, new_dirfd: i32, link_path: *const i8),
             (Sync = 162) => do_sync(),
+            (Mount = 165) => do_mount(source: *const i8, target: *const i8, fs_type: *const i8, flags: u32, options: *const i8),
+            (Umount2 = 166) => do_umount2(target: *const i8, flags: u32),
 
             (Pipe = 22) => do_pipe(fds_u: *mut i32),
             (Pipe2 = 293) => do_pipe2(fds_u: *mut i32, flags: u32),
@@ -225,8 +239,20 @@ macro_rules! process_syscall_table_with_callback {
             (Accept4 = 288) => do_accept4(fd: c_int, addr: *mut libc::sockaddr, addr_len: *mut libc::socklen_t, flags: c_int),
             (Bind = 49) => do_bind(fd: c_int, addr: *const libc::sockaddr, addr_len: libc::socklen_t),
             (Connect = 42) => do_connect(fd: c_int, addr: *const libc::sockaddr, addr_len: libc::socklen_t),
+            (Getpeername = 52) => do_getpeername(fd: c_int, addr: *mut libc::sockaddr, addr_len: *mut libc::socklen_t),
+            (Getsockname = 51) => do_getsockname(fd: c_int, addr: *mut libc::sockaddr, addr_len: *mut libc::socklen_t),
+            (Getsockopt = 55) => do_getsockopt(fd: c_int, level: c_int, optname: c_int, optval: *mut c_void, optlen: *mut libc::socklen_t),
             (Listen = 50) => do_listen(fd: c_int, backlog: c_int),
+            (Recvfrom = 45) => do_recvfrom(fd: c_int, base: *mut c_void, len: size_t, flags: c_int, addr: *mut libc::sockaddr, addr_len: *mut libc::socklen_t),
+            (Recvmmsg = 299) => do_recvmmsg(fd: c_int, msgvec: *mut mmsghdr, vlen: c_uint, flags_c: c_int, timeout: *const timespec_t),
+            (Recvmsg = 47) => do_recvmsg(fd: c_int, msg_mut_ptr: *mut msghdr_mut, flags_c: c_int),
+            (Sendmmsg = 307) => do_sendmmsg(fd: c_int, msgvec: *mut mmsghdr, vlen: c_uint, flags_c: c_int),
+            (Sendmsg = 46) => do_sendmsg(fd: c_int, msg_ptr: *const msghdr, flags_c: c_int),
+            (Sendto = 44) => do_sendto(fd: c_int, base: *const c_void, len: size_t, flags: c_int, addr: *const libc::sockaddr, addr_len: libc::socklen_t),
+            (Setsockopt = 54) => do_setsockopt(fd: c_int, level: c_int, optname: c_int, optval: *const c_void, optlen: libc::socklen_t),
+            (Shutdown = 48) => do_shutdown(fd: c_int, how: c_int),
             (Socket = 41) => do_socket(domain: c_int, socket_type: c_int, protocol: c_int),
+            (Socketpair = 53) => do_socketpair(domain: c_int, socket_type: c_int, protocol: c_int, sv: *mut c_int),
 
             (Poll = 7) => do_poll(fds: *mut libc::pollfd, nfds: libc::nfds_t, timeout: c_int),
 
@@ -0,0 +1,63 @@
+//! The runtime of `AsyncFile`, which backs the regular files of HostFS.
+//!
+//! All host files share a page cache in untrusted memory. The I/O between the
+//! page cache and the host files is done with the io_uring of the LibOS, so
+//! reads and writes on host files never block vCPUs.
+
+use std::time::Duration;
+
+use async_file::{
+    AsyncFileRt, Flusher, Page, PageCache, PageCacheStats, WritebackConfig, WritebackStats,
+};
+use io_uring_callback::IoUring;
+
+pub struct HostFileRt;
+
+/// The number of pages in the page cache (i.e., 64MB).
+const PAGE_CACHE_SIZE: usize = 16 * 1024;
+
+lazy_static! {
+    static ref PAGE_CACHE: PageCache = PageCache::with_capacity(PAGE_CACHE_SIZE);
+    static ref FLUSHER: Flusher<HostFileRt> = Flusher::with_config(WritebackConfig {
+        // A quarter and a half of the page cache
+        dirty_background_bytes: PAGE_CACHE_SIZE / 4 * Page::size(),
+        dirty_bytes: PAGE_CACHE_SIZE / 2 * Page::size(),
+        ..WritebackConfig::default()
+    });
+}
+
+impl AsyncFileRt for HostFileRt {
+    fn io_uring() -> &'static IoUring {
+        &*crate::io_uring::SINGLETON
+    }
+
+    fn page_cache() -> &'static PageCache {
+        &PAGE_CACHE
+    }
+
+    fn flusher() -> &'static Flusher<Self> {
+        &FLUSHER
+    }
+
+    fn now() -> Duration {
+        crate::time::up_time::get().unwrap_or_default()
+    }
+}
+
+impl HostFileRt {
+    /// Free up to `max_pages` pages of the page cache that are neither in use
+    /// nor dirty, returning the number of pages freed.
+    ///
+    /// This is to be called under memory pressure.
+    pub fn shrink_page_cache(max_pages: usize) -> usize {
+        PAGE_CACHE.shrink(max_pages)
+    }
+
+    pub fn page_cache_stats() -> PageCacheStats {
+        PAGE_CACHE.stats()
+    }
+
+    pub fn writeback_stats() -> WritebackStats {
+        FLUSHER.stats()
+    }
+}
@@ -0,0 +1,68 @@
+use super::*;
+
+/// An opened device.
+#[derive(Debug)]
+pub struct DevFile {
+    device: Arc<dyn Device>,
+    access_mode: AccessMode,
+    status_flags: RwLock<StatusFlags>,
+}
+
+impl DevFile {
+    pub fn new(
+        device: Arc<dyn Device>,
+        access_mode: AccessMode,
+        status_flags: StatusFlags,
+    ) -> Self {
+        Self {
+            device,
+            access_mode,
+            status_flags: RwLock::new(status_flags),
+        }
+    }
+}
+
+impl File for DevFile {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        if !self.access_mode.readable() {
+            return_errno!(EBADF, "File not readable");
+        }
+        self.device.read(buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        if !self.access_mode.writable() {
+            return_errno!(EBADF, "File not writable");
+        }
+        self.device.write(buf)
+    }
+
+    fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
+        self.device.poll(mask, poller)
+    }
+
+    fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        self.device.ioctl(cmd)
+    }
+
+    fn access_mode(&self) -> AccessMode {
+        self.access_mode
+    }
+
+    fn status_flags(&self) -> StatusFlags {
+        *self.status_flags.read().unwrap()
+    }
+
+    fn set_status_flags(&self, new_status_flags: StatusFlags) -> Result<()> {
+        let mut status_flags = self.status_flags.write().unwrap();
+        // Currently, F_SETFL can change only the O_APPEND,
+        // O_ASYNC, O_NOATIME, and O_NONBLOCK flags
+        let valid_flags_mask = StatusFlags::O_APPEND
+            | StatusFlags::O_ASYNC
+            | StatusFlags::O_NOATIME
+            | StatusFlags::O_NONBLOCK;
+        status_flags.remove(valid_flags_mask);
+        status_flags.insert(new_status_flags & valid_flags_mask);
+        Ok(())
+    }
+}
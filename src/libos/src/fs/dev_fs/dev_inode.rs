@@ -0,0 +1,78 @@
+use super::*;
+use rcore_fs_mountfs::MNode;
+
+/// The inode of a device in DevFS.
+#[derive(Debug)]
+pub struct DevINode {
+    device: Arc<dyn Device>,
+    id: usize,
+}
+
+impl DevINode {
+    pub fn new(device: Arc<dyn Device>) -> Arc<dyn INode> {
+        let id = alloc_inode_id();
+        Arc::new(Self { device, id })
+    }
+
+    pub fn from_inode(inode: &dyn INode) -> Option<&Self> {
+        // DevFS is mounted, so its inodes are wrapped by MountFS
+        let inode = match inode.downcast_ref::<MNode>() {
+            Some(mnode) => &*mnode.inode,
+            None => inode,
+        };
+        inode.downcast_ref::<Self>()
+    }
+
+    /// Open the device as a file.
+    pub fn open(&self, access_mode: AccessMode, status_flags: StatusFlags) -> FileRef {
+        FileRef::new_file(DevFile::new(
+            Arc::clone(&self.device),
+            access_mode,
+            status_flags,
+        ))
+    }
+}
+
+impl INode for DevINode {
+    // Devices are usually accessed through the files returned by `open`, but
+    // the I/O on the inodes still works, ignoring the offsets.
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> vfs::Result<usize> {
+        self.device.read(buf).map_err(|_| FsError::DeviceError)
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> vfs::Result<usize> {
+        self.device.write(buf).map_err(|_| FsError::DeviceError)
+    }
+
+    fn poll(&self) -> vfs::Result<vfs::PollStatus> {
+        let events = self.device.poll(Events::IN | Events::OUT, None);
+        Ok(vfs::PollStatus {
+            read: events.contains(Events::IN),
+            write: events.contains(Events::OUT),
+            error: events.contains(Events::ERR),
+        })
+    }
+
+    fn metadata(&self) -> vfs::Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: self.id,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: vfs::FileType::CharDevice,
+            mode: self.device.mode(),
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
@@ -3,43 +3,12 @@ use super::*;
 #[derive(Debug)]
 pub struct DevNull;
 
-impl INode for DevNull {
-    fn read_at(&self, offset: usize, buf: &mut [u8]) -> vfs::Result<usize> {
+impl Device for DevNull {
+    fn read(&self, _buf: &mut [u8]) -> Result<usize> {
         Ok(0)
     }
 
-    fn write_at(&self, offset: usize, buf: &[u8]) -> vfs::Result<usize> {
+    fn write(&self, buf: &[u8]) -> Result<usize> {
         Ok(buf.len())
     }
-
-    fn poll(&self) -> vfs::Result<vfs::PollStatus> {
-        Ok(vfs::PollStatus {
-            read: true,
-            write: true,
-            error: false,
-        })
-    }
-
-    fn metadata(&self) -> vfs::Result<Metadata> {
-        Ok(Metadata {
-            dev: 1,
-            inode: 0,
-            size: 0,
-            blk_size: 0,
-            blocks: 0,
-            atime: Timespec { sec: 0, nsec: 0 },
-            mtime: Timespec { sec: 0, nsec: 0 },
-            ctime: Timespec { sec: 0, nsec: 0 },
-            type_: vfs::FileType::CharDevice,
-            mode: 0o666,
-            nlinks: 1,
-            uid: 0,
-            gid: 0,
-            rdev: 0,
-        })
-    }
-
-    fn as_any_ref(&self) -> &dyn Any {
-        self
-    }
 }
@@ -4,44 +4,21 @@ use crate::util::random;
 #[derive(Debug)]
 pub struct DevRandom;
 
-impl INode for DevRandom {
-    fn read_at(&self, offset: usize, buf: &mut [u8]) -> vfs::Result<usize> {
-        random::get_random(buf).map_err(|_| FsError::Again)?;
-        Ok(buf.len())
-    }
-
-    fn write_at(&self, offset: usize, buf: &[u8]) -> vfs::Result<usize> {
-        Err(FsError::PermError)
+impl Device for DevRandom {
+    fn mode(&self) -> u16 {
+        0o444
     }
 
-    fn poll(&self) -> vfs::Result<vfs::PollStatus> {
-        Ok(vfs::PollStatus {
-            read: true,
-            write: false,
-            error: false,
-        })
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        random::get_random(buf)?;
+        Ok(buf.len())
     }
 
-    fn metadata(&self) -> vfs::Result<Metadata> {
-        Ok(Metadata {
-            dev: 1,
-            inode: 0,
-            size: 0,
-            blk_size: 0,
-            blocks: 0,
-            atime: Timespec { sec: 0, nsec: 0 },
-            mtime: Timespec { sec: 0, nsec: 0 },
-            ctime: Timespec { sec: 0, nsec: 0 },
-            type_: vfs::FileType::CharDevice,
-            mode: 0o444,
-            nlinks: 1,
-            uid: 0,
-            gid: 0,
-            rdev: 0,
-        })
+    fn write(&self, _buf: &[u8]) -> Result<usize> {
+        return_errno!(EPERM, "random devices are not writable");
     }
 
-    fn as_any_ref(&self) -> &dyn Any {
-        self
+    fn poll(&self, mask: Events, _poller: Option<&mut Poller>) -> Events {
+        Events::IN & mask
     }
 }
@@ -5,6 +5,8 @@ use super::*;
 mod consts;
 
 use self::consts::*;
+use super::super::file_ops::NonBuiltinIoctlCmd;
+use async_io::match_ioctl_cmd_mut;
 use util::mem_util::from_user::*;
 use util::sgx::*;
 
@@ -15,59 +17,31 @@ extern "C" {
 #[derive(Debug)]
 pub struct DevSgx;
 
-impl INode for DevSgx {
-    fn read_at(&self, offset: usize, buf: &mut [u8]) -> vfs::Result<usize> {
-        Err(FsError::PermError)
+impl Device for DevSgx {
+    fn read(&self, _buf: &mut [u8]) -> Result<usize> {
+        return_errno!(EPERM, "/dev/sgx does not support read");
     }
 
-    fn write_at(&self, offset: usize, buf: &[u8]) -> vfs::Result<usize> {
-        Err(FsError::PermError)
+    fn write(&self, _buf: &[u8]) -> Result<usize> {
+        return_errno!(EPERM, "/dev/sgx does not support write");
     }
 
-    fn poll(&self) -> vfs::Result<vfs::PollStatus> {
-        Err(FsError::PermError)
+    fn poll(&self, _mask: Events, _poller: Option<&mut Poller>) -> Events {
+        Events::empty()
     }
 
-    fn metadata(&self) -> vfs::Result<Metadata> {
-        Ok(Metadata {
-            dev: 1,
-            inode: 0,
-            size: 0,
-            blk_size: 0,
-            blocks: 0,
-            atime: Timespec { sec: 0, nsec: 0 },
-            mtime: Timespec { sec: 0, nsec: 0 },
-            ctime: Timespec { sec: 0, nsec: 0 },
-            type_: vfs::FileType::CharDevice,
-            mode: 0o666,
-            nlinks: 1,
-            uid: 0,
-            gid: 0,
-            rdev: 0,
+    fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        match_ioctl_cmd_mut!(&mut *cmd, {
+            cmd: NonBuiltinIoctlCmd => self.do_ioctl(cmd),
+            _ => {
+                return_errno!(EINVAL, "unknown ioctl cmd for /dev/sgx");
+            }
         })
     }
-
-    fn io_control(&self, _cmd: u32, _data: usize) -> vfs::Result<()> {
-        let mut ioctl_cmd =
-            unsafe { IoctlCmd::new(_cmd, _data as *mut u8).map_err(|_| FsError::InvalidParam)? };
-        self.ioctl(&mut ioctl_cmd).map_err(|e| {
-            error!("{}", e.backtrace());
-            FsError::IOCTLError
-        })?;
-        Ok(())
-    }
-
-    fn as_any_ref(&self) -> &dyn Any {
-        self
-    }
 }
 
 impl DevSgx {
-    fn ioctl(&self, cmd: &mut IoctlCmd) -> Result<i32> {
-        let nonbuiltin_cmd = match cmd {
-            IoctlCmd::NonBuiltin(nonbuiltin_cmd) => nonbuiltin_cmd,
-            _ => return_errno!(EINVAL, "unknown ioctl cmd for /dev/sgx"),
-        };
+    fn do_ioctl(&self, nonbuiltin_cmd: &mut NonBuiltinIoctlCmd) -> Result<()> {
         let cmd_num = nonbuiltin_cmd.cmd_num().as_u32();
         match cmd_num {
             SGX_CMD_NUM_IS_EDMM_SUPPORTED => {
@@ -225,7 +199,7 @@ impl DevSgx {
                 return_errno!(ENOSYS, "unknown ioctl cmd for /dev/sgx");
             }
         }
-        Ok(0)
+        Ok(())
     }
 }
 
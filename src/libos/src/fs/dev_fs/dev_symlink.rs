@@ -0,0 +1,60 @@
+use super::*;
+
+/// A symbolic link in DevFS, e.g., /dev/fd.
+#[derive(Debug)]
+pub struct DevSymLink {
+    target: &'static str,
+    id: usize,
+}
+
+impl DevSymLink {
+    pub fn new(target: &'static str) -> Arc<dyn INode> {
+        Arc::new(Self {
+            target,
+            id: alloc_inode_id(),
+        })
+    }
+}
+
+impl INode for DevSymLink {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> vfs::Result<usize> {
+        let target = self.target.as_bytes();
+        if offset >= target.len() {
+            return Ok(0);
+        }
+        let len = buf.len().min(target.len() - offset);
+        buf[..len].copy_from_slice(&target[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> vfs::Result<usize> {
+        Err(FsError::PermError)
+    }
+
+    fn poll(&self) -> vfs::Result<vfs::PollStatus> {
+        Err(FsError::NotFile)
+    }
+
+    fn metadata(&self) -> vfs::Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: self.id,
+            size: self.target.len(),
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: vfs::FileType::SymLink,
+            mode: 0o777,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,56 @@
+use super::*;
+
+lazy_static! {
+    /// The terminal shared by all the DevFS instances.
+    pub static ref DEV_TTY: Arc<DevTty> = Arc::new(DevTty::new());
+}
+
+/// The terminal of the LibOS (/dev/tty), which is that of the host.
+///
+/// The host fds of the terminal are the stdio fds given to the latest root
+/// process, or the stdio fds of the host process before any root process.
+#[derive(Debug)]
+pub struct DevTty {
+    host_tty: RwLock<HostTty>,
+}
+
+#[derive(Debug)]
+struct HostTty {
+    stdin: StdinFile,
+    stdout: StdoutFile,
+}
+
+impl DevTty {
+    fn new() -> Self {
+        let host_tty = HostTty {
+            stdin: StdinFile::new(libc::STDIN_FILENO as FileDesc),
+            stdout: StdoutFile::new(libc::STDOUT_FILENO as FileDesc),
+        };
+        Self {
+            host_tty: RwLock::new(host_tty),
+        }
+    }
+
+    /// Use the given host stdio fds as the terminal.
+    pub fn set_host_fds(&self, host_stdio_fds: &HostStdioFds) {
+        let mut host_tty = self.host_tty.write().unwrap();
+        *host_tty = HostTty {
+            stdin: StdinFile::new(host_stdio_fds.stdin_fd as FileDesc),
+            stdout: StdoutFile::new(host_stdio_fds.stdout_fd as FileDesc),
+        };
+    }
+}
+
+impl Device for DevTty {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.host_tty.read().unwrap().stdin.read(buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        self.host_tty.read().unwrap().stdout.write(buf)
+    }
+
+    fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        self.host_tty.read().unwrap().stdout.ioctl(cmd)
+    }
+}
@@ -3,46 +3,15 @@ use super::*;
 #[derive(Debug)]
 pub struct DevZero;
 
-impl INode for DevZero {
-    fn read_at(&self, offset: usize, buf: &mut [u8]) -> vfs::Result<usize> {
+impl Device for DevZero {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
         for b in buf.iter_mut() {
             *b = 0;
         }
         Ok(buf.len())
     }
 
-    fn write_at(&self, offset: usize, buf: &[u8]) -> vfs::Result<usize> {
+    fn write(&self, buf: &[u8]) -> Result<usize> {
         Ok(buf.len())
     }
-
-    fn poll(&self) -> vfs::Result<vfs::PollStatus> {
-        Ok(vfs::PollStatus {
-            read: true,
-            write: true,
-            error: false,
-        })
-    }
-
-    fn metadata(&self) -> vfs::Result<Metadata> {
-        Ok(Metadata {
-            dev: 1,
-            inode: 0,
-            size: 0,
-            blk_size: 0,
-            blocks: 0,
-            atime: Timespec { sec: 0, nsec: 0 },
-            mtime: Timespec { sec: 0, nsec: 0 },
-            ctime: Timespec { sec: 0, nsec: 0 },
-            type_: vfs::FileType::CharDevice,
-            mode: 0o666,
-            nlinks: 1,
-            uid: 0,
-            gid: 0,
-            rdev: 0,
-        })
-    }
-
-    fn as_any_ref(&self) -> &dyn Any {
-        self
-    }
 }
@@ -1,30 +1,109 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use super::*;
+use async_io::ioctl::IoctlCmd;
 use rcore_fs::vfs;
 use rcore_fs_devfs::DevFS;
+use rcore_fs_ramfs::RamFS;
+
+pub use self::dev_inode::DevINode;
 
+use self::dev_file::DevFile;
 use self::dev_null::DevNull;
 use self::dev_random::DevRandom;
 use self::dev_sgx::DevSgx;
+use self::dev_symlink::DevSymLink;
+use self::dev_tty::DEV_TTY;
 use self::dev_zero::DevZero;
 
+mod dev_file;
+mod dev_inode;
 mod dev_null;
 mod dev_random;
 mod dev_sgx;
+mod dev_symlink;
+mod dev_tty;
 mod dev_zero;
 
+/// A device, e.g., /dev/null.
+///
+/// A device is found in DevFS as a `DevINode`, but once opened, the I/O on it
+/// goes through a `File`. So just like the other files, the read and write
+/// methods must be non-blocking.
+pub trait Device: Debug + Sync + Send {
+    /// The permission bits of the device file.
+    fn mode(&self) -> u16 {
+        0o666
+    }
+
+    fn read(&self, _buf: &mut [u8]) -> Result<usize> {
+        return_errno!(EINVAL, "the device does not support read");
+    }
+
+    fn write(&self, _buf: &[u8]) -> Result<usize> {
+        return_errno!(EINVAL, "the device does not support write");
+    }
+
+    fn poll(&self, mask: Events, _poller: Option<&mut Poller>) -> Events {
+        (Events::IN | Events::OUT) & mask
+    }
+
+    fn ioctl(&self, _cmd: &mut dyn IoctlCmd) -> Result<()> {
+        return_errno!(ENOTTY, "not support ioctl");
+    }
+}
+
+/// The names of the devices that are available by default.
+///
+/// Other devices, e.g., "arandom" of BSD, which is not found on Linux, are
+/// available only if they are given explicitly.
+const DEFAULT_DEVICES: [&str; 6] = ["null", "zero", "random", "urandom", "tty", "sgx"];
+
 /// API to initialize the DevFS
-pub fn init_devfs() -> Result<Arc<DevFS>> {
+///
+/// Only the given devices are added if `devices` is not `None`. The RamFS of
+/// /dev/shm is returned along with the DevFS, which must be mounted on the shm
+/// directory after the DevFS is mounted.
+pub fn init_devfs(devices: Option<&[String]>) -> Result<(Arc<DevFS>, Arc<RamFS>)> {
     let devfs = DevFS::new();
-    let dev_null = Arc::new(DevNull) as _;
-    devfs.add("null", dev_null)?;
-    let dev_zero = Arc::new(DevZero) as _;
-    devfs.add("zero", dev_zero)?;
-    let dev_random = Arc::new(DevRandom) as _;
-    devfs.add("random", Arc::clone(&dev_random))?;
-    devfs.add("urandom", Arc::clone(&dev_random))?;
-    devfs.add("arandom", Arc::clone(&dev_random))?;
-    let dev_sgx = Arc::new(DevSgx) as _;
-    devfs.add("sgx", dev_sgx)?;
-    // TODO: Add stdio(stdin, stdout, stderr) into DevFS
-    Ok(devfs)
+    let dev_random = DevINode::new(Arc::new(DevRandom));
+    let device_names: Vec<&str> = match devices {
+        Some(devices) => devices.iter().map(|name| name.as_str()).collect(),
+        None => DEFAULT_DEVICES.to_vec(),
+    };
+    for name in device_names {
+        let dev_inode = match name {
+            "null" => DevINode::new(Arc::new(DevNull)),
+            "zero" => DevINode::new(Arc::new(DevZero)),
+            "random" | "urandom" | "arandom" => Arc::clone(&dev_random),
+            "tty" => DevINode::new(DEV_TTY.clone()),
+            "sgx" => DevINode::new(Arc::new(DevSgx)),
+            _ => return_errno!(EINVAL, "unknown device"),
+        };
+        devfs.add(name, dev_inode)?;
+    }
+
+    // The stdio of the current process is found via procfs, just like Linux
+    devfs.add("fd", DevSymLink::new("/proc/self/fd"))?;
+    devfs.add("stdin", DevSymLink::new("/proc/self/fd/0"))?;
+    devfs.add("stdout", DevSymLink::new("/proc/self/fd/1"))?;
+    devfs.add("stderr", DevSymLink::new("/proc/self/fd/2"))?;
+
+    // The shared memory objects of POSIX, e.g., those created by shm_open,
+    // are files in /dev/shm, which is a tmpfs. Its root is also the mount point
+    // so that the RamFS is kept alive by the mount.
+    let shm_fs = RamFS::new();
+    devfs.add("shm", shm_fs.root_inode())?;
+    Ok((devfs, shm_fs))
+}
+
+/// Use the host stdio fds of a new root process as the terminal (/dev/tty).
+pub fn set_host_tty_fds(host_stdio_fds: &HostStdioFds) {
+    DEV_TTY.set_host_fds(host_stdio_fds);
+}
+
+/// Allocate the inode number of a device or a symbolic link in DevFS.
+fn alloc_inode_id() -> usize {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
 }
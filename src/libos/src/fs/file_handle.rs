@@ -1,4 +1,5 @@
 use async_io::file::{Async, File};
+use async_io::ioctl::IoctlCmd;
 use inherit_methods_macro::inherit_methods;
 
 use std::sync::Weak;
@@ -72,6 +73,16 @@ impl FileHandle {
         Self::new(any_file)
     }
 
+    /// Create a file handle for an opened inode, which may be a device.
+    ///
+    /// The I/O on a device goes through the file of the device, not the inode.
+    pub fn from_opened_inode(file: InodeFile) -> Self {
+        match DevINode::from_inode(file.inode()) {
+            Some(dev_inode) => dev_inode.open(file.access_mode(), file.status_flags()),
+            None => Self::new_inode(file),
+        }
+    }
+
     /// Create a file handle for a socket file.
     pub fn new_socket(file: SocketFile) -> Self {
         let any_file = AnyFile::Socket(Arc::new(file));
@@ -103,6 +114,28 @@ impl FileHandle {
         apply_fn_on_any_file!(&self.0.file, |file| { file.writev(bufs).await })
     }
 
+    /// Read some data at a given offset into a buffer, without changing the
+    /// offset of the file.
+    ///
+    /// Only inode files support random access. Other files fail with ESPIPE.
+    pub async fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        match &self.0.file {
+            AnyFile::Inode(inode_file) => inode_file.read_at(offset, buf).await,
+            _ => return_errno!(ESPIPE, "not an inode file"),
+        }
+    }
+
+    /// Write the data from a buffer at a given offset, without changing the
+    /// offset of the file.
+    ///
+    /// Only inode files support random access. Other files fail with ESPIPE.
+    pub async fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        match &self.0.file {
+            AnyFile::Inode(inode_file) => inode_file.write_at(offset, buf).await,
+            _ => return_errno!(ESPIPE, "not an inode file"),
+        }
+    }
+
     /// Returns the access mode of the file.
     pub fn access_mode(&self) -> AccessMode {
         apply_fn_on_any_file!(&self.0.file, |file| { file.access_mode() })
@@ -123,6 +156,11 @@ impl FileHandle {
         apply_fn_on_any_file!(&self.0.file, |file| { file.poll(mask, poller) })
     }
 
+    /// Do an ioctl-style command on the file.
+    pub fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        apply_fn_on_any_file!(&self.0.file, |file| { file.ioctl(cmd) })
+    }
+
     /// Returns the underlying inode file if there is one.
     pub fn as_inode_file(&self) -> Option<&InodeFile> {
         match &self.0.file {
@@ -139,6 +177,14 @@ impl FileHandle {
         }
     }
 
+    /// Returns the underlying file of type `F` if there is one, e.g., a pipe end.
+    pub fn as_file<F: File>(&self) -> Option<&F> {
+        match &self.0.file {
+            AnyFile::File(file) => file.file().downcast_ref::<F>(),
+            _ => None,
+        }
+    }
+
     /// Downgrade the file handle to its weak counterpart.
     pub fn downgrade(&self) -> WeakFileHandle {
         let any_weak_file = match &self.0.file {
@@ -180,18 +226,83 @@ impl AsyncInode {
         &self.0
     }
 
-    // Inherit methods from the inner InodeFile. Note that all I/O methods are
-    // async wrappers of the original sync ones.
-    pub async fn read(&self, buf: &mut [u8]) -> Result<usize>;
-    pub async fn readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize>;
-    pub async fn write(&self, buf: &[u8]) -> Result<usize>;
-    pub async fn writev(&self, bufs: &[&[u8]]) -> Result<usize>;
+    // Inherit methods from the inner InodeFile
     pub fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events;
+    pub fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()>;
     pub fn access_mode(&self) -> AccessMode;
     pub fn status_flags(&self) -> StatusFlags;
     pub fn set_status_flags(&self, new_status: StatusFlags) -> Result<()>;
 }
 
+impl AsyncInode {
+    pub async fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        if self.0.is_direct() {
+            return self.0.read_direct(&mut [buf]).await;
+        }
+        self.do_io(Events::IN, || self.0.read(buf)).await
+    }
+
+    pub async fn readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        if self.0.is_direct() {
+            return self.0.read_direct(bufs).await;
+        }
+        self.do_io(Events::IN, || self.0.readv(bufs)).await
+    }
+
+    pub async fn write(&self, buf: &[u8]) -> Result<usize> {
+        if self.0.is_direct() {
+            return self.0.write_direct(&[buf]).await;
+        }
+        self.do_io(Events::OUT, || self.0.write(buf)).await
+    }
+
+    pub async fn writev(&self, bufs: &[&[u8]]) -> Result<usize> {
+        if self.0.is_direct() {
+            return self.0.write_direct(bufs).await;
+        }
+        self.do_io(Events::OUT, || self.0.writev(bufs)).await
+    }
+
+    pub async fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        if self.0.is_direct() {
+            return self.0.read_direct_at(offset, &mut [buf]).await;
+        }
+        self.do_io(Events::IN, || self.0.read_at(offset, buf)).await
+    }
+
+    pub async fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        if self.0.is_direct() {
+            return self.0.write_direct_at(offset, &[buf]).await;
+        }
+        self.do_io(Events::OUT, || self.0.write_at(offset, buf))
+            .await
+    }
+
+    // Do the I/O until it does not fail with EAGAIN, which happens when the
+    // I/O on a host file has to wait for the page cache. Just like Linux,
+    // O_NONBLOCK has no effect on the I/O of regular files.
+    async fn do_io(&self, mask: Events, mut io_fn: impl FnMut() -> Result<usize>) -> Result<usize> {
+        // Fast path
+        let res = io_fn();
+        if !res.has_errno(EAGAIN) {
+            return res;
+        }
+
+        // Slow path
+        let mut poller = Poller::new();
+        loop {
+            let events = self.0.poll(mask, Some(&mut poller));
+            if events.contains(mask) {
+                let res = io_fn();
+                if !res.has_errno(EAGAIN) {
+                    return res;
+                }
+            }
+            poller.wait().await;
+        }
+    }
+}
+
 /// The weak version of `FileHandle`. Similar to `Weak`, but for files.
 #[derive(Clone, Debug)]
 pub struct WeakFileHandle(AnyWeakFile);
@@ -0,0 +1,389 @@
+//! Advisory file locks.
+//!
+//! There are two independent kinds of locks, just like Linux:
+//!
+//! * Range locks, which are byte-range locks set via `fcntl`. A POSIX record
+//! lock is owned by a process, while an OFD lock is owned by an open file
+//! description. The two conflict with each other as long as their owners differ.
+//!
+//! * Whole-file locks, which are set via `flock` and owned by an open file
+//! description.
+//!
+//! All the locks are kept in a LibOS-wide table keyed by inode.
+use std::collections::HashSet;
+
+use async_rt::wait::WaiterQueue;
+use async_rt::waiter_loop;
+use rcore_fs_mountfs::MNode;
+
+use super::*;
+
+lazy_static! {
+    static ref LOCK_TABLE: SgxMutex<LockTable> = SgxMutex::new(LockTable::new());
+}
+
+/// The kind of a lock.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LockKind {
+    Shared,
+    Exclusive,
+}
+
+/// The owner of a lock.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LockOwner {
+    /// A POSIX record lock is owned by a process
+    Process(pid_t),
+    /// An OFD lock or a whole-file lock is owned by an open file description
+    File(usize),
+}
+
+impl LockOwner {
+    pub fn of_file(file: &InodeFile) -> Self {
+        LockOwner::File(file as *const InodeFile as usize)
+    }
+}
+
+/// A lock on the byte range of `[start, end)`, where `end` is `usize::MAX`
+/// if the range extends to the end of the file, however large it grows.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RangeLock {
+    pub owner: LockOwner,
+    pub kind: LockKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl RangeLock {
+    fn conflicts_with(&self, other: &RangeLock) -> bool {
+        self.owner != other.owner
+            && self.start < other.end
+            && other.start < self.end
+            && (self.kind == LockKind::Exclusive || other.kind == LockKind::Exclusive)
+    }
+}
+
+/// Returns the lock, if any, that prevents `lock` from being set on the file.
+pub fn test_range_lock(file: &InodeFile, lock: &RangeLock) -> Result<Option<RangeLock>> {
+    let key = InodeKey::of(file.inode())?;
+    let table = LOCK_TABLE.lock().unwrap();
+    let conflict = table
+        .inodes
+        .get(&key)
+        .and_then(|locks| locks.find_range_conflict(lock));
+    Ok(conflict)
+}
+
+/// Set a range lock on the file, replacing the locks of the same owner in the range.
+///
+/// If the lock is held by others, either return EAGAIN or wait until it is
+/// released. A POSIX record lock fails with EDEADLK instead of waiting if
+/// the wait would never end.
+pub async fn set_range_lock(file: &InodeFile, lock: RangeLock, wait: bool) -> Result<()> {
+    let key = InodeKey::of(file.inode())?;
+    let tid = current!().tid();
+    let waiters = LOCK_TABLE
+        .lock()
+        .unwrap()
+        .get_or_create(key)
+        .waiters
+        .clone();
+    let res = waiter_loop!(&waiters, {
+        let mut table = LOCK_TABLE.lock().unwrap();
+        let locks = table.get_or_create(key);
+        if locks.find_range_conflict(&lock).is_none() {
+            locks.insert_range_lock(lock);
+            break Ok(());
+        }
+        if !wait {
+            break Err(errno!(EAGAIN, "the range is locked by others"));
+        }
+        if let LockOwner::Process(pid) = lock.owner {
+            // The processes holding the conflicting POSIX record locks
+            let holders: Vec<pid_t> = locks
+                .range_conflicts(&lock)
+                .filter_map(|l| match l.owner {
+                    LockOwner::Process(holder) => Some(holder),
+                    LockOwner::File(_) => None,
+                })
+                .collect();
+            if table.would_deadlock(pid, &holders) {
+                break Err(errno!(EDEADLK, "waiting for the lock would deadlock"));
+            }
+            table.waits_for.insert(tid, Waiting { pid, holders });
+        }
+    });
+    let mut table = LOCK_TABLE.lock().unwrap();
+    table.waits_for.remove(&tid);
+    drop(waiters);
+    table.remove_if_unused(&key);
+    res
+}
+
+/// Remove the range of `[start, end)` from the range locks of the owner on the file.
+pub fn unlock_range(file: &InodeFile, owner: LockOwner, start: usize, end: usize) -> Result<()> {
+    let key = InodeKey::of(file.inode())?;
+    let mut table = LOCK_TABLE.lock().unwrap();
+    if let Some(locks) = table.inodes.get_mut(&key) {
+        if locks.remove_range(owner, start, end) {
+            locks.waiters.wake_all();
+        }
+    }
+    table.remove_if_unused(&key);
+    Ok(())
+}
+
+/// Set a whole-file lock on the file, which is owned by the file itself.
+///
+/// Just like Linux, converting the existing lock of the file is not atomic:
+/// the existing lock is released before waiting for the new one.
+pub async fn set_whole_file_lock(file: &InodeFile, kind: LockKind, wait: bool) -> Result<()> {
+    let key = InodeKey::of(file.inode())?;
+    let owner = LockOwner::of_file(file);
+    let waiters = {
+        let mut table = LOCK_TABLE.lock().unwrap();
+        let locks = table.get_or_create(key);
+        let existing = locks.whole_file_locks.iter().position(|l| l.0 == owner);
+        if let Some(idx) = existing {
+            if locks.whole_file_locks[idx].1 == kind {
+                return Ok(());
+            }
+            locks.whole_file_locks.swap_remove(idx);
+            locks.waiters.wake_all();
+        }
+        locks.waiters.clone()
+    };
+    let res = waiter_loop!(&waiters, {
+        let mut table = LOCK_TABLE.lock().unwrap();
+        let locks = table.get_or_create(key);
+        let is_locked_by_others = locks
+            .whole_file_locks
+            .iter()
+            .any(|l| l.0 != owner && (l.1 == LockKind::Exclusive || kind == LockKind::Exclusive));
+        if !is_locked_by_others {
+            locks.whole_file_locks.push((owner, kind));
+            break Ok(());
+        }
+        if !wait {
+            break Err(errno!(EAGAIN, "the file is locked by others"));
+        }
+    });
+    let mut table = LOCK_TABLE.lock().unwrap();
+    drop(waiters);
+    table.remove_if_unused(&key);
+    res
+}
+
+/// Release the whole-file lock of the file, if any.
+pub fn unlock_whole_file(file: &InodeFile) -> Result<()> {
+    let key = InodeKey::of(file.inode())?;
+    let owner = LockOwner::of_file(file);
+    let mut table = LOCK_TABLE.lock().unwrap();
+    if let Some(locks) = table.inodes.get_mut(&key) {
+        let len = locks.whole_file_locks.len();
+        locks.whole_file_locks.retain(|l| l.0 != owner);
+        if locks.whole_file_locks.len() != len {
+            locks.waiters.wake_all();
+        }
+    }
+    table.remove_if_unused(&key);
+    Ok(())
+}
+
+/// Release the POSIX record locks of the process on the file.
+///
+/// This is done when the process closes any of its file descriptors of the
+/// inode, no matter via which one the locks were set.
+pub fn release_process_locks_on(file: &InodeFile, pid: pid_t) {
+    // Avoid getting the metadata of the inode if there are no locks at all
+    if LOCK_TABLE.lock().unwrap().inodes.is_empty() {
+        return;
+    }
+    if let Err(e) = unlock_range(file, LockOwner::Process(pid), 0, usize::MAX) {
+        warn!("failed to release the locks on close: {:?}", e);
+    }
+}
+
+/// Release all the POSIX record locks of the process, which is exiting.
+pub fn release_process_locks(pid: pid_t) {
+    LOCK_TABLE
+        .lock()
+        .unwrap()
+        .release_all_of(LockOwner::Process(pid));
+}
+
+/// Release all the locks owned by the file, which is being closed.
+pub fn release_file_locks(file: &InodeFile) {
+    let mut table = LOCK_TABLE.lock().unwrap();
+    if table.inodes.is_empty() {
+        return;
+    }
+    table.release_all_of(LockOwner::of_file(file));
+}
+
+/// Identifies an inode by its file system and its inode number. The `Arc`s of
+/// `INode` cannot be used since looking up a path twice may give two of them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct InodeKey {
+    fs: usize,
+    ino: usize,
+}
+
+impl InodeKey {
+    fn of(inode: &dyn INode) -> Result<Self> {
+        // The inodes of mounted file systems are wrapped by MountFS, once per
+        // mount. An inode reachable via a bind mount is wrapped more than once.
+        let mut inode = inode;
+        while let Some(mnode) = inode.downcast_ref::<MNode>() {
+            inode = &*mnode.inode;
+        }
+        let fs = Arc::as_ptr(&inode.fs()) as *const u8 as usize;
+        let ino = inode.metadata()?.inode;
+        Ok(Self { fs, ino })
+    }
+}
+
+struct LockTable {
+    inodes: HashMap<InodeKey, InodeLocks>,
+    // What each blocked thread waits for, keyed by its tid, to detect deadlocks
+    waits_for: HashMap<pid_t, Waiting>,
+}
+
+/// A thread blocked on a POSIX record lock.
+struct Waiting {
+    // The process of the thread
+    pid: pid_t,
+    // The processes holding the conflicting locks
+    holders: Vec<pid_t>,
+}
+
+impl LockTable {
+    fn new() -> Self {
+        Self {
+            inodes: HashMap::new(),
+            waits_for: HashMap::new(),
+        }
+    }
+
+    fn get_or_create(&mut self, key: InodeKey) -> &mut InodeLocks {
+        self.inodes.entry(key).or_insert_with(InodeLocks::new)
+    }
+
+    /// Returns whether the process would deadlock if it waited for the holders,
+    /// i.e., any of them waits for the process, directly or not.
+    fn would_deadlock(&self, pid: pid_t, holders: &[pid_t]) -> bool {
+        let mut visited = HashSet::new();
+        let mut pending = holders.to_vec();
+        while let Some(next) = pending.pop() {
+            if next == pid {
+                return true;
+            }
+            if !visited.insert(next) {
+                continue;
+            }
+            // A process waits for whatever any of its blocked threads waits for
+            for waiting in self.waits_for.values().filter(|w| w.pid == next) {
+                pending.extend_from_slice(&waiting.holders);
+            }
+        }
+        false
+    }
+
+    fn release_all_of(&mut self, owner: LockOwner) {
+        for locks in self.inodes.values_mut() {
+            let mut is_released = locks.remove_range(owner, 0, usize::MAX);
+            let len = locks.whole_file_locks.len();
+            locks.whole_file_locks.retain(|l| l.0 != owner);
+            is_released |= locks.whole_file_locks.len() != len;
+            if is_released {
+                locks.waiters.wake_all();
+            }
+        }
+        self.inodes.retain(|_, locks| !locks.is_unused());
+    }
+
+    fn remove_if_unused(&mut self, key: &InodeKey) {
+        if self
+            .inodes
+            .get(key)
+            .map_or(false, |locks| locks.is_unused())
+        {
+            self.inodes.remove(key);
+        }
+    }
+}
+
+struct InodeLocks {
+    range_locks: Vec<RangeLock>,
+    whole_file_locks: Vec<(LockOwner, LockKind)>,
+    waiters: Arc<WaiterQueue>,
+}
+
+impl InodeLocks {
+    fn new() -> Self {
+        Self {
+            range_locks: Vec::new(),
+            whole_file_locks: Vec::new(),
+            waiters: Arc::new(WaiterQueue::new()),
+        }
+    }
+
+    /// Returns whether there are neither locks nor waiters.
+    fn is_unused(&self) -> bool {
+        self.range_locks.is_empty()
+            && self.whole_file_locks.is_empty()
+            && Arc::strong_count(&self.waiters) == 1
+    }
+
+    fn find_range_conflict(&self, lock: &RangeLock) -> Option<RangeLock> {
+        self.range_conflicts(lock).next().copied()
+    }
+
+    fn range_conflicts<'a>(&'a self, lock: &'a RangeLock) -> impl Iterator<Item = &'a RangeLock> {
+        self.range_locks
+            .iter()
+            .filter(move |l| l.conflicts_with(lock))
+    }
+
+    fn insert_range_lock(&mut self, lock: RangeLock) {
+        // The replaced locks may be downgraded or shrunk, which lets others in
+        if self.remove_range(lock.owner, lock.start, lock.end) {
+            self.waiters.wake_all();
+        }
+        // Merge the adjacent locks of the same owner and kind
+        let mut new_lock = lock;
+        self.range_locks.retain(|l| {
+            let is_mergeable = l.owner == new_lock.owner
+                && l.kind == new_lock.kind
+                && l.start <= new_lock.end
+                && new_lock.start <= l.end;
+            if is_mergeable {
+                new_lock.start = new_lock.start.min(l.start);
+                new_lock.end = new_lock.end.max(l.end);
+            }
+            !is_mergeable
+        });
+        self.range_locks.push(new_lock);
+    }
+
+    /// Returns whether any lock is removed or split.
+    fn remove_range(&mut self, owner: LockOwner, start: usize, end: usize) -> bool {
+        let mut remains = Vec::new();
+        let len = self.range_locks.len();
+        self.range_locks.retain(|l| {
+            if l.owner != owner || l.end <= start || end <= l.start {
+                return true;
+            }
+            if l.start < start {
+                remains.push(RangeLock { end: start, ..*l });
+            }
+            if end < l.end {
+                remains.push(RangeLock { start: end, ..*l });
+            }
+            false
+        });
+        let is_removed = self.range_locks.len() != len;
+        self.range_locks.extend(remains);
+        is_removed
+    }
+}
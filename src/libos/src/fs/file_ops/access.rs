@@ -60,6 +60,9 @@ pub fn do_faccessat(
     if mode.test_for_exist() {
         return Ok(());
     }
+    if mode.contains(AccessibilityCheckMode::W_OK) {
+        inode.check_not_read_only()?;
+    }
     // Check the permissions of file owner
     let owner_file_mode = {
         let metadata = inode.metadata()?;
@@ -9,6 +9,7 @@ pub fn do_fchmodat(fs_path: &FsPath, mode: FileMode) -> Result<()> {
         let fs = current.fs().lock().unwrap();
         fs.lookup_inode(&path)?
     };
+    inode.check_not_read_only()?;
     let mut info = inode.metadata()?;
     info.mode = mode.bits();
     inode.set_metadata(&info)?;
@@ -23,6 +24,7 @@ pub fn do_fchmod(fd: FileDesc, mode: FileMode) -> Result<()> {
         .as_inode_file()
         .ok_or_else(|| errno!(EINVAL, "not an inode"))?;
     let inode = inode_file.inode();
+    inode.check_not_read_only()?;
     let mut info = inode.metadata()?;
     info.mode = mode.bits();
     inode.set_metadata(&info)?;
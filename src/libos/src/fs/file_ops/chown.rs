@@ -23,6 +23,7 @@ pub fn do_fchownat(fs_path: &FsPath, uid: u32, gid: u32, flags: ChownFlags) -> R
             fs.lookup_inode(&path)?
         }
     };
+    inode.check_not_read_only()?;
     let mut info = inode.metadata()?;
     info.uid = uid as usize;
     info.gid = gid as usize;
@@ -38,6 +39,7 @@ pub fn do_fchown(fd: FileDesc, uid: u32, gid: u32) -> Result<()> {
         .as_inode_file()
         .ok_or_else(|| errno!(EINVAL, "not an inode"))?;
     let inode = inode_file.inode();
+    inode.check_not_read_only()?;
     let mut info = inode.metadata()?;
     info.uid = uid as usize;
     info.gid = gid as usize;
@@ -1,3 +1,4 @@
+use super::super::file_lock;
 use super::*;
 
 pub fn do_close(fd: FileDesc) -> Result<()> {
@@ -10,6 +11,18 @@ pub fn do_close(fd: FileDesc) -> Result<()> {
     // a deadlock.
     // TODO: make FileTable a struct of internal mutability to avoid deadlock.
     drop(files);
+    release_locks_on_close(&file, current.process().pid());
     drop(file);
     Ok(())
 }
+
+/// Release the POSIX record locks of the process on the inode of the file, whose
+/// fd is closed by the process, either explicitly or by `dup2` and `dup3`.
+///
+/// Just like Linux, closing any fd of an inode releases the locks, no matter
+/// via which fd they were set.
+pub fn release_locks_on_close(file: &FileRef, pid: pid_t) {
+    if let Some(inode_file) = file.as_inode_file() {
+        file_lock::release_process_locks_on(inode_file, pid);
+    }
+}
@@ -17,13 +17,16 @@ fn getdents_common<T: DirentType + Copy + Default>(fd: FileDesc, buf: &mut [u8])
     );
 
     let file_ref = current!().file(fd)?;
-    let info = file_ref.metadata()?;
+    let inode_file = file_ref
+        .as_inode_file()
+        .ok_or_else(|| errno!(ENOTDIR, "not a directory"))?;
+    let info = inode_file.inode().metadata()?;
     if info.type_ != FileType::Dir {
-        return_errno!(ENOTDIR, "");
+        return_errno!(ENOTDIR, "not a directory");
     }
     let mut writer = unsafe { DirentBufWriter::new(buf) };
     loop {
-        let name = match file_ref.read_entry() {
+        let name = match inode_file.read_entry() {
             Err(e) => {
                 let errno = e.errno();
                 if errno == ENOENT {
@@ -36,7 +39,7 @@ fn getdents_common<T: DirentType + Copy + Default>(fd: FileDesc, buf: &mut [u8])
         // TODO: get ino from dirent
         let dirent = LinuxDirent::<T>::new(1, &name);
         if let Err(e) = writer.try_write(&dirent, &name) {
-            file_ref.seek(SeekFrom::Current(-1))?;
+            inode_file.seek(SeekFrom::Current(-1))?;
             if writer.written_size == 0 {
                 return Err(e);
             } else {
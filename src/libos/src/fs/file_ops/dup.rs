@@ -1,3 +1,4 @@
+use super::close::release_locks_on_close;
 use super::*;
 
 pub fn do_dup(old_fd: FileDesc) -> Result<FileDesc> {
@@ -12,7 +13,9 @@ pub fn do_dup2(old_fd: FileDesc, new_fd: FileDesc) -> Result<FileDesc> {
     let mut files = current.files().lock().unwrap();
     let file = files.get(old_fd)?;
     if old_fd != new_fd {
-        files.put_at(new_fd, file, false);
+        let old_file = files.put_at(new_fd, file, false);
+        drop(files);
+        close_replaced_file(old_file, current.process().pid());
     }
     Ok(new_fd)
 }
@@ -25,6 +28,16 @@ pub fn do_dup3(old_fd: FileDesc, new_fd: FileDesc, flags: u32) -> Result<FileDes
     if old_fd == new_fd {
         return_errno!(EINVAL, "old_fd must not be equal to new_fd");
     }
-    files.put_at(new_fd, file, creation_flags.must_close_on_spawn());
+    let old_file = files.put_at(new_fd, file, creation_flags.must_close_on_spawn());
+    drop(files);
+    close_replaced_file(old_file, current.process().pid());
     Ok(new_fd)
 }
+
+/// The file that was at the new fd is closed silently, just as by `close`. It
+/// is dropped after the file table is unlocked, as `do_close` does.
+fn close_replaced_file(old_file: Option<FileRef>, pid: pid_t) {
+    if let Some(old_file) = old_file {
+        release_locks_on_close(&old_file, pid);
+    }
+}
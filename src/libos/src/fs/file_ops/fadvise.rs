@@ -0,0 +1,24 @@
+use super::*;
+use async_file::Advice;
+
+pub fn do_fadvise(fd: FileDesc, offset: usize, len: usize, advice: i32) -> Result<()> {
+    debug!(
+        "fadvise: fd: {}, offset: {}, len: {}, advice: {}",
+        fd, offset, len, advice
+    );
+    let advice = match advice {
+        0 => Advice::Normal,
+        1 => Advice::Random,
+        2 => Advice::Sequential,
+        3 => Advice::WillNeed,
+        4 => Advice::DontNeed,
+        5 => Advice::NoReuse,
+        _ => return_errno!(EINVAL, "invalid advice"),
+    };
+    let file_ref = current!().file(fd)?;
+    if let Some(inode_file) = file_ref.as_inode_file() {
+        inode_file.fadvise(advice, offset, len)
+    } else {
+        return_errno!(ESPIPE, "not an inode file");
+    }
+}
@@ -0,0 +1,15 @@
+use super::*;
+
+pub async fn do_fallocate(fd: FileDesc, mode: i32, offset: usize, len: usize) -> Result<()> {
+    debug!(
+        "fallocate: fd: {}, mode: {}, offset: {}, len: {}",
+        fd, mode, offset, len
+    );
+    let file_ref = current!().file(fd)?;
+    if let Some(inode_file) = file_ref.as_inode_file() {
+        inode_file.fallocate(mode, offset, len).await?;
+        Ok(())
+    } else {
+        return_errno!(ENODEV, "not an inode file");
+    }
+}
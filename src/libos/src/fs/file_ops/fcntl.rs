@@ -1,7 +1,14 @@
-use super::flock::flock;
+use super::super::file_lock::{self, LockOwner};
+use super::flock::{flock, Flock, FlockType};
 use super::*;
+use crate::misc::resource_t;
 use util::mem_util::from_user;
 
+// The commands of open file description locks
+const F_OFD_GETLK: c_int = 36;
+const F_OFD_SETLK: c_int = 37;
+const F_OFD_SETLKW: c_int = 38;
+
 #[derive(Debug)]
 pub enum FcntlCmd<'a> {
     /// Duplicate the file descriptor fd using the lowest-numbered available
@@ -18,10 +25,18 @@ pub enum FcntlCmd<'a> {
     GetFl(),
     /// Set the file status flags
     SetFl(u32),
-    /// Test a file lock
+    /// Test a POSIX record lock
     GetLk(&'a mut flock),
-    /// Acquire or release a file lock
+    /// Acquire or release a POSIX record lock
     SetLk(&'a flock),
+    /// As for `SetLk`, but wait if the lock is held by others
+    SetLkWait(&'a flock),
+    /// Test an open file description lock
+    OfdGetLk(&'a mut flock),
+    /// Acquire or release an open file description lock
+    OfdSetLk(&'a flock),
+    /// As for `OfdSetLk`, but wait if the lock is held by others
+    OfdSetLkWait(&'a flock),
 }
 
 impl<'a> FcntlCmd<'a> {
@@ -34,27 +49,58 @@ impl<'a> FcntlCmd<'a> {
             libc::F_SETFD => FcntlCmd::SetFd(arg as u32),
             libc::F_GETFL => FcntlCmd::GetFl(),
             libc::F_SETFL => FcntlCmd::SetFl(arg as u32),
-            libc::F_GETLK => {
-                let flock_mut_ptr = arg as *mut flock;
-                from_user::check_mut_ptr(flock_mut_ptr)?;
-                let flock_mut_c = unsafe { &mut *flock_mut_ptr };
-                FcntlCmd::GetLk(flock_mut_c)
-            }
-            libc::F_SETLK => {
-                let flock_ptr = arg as *const flock;
-                from_user::check_ptr(flock_ptr)?;
-                let flock_c = unsafe { &*flock_ptr };
-                FcntlCmd::SetLk(flock_c)
-            }
+            libc::F_GETLK => FcntlCmd::GetLk(Self::flock_mut_from_raw(arg)?),
+            libc::F_SETLK => FcntlCmd::SetLk(Self::flock_from_raw(arg)?),
+            libc::F_SETLKW => FcntlCmd::SetLkWait(Self::flock_from_raw(arg)?),
+            F_OFD_GETLK => FcntlCmd::OfdGetLk(Self::flock_mut_from_raw(arg)?),
+            F_OFD_SETLK => FcntlCmd::OfdSetLk(Self::flock_from_raw(arg)?),
+            F_OFD_SETLKW => FcntlCmd::OfdSetLkWait(Self::flock_from_raw(arg)?),
             _ => return_errno!(EINVAL, "unsupported command"),
         })
     }
+
+    fn flock_from_raw(arg: u64) -> Result<&'a flock> {
+        let lock_ptr = arg as *const flock;
+        from_user::check_ptr(lock_ptr)?;
+        Ok(unsafe { &*lock_ptr })
+    }
+
+    fn flock_mut_from_raw(arg: u64) -> Result<&'a mut flock> {
+        let lock_mut_ptr = arg as *mut flock;
+        from_user::check_mut_ptr(lock_mut_ptr)?;
+        Ok(unsafe { &mut *lock_mut_ptr })
+    }
 }
 
-pub fn do_fcntl(fd: FileDesc, cmd: &mut FcntlCmd) -> Result<isize> {
+pub async fn do_fcntl(fd: FileDesc, cmd: &mut FcntlCmd<'_>) -> Result<isize> {
     debug!("fcntl: fd: {:?}, cmd: {:?}", &fd, cmd);
 
     let current = current!();
+    match cmd {
+        FcntlCmd::GetLk(_)
+        | FcntlCmd::SetLk(_)
+        | FcntlCmd::SetLkWait(_)
+        | FcntlCmd::OfdGetLk(_)
+        | FcntlCmd::OfdSetLk(_)
+        | FcntlCmd::OfdSetLkWait(_) => {
+            // The file table must not be locked while waiting for the lock
+            let file_ref = current.file(fd)?;
+            do_fcntl_lock(&file_ref, current.process().pid(), cmd).await?;
+            return Ok(0);
+        }
+        _ => {}
+    }
+    if let FcntlCmd::DupFd(min_fd) | FcntlCmd::DupFdCloexec(min_fd) = cmd {
+        let soft_rlimit_nofile = current
+            .rlimits()
+            .lock()
+            .unwrap()
+            .get(resource_t::RLIMIT_NOFILE)
+            .get_cur();
+        if *min_fd as u64 >= soft_rlimit_nofile {
+            return_errno!(EINVAL, "the min fd exceeds the RLIMIT_NOFILE value");
+        }
+    }
     let mut file_table = current.files().lock().unwrap();
 
     let ret = match cmd {
@@ -92,22 +138,68 @@ pub fn do_fcntl(fd: FileDesc, cmd: &mut FcntlCmd) -> Result<isize> {
             file.set_status_flags(status_flags)?;
             0
         }
-        FcntlCmd::GetLk(flock_mut_c) => {
-            let file = file_table.get(fd)?;
-            let mut lock = Flock::from_c(*flock_mut_c)?;
-            if let FlockType::F_UNLCK = lock.l_type {
-                return_errno!(EINVAL, "invalid flock type for getlk");
+        _ => unreachable!(),
+    };
+    Ok(ret)
+}
+
+async fn do_fcntl_lock(file_ref: &FileRef, pid: pid_t, cmd: &mut FcntlCmd<'_>) -> Result<()> {
+    let inode_file = file_ref
+        .as_inode_file()
+        .ok_or_else(|| errno!(EINVAL, "not an inode file"))?;
+    let is_ofd = matches!(
+        cmd,
+        FcntlCmd::OfdGetLk(_) | FcntlCmd::OfdSetLk(_) | FcntlCmd::OfdSetLkWait(_)
+    );
+    let wait = matches!(cmd, FcntlCmd::SetLkWait(_) | FcntlCmd::OfdSetLkWait(_));
+    match cmd {
+        FcntlCmd::GetLk(lock_c) | FcntlCmd::OfdGetLk(lock_c) => {
+            let lock = Flock::from_c(lock_c)?;
+            if lock.l_type == FlockType::F_UNLCK {
+                return_errno!(EINVAL, "the lock type to test is F_UNLCK");
+            }
+            let owner = lock_owner(inode_file, pid, is_ofd, &lock)?;
+            let range_lock = lock.to_range_lock(inode_file, owner)?;
+            match file_lock::test_range_lock(inode_file, &range_lock)? {
+                Some(conflict) => lock_c.copy_from_safe(&Flock::from_range_lock(&conflict)),
+                // Only the type is changed if the lock could be placed
+                None => lock_c.l_type = FlockType::F_UNLCK as u16,
             }
-            file.test_advisory_lock(&mut lock)?;
-            (*flock_mut_c).copy_from_safe(&lock);
-            0
         }
-        FcntlCmd::SetLk(flock_c) => {
-            let file = file_table.get(fd)?;
-            let lock = Flock::from_c(*flock_c)?;
-            file.set_advisory_lock(&lock)?;
-            0
+        FcntlCmd::SetLk(lock_c)
+        | FcntlCmd::SetLkWait(lock_c)
+        | FcntlCmd::OfdSetLk(lock_c)
+        | FcntlCmd::OfdSetLkWait(lock_c) => {
+            let lock = Flock::from_c(lock_c)?;
+            let access_mode = inode_file.access_mode();
+            match lock.l_type {
+                FlockType::F_RDLCK if !access_mode.readable() => {
+                    return_errno!(EBADF, "the file is not opened for reading")
+                }
+                FlockType::F_WRLCK if !access_mode.writable() => {
+                    return_errno!(EBADF, "the file is not opened for writing")
+                }
+                _ => {}
+            }
+            let owner = lock_owner(inode_file, pid, is_ofd, &lock)?;
+            let range_lock = lock.to_range_lock(inode_file, owner)?;
+            if lock.l_type == FlockType::F_UNLCK {
+                file_lock::unlock_range(inode_file, owner, range_lock.start, range_lock.end)?;
+            } else {
+                file_lock::set_range_lock(inode_file, range_lock, wait).await?;
+            }
         }
-    };
-    Ok(ret)
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn lock_owner(inode_file: &InodeFile, pid: pid_t, is_ofd: bool, lock: &Flock) -> Result<LockOwner> {
+    if !is_ofd {
+        return Ok(LockOwner::Process(pid));
+    }
+    if lock.l_pid != 0 {
+        return_errno!(EINVAL, "the pid of an OFD lock must be 0");
+    }
+    Ok(LockOwner::of_file(inode_file))
 }
@@ -1,7 +1,41 @@
 /// File POSIX advisory lock
+use super::super::file_lock::{self, LockKind, LockOwner, RangeLock};
 use super::*;
 use process::pid_t;
 
+pub async fn do_flock(fd: FileDesc, ops: FlockOps) -> Result<()> {
+    debug!("flock: fd: {}, ops: {:?}", fd, ops);
+
+    let file_ref = current!().file(fd)?;
+    let inode_file = file_ref
+        .as_inode_file()
+        .ok_or_else(|| errno!(EINVAL, "not an inode file"))?;
+    let wait = !ops.contains(FlockOps::LOCK_NB);
+    match ops - FlockOps::LOCK_NB {
+        FlockOps::LOCK_SH => {
+            file_lock::set_whole_file_lock(inode_file, LockKind::Shared, wait).await
+        }
+        FlockOps::LOCK_EX => {
+            file_lock::set_whole_file_lock(inode_file, LockKind::Exclusive, wait).await
+        }
+        FlockOps::LOCK_UN => file_lock::unlock_whole_file(inode_file),
+        _ => return_errno!(EINVAL, "invalid flock operation"),
+    }
+}
+
+bitflags! {
+    pub struct FlockOps: u32 {
+        /// Place a shared lock
+        const LOCK_SH = 1;
+        /// Place an exclusive lock
+        const LOCK_EX = 2;
+        /// Do not block when the lock is held by others
+        const LOCK_NB = 4;
+        /// Remove the existing lock
+        const LOCK_UN = 8;
+    }
+}
+
 /// C struct for a lock
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -45,10 +79,73 @@ impl Flock {
             l_pid: flock_c.l_pid,
         })
     }
+
+    /// Returns the range lock to be set or removed on the file.
+    pub fn to_range_lock(&self, file: &InodeFile, owner: LockOwner) -> Result<RangeLock> {
+        let kind = match self.l_type {
+            FlockType::F_RDLCK => LockKind::Shared,
+            FlockType::F_WRLCK => LockKind::Exclusive,
+            // The kind makes no difference for unlocking
+            FlockType::F_UNLCK => LockKind::Shared,
+        };
+        let base = match self.l_whence {
+            FlockWhence::SEEK_SET => 0,
+            FlockWhence::SEEK_CUR => file.seek(SeekFrom::Current(0))? as off_t,
+            FlockWhence::SEEK_END => file.inode().metadata()?.size as off_t,
+        };
+        let start = base
+            .checked_add(self.l_start)
+            .ok_or_else(|| errno!(EOVERFLOW, "the start is too large"))?;
+        // A negative length covers the bytes before the start
+        let (start, end) = if self.l_len > 0 {
+            let end = start
+                .checked_add(self.l_len)
+                .ok_or_else(|| errno!(EOVERFLOW, "the length is too large"))?;
+            (start, end as usize)
+        } else if self.l_len < 0 {
+            (start.saturating_add(self.l_len), start as usize)
+        } else {
+            (start, usize::MAX)
+        };
+        if start < 0 {
+            return_errno!(EINVAL, "the range starts before the beginning of the file");
+        }
+        Ok(RangeLock {
+            owner,
+            kind,
+            start: start as usize,
+            end,
+        })
+    }
+
+    /// Describes the range lock in the way of `F_GETLK`.
+    pub fn from_range_lock(lock: &RangeLock) -> Self {
+        let l_type = match lock.kind {
+            LockKind::Shared => FlockType::F_RDLCK,
+            LockKind::Exclusive => FlockType::F_WRLCK,
+        };
+        let l_len = if lock.end == usize::MAX {
+            0
+        } else {
+            (lock.end - lock.start) as off_t
+        };
+        // An OFD lock is not owned by any process
+        let l_pid = match lock.owner {
+            LockOwner::Process(pid) => pid,
+            LockOwner::File(_) => -1i32 as pid_t,
+        };
+        Self {
+            l_type,
+            l_whence: FlockWhence::SEEK_SET,
+            l_start: lock.start as off_t,
+            l_len,
+            l_pid,
+        }
+    }
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(u16)]
 pub enum FlockType {
     F_RDLCK = 0,
@@ -7,7 +7,7 @@ pub async fn do_fsync(fd: FileDesc) -> Result<()> {
         .as_inode_file()
         .ok_or_else(|| errno!(EINVAL, "not an inode"))?;
     flush_vm_backed_by(&file_ref);
-    inode_file.inode().sync_all()?;
+    inode_file.sync_all().await?;
     Ok(())
 }
 
@@ -18,7 +18,7 @@ pub async fn do_fdatasync(fd: FileDesc) -> Result<()> {
         .as_inode_file()
         .ok_or_else(|| errno!(EINVAL, "not an inode"))?;
     flush_vm_backed_by(&file_ref);
-    inode_file.inode().sync_data()?;
+    inode_file.sync_data().await?;
     Ok(())
 }
 
@@ -1,8 +1,10 @@
 //! Built-in ioctls.
 
+use async_io::impl_ioctl_cmd;
+
 use super::*;
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy)]
 #[repr(C)]
 pub struct WinSize {
     pub ws_row: u16,
@@ -11,17 +13,17 @@ pub struct WinSize {
     pub ws_ypixel: u16,
 }
 
-#[derive(Debug)]
-#[repr(C)]
-pub struct IfConf {
-    pub ifc_len: i32,
-    pub ifc_buf: *const u8,
+impl_ioctl_cmd! {
+    /// Get the window size of a terminal (`TIOCGWINSZ`).
+    pub struct GetWinSize<Input=(), Output=WinSize> {}
 }
 
-const IFNAMSIZ: usize = 16;
-#[derive(Debug)]
-#[repr(C)]
-pub struct IfReq {
-    pub ifr_name: [u8; IFNAMSIZ],
-    pub ifr_union: [u8; 24],
+impl_ioctl_cmd! {
+    /// Set the window size of a terminal (`TIOCSWINSZ`).
+    pub struct SetWinSize<Input=WinSize, Output=()> {}
+}
+
+impl_ioctl_cmd! {
+    /// Get the number of bytes that are immediately available for reading (`FIONREAD`).
+    pub struct GetReadBufLen<Input=(), Output=usize> {}
 }
@@ -3,96 +3,152 @@
 //! A builtin ioctl is defined as part of the OS kernel and is used by various
 //! OS sub-system. In contrast, an non-builtin ioctl is specific to a device or
 //! driver.
+//!
+//! Just like socket options, each ioctl is represented by an ioctl-style command
+//! (see `async_io::ioctl`), which is handled by the `ioctl` method of a file.
+
+use async_io::ioctl::IoctlCmd;
+use async_io::{match_ioctl_cmd_mut, match_ioctl_cmd_ref};
 
 use super::*;
+use crate::util::mem_util::from_user;
 
 pub use self::builtin::*;
 pub use self::non_builtin::{NonBuiltinIoctlCmd, StructuredIoctlArgType, StructuredIoctlNum};
 
-#[macro_use]
-mod macros;
 mod builtin;
 mod non_builtin;
 
-/// This is the centralized place to define built-in ioctls.
-///
-/// By giving the names, numbers, and argument types of built-in ioctls,
-/// the macro below generates the corresponding code of `BuiltinIoctlNum` and
-/// `IoctlCmd`.
-///
-/// To add a new built-in ioctl, just follow the convention as shown
-/// by existing built-in ioctls.
-impl_ioctl_nums_and_cmds! {
-    // Format:
-    // ioctl_name => (ioctl_num, ioctl_type_arg)
-
-    // Get window size
-    TIOCGWINSZ => (0x5413, mut WinSize),
-    // Set window size
-    TIOCSWINSZ => (0x5414, WinSize),
-    // Set the nonblocking mode for socket
-    FIONBIO => (0x5421, i32),
-    // If the given terminal was the controlling terminal of the calling process, give up this
-    // controlling terminal. If the process was session leader, then send SIGHUP and SIGCONT to
-    // the foreground process group and all processes in the current session lose their controlling
-    // terminal
-    TIOCNOTTY => (0x5422, ()),
-    // Get the number of bytes in the input buffer
-    FIONREAD => (0x541B, mut i32),
-    // Low-level access to Linux network devices on man7/netdevice.7
-    // Only non-privileged operations are supported for now
-    SIOCGIFNAME => (0x8910, mut IfReq),
-    SIOCGIFCONF => (0x8912, mut IfConf),
-    SIOCGIFFLAGS => (0x8913, mut IfReq),
-    SIOCGIFADDR => (0x8915, mut IfReq),
-    SIOCGIFDSTADDR => (0x8917, mut IfReq),
-    SIOCGIFBRDADDR => (0x8919, mut IfReq),
-    SIOCGIFNETMASK => (0x891B, mut IfReq),
-    SIOCGIFMTU => (0x8921, mut IfReq),
-    SIOCGIFHWADDR => (0x8927, mut IfReq),
-    SIOCGIFINDEX => (0x8933, mut IfReq),
-    SIOCGIFPFLAGS => (0x8935, mut IfReq),
-    SIOCGIFTXQLEN => (0x8942, mut IfReq),
-    SIOCGIFMAP => (0x8970, mut IfReq),
-}
+// The numbers of builtin ioctls
+// Get window size
+const TIOCGWINSZ: u32 = 0x5413;
+// Set window size
+const TIOCSWINSZ: u32 = 0x5414;
+// Get the number of bytes in the input buffer
+const FIONREAD: u32 = 0x541B;
+// Set the nonblocking mode of a file
+const FIONBIO: u32 = 0x5421;
+// Clear the close-on-exec flag of a file descriptor
+const FIONCLEX: u32 = 0x5450;
+// Set the close-on-exec flag of a file descriptor
+const FIOCLEX: u32 = 0x5451;
 
-/// This is the centralized place to add sanity checks for the argument values
-/// of built-in ioctls.
-///
-/// Sanity checks are mostly useful when the argument values are returned from
-/// the untrusted host OS.
-impl<'a> IoctlCmd<'a> {
-    pub fn validate_arg_and_ret_vals(&self, ret: i32) -> Result<()> {
-        match self {
-            IoctlCmd::TIOCGWINSZ(winsize_ref) => {
-                // ws_row and ws_col are usually not zeros
-                if winsize_ref.ws_row == 0 || winsize_ref.ws_col == 0 {
-                    warn!(
-                        "window size: row: {:?}, col: {:?}",
-                        winsize_ref.ws_row, winsize_ref.ws_col
-                    );
-                }
-            }
-            IoctlCmd::FIONREAD(nread_ref) => {
-                if (**nread_ref < 0) {
-                    return_errno!(EINVAL, "invalid data from host");
-                }
-            }
-            _ => {}
+pub fn do_ioctl(fd: FileDesc, cmd_num: u32, argp: *mut u8) -> Result<()> {
+    debug!("ioctl: fd: {}, cmd: {:#x}, argp: {:?}", fd, cmd_num, argp);
+
+    let current = current!();
+    // Just like Linux, the ioctls that apply to any file are handled here
+    match cmd_num {
+        FIOCLEX | FIONCLEX => {
+            let mut file_table = current.files().lock().unwrap();
+            let entry = file_table.get_entry_mut(fd)?;
+            entry.set_close_on_spawn(cmd_num == FIOCLEX);
+            return Ok(());
         }
+        FIONBIO => {
+            let file_ref = current.file(fd)?;
+            let is_nonblocking = read_arg::<c_int>(argp)? != 0;
+            let mut status_flags = file_ref.status_flags();
+            status_flags.set(StatusFlags::O_NONBLOCK, is_nonblocking);
+            return file_ref.set_status_flags(status_flags);
+        }
+        _ => {}
+    }
 
-        // Current ioctl commands all return zero
-        if ret != 0 {
-            return_errno!(EINVAL, "return value should be zero");
+    let file_ref = current.file(fd)?;
+    let mut cmd = new_ioctl_cmd(cmd_num, argp)?;
+    file_ref.ioctl(cmd.as_mut())?;
+    copy_ioctl_output(cmd.as_ref(), argp)
+}
+
+fn new_ioctl_cmd(cmd_num: u32, argp: *mut u8) -> Result<Box<dyn IoctlCmd>> {
+    let cmd: Box<dyn IoctlCmd> = match cmd_num {
+        TIOCGWINSZ => Box::new(GetWinSize::new(())),
+        TIOCSWINSZ => Box::new(SetWinSize::new(read_arg::<WinSize>(argp)?)),
+        FIONREAD => Box::new(GetReadBufLen::new(())),
+        _ => {
+            let structured_cmd_num = StructuredIoctlNum::from_u32(cmd_num)
+                .map_err(|_| errno!(ENOTTY, "unknown ioctl cmd"))?;
+            Box::new(unsafe { NonBuiltinIoctlCmd::new(structured_cmd_num, argp)? })
         }
-        Ok(())
+    };
+    Ok(cmd)
+}
+
+/// Copy the output of a completed ioctl command to the user space.
+fn copy_ioctl_output(cmd: &dyn IoctlCmd, argp: *mut u8) -> Result<()> {
+    fn output_of<T>(output: Option<&T>) -> Result<&T> {
+        output.ok_or_else(|| errno!(EINVAL, "the ioctl cmd is not handled"))
     }
+
+    match_ioctl_cmd_ref!(cmd, {
+        cmd: GetWinSize => write_arg(argp, *output_of(cmd.output())?),
+        cmd: GetReadBufLen => {
+            let len = (*output_of(cmd.output())?).min(c_int::max_value() as usize);
+            write_arg(argp, len as c_int)
+        },
+        // The other commands have no output or write the output in place
+        _ => Ok(())
+    })
+}
+
+fn read_arg<T: Copy>(argp: *mut u8) -> Result<T> {
+    let ptr = argp as *const T;
+    from_user::check_ptr(ptr)?;
+    Ok(unsafe { ptr.read_unaligned() })
+}
+
+fn write_arg<T: Copy>(argp: *mut u8, val: T) -> Result<()> {
+    let ptr = argp as *mut T;
+    from_user::check_mut_ptr(ptr)?;
+    unsafe { ptr.write_unaligned(val) };
+    Ok(())
 }
 
-pub fn do_ioctl(fd: FileDesc, cmd: &mut IoctlCmd) -> Result<i32> {
-    debug!("ioctl: fd: {}, cmd: {:?}", fd, cmd);
-    let file_ref = current!().file(fd)?;
-    file_ref.ioctl(cmd)
+/// Delegate an ioctl on a terminal, e.g., the stdio, to the host.
+pub fn do_host_tty_ioctl(host_fd: FileDesc, cmd: &mut dyn IoctlCmd) -> Result<()> {
+    match_ioctl_cmd_mut!(&mut *cmd, {
+        cmd: GetWinSize => {
+            let mut winsize = WinSize::default();
+            do_host_ioctl(host_fd, TIOCGWINSZ, &mut winsize)?;
+            // ws_row and ws_col are usually not zeros
+            if winsize.ws_row == 0 || winsize.ws_col == 0 {
+                warn!(
+                    "window size: row: {:?}, col: {:?}",
+                    winsize.ws_row, winsize.ws_col
+                );
+            }
+            cmd.set_output(winsize);
+            Ok(())
+        },
+        cmd: SetWinSize => {
+            let mut winsize = *cmd.input();
+            do_host_ioctl(host_fd, TIOCSWINSZ, &mut winsize)
+        },
+        _ => {
+            return_errno!(ENOTTY, "unknown ioctl cmd for a terminal");
+        }
+    })
+}
+
+fn do_host_ioctl<T>(host_fd: FileDesc, cmd_num: u32, arg: &mut T) -> Result<()> {
+    let ret = try_libc!({
+        let mut retval: i32 = 0;
+        let status = occlum_ocall_ioctl(
+            &mut retval as *mut i32,
+            host_fd as c_int,
+            cmd_num as c_int,
+            arg as *mut T as *mut c_void,
+            std::mem::size_of::<T>(),
+        );
+        assert!(status == sgx_status_t::SGX_SUCCESS);
+        retval
+    });
+    // Current builtin ioctls all return zero
+    if ret != 0 {
+        return_errno!(EINVAL, "return value should be zero");
+    }
+    Ok(())
 }
 
 extern "C" {
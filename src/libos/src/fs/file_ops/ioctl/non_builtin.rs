@@ -2,28 +2,30 @@
 
 use super::*;
 
+/// A device-specific ioctl, whose argument is an untyped buffer in the user space.
+///
+/// The argument is checked to be in the user space, but it is up to the device
+/// to interpret it.
 #[derive(Debug)]
-pub struct NonBuiltinIoctlCmd<'a> {
+pub struct NonBuiltinIoctlCmd {
     cmd_num: StructuredIoctlNum,
-    arg_buf: Option<&'a mut [u8]>,
+    arg_ptr: *mut u8,
 }
 
-impl<'a> NonBuiltinIoctlCmd<'a> {
-    pub unsafe fn new(
-        cmd_num: StructuredIoctlNum,
-        arg_ptr: *mut u8,
-    ) -> Result<NonBuiltinIoctlCmd<'a>> {
-        let arg_buf = if cmd_num.require_arg() {
+impl IoctlCmd for NonBuiltinIoctlCmd {}
+
+impl NonBuiltinIoctlCmd {
+    pub unsafe fn new(cmd_num: StructuredIoctlNum, arg_ptr: *mut u8) -> Result<NonBuiltinIoctlCmd> {
+        let arg_ptr = if cmd_num.require_arg() {
             if arg_ptr.is_null() {
                 return_errno!(EINVAL, "arg_ptr must be provided for the ioctl");
             }
-            let arg_size = cmd_num.arg_size();
-            let arg_slice = unsafe { std::slice::from_raw_parts_mut::<'a>(arg_ptr, arg_size) };
-            Some(arg_slice)
+            from_user::check_mut_array(arg_ptr, cmd_num.arg_size())?;
+            arg_ptr
         } else {
-            None
+            std::ptr::null_mut()
         };
-        Ok(NonBuiltinIoctlCmd { cmd_num, arg_buf })
+        Ok(NonBuiltinIoctlCmd { cmd_num, arg_ptr })
     }
 
     pub fn cmd_num(&self) -> &StructuredIoctlNum {
@@ -41,7 +43,7 @@ impl<'a> NonBuiltinIoctlCmd<'a> {
             );
         }
 
-        let arg_ref = unsafe { &*(self.arg_buf.as_ref().unwrap().as_ptr() as *const T) };
+        let arg_ref = unsafe { &*(self.arg_ptr as *const T) };
         Ok(arg_ref)
     }
 
@@ -56,14 +58,12 @@ impl<'a> NonBuiltinIoctlCmd<'a> {
             );
         }
 
-        let arg_mut = unsafe { &mut *(self.arg_buf.as_mut().unwrap().as_mut_ptr() as *mut T) };
+        let arg_mut = unsafe { &mut *(self.arg_ptr as *mut T) };
         Ok(arg_mut)
     }
 
     pub fn arg_ptr(&self) -> *const u8 {
-        self.arg_buf
-            .as_ref()
-            .map_or(std::ptr::null(), |arg_slice| arg_slice.as_ptr())
+        self.arg_ptr
     }
 
     pub fn arg_len(&self) -> usize {
@@ -20,6 +20,7 @@ pub fn do_linkat(old_fs_path: &FsPath, new_fs_path: &FsPath, flags: LinkFlags) -
         let new_dir_inode = fs.lookup_inode(new_dir_path)?;
         (inode, new_dir_inode)
     };
+    new_dir_inode.check_not_read_only()?;
     new_dir_inode.link(new_file_name, &inode)?;
     Ok(())
 }
@@ -13,6 +13,7 @@ pub fn do_mkdirat(fs_path: &FsPath, mode: usize) -> Result<()> {
     if inode.find(file_name).is_ok() {
         return_errno!(EEXIST, "");
     }
+    inode.check_not_read_only()?;
     if !inode.allow_write()? {
         return_errno!(EPERM, "dir cannot be written");
     }
@@ -5,17 +5,19 @@ pub use self::access::{do_faccessat, AccessibilityCheckFlags, AccessibilityCheck
 pub use self::chmod::{do_fchmod, do_fchmodat};
 pub use self::chown::{do_fchown, do_fchownat, ChownFlags};
 pub use self::close::do_close;
-// pub use self::dirent::{do_getdents, do_getdents64};
+pub use self::dirent::{do_getdents, do_getdents64};
 pub use self::dup::{do_dup, do_dup2, do_dup3};
-// pub use self::fcntl::{do_fcntl, FcntlCmd};
+pub use self::fadvise::do_fadvise;
+pub use self::fallocate::do_fallocate;
+pub use self::fcntl::{do_fcntl, FcntlCmd};
 // pub use self::file_flags::{AccessMode, CreationFlags, StatusFlags};
-// pub use self::flock::{Flock, FlockType};
+pub use self::flock::{do_flock, FlockOps};
 pub use self::fspath::{FsPath, AT_FDCWD};
 pub use self::fsync::{do_fdatasync, do_fsync};
-// pub use self::ioctl::{
-//     do_ioctl, occlum_ocall_ioctl, BuiltinIoctlNum, IfConf, IoctlCmd, StructuredIoctlArgType,
-//     StructuredIoctlNum,
-// };
+pub use self::ioctl::{
+    do_host_tty_ioctl, do_ioctl, GetReadBufLen, GetWinSize, NonBuiltinIoctlCmd, SetWinSize,
+    StructuredIoctlArgType, StructuredIoctlNum, WinSize,
+};
 pub use self::link::{do_linkat, LinkFlags};
 pub use self::lseek::do_lseek;
 pub use self::mkdir::do_mkdirat;
@@ -23,7 +25,7 @@ pub use self::open::do_openat;
 pub use self::read::{do_pread, do_read, do_readv};
 pub use self::rename::do_renameat;
 pub use self::rmdir::do_rmdir;
-// pub use self::sendfile::do_sendfile;
+pub use self::sendfile::do_sendfile;
 pub use self::stat::{do_fstat, do_fstatat};
 pub use self::symlink::{do_readlinkat, do_symlinkat};
 pub use self::truncate::{do_ftruncate, do_truncate};
@@ -34,14 +36,16 @@ mod access;
 mod chmod;
 mod chown;
 mod close;
-// mod dirent;
+mod dirent;
 mod dup;
-// mod fcntl;
+mod fadvise;
+mod fallocate;
+mod fcntl;
 // mod file_flags;
-// mod flock;
+mod flock;
 mod fspath;
 mod fsync;
-// mod ioctl;
+mod ioctl;
 mod link;
 mod lseek;
 mod mkdir;
@@ -49,7 +53,7 @@ mod open;
 mod read;
 mod rename;
 mod rmdir;
-// mod sendfile;
+mod sendfile;
 mod stat;
 mod symlink;
 mod truncate;
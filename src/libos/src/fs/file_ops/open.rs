@@ -1,6 +1,6 @@
 use super::*;
 
-pub fn do_openat(fs_path: &FsPath, flags: u32, mode: u32) -> Result<FileDesc> {
+pub async fn do_openat(fs_path: &FsPath, flags: u32, mode: u32) -> Result<FileDesc> {
     debug!(
         "openat: fs_path: {:?}, flags: {:#o}, mode: {:#o}",
         fs_path, flags, mode
@@ -8,10 +8,9 @@ pub fn do_openat(fs_path: &FsPath, flags: u32, mode: u32) -> Result<FileDesc> {
 
     let path = fs_path.to_abs_path()?;
     let current = current!();
-    let fs = current.fs().lock().unwrap();
-
-    let inode_file = fs.open_file(&path, flags, mode)?;
-    let file_ref = FileRef::new_inode(inode_file);
+    let inode_file = current.fs().lock().unwrap().open_file(&path, flags, mode)?;
+    inode_file.truncate_on_open(flags).await?;
+    let file_ref = FileRef::from_opened_inode(inode_file);
 
     let fd = {
         let creation_flags = CreationFlags::from_bits_truncate(flags);
@@ -18,8 +18,8 @@ pub async fn do_pread(fd: FileDesc, buf: &mut [u8], offset: off_t) -> Result<usi
         return_errno!(EINVAL, "the offset is negative");
     }
     let file_ref = current!().file(fd)?;
-    if let Some(inode_file) = file_ref.as_inode_file() {
-        inode_file.read_at(offset as usize, buf)
+    if file_ref.as_inode_file().is_some() {
+        file_ref.read_at(offset as usize, buf).await
     } else {
         // For non-inode files, we simply ignore the offset
         file_ref.read(buf).await
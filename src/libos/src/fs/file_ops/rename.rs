@@ -23,6 +23,8 @@ pub fn do_renameat(old_fs_path: &FsPath, new_fs_path: &FsPath) -> Result<()> {
     if old_file_mode.has_sticky_bit() {
         warn!("ignoring the sticky bit");
     }
+    old_dir_inode.check_not_read_only()?;
+    new_dir_inode.check_not_read_only()?;
     // TODO: support to modify file's absolute path
     old_dir_inode.move_(old_file_name, &new_dir_inode, new_file_name)?;
     Ok(())
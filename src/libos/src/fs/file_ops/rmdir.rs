@@ -13,6 +13,7 @@ pub fn do_rmdir(path: &str) -> Result<()> {
     if file_inode.metadata()?.type_ != FileType::Dir {
         return_errno!(ENOTDIR, "rmdir on not directory");
     }
+    dir_inode.check_not_read_only()?;
     dir_inode.unlink(file_name)?;
     Ok(())
 }
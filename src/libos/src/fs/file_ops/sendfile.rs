@@ -1,6 +1,6 @@
 use super::*;
 
-pub fn do_sendfile(
+pub async fn do_sendfile(
     out_fd: FileDesc,
     in_fd: FileDesc,
     offset: Option<off_t>,
@@ -15,35 +15,66 @@ pub fn do_sendfile(
     let current = current!();
     let in_file = current.file(in_fd)?;
     let out_file = current.file(out_fd)?;
-    let mut buffer: [u8; 1024 * 11] = unsafe { MaybeUninit::uninit().assume_init() };
+    if !in_file.access_mode().readable() {
+        return_errno!(EBADF, "in_fd is not opened for reading");
+    }
+    if !out_file.access_mode().writable() {
+        return_errno!(EBADF, "out_fd is not opened for writing");
+    }
+    if out_file.status_flags().contains(StatusFlags::O_APPEND) {
+        return_errno!(EINVAL, "out_fd must not be opened with O_APPEND");
+    }
+    // Just like Linux, the input file must be one that supports random access
+    let in_inode_file = in_file
+        .as_inode_file()
+        .ok_or_else(|| errno!(EINVAL, "in_fd does not support random access"))?;
 
     let mut read_offset = match offset {
-        Some(offset) => offset,
-        None => in_file.seek(SeekFrom::Current(0))?,
-    } as usize;
+        Some(offset) if offset < 0 => return_errno!(EINVAL, "the offset is negative"),
+        Some(offset) => offset as usize,
+        None => in_inode_file.seek(SeekFrom::Current(0))?,
+    };
 
     // read from specified offset and write new offset back
+    const BUF_SIZE: usize = 1024 * 11;
+    let mut buffer = vec![0u8; min(BUF_SIZE, count)];
     let mut bytes_read = 0;
     while bytes_read < count {
         let len = min(buffer.len(), count - bytes_read);
-        let read_len = in_file.read_at(read_offset, &mut buffer[..len])?;
+        let read_len = match in_file.read_at(read_offset, &mut buffer[..len]).await {
+            Ok(read_len) => read_len,
+            // Report the bytes that have been sent, if any
+            Err(_) if bytes_read > 0 => break,
+            Err(e) => return Err(e),
+        };
         if read_len == 0 {
             break;
         }
-        bytes_read += read_len;
-        read_offset += read_len;
         let mut bytes_written = 0;
         while bytes_written < read_len {
-            let write_len = out_file.write(&buffer[bytes_written..read_len])?;
+            let write_len = match out_file.write(&buffer[bytes_written..read_len]).await {
+                Ok(write_len) => write_len,
+                // Report the bytes that have been sent, if any
+                Err(_) if bytes_read + bytes_written > 0 => break,
+                Err(e) => return Err(e),
+            };
             if write_len == 0 {
+                if bytes_read + bytes_written > 0 {
+                    break;
+                }
                 return_errno!(EBADF, "sendfile write return 0");
             }
             bytes_written += write_len;
         }
+        bytes_read += bytes_written;
+        read_offset += bytes_written;
+        if bytes_written < read_len {
+            break;
+        }
     }
 
     if offset.is_none() {
-        in_file.seek(SeekFrom::Current(bytes_read as i64))?;
+        in_inode_file.seek(SeekFrom::Start(read_offset as u64))?;
     }
     Ok((bytes_read, read_offset))
 }
@@ -41,6 +41,7 @@ pub fn do_symlinkat(target: &str, link_path: &FsPath) -> Result<usize> {
         let fs = current.fs().lock().unwrap();
         fs.lookup_inode(dir_path)?
     };
+    dir_inode.check_not_read_only()?;
     if !dir_inode.allow_write()? {
         return_errno!(EPERM, "symlink cannot be created");
     }
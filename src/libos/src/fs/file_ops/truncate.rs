@@ -1,21 +1,27 @@
+use super::super::hostfs::HNode;
 use super::*;
 
-pub fn do_truncate(path: &str, len: usize) -> Result<()> {
+pub async fn do_truncate(path: &str, len: usize) -> Result<()> {
     debug!("truncate: path: {:?}, len: {}", path, len);
     let inode = {
         let current = current!();
         let fs = current.fs().lock().unwrap();
         fs.lookup_inode(&path)?
     };
-    inode.resize(len)?;
+    inode.check_not_read_only()?;
+    // The size of a host file is changed through the page cache
+    match HNode::from_inode(&inode) {
+        Some(hnode) => hnode.open_file()?.set_len(len).await?,
+        None => inode.resize(len)?,
+    }
     Ok(())
 }
 
-pub fn do_ftruncate(fd: FileDesc, len: usize) -> Result<()> {
+pub async fn do_ftruncate(fd: FileDesc, len: usize) -> Result<()> {
     debug!("ftruncate: fd: {}, len: {}", fd, len);
     let file_ref = current!().file(fd)?;
     if let Some(inode_file) = file_ref.as_inode_file() {
-        inode_file.inode().resize(len)?;
+        inode_file.set_len(len).await?;
         Ok(())
     } else {
         return_errno!(EINVAL, "not an inode file");
@@ -33,6 +33,7 @@ fn do_unlink(path: &str) -> Result<()> {
     if file_mode.has_sticky_bit() {
         warn!("ignoring the sticky bit");
     }
+    dir_inode.check_not_read_only()?;
     dir_inode.unlink(file_name)?;
     Ok(())
 }
@@ -3,13 +3,17 @@ use super::*;
 pub async fn do_write(fd: FileDesc, buf: &[u8]) -> Result<usize> {
     debug!("write: fd: {}", fd);
     let file_ref = current!().file(fd)?;
-    file_ref.write(buf).await
+    let res = file_ref.write(buf).await;
+    raise_sigpipe_on_epipe(&res).await;
+    res
 }
 
 pub async fn do_writev(fd: FileDesc, bufs: &[&[u8]]) -> Result<usize> {
     debug!("writev: fd: {}", fd);
     let file_ref = current!().file(fd)?;
-    file_ref.writev(bufs).await
+    let res = file_ref.writev(bufs).await;
+    raise_sigpipe_on_epipe(&res).await;
+    res
 }
 
 pub async fn do_pwrite(fd: FileDesc, buf: &[u8], offset: off_t) -> Result<usize> {
@@ -18,10 +22,18 @@ pub async fn do_pwrite(fd: FileDesc, buf: &[u8], offset: off_t) -> Result<usize>
         return_errno!(EINVAL, "the offset is negative");
     }
     let file_ref = current!().file(fd)?;
-    if let Some(inode_file) = file_ref.as_inode_file() {
-        inode_file.write_at(offset as usize, buf)
+    if file_ref.as_inode_file().is_some() {
+        file_ref.write_at(offset as usize, buf).await
     } else {
         // For non-inode files, we simply ignore the offset
         file_ref.write(buf).await
     }
 }
+
+/// Just like Linux, raise SIGPIPE if the pipe or the connection is broken.
+async fn raise_sigpipe_on_epipe(res: &Result<usize>) {
+    if res.has_errno(EPIPE) {
+        let tid = current!().tid();
+        let _ = crate::signal::do_tkill(tid, crate::signal::SIGPIPE.as_u8() as i32).await;
+    }
+}
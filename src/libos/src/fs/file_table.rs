@@ -78,7 +78,8 @@ impl FileTable {
         min_free_fd as FileDesc
     }
 
-    pub fn put_at(&mut self, fd: FileDesc, file: FileRef, close_on_spawn: bool) {
+    /// Put the file at the fd, returning the file that was at the fd, if any.
+    pub fn put_at(&mut self, fd: FileDesc, file: FileRef, close_on_spawn: bool) -> Option<FileRef> {
         let mut table = &mut self.table;
         let mut table_entry = Some(FileTableEntry::new(file, close_on_spawn));
         if fd as usize >= table.len() {
@@ -88,6 +89,7 @@ impl FileTable {
         if table_entry.is_none() {
             self.num_fds += 1;
         }
+        table_entry.map(|entry| entry.file)
     }
 
     pub fn get(&self, fd: FileDesc) -> Result<FileRef> {
@@ -119,6 +121,21 @@ impl FileTable {
         }
     }
 
+    /// Returns the file descriptors in use, in ascending order.
+    pub fn fds(&self) -> Vec<FileDesc> {
+        self.table
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.is_some())
+            .map(|(fd, _)| fd as FileDesc)
+            .collect()
+    }
+
+    /// Returns the number of the slots for file descriptors in the table.
+    pub fn capacity(&self) -> usize {
+        self.table.len()
+    }
+
     pub fn del(&mut self, fd: FileDesc) -> Result<FileRef> {
         if fd as usize >= self.table.len() {
             return_errno!(EBADF, "Invalid file descriptor");
@@ -2,8 +2,10 @@ use super::*;
 
 pub use self::chdir::do_chdir;
 pub use self::getcwd::do_getcwd;
+pub use self::mount::{do_mount, do_umount, MountFlags, UmountFlags};
 pub use self::sync::do_sync;
 
 mod chdir;
 mod getcwd;
+mod mount;
 mod sync;
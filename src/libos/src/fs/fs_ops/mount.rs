@@ -0,0 +1,400 @@
+use super::super::dev_fs;
+use super::super::hostfs::HostFS;
+use super::super::procfs::ProcFS;
+use super::super::rootfs::{self, normalize_path};
+use super::super::sefs::{SgxStorage, SgxUuidProvider};
+use super::*;
+use crate::config::{self, LIBOS_CONFIG};
+use crate::process::table;
+use rcore_fs::vfs;
+use rcore_fs_mountfs::MNode;
+use rcore_fs_ramfs::RamFS;
+use rcore_fs_sefs::SEFS;
+use std::path::{Path, PathBuf};
+use std::untrusted::fs as host_fs;
+
+bitflags! {
+    pub struct MountFlags: u32 {
+        const MS_RDONLY = 1;
+        const MS_NOSUID = 1 << 1;
+        const MS_NODEV = 1 << 2;
+        const MS_NOEXEC = 1 << 3;
+        const MS_SYNCHRONOUS = 1 << 4;
+        const MS_REMOUNT = 1 << 5;
+        const MS_MANDLOCK = 1 << 6;
+        const MS_DIRSYNC = 1 << 7;
+        const MS_NOATIME = 1 << 10;
+        const MS_NODIRATIME = 1 << 11;
+        const MS_BIND = 1 << 12;
+        const MS_MOVE = 1 << 13;
+        const MS_REC = 1 << 14;
+        const MS_SILENT = 1 << 15;
+        const MS_POSIXACL = 1 << 16;
+        const MS_UNBINDABLE = 1 << 17;
+        const MS_PRIVATE = 1 << 18;
+        const MS_SLAVE = 1 << 19;
+        const MS_SHARED = 1 << 20;
+        const MS_RELATIME = 1 << 21;
+        const MS_STRICTATIME = 1 << 24;
+        const MS_LAZYTIME = 1 << 25;
+    }
+}
+
+impl MountFlags {
+    pub fn from_u32(raw_flags: u32) -> Self {
+        // The upper 16 bits may be the magic number of the old API
+        const MS_MGC_MSK: u32 = 0xffff_0000;
+        const MS_MGC_VAL: u32 = 0xc0ed_0000;
+        let raw_flags = if raw_flags & MS_MGC_MSK == MS_MGC_VAL {
+            raw_flags & !MS_MGC_MSK
+        } else {
+            raw_flags
+        };
+        Self::from_bits_truncate(raw_flags)
+    }
+
+    fn propagation_types() -> Self {
+        Self::MS_UNBINDABLE | Self::MS_PRIVATE | Self::MS_SLAVE | Self::MS_SHARED
+    }
+}
+
+bitflags! {
+    pub struct UmountFlags: u32 {
+        const MNT_FORCE = 1;
+        const MNT_DETACH = 1 << 1;
+        const MNT_EXPIRE = 1 << 2;
+        const UMOUNT_NOFOLLOW = 1 << 3;
+    }
+}
+
+pub fn do_mount(
+    source: &str,
+    target: &str,
+    fs_type: &str,
+    flags: MountFlags,
+    options: &str,
+) -> Result<()> {
+    debug!(
+        "mount: source: {:?}, target: {:?}, fs_type: {:?}, flags: {:?}, options: {:?}",
+        source, target, fs_type, flags, options
+    );
+
+    let current = current!();
+    // The mount policy applies to the canonical path of the target, which is
+    // also the path that the mount is recorded with. A final symlink is not
+    // followed, so that it cannot redirect the mount out of the policy.
+    let (target, target_inode) = {
+        let fs = current.fs().lock().unwrap();
+        let target = resolve_path(&fs, target, false)?;
+        let target_inode = fs.lookup_inode_no_follow(target.to_str().unwrap())?;
+        (target, target_inode)
+    };
+    check_target(&target)?;
+
+    // There is only one mount namespace, so the propagation types make no difference
+    if flags.intersects(MountFlags::propagation_types()) {
+        return Ok(());
+    }
+    if flags.contains(MountFlags::MS_MOVE) {
+        return_errno!(EINVAL, "MS_MOVE is not supported");
+    }
+    let read_only = flags.contains(MountFlags::MS_RDONLY);
+    if flags.contains(MountFlags::MS_REMOUNT) {
+        // Just like Linux, a file system cannot be remounted read-only while
+        // its files are open for writing
+        if read_only {
+            check_no_open_files(&target, true)?;
+        }
+        return rootfs::remount_fs(&target, read_only);
+    }
+
+    if target_inode.metadata()?.type_ != FileType::Dir {
+        return_errno!(ENOTDIR, "target must be a directory");
+    }
+    let mount_dir = target_inode
+        .downcast_ref::<MNode>()
+        .ok_or_else(|| errno!(EINVAL, "target cannot be mounted on"))?;
+
+    if flags.contains(MountFlags::MS_BIND) {
+        let (source, source_inode) = {
+            let fs = current.fs().lock().unwrap();
+            let source = resolve_path(&fs, source, true)?;
+            let source_inode = fs.lookup_inode_no_follow(source.to_str().unwrap())?;
+            (source, source_inode)
+        };
+        check_bind_source(&source)?;
+        if source_inode.metadata()?.type_ != FileType::Dir {
+            return_errno!(ENOTDIR, "only directories can be bind mounted");
+        }
+        let bind_fs = Arc::new(BindFS { root: source_inode });
+        return rootfs::mount_fs_on(bind_fs, mount_dir, &target, None, read_only);
+    }
+
+    if fs_type == "devfs" || fs_type == "devtmpfs" {
+        let (devfs, shm_fs) = dev_fs::init_devfs(None)?;
+        rootfs::mount_fs_on(devfs, mount_dir, &target, None, read_only)?;
+        let shm_target = target.join("shm");
+        let shm_inode = current
+            .fs()
+            .lock()
+            .unwrap()
+            .lookup_inode(shm_target.to_str().unwrap())?;
+        let shm_dir = shm_inode
+            .downcast_ref::<MNode>()
+            .ok_or_else(|| errno!(EINVAL, "shm cannot be mounted on"))?;
+        return rootfs::mount_fs_on(shm_fs, shm_dir, &shm_target, None, read_only);
+    }
+
+    let (fs, host_source): (Arc<dyn FileSystem>, Option<PathBuf>) = match fs_type {
+        "sefs" => {
+            let source = check_host_source(source)?;
+            let sefs_options = SefsOptions::parse(options)?;
+            (open_sefs(&source, &sefs_options)?, None)
+        }
+        "hostfs" => {
+            let source = check_host_source(source)?;
+            (HostFS::new(&source), Some(source))
+        }
+        "ramfs" | "tmpfs" => (RamFS::new(), None),
+        "procfs" | "proc" => (ProcFS::new(), None),
+        _ => return_errno!(ENODEV, "unknown file system type"),
+    };
+    if fs_type != "sefs" && !options.is_empty() {
+        warn!("mount options {:?} are ignored for {}", options, fs_type);
+    }
+    rootfs::mount_fs_on(fs, mount_dir, &target, host_source.as_deref(), read_only)
+}
+
+pub fn do_umount(target: &str, flags: UmountFlags) -> Result<()> {
+    debug!("umount: target: {:?}, flags: {:?}", target, flags);
+
+    if flags.contains(UmountFlags::MNT_EXPIRE) {
+        return_errno!(EINVAL, "MNT_EXPIRE is not supported");
+    }
+    let target = {
+        let current = current!();
+        let fs = current.fs().lock().unwrap();
+        let follow_symlink = !flags.contains(UmountFlags::UMOUNT_NOFOLLOW);
+        let target = resolve_path(&fs, target, follow_symlink)?;
+        // Make sure that the target exists
+        fs.lookup_inode_no_follow(target.to_str().unwrap())?;
+        target
+    };
+    check_target(&target)?;
+
+    // A lazy unmount detaches the file system even if it is busy. The files
+    // that are still open keep working until they are closed.
+    if !flags.contains(UmountFlags::MNT_DETACH) {
+        check_not_busy(&target)?;
+    }
+    rootfs::umount_fs(&target)
+}
+
+fn check_target(target: &Path) -> Result<()> {
+    let policy = &LIBOS_CONFIG.mount_policy;
+    if !policy.targets.iter().any(|t| target.starts_with(t)) {
+        return_errno!(EPERM, "target is not allowed by the mount policy");
+    }
+    Ok(())
+}
+
+// The source of a bind mount, which is in the LibOS, is allowed just like a target
+fn check_bind_source(source: &Path) -> Result<()> {
+    let policy = &LIBOS_CONFIG.mount_policy;
+    if !policy.targets.iter().any(|t| source.starts_with(t)) {
+        return_errno!(EPERM, "source is not allowed by the mount policy");
+    }
+    Ok(())
+}
+
+fn check_host_source(source: &str) -> Result<PathBuf> {
+    let source = normalize_path(Path::new(source))
+        .ok_or_else(|| errno!(EINVAL, "source must be an absolute path on the host"))?;
+    let policy = &LIBOS_CONFIG.mount_policy;
+    if !policy.sources.iter().any(|s| source.starts_with(s)) {
+        return_errno!(EPERM, "source is not allowed by the mount policy");
+    }
+    Ok(source)
+}
+
+fn check_not_busy(target: &Path) -> Result<()> {
+    if !rootfs::mounts_under(target).is_empty() {
+        return_errno!(EBUSY, "other file systems are mounted under the target");
+    }
+    for process in table::get_all_processes() {
+        let thread = match process.main_thread() {
+            Some(thread) => thread,
+            None => continue,
+        };
+        if Path::new(thread.fs().lock().unwrap().cwd()).starts_with(target) {
+            return_errno!(EBUSY, "the target is the cwd of a process");
+        }
+    }
+    check_no_open_files(target, false)
+}
+
+// Check that no files of the file system mounted at the target are open, or
+// open for writing if `writable_only` is true.
+fn check_no_open_files(target: &Path, writable_only: bool) -> Result<()> {
+    for process in table::get_all_processes() {
+        let thread = match process.main_thread() {
+            Some(thread) => thread,
+            None => continue,
+        };
+        let files = thread.files().lock().unwrap();
+        for fd in files.fds() {
+            let file_ref = files.get(fd)?;
+            let inode_file = match file_ref.as_inode_file() {
+                Some(inode_file) => inode_file,
+                None => continue,
+            };
+            if writable_only && !inode_file.access_mode().writable() {
+                continue;
+            }
+            if rootfs::is_inode_in_mount(inode_file.inode(), target) {
+                return_errno!(EBUSY, "the target has open files");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Resolve a path to its canonical absolute path, in which the symlinks, `.`
+/// and `..` are all resolved, except for a final symlink if `follow_symlink`
+/// is false.
+fn resolve_path(fs: &FsView, path: &str, follow_symlink: bool) -> Result<PathBuf> {
+    // The same limit as that of `FsView::lookup_inode`
+    const MAX_SYMLINKS: usize = 40;
+
+    fn push_components(components: &mut Vec<String>, path: &str) {
+        let names = path.split('/').filter(|name| !name.is_empty());
+        components.extend(names.rev().map(String::from));
+    }
+
+    // Just like Linux, a final symlink is always followed if the path ends with a slash
+    let follow_symlink = follow_symlink || path.ends_with('/');
+    let mut resolved_path = PathBuf::from("/");
+    // The components that are yet to be resolved, in the reverse order
+    let mut components = Vec::new();
+    push_components(&mut components, &fs.convert_to_abs_path(path));
+    let mut num_symlinks = 0;
+    while let Some(name) = components.pop() {
+        match name.as_str() {
+            "." => continue,
+            // Just like Linux, the parent of the root is the root itself
+            ".." => {
+                resolved_path.pop();
+                continue;
+            }
+            _ => {}
+        }
+        let next_path = resolved_path.join(&name);
+        if components.is_empty() && !follow_symlink {
+            resolved_path = next_path;
+            break;
+        }
+        let inode = fs.lookup_inode_no_follow(next_path.to_str().unwrap())?;
+        if inode.metadata()?.type_ != FileType::SymLink {
+            resolved_path = next_path;
+            continue;
+        }
+
+        num_symlinks += 1;
+        if num_symlinks > MAX_SYMLINKS {
+            return_errno!(ELOOP, "too many symlinks");
+        }
+        let content = inode.read_as_vec()?;
+        let link =
+            std::str::from_utf8(&content).map_err(|_| errno!(ENOENT, "invalid symlink content"))?;
+        if link.starts_with('/') {
+            resolved_path = PathBuf::from("/");
+        }
+        push_components(&mut components, link);
+    }
+    Ok(resolved_path)
+}
+
+#[derive(Debug, Default)]
+struct SefsOptions {
+    // An integrity-only SEFS is given the MAC of its root
+    mac: Option<sgx_aes_gcm_128bit_tag_t>,
+    key: Option<sgx_key_128bit_t>,
+    temporary: bool,
+}
+
+impl SefsOptions {
+    fn parse(options: &str) -> Result<Self> {
+        let mut sefs_options = Self::default();
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            let mut name_value = option.splitn(2, '=');
+            match (name_value.next(), name_value.next()) {
+                (Some("MAC"), Some(mac)) => sefs_options.mac = Some(config::parse_mac(mac)?),
+                // The key is in the same format as the MAC
+                (Some("key"), Some(key)) => sefs_options.key = Some(config::parse_mac(key)?),
+                (Some("temporary"), None) => sefs_options.temporary = true,
+                _ => return_errno!(EINVAL, "unknown SEFS mount option"),
+            }
+        }
+        if sefs_options.mac.is_some() && (sefs_options.key.is_some() || sefs_options.temporary) {
+            return_errno!(
+                EINVAL,
+                "integrity-only SEFS cannot have a key or be temporary"
+            );
+        }
+        Ok(sefs_options)
+    }
+}
+
+fn open_sefs(source: &Path, options: &SefsOptions) -> Result<Arc<dyn FileSystem>> {
+    let new_storage = || -> Result<Box<SgxStorage>> {
+        let mut storage = SgxStorage::new(source, options.mac.is_some(), options.mac);
+        if let Some(key) = options.key {
+            storage.set_key(key)?;
+        }
+        Ok(Box::new(storage))
+    };
+    let sefs = if options.temporary {
+        SEFS::create(new_storage()?, &time::OcclumTimeProvider, &SgxUuidProvider)?
+    } else {
+        match SEFS::open(new_storage()?, &time::OcclumTimeProvider, &SgxUuidProvider) {
+            Ok(sefs) => sefs,
+            // An integrity-only SEFS is an image, which must have been created.
+            // And creating a SEFS wipes the source, so a SEFS that fails to open,
+            // e.g., with a wrong key, must not be replaced by a new one.
+            Err(e) if options.mac.is_some() || !is_absent_or_empty(source)? => return Err(e.into()),
+            Err(_) => {
+                host_fs::create_dir_all(source)?;
+                SEFS::create(new_storage()?, &time::OcclumTimeProvider, &SgxUuidProvider)?
+            }
+        }
+    };
+    Ok(sefs)
+}
+
+/// Returns whether the host directory is absent or empty, i.e., holds no data.
+fn is_absent_or_empty(dir: &Path) -> Result<bool> {
+    match host_fs::read_dir(dir) {
+        Ok(mut entries) => Ok(entries.next().is_none()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The file system of a bind mount, whose root is an existing directory.
+struct BindFS {
+    root: Arc<dyn INode>,
+}
+
+impl FileSystem for BindFS {
+    fn sync(&self) -> vfs::Result<()> {
+        self.root.fs().sync()
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        Arc::clone(&self.root)
+    }
+
+    fn info(&self) -> vfs::FsInfo {
+        self.root.fs().info()
+    }
+}
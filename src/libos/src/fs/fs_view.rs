@@ -64,6 +64,7 @@ impl FsView {
                 Err(e) if e.errno() == ENOENT && creation_flags.can_create() => {
                     let (dir_path, file_name) = split_path(&path);
                     let dir_inode = self.lookup_inode(dir_path)?;
+                    dir_inode.check_not_read_only()?;
                     if !dir_inode.allow_write()? {
                         return_errno!(EPERM, "file cannot be created");
                     }
@@ -91,6 +92,7 @@ impl FsView {
                     let real_path = self.lookup_real_path(&path)?;
                     let (dir_path, file_name) = split_path(&real_path);
                     let dir_inode = self.lookup_inode(dir_path)?;
+                    dir_inode.check_not_read_only()?;
                     if !dir_inode.allow_write()? {
                         return_errno!(EPERM, "file cannot be created");
                     }
@@ -103,6 +105,17 @@ impl FsView {
         Ok(INodeFile::open(inode, &abs_path, flags)?)
     }
 
+    /// Create an inode of the given type at the given path, which must not exist.
+    pub fn create_inode(&self, path: &str, type_: FileType, mode: u32) -> Result<Arc<dyn INode>> {
+        let (dir_path, file_name) = split_path(&path);
+        let dir_inode = self.lookup_inode(dir_path)?;
+        dir_inode.check_not_read_only()?;
+        if !dir_inode.allow_write()? {
+            return_errno!(EPERM, "file cannot be created");
+        }
+        Ok(dir_inode.create(file_name, type_, mode)?)
+    }
+
     /// Recursively lookup the real path of giving path, dereference symlinks
     pub fn lookup_real_path(&self, path: &str) -> Result<String> {
         let (dir_path, file_name) = split_path(&path);
@@ -1,13 +1,22 @@
-use alloc::string::String;
+use alloc::string::{String, ToString};
 use alloc::sync::{Arc, Weak};
+use async_file::{AsyncFile, Page};
 use core::any::Any;
+use errno::prelude::{Errno::*, ToErrno};
 use rcore_fs::vfs::*;
-use std::io::{Read, Seek, SeekFrom, Write};
+use rcore_fs_mountfs::MNode;
+use sgx_trts::libc;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
-use std::sync::{SgxMutex as Mutex, SgxMutexGuard as MutexGuard};
+use std::sync::SgxMutex as Mutex;
 use std::untrusted::fs;
 use std::untrusted::path::PathEx;
 
+use super::async_file_rt::HostFileRt;
+
+/// A regular file of HostFS, whose I/O goes through the page cache.
+pub type HostFile = AsyncFile<HostFileRt>;
+
 /// Untrusted file system at host
 pub struct HostFS {
     path: PathBuf,
@@ -17,7 +26,7 @@ pub struct HostFS {
 /// INode for `HostFS`
 pub struct HNode {
     path: PathBuf,
-    file: Mutex<Option<fs::File>>,
+    file: Mutex<Option<Arc<HostFile>>>,
     fs: Arc<HostFS>,
 }
 
@@ -73,29 +82,64 @@ macro_rules! try_std {
 }
 
 impl INode for HNode {
+    // The I/O of the opened files goes through `HostFile`, which can wait for
+    // the page cache. The sync methods here are for the code paths that cannot
+    // wait, e.g., loading executables or mapping files.
+
     fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
-        let mut guard = self.open_file()?;
-        let file = guard.as_mut().unwrap();
-        try_std!(file.seek(SeekFrom::Start(offset as u64)));
-        let len = try_std!(file.read(buf));
-        Ok(len)
+        let file = self.open_file()?;
+        let file_len = file.len();
+        if offset >= file_len {
+            return Ok(0);
+        }
+        let buf_len = buf.len().min(file_len - offset);
+        // The pages that are not ready in the page cache are read from the host
+        // directly, which is up to date since such pages are never dirty.
+        let mut host_file = None;
+        let mut read_nbytes = 0;
+        while read_nbytes < buf_len {
+            let pos = offset + read_nbytes;
+            match file.read_at(pos, &mut buf[read_nbytes..buf_len]) {
+                Ok(0) => break,
+                Ok(nbytes) => read_nbytes += nbytes,
+                Err(e) if e.errno() == EAGAIN => {
+                    let page_remain = Page::size() - pos % Page::size();
+                    let len = page_remain.min(buf_len - read_nbytes);
+                    let buf = &mut buf[read_nbytes..read_nbytes + len];
+                    self.read_host_at(&mut host_file, pos, buf)?;
+                    read_nbytes += len;
+                }
+                Err(_) if read_nbytes > 0 => break,
+                Err(e) => return Err(e.into_fs_error()),
+            }
+        }
+        Ok(read_nbytes)
     }
 
     fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
-        let mut guard = self.open_file()?;
-        let file = guard.as_mut().unwrap();
-        try_std!(file.seek(SeekFrom::Start(offset as u64)));
-        let len = try_std!(file.write(buf));
-        Ok(len)
+        // The write stops at the first page that is not ready in the page cache,
+        // or fails with `FsError::Again` if no bytes can be written.
+        let file = self.open_file()?;
+        file.write_at(offset, buf).map_err(|e| e.into_fs_error())
     }
 
     fn poll(&self) -> Result<PollStatus> {
-        unimplemented!()
+        // A regular file is always ready for I/O
+        Ok(PollStatus {
+            read: true,
+            write: true,
+            error: false,
+        })
     }
 
     fn metadata(&self) -> Result<Metadata> {
         let metadata = try_std!(self.path.metadata());
-        Ok(metadata.into_fs_metadata())
+        let mut metadata = metadata.into_fs_metadata();
+        // The host is not aware of the data that is not yet flushed
+        if let Some(file) = self.file.lock().unwrap().as_ref() {
+            metadata.size = metadata.size.max(file.len());
+        }
+        Ok(metadata)
     }
 
     fn set_metadata(&self, metadata: &Metadata) -> Result<()> {
@@ -103,19 +147,19 @@ impl INode for HNode {
         Ok(())
     }
 
+    // Syncing and resizing have to wait for the page cache. So they are done
+    // by `HostFile::flush`, `HostFile::sync_data` and `HostFile::set_len`.
+
     fn sync_all(&self) -> Result<()> {
-        warn!("HostFS: sync_all() is unimplemented");
-        Ok(())
+        Err(FsError::NotSupported)
     }
 
     fn sync_data(&self) -> Result<()> {
-        warn!("HostFS: sync_data() is unimplemented");
-        Ok(())
+        Err(FsError::NotSupported)
     }
 
-    fn resize(&self, len: usize) -> Result<()> {
-        warn!("HostFS: resize() is unimplemented");
-        Ok(())
+    fn resize(&self, _len: usize) -> Result<()> {
+        Err(FsError::NotSupported)
     }
 
     fn create(&self, name: &str, type_: FileType, mode: u32) -> Result<Arc<dyn INode>> {
@@ -211,10 +255,10 @@ impl INode for HNode {
 }
 
 impl HNode {
-    /// Ensure to open the file and store a `File` into `self.file`,
-    /// return the `MutexGuard`.
+    /// Ensure to open the file and store a `HostFile` into `self.file`,
+    /// return the `HostFile`.
     /// If the type of `self.path` is not file, then return Err
-    fn open_file(&self) -> Result<MutexGuard<Option<fs::File>>> {
+    pub fn open_file(&self) -> Result<Arc<HostFile>> {
         if !self.path.exists() {
             return Err(FsError::EntryNotFound);
         }
@@ -222,15 +266,72 @@ impl HNode {
             return Err(FsError::NotFile);
         }
         let mut maybe_file = self.file.lock().unwrap();
-        if maybe_file.is_none() {
-            let file = try_std!(fs::OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .open(&self.path));
-            *maybe_file = Some(file);
+        if let Some(file) = maybe_file.as_ref() {
+            return Ok(file.clone());
+        }
+
+        let path = self.path.to_str().ok_or(FsError::InvalidParam)?.to_string();
+        // The file is shared by all opened instances. So open it for both read
+        // and write, unless the host only allows reading it.
+        let file = HostFile::open(path.clone(), libc::O_RDWR, 0)
+            .or_else(|e| match e.errno() {
+                EACCES | EROFS => HostFile::open(path, libc::O_RDONLY, 0),
+                _ => Err(e),
+            })
+            .map_err(|e| e.into_fs_error())?;
+        *maybe_file = Some(file.clone());
+        Ok(file)
+    }
+
+    /// Open the file for direct I/O, which bypasses the page cache.
+    ///
+    /// Unlike `open_file`, each call returns a new `HostFile`.
+    pub fn open_direct_file(&self, writable: bool) -> Result<Arc<HostFile>> {
+        if !self.path.is_file() {
+            return Err(FsError::NotFile);
         }
-        Ok(maybe_file)
+        let path = self.path.to_str().ok_or(FsError::InvalidParam)?.to_string();
+        let access_mode = if writable {
+            libc::O_RDWR
+        } else {
+            libc::O_RDONLY
+        };
+        HostFile::open(path, access_mode | libc::O_DIRECT, 0).map_err(|e| e.into_fs_error())
+    }
+
+    // Read from the host file directly, which is opened upon the first read. The
+    // bytes beyond the end of the host file, which are yet to be flushed, are
+    // zeros.
+    fn read_host_at(
+        &self,
+        host_file: &mut Option<fs::File>,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<()> {
+        if host_file.is_none() {
+            *host_file = Some(try_std!(fs::File::open(&self.path)));
+        }
+        let host_file = host_file.as_mut().unwrap();
+        try_std!(host_file.seek(SeekFrom::Start(offset as u64)));
+        let mut read_nbytes = 0;
+        while read_nbytes < buf.len() {
+            match try_std!(host_file.read(&mut buf[read_nbytes..])) {
+                0 => break,
+                nbytes => read_nbytes += nbytes,
+            }
+        }
+        buf[read_nbytes..].fill(0);
+        Ok(())
+    }
+
+    /// Returns the HostFS inode behind an inode, if there is one.
+    pub fn from_inode(inode: &Arc<dyn INode>) -> Option<&Self> {
+        // HostFS is mounted, so its inodes are wrapped by MountFS
+        let inode = match inode.downcast_ref::<MNode>() {
+            Some(mnode) => &mnode.inode,
+            None => inode,
+        };
+        inode.downcast_ref::<Self>()
     }
 }
 
@@ -238,6 +339,22 @@ trait IntoFsError {
     fn into_fs_error(self) -> FsError;
 }
 
+impl IntoFsError for errno::Error {
+    fn into_fs_error(self) -> FsError {
+        match self.errno() {
+            ENOENT => FsError::EntryNotFound,
+            EEXIST => FsError::EntryExist,
+            EAGAIN => FsError::Again,
+            EINVAL => FsError::InvalidParam,
+            EISDIR => FsError::IsDir,
+            ENOSPC => FsError::NoDeviceSpace,
+            EROFS => FsError::WrProtected,
+            EACCES | EPERM => FsError::PermError,
+            _ => FsError::NotSupported,
+        }
+    }
+}
+
 impl IntoFsError for std::io::Error {
     fn into_fs_error(self) -> FsError {
         use std::io::ErrorKind;
@@ -258,7 +375,6 @@ trait IntoFsMetadata {
 
 impl IntoFsMetadata for fs::Metadata {
     fn into_fs_metadata(self) -> Metadata {
-        use sgx_trts::libc;
         use std::os::fs::MetadataExt;
         Metadata {
             dev: self.st_dev() as usize,
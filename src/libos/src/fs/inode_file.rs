@@ -1,4 +1,11 @@
+use super::file_lock;
+use super::file_ops::{GetReadBufLen, NonBuiltinIoctlCmd};
+use super::hostfs::{HNode, HostFile};
+use super::rootfs;
 use super::*;
+use async_file::Advice;
+use async_io::ioctl::IoctlCmd;
+use async_io::match_ioctl_cmd_mut;
 use rcore_fs_sefs::dev::SefsMac;
 
 // TODO: rename all INodeFile to InodeFile
@@ -6,6 +13,9 @@ pub use self::INodeFile as InodeFile;
 
 pub struct INodeFile {
     inode: Arc<dyn INode>,
+    // The regular files of HostFS are accessed through the page cache, unless
+    // they are opened with O_DIRECT
+    host_file: Option<Arc<HostFile>>,
     abs_path: String,
     offset: SgxMutex<usize>,
     access_mode: AccessMode,
@@ -18,6 +28,9 @@ impl INodeFile {
         if (access_mode.readable() && !inode.allow_read()?) {
             return_errno!(EACCES, "File not readable");
         }
+        if access_mode.writable() {
+            inode.check_not_read_only()?;
+        }
         if (access_mode.writable() && !inode.allow_write()?) {
             return_errno!(EACCES, "File not writable");
         }
@@ -25,8 +38,19 @@ impl INodeFile {
             return_errno!(EISDIR, "Directory cannot be open to write");
         }
         let status_flags = StatusFlags::from_bits_truncate(flags);
+        let host_file = match HNode::from_inode(&inode) {
+            Some(hnode) if inode.metadata()?.type_ == FileType::File => {
+                if status_flags.contains(StatusFlags::O_DIRECT) {
+                    Some(hnode.open_direct_file(access_mode.writable())?)
+                } else {
+                    Some(hnode.open_file()?)
+                }
+            }
+            _ => None,
+        };
         Ok(INodeFile {
             inode,
+            host_file,
             abs_path: abs_path.to_owned(),
             offset: SgxMutex::new(0),
             access_mode,
@@ -43,7 +67,7 @@ impl INodeFile {
             return_errno!(EACCES, "File not readable");
         }
         let mut offset = self.offset.lock().unwrap();
-        let len = self.inode.read_at(*offset, buf).map_err(|e| errno!(e))?;
+        let len = self.do_read_at(*offset, buf)?;
         *offset += len;
         Ok(len)
     }
@@ -57,25 +81,29 @@ impl INodeFile {
             let info = self.inode.metadata()?;
             *offset = info.size;
         }
-        let len = self.inode.write_at(*offset, buf)?;
+        let len = self.do_write_at(*offset, buf)?;
         *offset += len;
         Ok(len)
     }
 
+    /// Read at a given offset, without changing the offset of the file.
+    ///
+    /// Just like `read`, it does not block on host files, but may return EAGAIN.
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
         if !self.access_mode.readable() {
             return_errno!(EACCES, "File not readable");
         }
-        let len = self.inode.read_at(offset, buf)?;
-        Ok(len)
+        self.do_read_at(offset, buf)
     }
 
+    /// Write at a given offset, without changing the offset of the file.
+    ///
+    /// Just like `write`, it does not block on host files, but may return EAGAIN.
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
         if !self.access_mode.writable() {
             return_errno!(EACCES, "File not writable");
         }
-        let len = self.inode.write_at(offset, buf)?;
-        Ok(len)
+        self.do_write_at(offset, buf)
     }
 
     pub fn readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
@@ -85,13 +113,18 @@ impl INodeFile {
         let mut offset = self.offset.lock().unwrap();
         let mut total_len = 0;
         for buf in bufs {
-            match self.inode.read_at(*offset, buf) {
+            match self.do_read_at(*offset, buf) {
                 Ok(len) => {
                     total_len += len;
                     *offset += len;
+                    // A short I/O, e.g., when only part of the data is ready
+                    // in the page cache, must not skip the rest of the buffer
+                    if len < buf.len() {
+                        break;
+                    }
                 }
                 Err(_) if total_len != 0 => break,
-                Err(e) => return Err(e.into()),
+                Err(e) => return Err(e),
             }
         }
         Ok(total_len)
@@ -108,13 +141,109 @@ impl INodeFile {
         }
         let mut total_len = 0;
         for buf in bufs {
-            match self.inode.write_at(*offset, buf) {
+            match self.do_write_at(*offset, buf) {
                 Ok(len) => {
                     total_len += len;
                     *offset += len;
+                    // A short I/O, e.g., when only part of the data is ready
+                    // in the page cache, must not skip the rest of the buffer
+                    if len < buf.len() {
+                        break;
+                    }
+                }
+                Err(_) if total_len != 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(total_len)
+    }
+
+    // This method does not block on host files, but may return EAGAIN.
+    fn do_read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        match &self.host_file {
+            Some(host_file) => host_file.read_at(offset, buf),
+            None => Ok(self.inode.read_at(offset, buf)?),
+        }
+    }
+
+    // This method does not block on host files, but may return EAGAIN.
+    fn do_write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        match &self.host_file {
+            Some(host_file) => host_file.write_at(offset, buf),
+            None => Ok(self.inode.write_at(offset, buf)?),
+        }
+    }
+
+    /// Returns whether the I/O of the file bypasses the page cache.
+    pub fn is_direct(&self) -> bool {
+        self.host_file
+            .as_ref()
+            .map_or(false, |host_file| host_file.is_direct())
+    }
+
+    /// Read into a set of buffers with direct I/O, which waits for the host.
+    pub async fn read_direct(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        let offset = *self.offset.lock().unwrap();
+        let len = self.read_direct_at(offset, bufs).await?;
+        *self.offset.lock().unwrap() = offset + len;
+        Ok(len)
+    }
+
+    /// Read at a given offset into a set of buffers with direct I/O, without
+    /// changing the offset of the file.
+    pub async fn read_direct_at(&self, mut offset: usize, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        if !self.access_mode.readable() {
+            return_errno!(EACCES, "File not readable");
+        }
+        let host_file = self.host_file.as_ref().unwrap();
+        let mut total_len = 0;
+        for buf in bufs {
+            match host_file.read_exact_at(offset, buf).await {
+                Ok(len) => {
+                    total_len += len;
+                    offset += len;
+                    if len < buf.len() {
+                        break;
+                    }
                 }
                 Err(_) if total_len != 0 => break,
-                Err(e) => return Err(e.into()),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(total_len)
+    }
+
+    /// Write from a set of buffers with direct I/O, which waits for the host.
+    pub async fn write_direct(&self, bufs: &[&[u8]]) -> Result<usize> {
+        let offset = if self.status_flags.read().unwrap().always_append() {
+            self.host_file.as_ref().unwrap().len()
+        } else {
+            *self.offset.lock().unwrap()
+        };
+        let len = self.write_direct_at(offset, bufs).await?;
+        *self.offset.lock().unwrap() = offset + len;
+        Ok(len)
+    }
+
+    /// Write at a given offset from a set of buffers with direct I/O, without
+    /// changing the offset of the file.
+    pub async fn write_direct_at(&self, mut offset: usize, bufs: &[&[u8]]) -> Result<usize> {
+        if !self.access_mode.writable() {
+            return_errno!(EACCES, "File not writable");
+        }
+        let host_file = self.host_file.as_ref().unwrap();
+        let mut total_len = 0;
+        for buf in bufs {
+            match host_file.write_exact_at(offset, buf).await {
+                Ok(len) => {
+                    total_len += len;
+                    offset += len;
+                    if len < buf.len() {
+                        break;
+                    }
+                }
+                Err(_) if total_len != 0 => break,
+                Err(e) => return Err(e),
             }
         }
         Ok(total_len)
@@ -149,11 +278,120 @@ impl INodeFile {
         Ok(new_offset)
     }
 
+    /// Read the name of the next entry of a directory.
+    ///
+    /// The offset of a directory is the index of its next entry. If there are
+    /// no more entries, the method fails with `ENOENT`.
+    pub fn read_entry(&self) -> Result<String> {
+        if !self.access_mode.readable() {
+            return_errno!(EBADF, "File not readable. Can't read entry.");
+        }
+        let mut offset = self.offset.lock().unwrap();
+        let name = self.inode.get_entry(*offset)?;
+        *offset += 1;
+        Ok(name)
+    }
+
     pub fn flush(&self) -> Result<()> {
         self.inode.sync_data()?;
         Ok(())
     }
 
+    pub async fn sync_all(&self) -> Result<()> {
+        match &self.host_file {
+            Some(host_file) => host_file.flush().await,
+            None => Ok(self.inode.sync_all()?),
+        }
+    }
+
+    pub async fn sync_data(&self) -> Result<()> {
+        match &self.host_file {
+            Some(host_file) => host_file.sync_data().await,
+            None => Ok(self.inode.sync_data()?),
+        }
+    }
+
+    pub async fn set_len(&self, len: usize) -> Result<()> {
+        if !self.access_mode.writable() {
+            return_errno!(EINVAL, "File not writable");
+        }
+        match &self.host_file {
+            Some(host_file) => host_file.set_len(len).await,
+            None => Ok(self.inode.resize(len)?),
+        }
+    }
+
+    /// Truncate the file if it is a regular file opened for writing with O_TRUNC.
+    ///
+    /// This is not done by `open`, since the files of HostFS are truncated through
+    /// the page cache, which may have to wait for the pages being flushed.
+    pub async fn truncate_on_open(&self, flags: u32) -> Result<()> {
+        let creation_flags = CreationFlags::from_bits_truncate(flags);
+        if !creation_flags.contains(CreationFlags::O_TRUNC) || !self.access_mode.writable() {
+            return Ok(());
+        }
+        if self.inode.metadata()?.type_ != FileType::File {
+            return Ok(());
+        }
+        self.set_len(0).await
+    }
+
+    pub async fn fallocate(&self, mode: i32, offset: usize, len: usize) -> Result<()> {
+        if !self.access_mode.writable() {
+            return_errno!(EBADF, "File not writable");
+        }
+        match &self.host_file {
+            Some(host_file) => host_file.fallocate(mode, offset, len).await,
+            None => {
+                // Other file systems can only be extended
+                if mode != 0 {
+                    return_errno!(EOPNOTSUPP, "mode is not supported");
+                }
+                let end = offset
+                    .checked_add(len)
+                    .ok_or_else(|| errno!(EFBIG, "offset or len is too large"))?;
+                if end > self.inode.metadata()?.size {
+                    self.inode.resize(end)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn fadvise(&self, advice: Advice, offset: usize, len: usize) -> Result<()> {
+        match &self.host_file {
+            Some(host_file) => host_file.fadvise(advice, offset, len),
+            // Other file systems have no page cache to tune
+            None => Ok(()),
+        }
+    }
+
+    pub fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        match_ioctl_cmd_mut!(&mut *cmd, {
+            cmd: GetReadBufLen => {
+                let metadata = self.inode.metadata()?;
+                if metadata.type_ != FileType::File {
+                    return_errno!(ENOTTY, "not a regular file");
+                }
+                let offset = *self.offset.lock().unwrap();
+                cmd.set_output(metadata.size.checked_sub(offset).unwrap_or(0));
+                Ok(())
+            },
+            // The device-specific ioctls are handled by the inodes of devices
+            cmd: NonBuiltinIoctlCmd => {
+                self.inode
+                    .io_control(cmd.cmd_num().as_u32(), cmd.arg_ptr() as usize)
+                    .map_err(|e| match e {
+                        FsError::NotSupported => errno!(ENOTTY, "not support ioctl"),
+                        e => e.into(),
+                    })
+            },
+            _ => {
+                return_errno!(ENOTTY, "unknown ioctl cmd for an inode");
+            }
+        })
+    }
+
     pub fn access_mode(&self) -> AccessMode {
         self.access_mode
     }
@@ -163,7 +401,11 @@ impl INodeFile {
         *status_flags
     }
 
-    pub fn poll(&self, mask: Events, _poller: Option<&mut Poller>) -> Events {
+    pub fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
+        // The I/O on a host file may have to wait for the page cache
+        if let Some(host_file) = &self.host_file {
+            host_file.poll(mask, poller);
+        }
         let events = match self.access_mode {
             AccessMode::O_RDONLY => Events::IN,
             AccessMode::O_WRONLY => Events::OUT,
@@ -190,6 +432,26 @@ impl INodeFile {
     }
 }
 
+impl Drop for INodeFile {
+    fn drop(&mut self) {
+        // The OFD locks and the flock of the file go away with it
+        file_lock::release_file_locks(self);
+        // Write back the dirty pages of a host file upon close
+        if let Some(host_file) = self.host_file.take() {
+            if self.access_mode.writable() {
+                let abs_path = self.abs_path.clone();
+                async_rt::task::spawn(async move {
+                    // Just like Linux, the error is not reported by close, but by
+                    // the next fsync of the file, even through another open.
+                    if let Err(e) = host_file.flush_dirty_pages().await {
+                        error!("failed to write back {}: {}", abs_path, e);
+                    }
+                });
+            }
+        }
+    }
+}
+
 impl Debug for INodeFile {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -207,6 +469,8 @@ pub trait INodeExt {
     fn read_as_vec(&self) -> Result<Vec<u8>>;
     fn allow_write(&self) -> Result<bool>;
     fn allow_read(&self) -> Result<bool>;
+    /// Fails with EROFS if the inode belongs to a read-only mount.
+    fn check_not_read_only(&self) -> Result<()>;
 }
 
 impl INodeExt for dyn INode {
@@ -231,4 +495,11 @@ impl INodeExt for dyn INode {
         let file_mode = FileMode::from_bits_truncate(info.mode);
         Ok(file_mode.is_readable())
     }
+
+    fn check_not_read_only(&self) -> Result<()> {
+        if rootfs::is_read_only(self) {
+            return_errno!(EROFS, "the file is on a read-only mount");
+        }
+        Ok(())
+    }
 }
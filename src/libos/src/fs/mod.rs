@@ -20,21 +20,26 @@ pub use async_io::fs::{
     occlum_ocall_ioctl, AccessMode, BuiltinIoctlNum, CreationFlags, FileMode, Flock, FlockType,
     IfConf, IoctlCmd, Stat, StatusFlags, StructuredIoctlArgType, StructuredIoctlNum,
 };*/
+pub use self::async_file_rt::HostFileRt;
+pub use self::dev_fs::{set_host_tty_fds, DevINode};
 pub use self::event_file::{EventFile, EventFileFlags};
 pub use self::file_handle::{FileHandle as FileRef, WeakFileHandle as WeakFileRef};
+pub use self::file_lock::release_process_locks;
 pub use self::file_table::{FileDesc, FileTable};
 pub use self::fs_view::FsView;
 pub use self::host_fd::HostFd;
 pub use self::inode_file::{INodeExt, INodeFile, InodeFile};
-pub use self::rootfs::ROOT_INODE;
+pub use self::rootfs::{host_path_of, libos_path_of, ROOT_INODE};
 pub use self::stdio::{HostStdioFds, StdinFile, StdoutFile};
 pub use self::syscalls::*;
 
+mod async_file_rt;
+mod dev_fs;
 mod event_file;
-//mod dev_fs;
 // TODO: remove the file
 //mod file;
 mod file_handle;
+mod file_lock;
 mod file_ops;
 mod file_table;
 mod fs_ops;
@@ -43,7 +48,7 @@ mod host_fd;
 mod hostfs;
 mod inode_file;
 mod pipe;
-//mod procfs;
+mod procfs;
 mod rootfs;
 mod sefs;
 mod stdio;
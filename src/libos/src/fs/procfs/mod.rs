@@ -1,20 +1,25 @@
 use super::*;
-use alloc::sync::{Arc, Weak};
 use rcore_fs::vfs;
+use std::sync::Weak;
 
 use crate::process::pid_t;
+use crate::process::table;
 
 use self::cpuinfo_inode::CpuInfoINode;
 use self::meminfo_inode::MemInfoINode;
 use self::pid_inode::LockedPidDirINode;
 use self::proc_inode::{Dir, DirProcINode, File, ProcINode, SymLink};
 use self::self_inode::SelfSymINode;
+use self::stat_inode::StatINode;
+use self::uptime_inode::UptimeINode;
 
 mod cpuinfo_inode;
 mod meminfo_inode;
 mod pid_inode;
 mod proc_inode;
 mod self_inode;
+mod stat_inode;
+mod uptime_inode;
 
 /// Proc file system
 pub struct ProcFS {
@@ -90,8 +95,6 @@ impl LockedProcRootINode {
         let mut file = self.0.write().unwrap();
         file.this = Arc::downgrade(&fs.root);
         file.parent = Arc::downgrade(&fs.root);
-        // Currently, we only init the 'cpuinfo', 'meminfo' and 'self' entry.
-        // TODO: Add more entries for root.
         // All [pid] entries are lazy-initialized at the find() step.
         let cpuinfo_inode = CpuInfoINode::new();
         file.non_volatile_entries
@@ -102,6 +105,12 @@ impl LockedProcRootINode {
         let self_inode = SelfSymINode::new();
         file.non_volatile_entries
             .insert(String::from("self"), self_inode);
+        let stat_inode = StatINode::new();
+        file.non_volatile_entries
+            .insert(String::from("stat"), stat_inode);
+        let uptime_inode = UptimeINode::new();
+        file.non_volatile_entries
+            .insert(String::from("uptime"), uptime_inode);
     }
 }
 
@@ -131,11 +140,19 @@ impl DirProcINode for LockedProcRootINode {
             1 => Ok(String::from("..")),
             i => {
                 let file = self.0.read().unwrap();
+                let num_entries = file.non_volatile_entries.len();
                 if let Some(s) = file.non_volatile_entries.keys().nth(i - 2) {
                     Ok(s.to_string())
                 } else {
-                    // TODO: When to iterate the process table ?
-                    Err(FsError::EntryNotFound)
+                    // The [pid] entries follow the non-volatile ones
+                    let mut pids: Vec<pid_t> = table::get_all_processes()
+                        .iter()
+                        .map(|process| process.pid())
+                        .collect();
+                    pids.sort();
+                    pids.get(i - 2 - num_entries)
+                        .map(|pid| pid.to_string())
+                        .ok_or(FsError::EntryNotFound)
                 }
             }
         }
@@ -1,6 +1,8 @@
 use super::*;
+use crate::fs::pipe::{PipeReader, PipeWriter};
 use crate::process::table::get_process;
-use crate::process::ProcessRef;
+use crate::process::{ProcessRef, ProcessStatus, ThreadRef};
+use crate::vm::VMRange;
 
 pub struct LockedPidDirINode(RwLock<PidDirINode>);
 
@@ -29,15 +31,30 @@ impl LockedPidDirINode {
         // cmdline
         let cmdline_inode = ProcCmdlineINode::new(&file.process_ref);
         file.entries.insert(String::from("cmdline"), cmdline_inode);
+        // comm
+        let comm_inode = ProcCommINode::new(&file.process_ref);
+        file.entries.insert(String::from("comm"), comm_inode);
         // cwd
         let cwd_inode = ProcCwdSymINode::new(&file.process_ref);
         file.entries.insert(String::from("cwd"), cwd_inode);
+        // environ
+        let environ_inode = ProcEnvironINode::new(&file.process_ref);
+        file.entries.insert(String::from("environ"), environ_inode);
         // exe
         let exe_inode = ProcExeSymINode::new(&file.process_ref);
         file.entries.insert(String::from("exe"), exe_inode);
         // fd
         let fd_inode = LockedProcFdDirINode::new(&file.process_ref, file.this.upgrade().unwrap());
         file.entries.insert(String::from("fd"), fd_inode);
+        // maps
+        let maps_inode = ProcMapsINode::new(&file.process_ref);
+        file.entries.insert(String::from("maps"), maps_inode);
+        // stat
+        let stat_inode = ProcStatINode::new(&file.process_ref);
+        file.entries.insert(String::from("stat"), stat_inode);
+        // status
+        let status_inode = ProcStatusINode::new(&file.process_ref);
+        file.entries.insert(String::from("status"), status_inode);
         Ok(())
     }
 }
@@ -115,8 +132,12 @@ impl DirProcINode for LockedProcFdDirINode {
             0 => Ok(String::from(".")),
             1 => Ok(String::from("..")),
             i => {
-                // TODO: When to iterate the file table ?
-                Err(FsError::EntryNotFound)
+                let file = self.0.read().unwrap();
+                let main_thread = main_thread_of(&file.process_ref)?;
+                let fds = main_thread.files().lock().unwrap().fds();
+                fds.get(i - 2)
+                    .map(|fd| fd.to_string())
+                    .ok_or(FsError::EntryNotFound)
             }
         }
     }
@@ -132,7 +153,48 @@ impl ProcCmdlineINode {
 
 impl ProcINode for ProcCmdlineINode {
     fn generate_data_in_bytes(&self) -> vfs::Result<Vec<u8>> {
-        Ok(self.0.exec_path().to_owned().into_bytes())
+        // The arguments are separated and terminated by null bytes
+        Ok(self
+            .0
+            .argv()
+            .iter()
+            .flat_map(|arg| arg.as_bytes_with_nul().iter().copied())
+            .collect())
+    }
+}
+
+pub struct ProcEnvironINode(ProcessRef);
+
+impl ProcEnvironINode {
+    pub fn new(process_ref: &ProcessRef) -> Arc<dyn INode> {
+        Arc::new(File::new(Self(Arc::clone(process_ref))))
+    }
+}
+
+impl ProcINode for ProcEnvironINode {
+    fn generate_data_in_bytes(&self) -> vfs::Result<Vec<u8>> {
+        Ok(self
+            .0
+            .envp()
+            .iter()
+            .flat_map(|env| env.as_bytes_with_nul().iter().copied())
+            .collect())
+    }
+}
+
+pub struct ProcCommINode(ProcessRef);
+
+impl ProcCommINode {
+    pub fn new(process_ref: &ProcessRef) -> Arc<dyn INode> {
+        Arc::new(File::new(Self(Arc::clone(process_ref))))
+    }
+}
+
+impl ProcINode for ProcCommINode {
+    fn generate_data_in_bytes(&self) -> vfs::Result<Vec<u8>> {
+        let mut comm = comm_of(&self.0).into_bytes();
+        comm.push(b'\n');
+        Ok(comm)
     }
 }
 
@@ -160,34 +222,233 @@ impl ProcCwdSymINode {
 
 impl ProcINode for ProcCwdSymINode {
     fn generate_data_in_bytes(&self) -> vfs::Result<Vec<u8>> {
-        let main_thread = self.0.main_thread().ok_or(FsError::EntryNotFound)?;
+        let main_thread = main_thread_of(&self.0)?;
         let fs = main_thread.fs().lock().unwrap();
         Ok(fs.cwd().to_owned().into_bytes())
     }
 }
 
+pub struct ProcMapsINode(ProcessRef);
+
+impl ProcMapsINode {
+    pub fn new(process_ref: &ProcessRef) -> Arc<dyn INode> {
+        Arc::new(File::new(Self(Arc::clone(process_ref))))
+    }
+}
+
+impl ProcINode for ProcMapsINode {
+    fn generate_data_in_bytes(&self) -> vfs::Result<Vec<u8>> {
+        let main_thread = main_thread_of(&self.0)?;
+        let vm = main_thread.vm();
+
+        let mut maps = String::new();
+        // The memory of ELFs is readable, writable and executable in an enclave
+        // The first ELF is the executable and the others are loaded by it
+        for (i, elf_range) in vm.get_elf_ranges().iter().enumerate() {
+            let elf_path = if i == 0 { self.0.exec_path() } else { "" };
+            push_map_line(&mut maps, elf_range, "rwxp", 0, elf_path);
+        }
+        push_map_line(&mut maps, vm.get_heap_range(), "rw-p", 0, "[heap]");
+        push_map_line(&mut maps, vm.get_stack_range(), "rw-p", 0, "[stack]");
+        for vma in vm.get_mmap_areas() {
+            let perms = vma.perms();
+            let perms = format!(
+                "{}{}{}{}",
+                if perms.can_read() { 'r' } else { '-' },
+                if perms.can_write() { 'w' } else { '-' },
+                if perms.can_execute() { 'x' } else { '-' },
+                if vma.writeback_file().is_some() {
+                    's'
+                } else {
+                    'p'
+                },
+            );
+            let (offset, path) = match vma.writeback_file() {
+                Some((file_ref, offset)) => {
+                    let path = file_ref
+                        .as_inode_file()
+                        .map(|inode_file| inode_file.abs_path().to_owned())
+                        .unwrap_or_default();
+                    (*offset, path)
+                }
+                None => (0, String::new()),
+            };
+            push_map_line(&mut maps, vma.range(), &perms, offset, &path);
+        }
+        Ok(maps.into_bytes())
+    }
+}
+
+fn push_map_line(maps: &mut String, range: &VMRange, perms: &str, offset: usize, path: &str) {
+    if range.empty() {
+        return;
+    }
+    let line = format!(
+        "{:08x}-{:08x} {} {:08x} 00:00 0",
+        range.start(),
+        range.end(),
+        perms,
+        offset
+    );
+    if path.is_empty() {
+        maps.push_str(&line);
+    } else {
+        // Just like Linux, pad the line so that the paths are aligned
+        maps.push_str(&format!("{:<72} {}", line, path));
+    }
+    maps.push('\n');
+}
+
+pub struct ProcStatINode(ProcessRef);
+
+impl ProcStatINode {
+    pub fn new(process_ref: &ProcessRef) -> Arc<dyn INode> {
+        Arc::new(File::new(Self(Arc::clone(process_ref))))
+    }
+}
+
+impl ProcINode for ProcStatINode {
+    fn generate_data_in_bytes(&self) -> vfs::Result<Vec<u8>> {
+        let process = &self.0;
+        let main_thread = main_thread_of(process)?;
+        let vm = main_thread.vm();
+        let vm_size = vm.get_process_range().size();
+        let (start_code, end_code) = vm
+            .get_elf_ranges()
+            .first()
+            .map_or((0, 0), |range| (range.start(), range.end()));
+        let heap_start = vm.get_heap_range().start();
+
+        // The fields that are not tracked by the LibOS are zeros, including the
+        // session, which is not supported, and the RSS, which is unknown as
+        // the memory is committed by the enclave.
+        let stat = format!(
+            "{pid} ({comm}) {state} {ppid} {pgrp} {session} 0 -1 0 \
+             0 0 0 0 0 0 0 0 20 0 {num_threads} 0 0 {vsize} {rss} {rsslim} \
+             {start_code} {end_code} {start_stack} 0 0 0 0 0 0 0 0 0 17 0 0 0 \
+             0 0 0 0 0 {start_brk} 0 0 0 0 0\n",
+            pid = process.pid(),
+            comm = comm_of(process),
+            state = state_of(process).0,
+            ppid = ppid_of(process),
+            pgrp = process.pgid(),
+            session = 0,
+            num_threads = process.threads().len(),
+            vsize = vm_size,
+            rss = 0,
+            rsslim = u64::max_value(),
+            start_code = start_code,
+            end_code = end_code,
+            start_stack = vm.get_stack_base(),
+            start_brk = heap_start,
+        );
+        Ok(stat.into_bytes())
+    }
+}
+
+pub struct ProcStatusINode(ProcessRef);
+
+impl ProcStatusINode {
+    pub fn new(process_ref: &ProcessRef) -> Arc<dyn INode> {
+        Arc::new(File::new(Self(Arc::clone(process_ref))))
+    }
+}
+
+impl ProcINode for ProcStatusINode {
+    fn generate_data_in_bytes(&self) -> vfs::Result<Vec<u8>> {
+        let process = &self.0;
+        let main_thread = main_thread_of(process)?;
+        let (state, state_name) = state_of(process);
+        let fd_size = main_thread.files().lock().unwrap().capacity();
+        let vm_size_kb = main_thread.vm().get_process_range().size() / 1024;
+
+        // Just like the stat, the RSS is reported as zero
+        let status = format!(
+            "Name:\t{name}\n\
+             Umask:\t0022\n\
+             State:\t{state} ({state_name})\n\
+             Tgid:\t{pid}\n\
+             Ngid:\t0\n\
+             Pid:\t{pid}\n\
+             PPid:\t{ppid}\n\
+             TracerPid:\t0\n\
+             Uid:\t0\t0\t0\t0\n\
+             Gid:\t0\t0\t0\t0\n\
+             FDSize:\t{fd_size}\n\
+             VmPeak:\t{vm_size_kb} kB\n\
+             VmSize:\t{vm_size_kb} kB\n\
+             VmHWM:\t0 kB\n\
+             VmRSS:\t0 kB\n\
+             Threads:\t{num_threads}\n",
+            name = comm_of(process),
+            state = state,
+            state_name = state_name,
+            pid = process.pid(),
+            ppid = ppid_of(process),
+            fd_size = fd_size,
+            vm_size_kb = vm_size_kb,
+            num_threads = process.threads().len(),
+        );
+        Ok(status.into_bytes())
+    }
+}
+
 pub struct FdSymINode(FileRef);
 
 impl FdSymINode {
     pub fn new(process_ref: &ProcessRef, fd: FileDesc) -> vfs::Result<Arc<dyn INode>> {
-        let main_thread = process_ref.main_thread().ok_or(FsError::EntryNotFound)?;
+        let main_thread = main_thread_of(process_ref)?;
         let file_ref = main_thread.file(fd).map_err(|_| FsError::EntryNotFound)?;
-        Ok(Arc::new(SymLink::new(Self(Arc::clone(&file_ref)))))
+        Ok(Arc::new(SymLink::new(Self(file_ref))))
     }
 }
 
 impl ProcINode for FdSymINode {
     fn generate_data_in_bytes(&self) -> vfs::Result<Vec<u8>> {
-        let path = if let Ok(inode_file) = self.0.as_inode_file() {
+        let path = if let Some(inode_file) = self.0.as_inode_file() {
             inode_file.abs_path().to_owned()
+        } else if let Some(socket_file) = self.0.as_socket_file() {
+            format!("socket:[{}]", socket_file.id())
+        } else if let Some(pipe_reader) = self.0.as_file::<PipeReader>() {
+            format!("pipe:[{}]", pipe_reader.channel_id())
+        } else if let Some(pipe_writer) = self.0.as_file::<PipeWriter>() {
+            format!("pipe:[{}]", pipe_writer.channel_id())
+        } else if self.0.as_file::<EventFile>().is_some() {
+            String::from("anon_inode:[eventfd]")
         } else {
-            // TODO: Support other file types
-            // For file descriptors for pipes and sockets,
-            // the content is: type:[inode].
-            // For file descriptors that have no corresponding inode,
-            // the content is: anon_inode:[file-type]
-            return Err(FsError::EntryNotFound);
+            // For the other files that have no corresponding inodes,
+            // the content is anon_inode:[file-type].
+            String::from("anon_inode:[file]")
         };
         Ok(path.into_bytes())
     }
 }
+
+fn main_thread_of(process_ref: &ProcessRef) -> vfs::Result<ThreadRef> {
+    // A zombie process has no threads
+    process_ref.main_thread().ok_or(FsError::EntryNotFound)
+}
+
+fn comm_of(process_ref: &ProcessRef) -> String {
+    let name = match process_ref.main_thread() {
+        Some(main_thread) => main_thread.name(),
+        None => return String::new(),
+    };
+    name.as_c_str().to_string_lossy().into_owned()
+}
+
+fn state_of(process_ref: &ProcessRef) -> (char, &'static str) {
+    match process_ref.status() {
+        ProcessStatus::Running => ('R', "running"),
+        ProcessStatus::Stopped => ('T', "stopped"),
+        ProcessStatus::Zombie => ('Z', "zombie"),
+    }
+}
+
+fn ppid_of(process_ref: &ProcessRef) -> pid_t {
+    if process_ref.pid() > 0 {
+        process_ref.parent().pid()
+    } else {
+        0
+    }
+}
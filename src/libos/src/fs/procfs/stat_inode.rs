@@ -0,0 +1,51 @@
+use super::*;
+use crate::process::ProcessStatus;
+use crate::sched::NCORES;
+use crate::time::{do_gettimeofday, up_time};
+
+pub struct StatINode;
+
+// The unit of the time in /proc/stat, i.e., USER_HZ
+const CLOCK_TICKS_PER_SEC: u64 = 100;
+
+impl StatINode {
+    pub fn new() -> Arc<dyn INode> {
+        Arc::new(File::new(Self))
+    }
+}
+
+impl ProcINode for StatINode {
+    fn generate_data_in_bytes(&self) -> vfs::Result<Vec<u8>> {
+        let uptime = up_time::get().unwrap_or_default();
+        let boot_time = do_gettimeofday()
+            .as_duration()
+            .checked_sub(uptime)
+            .unwrap_or_default()
+            .as_secs();
+        // The LibOS does not track the CPU time, so just treat all cores as idle
+        let idle_ticks = uptime.as_millis() as u64 * CLOCK_TICKS_PER_SEC / 1000;
+
+        let mut stat = format!("cpu  0 0 0 {} 0 0 0 0 0 0\n", idle_ticks * (*NCORES as u64));
+        for cpu in 0..*NCORES {
+            stat.push_str(&format!("cpu{} 0 0 0 {} 0 0 0 0 0 0\n", cpu, idle_ticks));
+        }
+
+        let processes = table::get_all_processes();
+        let procs_running = processes
+            .iter()
+            .filter(|process| process.status() == ProcessStatus::Running)
+            .count();
+        stat.push_str(&format!(
+            "intr 0\n\
+             ctxt 0\n\
+             btime {}\n\
+             processes {}\n\
+             procs_running {}\n\
+             procs_blocked 0\n",
+            boot_time,
+            processes.len(),
+            procs_running,
+        ));
+        Ok(stat.into_bytes())
+    }
+}
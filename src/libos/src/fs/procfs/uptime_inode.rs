@@ -0,0 +1,20 @@
+use super::*;
+use crate::sched::NCORES;
+use crate::time::up_time;
+
+pub struct UptimeINode;
+
+impl UptimeINode {
+    pub fn new() -> Arc<dyn INode> {
+        Arc::new(File::new(Self))
+    }
+}
+
+impl ProcINode for UptimeINode {
+    fn generate_data_in_bytes(&self) -> vfs::Result<Vec<u8>> {
+        let uptime = up_time::get().unwrap_or_default().as_secs_f64();
+        // The LibOS does not track the idle time, so just treat all cores as idle
+        let idle_time = uptime * (*NCORES as f64);
+        Ok(format!("{:.2} {:.2}\n", uptime, idle_time).into_bytes())
+    }
+}
@@ -1,6 +1,6 @@
-//use super::dev_fs;
+use super::dev_fs;
 use super::hostfs::HostFS;
-//use super::procfs::ProcFS;
+use super::procfs::ProcFS;
 use super::sefs::{SgxStorage, SgxUuidProvider};
 use super::*;
 use config::{ConfigMount, ConfigMountFsType};
@@ -32,6 +32,20 @@ lazy_static! {
     };
 }
 
+lazy_static! {
+    /// The non-root mounts, in the order that they are mounted
+    static ref MOUNTS: RwLock<Vec<Mount>> = RwLock::new(Vec::new());
+}
+
+/// A non-root mount
+struct Mount {
+    target: PathBuf,
+    fs: Arc<MountFS>,
+    // The source path on the host, if the file system is HostFS
+    host_source: Option<PathBuf>,
+    read_only: bool,
+}
+
 fn open_root_fs_according_to(mount_configs: &Vec<ConfigMount>) -> Result<Arc<MountFS>> {
     let mount_config = mount_configs
         .iter()
@@ -131,7 +145,7 @@ fn mount_nonroot_fs_according_to(mount_config: &Vec<ConfigMount>, root: &MNode)
                         &SgxUuidProvider,
                     )?
                 };
-                mount_fs_at(sefs, root, &mc.target)?;
+                mount_fs_at(sefs, None, root, &mc.target)?;
             }
             TYPE_HOSTFS => {
                 if mc.source.is_none() {
@@ -140,21 +154,20 @@ fn mount_nonroot_fs_according_to(mount_config: &Vec<ConfigMount>, root: &MNode)
                 let source_path = mc.source.as_ref().unwrap();
 
                 let hostfs = HostFS::new(source_path);
-                mount_fs_at(hostfs, root, &mc.target)?;
+                mount_fs_at(hostfs, Some(source_path), root, &mc.target)?;
             }
             TYPE_RAMFS => {
                 let ramfs = RamFS::new();
-                mount_fs_at(ramfs, root, &mc.target)?;
+                mount_fs_at(ramfs, None, root, &mc.target)?;
             }
             TYPE_DEVFS => {
-                //let devfs = dev_fs::init_devfs()?;
-                //mount_fs_at(devfs, root, &mc.target)?;
-                warn!("devfs is not supported");
+                let (devfs, shm_fs) = dev_fs::init_devfs(mc.options.devices.as_deref())?;
+                mount_fs_at(devfs, None, root, &mc.target)?;
+                mount_fs_at(shm_fs, None, root, &mc.target.join("shm"))?;
             }
             TYPE_PROCFS => {
-                //let procfs = ProcFS::new();
-                //mount_fs_at(procfs, root, &mc.target)?;
-                warn!("procfs is not supported");
+                let procfs = ProcFS::new();
+                mount_fs_at(procfs, None, root, &mc.target)?;
             }
             TYPE_UNIONFS => {
                 return_errno!(EINVAL, "Cannot mount UnionFS at non-root path");
@@ -164,7 +177,12 @@ fn mount_nonroot_fs_according_to(mount_config: &Vec<ConfigMount>, root: &MNode)
     Ok(())
 }
 
-fn mount_fs_at(fs: Arc<dyn FileSystem>, parent_inode: &MNode, abs_path: &Path) -> Result<()> {
+fn mount_fs_at(
+    fs: Arc<dyn FileSystem>,
+    host_source: Option<&Path>,
+    parent_inode: &MNode,
+    abs_path: &Path,
+) -> Result<()> {
     let mut mount_dir = parent_inode.find(false, ".")?;
     // The first component of abs_path is the RootDir, skip it.
     for dirname in abs_path.iter().skip(1) {
@@ -178,6 +196,136 @@ fn mount_fs_at(fs: Arc<dyn FileSystem>, parent_inode: &MNode, abs_path: &Path) -
             Err(_) => return_errno!(ENOENT, "Mount point does not exist"),
         };
     }
-    mount_dir.mount(fs);
+    mount_fs_on(fs, &mount_dir, abs_path, host_source, false)
+}
+
+/// Mount a file system on the directory at `target`.
+pub fn mount_fs_on(
+    fs: Arc<dyn FileSystem>,
+    mount_dir: &MNode,
+    target: &Path,
+    host_source: Option<&Path>,
+    read_only: bool,
+) -> Result<()> {
+    let target = normalize_path(target).ok_or_else(|| errno!(EINVAL, "target must be absolute"))?;
+    let mut mounts = MOUNTS.write().unwrap();
+    let mount_fs = mount_dir.mount(fs)?;
+    mounts.push(Mount {
+        target,
+        fs: mount_fs,
+        host_source: host_source.map(|path| path.to_path_buf()),
+        read_only,
+    });
+    Ok(())
+}
+
+/// Unmount the file system that is mounted last at `target`.
+pub fn umount_fs(target: &Path) -> Result<()> {
+    let mut mounts = MOUNTS.write().unwrap();
+    let mount_idx = find_mount(&mounts, target)?;
+    // Write back the data before the file system is detached
+    mounts[mount_idx].fs.sync()?;
+    mounts[mount_idx].fs.root_inode().umount()?;
+    let mount = mounts.remove(mount_idx);
+    // The mounts under the target are detached along with it
+    mounts.retain(|m| m.target == mount.target || !m.target.starts_with(&mount.target));
     Ok(())
 }
+
+/// Change whether the file system that is mounted last at `target` is read-only.
+pub fn remount_fs(target: &Path, read_only: bool) -> Result<()> {
+    let mut mounts = MOUNTS.write().unwrap();
+    let mount_idx = find_mount(&mounts, target)?;
+    mounts[mount_idx].read_only = read_only;
+    Ok(())
+}
+
+/// Returns the targets of the mounts under `target`, excluding those at `target`.
+pub fn mounts_under(target: &Path) -> Vec<PathBuf> {
+    let target = match normalize_path(target) {
+        Some(target) => target,
+        None => return Vec::new(),
+    };
+    let mounts = MOUNTS.read().unwrap();
+    mounts
+        .iter()
+        .filter(|m| m.target != target && m.target.starts_with(&target))
+        .map(|m| m.target.clone())
+        .collect()
+}
+
+/// Returns whether the inode belongs to the file system that is mounted last at `target`.
+pub fn is_inode_in_mount(inode: &dyn INode, target: &Path) -> bool {
+    let mounts = MOUNTS.read().unwrap();
+    match find_mount(&mounts, target) {
+        Ok(mount_idx) => is_same_fs(&inode.fs(), &mounts[mount_idx].fs),
+        Err(_) => false,
+    }
+}
+
+/// Returns whether the inode belongs to a read-only mount.
+pub fn is_read_only(inode: &dyn INode) -> bool {
+    let fs = inode.fs();
+    let mounts = MOUNTS.read().unwrap();
+    mounts.iter().any(|m| m.read_only && is_same_fs(&fs, &m.fs))
+}
+
+fn find_mount(mounts: &[Mount], target: &Path) -> Result<usize> {
+    let target = normalize_path(target).ok_or_else(|| errno!(EINVAL, "target must be absolute"))?;
+    mounts
+        .iter()
+        .rposition(|m| m.target == target)
+        .ok_or_else(|| errno!(EINVAL, "target is not a mount point"))
+}
+
+fn is_same_fs(fs: &Arc<dyn FileSystem>, mount_fs: &Arc<MountFS>) -> bool {
+    Arc::as_ptr(fs) as *const u8 == Arc::as_ptr(mount_fs) as *const u8
+}
+
+/// Translate an absolute LibOS path to the host path if the path is under a HostFS mount.
+pub fn host_path_of(abs_path: &str) -> Option<PathBuf> {
+    let abs_path = normalize_path(Path::new(abs_path))?;
+    let mounts = MOUNTS.read().unwrap();
+    // The innermost mount takes effect if mounts are nested
+    let mount = mounts
+        .iter()
+        .filter(|m| abs_path.starts_with(&m.target))
+        .max_by_key(|m| m.target.components().count())?;
+    let host_source = mount.host_source.as_ref()?;
+
+    let relative_path = abs_path.strip_prefix(&mount.target).ok()?;
+    Some(host_source.join(relative_path))
+}
+
+/// Translate a host path to the LibOS path if the host path is under the source
+/// of a HostFS mount.
+pub fn libos_path_of(host_path: &Path) -> Option<PathBuf> {
+    let mounts = MOUNTS.read().unwrap();
+    mounts.iter().rev().find_map(|m| {
+        let relative_path = host_path.strip_prefix(m.host_source.as_ref()?).ok()?;
+        Some(m.target.join(relative_path))
+    })
+}
+
+/// Normalize an absolute path lexically, i.e., without resolving symlinks.
+///
+/// Returns `None` if the path is not absolute.
+pub fn normalize_path(path: &Path) -> Option<PathBuf> {
+    use std::path::Component;
+
+    if !path.is_absolute() {
+        return None;
+    }
+    let mut normalized_path = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized_path.push(name),
+            // Just like Linux, the parent of the root is the root itself
+            Component::ParentDir => {
+                normalized_path.pop();
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+    Some(normalized_path)
+}
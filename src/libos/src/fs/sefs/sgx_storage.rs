@@ -33,6 +33,7 @@ pub struct SgxStorage {
     integrity_only: bool,
     file_cache: Mutex<BTreeMap<u64, LockedFile>>,
     root_mac: Option<sgx_aes_gcm_128bit_tag_t>,
+    key: Option<sgx_key_128bit_t>,
 }
 
 impl SgxStorage {
@@ -47,6 +48,7 @@ impl SgxStorage {
             integrity_only: integrity_only,
             file_cache: Mutex::new(BTreeMap::new()),
             root_mac: file_mac,
+            key: None,
         }
     }
     /// Get file by `file_id`.
@@ -95,6 +97,29 @@ impl SgxStorage {
         self.root_mac = Some(mac);
         Ok(())
     }
+
+    /// Set the key to encrypt the files of the SGX storage.
+    ///
+    /// Without this key, the files are encrypted with a key derived from the
+    /// enclave, which is not applicable to integrity-only storages.
+    pub fn set_key(&mut self, key: sgx_key_128bit_t) -> Result<()> {
+        if self.integrity_only {
+            return_errno!(EINVAL, "integrity-only storage cannot be given a key");
+        }
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn open_file(&self, options: &OpenOptions, path: &Path) -> Result<SgxFile> {
+        let file = if self.integrity_only {
+            options.open_integrity_only(path)?
+        } else if let Some(key) = self.key.as_ref() {
+            options.open_ex(path, key)?
+        } else {
+            options.open(path)?
+        };
+        Ok(file)
+    }
 }
 
 impl Storage for SgxStorage {
@@ -107,11 +132,7 @@ impl Storage for SgxStorage {
                 options.read(true).update(true);
                 options
             };
-            let file = if !self.integrity_only {
-                options.open(path)?
-            } else {
-                options.open_integrity_only(path)?
-            };
+            let file = this.open_file(&options, &path)?;
 
             // Check the MAC of the root file against the given root MAC of the storage
             if file_id == "metadata" && self.root_mac.is_some() {
@@ -140,11 +161,7 @@ impl Storage for SgxStorage {
                 options.write(true).update(true);
                 options
             };
-            let file = if !self.integrity_only {
-                options.open(path)?
-            } else {
-                options.open_integrity_only(path)?
-            };
+            let file = this.open_file(&options, &path)?;
             Ok(LockedFile(Arc::new(Mutex::new(file))))
         })?;
         Ok(Box::new(locked_file))
@@ -1,4 +1,6 @@
+use super::file_ops::do_host_tty_ioctl;
 use super::*;
+use async_io::ioctl::IoctlCmd;
 use core::cell::RefCell;
 use core::cmp;
 use std::io::{BufReader, LineWriter};
@@ -140,38 +142,10 @@ impl File for StdoutFile {
     fn poll(&self, mask: Events, _poller: Option<&mut Poller>) -> Events {
         Events::OUT
     }
-    /*
-        fn ioctl(&self, cmd: &mut IoctlCmd) -> Result<i32> {
-            let can_delegate_to_host = match cmd {
-                IoctlCmd::TIOCGWINSZ(_) => true,
-                IoctlCmd::TIOCSWINSZ(_) => true,
-                _ => false,
-            };
-            if !can_delegate_to_host {
-                return_errno!(EINVAL, "unknown ioctl cmd for stdout");
-            }
-
-            let cmd_bits = cmd.cmd_num() as c_int;
-            let cmd_arg_ptr = cmd.arg_ptr() as *mut c_void;
-            let host_stdout_fd = self.host_fd() as i32;
-            let cmd_arg_len = cmd.arg_len();
-            let ret = try_libc!({
-                let mut retval: i32 = 0;
-                let status = occlum_ocall_ioctl(
-                    &mut retval as *mut i32,
-                    host_stdout_fd,
-                    cmd_bits,
-                    cmd_arg_ptr,
-                    cmd_arg_len,
-                );
-                assert!(status == sgx_status_t::SGX_SUCCESS);
-                retval
-            });
-            cmd.validate_arg_and_ret_vals(ret)?;
 
-            Ok(ret)
-        }
-    */
+    fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        do_host_tty_ioctl(self.host_fd(), cmd)
+    }
 }
 
 impl Debug for StdoutFile {
@@ -271,38 +245,10 @@ impl File for StdinFile {
     fn poll(&self, mask: Events, _poller: Option<&mut Poller>) -> Events {
         Events::IN
     }
-    /*
-        fn ioctl(&self, cmd: &mut IoctlCmd) -> Result<i32> {
-            let can_delegate_to_host = match cmd {
-                IoctlCmd::TIOCGWINSZ(_) => true,
-                IoctlCmd::TIOCSWINSZ(_) => true,
-                _ => false,
-            };
-            if !can_delegate_to_host {
-                return_errno!(EINVAL, "unknown ioctl cmd for stdin");
-            }
-
-            let cmd_bits = cmd.cmd_num() as c_int;
-            let cmd_arg_ptr = cmd.arg_ptr() as *mut c_void;
-            let host_stdin_fd = self.host_fd() as i32;
-            let cmd_arg_len = cmd.arg_len();
-            let ret = try_libc!({
-                let mut retval: i32 = 0;
-                let status = occlum_ocall_ioctl(
-                    &mut retval as *mut i32,
-                    host_stdin_fd,
-                    cmd_bits,
-                    cmd_arg_ptr,
-                    cmd_arg_len,
-                );
-                assert!(status == sgx_status_t::SGX_SUCCESS);
-                retval
-            });
-            cmd.validate_arg_and_ret_vals(ret)?;
 
-            Ok(ret)
-        }
-    */
+    fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        do_host_tty_ioctl(self.host_fd(), cmd)
+    }
 }
 
 impl Debug for StdinFile {
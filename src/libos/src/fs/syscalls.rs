@@ -1,7 +1,7 @@
 use super::file_ops::{
     /* AccessibilityCheckFlags, AccessibilityCheckMode, ChownFlags, FcntlCmd, FsPath, LinkFlags,
     StatFlags, UnlinkFlags, AT_FDCWD, */
-    self, AccessibilityCheckFlags, AccessibilityCheckMode, ChownFlags, FsPath, LinkFlags,
+    self, AccessibilityCheckFlags, AccessibilityCheckMode, ChownFlags, FcntlCmd, FsPath, LinkFlags,
     UnlinkFlags, AT_FDCWD,
 };
 //use super::fs_ops;
@@ -33,7 +33,7 @@ pub async fn do_openat(dirfd: i32, path: *const i8, flags: u32, mode: u32) -> Re
         .to_string_lossy()
         .into_owned();
     let fs_path = FsPath::new(&path, dirfd, false)?;
-    let fd = file_ops::do_openat(&fs_path, flags, mode)?;
+    let fd = file_ops::do_openat(&fs_path, flags, mode).await?;
     Ok(fd as isize)
 }
 
@@ -225,17 +225,32 @@ pub async fn do_truncate(path: *const i8, len: usize) -> Result<isize> {
     let path = from_user::clone_cstring_safely(path)?
         .to_string_lossy()
         .into_owned();
-    file_ops::do_truncate(&path, len)?;
+    file_ops::do_truncate(&path, len).await?;
     Ok(0)
 }
 
 pub async fn do_ftruncate(fd: FileDesc, len: usize) -> Result<isize> {
-    file_ops::do_ftruncate(fd, len)?;
+    file_ops::do_ftruncate(fd, len).await?;
     Ok(0)
 }
 
-/*
-pub fn do_getdents64(fd: FileDesc, buf: *mut u8, buf_size: usize) -> Result<isize> {
+pub async fn do_fallocate(fd: FileDesc, mode: i32, offset: off_t, len: off_t) -> Result<isize> {
+    if offset < 0 || len <= 0 {
+        return_errno!(EINVAL, "invalid offset or len");
+    }
+    file_ops::do_fallocate(fd, mode, offset as usize, len as usize).await?;
+    Ok(0)
+}
+
+pub async fn do_fadvise64(fd: FileDesc, offset: off_t, len: off_t, advice: i32) -> Result<isize> {
+    if offset < 0 || len < 0 {
+        return_errno!(EINVAL, "invalid offset or len");
+    }
+    file_ops::do_fadvise(fd, offset as usize, len as usize, advice)?;
+    Ok(0)
+}
+
+pub async fn do_getdents64(fd: FileDesc, buf: *mut u8, buf_size: usize) -> Result<isize> {
     let safe_buf = {
         from_user::check_mut_array(buf, buf_size)?;
         unsafe { std::slice::from_raw_parts_mut(buf, buf_size) }
@@ -244,7 +259,7 @@ pub fn do_getdents64(fd: FileDesc, buf: *mut u8, buf_size: usize) -> Result<isiz
     Ok(len as isize)
 }
 
-pub fn do_getdents(fd: FileDesc, buf: *mut u8, buf_size: usize) -> Result<isize> {
+pub async fn do_getdents(fd: FileDesc, buf: *mut u8, buf_size: usize) -> Result<isize> {
     let safe_buf = {
         from_user::check_mut_array(buf, buf_size)?;
         unsafe { std::slice::from_raw_parts_mut(buf, buf_size) }
@@ -252,13 +267,49 @@ pub fn do_getdents(fd: FileDesc, buf: *mut u8, buf_size: usize) -> Result<isize>
     let len = file_ops::do_getdents(fd, safe_buf)?;
     Ok(len as isize)
 }
-*/
 
 pub async fn do_sync() -> Result<isize> {
     fs_ops::do_sync()?;
     Ok(0)
 }
 
+pub async fn do_mount(
+    source: *const i8,
+    target: *const i8,
+    fs_type: *const i8,
+    flags: u32,
+    options: *const i8,
+) -> Result<isize> {
+    // The source, the type and the options are ignored by some kinds of mount
+    let clone_string_or_empty = |ptr: *const i8| -> Result<String> {
+        if ptr.is_null() {
+            return Ok(String::new());
+        }
+        Ok(from_user::clone_cstring_safely(ptr)?
+            .to_string_lossy()
+            .into_owned())
+    };
+    let source = clone_string_or_empty(source)?;
+    let target = from_user::clone_cstring_safely(target)?
+        .to_string_lossy()
+        .into_owned();
+    let fs_type = clone_string_or_empty(fs_type)?;
+    let options = clone_string_or_empty(options)?;
+    let flags = fs_ops::MountFlags::from_u32(flags);
+    fs_ops::do_mount(&source, &target, &fs_type, flags, &options)?;
+    Ok(0)
+}
+
+pub async fn do_umount2(target: *const i8, flags: u32) -> Result<isize> {
+    let target = from_user::clone_cstring_safely(target)?
+        .to_string_lossy()
+        .into_owned();
+    let flags =
+        fs_ops::UmountFlags::from_bits(flags).ok_or_else(|| errno!(EINVAL, "invalid flags"))?;
+    fs_ops::do_umount(&target, flags)?;
+    Ok(0)
+}
+
 pub async fn do_pipe(fds_u: *mut i32) -> Result<isize> {
     do_pipe2(fds_u, 0).await
 }
@@ -493,8 +544,8 @@ pub async fn do_lchown(path: *const i8, uid: u32, gid: u32) -> Result<isize> {
     )
     .await
 }
-/*
-pub fn do_sendfile(
+
+pub async fn do_sendfile(
     out_fd: FileDesc,
     in_fd: FileDesc,
     offset_ptr: *mut off_t,
@@ -507,7 +558,7 @@ pub fn do_sendfile(
         Some(unsafe { offset_ptr.read() })
     };
 
-    let (len, offset) = file_ops::do_sendfile(out_fd, in_fd, offset, count)?;
+    let (len, offset) = file_ops::do_sendfile(out_fd, in_fd, offset, count).await?;
     if !offset_ptr.is_null() {
         unsafe {
             offset_ptr.write(offset as off_t);
@@ -516,19 +567,19 @@ pub fn do_sendfile(
     Ok(len as isize)
 }
 
-pub fn do_fcntl(fd: FileDesc, cmd: u32, arg: u64) -> Result<isize> {
+pub async fn do_fcntl(fd: FileDesc, cmd: u32, arg: u64) -> Result<isize> {
     let mut cmd = FcntlCmd::from_raw(cmd, arg)?;
-    file_ops::do_fcntl(fd, &mut cmd)
+    file_ops::do_fcntl(fd, &mut cmd).await
+}
+
+pub async fn do_flock(fd: FileDesc, operation: u32) -> Result<isize> {
+    let ops = file_ops::FlockOps::from_bits(operation)
+        .ok_or_else(|| errno!(EINVAL, "invalid operation"))?;
+    file_ops::do_flock(fd, ops).await?;
+    Ok(0)
 }
 
 pub async fn do_ioctl(fd: FileDesc, cmd: u32, argp: *mut u8) -> Result<isize> {
-    let mut ioctl_cmd = unsafe {
-        if argp.is_null() == false {
-            from_user::check_mut_ptr(argp)?;
-        }
-        IoctlCmd::new(cmd, argp)?
-    };
-    file_ops::do_ioctl(fd, &mut ioctl_cmd)?;
+    file_ops::do_ioctl(fd, cmd, argp)?;
     Ok(0)
 }
-*/
@@ -2,12 +2,17 @@ use num_enum::IntoPrimitive;
 
 use crate::prelude::*;
 
-pub use async_io::socket::{Addr, CSockAddr, Domain, Ipv4Addr, Ipv4SocketAddr, UnixAddr};
+pub use async_io::socket::{
+    Addr, CSockAddr, Domain, Ipv4Addr, Ipv4SocketAddr, Ipv6Addr, Ipv6SocketAddr, NetlinkSocketAddr,
+    UnixAddr,
+};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum AnyAddr {
     Ipv4(Ipv4SocketAddr),
+    Ipv6(Ipv6SocketAddr),
     Unix(UnixAddr),
+    Netlink(NetlinkSocketAddr),
 }
 
 impl AnyAddr {
@@ -17,10 +22,18 @@ impl AnyAddr {
                 let ipv4_addr = Ipv4SocketAddr::from_c_storage(c_addr, c_addr_len)?;
                 Self::Ipv4(ipv4_addr)
             }
+            libc::AF_INET6 => {
+                let ipv6_addr = Ipv6SocketAddr::from_c_storage(c_addr, c_addr_len)?;
+                Self::Ipv6(ipv6_addr)
+            }
             libc::AF_UNIX | libc::AF_LOCAL => {
                 let unix_addr = UnixAddr::from_c_storage(c_addr, c_addr_len)?;
                 Self::Unix(unix_addr)
             }
+            libc::AF_NETLINK => {
+                let netlink_addr = NetlinkSocketAddr::from_c_storage(c_addr, c_addr_len)?;
+                Self::Netlink(netlink_addr)
+            }
             _ => {
                 return_errno!(EINVAL, "unsupported or invalid address family");
             }
@@ -31,7 +44,9 @@ impl AnyAddr {
     pub fn to_c_storage(&self) -> (libc::sockaddr_storage, usize) {
         match self {
             Self::Ipv4(ipv4_addr) => ipv4_addr.to_c_storage(),
+            Self::Ipv6(ipv6_addr) => ipv6_addr.to_c_storage(),
             Self::Unix(unix_addr) => unix_addr.to_c_storage(),
+            Self::Netlink(netlink_addr) => netlink_addr.to_c_storage(),
         }
     }
 
@@ -42,10 +57,24 @@ impl AnyAddr {
         }
     }
 
+    pub fn as_ipv6(&self) -> Option<&Ipv6SocketAddr> {
+        match self {
+            Self::Ipv6(ipv6_addr) => Some(ipv6_addr),
+            _ => None,
+        }
+    }
+
     pub fn as_unix(&self) -> Option<&UnixAddr> {
         match self {
             Self::Unix(unix_addr) => Some(unix_addr),
             _ => None,
         }
     }
+
+    pub fn as_netlink(&self) -> Option<&NetlinkSocketAddr> {
+        match self {
+            Self::Netlink(netlink_addr) => Some(netlink_addr),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,338 @@
+//! In-enclave connections between the stream sockets of the same LibOS instance.
+//!
+//! Both trusted unix stream sockets and the loopback connections of IP stream
+//! sockets are built upon the queue of pending connections and the pair of
+//! pipes here. The socket-specific parts, e.g., the addresses or the ancillary
+//! data of unix sockets, are left to the users.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use async_io::socket::{RecvFlags, SendFlags, Shutdown};
+use async_io::util::channel::{Channel, Consumer, Producer};
+use host_socket::Timer;
+
+use crate::fs::{Events, Pollee, Poller};
+use crate::net::util::{skip_bufs, skip_bufs_mut};
+use crate::prelude::*;
+
+/// The default buffer size of each direction, which is the same as the default
+/// value of /proc/sys/net/core/{r,w}mem_default on Linux.
+pub const DEFAULT_BUF_SIZE: usize = 208 * 1024;
+
+/// The queue of the pending connections of a listening socket, each of which is
+/// the accepted end of a connection.
+#[derive(Debug)]
+pub struct ConnQueue<E> {
+    incoming: SgxMutex<VecDeque<E>>,
+    capacity: AtomicUsize,
+    pollee: Pollee,
+    is_shutdown: AtomicBool,
+}
+
+impl<E> ConnQueue<E> {
+    /// Create a queue for the backlog argument of `listen`.
+    pub fn new(backlog: u32) -> Self {
+        Self {
+            incoming: SgxMutex::new(VecDeque::new()),
+            capacity: AtomicUsize::new(Self::capacity_of(backlog)),
+            pollee: Pollee::new(Events::OUT),
+            is_shutdown: AtomicBool::new(false),
+        }
+    }
+
+    /// Convert the backlog argument of `listen` to the capacity of the queue.
+    fn capacity_of(backlog: u32) -> usize {
+        // The same as the default value of /proc/sys/net/core/somaxconn on Linux
+        const SOMAXCONN: usize = 4096;
+        // Just like Linux, a backlog of N allows N + 1 pending connections
+        (backlog as usize).min(SOMAXCONN) + 1
+    }
+
+    /// Update the capacity when `listen` is called again.
+    pub fn set_backlog(&self, backlog: u32) {
+        let incoming = self.incoming.lock().unwrap();
+        self.capacity
+            .store(Self::capacity_of(backlog), Ordering::Relaxed);
+        self.update_pollee(&incoming);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.is_shutdown.load(Ordering::Relaxed)
+    }
+
+    /// Push a new connection, returning the connecting end of it.
+    ///
+    /// The pair of the connecting end and the accepted end is created only if
+    /// the connection can be pushed.
+    pub fn push(&self, new_pair: impl FnOnce() -> Result<(E, E)>) -> Result<E> {
+        let mut incoming = self.incoming.lock().unwrap();
+        if self.is_shutdown() {
+            return_errno!(ECONNREFUSED, "the peer socket is no longer listening");
+        }
+        if incoming.len() >= self.capacity.load(Ordering::Relaxed) {
+            return_errno!(EAGAIN, "the backlog is full");
+        }
+
+        let (connecting_end, accepted_end) = new_pair()?;
+        incoming.push_back(accepted_end);
+        self.update_pollee(&incoming);
+        Ok(connecting_end)
+    }
+
+    pub fn pop(&self) -> Result<E> {
+        let mut incoming = self.incoming.lock().unwrap();
+        let accepted_end = incoming
+            .pop_front()
+            .ok_or_else(|| errno!(EAGAIN, "no pending connections"))?;
+        self.update_pollee(&incoming);
+        Ok(accepted_end)
+    }
+
+    pub fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
+        self.pollee.poll(mask, poller)
+    }
+
+    /// Refuse the pending and future connections.
+    pub fn shutdown(&self) {
+        let mut incoming = self.incoming.lock().unwrap();
+        self.is_shutdown.store(true, Ordering::Relaxed);
+        // Dropping the accepted ends resets the pending connections
+        incoming.clear();
+        // Wake up the blocked connectors so that they can be refused
+        self.pollee.add_events(Events::OUT);
+    }
+
+    fn update_pollee(&self, incoming: &VecDeque<E>) {
+        if incoming.is_empty() {
+            self.pollee.del_events(Events::IN);
+        } else {
+            self.pollee.add_events(Events::IN);
+        }
+        if incoming.len() >= self.capacity.load(Ordering::Relaxed) {
+            self.pollee.del_events(Events::OUT);
+        } else {
+            self.pollee.add_events(Events::OUT);
+        }
+    }
+}
+
+/// The pipes of one end of an in-enclave connection, i.e., one to read the data
+/// from the peer and the other to write the data to the peer.
+#[derive(Debug)]
+pub struct Pipe {
+    reader: Consumer,
+    writer: Producer,
+}
+
+impl Pipe {
+    /// Create the pipes of the two ends of a connection.
+    pub fn new_pair() -> Result<(Self, Self)> {
+        let (this_writer, peer_reader) = Channel::with_capacity(DEFAULT_BUF_SIZE)?.split();
+        let (peer_writer, this_reader) = Channel::with_capacity(DEFAULT_BUF_SIZE)?.split();
+        let this_end = Self {
+            reader: this_reader,
+            writer: this_writer,
+        };
+        let peer_end = Self {
+            reader: peer_reader,
+            writer: peer_writer,
+        };
+        Ok((this_end, peer_end))
+    }
+
+    pub fn reader(&self) -> &Consumer {
+        &self.reader
+    }
+
+    pub fn writer(&self) -> &Producer {
+        &self.writer
+    }
+
+    pub fn buf_size(&self) -> usize {
+        self.writer.capacity()
+    }
+
+    /// Send the data with `try_send`, which writes the data to the writer without
+    /// blocking, waiting for the writer to have room if needed.
+    ///
+    /// Just like Linux, a blocking send returns only after all data are sent,
+    /// unless it fails or times out after some data are sent.
+    pub async fn sendmsg(
+        &self,
+        bufs: &[&[u8]],
+        flags: SendFlags,
+        is_nonblocking: bool,
+        timeout: Option<Duration>,
+        mut try_send: impl FnMut(&[&[u8]]) -> Result<usize>,
+    ) -> Result<usize> {
+        if flags.contains(SendFlags::MSG_OOB) {
+            return_errno!(EOPNOTSUPP, "MSG_OOB is not supported");
+        }
+        let is_nonblocking = is_nonblocking || flags.contains(SendFlags::MSG_DONTWAIT);
+
+        let total_len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        let mut sent_len = 0;
+        let mut poller = Poller::new();
+        let mut deadline = Deadline::new(timeout);
+        loop {
+            let remain_bufs = skip_bufs(bufs, sent_len);
+            match try_send(&remain_bufs) {
+                Ok(nbytes) => {
+                    sent_len += nbytes;
+                    if sent_len >= total_len || is_nonblocking {
+                        break;
+                    }
+                }
+                Err(e) if e.errno() != EAGAIN || is_nonblocking => {
+                    if sent_len > 0 {
+                        break;
+                    }
+                    return Err(e);
+                }
+                Err(_) => (),
+            }
+
+            let events = self.writer.poll(Events::OUT, Some(&mut poller));
+            if !events.contains(Events::OUT) {
+                if let Err(e) = deadline.wait(&mut poller).await {
+                    if sent_len > 0 {
+                        break;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(sent_len)
+    }
+
+    /// Receive the data with `try_recv`, which reads the data from the reader
+    /// without blocking, waiting for the reader to have data if needed.
+    ///
+    /// Besides the number of bytes received, `try_recv` returns whether to stop
+    /// receiving more data even if MSG_WAITALL is given.
+    pub async fn recvmsg(
+        &self,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+        is_nonblocking: bool,
+        timeout: Option<Duration>,
+        mut try_recv: impl FnMut(&mut [&mut [u8]]) -> Result<(usize, bool)>,
+    ) -> Result<usize> {
+        if flags.intersects(RecvFlags::MSG_OOB | RecvFlags::MSG_ERRQUEUE) {
+            return_errno!(EOPNOTSUPP, "MSG_OOB or MSG_ERRQUEUE is not supported");
+        }
+        let is_nonblocking = is_nonblocking || flags.contains(RecvFlags::MSG_DONTWAIT);
+        let is_waitall =
+            flags.contains(RecvFlags::MSG_WAITALL) && !flags.contains(RecvFlags::MSG_PEEK);
+
+        let total_len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        let mut recv_len = 0;
+        let mut poller = Poller::new();
+        let mut deadline = Deadline::new(timeout);
+        loop {
+            let res = {
+                let mut remain_bufs = skip_bufs_mut(bufs, recv_len);
+                try_recv(&mut remain_bufs)
+            };
+            match res {
+                // EOF
+                Ok((0, _)) => break,
+                Ok((nbytes, should_stop)) => {
+                    recv_len += nbytes;
+                    if !is_waitall || recv_len >= total_len || should_stop {
+                        break;
+                    }
+                }
+                Err(e) if e.errno() != EAGAIN || is_nonblocking => {
+                    if recv_len > 0 {
+                        break;
+                    }
+                    return Err(e);
+                }
+                Err(_) => (),
+            }
+
+            let events = self.reader.poll(Events::IN, Some(&mut poller));
+            if !events.contains(Events::IN) {
+                if let Err(e) = deadline.wait(&mut poller).await {
+                    if recv_len > 0 {
+                        break;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(recv_len)
+    }
+
+    /// Read or, if MSG_PEEK is given, peek the data without blocking.
+    pub fn try_recv(&self, bufs: &mut [&mut [u8]], flags: RecvFlags) -> Result<usize> {
+        if flags.contains(RecvFlags::MSG_PEEK) {
+            self.reader.peekv(bufs)
+        } else {
+            self.reader.readv(bufs)
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) {
+        if how.should_shut_read() {
+            self.reader.shutdown();
+        }
+        if how.should_shut_write() {
+            self.writer.shutdown();
+        }
+    }
+
+    pub fn poll(&self, mask: Events, mut poller: Option<&mut Poller>) -> Events {
+        let reader_events = self.reader.poll(Events::IN, poller.as_deref_mut());
+        let writer_events = self.writer.poll(Events::OUT, poller);
+
+        let mut events = (reader_events & Events::IN) | (writer_events & Events::OUT);
+        let is_read_shutdown = self.reader.is_shutdown();
+        let is_write_shutdown = self.writer.is_shutdown();
+        if is_read_shutdown {
+            events |= Events::IN | Events::RDHUP;
+        }
+        if is_write_shutdown {
+            // Writing will not block, but fail with EPIPE
+            events |= Events::OUT;
+        }
+        if is_read_shutdown && is_write_shutdown {
+            events |= Events::HUP;
+        }
+        events & (mask | Events::ALWAYS_POLL)
+    }
+}
+
+/// The deadline of a send or receive, i.e., SO_SNDTIMEO or SO_RCVTIMEO.
+pub struct Deadline {
+    timeout: Option<Duration>,
+    // The timer starts upon the first wait
+    timer: Option<Timer>,
+}
+
+impl Deadline {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            timer: None,
+        }
+    }
+
+    /// Wait for the events of the poller, failing with EAGAIN if the deadline
+    /// has passed, just like Linux.
+    pub async fn wait(&mut self, poller: &mut Poller) -> Result<()> {
+        if let Some(timeout) = self.timeout {
+            let timer = self
+                .timer
+                .get_or_insert_with(|| Timer::start(&*crate::io_uring::SINGLETON, timeout));
+            if timer.poll(Some(poller)) {
+                return_errno!(EAGAIN, "timed out");
+            }
+        }
+        poller.wait().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Weak;
+use std::time::Duration;
+
+use async_io::socket::{RecvFlags, SendFlags, Shutdown};
+
+use super::{is_loopback, is_unspecified};
+use crate::fs::{Events, Poller};
+use crate::net::conn::{ConnQueue, Pipe};
+use crate::net::{Domain, Ipv6SocketAddr};
+use crate::prelude::*;
+
+lazy_static! {
+    /// The in-enclave listeners of all listening IP stream sockets.
+    static ref LISTENERS: SgxMutex<Vec<Weak<Backlog>>> = SgxMutex::new(Vec::new());
+}
+
+/// The queue of the in-enclave incoming connections of a listening socket.
+///
+/// A listening IP stream socket accepts connections from both its host socket
+/// and its backlog, into which the connects from the same LibOS instance go.
+#[derive(Debug)]
+pub struct Backlog {
+    // The address of the listening socket in the IPv6 form
+    addr: Ipv6SocketAddr,
+    domain: Domain,
+    is_ipv6_only: bool,
+    is_reuse_port: bool,
+    queue: ConnQueue<Endpoint>,
+}
+
+impl Backlog {
+    /// Create a backlog for a listening socket and make it visible to the connects
+    /// of the same LibOS instance.
+    pub fn register(
+        addr: Ipv6SocketAddr,
+        domain: Domain,
+        is_ipv6_only: bool,
+        is_reuse_port: bool,
+        backlog: u32,
+    ) -> Arc<Self> {
+        let new_self = Arc::new(Self {
+            addr,
+            domain,
+            is_ipv6_only,
+            is_reuse_port,
+            queue: ConnQueue::new(backlog),
+        });
+        let mut listeners = LISTENERS.lock().unwrap();
+        listeners.retain(|listener| listener.strong_count() > 0);
+        listeners.push(Arc::downgrade(&new_self));
+        new_self
+    }
+
+    /// Look up the in-enclave listener of a peer address in the IPv6 form.
+    ///
+    /// Only loopback addresses are looked up. Other addresses, even if they are
+    /// the addresses of the host, always go through the host.
+    ///
+    /// Just like Linux, the listeners bound to the exact address are preferred to
+    /// the ones bound to the wildcard address. And if multiple listeners are bound
+    /// to the same address with `SO_REUSEPORT`, the connections are spread among
+    /// them in a round-robin way.
+    ///
+    /// The connections that go through the host are spread by the host instead,
+    /// as `SO_REUSEPORT` is set on the host sockets, which are thus grouped by the
+    /// host just as the listeners here are.
+    pub fn lookup(peer_addr: &Ipv6SocketAddr) -> Option<Arc<Self>> {
+        if !is_loopback(peer_addr.ip()) {
+            return None;
+        }
+        let candidates: Vec<Arc<Self>> = {
+            let listeners = LISTENERS.lock().unwrap();
+            listeners
+                .iter()
+                .filter_map(|listener| listener.upgrade())
+                .filter(|listener| listener.accepts(peer_addr))
+                .collect()
+        };
+
+        let is_exact = |listener: &Arc<Self>| listener.addr.ip() == peer_addr.ip();
+        let group: Vec<Arc<Self>> = if candidates.iter().any(is_exact) {
+            candidates.into_iter().filter(is_exact).collect()
+        } else {
+            candidates
+        };
+        // A listener without `SO_REUSEPORT` belongs to no group and thus takes all
+        // the connections
+        if let Some(listener) = group.iter().rev().find(|listener| !listener.is_reuse_port) {
+            return Some(listener.clone());
+        }
+        if group.is_empty() {
+            return None;
+        }
+        static NEXT_PICK: AtomicUsize = AtomicUsize::new(0);
+        let pick = NEXT_PICK.fetch_add(1, Ordering::Relaxed) % group.len();
+        group.into_iter().nth(pick)
+    }
+
+    fn accepts(&self, peer_addr: &Ipv6SocketAddr) -> bool {
+        if self.queue.is_shutdown() || self.addr.port() != peer_addr.port() {
+            return false;
+        }
+        let is_peer_ipv4 = peer_addr.ip().to_ipv4_mapped().is_some();
+        match self.domain {
+            Domain::Ipv4 if !is_peer_ipv4 => false,
+            Domain::Ipv6 if is_peer_ipv4 && self.is_ipv6_only => false,
+            _ => is_unspecified(self.addr.ip()) || self.addr.ip() == peer_addr.ip(),
+        }
+    }
+
+    /// Push an incoming connection, returning the endpoint of the connecting side.
+    ///
+    /// The addresses are the ones of the connecting side and the listening side.
+    pub fn push_incoming(
+        &self,
+        addr: Ipv6SocketAddr,
+        peer_addr: Ipv6SocketAddr,
+    ) -> Result<Endpoint> {
+        self.queue.push(|| Endpoint::new_pair(addr, peer_addr))
+    }
+
+    pub fn pop_incoming(&self) -> Result<Endpoint> {
+        self.queue.pop()
+    }
+
+    pub fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
+        self.queue.poll(mask, poller)
+    }
+
+    /// Refuse the pending and future connections.
+    pub fn shutdown(&self) {
+        self.queue.shutdown();
+    }
+}
+
+/// One end of an in-enclave connection between two IP stream sockets.
+///
+/// The addresses are in the IPv6 form, which are converted to the addresses of
+/// the domain of the socket when needed.
+#[derive(Debug)]
+pub struct Endpoint {
+    addr: Ipv6SocketAddr,
+    peer_addr: Ipv6SocketAddr,
+    pipe: Pipe,
+}
+
+impl Endpoint {
+    /// Create a pair of connected endpoints.
+    fn new_pair(this_addr: Ipv6SocketAddr, peer_addr: Ipv6SocketAddr) -> Result<(Self, Self)> {
+        let (this_pipe, peer_pipe) = Pipe::new_pair()?;
+        let this_end = Self {
+            addr: this_addr,
+            peer_addr,
+            pipe: this_pipe,
+        };
+        let peer_end = Self {
+            addr: peer_addr,
+            peer_addr: this_addr,
+            pipe: peer_pipe,
+        };
+        Ok((this_end, peer_end))
+    }
+
+    pub fn addr(&self) -> &Ipv6SocketAddr {
+        &self.addr
+    }
+
+    pub fn peer_addr(&self) -> &Ipv6SocketAddr {
+        &self.peer_addr
+    }
+
+    pub fn buf_size(&self) -> usize {
+        self.pipe.buf_size()
+    }
+
+    pub async fn sendmsg(
+        &self,
+        bufs: &[&[u8]],
+        flags: SendFlags,
+        is_nonblocking: bool,
+        timeout: Option<Duration>,
+    ) -> Result<usize> {
+        let writer = self.pipe.writer();
+        self.pipe
+            .sendmsg(bufs, flags, is_nonblocking, timeout, |bufs| {
+                writer.writev(bufs)
+            })
+            .await
+    }
+
+    pub async fn recvmsg(
+        &self,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+        is_nonblocking: bool,
+        timeout: Option<Duration>,
+    ) -> Result<usize> {
+        let pipe = &self.pipe;
+        pipe.recvmsg(bufs, flags, is_nonblocking, timeout, |bufs| {
+            Ok((pipe.try_recv(bufs, flags)?, false))
+        })
+        .await
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        self.pipe.shutdown(how);
+        Ok(())
+    }
+
+    pub fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
+        self.pipe.poll(mask, poller)
+    }
+}
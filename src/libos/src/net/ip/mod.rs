@@ -0,0 +1,67 @@
+//! IP sockets.
+//!
+//! IP stream sockets are backed by host sockets. But a connection between two
+//! sockets of the same LibOS instance over the loopback interface never leaves
+//! the enclave: a connect to a loopback address is served by an in-enclave
+//! listener if a LibOS socket listens on the address, which is both faster and
+//! invisible to the host. Otherwise, the connect goes through the host socket,
+//! e.g., to talk with a server on the host.
+
+use crate::net::{Addr, Ipv4Addr, Ipv4SocketAddr, Ipv6Addr, Ipv6SocketAddr};
+
+mod loopback;
+mod stream;
+
+pub use self::stream::IpStream;
+
+pub type Ipv4Stream = IpStream<Ipv4SocketAddr>;
+// An IPv6 stream is dual-stack, i.e., it can also talk with IPv4 peers through
+// IPv4-mapped IPv6 addresses (e.g., `::ffff:127.0.0.1`).
+pub type Ipv6Stream = IpStream<Ipv6SocketAddr>;
+
+/// An IP socket address, which can be converted to and from an IPv6 socket address.
+///
+/// IPv4 addresses are converted to IPv4-mapped IPv6 addresses so that the in-enclave
+/// connections between IPv4 and IPv6 sockets can be handled uniformly.
+pub trait IpSocketAddr: Addr + Copy + 'static {
+    fn to_ipv6(&self) -> Ipv6SocketAddr;
+
+    /// Returns `None` if the address cannot be represented in this type.
+    fn from_ipv6(addr: &Ipv6SocketAddr) -> Option<Self>;
+}
+
+impl IpSocketAddr for Ipv4SocketAddr {
+    fn to_ipv6(&self) -> Ipv6SocketAddr {
+        Ipv6SocketAddr::new(Ipv6Addr::from_ipv4_mapped(self.ip()), self.port())
+    }
+
+    fn from_ipv6(addr: &Ipv6SocketAddr) -> Option<Self> {
+        let ip = addr.ip().to_ipv4_mapped()?;
+        Some(Ipv4SocketAddr::new(ip, addr.port()))
+    }
+}
+
+impl IpSocketAddr for Ipv6SocketAddr {
+    fn to_ipv6(&self) -> Ipv6SocketAddr {
+        *self
+    }
+
+    fn from_ipv6(addr: &Ipv6SocketAddr) -> Option<Self> {
+        Some(*addr)
+    }
+}
+
+/// Returns whether the IP address, in the IPv6 form, is a loopback address,
+/// i.e., `::1` or `127.0.0.0/8`.
+fn is_loopback(ip: &Ipv6Addr) -> bool {
+    match ip.to_ipv4_mapped() {
+        Some(ipv4) => ipv4.octets()[0] == 127,
+        None => ip.is_loopback(),
+    }
+}
+
+/// Returns whether the IP address, in the IPv6 form, is a wildcard address,
+/// i.e., `::` or `0.0.0.0`.
+fn is_unspecified(ip: &Ipv6Addr) -> bool {
+    ip.is_unspecified() || ip.to_ipv4_mapped() == Some(Ipv4Addr::new(0, 0, 0, 0))
+}
@@ -0,0 +1,468 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use async_io::ioctl::IoctlCmd;
+use async_io::match_ioctl_cmd_mut;
+use async_io::socket::sockopt::*;
+use async_io::socket::{MsgFlags, RecvFlags, SendFlags, Shutdown};
+
+use super::loopback::{Backlog, Endpoint};
+use super::{is_unspecified, IpSocketAddr};
+use crate::fs::{Events, Poller, StatusFlags};
+use crate::net::conn::Deadline;
+use crate::net::socket_file::SocketRuntime;
+use crate::net::{Domain, Ipv6SocketAddr};
+use crate::prelude::*;
+
+type HostStream<A> = host_socket::StreamSocket<A, SocketRuntime>;
+
+/// An IP stream socket, whose connections go through either the host or, for the
+/// loopback connections between LibOS sockets, an in-enclave channel.
+///
+/// The host socket is kept even if the socket is connected inside the enclave,
+/// as it holds the port of the socket as well as the socket options. The only
+/// exception is the sockets accepted from in-enclave connections, which have no
+/// ports of their own and thus need no host sockets.
+#[derive(Debug)]
+pub struct IpStream<A: IpSocketAddr> {
+    host_stream: Option<HostStream<A>>,
+    loopback: RwLock<Loopback>,
+    // The status flags and the timeouts are also kept here since the socket may
+    // have no host socket.
+    is_nonblocking: AtomicBool,
+    timeouts: SgxMutex<Timeouts>,
+}
+
+#[derive(Debug, Default)]
+struct Timeouts {
+    recv: Option<Duration>,
+    send: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+enum Loopback {
+    // Not listening or connected inside the enclave
+    None,
+    Listen(Arc<Listener>),
+    Connected(Arc<Endpoint>),
+}
+
+/// The in-enclave part of a listening socket.
+#[derive(Debug)]
+struct Listener {
+    backlog: Arc<Backlog>,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        // Refuse the pending and future in-enclave connections
+        self.backlog.shutdown();
+    }
+}
+
+impl<A: IpSocketAddr> IpStream<A> {
+    pub fn new() -> Result<Self> {
+        let host_stream = HostStream::new()?;
+        Ok(Self::new_with_host(Some(host_stream), Loopback::None))
+    }
+
+    fn new_with_host(host_stream: Option<HostStream<A>>, loopback: Loopback) -> Self {
+        Self {
+            host_stream,
+            loopback: RwLock::new(loopback),
+            is_nonblocking: AtomicBool::new(false),
+            timeouts: SgxMutex::new(Timeouts::default()),
+        }
+    }
+
+    pub fn domain(&self) -> Domain {
+        A::domain()
+    }
+
+    pub fn bind(&self, addr: &A) -> Result<()> {
+        if !matches!(self.loopback(), Loopback::None) {
+            return_errno!(EINVAL, "cannot bind");
+        }
+        self.host_stream()?.bind(addr)
+    }
+
+    pub fn listen(&self, backlog: u32) -> Result<()> {
+        let mut loopback = self.loopback.write().unwrap();
+        if !matches!(&*loopback, Loopback::None) {
+            return_errno!(EINVAL, "cannot listen");
+        }
+        let host_stream = self.host_stream()?;
+        host_stream.listen(backlog)?;
+
+        let addr = host_stream.addr()?.to_ipv6();
+        let is_ipv6_only = self.is_ipv6_only();
+        let is_reuse_port = self.is_reuse_port();
+        let backlog = Backlog::register(addr, A::domain(), is_ipv6_only, is_reuse_port, backlog);
+        *loopback = Loopback::Listen(Arc::new(Listener { backlog }));
+        Ok(())
+    }
+
+    pub async fn connect(&self, peer_addr: &A) -> Result<()> {
+        match self.loopback() {
+            Loopback::None => (),
+            Loopback::Listen(_) => return_errno!(EINVAL, "the socket is listening"),
+            Loopback::Connected(_) => return_errno!(EISCONN, "the socket is already connected"),
+        }
+
+        let ipv6_peer_addr = peer_addr.to_ipv6();
+        match Backlog::lookup(&ipv6_peer_addr) {
+            Some(backlog) => self.connect_loopback(&backlog, &ipv6_peer_addr).await,
+            // No LibOS socket listens on the address, so it must be the host that does
+            None => self.host_stream()?.connect(peer_addr).await,
+        }
+    }
+
+    async fn connect_loopback(&self, backlog: &Backlog, peer_addr: &Ipv6SocketAddr) -> Result<()> {
+        let addr = self.bind_for_loopback(peer_addr)?;
+
+        // Fast path
+        let res = backlog.push_incoming(addr, *peer_addr);
+        let endpoint = if self.is_nonblocking() || !res.has_errno(EAGAIN) {
+            res?
+        } else {
+            // Slow path: wait for the backlog to have room. Unlike the host, the
+            // connect is not left in progress once it times out, so it fails with
+            // ETIMEDOUT rather than EINPROGRESS.
+            let timeout = self.timeouts.lock().unwrap().send;
+            let mut deadline = Deadline::new(timeout);
+            let mut poller = Poller::new();
+            loop {
+                let events = backlog.poll(Events::OUT, Some(&mut poller));
+                if events.contains(Events::OUT) {
+                    let res = backlog.push_incoming(addr, *peer_addr);
+                    if !res.has_errno(EAGAIN) {
+                        break res?;
+                    }
+                }
+                if deadline.wait(&mut poller).await.is_err() {
+                    return_errno!(ETIMEDOUT, "the connect timed out");
+                }
+            }
+        };
+
+        let mut loopback = self.loopback.write().unwrap();
+        if !matches!(&*loopback, Loopback::None) {
+            // The state may have been changed by another thread. Dropping the
+            // endpoint resets the connection.
+            return_errno!(EISCONN, "the socket is already connected");
+        }
+        *loopback = Loopback::Connected(Arc::new(endpoint));
+        Ok(())
+    }
+
+    /// Bind the host socket to the address of the peer with an ephemeral port if it
+    /// is unbound, returning the local address of an in-enclave connection.
+    ///
+    /// Binding the host socket reserves the port so that no other sockets, inside
+    /// or outside the enclave, can use the same local address.
+    fn bind_for_loopback(&self, peer_addr: &Ipv6SocketAddr) -> Result<Ipv6SocketAddr> {
+        let host_stream = self.host_stream()?;
+        let mut addr = host_stream.addr()?.to_ipv6();
+        if addr.port() == 0 {
+            let mut new_addr = *peer_addr;
+            new_addr.set_port(0);
+            host_stream.bind(&A::from_ipv6(&new_addr).unwrap())?;
+            addr = host_stream.addr()?.to_ipv6();
+        }
+        if is_unspecified(addr.ip()) {
+            addr.set_ip(*peer_addr.ip());
+        }
+        // E.g., an IPv6 socket bound to `::1` cannot reach `::ffff:127.0.0.1`
+        let is_ipv4 = |addr: &Ipv6SocketAddr| addr.ip().to_ipv4_mapped().is_some();
+        if is_ipv4(&addr) != is_ipv4(peer_addr) {
+            return_errno!(
+                ENETUNREACH,
+                "the local and peer addresses are of different families"
+            );
+        }
+        Ok(addr)
+    }
+
+    pub async fn accept(&self) -> Result<Self> {
+        let listener = match self.loopback() {
+            Loopback::Listen(listener) => listener,
+            // Let the host socket report the error
+            _ => return self.accept_host().await,
+        };
+        let backlog = &listener.backlog;
+
+        // Fast path
+        if let Ok(endpoint) = backlog.pop_incoming() {
+            return Ok(Self::new_loopback_accepted(endpoint));
+        }
+        if self.is_nonblocking() {
+            return self.accept_host().await;
+        }
+
+        // Slow path: wait for incoming connections from either the host or the
+        // enclave, bounded by SO_RCVTIMEO just like Linux
+        let host_stream = self.host_stream()?;
+        let timeout = self.timeouts.lock().unwrap().recv;
+        let mut deadline = Deadline::new(timeout);
+        let mut poller = Poller::new();
+        loop {
+            let mut events = backlog.poll(Events::IN, Some(&mut poller));
+            events |= host_stream.poll(Events::IN, Some(&mut poller));
+            if events.contains(Events::IN) {
+                if let Ok(endpoint) = backlog.pop_incoming() {
+                    return Ok(Self::new_loopback_accepted(endpoint));
+                }
+                if host_stream.poll(Events::IN, None).contains(Events::IN) {
+                    return self.accept_host().await;
+                }
+            }
+            deadline.wait(&mut poller).await?;
+        }
+    }
+
+    async fn accept_host(&self) -> Result<Self> {
+        let accepted_stream = self.host_stream()?.accept().await?;
+        Ok(Self::new_with_host(Some(accepted_stream), Loopback::None))
+    }
+
+    fn new_loopback_accepted(endpoint: Endpoint) -> Self {
+        let loopback = Loopback::Connected(Arc::new(endpoint));
+        Self::new_with_host(None, loopback)
+    }
+
+    pub async fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.readv(&mut [buf]).await
+    }
+
+    pub async fn readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        let (nbytes, _) = self.recvmsg(bufs, RecvFlags::empty()).await?;
+        Ok(nbytes)
+    }
+
+    pub async fn recvmsg(
+        &self,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+    ) -> Result<(usize, MsgFlags)> {
+        match self.loopback() {
+            Loopback::Connected(endpoint) => {
+                let timeout = self.timeouts.lock().unwrap().recv;
+                let nbytes = endpoint
+                    .recvmsg(bufs, flags, self.is_nonblocking(), timeout)
+                    .await?;
+                Ok((nbytes, MsgFlags::empty()))
+            }
+            _ => self.host_stream()?.recvmsg(bufs, flags).await,
+        }
+    }
+
+    pub async fn write(&self, buf: &[u8]) -> Result<usize> {
+        self.writev(&[buf]).await
+    }
+
+    pub async fn writev(&self, bufs: &[&[u8]]) -> Result<usize> {
+        self.sendmsg(bufs, SendFlags::empty()).await
+    }
+
+    pub async fn sendmsg(&self, bufs: &[&[u8]], flags: SendFlags) -> Result<usize> {
+        match self.loopback() {
+            Loopback::Connected(endpoint) => {
+                let timeout = self.timeouts.lock().unwrap().send;
+                endpoint
+                    .sendmsg(bufs, flags, self.is_nonblocking(), timeout)
+                    .await
+            }
+            _ => self.host_stream()?.sendmsg(bufs, flags).await,
+        }
+    }
+
+    pub fn poll(&self, mask: Events, mut poller: Option<&mut Poller>) -> Events {
+        match (self.loopback(), &self.host_stream) {
+            (Loopback::Connected(endpoint), _) => endpoint.poll(mask, poller),
+            (Loopback::Listen(listener), Some(host_stream)) => {
+                let backlog_events = listener
+                    .backlog
+                    .poll(mask & Events::IN, poller.as_deref_mut());
+                backlog_events | host_stream.poll(mask, poller)
+            }
+            (_, Some(host_stream)) => host_stream.poll(mask, poller),
+            // Unreachable since only the accepted sockets of in-enclave connections
+            // have no host sockets
+            (_, None) => Events::empty(),
+        }
+    }
+
+    pub fn status_flags(&self) -> StatusFlags {
+        if self.is_nonblocking() {
+            StatusFlags::O_NONBLOCK
+        } else {
+            StatusFlags::empty()
+        }
+    }
+
+    pub fn set_status_flags(&self, new_flags: StatusFlags) -> Result<()> {
+        if let Some(host_stream) = &self.host_stream {
+            host_stream.set_status_flags(new_flags)?;
+        }
+        let is_nonblocking = new_flags.contains(StatusFlags::O_NONBLOCK);
+        self.is_nonblocking.store(is_nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        match self.loopback() {
+            Loopback::Connected(endpoint) => endpoint.shutdown(how),
+            _ => self.host_stream()?.shutdown(how),
+        }
+    }
+
+    pub fn addr(&self) -> Result<A> {
+        match self.loopback() {
+            Loopback::Connected(endpoint) => Ok(Self::addr_from_ipv6(endpoint.addr())),
+            _ => self.host_stream()?.addr(),
+        }
+    }
+
+    pub fn peer_addr(&self) -> Result<A> {
+        match self.loopback() {
+            Loopback::Connected(endpoint) => Ok(Self::addr_from_ipv6(endpoint.peer_addr())),
+            _ => self.host_stream()?.peer_addr(),
+        }
+    }
+
+    pub fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        // The timeouts are kept by both this socket and the host socket, if any,
+        // so that they apply to both in-enclave and host connections.
+        match_ioctl_cmd_mut!(&mut *cmd, {
+            cmd: SetRecvTimeout => {
+                if let Some(host_stream) = &self.host_stream {
+                    host_stream.ioctl(cmd)?;
+                }
+                self.timeouts.lock().unwrap().recv = *cmd.input();
+                return Ok(());
+            },
+            cmd: GetRecvTimeout => {
+                cmd.set_output(self.timeouts.lock().unwrap().recv);
+                return Ok(());
+            },
+            cmd: SetSendTimeout => {
+                if let Some(host_stream) = &self.host_stream {
+                    host_stream.ioctl(cmd)?;
+                }
+                self.timeouts.lock().unwrap().send = *cmd.input();
+                return Ok(());
+            },
+            cmd: GetSendTimeout => {
+                cmd.set_output(self.timeouts.lock().unwrap().send);
+                return Ok(());
+            },
+            _ => (),
+        });
+
+        let endpoint = match self.loopback() {
+            Loopback::Connected(endpoint) => endpoint,
+            _ => return self.host_stream()?.ioctl(cmd),
+        };
+        match_ioctl_cmd_mut!(&mut *cmd, {
+            cmd: GetSockError => {
+                cmd.set_output(None);
+                Ok(())
+            },
+            cmd: GetSendBufSize => {
+                cmd.set_output(endpoint.buf_size());
+                Ok(())
+            },
+            cmd: GetRecvBufSize => {
+                cmd.set_output(endpoint.buf_size());
+                Ok(())
+            },
+            // The buffer sizes of in-enclave connections are fixed for now. But,
+            // just like Linux, setting them always succeeds.
+            cmd: SetSendBufSize => {
+                Ok(())
+            },
+            cmd: SetRecvBufSize => {
+                Ok(())
+            },
+            _ => {
+                match &self.host_stream {
+                    // Other options, e.g., TCP_NODELAY, are kept by the host socket
+                    Some(host_stream) => host_stream.ioctl(cmd),
+                    None => Self::ioctl_without_host(cmd),
+                }
+            }
+        })
+    }
+
+    /// Handle the options of an accepted socket of an in-enclave connection,
+    /// which has no host socket to keep them.
+    ///
+    /// The TCP options and the like have no effect on in-enclave connections. So
+    /// setting them succeeds, but they are not kept.
+    fn ioctl_without_host(cmd: &mut dyn IoctlCmd) -> Result<()> {
+        match_ioctl_cmd_mut!(&mut *cmd, {
+            cmd: SetReuseAddr => { Ok(()) },
+            cmd: SetReusePort => { Ok(()) },
+            cmd: SetKeepAlive => { Ok(()) },
+            cmd: SetLinger => { Ok(()) },
+            cmd: SetTcpNoDelay => { Ok(()) },
+            cmd: SetTcpCork => { Ok(()) },
+            cmd: SetTcpKeepIdle => { Ok(()) },
+            cmd: SetTcpKeepIntvl => { Ok(()) },
+            cmd: SetTcpKeepCnt => { Ok(()) },
+            _ => {
+                return_errno!(ENOPROTOOPT, "the socket option is not kept for in-enclave connections");
+            }
+        })
+    }
+
+    fn is_ipv6_only(&self) -> bool {
+        if A::domain() != Domain::Ipv6 {
+            return false;
+        }
+        let host_stream = match &self.host_stream {
+            Some(host_stream) => host_stream,
+            None => return false,
+        };
+        let mut cmd = GetIpv6Only::new(());
+        match host_stream.ioctl(&mut cmd) {
+            Ok(()) => cmd.output().copied().unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    fn is_reuse_port(&self) -> bool {
+        let host_stream = match &self.host_stream {
+            Some(host_stream) => host_stream,
+            None => return false,
+        };
+        let mut cmd = GetReusePort::new(());
+        match host_stream.ioctl(&mut cmd) {
+            Ok(()) => cmd.output().copied().unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        self.is_nonblocking.load(Ordering::Relaxed)
+    }
+
+    fn host_stream(&self) -> Result<&HostStream<A>> {
+        // Only the accepted sockets of in-enclave connections have no host sockets
+        self.host_stream
+            .as_ref()
+            .ok_or_else(|| errno!(EINVAL, "the socket is connected inside the enclave"))
+    }
+
+    /// Convert an address of an in-enclave connection to the domain of the socket.
+    ///
+    /// The conversion never fails as an IPv4 socket can only be connected with
+    /// IPv4 addresses.
+    fn addr_from_ipv6(addr: &Ipv6SocketAddr) -> A {
+        A::from_ipv6(addr).unwrap()
+    }
+
+    fn loopback(&self) -> Loopback {
+        self.loopback.read().unwrap().clone()
+    }
+}
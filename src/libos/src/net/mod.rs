@@ -1,9 +1,36 @@
 //! The network subsystem.
 
 mod addr;
+mod conn;
+mod ip;
+mod msg;
+mod netlink;
 mod socket_file;
+mod sockopt;
 mod syscalls;
+mod unix;
+mod util;
 
-pub use self::addr::{Addr, AnyAddr, CSockAddr, Domain, Ipv4Addr, Ipv4SocketAddr, UnixAddr};
+use crate::config::LIBOS_CONFIG;
+
+pub use self::addr::{
+    Addr, AnyAddr, CSockAddr, Domain, Ipv4Addr, Ipv4SocketAddr, Ipv6Addr, Ipv6SocketAddr,
+    NetlinkSocketAddr, UnixAddr,
+};
+pub use self::msg::{mmsghdr, msghdr, msghdr_mut};
 pub use self::socket_file::SocketFile;
 pub use self::syscalls::*;
+
+/// Initialize the network subsystem according to the LibOS configuration.
+pub fn init() {
+    let config = &LIBOS_CONFIG.network;
+    if let Some(min_size) = config.socket_buf_min_size {
+        host_socket::config::set_min_buf_size(min_size);
+    }
+    if let Some(max_size) = config.socket_buf_max_size {
+        host_socket::config::set_max_buf_size(max_size);
+    }
+    if let Some(budget) = config.socket_buf_budget {
+        host_socket::config::set_buf_budget(budget);
+    }
+}
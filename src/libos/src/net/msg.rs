@@ -0,0 +1,182 @@
+//! Socket messages and their control messages.
+
+use std::mem::size_of;
+
+use crate::prelude::*;
+
+/// C struct for a socket message with const pointers
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct msghdr {
+    pub msg_name: *const c_void,
+    pub msg_namelen: libc::socklen_t,
+    pub msg_iov: *const libc::iovec,
+    pub msg_iovlen: size_t,
+    pub msg_control: *const c_void,
+    pub msg_controllen: size_t,
+    pub msg_flags: c_int,
+}
+
+/// C struct for a socket message with mutable pointers
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct msghdr_mut {
+    pub msg_name: *mut c_void,
+    pub msg_namelen: libc::socklen_t,
+    pub msg_iov: *mut libc::iovec,
+    pub msg_iovlen: size_t,
+    pub msg_control: *mut c_void,
+    pub msg_controllen: size_t,
+    pub msg_flags: c_int,
+}
+
+/// C struct for an element of the message vectors used by `sendmmsg` and `recvmmsg`
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct mmsghdr {
+    pub msg_hdr: msghdr_mut,
+    pub msg_len: c_uint,
+}
+
+/// C struct for the header of a control message
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct cmsghdr {
+    cmsg_len: size_t,
+    cmsg_level: c_int,
+    cmsg_type: c_int,
+}
+
+/// A control message, a.k.a., ancillary data.
+#[derive(Debug)]
+pub struct CMsg<'a> {
+    level: i32,
+    type_: i32,
+    data: &'a [u8],
+}
+
+impl<'a> CMsg<'a> {
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    pub fn type_(&self) -> i32 {
+        self.type_
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// An iterator over the control messages in a control buffer.
+///
+/// Malformed control messages, e.g., the ones whose lengths exceed the
+/// buffer, result in EINVAL, just like Linux.
+pub struct CMsgIter<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> CMsgIter<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+}
+
+impl<'a> Iterator for CMsgIter<'a> {
+    type Item = Result<CMsg<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Just like CMSG_FIRSTHDR and CMSG_NXTHDR, the remaining bytes that are
+        // too few to hold a header are ignored.
+        const HDR_LEN: usize = size_of::<cmsghdr>();
+        if self.buf.len() < HDR_LEN {
+            return None;
+        }
+
+        // Safety. The buffer is large enough to hold a header, which is C-style.
+        let hdr = unsafe { std::ptr::read_unaligned(self.buf.as_ptr() as *const cmsghdr) };
+        let cmsg_len = hdr.cmsg_len as usize;
+        if cmsg_len < HDR_LEN || cmsg_len > self.buf.len() {
+            // Stop the iteration after an error
+            self.buf = &[];
+            return Some(Err(errno!(EINVAL, "invalid control message length")));
+        }
+
+        let cmsg = CMsg {
+            level: hdr.cmsg_level,
+            type_: hdr.cmsg_type,
+            data: &self.buf[HDR_LEN..cmsg_len],
+        };
+        let next_offset = align_up(cmsg_len, size_of::<size_t>()).min(self.buf.len());
+        self.buf = &self.buf[next_offset..];
+        Some(Ok(cmsg))
+    }
+}
+
+/// A writer that puts control messages into a control buffer.
+///
+/// Just like Linux, a control message that does not fit into the buffer is
+/// truncated, which is indicated by `MSG_CTRUNC`.
+pub struct CMsgWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    is_truncated: bool,
+}
+
+impl<'a> CMsgWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            len: 0,
+            is_truncated: false,
+        }
+    }
+
+    /// Returns the max length of the data that the next control message can carry.
+    pub fn data_capacity(&self) -> usize {
+        (self.buf.len() - self.len).saturating_sub(size_of::<cmsghdr>())
+    }
+
+    /// Write a control message, returning the length of the data actually written.
+    pub fn write(&mut self, level: i32, type_: i32, data: &[u8]) -> usize {
+        const HDR_LEN: usize = size_of::<cmsghdr>();
+        if self.buf.len() - self.len < HDR_LEN {
+            self.is_truncated = true;
+            return 0;
+        }
+
+        let data_len = data.len().min(self.data_capacity());
+        if data_len < data.len() {
+            self.is_truncated = true;
+        }
+        let hdr = cmsghdr {
+            cmsg_len: (HDR_LEN + data_len) as size_t,
+            cmsg_level: level,
+            cmsg_type: type_,
+        };
+        let hdr_buf = &mut self.buf[self.len..self.len + HDR_LEN];
+        // Safety. The buffer is large enough to hold a header, which is C-style.
+        unsafe { std::ptr::write_unaligned(hdr_buf.as_mut_ptr() as *mut cmsghdr, hdr) };
+        let data_buf = &mut self.buf[self.len + HDR_LEN..self.len + HDR_LEN + data_len];
+        data_buf.copy_from_slice(&data[..data_len]);
+
+        let next_len = align_up(self.len + HDR_LEN + data_len, size_of::<size_t>());
+        self.len = next_len.min(self.buf.len());
+        data_len
+    }
+
+    /// Mark the control messages as truncated.
+    pub fn set_truncated(&mut self) {
+        self.is_truncated = true;
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.is_truncated
+    }
+
+    /// Returns the total length of the control messages written so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+}
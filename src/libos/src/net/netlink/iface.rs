@@ -0,0 +1,185 @@
+use std::net::IpAddr;
+
+use crate::config::{ConfigNetworkInterface, LIBOS_CONFIG};
+use crate::prelude::*;
+
+lazy_static! {
+    /// The network interfaces, which are fetched only once.
+    pub static ref IFACES: Vec<Iface> = {
+        match &LIBOS_CONFIG.network.interfaces {
+            Some(config_ifaces) => config_ifaces.iter().map(Iface::from_config).collect(),
+            None => get_host_ifaces().unwrap_or_else(|e| {
+                warn!("failed to get the host network interfaces: {:?}", e);
+                Vec::new()
+            }),
+        }
+    };
+}
+
+/// A network interface.
+#[derive(Debug)]
+pub struct Iface {
+    pub index: u32,
+    pub name: String,
+    /// The flags, i.e., IFF_*.
+    pub flags: u32,
+    pub mtu: u32,
+    pub hw_addr: [u8; 6],
+    pub addrs: Vec<IfaceAddr>,
+}
+
+/// An IP address of a network interface.
+#[derive(Debug)]
+pub struct IfaceAddr {
+    pub ip: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl Iface {
+    pub fn is_loopback(&self) -> bool {
+        self.flags & IFF_LOOPBACK != 0
+    }
+
+    fn from_config(config_iface: &ConfigNetworkInterface) -> Self {
+        let flags = if config_iface.loopback {
+            IFF_UP | IFF_LOOPBACK | IFF_RUNNING
+        } else {
+            IFF_UP | IFF_BROADCAST | IFF_RUNNING | IFF_MULTICAST
+        };
+        let addrs = config_iface
+            .addrs
+            .iter()
+            .map(|(ip, prefix_len)| IfaceAddr {
+                ip: *ip,
+                prefix_len: *prefix_len,
+            })
+            .collect();
+        Self {
+            index: config_iface.index,
+            name: config_iface.name.clone(),
+            flags,
+            mtu: config_iface.mtu,
+            hw_addr: config_iface.mac,
+            addrs,
+        }
+    }
+}
+
+pub const IFF_UP: u32 = 0x1;
+pub const IFF_BROADCAST: u32 = 0x2;
+pub const IFF_LOOPBACK: u32 = 0x8;
+pub const IFF_RUNNING: u32 = 0x40;
+pub const IFF_MULTICAST: u32 = 0x1000;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(non_camel_case_types)]
+struct occlum_ifaddr {
+    name: [u8; 16],
+    index: u32,
+    flags: u32,
+    mtu: u32,
+    hw_addr: [u8; 6],
+    family: u16,
+    addr: [u8; 16],
+    prefix_len: u32,
+}
+
+fn get_host_ifaces() -> Result<Vec<Iface>> {
+    extern "C" {
+        fn occlum_ocall_get_ifaddrs(
+            ret: *mut i32,
+            ifaddrs: *mut occlum_ifaddr,
+            max_count: usize,
+        ) -> sgx_status_t;
+    }
+
+    // The count of addresses reported by the host is untrusted. So the buffer
+    // grows to no more than MAX_COUNT entries, and only a few times.
+    const INIT_COUNT: usize = 64;
+    const MAX_COUNT: usize = 1024;
+    const MAX_RETRIES: usize = 3;
+
+    let mut max_count = INIT_COUNT;
+    let mut retries = 0;
+    let ifaddrs = loop {
+        // Safety. All fields of occlum_ifaddr can be zeros.
+        let mut ifaddrs = vec![unsafe { std::mem::zeroed::<occlum_ifaddr>() }; max_count];
+        let count = try_libc!({
+            let mut retval: i32 = 0;
+            let status =
+                occlum_ocall_get_ifaddrs(&mut retval as *mut i32, ifaddrs.as_mut_ptr(), max_count);
+            if status != sgx_status_t::SGX_SUCCESS {
+                return_errno!(EIO, "failed to get the host network interfaces");
+            }
+            retval
+        }) as usize;
+        if count <= max_count {
+            ifaddrs.truncate(count);
+            break ifaddrs;
+        }
+        if count > MAX_COUNT || retries >= MAX_RETRIES {
+            return_errno!(EIO, "too many host network interface addresses");
+        }
+        max_count = count;
+        retries += 1;
+    };
+
+    // Group the addresses by the interfaces
+    let mut ifaces: Vec<Iface> = Vec::new();
+    for ifaddr in &ifaddrs {
+        // The name from the host may not be NUL-terminated. So at most
+        // IFNAMSIZ - 1 bytes are taken, just as the name is NUL-terminated.
+        let name = {
+            let name = &ifaddr.name[..ifaddr.name.len() - 1];
+            let name_len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+            String::from_utf8_lossy(&name[..name_len]).into_owned()
+        };
+        let iface = match ifaces.iter_mut().position(|iface| iface.name == name) {
+            Some(pos) => &mut ifaces[pos],
+            None => {
+                ifaces.push(Iface {
+                    index: ifaddr.index,
+                    name,
+                    flags: ifaddr.flags,
+                    mtu: ifaddr.mtu,
+                    hw_addr: [0; 6],
+                    addrs: Vec::new(),
+                });
+                ifaces.last_mut().unwrap()
+            }
+        };
+
+        match ifaddr.family as i32 {
+            libc::AF_PACKET => iface.hw_addr = ifaddr.hw_addr,
+            libc::AF_INET => {
+                if ifaddr.prefix_len > 32 {
+                    return_errno!(EIO, "invalid prefix length of an IPv4 address");
+                }
+                let mut octets = [0u8; 4];
+                octets.copy_from_slice(&ifaddr.addr[..4]);
+                iface.addrs.push(IfaceAddr {
+                    ip: IpAddr::from(octets),
+                    prefix_len: ifaddr.prefix_len as u8,
+                });
+            }
+            libc::AF_INET6 => {
+                if ifaddr.prefix_len > 128 {
+                    return_errno!(EIO, "invalid prefix length of an IPv6 address");
+                }
+                iface.addrs.push(IfaceAddr {
+                    ip: IpAddr::from(ifaddr.addr),
+                    prefix_len: ifaddr.prefix_len as u8,
+                });
+            }
+            _ => {
+                warn!(
+                    "unexpected family of interface addresses: {}",
+                    ifaddr.family
+                );
+            }
+        }
+    }
+    ifaces.sort_by_key(|iface| iface.index);
+    Ok(ifaces)
+}
@@ -0,0 +1,262 @@
+//! Netlink messages, including the ones specific to the route protocol.
+
+use std::mem::size_of;
+
+use crate::prelude::*;
+
+// Standard message types
+pub const NLMSG_ERROR: u16 = 2;
+pub const NLMSG_DONE: u16 = 3;
+
+// Message types of the route protocol
+pub const RTM_NEWLINK: u16 = 16;
+pub const RTM_GETLINK: u16 = 18;
+pub const RTM_NEWADDR: u16 = 20;
+pub const RTM_GETADDR: u16 = 22;
+pub const RTM_NEWROUTE: u16 = 24;
+pub const RTM_GETROUTE: u16 = 26;
+
+// Message flags
+pub const NLM_F_REQUEST: u16 = 0x1;
+pub const NLM_F_MULTI: u16 = 0x2;
+pub const NLM_F_ACK: u16 = 0x4;
+pub const NLM_F_DUMP: u16 = 0x300;
+
+// Attribute types of RTM_NEWLINK
+pub const IFLA_ADDRESS: u16 = 1;
+pub const IFLA_BROADCAST: u16 = 2;
+pub const IFLA_IFNAME: u16 = 3;
+pub const IFLA_MTU: u16 = 4;
+pub const IFLA_TXQLEN: u16 = 13;
+pub const IFLA_OPERSTATE: u16 = 16;
+
+// Attribute types of RTM_NEWADDR
+pub const IFA_ADDRESS: u16 = 1;
+pub const IFA_LOCAL: u16 = 2;
+pub const IFA_LABEL: u16 = 3;
+pub const IFA_BROADCAST: u16 = 4;
+
+// Attribute types of RTM_NEWROUTE
+pub const RTA_DST: u16 = 1;
+pub const RTA_OIF: u16 = 4;
+pub const RTA_PREFSRC: u16 = 7;
+pub const RTA_TABLE: u16 = 15;
+
+/// The alignment of messages and attributes.
+const NLMSG_ALIGNTO: usize = 4;
+
+/// C struct for the header of a netlink message.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+#[allow(non_camel_case_types)]
+pub struct nlmsghdr {
+    pub nlmsg_len: u32,
+    pub nlmsg_type: u16,
+    pub nlmsg_flags: u16,
+    pub nlmsg_seq: u32,
+    pub nlmsg_pid: u32,
+}
+
+/// C struct for the payload of an `NLMSG_ERROR` message.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+#[allow(non_camel_case_types)]
+pub struct nlmsgerr {
+    pub error: i32,
+    pub msg: nlmsghdr,
+}
+
+/// C struct for the payload of a link message.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+#[allow(non_camel_case_types)]
+pub struct ifinfomsg {
+    pub ifi_family: u8,
+    pub ifi_pad: u8,
+    pub ifi_type: u16,
+    pub ifi_index: i32,
+    pub ifi_flags: u32,
+    pub ifi_change: u32,
+}
+
+/// C struct for the payload of an address message.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+#[allow(non_camel_case_types)]
+pub struct ifaddrmsg {
+    pub ifa_family: u8,
+    pub ifa_prefixlen: u8,
+    pub ifa_flags: u8,
+    pub ifa_scope: u8,
+    pub ifa_index: u32,
+}
+
+/// C struct for the payload of a route message.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+#[allow(non_camel_case_types)]
+pub struct rtmsg {
+    pub rtm_family: u8,
+    pub rtm_dst_len: u8,
+    pub rtm_src_len: u8,
+    pub rtm_tos: u8,
+    pub rtm_table: u8,
+    pub rtm_protocol: u8,
+    pub rtm_scope: u8,
+    pub rtm_type: u8,
+    pub rtm_flags: u32,
+}
+
+/// C struct for the header of an attribute.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+#[allow(non_camel_case_types)]
+struct rtattr {
+    rta_len: u16,
+    rta_type: u16,
+}
+
+/// A netlink message received from the user.
+pub struct Request<'a> {
+    pub hdr: nlmsghdr,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Request<'a> {
+    /// Returns the family in the payload, which is the first byte of all request
+    /// payloads of the route protocol.
+    pub fn family(&self) -> u8 {
+        self.payload.first().copied().unwrap_or(0)
+    }
+
+    pub fn is_dump(&self) -> bool {
+        self.hdr.nlmsg_flags & NLM_F_DUMP == NLM_F_DUMP
+    }
+
+    /// Read the payload as a C struct, returning `None` if the payload is too short.
+    pub fn payload_as<T: Copy>(&self) -> Option<T> {
+        read_val(self.payload)
+    }
+}
+
+/// An iterator over the netlink messages in a buffer.
+///
+/// Just like Linux, the iteration stops at the first malformed message.
+pub struct RequestIter<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> RequestIter<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+}
+
+impl<'a> Iterator for RequestIter<'a> {
+    type Item = Request<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        const HDR_LEN: usize = size_of::<nlmsghdr>();
+        let hdr: nlmsghdr = read_val(self.buf)?;
+        let msg_len = hdr.nlmsg_len as usize;
+        if msg_len < HDR_LEN || msg_len > self.buf.len() {
+            self.buf = &[];
+            return None;
+        }
+
+        let request = Request {
+            hdr,
+            payload: &self.buf[HDR_LEN..msg_len],
+        };
+        let next_offset = align_up(msg_len, NLMSG_ALIGNTO).min(self.buf.len());
+        self.buf = &self.buf[next_offset..];
+        Some(request)
+    }
+}
+
+/// A netlink message to be sent to the user.
+pub struct Response {
+    buf: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(type_: u16, flags: u16, seq: u32, port_id: u32) -> Self {
+        let mut new_self = Self { buf: Vec::new() };
+        new_self.put(&nlmsghdr {
+            nlmsg_len: 0,
+            nlmsg_type: type_,
+            nlmsg_flags: flags,
+            nlmsg_seq: seq,
+            nlmsg_pid: port_id,
+        });
+        new_self
+    }
+
+    /// Create an `NLMSG_ERROR` message, which is an acknowledgment if the errno is zero.
+    pub fn new_error(request: &nlmsghdr, errno: i32, port_id: u32) -> Self {
+        let mut new_self = Self::new(NLMSG_ERROR, 0, request.nlmsg_seq, port_id);
+        new_self.put(&nlmsgerr {
+            error: -errno,
+            msg: *request,
+        });
+        new_self
+    }
+
+    /// Create an `NLMSG_DONE` message, which ends a dump.
+    pub fn new_done(seq: u32, port_id: u32) -> Self {
+        let mut new_self = Self::new(NLMSG_DONE, NLM_F_MULTI, seq, port_id);
+        new_self.put(&0i32);
+        new_self
+    }
+
+    /// Put a C struct into the payload.
+    pub fn put<T: Copy>(&mut self, val: &T) {
+        // Safety. All the C structs put into messages have no padding bytes.
+        let bytes =
+            unsafe { std::slice::from_raw_parts(val as *const T as *const u8, size_of::<T>()) };
+        self.put_bytes(bytes);
+    }
+
+    /// Put an attribute into the payload.
+    pub fn put_attr(&mut self, type_: u16, data: &[u8]) {
+        self.put(&rtattr {
+            rta_len: (size_of::<rtattr>() + data.len()) as u16,
+            rta_type: type_,
+        });
+        self.put_bytes(data);
+    }
+
+    /// Put an attribute of a string, which is null-terminated.
+    pub fn put_attr_str(&mut self, type_: u16, s: &str) {
+        let mut data = Vec::with_capacity(s.len() + 1);
+        data.extend_from_slice(s.as_bytes());
+        data.push(0);
+        self.put_attr(type_, &data);
+    }
+
+    pub fn put_attr_u32(&mut self, type_: u16, val: u32) {
+        self.put_attr(type_, &val.to_ne_bytes());
+    }
+
+    fn put_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+        let aligned_len = align_up(self.buf.len(), NLMSG_ALIGNTO);
+        self.buf.resize(aligned_len, 0);
+    }
+
+    /// Returns the message in bytes.
+    pub fn into_bytes(mut self) -> Vec<u8> {
+        let msg_len = self.buf.len() as u32;
+        self.buf[..size_of::<u32>()].copy_from_slice(&msg_len.to_ne_bytes());
+        self.buf
+    }
+}
+
+fn read_val<T: Copy>(buf: &[u8]) -> Option<T> {
+    if buf.len() < size_of::<T>() {
+        return None;
+    }
+    // Safety. The buffer is large enough and all netlink structs are C-style types.
+    let val = unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const T) };
+    Some(val)
+}
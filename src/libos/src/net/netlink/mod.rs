@@ -0,0 +1,14 @@
+//! Netlink sockets.
+//!
+//! Only the route protocol (`NETLINK_ROUTE`) is supported, which is emulated
+//! inside the LibOS. It is just enough to answer the dump requests of network
+//! interfaces, addresses and routes, which are used by, e.g., `getifaddrs`,
+//! `if_nameindex` and the network interface APIs of Go and Java. The answers come
+//! from the network interfaces of the host, or from the synthetic interfaces given
+//! in the config.
+
+mod iface;
+mod message;
+mod route;
+
+pub use self::route::NetlinkRouteSocket;
@@ -0,0 +1,581 @@
+use std::collections::{HashSet, VecDeque};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use async_io::ioctl::IoctlCmd;
+use async_io::match_ioctl_cmd_mut;
+use async_io::socket::sockopt::*;
+use async_io::socket::{MsgFlags, RecvFlags, SendFlags, Shutdown};
+
+use super::iface::{Iface, IfaceAddr, IFACES, IFF_RUNNING};
+use super::message::*;
+use crate::fs::{Events, Pollee, Poller, StatusFlags};
+use crate::net::{Domain, NetlinkSocketAddr};
+use crate::prelude::*;
+
+// The same as the default socket buffer size of Linux
+const MAX_BUF_SIZE: usize = 208 * 1024;
+// Just like Linux, a dump is split into datagrams of about a page, which is the
+// buffer size used by most netlink users
+const MAX_DUMP_DGRAM_SIZE: usize = 4096;
+
+// The types of links
+const ARPHRD_ETHER: u16 = 1;
+const ARPHRD_LOOPBACK: u16 = 772;
+// The operational state of links, i.e., IF_OPER_*
+const IF_OPER_DOWN: u8 = 2;
+const IF_OPER_UP: u8 = 6;
+// The scopes of addresses and routes
+const RT_SCOPE_UNIVERSE: u8 = 0;
+const RT_SCOPE_LINK: u8 = 253;
+const RT_SCOPE_HOST: u8 = 254;
+const IFA_F_PERMANENT: u8 = 0x80;
+const RT_TABLE_MAIN: u8 = 254;
+const RTPROT_KERNEL: u8 = 2;
+const RTN_UNICAST: u8 = 1;
+
+lazy_static! {
+    /// The port IDs that are in use.
+    static ref PORT_IDS: SgxMutex<HashSet<u32>> = SgxMutex::new(HashSet::new());
+}
+
+/// A netlink socket of the route protocol, which is emulated inside the LibOS.
+///
+/// Requests are answered as soon as they are sent. So the responses are queued in
+/// the socket until they are received.
+#[derive(Debug)]
+pub struct NetlinkRouteSocket {
+    state: SgxMutex<State>,
+    pollee: Pollee,
+    is_nonblocking: AtomicBool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    addr: Option<NetlinkSocketAddr>,
+    responses: VecDeque<Vec<u8>>,
+    responses_len: usize,
+    is_read_shutdown: bool,
+    is_write_shutdown: bool,
+}
+
+impl NetlinkRouteSocket {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            state: SgxMutex::new(State::default()),
+            pollee: Pollee::new(Events::OUT),
+            is_nonblocking: AtomicBool::new(false),
+        })
+    }
+
+    pub fn domain(&self) -> Domain {
+        Domain::Netlink
+    }
+
+    pub fn bind(&self, addr: &NetlinkSocketAddr) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(bound_addr) = state.addr {
+            // Just like Linux, binding again is allowed only to the same port ID,
+            // which updates the multicast groups
+            if addr.port_id() != 0 && addr.port_id() != bound_addr.port_id() {
+                return_errno!(EINVAL, "the socket is already bound");
+            }
+            state.addr = Some(NetlinkSocketAddr::new(bound_addr.port_id(), addr.groups()));
+            return Ok(());
+        }
+
+        let port_id = alloc_port_id(addr.port_id())?;
+        // No notifications are ever sent, so joining multicast groups is harmless
+        state.addr = Some(NetlinkSocketAddr::new(port_id, addr.groups()));
+        Ok(())
+    }
+
+    pub fn connect(&self, peer_addr: &NetlinkSocketAddr) -> Result<()> {
+        if peer_addr.port_id() != 0 {
+            return_errno!(ECONNREFUSED, "only the kernel can be connected to");
+        }
+        // Just like Linux, connecting binds the socket implicitly
+        self.ensure_bound();
+        Ok(())
+    }
+
+    pub async fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.readv(&mut [buf]).await
+    }
+
+    pub async fn readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        let (nbytes, _, _) = self.recvmsg(bufs, RecvFlags::empty()).await?;
+        Ok(nbytes)
+    }
+
+    pub async fn write(&self, buf: &[u8]) -> Result<usize> {
+        self.writev(&[buf]).await
+    }
+
+    pub async fn writev(&self, bufs: &[&[u8]]) -> Result<usize> {
+        self.sendmsg(bufs, SendFlags::empty(), None).await
+    }
+
+    /// Send requests to the kernel, whose responses are ready to receive once this
+    /// method returns.
+    pub async fn sendmsg(
+        &self,
+        bufs: &[&[u8]],
+        flags: SendFlags,
+        addr: Option<&NetlinkSocketAddr>,
+    ) -> Result<usize> {
+        if flags.contains(SendFlags::MSG_OOB) {
+            return_errno!(EOPNOTSUPP, "MSG_OOB is not supported");
+        }
+        if let Some(addr) = addr {
+            if addr.port_id() != 0 {
+                return_errno!(ECONNREFUSED, "only the kernel can be sent to");
+            }
+        }
+
+        let data_len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        if data_len > MAX_BUF_SIZE {
+            return_errno!(EMSGSIZE, "the message is too large");
+        }
+        let mut data = Vec::with_capacity(data_len);
+        for buf in bufs {
+            data.extend_from_slice(buf);
+        }
+
+        let port_id = self.ensure_bound();
+        let mut state = self.state.lock().unwrap();
+        if state.is_write_shutdown {
+            return_errno!(EPIPE, "the socket is shut down for writing");
+        }
+        if state.responses_len >= MAX_BUF_SIZE {
+            return_errno!(ENOBUFS, "too many responses are not received yet");
+        }
+
+        for request in RequestIter::new(&data) {
+            if request.hdr.nlmsg_flags & NLM_F_REQUEST == 0 {
+                continue;
+            }
+            for response in handle_request(&request, port_id) {
+                state.responses_len += response.len();
+                state.responses.push_back(response);
+            }
+        }
+        self.update_pollee(&state);
+        Ok(data_len)
+    }
+
+    /// Receive a response, returning the length of the received data, the source
+    /// address, which is always the kernel, and the flags of the received message.
+    ///
+    /// If the flag `MSG_TRUNC` is given, the returned length is the real length of
+    /// the response, even if it is longer than the buffers.
+    pub async fn recvmsg(
+        &self,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+    ) -> Result<(usize, NetlinkSocketAddr, MsgFlags)> {
+        if flags.intersects(RecvFlags::MSG_OOB | RecvFlags::MSG_ERRQUEUE) {
+            return_errno!(EOPNOTSUPP, "MSG_OOB or MSG_ERRQUEUE is not supported");
+        }
+        let is_nonblocking = self.is_nonblocking() || flags.contains(RecvFlags::MSG_DONTWAIT);
+
+        // Fast path
+        let res = self.try_recvmsg(bufs, flags);
+        if is_nonblocking || !res.has_errno(EAGAIN) {
+            return res;
+        }
+
+        // Slow path
+        let mut poller = Poller::new();
+        loop {
+            let events = self.pollee.poll(Events::IN, Some(&mut poller));
+            if events.contains(Events::IN) {
+                let res = self.try_recvmsg(bufs, flags);
+                if !res.has_errno(EAGAIN) {
+                    return res;
+                }
+            }
+            poller.wait().await;
+        }
+    }
+
+    fn try_recvmsg(
+        &self,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+    ) -> Result<(usize, NetlinkSocketAddr, MsgFlags)> {
+        let mut state = self.state.lock().unwrap();
+        let response = match state.responses.front() {
+            Some(response) => response,
+            None if state.is_read_shutdown => {
+                return Ok((0, NetlinkSocketAddr::KERNEL, MsgFlags::empty()));
+            }
+            None => return_errno!(EAGAIN, "no responses to receive"),
+        };
+
+        let mut copied_len = 0;
+        for buf in bufs.iter_mut() {
+            let remain_data = &response[copied_len..];
+            let copy_len = remain_data.len().min(buf.len());
+            buf[..copy_len].copy_from_slice(&remain_data[..copy_len]);
+            copied_len += copy_len;
+            if copied_len == response.len() {
+                break;
+            }
+        }
+
+        let data_len = response.len();
+        let msg_flags = if copied_len < data_len {
+            MsgFlags::MSG_TRUNC
+        } else {
+            MsgFlags::empty()
+        };
+        let recv_len = if flags.contains(RecvFlags::MSG_TRUNC) {
+            data_len
+        } else {
+            copied_len
+        };
+
+        if !flags.contains(RecvFlags::MSG_PEEK) {
+            // The rest of a truncated response is discarded
+            state.responses.pop_front();
+            state.responses_len -= data_len;
+            self.update_pollee(&state);
+        }
+        Ok((recv_len, NetlinkSocketAddr::KERNEL, msg_flags))
+    }
+
+    pub fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
+        self.pollee.poll(mask, poller)
+    }
+
+    pub fn status_flags(&self) -> StatusFlags {
+        if self.is_nonblocking() {
+            StatusFlags::O_NONBLOCK
+        } else {
+            StatusFlags::empty()
+        }
+    }
+
+    pub fn set_status_flags(&self, new_flags: StatusFlags) -> Result<()> {
+        let is_nonblocking = new_flags.contains(StatusFlags::O_NONBLOCK);
+        self.is_nonblocking.store(is_nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if how.should_shut_read() {
+            state.is_read_shutdown = true;
+        }
+        if how.should_shut_write() {
+            state.is_write_shutdown = true;
+        }
+        self.update_pollee(&state);
+        Ok(())
+    }
+
+    pub fn addr(&self) -> Result<NetlinkSocketAddr> {
+        let state = self.state.lock().unwrap();
+        Ok(state.addr.unwrap_or_default())
+    }
+
+    pub fn peer_addr(&self) -> Result<NetlinkSocketAddr> {
+        // The peer is always the kernel
+        Ok(NetlinkSocketAddr::KERNEL)
+    }
+
+    pub fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        match_ioctl_cmd_mut!(&mut *cmd, {
+            cmd: GetSockError => {
+                cmd.set_output(None);
+                Ok(())
+            },
+            cmd: GetSendBufSize => {
+                cmd.set_output(MAX_BUF_SIZE);
+                Ok(())
+            },
+            cmd: GetRecvBufSize => {
+                cmd.set_output(MAX_BUF_SIZE);
+                Ok(())
+            },
+            // The buffer sizes are fixed for now. But, just like Linux, setting
+            // them always succeeds.
+            cmd: SetSendBufSize => {
+                Ok(())
+            },
+            cmd: SetRecvBufSize => {
+                Ok(())
+            },
+            _ => {
+                return_errno!(ENOPROTOOPT, "the socket option is not supported");
+            }
+        })
+    }
+
+    /// Bind the socket to an automatically allocated port ID if it is unbound,
+    /// returning the bound port ID.
+    fn ensure_bound(&self) -> u32 {
+        let mut state = self.state.lock().unwrap();
+        if let Some(addr) = state.addr {
+            return addr.port_id();
+        }
+        // Allocating a port ID automatically never fails
+        let port_id = alloc_port_id(0).unwrap();
+        state.addr = Some(NetlinkSocketAddr::new(port_id, 0));
+        port_id
+    }
+
+    fn update_pollee(&self, state: &State) {
+        if state.responses.is_empty() && !state.is_read_shutdown {
+            self.pollee.del_events(Events::IN);
+        } else {
+            self.pollee.add_events(Events::IN);
+        }
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        self.is_nonblocking.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for NetlinkRouteSocket {
+    fn drop(&mut self) {
+        if let Some(addr) = self.state.get_mut().unwrap().addr {
+            PORT_IDS.lock().unwrap().remove(&addr.port_id());
+        }
+    }
+}
+
+/// Allocate a port ID. If the given port ID is zero, one is allocated automatically.
+///
+/// Just like Linux, the first automatically allocated port ID is the process ID,
+/// and the rest are negative numbers.
+fn alloc_port_id(port_id: u32) -> Result<u32> {
+    let mut port_ids = PORT_IDS.lock().unwrap();
+    if port_id != 0 {
+        if !port_ids.insert(port_id) {
+            return_errno!(EADDRINUSE, "the port ID is in use");
+        }
+        return Ok(port_id);
+    }
+
+    let pid = current!().process().pid() as u32;
+    let port_id = std::iter::once(pid)
+        .chain((1..=i32::MAX as u32).map(|n| (-(n as i32)) as u32))
+        .find(|port_id| !port_ids.contains(port_id))
+        .unwrap();
+    port_ids.insert(port_id);
+    Ok(port_id)
+}
+
+/// Handle a request, returning the responses that are packed into datagrams.
+fn handle_request(request: &Request, port_id: u32) -> Vec<Vec<u8>> {
+    let hdr = &request.hdr;
+    let messages = match (hdr.nlmsg_type, request.is_dump()) {
+        (RTM_GETLINK, true) => dump_links(request, port_id),
+        (RTM_GETADDR, true) => dump_addrs(request, port_id),
+        (RTM_GETROUTE, true) => dump_routes(request, port_id),
+        (RTM_GETLINK, false) => get_link(request, port_id),
+        _ => {
+            // Other requests, e.g., the ones that modify interfaces, are unsupported
+            let response = Response::new_error(hdr, EOPNOTSUPP as i32, port_id);
+            return vec![response.into_bytes()];
+        }
+    };
+
+    match messages {
+        Ok(messages) if request.is_dump() => {
+            let done = Response::new_done(hdr.nlmsg_seq, port_id).into_bytes();
+            pack_messages(messages.into_iter().chain(std::iter::once(done)))
+        }
+        Ok(mut messages) => {
+            if hdr.nlmsg_flags & NLM_F_ACK != 0 {
+                messages.push(Response::new_error(hdr, 0, port_id).into_bytes());
+            }
+            pack_messages(messages.into_iter())
+        }
+        Err(e) => {
+            let response = Response::new_error(hdr, e.errno() as i32, port_id);
+            vec![response.into_bytes()]
+        }
+    }
+}
+
+/// Pack messages into datagrams, each of which is no larger than a page unless it
+/// has only one message.
+fn pack_messages(messages: impl Iterator<Item = Vec<u8>>) -> Vec<Vec<u8>> {
+    let mut datagrams: Vec<Vec<u8>> = Vec::new();
+    for message in messages {
+        match datagrams.last_mut() {
+            Some(datagram) if datagram.len() + message.len() <= MAX_DUMP_DGRAM_SIZE => {
+                datagram.extend_from_slice(&message);
+            }
+            _ => datagrams.push(message),
+        }
+    }
+    datagrams
+}
+
+fn get_link(request: &Request, port_id: u32) -> Result<Vec<Vec<u8>>> {
+    let ifi: ifinfomsg = request
+        .payload_as()
+        .ok_or_else(|| errno!(EINVAL, "the request is too short"))?;
+    let iface = IFACES
+        .iter()
+        .find(|iface| iface.index as i32 == ifi.ifi_index)
+        .ok_or_else(|| errno!(ENODEV, "no such interface"))?;
+    Ok(vec![new_link_message(iface, &request.hdr, 0, port_id)])
+}
+
+fn dump_links(request: &Request, port_id: u32) -> Result<Vec<Vec<u8>>> {
+    // Links have no family, so the family in the request is ignored
+    let messages = IFACES
+        .iter()
+        .map(|iface| new_link_message(iface, &request.hdr, NLM_F_MULTI, port_id))
+        .collect();
+    Ok(messages)
+}
+
+fn dump_addrs(request: &Request, port_id: u32) -> Result<Vec<Vec<u8>>> {
+    let family = request.family();
+    let messages = IFACES
+        .iter()
+        .flat_map(|iface| iface.addrs.iter().map(move |addr| (iface, addr)))
+        .filter(|(_, addr)| family_matches(family, &addr.ip))
+        .map(|(iface, addr)| new_addr_message(iface, addr, &request.hdr, port_id))
+        .collect();
+    Ok(messages)
+}
+
+fn dump_routes(request: &Request, port_id: u32) -> Result<Vec<Vec<u8>>> {
+    // Only the prefix routes of the interface addresses are reported. Just like the
+    // main routing table of Linux, there are no such routes for the IPv4 loopback
+    // addresses or IPv4 host addresses.
+    let family = request.family();
+    let messages = IFACES
+        .iter()
+        .flat_map(|iface| iface.addrs.iter().map(move |addr| (iface, addr)))
+        .filter(|(_, addr)| family_matches(family, &addr.ip))
+        .filter(|(_, addr)| match addr.ip {
+            IpAddr::V4(ip) => !ip.is_loopback() && addr.prefix_len < 32,
+            IpAddr::V6(_) => true,
+        })
+        .map(|(iface, addr)| new_route_message(iface, addr, &request.hdr, port_id))
+        .collect();
+    Ok(messages)
+}
+
+fn family_matches(family: u8, ip: &IpAddr) -> bool {
+    match family as i32 {
+        libc::AF_UNSPEC => true,
+        libc::AF_INET => ip.is_ipv4(),
+        libc::AF_INET6 => ip.is_ipv6(),
+        _ => false,
+    }
+}
+
+fn new_link_message(iface: &Iface, request: &nlmsghdr, flags: u16, port_id: u32) -> Vec<u8> {
+    let mut msg = Response::new(RTM_NEWLINK, flags, request.nlmsg_seq, port_id);
+    let (type_, broadcast) = if iface.is_loopback() {
+        (ARPHRD_LOOPBACK, [0u8; 6])
+    } else {
+        (ARPHRD_ETHER, [0xffu8; 6])
+    };
+    msg.put(&ifinfomsg {
+        ifi_family: libc::AF_UNSPEC as u8,
+        ifi_pad: 0,
+        ifi_type: type_,
+        ifi_index: iface.index as i32,
+        ifi_flags: iface.flags,
+        ifi_change: 0,
+    });
+    msg.put_attr_str(IFLA_IFNAME, &iface.name);
+    msg.put_attr_u32(IFLA_MTU, iface.mtu);
+    msg.put_attr_u32(IFLA_TXQLEN, 1000);
+    let oper_state = if iface.flags & IFF_RUNNING != 0 {
+        IF_OPER_UP
+    } else {
+        IF_OPER_DOWN
+    };
+    msg.put_attr(IFLA_OPERSTATE, &[oper_state]);
+    msg.put_attr(IFLA_ADDRESS, &iface.hw_addr);
+    msg.put_attr(IFLA_BROADCAST, &broadcast);
+    msg.into_bytes()
+}
+
+fn new_addr_message(iface: &Iface, addr: &IfaceAddr, request: &nlmsghdr, port_id: u32) -> Vec<u8> {
+    let mut msg = Response::new(RTM_NEWADDR, NLM_F_MULTI, request.nlmsg_seq, port_id);
+    let scope = match addr.ip {
+        IpAddr::V4(ip) if ip.is_loopback() => RT_SCOPE_HOST,
+        IpAddr::V6(ip) if ip.is_loopback() => RT_SCOPE_HOST,
+        // Link-local addresses, i.e., fe80::/10
+        IpAddr::V6(ip) if ip.segments()[0] & 0xffc0 == 0xfe80 => RT_SCOPE_LINK,
+        _ => RT_SCOPE_UNIVERSE,
+    };
+    msg.put(&ifaddrmsg {
+        ifa_family: family_of(&addr.ip),
+        ifa_prefixlen: addr.prefix_len,
+        ifa_flags: IFA_F_PERMANENT,
+        ifa_scope: scope,
+        ifa_index: iface.index,
+    });
+    match addr.ip {
+        IpAddr::V4(ip) => {
+            msg.put_attr(IFA_ADDRESS, &ip.octets());
+            msg.put_attr(IFA_LOCAL, &ip.octets());
+            if !iface.is_loopback() && addr.prefix_len < 31 {
+                let host_mask = u32::MAX >> addr.prefix_len;
+                let broadcast = u32::from(ip) | host_mask;
+                msg.put_attr(IFA_BROADCAST, &broadcast.to_be_bytes());
+            }
+            msg.put_attr_str(IFA_LABEL, &iface.name);
+        }
+        IpAddr::V6(ip) => {
+            msg.put_attr(IFA_ADDRESS, &ip.octets());
+        }
+    }
+    msg.into_bytes()
+}
+
+fn new_route_message(iface: &Iface, addr: &IfaceAddr, request: &nlmsghdr, port_id: u32) -> Vec<u8> {
+    let mut msg = Response::new(RTM_NEWROUTE, NLM_F_MULTI, request.nlmsg_seq, port_id);
+    msg.put(&rtmsg {
+        rtm_family: family_of(&addr.ip),
+        rtm_dst_len: addr.prefix_len,
+        rtm_src_len: 0,
+        rtm_tos: 0,
+        rtm_table: RT_TABLE_MAIN,
+        rtm_protocol: RTPROT_KERNEL,
+        rtm_scope: RT_SCOPE_LINK,
+        rtm_type: RTN_UNICAST,
+        rtm_flags: 0,
+    });
+    msg.put_attr_u32(RTA_TABLE, RT_TABLE_MAIN as u32);
+    match addr.ip {
+        IpAddr::V4(ip) => {
+            let net_mask = u32::MAX
+                .checked_shl(32 - addr.prefix_len as u32)
+                .unwrap_or(0);
+            let dst = u32::from(ip) & net_mask;
+            msg.put_attr(RTA_DST, &dst.to_be_bytes());
+            msg.put_attr(RTA_PREFSRC, &ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            let net_mask = u128::MAX
+                .checked_shl(128 - addr.prefix_len as u32)
+                .unwrap_or(0);
+            let dst = u128::from(ip) & net_mask;
+            msg.put_attr(RTA_DST, &dst.to_be_bytes());
+        }
+    }
+    msg.put_attr_u32(RTA_OIF, iface.index);
+    msg.into_bytes()
+}
+
+fn family_of(ip: &IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(_) => libc::AF_INET as u8,
+        IpAddr::V6(_) => libc::AF_INET6 as u8,
+    }
+}
@@ -1,17 +1,34 @@
-use self::impls::{Ipv4Stream, UnixStream};
+use std::convert::TryInto;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_io::ioctl::IoctlCmd;
+use async_io::socket::sockopt::PeerCred;
+use async_io::socket::{MsgFlags, RecvFlags, SendFlags, Shutdown};
+
+use super::ip::{Ipv4Stream, Ipv6Stream};
+use super::netlink::NetlinkRouteSocket;
+use super::unix::{current_cred, Ancillary, UnixDatagram, UnixStream};
 use crate::fs::{AccessMode, Events, Poller, StatusFlags};
-use crate::net::{Addr, AnyAddr, Domain, Ipv4SocketAddr, UnixAddr};
+use crate::net::msg::{CMsgIter, CMsgWriter};
+use crate::net::{Addr, AnyAddr, Domain};
 use crate::prelude::*;
 
+pub use self::impls::SocketRuntime;
+
 #[derive(Debug)]
 pub struct SocketFile {
+    id: usize,
     socket: AnySocket,
 }
 
 #[derive(Debug)]
 enum AnySocket {
     UnixStream(UnixStream),
+    UnixDatagram(UnixDatagram),
     Ipv4Stream(Ipv4Stream),
+    Ipv6Stream(Ipv6Stream),
+    NetlinkRoute(NetlinkRouteSocket),
 }
 
 // Apply a function to all variants of AnySocket enum.
@@ -22,9 +39,18 @@ macro_rules! apply_fn_on_any_socket {
             AnySocket::UnixStream($socket) => {
                 $($fn_body)*
             }
+            AnySocket::UnixDatagram($socket) => {
+                $($fn_body)*
+            }
             AnySocket::Ipv4Stream($socket) => {
                 $($fn_body)*
             }
+            AnySocket::Ipv6Stream($socket) => {
+                $($fn_body)*
+            }
+            AnySocket::NetlinkRoute($socket) => {
+                $($fn_body)*
+            }
         }
     }}
 }
@@ -53,13 +79,13 @@ impl SocketFile {
     }
 
     pub fn status_flags(&self) -> StatusFlags {
-        //apply_fn_on_any_socket!(&self.socket, |socket| { socket.status_flags() })
-        todo!("")
+        apply_fn_on_any_socket!(&self.socket, |socket| { socket.status_flags() })
     }
 
     pub fn set_status_flags(&self, new_flags: StatusFlags) -> Result<()> {
-        //apply_fn_on_any_socket!(&self.socket, |socket| { socket.set_status_flags(new_flags) })
-        todo!("")
+        apply_fn_on_any_socket!(&self.socket, |socket| {
+            socket.set_status_flags(new_flags)
+        })
     }
 
     pub fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
@@ -76,19 +102,69 @@ impl SocketFile {
                     let ipv4_stream = Ipv4Stream::new()?;
                     AnySocket::Ipv4Stream(ipv4_stream)
                 }
+                Domain::Ipv6 => {
+                    let ipv6_stream = Ipv6Stream::new()?;
+                    AnySocket::Ipv6Stream(ipv6_stream)
+                }
                 Domain::Unix => {
                     let unix_stream = UnixStream::new()?;
                     AnySocket::UnixStream(unix_stream)
                 }
-                _ => {
-                    return_errno!(EINVAL, "not support IPv6, yet");
+                Domain::Netlink => {
+                    return_errno!(ESOCKTNOSUPPORT, "netlink sockets are not stream sockets");
                 }
             };
-            let new_self = Self { socket: any_socket };
-            Ok(new_self)
+            Ok(Self::from_socket(any_socket))
         } else {
-            return_errno!(EINVAL, "not support non-stream sockets, yet");
+            let any_socket = match domain {
+                Domain::Unix => {
+                    let unix_datagram = UnixDatagram::new()?;
+                    AnySocket::UnixDatagram(unix_datagram)
+                }
+                Domain::Netlink => {
+                    let netlink_socket = NetlinkRouteSocket::new()?;
+                    AnySocket::NetlinkRoute(netlink_socket)
+                }
+                _ => {
+                    return_errno!(EINVAL, "not support non-stream sockets, yet");
+                }
+            };
+            Ok(Self::from_socket(any_socket))
+        }
+    }
+
+    /// Create a pair of connected sockets, which is only supported by unix sockets.
+    pub fn new_pair(domain: Domain, is_stream: bool) -> Result<(Self, Self)> {
+        if domain != Domain::Unix {
+            return_errno!(EOPNOTSUPP, "only unix sockets can be created in pairs");
         }
+        let (any_socket_a, any_socket_b) = if is_stream {
+            let (unix_stream_a, unix_stream_b) = UnixStream::new_pair()?;
+            (
+                AnySocket::UnixStream(unix_stream_a),
+                AnySocket::UnixStream(unix_stream_b),
+            )
+        } else {
+            let (unix_datagram_a, unix_datagram_b) = UnixDatagram::new_pair()?;
+            (
+                AnySocket::UnixDatagram(unix_datagram_a),
+                AnySocket::UnixDatagram(unix_datagram_b),
+            )
+        };
+        let socket_a = Self::from_socket(any_socket_a);
+        let socket_b = Self::from_socket(any_socket_b);
+        Ok((socket_a, socket_b))
+    }
+
+    fn from_socket(socket: AnySocket) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        Self { id, socket }
+    }
+
+    /// Returns the ID of the socket, which is unique among all sockets.
+    pub fn id(&self) -> usize {
+        self.id
     }
 
     pub fn domain(&self) -> Domain {
@@ -96,7 +172,10 @@ impl SocketFile {
     }
 
     pub fn is_stream(&self) -> bool {
-        matches!(&self.socket, AnySocket::Ipv4Stream(_) | AnySocket::UnixStream(_))
+        matches!(
+            &self.socket,
+            AnySocket::Ipv4Stream(_) | AnySocket::Ipv6Stream(_) | AnySocket::UnixStream(_)
+        )
     }
 
     pub async fn connect(&self, addr: &AnyAddr) -> Result<()> {
@@ -107,14 +186,29 @@ impl SocketFile {
                     .ok_or_else(|| errno!(EAFNOSUPPORT, "not ipv4 address"))?;
                 ipv4_stream.connect(ip_addr).await
             }
+            AnySocket::Ipv6Stream(ipv6_stream) => {
+                let ip_addr = addr
+                    .as_ipv6()
+                    .ok_or_else(|| errno!(EAFNOSUPPORT, "not ipv6 address"))?;
+                ipv6_stream.connect(ip_addr).await
+            }
             AnySocket::UnixStream(unix_stream) => {
                 let unix_addr = addr
                     .as_unix()
                     .ok_or_else(|| errno!(EAFNOSUPPORT, "not unix address"))?;
                 unix_stream.connect(unix_addr).await
             }
-            _ => {
-                return_errno!(EINVAL, "connect is not supported");
+            AnySocket::UnixDatagram(unix_datagram) => {
+                let unix_addr = addr
+                    .as_unix()
+                    .ok_or_else(|| errno!(EAFNOSUPPORT, "not unix address"))?;
+                unix_datagram.connect(unix_addr)
+            }
+            AnySocket::NetlinkRoute(netlink_socket) => {
+                let netlink_addr = addr
+                    .as_netlink()
+                    .ok_or_else(|| errno!(EAFNOSUPPORT, "not netlink address"))?;
+                netlink_socket.connect(netlink_addr)
             }
         }
     }
@@ -127,14 +221,29 @@ impl SocketFile {
                     .ok_or_else(|| errno!(EAFNOSUPPORT, "not ipv4 address"))?;
                 ipv4_stream.bind(ip_addr)
             }
+            AnySocket::Ipv6Stream(ipv6_stream) => {
+                let ip_addr = addr
+                    .as_ipv6()
+                    .ok_or_else(|| errno!(EAFNOSUPPORT, "not ipv6 address"))?;
+                ipv6_stream.bind(ip_addr)
+            }
             AnySocket::UnixStream(unix_stream) => {
                 let unix_addr = addr
                     .as_unix()
                     .ok_or_else(|| errno!(EAFNOSUPPORT, "not unix address"))?;
                 unix_stream.bind(unix_addr)
             }
-            _ => {
-                return_errno!(EINVAL, "bind is not supported");
+            AnySocket::UnixDatagram(unix_datagram) => {
+                let unix_addr = addr
+                    .as_unix()
+                    .ok_or_else(|| errno!(EAFNOSUPPORT, "not unix address"))?;
+                unix_datagram.bind(unix_addr)
+            }
+            AnySocket::NetlinkRoute(netlink_socket) => {
+                let netlink_addr = addr
+                    .as_netlink()
+                    .ok_or_else(|| errno!(EAFNOSUPPORT, "not netlink address"))?;
+                netlink_socket.bind(netlink_addr)
             }
         }
     }
@@ -142,9 +251,10 @@ impl SocketFile {
     pub fn listen(&self, backlog: u32) -> Result<()> {
         match &self.socket {
             AnySocket::Ipv4Stream(ipv4_stream) => ipv4_stream.listen(backlog),
+            AnySocket::Ipv6Stream(ipv6_stream) => ipv6_stream.listen(backlog),
             AnySocket::UnixStream(unix_stream) => unix_stream.listen(backlog),
             _ => {
-                return_errno!(EINVAL, "listen is not supported");
+                return_errno!(EOPNOTSUPP, "listen is not supported");
             }
         }
     }
@@ -155,37 +265,280 @@ impl SocketFile {
                 let accepted_ipv4_stream = ipv4_stream.accept().await?;
                 AnySocket::Ipv4Stream(accepted_ipv4_stream)
             }
+            AnySocket::Ipv6Stream(ipv6_stream) => {
+                let accepted_ipv6_stream = ipv6_stream.accept().await?;
+                AnySocket::Ipv6Stream(accepted_ipv6_stream)
+            }
             AnySocket::UnixStream(unix_stream) => {
                 let accepted_unix_stream = unix_stream.accept().await?;
                 AnySocket::UnixStream(accepted_unix_stream)
             }
             _ => {
-                return_errno!(EINVAL, "listen is not supported");
+                return_errno!(EOPNOTSUPP, "listen is not supported");
             }
         };
-        let accepted_socket_file = SocketFile {
-            socket: accepted_any_socket,
-        };
+        let accepted_socket_file = Self::from_socket(accepted_any_socket);
         Ok(accepted_socket_file)
     }
+
+    /// Send a message, with the destination address and control messages if any.
+    ///
+    /// The destination address is ignored by connection-mode sockets.
+    pub async fn sendmsg(
+        &self,
+        bufs: &[&[u8]],
+        flags: SendFlags,
+        addr: Option<&AnyAddr>,
+        control: Option<&[u8]>,
+    ) -> Result<usize> {
+        match &self.socket {
+            AnySocket::UnixStream(unix_stream) => {
+                let ancillary = control.map(ancillary_from_control).transpose()?.flatten();
+                unix_stream.sendmsg(bufs, flags, ancillary).await
+            }
+            AnySocket::UnixDatagram(unix_datagram) => {
+                let unix_addr = addr
+                    .map(|addr| {
+                        addr.as_unix()
+                            .ok_or_else(|| errno!(EAFNOSUPPORT, "not unix address"))
+                    })
+                    .transpose()?;
+                let ancillary = control.map(ancillary_from_control).transpose()?.flatten();
+                unix_datagram
+                    .sendmsg(bufs, flags, unix_addr, ancillary)
+                    .await
+            }
+            AnySocket::Ipv4Stream(ipv4_stream) => {
+                check_host_control(control)?;
+                ipv4_stream.sendmsg(bufs, flags).await
+            }
+            AnySocket::Ipv6Stream(ipv6_stream) => {
+                check_host_control(control)?;
+                ipv6_stream.sendmsg(bufs, flags).await
+            }
+            AnySocket::NetlinkRoute(netlink_socket) => {
+                let netlink_addr = addr
+                    .map(|addr| {
+                        addr.as_netlink()
+                            .ok_or_else(|| errno!(EAFNOSUPPORT, "not netlink address"))
+                    })
+                    .transpose()?;
+                netlink_socket.sendmsg(bufs, flags, netlink_addr).await
+            }
+        }
+    }
+
+    /// Receive a message, with the source address and control messages if any.
+    ///
+    /// The control messages are written into the given control buffer. Returns the
+    /// length of the received data, the source address, the flags of the received
+    /// message and the length of the control messages.
+    pub async fn recvmsg(
+        &self,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+        control: Option<&mut [u8]>,
+    ) -> Result<(usize, Option<AnyAddr>, MsgFlags, usize)> {
+        let (nbytes, src_addr, ancillary, mut msg_flags) = match &self.socket {
+            AnySocket::UnixStream(unix_stream) => {
+                let (nbytes, ancillary, msg_flags) = unix_stream.recvmsg(bufs, flags).await?;
+                (nbytes, None, ancillary, msg_flags)
+            }
+            AnySocket::UnixDatagram(unix_datagram) => {
+                let (nbytes, src_addr, ancillary, msg_flags) =
+                    unix_datagram.recvmsg(bufs, flags).await?;
+                (nbytes, Some(AnyAddr::Unix(src_addr)), ancillary, msg_flags)
+            }
+            AnySocket::Ipv4Stream(ipv4_stream) => {
+                let (nbytes, msg_flags) = ipv4_stream.recvmsg(bufs, flags).await?;
+                (nbytes, None, None, msg_flags)
+            }
+            AnySocket::Ipv6Stream(ipv6_stream) => {
+                let (nbytes, msg_flags) = ipv6_stream.recvmsg(bufs, flags).await?;
+                (nbytes, None, None, msg_flags)
+            }
+            AnySocket::NetlinkRoute(netlink_socket) => {
+                let (nbytes, src_addr, msg_flags) = netlink_socket.recvmsg(bufs, flags).await?;
+                (nbytes, Some(AnyAddr::Netlink(src_addr)), None, msg_flags)
+            }
+        };
+
+        let mut control_len = 0;
+        if let Some(ancillary) = ancillary {
+            let close_on_spawn = flags.contains(RecvFlags::MSG_CMSG_CLOEXEC);
+            let is_truncated = match control {
+                Some(control) => {
+                    let mut writer = CMsgWriter::new(control);
+                    put_ancillary_into_control(ancillary, &mut writer, close_on_spawn);
+                    control_len = writer.len();
+                    writer.is_truncated()
+                }
+                // The files are closed as the ancillary data are dropped
+                None => !ancillary.files().is_empty(),
+            };
+            if is_truncated {
+                msg_flags |= MsgFlags::MSG_CTRUNC;
+            }
+        }
+        Ok((nbytes, src_addr, msg_flags, control_len))
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        apply_fn_on_any_socket!(&self.socket, |socket| { socket.shutdown(how) })
+    }
+
+    pub fn addr(&self) -> Result<AnyAddr> {
+        let addr = match &self.socket {
+            AnySocket::Ipv4Stream(ipv4_stream) => AnyAddr::Ipv4(ipv4_stream.addr()?),
+            AnySocket::Ipv6Stream(ipv6_stream) => AnyAddr::Ipv6(ipv6_stream.addr()?),
+            AnySocket::UnixStream(unix_stream) => AnyAddr::Unix(unix_stream.addr()?),
+            AnySocket::UnixDatagram(unix_datagram) => AnyAddr::Unix(unix_datagram.addr()?),
+            AnySocket::NetlinkRoute(netlink_socket) => AnyAddr::Netlink(netlink_socket.addr()?),
+        };
+        Ok(addr)
+    }
+
+    pub fn peer_addr(&self) -> Result<AnyAddr> {
+        let peer_addr = match &self.socket {
+            AnySocket::Ipv4Stream(ipv4_stream) => AnyAddr::Ipv4(ipv4_stream.peer_addr()?),
+            AnySocket::Ipv6Stream(ipv6_stream) => AnyAddr::Ipv6(ipv6_stream.peer_addr()?),
+            AnySocket::UnixStream(unix_stream) => AnyAddr::Unix(unix_stream.peer_addr()?),
+            AnySocket::UnixDatagram(unix_datagram) => AnyAddr::Unix(unix_datagram.peer_addr()?),
+            AnySocket::NetlinkRoute(netlink_socket) => {
+                AnyAddr::Netlink(netlink_socket.peer_addr()?)
+            }
+        };
+        Ok(peer_addr)
+    }
+
+    pub fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        apply_fn_on_any_socket!(&self.socket, |socket| { socket.ioctl(cmd) })
+    }
+}
+
+/// Parse the control messages to be sent by a trusted unix socket.
+///
+/// Besides the files of `SCM_RIGHTS`, the credentials of `SCM_CREDENTIALS` are
+/// accepted as Linux does, but only if they are the ones of the sender. As no
+/// socket can ask for the credentials with `SO_PASSCRED` yet, they are checked
+/// and then dropped.
+fn ancillary_from_control(control: &[u8]) -> Result<Option<Ancillary>> {
+    let current = current!();
+    let mut files = Vec::new();
+    for cmsg in CMsgIter::new(control) {
+        let cmsg = cmsg?;
+        match (cmsg.level(), cmsg.type_()) {
+            (libc::SOL_SOCKET, libc::SCM_RIGHTS) => {
+                for fd_bytes in cmsg.data().chunks_exact(size_of::<c_int>()) {
+                    let fd = c_int::from_ne_bytes(fd_bytes.try_into().unwrap());
+                    if fd < 0 {
+                        return_errno!(EBADF, "invalid fd");
+                    }
+                    files.push(current.file(fd as FileDesc)?);
+                }
+            }
+            (libc::SOL_SOCKET, libc::SCM_CREDENTIALS) => check_credentials(cmsg.data())?,
+            _ => return_errno!(EOPNOTSUPP, "the control message cannot be sent"),
+        }
+    }
+
+    if files.is_empty() {
+        return Ok(None);
+    }
+    // The same as SCM_MAX_FD of Linux
+    const MAX_FDS: usize = 253;
+    if files.len() > MAX_FDS {
+        return_errno!(EINVAL, "too many fds to pass");
+    }
+    Ok(Some(Ancillary::with_files(files)))
+}
+
+/// Check the credentials of `SCM_CREDENTIALS`, which must be the ones of the
+/// current process since there is no privileged user to forge them.
+fn check_credentials(data: &[u8]) -> Result<()> {
+    if data.len() < size_of::<PeerCred>() {
+        return_errno!(EINVAL, "the credentials are too short");
+    }
+    // PeerCred has the same memory layout as `struct ucred`
+    let cred = unsafe { std::ptr::read_unaligned(data.as_ptr() as *const PeerCred) };
+    if cred != current_cred() {
+        return_errno!(EPERM, "the credentials are not the ones of the sender");
+    }
+    Ok(())
+}
+
+/// Install the received files and write their fds as an SCM_RIGHTS control message.
+///
+/// Just like Linux, the files that do not fit into the control buffer are closed.
+fn put_ancillary_into_control(ancillary: Ancillary, writer: &mut CMsgWriter, close_on_spawn: bool) {
+    let files = ancillary.into_files();
+    if files.is_empty() {
+        return;
+    }
+    let max_nfiles = writer.data_capacity() / size_of::<c_int>();
+    if files.len() > max_nfiles {
+        writer.set_truncated();
+    }
+
+    let current = current!();
+    let fd_bytes: Vec<u8> = files
+        .into_iter()
+        .take(max_nfiles)
+        .flat_map(|file| {
+            let fd = current.add_file(file, close_on_spawn) as c_int;
+            fd.to_ne_bytes().to_vec()
+        })
+        .collect();
+    if fd_bytes.is_empty() {
+        return;
+    }
+    writer.write(libc::SOL_SOCKET, libc::SCM_RIGHTS, &fd_bytes);
+}
+
+/// Check the control messages to be sent by a host socket.
+///
+/// The data of a host stream socket is sent by the host, to which no control
+/// messages are passed. The ones that carry fds, credentials or packet info
+/// cannot be honored, so they result in EOPNOTSUPP rather than being silently
+/// dropped. The others, e.g., SO_TIMESTAMP, have no effect on a stream and are
+/// ignored, just like Linux does for TCP.
+fn check_host_control(control: Option<&[u8]>) -> Result<()> {
+    let control = match control {
+        Some(control) => control,
+        None => return Ok(()),
+    };
+    for cmsg in CMsgIter::new(control) {
+        let cmsg = cmsg?;
+        match (cmsg.level(), cmsg.type_()) {
+            (libc::SOL_SOCKET, libc::SCM_RIGHTS)
+            | (libc::SOL_SOCKET, libc::SCM_CREDENTIALS)
+            | (libc::IPPROTO_IP, libc::IP_PKTINFO)
+            | (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                return_errno!(EOPNOTSUPP, "cannot send the control message via the socket");
+            }
+            _ => {}
+        }
+    }
+    Ok(())
 }
 
 mod impls {
     use super::*;
     use io_uring_callback::IoUring;
 
-    pub type Ipv4Stream = host_socket::StreamSocket<Ipv4SocketAddr, SocketRuntime>;
-    // TODO: UnixStream cannot be simply re-exported from host_socket.
-    // There are two reasons. First, there needs to be some translation between LibOS
-    // and host paths. Second, we need two types of unix domain sockets: the trusted one that
-    // is implemented inside LibOS and the untrusted one that is implemented by host OS.
-    pub type UnixStream = host_socket::StreamSocket<UnixAddr, SocketRuntime>;
-
     pub struct SocketRuntime;
 
     impl host_socket::Runtime for SocketRuntime {
         fn io_uring() -> &'static IoUring {
             &*crate::io_uring::SINGLETON
         }
+
+        fn num_vcpus() -> usize {
+            async_rt::executor::parallelism() as usize
+        }
+
+        fn vcpu_id() -> usize {
+            async_rt::executor::current_thread_id().unwrap_or(0) as usize
+        }
     }
 }
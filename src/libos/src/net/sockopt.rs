@@ -0,0 +1,177 @@
+//! Conversions between the C arguments of `setsockopt`/`getsockopt` and
+//! the ioctl-style commands of socket options.
+
+use std::mem::size_of;
+use std::time::Duration;
+
+use async_io::ioctl::IoctlCmd;
+use async_io::match_ioctl_cmd_ref;
+use async_io::socket::sockopt::*;
+
+use crate::prelude::*;
+
+/// Create a command for `setsockopt` from its C arguments.
+pub fn new_setsockopt_cmd(level: i32, optname: i32, optval: &[u8]) -> Result<Box<dyn IoctlCmd>> {
+    let cmd: Box<dyn IoctlCmd> = match (level, optname) {
+        (libc::SOL_SOCKET, libc::SO_REUSEADDR) => Box::new(SetReuseAddr::new(read_bool(optval)?)),
+        (libc::SOL_SOCKET, libc::SO_REUSEPORT) => Box::new(SetReusePort::new(read_bool(optval)?)),
+        (libc::SOL_SOCKET, libc::SO_KEEPALIVE) => Box::new(SetKeepAlive::new(read_bool(optval)?)),
+        (libc::SOL_SOCKET, libc::SO_LINGER) => {
+            let c_linger: libc::linger = read_val(optval)?;
+            Box::new(SetLinger::new(Linger::from_c(&c_linger)?))
+        }
+        (libc::SOL_SOCKET, libc::SO_RCVBUF) => {
+            // Negative values are treated as zero, just like Linux
+            let buf_size = read_val::<c_int>(optval)?.max(0) as usize;
+            Box::new(SetRecvBufSize::new(buf_size))
+        }
+        (libc::SOL_SOCKET, libc::SO_SNDBUF) => {
+            let buf_size = read_val::<c_int>(optval)?.max(0) as usize;
+            Box::new(SetSendBufSize::new(buf_size))
+        }
+        (libc::SOL_SOCKET, libc::SO_RCVTIMEO) => {
+            Box::new(SetRecvTimeout::new(read_timeout(optval)?))
+        }
+        (libc::SOL_SOCKET, libc::SO_SNDTIMEO) => {
+            Box::new(SetSendTimeout::new(read_timeout(optval)?))
+        }
+        (libc::IPPROTO_IPV6, libc::IPV6_V6ONLY) => Box::new(SetIpv6Only::new(read_bool(optval)?)),
+        (libc::IPPROTO_TCP, libc::TCP_NODELAY) => Box::new(SetTcpNoDelay::new(read_bool(optval)?)),
+        (libc::IPPROTO_TCP, libc::TCP_CORK) => Box::new(SetTcpCork::new(read_bool(optval)?)),
+        (libc::IPPROTO_TCP, libc::TCP_KEEPIDLE) => {
+            Box::new(SetTcpKeepIdle::new(read_positive(optval)?))
+        }
+        (libc::IPPROTO_TCP, libc::TCP_KEEPINTVL) => {
+            Box::new(SetTcpKeepIntvl::new(read_positive(optval)?))
+        }
+        (libc::IPPROTO_TCP, libc::TCP_KEEPCNT) => {
+            Box::new(SetTcpKeepCnt::new(read_positive(optval)?))
+        }
+        _ => {
+            return_errno!(ENOPROTOOPT, "unsupported or invalid socket option");
+        }
+    };
+    Ok(cmd)
+}
+
+/// Create a command for `getsockopt` from its C arguments.
+pub fn new_getsockopt_cmd(level: i32, optname: i32) -> Result<Box<dyn IoctlCmd>> {
+    let cmd: Box<dyn IoctlCmd> = match (level, optname) {
+        (libc::SOL_SOCKET, libc::SO_REUSEADDR) => Box::new(GetReuseAddr::new(())),
+        (libc::SOL_SOCKET, libc::SO_REUSEPORT) => Box::new(GetReusePort::new(())),
+        (libc::SOL_SOCKET, libc::SO_KEEPALIVE) => Box::new(GetKeepAlive::new(())),
+        (libc::SOL_SOCKET, libc::SO_LINGER) => Box::new(GetLinger::new(())),
+        (libc::SOL_SOCKET, libc::SO_ERROR) => Box::new(GetSockError::new(())),
+        (libc::SOL_SOCKET, libc::SO_PEERCRED) => Box::new(GetPeerCred::new(())),
+        (libc::SOL_SOCKET, libc::SO_RCVBUF) => Box::new(GetRecvBufSize::new(())),
+        (libc::SOL_SOCKET, libc::SO_SNDBUF) => Box::new(GetSendBufSize::new(())),
+        (libc::SOL_SOCKET, libc::SO_RCVTIMEO) => Box::new(GetRecvTimeout::new(())),
+        (libc::SOL_SOCKET, libc::SO_SNDTIMEO) => Box::new(GetSendTimeout::new(())),
+        (libc::IPPROTO_IPV6, libc::IPV6_V6ONLY) => Box::new(GetIpv6Only::new(())),
+        (libc::IPPROTO_TCP, libc::TCP_NODELAY) => Box::new(GetTcpNoDelay::new(())),
+        (libc::IPPROTO_TCP, libc::TCP_CORK) => Box::new(GetTcpCork::new(())),
+        (libc::IPPROTO_TCP, libc::TCP_KEEPIDLE) => Box::new(GetTcpKeepIdle::new(())),
+        (libc::IPPROTO_TCP, libc::TCP_KEEPINTVL) => Box::new(GetTcpKeepIntvl::new(())),
+        (libc::IPPROTO_TCP, libc::TCP_KEEPCNT) => Box::new(GetTcpKeepCnt::new(())),
+        _ => {
+            return_errno!(ENOPROTOOPT, "unsupported or invalid socket option");
+        }
+    };
+    Ok(cmd)
+}
+
+/// Convert the output of a completed `getsockopt` command into its C representation.
+pub fn getsockopt_output(cmd: &dyn IoctlCmd) -> Result<Vec<u8>> {
+    fn output_of<T>(output: Option<&T>) -> Result<&T> {
+        output.ok_or_else(|| errno!(EINVAL, "the socket option is not handled"))
+    }
+
+    match_ioctl_cmd_ref!(cmd, {
+        cmd: GetReuseAddr => Ok(bool_to_bytes(*output_of(cmd.output())?)),
+        cmd: GetReusePort => Ok(bool_to_bytes(*output_of(cmd.output())?)),
+        cmd: GetKeepAlive => Ok(bool_to_bytes(*output_of(cmd.output())?)),
+        cmd: GetLinger => Ok(val_to_bytes(&output_of(cmd.output())?.to_c())),
+        cmd: GetSockError => {
+            let errno = output_of(cmd.output())?.map_or(0, |errno| errno as c_int);
+            Ok(val_to_bytes(&errno))
+        },
+        cmd: GetPeerCred => Ok(val_to_bytes(output_of(cmd.output())?)),
+        cmd: GetRecvBufSize => Ok(val_to_bytes(&(*output_of(cmd.output())? as c_int))),
+        cmd: GetSendBufSize => Ok(val_to_bytes(&(*output_of(cmd.output())? as c_int))),
+        cmd: GetRecvTimeout => Ok(timeout_to_bytes(output_of(cmd.output())?)),
+        cmd: GetSendTimeout => Ok(timeout_to_bytes(output_of(cmd.output())?)),
+        cmd: GetIpv6Only => Ok(bool_to_bytes(*output_of(cmd.output())?)),
+        cmd: GetTcpNoDelay => Ok(bool_to_bytes(*output_of(cmd.output())?)),
+        cmd: GetTcpCork => Ok(bool_to_bytes(*output_of(cmd.output())?)),
+        cmd: GetTcpKeepIdle => Ok(val_to_bytes(&(*output_of(cmd.output())? as c_int))),
+        cmd: GetTcpKeepIntvl => Ok(val_to_bytes(&(*output_of(cmd.output())? as c_int))),
+        cmd: GetTcpKeepCnt => Ok(val_to_bytes(&(*output_of(cmd.output())? as c_int))),
+        _ => {
+            return_errno!(EINVAL, "not a getsockopt command");
+        }
+    })
+}
+
+fn read_val<T: Copy>(optval: &[u8]) -> Result<T> {
+    if optval.len() < size_of::<T>() {
+        return_errno!(EINVAL, "optlen is too small");
+    }
+    // Safety. The buffer is large enough and all option values are C-style types.
+    let val = unsafe { std::ptr::read_unaligned(optval.as_ptr() as *const T) };
+    Ok(val)
+}
+
+fn read_bool(optval: &[u8]) -> Result<bool> {
+    read_val::<c_int>(optval).map(|val| val != 0)
+}
+
+fn read_positive(optval: &[u8]) -> Result<u32> {
+    let val = read_val::<c_int>(optval)?;
+    if val < 1 {
+        return_errno!(EINVAL, "the value must be positive");
+    }
+    Ok(val as u32)
+}
+
+/// Read the value of `SO_RCVTIMEO` or `SO_SNDTIMEO`, which is a `timeval`.
+///
+/// Just like Linux, a zero timeval means no timeout and a negative one means
+/// timing out immediately.
+fn read_timeout(optval: &[u8]) -> Result<Option<Duration>> {
+    let tv: libc::timeval = read_val(optval)?;
+    if tv.tv_usec < 0 || tv.tv_usec >= 1_000_000 {
+        return_errno!(EDOM, "tv_usec is out of range");
+    }
+    if tv.tv_sec < 0 {
+        return Ok(Some(Duration::from_secs(0)));
+    }
+    if tv.tv_sec == 0 && tv.tv_usec == 0 {
+        return Ok(None);
+    }
+    let timeout = Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64);
+    Ok(Some(timeout))
+}
+
+fn timeout_to_bytes(timeout: &Option<Duration>) -> Vec<u8> {
+    let tv = match timeout {
+        Some(timeout) => libc::timeval {
+            tv_sec: timeout.as_secs() as _,
+            tv_usec: timeout.subsec_micros() as _,
+        },
+        None => libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+    };
+    val_to_bytes(&tv)
+}
+
+fn val_to_bytes<T: Copy>(val: &T) -> Vec<u8> {
+    // Safety. All option values are C-style types.
+    let bytes = unsafe { std::slice::from_raw_parts(val as *const T as *const u8, size_of::<T>()) };
+    bytes.to_vec()
+}
+
+fn bool_to_bytes(val: bool) -> Vec<u8> {
+    val_to_bytes(&(val as c_int))
+}
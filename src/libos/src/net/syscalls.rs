@@ -1,11 +1,15 @@
 use std::convert::TryFrom;
 use std::mem::MaybeUninit;
+use std::time::Duration;
 
+use async_io::socket::{RecvFlags, SendFlags, Shutdown};
 use num_enum::TryFromPrimitive;
 
+use super::sockopt::{getsockopt_output, new_getsockopt_cmd, new_setsockopt_cmd};
 use super::*;
 use crate::fs::StatusFlags;
 use crate::prelude::*;
+use crate::time::{timespec_t, up_time};
 use crate::util::mem_util::from_user;
 
 pub async fn do_socket(domain: c_int, type_and_flags: c_int, protocol: c_int) -> Result<isize> {
@@ -33,12 +37,18 @@ pub async fn do_socket(domain: c_int, type_and_flags: c_int, protocol: c_int) ->
         match type_ {
             Type::STREAM => true,
             Type::DGRAM => false,
+            // Netlink sockets make no difference between raw and datagram sockets
+            Type::RAW if domain == Domain::Netlink => false,
             _ => return_errno!(EINVAL, "invalid type"),
         }
     };
     let _protocol = {
-        // Only the default protocol is supported for now
+        // Only the default protocol is supported for now, which is NETLINK_ROUTE
+        // for netlink sockets
         if protocol != 0 {
+            if domain == Domain::Netlink {
+                return_errno!(EPROTONOSUPPORT, "only NETLINK_ROUTE is supported");
+            }
             return_errno!(EINVAL, "invalid protocol");
         }
         protocol
@@ -46,6 +56,9 @@ pub async fn do_socket(domain: c_int, type_and_flags: c_int, protocol: c_int) ->
 
     // Create the socket
     let socket_file = SocketFile::new(domain, is_stream)?;
+    if flags.contains(SocketFlags::SOCK_NONBLOCK) {
+        socket_file.set_status_flags(StatusFlags::O_NONBLOCK)?;
+    }
     let file_ref = FileRef::new_socket(socket_file);
 
     let close_on_spawn = flags.contains(SocketFlags::SOCK_CLOEXEC);
@@ -53,6 +66,46 @@ pub async fn do_socket(domain: c_int, type_and_flags: c_int, protocol: c_int) ->
     Ok(fd as isize)
 }
 
+pub async fn do_socketpair(
+    domain: c_int,
+    type_and_flags: c_int,
+    protocol: c_int,
+    sv: *mut c_int,
+) -> Result<isize> {
+    // Check arguments
+    let sv = {
+        from_user::check_mut_array(sv, 2)?;
+        unsafe { std::slice::from_raw_parts_mut(sv, 2) }
+    };
+    let domain = Domain::try_from(domain)
+        .map_err(|_| errno!(EINVAL, "invalid or unsupported network domain"))?;
+    let flags = SocketFlags::from_bits_truncate(type_and_flags);
+    let is_stream = {
+        let type_bits = type_and_flags & !flags.bits();
+        match type_bits {
+            libc::SOCK_STREAM => true,
+            libc::SOCK_DGRAM => false,
+            _ => return_errno!(EINVAL, "invalid type"),
+        }
+    };
+    if protocol != 0 {
+        return_errno!(EPROTONOSUPPORT, "invalid protocol");
+    }
+
+    // Create the sockets
+    let (socket_a, socket_b) = SocketFile::new_pair(domain, is_stream)?;
+    if flags.contains(SocketFlags::SOCK_NONBLOCK) {
+        socket_a.set_status_flags(StatusFlags::O_NONBLOCK)?;
+        socket_b.set_status_flags(StatusFlags::O_NONBLOCK)?;
+    }
+
+    let close_on_spawn = flags.contains(SocketFlags::SOCK_CLOEXEC);
+    let current = current!();
+    sv[0] = current.add_file(FileRef::new_socket(socket_a), close_on_spawn) as c_int;
+    sv[1] = current.add_file(FileRef::new_socket(socket_b), close_on_spawn) as c_int;
+    Ok(0)
+}
+
 pub async fn do_bind(
     fd: c_int,
     addr: *const libc::sockaddr,
@@ -75,7 +128,7 @@ pub async fn do_listen(fd: c_int, backlog: c_int) -> Result<isize> {
     let file_ref = current!().file(fd as FileDesc)?;
     let socket_file = file_ref
         .as_socket_file()
-        .ok_or_else(|| errno!(EINVAL, "not a socket"))?;
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
     socket_file.listen(backlog as u32)?;
     Ok(0)
 }
@@ -97,7 +150,7 @@ pub async fn do_connect(
     let file_ref = current!().file(fd as FileDesc)?;
     let socket_file = file_ref
         .as_socket_file()
-        .ok_or_else(|| errno!(EINVAL, "not a socket"))?;
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
     socket_file.connect(&addr).await?;
     Ok(0)
 }
@@ -116,63 +169,27 @@ pub async fn do_accept4(
     addr_len: *mut libc::socklen_t,
     flags: c_int,
 ) -> Result<isize> {
-    // Set output vars for the accepted address and its length, if needed
-    let output_addr_buf_and_len: Option<(&mut [u8], &mut libc::socklen_t)> = {
-        if !addr.is_null() {
-            let output_len = {
-                from_user::check_ptr(addr_len)?;
-                unsafe { &mut *addr_len }
-            };
-            let addr = addr as *mut u8;
-            let addr_len = *output_len as usize;
-            let output_buf = {
-                from_user::check_mut_array(addr, addr_len)?;
-                unsafe { std::slice::from_raw_parts_mut(addr, addr_len) }
-            };
-            Some((output_buf, output_len))
-        } else {
-            None
-        }
-    };
+    // Check the output vars for the accepted address and its length, if needed
+    let output_addr = check_sock_addr_output(addr, addr_len)?;
     // Process other input arguments
     let flags = SocketFlags::from_bits_truncate(flags);
     let file_ref = current!().file(fd as FileDesc)?;
     let socket_file = file_ref
         .as_socket_file()
-        .ok_or_else(|| errno!(EINVAL, "not a socket"))?;
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
 
     // Do accept
     let accepted_socket = socket_file.accept().await?;
 
     // Set the non-blocking flag
     if flags.contains(SocketFlags::SOCK_NONBLOCK) {
-        /*
-        let new_flags = StatusFlags::O_NONBLOCK;
-        socket_file.set_status_flags(new_flags).unwrap();
-        */
-        todo!("implement SocketFile::set_status_flags()")
+        accepted_socket.set_status_flags(StatusFlags::O_NONBLOCK)?;
     }
     // Output the address
-    if let Some((output_addr_buf, output_addr_len)) = output_addr_buf_and_len {
-        /*
-        let (addr_storage, addr_len) = {
-            let addr = accepted_socket.addr();
-            addr.to_c_storage()
-        };
-
-        // Output the address's _actual_ length
-        *output_addr_len = addr_len as _;
-        // Output the address content
-        let addr_buf = {
-            let ptr = &addr_storage as *const u8;
-            let len = addr_len;
-            std::slice::from_raw_parts(ptr, len)
-        };
-        let copy_len = output_addr_buf.len().min(addr_buf.len());
-        (&mut output_addr_buf[..copy_len]).copy_from_slice(&addr_buf[..copy_len]);
-        */
-        todo!("implement SocketFile::addr()")
-    };
+    if let Some((output_addr_buf, output_addr_len)) = output_addr {
+        let peer_addr = accepted_socket.peer_addr()?;
+        copy_sock_addr_to_user(&peer_addr, output_addr_buf, output_addr_len);
+    }
     // Update the file table
     let new_fd = {
         let new_file_ref = FileRef::new_socket(accepted_socket);
@@ -182,6 +199,417 @@ pub async fn do_accept4(
     Ok(new_fd as isize)
 }
 
+pub async fn do_shutdown(fd: c_int, how: c_int) -> Result<isize> {
+    let how = Shutdown::from_c(how as u32)?;
+    let file_ref = current!().file(fd as FileDesc)?;
+    let socket_file = file_ref
+        .as_socket_file()
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
+    socket_file.shutdown(how)?;
+    Ok(0)
+}
+
+pub async fn do_getsockname(
+    fd: c_int,
+    addr: *mut libc::sockaddr,
+    addr_len: *mut libc::socklen_t,
+) -> Result<isize> {
+    let (output_addr_buf, output_addr_len) = check_sock_addr_output(addr, addr_len)?
+        .ok_or_else(|| errno!(EFAULT, "addr must not be null"))?;
+    let file_ref = current!().file(fd as FileDesc)?;
+    let socket_file = file_ref
+        .as_socket_file()
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
+    let addr = socket_file.addr()?;
+    copy_sock_addr_to_user(&addr, output_addr_buf, output_addr_len);
+    Ok(0)
+}
+
+pub async fn do_getpeername(
+    fd: c_int,
+    addr: *mut libc::sockaddr,
+    addr_len: *mut libc::socklen_t,
+) -> Result<isize> {
+    let (output_addr_buf, output_addr_len) = check_sock_addr_output(addr, addr_len)?
+        .ok_or_else(|| errno!(EFAULT, "addr must not be null"))?;
+    let file_ref = current!().file(fd as FileDesc)?;
+    let socket_file = file_ref
+        .as_socket_file()
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
+    let peer_addr = socket_file.peer_addr()?;
+    copy_sock_addr_to_user(&peer_addr, output_addr_buf, output_addr_len);
+    Ok(0)
+}
+
+pub async fn do_setsockopt(
+    fd: c_int,
+    level: c_int,
+    optname: c_int,
+    optval: *const c_void,
+    optlen: libc::socklen_t,
+) -> Result<isize> {
+    let optval: &[u8] = {
+        let optlen = optlen as usize;
+        if optval.is_null() && optlen > 0 {
+            return_errno!(EFAULT, "optval must not be null");
+        }
+        if optlen == 0 {
+            &[]
+        } else {
+            from_user::check_array(optval as *const u8, optlen)?;
+            unsafe { std::slice::from_raw_parts(optval as *const u8, optlen) }
+        }
+    };
+    let file_ref = current!().file(fd as FileDesc)?;
+    let socket_file = file_ref
+        .as_socket_file()
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
+
+    let mut cmd = new_setsockopt_cmd(level, optname, optval)?;
+    socket_file.ioctl(cmd.as_mut())?;
+    Ok(0)
+}
+
+pub async fn do_getsockopt(
+    fd: c_int,
+    level: c_int,
+    optname: c_int,
+    optval: *mut c_void,
+    optlen: *mut libc::socklen_t,
+) -> Result<isize> {
+    let optlen = {
+        from_user::check_mut_ptr(optlen)?;
+        unsafe { &mut *optlen }
+    };
+    if (*optlen as i32) < 0 {
+        return_errno!(EINVAL, "optlen must not be negative");
+    }
+    let optval: &mut [u8] = {
+        let optlen = *optlen as usize;
+        if optval.is_null() && optlen > 0 {
+            return_errno!(EFAULT, "optval must not be null");
+        }
+        if optlen == 0 {
+            &mut []
+        } else {
+            from_user::check_mut_array(optval as *mut u8, optlen)?;
+            unsafe { std::slice::from_raw_parts_mut(optval as *mut u8, optlen) }
+        }
+    };
+    let file_ref = current!().file(fd as FileDesc)?;
+    let socket_file = file_ref
+        .as_socket_file()
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
+
+    let mut cmd = new_getsockopt_cmd(level, optname)?;
+    socket_file.ioctl(cmd.as_mut())?;
+    let output = getsockopt_output(cmd.as_ref())?;
+
+    // Just like Linux, the output is truncated if the user buffer is too small
+    let copy_len = optval.len().min(output.len());
+    optval[..copy_len].copy_from_slice(&output[..copy_len]);
+    *optlen = copy_len as _;
+    Ok(0)
+}
+
+pub async fn do_sendto(
+    fd: c_int,
+    base: *const c_void,
+    len: size_t,
+    flags: c_int,
+    addr: *const libc::sockaddr,
+    addr_len: libc::socklen_t,
+) -> Result<isize> {
+    let buf = buf_from_user(base as *const u8, len)?;
+    let flags = SendFlags::from_bits_truncate(flags);
+    let file_ref = current!().file(fd as FileDesc)?;
+    let socket_file = file_ref
+        .as_socket_file()
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
+
+    // The destination address is ignored by connection-mode sockets
+    let addr = if !addr.is_null() {
+        let addr_len = addr_len as usize;
+        let sockaddr_storage = copy_sock_addr_from_user(addr, addr_len)?;
+        Some(AnyAddr::from_c_storage(&sockaddr_storage, addr_len)?)
+    } else {
+        None
+    };
+
+    let res = socket_file
+        .sendmsg(&[buf], flags, addr.as_ref(), None)
+        .await;
+    raise_sigpipe_on_epipe(&res, flags).await;
+    res.map(|nbytes| nbytes as isize)
+}
+
+pub async fn do_recvfrom(
+    fd: c_int,
+    base: *mut c_void,
+    len: size_t,
+    flags: c_int,
+    addr: *mut libc::sockaddr,
+    addr_len: *mut libc::socklen_t,
+) -> Result<isize> {
+    let buf = buf_from_user_mut(base as *mut u8, len)?;
+    let output_addr = check_sock_addr_output(addr, addr_len)?;
+    let flags = RecvFlags::from_bits_truncate(flags);
+    let file_ref = current!().file(fd as FileDesc)?;
+    let socket_file = file_ref
+        .as_socket_file()
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
+
+    let (nbytes, src_addr, _, _) = socket_file.recvmsg(&mut [buf], flags, None).await?;
+
+    if let Some((output_addr_buf, output_addr_len)) = output_addr {
+        match src_addr {
+            Some(src_addr) => copy_sock_addr_to_user(&src_addr, output_addr_buf, output_addr_len),
+            // Connection-mode sockets have no source addresses to return
+            None => *output_addr_len = 0,
+        }
+    }
+    Ok(nbytes as isize)
+}
+
+pub async fn do_sendmsg(fd: c_int, msg_ptr: *const msghdr, flags_c: c_int) -> Result<isize> {
+    let msg = {
+        from_user::check_ptr(msg_ptr)?;
+        unsafe { &*msg_ptr }
+    };
+    let flags = SendFlags::from_bits_truncate(flags_c);
+    let file_ref = current!().file(fd as FileDesc)?;
+    let socket_file = file_ref
+        .as_socket_file()
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
+
+    let nbytes = sendmsg_with_socket(socket_file, msg, flags).await?;
+    Ok(nbytes as isize)
+}
+
+pub async fn do_recvmsg(fd: c_int, msg_mut_ptr: *mut msghdr_mut, flags_c: c_int) -> Result<isize> {
+    let msg = {
+        from_user::check_mut_ptr(msg_mut_ptr)?;
+        unsafe { &mut *msg_mut_ptr }
+    };
+    let flags = RecvFlags::from_bits_truncate(flags_c);
+    let file_ref = current!().file(fd as FileDesc)?;
+    let socket_file = file_ref
+        .as_socket_file()
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
+
+    let nbytes = recvmsg_with_socket(socket_file, msg, flags).await?;
+    Ok(nbytes as isize)
+}
+
+pub async fn do_sendmmsg(
+    fd: c_int,
+    msgvec: *mut mmsghdr,
+    vlen: c_uint,
+    flags_c: c_int,
+) -> Result<isize> {
+    let msgvec = {
+        // Just like Linux, the number of messages is silently capped
+        let vlen = (vlen as usize).min(UIO_MAXIOV);
+        from_user::check_mut_array(msgvec, vlen)?;
+        unsafe { std::slice::from_raw_parts_mut(msgvec, vlen) }
+    };
+    let flags = SendFlags::from_bits_truncate(flags_c);
+    let file_ref = current!().file(fd as FileDesc)?;
+    let socket_file = file_ref
+        .as_socket_file()
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
+
+    let mut num_sent = 0;
+    for mmsg in msgvec {
+        // Safety. msghdr and msghdr_mut only differ in the mutability of pointers.
+        let msg = unsafe { &*(&mmsg.msg_hdr as *const msghdr_mut as *const msghdr) };
+        match sendmsg_with_socket(socket_file, msg, flags).await {
+            Ok(nbytes) => mmsg.msg_len = nbytes as c_uint,
+            // An error is reported only if no message has been sent
+            Err(e) if num_sent == 0 => return Err(e),
+            Err(_) => break,
+        }
+        num_sent += 1;
+    }
+    Ok(num_sent as isize)
+}
+
+pub async fn do_recvmmsg(
+    fd: c_int,
+    msgvec: *mut mmsghdr,
+    vlen: c_uint,
+    flags_c: c_int,
+    timeout: *const timespec_t,
+) -> Result<isize> {
+    let msgvec = {
+        // Just like Linux, the number of messages is silently capped
+        let vlen = (vlen as usize).min(UIO_MAXIOV);
+        from_user::check_mut_array(msgvec, vlen)?;
+        unsafe { std::slice::from_raw_parts_mut(msgvec, vlen) }
+    };
+    let mut flags = RecvFlags::from_bits_truncate(flags_c);
+    // Just like Linux, the timeout is only checked after the receipt of each message
+    let deadline = if !timeout.is_null() {
+        from_user::check_ptr(timeout)?;
+        let timeout = timespec_t::from_raw_ptr(timeout)?;
+        Some(now()? + timeout.as_duration())
+    } else {
+        None
+    };
+    let file_ref = current!().file(fd as FileDesc)?;
+    let socket_file = file_ref
+        .as_socket_file()
+        .ok_or_else(|| errno!(ENOTSOCK, "not a socket"))?;
+
+    let mut num_recv = 0;
+    for mmsg in msgvec {
+        match recvmsg_with_socket(socket_file, &mut mmsg.msg_hdr, flags).await {
+            Ok(nbytes) => mmsg.msg_len = nbytes as c_uint,
+            // An error is reported only if no message has been received
+            Err(e) if num_recv == 0 => return Err(e),
+            Err(_) => break,
+        }
+        num_recv += 1;
+
+        // Only the first message is waited for if MSG_WAITFORONE is given
+        if flags.contains(RecvFlags::MSG_WAITFORONE) {
+            flags |= RecvFlags::MSG_DONTWAIT;
+        }
+        if let Some(deadline) = deadline {
+            if now()? >= deadline {
+                break;
+            }
+        }
+    }
+    Ok(num_recv as isize)
+}
+
+fn now() -> Result<Duration> {
+    up_time::get().ok_or_else(|| errno!(EINVAL, "failed to get the up time"))
+}
+
+async fn sendmsg_with_socket(
+    socket_file: &SocketFile,
+    msg: &msghdr,
+    flags: SendFlags,
+) -> Result<usize> {
+    let bufs = bufs_from_user(msg.msg_iov, msg.msg_iovlen as usize)?;
+    let control = if !msg.msg_control.is_null() && msg.msg_controllen > 0 {
+        let control = buf_from_user(msg.msg_control as *const u8, msg.msg_controllen as usize)?;
+        Some(control)
+    } else {
+        None
+    };
+    // The destination address is ignored by connection-mode sockets
+    let addr = if !msg.msg_name.is_null() && msg.msg_namelen > 0 {
+        let addr_len = msg.msg_namelen as usize;
+        let sockaddr_storage =
+            copy_sock_addr_from_user(msg.msg_name as *const libc::sockaddr, addr_len)?;
+        Some(AnyAddr::from_c_storage(&sockaddr_storage, addr_len)?)
+    } else {
+        None
+    };
+
+    let res = socket_file
+        .sendmsg(&bufs, flags, addr.as_ref(), control)
+        .await;
+    raise_sigpipe_on_epipe(&res, flags).await;
+    res
+}
+
+async fn recvmsg_with_socket(
+    socket_file: &SocketFile,
+    msg: &mut msghdr_mut,
+    flags: RecvFlags,
+) -> Result<usize> {
+    let mut bufs = bufs_from_user_mut(msg.msg_iov, msg.msg_iovlen as usize)?;
+    let name = if !msg.msg_name.is_null() {
+        Some(buf_from_user_mut(
+            msg.msg_name as *mut u8,
+            msg.msg_namelen as usize,
+        )?)
+    } else {
+        None
+    };
+    let control = if !msg.msg_control.is_null() && msg.msg_controllen > 0 {
+        Some(buf_from_user_mut(
+            msg.msg_control as *mut u8,
+            msg.msg_controllen as usize,
+        )?)
+    } else {
+        None
+    };
+
+    let (nbytes, src_addr, msg_flags, control_len) =
+        socket_file.recvmsg(&mut bufs, flags, control).await?;
+
+    match (src_addr, name) {
+        (Some(src_addr), Some(name)) => {
+            copy_sock_addr_to_user(&src_addr, name, &mut msg.msg_namelen);
+        }
+        // Connection-mode sockets have no source addresses to return
+        _ => msg.msg_namelen = 0,
+    }
+    msg.msg_controllen = control_len as size_t;
+    msg.msg_flags = msg_flags.bits();
+    Ok(nbytes)
+}
+
+/// Just like Linux, raise SIGPIPE if the connection is broken, unless MSG_NOSIGNAL is given.
+async fn raise_sigpipe_on_epipe(res: &Result<usize>, flags: SendFlags) {
+    if res.has_errno(EPIPE) && !flags.contains(SendFlags::MSG_NOSIGNAL) {
+        let tid = current!().tid();
+        let _ = crate::signal::do_tkill(tid, crate::signal::SIGPIPE.as_u8() as i32).await;
+    }
+}
+
+/// The max number of iovecs in a message, or messages in a message vector.
+const UIO_MAXIOV: usize = 1024;
+
+fn buf_from_user<'a>(base: *const u8, len: usize) -> Result<&'a [u8]> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    from_user::check_array(base, len)?;
+    Ok(unsafe { std::slice::from_raw_parts(base, len) })
+}
+
+fn buf_from_user_mut<'a>(base: *mut u8, len: usize) -> Result<&'a mut [u8]> {
+    if len == 0 {
+        return Ok(&mut []);
+    }
+    from_user::check_mut_array(base, len)?;
+    Ok(unsafe { std::slice::from_raw_parts_mut(base, len) })
+}
+
+fn bufs_from_user<'a>(iov: *const libc::iovec, iovlen: usize) -> Result<Vec<&'a [u8]>> {
+    if iovlen > UIO_MAXIOV {
+        return_errno!(EMSGSIZE, "too many iovecs");
+    }
+    if iovlen == 0 {
+        return Ok(Vec::new());
+    }
+    from_user::check_array(iov, iovlen)?;
+    let iovs = unsafe { std::slice::from_raw_parts(iov, iovlen) };
+    iovs.iter()
+        .map(|iov| buf_from_user(iov.iov_base as *const u8, iov.iov_len as usize))
+        .collect()
+}
+
+fn bufs_from_user_mut<'a>(iov: *mut libc::iovec, iovlen: usize) -> Result<Vec<&'a mut [u8]>> {
+    if iovlen > UIO_MAXIOV {
+        return_errno!(EMSGSIZE, "too many iovecs");
+    }
+    if iovlen == 0 {
+        return Ok(Vec::new());
+    }
+    from_user::check_array(iov as *const libc::iovec, iovlen)?;
+    let iovs = unsafe { std::slice::from_raw_parts(iov, iovlen) };
+    iovs.iter()
+        .map(|iov| buf_from_user_mut(iov.iov_base as *mut u8, iov.iov_len as usize))
+        .collect()
+}
+
 // Flags to use when creating a new socket
 bitflags! {
     struct SocketFlags: i32 {
@@ -190,6 +618,47 @@ bitflags! {
     }
 }
 
+fn check_sock_addr_output<'a>(
+    addr: *mut libc::sockaddr,
+    addr_len: *mut libc::socklen_t,
+) -> Result<Option<(&'a mut [u8], &'a mut libc::socklen_t)>> {
+    if addr.is_null() {
+        return Ok(None);
+    }
+    let output_len = {
+        from_user::check_mut_ptr(addr_len)?;
+        unsafe { &mut *addr_len }
+    };
+    if (*output_len as i32) < 0 {
+        return_errno!(EINVAL, "addr len must not be negative");
+    }
+    let addr = addr as *mut u8;
+    let addr_len = *output_len as usize;
+    let output_buf = {
+        from_user::check_mut_array(addr, addr_len)?;
+        unsafe { std::slice::from_raw_parts_mut(addr, addr_len) }
+    };
+    Ok(Some((output_buf, output_len)))
+}
+
+fn copy_sock_addr_to_user(
+    addr: &AnyAddr,
+    output_addr_buf: &mut [u8],
+    output_addr_len: &mut libc::socklen_t,
+) {
+    let (addr_storage, addr_len) = addr.to_c_storage();
+    // Safety. The length of a socket address never exceeds that of sockaddr_storage.
+    let addr_buf = unsafe {
+        let ptr = &addr_storage as *const _ as *const u8;
+        std::slice::from_raw_parts(ptr, addr_len)
+    };
+    // Just like Linux, the address is truncated if the user buffer is too small,
+    // but the _actual_ length is always returned.
+    let copy_len = output_addr_buf.len().min(addr_buf.len());
+    output_addr_buf[..copy_len].copy_from_slice(&addr_buf[..copy_len]);
+    *output_addr_len = addr_len as _;
+}
+
 fn copy_sock_addr_from_user(
     addr: *const libc::sockaddr,
     addr_len: usize,
@@ -0,0 +1,178 @@
+//! The address space of trusted unix domain sockets.
+//!
+//! A pathname address is identified by the inode of its socket file, which is
+//! created in the LibOS file system when the address is bound. So it does not
+//! matter whether a pathname is relative, absolute or contains symlinks. An abstract
+//! address is identified by its name.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Weak;
+
+use super::datagram::Inbox;
+use super::stream::Backlog;
+use crate::fs::FileType;
+use crate::net::UnixAddr;
+use crate::prelude::*;
+
+lazy_static! {
+    pub static ref ADDR_SPACE: AddrSpace = AddrSpace::new();
+}
+
+/// The address space, which maps addresses to the sockets bound to them.
+pub struct AddrSpace {
+    table: SgxMutex<HashMap<AddrKey, Binding>>,
+}
+
+/// A socket bound to an address.
+#[derive(Clone, Debug)]
+pub enum Binding {
+    /// A stream socket, which accepts connections only when it is listening.
+    Stream(Option<Arc<Backlog>>),
+    /// A datagram socket.
+    Datagram(Weak<Inbox>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum AddrKey {
+    Pathname { dev: usize, ino: usize },
+    Abstract(Vec<u8>),
+}
+
+impl AddrSpace {
+    fn new() -> Self {
+        Self {
+            table: SgxMutex::new(HashMap::new()),
+        }
+    }
+
+    /// Bind an address.
+    ///
+    /// For a pathname address, the socket file is created. For an unnamed address, an
+    /// unused abstract address is picked, just like the autobind feature of Linux.
+    /// The address is unbound when the returned `BoundAddr` is dropped.
+    pub fn bind(&self, addr: &UnixAddr, binding: Binding) -> Result<BoundAddr> {
+        match addr {
+            UnixAddr::Pathname(path) => {
+                let inode = {
+                    let current = current!();
+                    let fs = current.fs().lock().unwrap();
+                    // Just like Linux, the socket file is created with all permissions
+                    // (minus umask, which is not supported yet)
+                    fs.create_inode(path, FileType::Socket, 0o777)
+                        .map_err(|e| {
+                            if e.errno() == EEXIST {
+                                errno!(EADDRINUSE, "the address is already in use")
+                            } else {
+                                e
+                            }
+                        })?
+                };
+                let metadata = inode.metadata()?;
+                let key = AddrKey::Pathname {
+                    dev: metadata.dev,
+                    ino: metadata.inode,
+                };
+                self.insert(key, addr.clone(), binding)
+            }
+            UnixAddr::Abstract(name) => {
+                let key = AddrKey::Abstract(name.clone());
+                self.insert(key, addr.clone(), binding)
+            }
+            UnixAddr::Unnamed => self.autobind(binding),
+        }
+    }
+
+    /// Look up the socket bound to an address.
+    pub fn lookup(&self, addr: &UnixAddr) -> Result<Binding> {
+        let key = match addr {
+            UnixAddr::Pathname(path) => {
+                let inode = {
+                    let current = current!();
+                    let fs = current.fs().lock().unwrap();
+                    fs.lookup_inode(path)?
+                };
+                let metadata = inode.metadata()?;
+                if metadata.type_ != FileType::Socket {
+                    return_errno!(ECONNREFUSED, "not a socket file");
+                }
+                AddrKey::Pathname {
+                    dev: metadata.dev,
+                    ino: metadata.inode,
+                }
+            }
+            UnixAddr::Abstract(name) => AddrKey::Abstract(name.clone()),
+            UnixAddr::Unnamed => return_errno!(EINVAL, "cannot look up an unnamed address"),
+        };
+
+        let table = self.table.lock().unwrap();
+        table
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| errno!(ECONNREFUSED, "no socket is bound to the address"))
+    }
+
+    /// Returns whether an address is bound by a trusted socket.
+    pub fn contains(&self, addr: &UnixAddr) -> bool {
+        self.lookup(addr).is_ok()
+    }
+
+    /// Update the socket bound to an address.
+    pub fn update(&self, bound_addr: &BoundAddr, binding: Binding) {
+        let mut table = self.table.lock().unwrap();
+        table.insert(bound_addr.key.clone(), binding);
+    }
+
+    fn insert(&self, key: AddrKey, addr: UnixAddr, binding: Binding) -> Result<BoundAddr> {
+        let mut table = self.table.lock().unwrap();
+        if table.contains_key(&key) {
+            return_errno!(EADDRINUSE, "the address is already in use");
+        }
+        table.insert(key.clone(), binding);
+        Ok(BoundAddr { key, addr })
+    }
+
+    fn autobind(&self, binding: Binding) -> Result<BoundAddr> {
+        // Just like Linux, an autobind address consists of five hex digits
+        const MAX_AUTOBIND_NAMES: u32 = 0x100000;
+        static NEXT_NAME: AtomicU32 = AtomicU32::new(0);
+
+        for _ in 0..MAX_AUTOBIND_NAMES {
+            let name = {
+                let name_num = NEXT_NAME.fetch_add(1, Ordering::Relaxed) % MAX_AUTOBIND_NAMES;
+                format!("{:05x}", name_num).into_bytes()
+            };
+            let addr = UnixAddr::Abstract(name.clone());
+            match self.insert(AddrKey::Abstract(name), addr, binding.clone()) {
+                Ok(bound_addr) => return Ok(bound_addr),
+                Err(e) if e.errno() == EADDRINUSE => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        return_errno!(EADDRINUSE, "no autobind address is available");
+    }
+
+    fn remove(&self, key: &AddrKey) {
+        let mut table = self.table.lock().unwrap();
+        table.remove(key);
+    }
+}
+
+/// A bound address, which is unbound when dropped.
+#[derive(Debug)]
+pub struct BoundAddr {
+    key: AddrKey,
+    addr: UnixAddr,
+}
+
+impl BoundAddr {
+    pub fn addr(&self) -> &UnixAddr {
+        &self.addr
+    }
+}
+
+impl Drop for BoundAddr {
+    fn drop(&mut self) {
+        ADDR_SPACE.remove(&self.key);
+    }
+}
@@ -0,0 +1,486 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Weak;
+use std::time::Duration;
+
+use async_io::ioctl::IoctlCmd;
+use async_io::match_ioctl_cmd_mut;
+use async_io::socket::sockopt::*;
+use async_io::socket::{MsgFlags, RecvFlags, SendFlags, Shutdown};
+
+use super::addr_space::{Binding, BoundAddr, ADDR_SPACE};
+use super::{current_cred, Ancillary};
+use crate::fs::{Events, Pollee, Poller, StatusFlags};
+use crate::net::conn::Deadline;
+use crate::net::{Domain, UnixAddr};
+use crate::prelude::*;
+
+// The same as the default socket buffer size of Linux
+const MAX_DGRAM_SIZE: usize = 208 * 1024;
+// The same as the default value of /proc/sys/net/unix/max_dgram_qlen on Linux
+const MAX_DGRAM_QLEN: usize = 512;
+
+/// A trusted unix datagram socket, which is implemented inside the LibOS.
+#[derive(Debug)]
+pub struct UnixDatagram {
+    inbox: Arc<Inbox>,
+    state: RwLock<State>,
+    is_nonblocking: AtomicBool,
+    timeouts: SgxMutex<Timeouts>,
+}
+
+#[derive(Debug, Default)]
+struct Timeouts {
+    recv: Option<Duration>,
+    send: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    bound_addr: Option<BoundAddr>,
+    peer: Option<Peer>,
+    is_write_shutdown: bool,
+}
+
+#[derive(Debug)]
+struct Peer {
+    addr: UnixAddr,
+    inbox: Weak<Inbox>,
+}
+
+impl UnixDatagram {
+    pub fn new() -> Result<Self> {
+        Ok(Self::new_with_state(State::default()))
+    }
+
+    /// Create a pair of connected sockets.
+    pub fn new_pair() -> Result<(Self, Self)> {
+        let socket_a = Self::new_with_state(State::default());
+        let socket_b = Self::new_with_state(State::default());
+        socket_a.state.write().unwrap().peer = Some(Peer {
+            addr: UnixAddr::Unnamed,
+            inbox: Arc::downgrade(&socket_b.inbox),
+        });
+        socket_b.state.write().unwrap().peer = Some(Peer {
+            addr: UnixAddr::Unnamed,
+            inbox: Arc::downgrade(&socket_a.inbox),
+        });
+        Ok((socket_a, socket_b))
+    }
+
+    fn new_with_state(state: State) -> Self {
+        Self {
+            inbox: Arc::new(Inbox::new()),
+            state: RwLock::new(state),
+            is_nonblocking: AtomicBool::new(false),
+            timeouts: SgxMutex::new(Timeouts::default()),
+        }
+    }
+
+    pub fn domain(&self) -> Domain {
+        Domain::Unix
+    }
+
+    pub fn bind(&self, addr: &UnixAddr) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        if state.bound_addr.is_some() {
+            return_errno!(EINVAL, "the socket is already bound");
+        }
+        let binding = Binding::Datagram(Arc::downgrade(&self.inbox));
+        state.bound_addr = Some(ADDR_SPACE.bind(addr, binding)?);
+        Ok(())
+    }
+
+    /// Set the default destination of the socket, or dissolve the association if
+    /// the address is unnamed.
+    pub fn connect(&self, peer_addr: &UnixAddr) -> Result<()> {
+        let peer = match peer_addr {
+            UnixAddr::Unnamed => None,
+            _ => {
+                let peer_inbox = Self::lookup_inbox(peer_addr)?;
+                Some(Peer {
+                    addr: peer_addr.clone(),
+                    inbox: peer_inbox,
+                })
+            }
+        };
+
+        let mut state = self.state.write().unwrap();
+        state.peer = peer;
+        Ok(())
+    }
+
+    fn lookup_inbox(addr: &UnixAddr) -> Result<Weak<Inbox>> {
+        match ADDR_SPACE.lookup(addr)? {
+            Binding::Datagram(inbox) => Ok(inbox),
+            Binding::Stream(_) => {
+                return_errno!(EPROTOTYPE, "the peer socket is not a datagram socket");
+            }
+        }
+    }
+
+    pub async fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.readv(&mut [buf]).await
+    }
+
+    pub async fn readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        let (nbytes, _, _, _) = self.recvmsg(bufs, RecvFlags::empty()).await?;
+        Ok(nbytes)
+    }
+
+    pub async fn write(&self, buf: &[u8]) -> Result<usize> {
+        self.writev(&[buf]).await
+    }
+
+    pub async fn writev(&self, bufs: &[&[u8]]) -> Result<usize> {
+        self.sendmsg(bufs, SendFlags::empty(), None, None).await
+    }
+
+    /// Send a datagram to the given address or, if no address is given, to the
+    /// connected peer.
+    pub async fn sendmsg(
+        &self,
+        bufs: &[&[u8]],
+        flags: SendFlags,
+        addr: Option<&UnixAddr>,
+        ancillary: Option<Ancillary>,
+    ) -> Result<usize> {
+        if flags.contains(SendFlags::MSG_OOB) {
+            return_errno!(EOPNOTSUPP, "MSG_OOB is not supported");
+        }
+        let is_nonblocking = self.is_nonblocking() || flags.contains(SendFlags::MSG_DONTWAIT);
+
+        let data_len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        if data_len > MAX_DGRAM_SIZE {
+            return_errno!(EMSGSIZE, "the datagram is too large");
+        }
+
+        let (src_addr, peer_inbox) = {
+            let state = self.state.read().unwrap();
+            if state.is_write_shutdown {
+                return_errno!(EPIPE, "the socket is shut down for writing");
+            }
+            let src_addr = state
+                .bound_addr
+                .as_ref()
+                .map_or(UnixAddr::Unnamed, |bound_addr| bound_addr.addr().clone());
+            let peer_inbox = match (addr, &state.peer) {
+                (Some(addr), _) => Self::lookup_inbox(addr)?,
+                (None, Some(peer)) => peer.inbox.clone(),
+                (None, None) => return_errno!(ENOTCONN, "the destination is not given"),
+            };
+            (src_addr, peer_inbox)
+        };
+        let peer_inbox = peer_inbox
+            .upgrade()
+            .ok_or_else(|| errno!(ECONNREFUSED, "the peer socket is closed"))?;
+
+        let mut data = Vec::with_capacity(data_len);
+        for buf in bufs {
+            data.extend_from_slice(buf);
+        }
+        let mut datagram = Some(Datagram {
+            data,
+            src_addr,
+            ancillary,
+        });
+
+        // Fast path
+        let res = peer_inbox.try_push(&mut datagram);
+        if is_nonblocking || !res.has_errno(EAGAIN) {
+            return res.map(|_| data_len);
+        }
+
+        // Slow path: wait for the peer to have room, bounded by SO_SNDTIMEO
+        let timeout = self.timeouts.lock().unwrap().send;
+        let mut deadline = Deadline::new(timeout);
+        let mut poller = Poller::new();
+        loop {
+            let events = peer_inbox.poll(Events::OUT, Some(&mut poller));
+            if events.contains(Events::OUT) {
+                let res = peer_inbox.try_push(&mut datagram);
+                if !res.has_errno(EAGAIN) {
+                    return res.map(|_| data_len);
+                }
+            }
+            deadline.wait(&mut poller).await?;
+        }
+    }
+
+    /// Receive a datagram, returning the length of the received data, the source
+    /// address, the ancillary data and the flags of the received message.
+    ///
+    /// If the flag `MSG_TRUNC` is given, the returned length is the real length of
+    /// the datagram, even if it is longer than the buffers.
+    pub async fn recvmsg(
+        &self,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+    ) -> Result<(usize, UnixAddr, Option<Ancillary>, MsgFlags)> {
+        if flags.intersects(RecvFlags::MSG_OOB | RecvFlags::MSG_ERRQUEUE) {
+            return_errno!(EOPNOTSUPP, "MSG_OOB or MSG_ERRQUEUE is not supported");
+        }
+        let is_nonblocking = self.is_nonblocking() || flags.contains(RecvFlags::MSG_DONTWAIT);
+
+        // Fast path
+        let res = self.inbox.try_pop(bufs, flags);
+        if is_nonblocking || !res.has_errno(EAGAIN) {
+            return res;
+        }
+
+        // Slow path: wait for datagrams, bounded by SO_RCVTIMEO
+        let timeout = self.timeouts.lock().unwrap().recv;
+        let mut deadline = Deadline::new(timeout);
+        let mut poller = Poller::new();
+        loop {
+            let events = self.inbox.poll(Events::IN, Some(&mut poller));
+            if events.contains(Events::IN) {
+                let res = self.inbox.try_pop(bufs, flags);
+                if !res.has_errno(EAGAIN) {
+                    return res;
+                }
+            }
+            deadline.wait(&mut poller).await?;
+        }
+    }
+
+    pub fn poll(&self, mask: Events, mut poller: Option<&mut Poller>) -> Events {
+        let mut events = self.inbox.poll(Events::IN, poller.as_deref_mut()) & Events::IN;
+
+        // Just like Linux, a connected socket is writable only if its peer has room
+        // for more datagrams
+        let state = self.state.read().unwrap();
+        let peer_inbox = state.peer.as_ref().and_then(|peer| peer.inbox.upgrade());
+        match peer_inbox {
+            _ if state.is_write_shutdown => events |= Events::OUT,
+            Some(peer_inbox) => events |= peer_inbox.poll(Events::OUT, poller) & Events::OUT,
+            None => events |= Events::OUT,
+        }
+
+        if self.inbox.is_shutdown() {
+            events |= Events::IN | Events::RDHUP;
+            if state.is_write_shutdown {
+                events |= Events::HUP;
+            }
+        }
+        events & (mask | Events::ALWAYS_POLL)
+    }
+
+    pub fn status_flags(&self) -> StatusFlags {
+        if self.is_nonblocking() {
+            StatusFlags::O_NONBLOCK
+        } else {
+            StatusFlags::empty()
+        }
+    }
+
+    pub fn set_status_flags(&self, new_flags: StatusFlags) -> Result<()> {
+        let is_nonblocking = new_flags.contains(StatusFlags::O_NONBLOCK);
+        self.is_nonblocking.store(is_nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        if how.should_shut_read() {
+            self.inbox.shutdown();
+        }
+        if how.should_shut_write() {
+            state.is_write_shutdown = true;
+        }
+        Ok(())
+    }
+
+    pub fn addr(&self) -> Result<UnixAddr> {
+        let state = self.state.read().unwrap();
+        let addr = state
+            .bound_addr
+            .as_ref()
+            .map_or(UnixAddr::Unnamed, |bound_addr| bound_addr.addr().clone());
+        Ok(addr)
+    }
+
+    pub fn peer_addr(&self) -> Result<UnixAddr> {
+        let state = self.state.read().unwrap();
+        let peer = state
+            .peer
+            .as_ref()
+            .ok_or_else(|| errno!(ENOTCONN, "not connected"))?;
+        Ok(peer.addr.clone())
+    }
+
+    pub fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        let state = self.state.read().unwrap();
+        match_ioctl_cmd_mut!(&mut *cmd, {
+            cmd: GetPeerCred => {
+                let peer_inbox = state.peer.as_ref().and_then(|peer| peer.inbox.upgrade());
+                let peer_cred = peer_inbox.map_or(PeerCred::invalid(), |peer_inbox| peer_inbox.cred);
+                cmd.set_output(peer_cred);
+                Ok(())
+            },
+            cmd: GetSockError => {
+                cmd.set_output(None);
+                Ok(())
+            },
+            cmd: GetSendBufSize => {
+                cmd.set_output(MAX_DGRAM_SIZE);
+                Ok(())
+            },
+            cmd: GetRecvBufSize => {
+                cmd.set_output(MAX_DGRAM_SIZE);
+                Ok(())
+            },
+            // The buffer sizes are fixed for now. But, just like Linux, setting
+            // them always succeeds.
+            cmd: SetSendBufSize => {
+                Ok(())
+            },
+            cmd: SetRecvBufSize => {
+                Ok(())
+            },
+            cmd: SetRecvTimeout => {
+                self.timeouts.lock().unwrap().recv = *cmd.input();
+                Ok(())
+            },
+            cmd: GetRecvTimeout => {
+                cmd.set_output(self.timeouts.lock().unwrap().recv);
+                Ok(())
+            },
+            cmd: SetSendTimeout => {
+                self.timeouts.lock().unwrap().send = *cmd.input();
+                Ok(())
+            },
+            cmd: GetSendTimeout => {
+                cmd.set_output(self.timeouts.lock().unwrap().send);
+                Ok(())
+            },
+            _ => {
+                return_errno!(ENOPROTOOPT, "the socket option is not supported");
+            }
+        })
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        self.is_nonblocking.load(Ordering::Relaxed)
+    }
+}
+
+/// The queue of the incoming datagrams of a socket.
+#[derive(Debug)]
+pub struct Inbox {
+    datagrams: SgxMutex<VecDeque<Datagram>>,
+    cred: PeerCred,
+    pollee: Pollee,
+    is_shutdown: AtomicBool,
+}
+
+#[derive(Debug)]
+struct Datagram {
+    data: Vec<u8>,
+    src_addr: UnixAddr,
+    ancillary: Option<Ancillary>,
+}
+
+impl Inbox {
+    fn new() -> Self {
+        Self {
+            datagrams: SgxMutex::new(VecDeque::new()),
+            cred: current_cred(),
+            pollee: Pollee::new(Events::OUT),
+            is_shutdown: AtomicBool::new(false),
+        }
+    }
+
+    /// Push a datagram, which is taken only if the push succeeds.
+    fn try_push(&self, datagram: &mut Option<Datagram>) -> Result<()> {
+        let mut datagrams = self.datagrams.lock().unwrap();
+        if self.is_shutdown() {
+            return_errno!(EPIPE, "the peer socket is shut down for reading");
+        }
+        if datagrams.len() >= MAX_DGRAM_QLEN {
+            return_errno!(EAGAIN, "the peer socket has no room for more datagrams");
+        }
+        datagrams.push_back(datagram.take().unwrap());
+        self.update_pollee(&datagrams);
+        Ok(())
+    }
+
+    fn try_pop(
+        &self,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+    ) -> Result<(usize, UnixAddr, Option<Ancillary>, MsgFlags)> {
+        let mut datagrams = self.datagrams.lock().unwrap();
+        let datagram = match datagrams.front() {
+            Some(datagram) => datagram,
+            // Just like Linux, receiving from a socket shut down for reading returns EOF
+            None if self.is_shutdown() => {
+                return Ok((0, UnixAddr::Unnamed, None, MsgFlags::empty()));
+            }
+            None => return_errno!(EAGAIN, "no datagrams to receive"),
+        };
+
+        let mut copied_len = 0;
+        for buf in bufs.iter_mut() {
+            let remain_data = &datagram.data[copied_len..];
+            let copy_len = remain_data.len().min(buf.len());
+            buf[..copy_len].copy_from_slice(&remain_data[..copy_len]);
+            copied_len += copy_len;
+            if copied_len == datagram.data.len() {
+                break;
+            }
+        }
+
+        let data_len = datagram.data.len();
+        let msg_flags = if copied_len < data_len {
+            MsgFlags::MSG_TRUNC
+        } else {
+            MsgFlags::empty()
+        };
+        let recv_len = if flags.contains(RecvFlags::MSG_TRUNC) {
+            data_len
+        } else {
+            copied_len
+        };
+        let src_addr = datagram.src_addr.clone();
+
+        if flags.contains(RecvFlags::MSG_PEEK) {
+            // The ancillary data are not received when peeking
+            return Ok((recv_len, src_addr, None, msg_flags));
+        }
+
+        // The rest of a truncated datagram is discarded
+        let datagram = datagrams.pop_front().unwrap();
+        self.update_pollee(&datagrams);
+        Ok((recv_len, src_addr, datagram.ancillary, msg_flags))
+    }
+
+    fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
+        self.pollee.poll(mask, poller)
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.is_shutdown.load(Ordering::Relaxed)
+    }
+
+    fn shutdown(&self) {
+        let datagrams = self.datagrams.lock().unwrap();
+        self.is_shutdown.store(true, Ordering::Relaxed);
+        // Wake up the blocked receivers and senders
+        self.update_pollee(&datagrams);
+        self.pollee.add_events(Events::IN | Events::OUT);
+    }
+
+    fn update_pollee(&self, datagrams: &VecDeque<Datagram>) {
+        if datagrams.is_empty() && !self.is_shutdown() {
+            self.pollee.del_events(Events::IN);
+        } else {
+            self.pollee.add_events(Events::IN);
+        }
+        if datagrams.len() >= MAX_DGRAM_QLEN && !self.is_shutdown() {
+            self.pollee.del_events(Events::OUT);
+        } else {
+            self.pollee.add_events(Events::OUT);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Unix domain sockets.
+//!
+//! There are two kinds of unix domain sockets. The trusted ones are implemented
+//! inside the LibOS, so their traffic never leaves the enclave. The untrusted ones
+//! are backed by host sockets, which are useful to talk with host daemons.
+//!
+//! A stream socket is trusted at the beginning and becomes untrusted only if it
+//! binds to a pathname under a HostFS mount, or connects to an address that is
+//! not bound by any trusted socket but visible to the host. The pathnames of
+//! untrusted sockets are translated between the LibOS and the host through the
+//! HostFS mounts. Datagram sockets are always trusted.
+
+use async_io::socket::sockopt::PeerCred;
+
+use crate::prelude::*;
+
+mod addr_space;
+mod datagram;
+mod stream;
+
+pub use self::datagram::UnixDatagram;
+pub use self::stream::UnixStream;
+
+/// The ancillary data sent along with the data of a trusted unix socket.
+#[derive(Debug, Default)]
+pub struct Ancillary {
+    files: Vec<FileRef>,
+}
+
+impl Ancillary {
+    /// Create ancillary data that pass files, i.e., `SCM_RIGHTS`.
+    pub fn with_files(files: Vec<FileRef>) -> Self {
+        Self { files }
+    }
+
+    pub fn files(&self) -> &[FileRef] {
+        &self.files
+    }
+
+    pub fn into_files(self) -> Vec<FileRef> {
+        self.files
+    }
+
+    /// Merge the ancillary data of two messages that are received at once.
+    fn merge(&mut self, other: Ancillary) {
+        self.files.extend(other.files);
+    }
+}
+
+/// Returns the credentials of the current process.
+pub fn current_cred() -> PeerCred {
+    // TODO: use the real UID and GID once they are supported
+    PeerCred {
+        pid: current!().process().pid() as i32,
+        uid: 0,
+        gid: 0,
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use async_io::socket::sockopt::PeerCred;
+use async_io::socket::{RecvFlags, SendFlags, Shutdown};
+
+use super::super::addr_space::BoundAddr;
+use super::super::Ancillary;
+use crate::fs::{Events, Poller};
+use crate::net::conn::Pipe;
+use crate::net::UnixAddr;
+use crate::prelude::*;
+
+/// One end of a pair of connected trusted stream sockets.
+///
+/// Each direction of the pair is a channel, along which ancillary data are sent
+/// through a queue shared by the two ends.
+#[derive(Debug)]
+pub struct Endpoint {
+    addr: UnixAddr,
+    peer_addr: UnixAddr,
+    peer_cred: PeerCred,
+    pipe: Pipe,
+    // The ancillary data sent by the peer
+    incoming: Arc<SgxMutex<AncillaryQueue>>,
+    // The ancillary data sent by this end
+    outgoing: Arc<SgxMutex<AncillaryQueue>>,
+    // Keep the address bound as long as the socket is alive
+    bound_addr: Option<BoundAddr>,
+}
+
+/// The info about one end that is needed to create a pair of endpoints.
+#[derive(Debug)]
+pub struct EndInfo {
+    pub addr: UnixAddr,
+    pub cred: PeerCred,
+    pub bound_addr: Option<BoundAddr>,
+}
+
+#[derive(Debug, Default)]
+struct AncillaryQueue {
+    // The total number of bytes written to the channel
+    write_pos: u64,
+    // The total number of bytes read from the channel
+    read_pos: u64,
+    // The start position, the length, and the ancillary data of the writes
+    // that come with ancillary data
+    items: VecDeque<(u64, usize, Ancillary)>,
+}
+
+impl Endpoint {
+    /// Create a pair of connected endpoints.
+    pub fn new_pair(this_info: EndInfo, peer_info: EndInfo) -> Result<(Self, Self)> {
+        let (this_pipe, peer_pipe) = Pipe::new_pair()?;
+        let this_outgoing = Arc::new(SgxMutex::new(AncillaryQueue::default()));
+        let peer_outgoing = Arc::new(SgxMutex::new(AncillaryQueue::default()));
+
+        let this_end = Self {
+            addr: this_info.addr.clone(),
+            peer_addr: peer_info.addr.clone(),
+            peer_cred: peer_info.cred,
+            pipe: this_pipe,
+            incoming: peer_outgoing.clone(),
+            outgoing: this_outgoing.clone(),
+            bound_addr: this_info.bound_addr,
+        };
+        let peer_end = Self {
+            addr: peer_info.addr,
+            peer_addr: this_info.addr,
+            peer_cred: this_info.cred,
+            pipe: peer_pipe,
+            incoming: this_outgoing,
+            outgoing: peer_outgoing,
+            bound_addr: peer_info.bound_addr,
+        };
+        Ok((this_end, peer_end))
+    }
+
+    pub fn addr(&self) -> &UnixAddr {
+        &self.addr
+    }
+
+    pub fn peer_addr(&self) -> &UnixAddr {
+        &self.peer_addr
+    }
+
+    pub fn peer_cred(&self) -> &PeerCred {
+        &self.peer_cred
+    }
+
+    pub fn buf_size(&self) -> usize {
+        self.pipe.buf_size()
+    }
+
+    pub async fn sendmsg(
+        &self,
+        bufs: &[&[u8]],
+        flags: SendFlags,
+        ancillary: Option<Ancillary>,
+        is_nonblocking: bool,
+        timeout: Option<Duration>,
+    ) -> Result<usize> {
+        let mut ancillary = ancillary;
+        self.pipe
+            .sendmsg(bufs, flags, is_nonblocking, timeout, |bufs| {
+                self.try_send(bufs, &mut ancillary)
+            })
+            .await
+    }
+
+    fn try_send(&self, bufs: &[&[u8]], ancillary: &mut Option<Ancillary>) -> Result<usize> {
+        let mut queue = self.outgoing.lock().unwrap();
+        let nbytes = self.pipe.writer().writev(bufs)?;
+        if nbytes > 0 {
+            // The ancillary data go along with the first byte sent
+            if let Some(ancillary) = ancillary.take() {
+                let item = (queue.write_pos, nbytes, ancillary);
+                queue.items.push_back(item);
+            }
+            queue.write_pos += nbytes as u64;
+        }
+        Ok(nbytes)
+    }
+
+    pub async fn recvmsg(
+        &self,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+        is_nonblocking: bool,
+        timeout: Option<Duration>,
+    ) -> Result<(usize, Option<Ancillary>)> {
+        let mut recv_ancillary: Option<Ancillary> = None;
+        let recv_len = self
+            .pipe
+            .recvmsg(bufs, flags, is_nonblocking, timeout, |bufs| {
+                let (nbytes, ancillary) = self.try_recv(bufs, flags)?;
+                if let Some(ancillary) = ancillary {
+                    match recv_ancillary.as_mut() {
+                        Some(recv_ancillary) => recv_ancillary.merge(ancillary),
+                        None => recv_ancillary = Some(ancillary),
+                    }
+                }
+                // Just like Linux, stop receiving more data after receiving any
+                // ancillary data
+                Ok((nbytes, recv_ancillary.is_some()))
+            })
+            .await?;
+        Ok((recv_len, recv_ancillary))
+    }
+
+    fn try_recv(
+        &self,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+    ) -> Result<(usize, Option<Ancillary>)> {
+        let mut queue = self.incoming.lock().unwrap();
+
+        // Do not receive the data beyond a write that comes with ancillary data so
+        // that the ancillary data can be received along with the data of the write.
+        // This is also what Linux does.
+        let max_len = queue
+            .items
+            .front()
+            .map(|(pos, len, _)| (pos + *len as u64 - queue.read_pos) as usize);
+        let mut bufs = match max_len {
+            Some(max_len) => truncate_bufs(bufs, max_len),
+            None => bufs.iter_mut().map(|buf| &mut **buf).collect(),
+        };
+
+        if flags.contains(RecvFlags::MSG_PEEK) {
+            // The ancillary data are not received when peeking
+            let nbytes = self.pipe.reader().peekv(&mut bufs)?;
+            return Ok((nbytes, None));
+        }
+
+        let nbytes = self.pipe.reader().readv(&mut bufs)?;
+        queue.read_pos += nbytes as u64;
+        let mut recv_ancillary: Option<Ancillary> = None;
+        while let Some((pos, _, _)) = queue.items.front() {
+            if *pos >= queue.read_pos {
+                break;
+            }
+            let (_, _, ancillary) = queue.items.pop_front().unwrap();
+            match recv_ancillary.as_mut() {
+                Some(recv_ancillary) => recv_ancillary.merge(ancillary),
+                None => recv_ancillary = Some(ancillary),
+            }
+        }
+        Ok((nbytes, recv_ancillary))
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        self.pipe.shutdown(how);
+        Ok(())
+    }
+
+    pub fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
+        self.pipe.poll(mask, poller)
+    }
+}
+
+/// Keep only the first `max_len` bytes of a set of mutable buffers.
+fn truncate_bufs<'a>(bufs: &'a mut [&mut [u8]], max_len: usize) -> Vec<&'a mut [u8]> {
+    let mut max_len = max_len;
+    let mut truncated_bufs = Vec::with_capacity(bufs.len());
+    for buf in bufs.iter_mut() {
+        if max_len == 0 {
+            break;
+        }
+        let len = buf.len().min(max_len);
+        truncated_bufs.push(&mut buf[..len]);
+        max_len -= len;
+    }
+    truncated_bufs
+}
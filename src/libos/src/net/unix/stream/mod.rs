@@ -0,0 +1,294 @@
+use std::path::Path;
+
+use async_io::ioctl::IoctlCmd;
+use async_io::socket::sockopt::{SetRecvTimeout, SetSendTimeout};
+use async_io::socket::{MsgFlags, RecvFlags, SendFlags, Shutdown};
+
+use self::trusted::TrustedStream;
+use super::addr_space::ADDR_SPACE;
+use super::Ancillary;
+use crate::fs::{host_path_of, libos_path_of, Events, Poller, StatusFlags};
+use crate::net::socket_file::SocketRuntime;
+use crate::net::{Domain, UnixAddr};
+use crate::prelude::*;
+
+mod endpoint;
+mod trusted;
+
+pub use self::trusted::Backlog;
+
+// The addresses of host sockets are host addresses, which must be translated
+// from and to LibOS addresses.
+type HostStream = host_socket::StreamSocket<UnixAddr, SocketRuntime>;
+
+/// A unix stream socket, which is either trusted or backed by a host socket.
+#[derive(Debug)]
+pub struct UnixStream {
+    inner: RwLock<Inner>,
+}
+
+#[derive(Debug, Clone)]
+enum Inner {
+    Trusted(Arc<TrustedStream>),
+    Host(Arc<HostStream>),
+}
+
+// Apply a function to all variants of Inner enum.
+macro_rules! apply_fn_on_inner {
+    ($inner:expr, |$stream:ident| { $($fn_body:tt)* }) => {{
+        let inner: &Inner = $inner;
+        match inner {
+            Inner::Trusted($stream) => {
+                $($fn_body)*
+            }
+            Inner::Host($stream) => {
+                $($fn_body)*
+            }
+        }
+    }}
+}
+
+impl UnixStream {
+    pub fn new() -> Result<Self> {
+        let trusted_stream = TrustedStream::new();
+        Ok(Self::new_with_inner(Inner::Trusted(Arc::new(
+            trusted_stream,
+        ))))
+    }
+
+    /// Create a pair of connected sockets, which are always trusted.
+    pub fn new_pair() -> Result<(Self, Self)> {
+        let (stream_a, stream_b) = TrustedStream::new_pair()?;
+        let socket_a = Self::new_with_inner(Inner::Trusted(Arc::new(stream_a)));
+        let socket_b = Self::new_with_inner(Inner::Trusted(Arc::new(stream_b)));
+        Ok((socket_a, socket_b))
+    }
+
+    fn new_with_inner(inner: Inner) -> Self {
+        Self {
+            inner: RwLock::new(inner),
+        }
+    }
+
+    pub fn domain(&self) -> Domain {
+        Domain::Unix
+    }
+
+    pub fn bind(&self, addr: &UnixAddr) -> Result<()> {
+        match self.inner() {
+            Inner::Trusted(trusted_stream) => {
+                let is_pathname = matches!(addr, UnixAddr::Pathname(_));
+                if is_pathname && trusted_stream.is_unbound_init() {
+                    // A socket file under a HostFS mount is visible to the host. So bind
+                    // a host socket to it, which host processes can connect to.
+                    if let Some(host_addr) = host_addr_of(addr)? {
+                        let host_stream = self.switch_to_host(&trusted_stream)?;
+                        return host_stream.bind(&host_addr);
+                    }
+                }
+                trusted_stream.bind(addr)
+            }
+            Inner::Host(host_stream) => {
+                let host_addr = host_addr_of(addr)?
+                    .ok_or_else(|| errno!(EINVAL, "the address is invisible to the host"))?;
+                host_stream.bind(&host_addr)
+            }
+        }
+    }
+
+    pub fn listen(&self, backlog: u32) -> Result<()> {
+        apply_fn_on_inner!(&self.inner(), |stream| { stream.listen(backlog) })
+    }
+
+    pub async fn connect(&self, peer_addr: &UnixAddr) -> Result<()> {
+        match self.inner() {
+            Inner::Trusted(trusted_stream) => {
+                if !trusted_stream.is_unbound_init() || ADDR_SPACE.contains(peer_addr) {
+                    return trusted_stream.connect(peer_addr).await;
+                }
+
+                // No trusted socket is bound to the address. So fall back to a host
+                // socket, e.g., to talk with a host daemon, but only if the address
+                // is a socket file under a HostFS mount. Otherwise, the trusted
+                // socket reports that there is no such peer.
+                let host_peer_addr = match host_addr_of(peer_addr)? {
+                    Some(host_peer_addr) => host_peer_addr,
+                    None => return trusted_stream.connect(peer_addr).await,
+                };
+                let host_stream = self.switch_to_host(&trusted_stream)?;
+                host_stream.connect(&host_peer_addr).await
+            }
+            Inner::Host(host_stream) => {
+                let host_peer_addr = host_addr_of(peer_addr)?
+                    .ok_or_else(|| errno!(ECONNREFUSED, "the address is invisible to the host"))?;
+                host_stream.connect(&host_peer_addr).await
+            }
+        }
+    }
+
+    /// Replace the trusted socket, which must be neither bound nor connected, with
+    /// a host socket.
+    fn switch_to_host(&self, trusted_stream: &TrustedStream) -> Result<Arc<HostStream>> {
+        let host_stream = Arc::new(HostStream::new()?);
+        host_stream.set_status_flags(trusted_stream.status_flags())?;
+        host_stream.ioctl(&mut SetRecvTimeout::new(trusted_stream.recv_timeout()))?;
+        host_stream.ioctl(&mut SetSendTimeout::new(trusted_stream.send_timeout()))?;
+        *self.inner.write().unwrap() = Inner::Host(host_stream.clone());
+        Ok(host_stream)
+    }
+
+    pub async fn accept(&self) -> Result<Self> {
+        let accepted_inner = match self.inner() {
+            Inner::Trusted(trusted_stream) => {
+                let accepted_stream = trusted_stream.accept().await?;
+                Inner::Trusted(Arc::new(accepted_stream))
+            }
+            Inner::Host(host_stream) => {
+                let accepted_stream = host_stream.accept().await?;
+                Inner::Host(Arc::new(accepted_stream))
+            }
+        };
+        Ok(Self::new_with_inner(accepted_inner))
+    }
+
+    pub async fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.readv(&mut [buf]).await
+    }
+
+    pub async fn readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        let (nbytes, _, _) = self.recvmsg(bufs, RecvFlags::empty()).await?;
+        Ok(nbytes)
+    }
+
+    /// Receive data and, for trusted sockets, ancillary data.
+    pub async fn recvmsg(
+        &self,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+    ) -> Result<(usize, Option<Ancillary>, MsgFlags)> {
+        match self.inner() {
+            Inner::Trusted(trusted_stream) => {
+                let (nbytes, ancillary) = trusted_stream.recvmsg(bufs, flags).await?;
+                Ok((nbytes, ancillary, MsgFlags::empty()))
+            }
+            Inner::Host(host_stream) => {
+                let (nbytes, msg_flags) = host_stream.recvmsg(bufs, flags).await?;
+                Ok((nbytes, None, msg_flags))
+            }
+        }
+    }
+
+    pub async fn write(&self, buf: &[u8]) -> Result<usize> {
+        self.writev(&[buf]).await
+    }
+
+    pub async fn writev(&self, bufs: &[&[u8]]) -> Result<usize> {
+        self.sendmsg(bufs, SendFlags::empty(), None).await
+    }
+
+    /// Send data and, for trusted sockets, ancillary data.
+    pub async fn sendmsg(
+        &self,
+        bufs: &[&[u8]],
+        flags: SendFlags,
+        ancillary: Option<Ancillary>,
+    ) -> Result<usize> {
+        match self.inner() {
+            Inner::Trusted(trusted_stream) => trusted_stream.sendmsg(bufs, flags, ancillary).await,
+            Inner::Host(host_stream) => {
+                if ancillary.is_some() {
+                    return_errno!(EOPNOTSUPP, "cannot pass ancillary data to host sockets");
+                }
+                host_stream.sendmsg(bufs, flags).await
+            }
+        }
+    }
+
+    pub fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
+        apply_fn_on_inner!(&self.inner(), |stream| { stream.poll(mask, poller) })
+    }
+
+    pub fn status_flags(&self) -> StatusFlags {
+        apply_fn_on_inner!(&self.inner(), |stream| { stream.status_flags() })
+    }
+
+    pub fn set_status_flags(&self, new_flags: StatusFlags) -> Result<()> {
+        apply_fn_on_inner!(&self.inner(), |stream| {
+            stream.set_status_flags(new_flags)
+        })
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        apply_fn_on_inner!(&self.inner(), |stream| { stream.shutdown(how) })
+    }
+
+    pub fn addr(&self) -> Result<UnixAddr> {
+        match self.inner() {
+            Inner::Trusted(trusted_stream) => Ok(trusted_stream.addr()),
+            Inner::Host(host_stream) => Ok(libos_addr_of(host_stream.addr()?)),
+        }
+    }
+
+    pub fn peer_addr(&self) -> Result<UnixAddr> {
+        match self.inner() {
+            Inner::Trusted(trusted_stream) => trusted_stream.peer_addr(),
+            Inner::Host(host_stream) => Ok(libos_addr_of(host_stream.peer_addr()?)),
+        }
+    }
+
+    pub fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        apply_fn_on_inner!(&self.inner(), |stream| { stream.ioctl(cmd) })
+    }
+
+    fn inner(&self) -> Inner {
+        self.inner.read().unwrap().clone()
+    }
+}
+
+/// Translate a LibOS address to the host address, returning `None` if the address
+/// is invisible to the host.
+///
+/// A pathname address is visible to the host only if it is under a HostFS mount.
+/// An abstract address is never visible, so that the abstract namespace of the
+/// LibOS is not mixed up with the one of the host.
+fn host_addr_of(addr: &UnixAddr) -> Result<Option<UnixAddr>> {
+    let path = match addr {
+        UnixAddr::Pathname(path) => path,
+        UnixAddr::Abstract(_) => return Ok(None),
+        UnixAddr::Unnamed => return Ok(Some(addr.clone())),
+    };
+
+    let abs_path = {
+        let current = current!();
+        let fs = current.fs().lock().unwrap();
+        fs.convert_to_abs_path(path)
+    };
+    let host_path = match host_path_of(&abs_path) {
+        Some(host_path) => host_path,
+        None => return Ok(None),
+    };
+    let host_path = host_path
+        .into_os_string()
+        .into_string()
+        .map_err(|_| errno!(EINVAL, "the host path is not UTF8"))?;
+    // The host path, including the trailing null, must fit into sun_path
+    const MAX_PATH_LEN: usize = 108 - 1;
+    if host_path.len() > MAX_PATH_LEN {
+        return_errno!(ENAMETOOLONG, "the host path is too long");
+    }
+    Ok(Some(UnixAddr::Pathname(host_path)))
+}
+
+/// Translate a host address to the LibOS address.
+///
+/// A pathname address outside any HostFS mount has no LibOS counterpart and thus
+/// is returned as is.
+fn libos_addr_of(host_addr: UnixAddr) -> UnixAddr {
+    let libos_path = match &host_addr {
+        UnixAddr::Pathname(host_path) => libos_path_of(Path::new(host_path)),
+        UnixAddr::Abstract(_) | UnixAddr::Unnamed => None,
+    };
+    libos_path
+        .and_then(|libos_path| libos_path.into_os_string().into_string().ok())
+        .map_or(host_addr, UnixAddr::Pathname)
+}
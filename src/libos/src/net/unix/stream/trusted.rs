@@ -0,0 +1,429 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use async_io::ioctl::IoctlCmd;
+use async_io::match_ioctl_cmd_mut;
+use async_io::socket::sockopt::*;
+use async_io::socket::{RecvFlags, SendFlags, Shutdown};
+
+use super::super::addr_space::{Binding, BoundAddr, ADDR_SPACE};
+use super::super::{current_cred, Ancillary};
+use super::endpoint::{EndInfo, Endpoint};
+use crate::fs::{Events, Poller, StatusFlags};
+use crate::net::conn::{ConnQueue, Deadline, DEFAULT_BUF_SIZE};
+use crate::net::UnixAddr;
+use crate::prelude::*;
+
+/// A trusted unix stream socket, which is implemented inside the LibOS.
+#[derive(Debug)]
+pub struct TrustedStream {
+    state: RwLock<State>,
+    is_nonblocking: AtomicBool,
+    timeouts: SgxMutex<Timeouts>,
+}
+
+#[derive(Debug, Default)]
+struct Timeouts {
+    recv: Option<Duration>,
+    send: Option<Duration>,
+}
+
+#[derive(Debug)]
+enum State {
+    Init(InitStream),
+    Listen(ListenStream),
+    Connected(Arc<Endpoint>),
+}
+
+#[derive(Debug)]
+struct InitStream {
+    bound_addr: Option<BoundAddr>,
+}
+
+#[derive(Debug)]
+struct ListenStream {
+    backlog: Arc<Backlog>,
+    // Keep the address bound as long as the socket is listening
+    bound_addr: BoundAddr,
+}
+
+impl TrustedStream {
+    pub fn new() -> Self {
+        let init_stream = InitStream { bound_addr: None };
+        Self::new_with_state(State::Init(init_stream))
+    }
+
+    /// Create a pair of connected sockets.
+    pub fn new_pair() -> Result<(Self, Self)> {
+        let new_info = || EndInfo {
+            addr: UnixAddr::Unnamed,
+            cred: current_cred(),
+            bound_addr: None,
+        };
+        let (end_a, end_b) = Endpoint::new_pair(new_info(), new_info())?;
+        let socket_a = Self::new_with_state(State::Connected(Arc::new(end_a)));
+        let socket_b = Self::new_with_state(State::Connected(Arc::new(end_b)));
+        Ok((socket_a, socket_b))
+    }
+
+    fn new_with_state(state: State) -> Self {
+        Self {
+            state: RwLock::new(state),
+            is_nonblocking: AtomicBool::new(false),
+            timeouts: SgxMutex::new(Timeouts::default()),
+        }
+    }
+
+    /// Returns whether the socket is neither bound, listening nor connected.
+    pub fn is_unbound_init(&self) -> bool {
+        let state = self.state.read().unwrap();
+        match &*state {
+            State::Init(init_stream) => init_stream.bound_addr.is_none(),
+            _ => false,
+        }
+    }
+
+    pub fn bind(&self, addr: &UnixAddr) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        match &mut *state {
+            State::Init(init_stream) if init_stream.bound_addr.is_none() => {
+                let bound_addr = ADDR_SPACE.bind(addr, Binding::Stream(None))?;
+                init_stream.bound_addr = Some(bound_addr);
+                Ok(())
+            }
+            _ => return_errno!(EINVAL, "the socket is already bound"),
+        }
+    }
+
+    pub fn listen(&self, backlog: u32) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        let bound_addr = match &mut *state {
+            State::Init(init_stream) => match init_stream.bound_addr.take() {
+                Some(bound_addr) => bound_addr,
+                // Just like Linux, an unbound socket is bound automatically
+                None => ADDR_SPACE.bind(&UnixAddr::Unnamed, Binding::Stream(None))?,
+            },
+            State::Listen(listen_stream) => {
+                listen_stream.backlog.queue.set_backlog(backlog);
+                return Ok(());
+            }
+            State::Connected(_) => {
+                return_errno!(EINVAL, "the socket is already connected");
+            }
+        };
+
+        let backlog = Arc::new(Backlog::new(bound_addr.addr().clone(), backlog));
+        ADDR_SPACE.update(&bound_addr, Binding::Stream(Some(backlog.clone())));
+        *state = State::Listen(ListenStream {
+            backlog,
+            bound_addr,
+        });
+        Ok(())
+    }
+
+    pub async fn connect(&self, peer_addr: &UnixAddr) -> Result<()> {
+        let backlog = match ADDR_SPACE.lookup(peer_addr)? {
+            Binding::Stream(Some(backlog)) => backlog,
+            Binding::Stream(None) => {
+                return_errno!(ECONNREFUSED, "the peer socket is not listening");
+            }
+            Binding::Datagram(_) => {
+                return_errno!(EPROTOTYPE, "the peer socket is not a stream socket");
+            }
+        };
+
+        // Fast path
+        let res = self.try_connect(&backlog);
+        if self.is_nonblocking() || !res.has_errno(EAGAIN) {
+            return res;
+        }
+
+        // Slow path: wait for the backlog to have room, bounded by SO_SNDTIMEO
+        // just like Linux
+        let mut deadline = Deadline::new(self.send_timeout());
+        let mut poller = Poller::new();
+        loop {
+            let events = backlog.poll(Events::OUT, Some(&mut poller));
+            if events.contains(Events::OUT) {
+                let res = self.try_connect(&backlog);
+                if !res.has_errno(EAGAIN) {
+                    return res;
+                }
+            }
+            deadline.wait(&mut poller).await?;
+        }
+    }
+
+    fn try_connect(&self, backlog: &Backlog) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        let init_stream = match &mut *state {
+            State::Init(init_stream) => init_stream,
+            State::Listen(_) => {
+                return_errno!(EINVAL, "the socket is listening");
+            }
+            State::Connected(_) => {
+                return_errno!(EISCONN, "the socket is already connected");
+            }
+        };
+
+        let endpoint = backlog.push_incoming(|| {
+            let bound_addr = init_stream.bound_addr.take();
+            let addr = bound_addr
+                .as_ref()
+                .map_or(UnixAddr::Unnamed, |bound_addr| bound_addr.addr().clone());
+            EndInfo {
+                addr,
+                cred: current_cred(),
+                bound_addr,
+            }
+        })?;
+        *state = State::Connected(Arc::new(endpoint));
+        Ok(())
+    }
+
+    pub async fn accept(&self) -> Result<Self> {
+        let backlog = {
+            let state = self.state.read().unwrap();
+            match &*state {
+                State::Listen(listen_stream) => listen_stream.backlog.clone(),
+                _ => return_errno!(EINVAL, "the socket is not listening"),
+            }
+        };
+
+        // Fast path
+        let res = backlog.pop_incoming();
+        let endpoint = if self.is_nonblocking() || !res.has_errno(EAGAIN) {
+            res?
+        } else {
+            // Slow path: wait for incoming connections, bounded by SO_RCVTIMEO
+            let mut deadline = Deadline::new(self.recv_timeout());
+            let mut poller = Poller::new();
+            loop {
+                let events = backlog.poll(Events::IN, Some(&mut poller));
+                if events.contains(Events::IN) {
+                    let res = backlog.pop_incoming();
+                    if !res.has_errno(EAGAIN) {
+                        break res?;
+                    }
+                }
+                deadline.wait(&mut poller).await?;
+            }
+        };
+        Ok(Self::new_with_state(State::Connected(Arc::new(endpoint))))
+    }
+
+    pub async fn sendmsg(
+        &self,
+        bufs: &[&[u8]],
+        flags: SendFlags,
+        ancillary: Option<Ancillary>,
+    ) -> Result<usize> {
+        let endpoint = self
+            .endpoint()
+            .ok_or_else(|| errno!(ENOTCONN, "not connected"))?;
+        let timeout = self.send_timeout();
+        endpoint
+            .sendmsg(bufs, flags, ancillary, self.is_nonblocking(), timeout)
+            .await
+    }
+
+    pub async fn recvmsg(
+        &self,
+        bufs: &mut [&mut [u8]],
+        flags: RecvFlags,
+    ) -> Result<(usize, Option<Ancillary>)> {
+        let endpoint = self
+            .endpoint()
+            .ok_or_else(|| errno!(ENOTCONN, "not connected"))?;
+        let timeout = self.recv_timeout();
+        endpoint
+            .recvmsg(bufs, flags, self.is_nonblocking(), timeout)
+            .await
+    }
+
+    pub fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
+        let state = self.state.read().unwrap();
+        match &*state {
+            // Just like Linux, an unconnected stream socket is writable and hung up
+            State::Init(_) => (Events::OUT | Events::HUP) & (mask | Events::ALWAYS_POLL),
+            State::Listen(listen_stream) => listen_stream.backlog.poll(mask & Events::IN, poller),
+            State::Connected(endpoint) => endpoint.poll(mask, poller),
+        }
+    }
+
+    pub fn status_flags(&self) -> StatusFlags {
+        if self.is_nonblocking() {
+            StatusFlags::O_NONBLOCK
+        } else {
+            StatusFlags::empty()
+        }
+    }
+
+    pub fn set_status_flags(&self, new_flags: StatusFlags) -> Result<()> {
+        let is_nonblocking = new_flags.contains(StatusFlags::O_NONBLOCK);
+        self.is_nonblocking.store(is_nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        let endpoint = self
+            .endpoint()
+            .ok_or_else(|| errno!(ENOTCONN, "not connected"))?;
+        endpoint.shutdown(how)
+    }
+
+    pub fn addr(&self) -> UnixAddr {
+        let state = self.state.read().unwrap();
+        match &*state {
+            State::Init(init_stream) => init_stream
+                .bound_addr
+                .as_ref()
+                .map_or(UnixAddr::Unnamed, |bound_addr| bound_addr.addr().clone()),
+            State::Listen(listen_stream) => listen_stream.bound_addr.addr().clone(),
+            State::Connected(endpoint) => endpoint.addr().clone(),
+        }
+    }
+
+    pub fn peer_addr(&self) -> Result<UnixAddr> {
+        let endpoint = self
+            .endpoint()
+            .ok_or_else(|| errno!(ENOTCONN, "not connected"))?;
+        Ok(endpoint.peer_addr().clone())
+    }
+
+    pub fn ioctl(&self, cmd: &mut dyn IoctlCmd) -> Result<()> {
+        let state = self.state.read().unwrap();
+        match_ioctl_cmd_mut!(&mut *cmd, {
+            cmd: GetPeerCred => {
+                let peer_cred = match &*state {
+                    State::Init(_) => PeerCred::invalid(),
+                    // Just like Linux, the credentials of a listening socket are its own
+                    State::Listen(listen_stream) => *listen_stream.backlog.cred(),
+                    State::Connected(endpoint) => *endpoint.peer_cred(),
+                };
+                cmd.set_output(peer_cred);
+                Ok(())
+            },
+            cmd: GetSockError => {
+                cmd.set_output(None);
+                Ok(())
+            },
+            cmd: GetSendBufSize => {
+                let buf_size = match &*state {
+                    State::Connected(endpoint) => endpoint.buf_size(),
+                    _ => DEFAULT_BUF_SIZE,
+                };
+                cmd.set_output(buf_size);
+                Ok(())
+            },
+            cmd: GetRecvBufSize => {
+                let buf_size = match &*state {
+                    State::Connected(endpoint) => endpoint.buf_size(),
+                    _ => DEFAULT_BUF_SIZE,
+                };
+                cmd.set_output(buf_size);
+                Ok(())
+            },
+            // The buffer sizes are fixed for now. But, just like Linux, setting
+            // them always succeeds.
+            cmd: SetSendBufSize => {
+                Ok(())
+            },
+            cmd: SetRecvBufSize => {
+                Ok(())
+            },
+            cmd: SetRecvTimeout => {
+                self.timeouts.lock().unwrap().recv = *cmd.input();
+                Ok(())
+            },
+            cmd: GetRecvTimeout => {
+                cmd.set_output(self.recv_timeout());
+                Ok(())
+            },
+            cmd: SetSendTimeout => {
+                self.timeouts.lock().unwrap().send = *cmd.input();
+                Ok(())
+            },
+            cmd: GetSendTimeout => {
+                cmd.set_output(self.send_timeout());
+                Ok(())
+            },
+            _ => {
+                return_errno!(ENOPROTOOPT, "the socket option is not supported");
+            }
+        })
+    }
+
+    pub fn recv_timeout(&self) -> Option<Duration> {
+        self.timeouts.lock().unwrap().recv
+    }
+
+    pub fn send_timeout(&self) -> Option<Duration> {
+        self.timeouts.lock().unwrap().send
+    }
+
+    fn endpoint(&self) -> Option<Arc<Endpoint>> {
+        let state = self.state.read().unwrap();
+        match &*state {
+            State::Connected(endpoint) => Some(endpoint.clone()),
+            _ => None,
+        }
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        self.is_nonblocking.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for ListenStream {
+    fn drop(&mut self) {
+        // Refuse the pending and future connections
+        self.backlog.shutdown();
+    }
+}
+
+/// The queue of the incoming connections of a listening socket.
+#[derive(Debug)]
+pub struct Backlog {
+    addr: UnixAddr,
+    cred: PeerCred,
+    queue: ConnQueue<Endpoint>,
+}
+
+impl Backlog {
+    fn new(addr: UnixAddr, backlog: u32) -> Self {
+        Self {
+            addr,
+            cred: current_cred(),
+            queue: ConnQueue::new(backlog),
+        }
+    }
+
+    fn cred(&self) -> &PeerCred {
+        &self.cred
+    }
+
+    /// Push an incoming connection, returning the endpoint of the connecting side.
+    fn push_incoming(&self, connecting_info: impl FnOnce() -> EndInfo) -> Result<Endpoint> {
+        self.queue.push(|| {
+            let accepted_info = EndInfo {
+                addr: self.addr.clone(),
+                cred: self.cred,
+                bound_addr: None,
+            };
+            Endpoint::new_pair(connecting_info(), accepted_info)
+        })
+    }
+
+    fn pop_incoming(&self) -> Result<Endpoint> {
+        self.queue.pop()
+    }
+
+    fn poll(&self, mask: Events, poller: Option<&mut Poller>) -> Events {
+        self.queue.poll(mask, poller)
+    }
+
+    fn shutdown(&self) {
+        self.queue.shutdown();
+    }
+}
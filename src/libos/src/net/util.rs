@@ -0,0 +1,33 @@
+//! Utilities shared by the socket implementations.
+
+use crate::prelude::*;
+
+/// Skip the first `nbytes` bytes of a set of buffers.
+pub fn skip_bufs<'a>(bufs: &[&'a [u8]], nbytes: usize) -> Vec<&'a [u8]> {
+    let mut nbytes = nbytes;
+    let mut remain_bufs = Vec::with_capacity(bufs.len());
+    for buf in bufs {
+        if nbytes >= buf.len() {
+            nbytes -= buf.len();
+            continue;
+        }
+        remain_bufs.push(&buf[nbytes..]);
+        nbytes = 0;
+    }
+    remain_bufs
+}
+
+/// Skip the first `nbytes` bytes of a set of mutable buffers.
+pub fn skip_bufs_mut<'a>(bufs: &'a mut [&mut [u8]], nbytes: usize) -> Vec<&'a mut [u8]> {
+    let mut nbytes = nbytes;
+    let mut remain_bufs = Vec::with_capacity(bufs.len());
+    for buf in bufs.iter_mut() {
+        if nbytes >= buf.len() {
+            nbytes -= buf.len();
+            continue;
+        }
+        remain_bufs.push(&mut buf[nbytes..]);
+        nbytes = 0;
+    }
+    remain_bufs
+}
@@ -4,6 +4,7 @@ use std::sync::Weak;
 use super::do_futex::futex_wake;
 use super::process::{Process, ProcessFilter};
 use super::{table, ProcessRef, TermStatus, ThreadRef, ThreadStatus};
+use crate::fs::release_process_locks;
 use crate::prelude::*;
 use crate::signal::constants::*;
 use crate::signal::{KernelSignal, SigNum};
@@ -62,6 +63,9 @@ fn exit_thread(term_status: TermStatus) {
 fn exit_process(thread: &ThreadRef, term_status: TermStatus) {
     let process = thread.process();
 
+    // The POSIX record locks must be released before the parent is notified
+    release_process_locks(process.pid());
+
     // Deadlock note: always lock parent first, then child.
 
     // Lock the idle process since it may adopt new children.
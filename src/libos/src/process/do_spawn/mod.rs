@@ -19,6 +19,37 @@ mod exec_loader;
 mod init_stack;
 mod init_vm;
 
+/// Truncate the files that the file actions open with O_TRUNC.
+///
+/// The truncation may have to wait for the page cache of HostFS, so it is done
+/// before the spawn, which opens the files without waiting.
+pub async fn truncate_files_to_open(
+    file_actions: &[FileAction],
+    current_ref: &ThreadRef,
+) -> Result<()> {
+    for file_action in file_actions {
+        let (path, mode, oflag) = match file_action {
+            FileAction::Open {
+                path, mode, oflag, ..
+            } => (path, *mode, *oflag),
+            _ => continue,
+        };
+        // A file created exclusively is empty, and opening it here would make
+        // the open of the file action fail
+        let creation_flags = CreationFlags::from_bits_truncate(oflag);
+        if !creation_flags.contains(CreationFlags::O_TRUNC) || creation_flags.is_exclusive() {
+            continue;
+        }
+        let inode_file = current_ref
+            .fs()
+            .lock()
+            .unwrap()
+            .open_file(path.as_str(), oflag, mode)?;
+        inode_file.truncate_on_open(oflag).await?;
+    }
+    Ok(())
+}
+
 /// Spawn a new process and execute it in a new host thread.
 pub fn do_spawn(
     elf_path: &str,
@@ -203,6 +234,8 @@ fn new_process(
         let mut builder = ProcessBuilder::new()
             .vm(vm_ref)
             .exec_path(&elf_path)
+            .argv(&argv)
+            .envp(envp)
             .parent(process_ref)
             .sched(sched_ref)
             .rlimits(rlimit_ref)
@@ -270,7 +303,7 @@ fn init_files(
                             .lock()
                             .unwrap()
                             .open_file(path.as_str(), oflag, mode)?;
-                    let file_ref = FileRef::new_inode(inode_file);
+                    let file_ref = FileRef::from_opened_inode(inode_file);
                     let creation_flags = CreationFlags::from_bits_truncate(oflag);
                     cloned_file_table.put_at(fd, file_ref, creation_flags.must_close_on_spawn());
                 }
@@ -286,7 +319,9 @@ fn init_files(
                 }
             }
         }
-        // Exec: close fd with close_on_spawn
+        // Exec: close fd with close_on_spawn. Unlike `close`, no POSIX record
+        // locks are to be released, as the file table belongs to the new
+        // process, which inherits no locks.
         cloned_file_table.close_on_spawn();
         return Ok(cloned_file_table);
     }
@@ -1,3 +1,4 @@
+use std::ffi::CString;
 use std::sync::Weak;
 
 use async_rt::wait::WaiterQueue;
@@ -19,6 +20,8 @@ pub struct ProcessBuilder {
     vm: Option<ProcessVMRef>,
     // Optional fields, which have reasonable default values
     exec_path: Option<String>,
+    argv: Vec<CString>,
+    envp: Vec<CString>,
     parent: Option<ProcessRef>,
     no_parent: bool,
     host_waker: Option<HostWaker>,
@@ -32,6 +35,8 @@ impl ProcessBuilder {
             thread_builder: Some(thread_builder),
             vm: None,
             exec_path: None,
+            argv: Vec::new(),
+            envp: Vec::new(),
             parent: None,
             no_parent: false,
             host_waker: None,
@@ -48,6 +53,16 @@ impl ProcessBuilder {
         self
     }
 
+    pub fn argv(mut self, argv: &[CString]) -> Self {
+        self.argv = argv.to_vec();
+        self
+    }
+
+    pub fn envp(mut self, envp: &[CString]) -> Self {
+        self.envp = envp.to_vec();
+        self
+    }
+
     pub fn parent(mut self, parent: ProcessRef) -> Self {
         self.parent = Some(parent);
         self
@@ -103,6 +118,8 @@ impl ProcessBuilder {
         // Build a new process
         let new_process = {
             let exec_path = self.exec_path.take().unwrap_or_default();
+            let argv = std::mem::take(&mut self.argv);
+            let envp = std::mem::take(&mut self.envp);
             let parent = self.parent.take().map(|parent| RwLock::new(parent));
             let host_waker = self.host_waker.take();
             let inner = SgxMutex::new(ProcessInner::new());
@@ -114,6 +131,8 @@ impl ProcessBuilder {
             Arc::new(Process {
                 pid,
                 exec_path,
+                argv,
+                envp,
                 host_waker,
                 parent,
                 inner,
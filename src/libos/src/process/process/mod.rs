@@ -1,3 +1,4 @@
+use std::ffi::CString;
 use std::fmt;
 use std::sync::Weak;
 
@@ -17,6 +18,8 @@ pub struct Process {
     // Immutable info
     pid: pid_t,
     exec_path: String,
+    argv: Vec<CString>,
+    envp: Vec<CString>,
     host_waker: Option<HostWaker>,
     // Mutable info
     parent: Option<RwLock<ProcessRef>>,
@@ -105,6 +108,16 @@ impl Process {
         &self.exec_path
     }
 
+    /// Get the arguments that the process was spawned with.
+    pub fn argv(&self) -> &[CString] {
+        &self.argv
+    }
+
+    /// Get the environment variables that the process was spawned with.
+    pub fn envp(&self) -> &[CString] {
+        &self.envp
+    }
+
     /// Get the host wake pointer.
     pub fn host_waker(&self) -> &Option<HostWaker> {
         &self.host_waker
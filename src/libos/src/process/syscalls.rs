@@ -29,6 +29,7 @@ pub async fn do_spawn_for_musl(
         path, argv, envp, file_actions
     );
 
+    super::do_spawn::truncate_files_to_open(&file_actions, &current).await?;
     let child_pid = super::do_spawn::do_spawn(&path, &argv, &envp, &file_actions, &current)?;
 
     unsafe { *child_pid_ptr = child_pid };
@@ -105,6 +106,7 @@ pub async fn do_spawn_for_glibc(
         path, argv, envp, file_actions
     );
 
+    super::do_spawn::truncate_files_to_open(&file_actions, &current).await?;
     let child_pid = super::do_spawn::do_spawn(&path, &argv, &envp, &file_actions, &current)?;
 
     unsafe { *child_pid_ptr = child_pid };
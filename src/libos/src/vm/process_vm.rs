@@ -381,6 +381,11 @@ impl ProcessVM {
         mmap_manager.msync_by_file(sync_file);
     }
 
+    /// Returns a copy of the areas that are mapped by mmap, in ascending order.
+    pub fn get_mmap_areas(&self) -> Vec<VMArea> {
+        self.mmap_manager.lock().unwrap().vmas().cloned().collect()
+    }
+
     // Return: a copy of the found region
     pub fn find_mmap_region(&self, addr: usize) -> Result<VMRange> {
         self.mmap_manager
@@ -37,9 +37,11 @@ impl VMInitializer {
                 }
             }
             VMInitializer::LoadFromFile { file, offset } => {
+                // Read through the inode, which does not wait on the page cache
                 let len = file
                     .as_inode_file()
                     .unwrap()
+                    .inode()
                     .read_at(*offset, buf)
                     .map_err(|_| errno!(EIO, "failed to init memory from file"))?;
                 for b in &mut buf[len..] {
@@ -301,6 +303,12 @@ impl VMManager {
         &self.range
     }
 
+    /// Returns the mapped areas, excluding the sentries, in ascending order.
+    pub fn vmas(&self) -> impl Iterator<Item = &VMArea> {
+        let num_vmas = self.vmas.len().saturating_sub(2);
+        self.vmas.iter().skip(1).take(num_vmas)
+    }
+
     pub fn mmap(&mut self, mut options: VMMapOptions) -> Result<usize> {
         // TODO: respect options.align when mmap
         let addr = *options.addr();
@@ -629,7 +637,31 @@ impl VMManager {
         if !cond_fn(file) {
             return;
         }
-        inode_file.write_at(*file_offset, unsafe { vma.as_slice() });
+        let buf = unsafe { vma.as_slice() };
+        let written_len = inode_file.inode().write_at(*file_offset, buf).unwrap_or(0);
+        if written_len == buf.len() {
+            return;
+        }
+        // The rest of a host file has to wait for the page cache, which cannot
+        // be done here. So write back a copy of it asynchronously.
+        let file = file.clone();
+        let mut offset = *file_offset + written_len;
+        let mut rest = buf[written_len..].to_vec();
+        async_rt::task::spawn(async move {
+            while !rest.is_empty() {
+                match file.write_at(offset, &rest).await {
+                    Ok(0) => break,
+                    Ok(len) => {
+                        offset += len;
+                        rest.drain(..len);
+                    }
+                    Err(e) => {
+                        error!("failed to write back the memory-mapped file: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
     }
 
     pub fn find_mmap_region(&self, addr: usize) -> Result<&VMRange> {
@@ -1,10 +1,27 @@
 #ifndef __OCCLUM_EDL_TYPES__
 #define __OCCLUM_EDL_TYPES__
 
+#include <stdint.h>             // import uint32_t
 #include <time.h>               // import struct timespec
 #include <sys/select.h>         // import fd_set
 #include <sys/time.h>           // import struct timeval
 #include <sys/uio.h>            // import struct iovec
 #include <occlum_pal_api.h>     // import occlum_stdio_fds
 
+/*
+ * An address of a network interface, or the interface itself if the family is
+ * AF_PACKET.
+ */
+#define OCCLUM_IFNAMSIZ 16
+struct occlum_ifaddr {
+    char        name[OCCLUM_IFNAMSIZ];
+    uint32_t    index;
+    uint32_t    flags;          /* IFF_* */
+    uint32_t    mtu;
+    uint8_t     hw_addr[6];     /* only valid if the family is AF_PACKET */
+    uint16_t    family;         /* AF_PACKET, AF_INET or AF_INET6 */
+    uint8_t     addr[16];       /* only valid if the family is AF_INET or AF_INET6 */
+    uint32_t    prefix_len;
+};
+
 #endif /* __OCCLUM_EDL_TYPES__ */
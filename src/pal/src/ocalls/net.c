@@ -5,6 +5,13 @@
 #include <errno.h>
 #include <stdio.h>
 #include <stddef.h>
+#include <string.h>
+#include <unistd.h>
+#include <ifaddrs.h>
+#include <net/if.h>
+#include <netinet/in.h>
+#include <netpacket/packet.h>
+#include <sys/ioctl.h>
 #include "ocalls.h"
 
 ssize_t occlum_ocall_sendmsg(int sockfd,
@@ -88,3 +95,85 @@ int occlum_ocall_poll(struct pollfd *fds,
     errno = saved_errno;
     return ret;
 }
+
+static uint32_t get_prefix_len(const struct sockaddr *netmask) {
+    const uint8_t *bytes;
+    size_t len;
+    if (netmask == NULL) {
+        return 0;
+    }
+    if (netmask->sa_family == AF_INET) {
+        bytes = (const uint8_t *) &((const struct sockaddr_in *) netmask)->sin_addr;
+        len = sizeof(struct in_addr);
+    } else {
+        bytes = (const uint8_t *) &((const struct sockaddr_in6 *) netmask)->sin6_addr;
+        len = sizeof(struct in6_addr);
+    }
+
+    uint32_t prefix_len = 0;
+    for (size_t i = 0; i < len; i++) {
+        prefix_len += __builtin_popcount(bytes[i]);
+    }
+    return prefix_len;
+}
+
+static uint32_t get_mtu(int sockfd, const char *name) {
+    struct ifreq ifr;
+    memset(&ifr, 0, sizeof(ifr));
+    strncpy(ifr.ifr_name, name, IFNAMSIZ - 1);
+    if (sockfd < 0 || ioctl(sockfd, SIOCGIFMTU, &ifr) < 0) {
+        return 0;
+    }
+    return ifr.ifr_mtu;
+}
+
+int occlum_ocall_get_ifaddrs(struct occlum_ifaddr *ifaddrs, size_t max_count) {
+    struct ifaddrs *host_ifaddrs;
+    if (getifaddrs(&host_ifaddrs) < 0) {
+        return -1;
+    }
+    // The socket is only used to get the MTUs
+    int sockfd = socket(AF_INET, SOCK_DGRAM, 0);
+
+    size_t count = 0;
+    for (struct ifaddrs *ifa = host_ifaddrs; ifa != NULL; ifa = ifa->ifa_next) {
+        if (ifa->ifa_addr == NULL) {
+            continue;
+        }
+        int family = ifa->ifa_addr->sa_family;
+        if (family != AF_PACKET && family != AF_INET && family != AF_INET6) {
+            continue;
+        }
+        if (count++ >= max_count) {
+            continue;
+        }
+
+        struct occlum_ifaddr *ifaddr = &ifaddrs[count - 1];
+        memset(ifaddr, 0, sizeof(*ifaddr));
+        strncpy(ifaddr->name, ifa->ifa_name, OCCLUM_IFNAMSIZ - 1);
+        ifaddr->index = if_nametoindex(ifa->ifa_name);
+        ifaddr->flags = ifa->ifa_flags;
+        ifaddr->mtu = get_mtu(sockfd, ifa->ifa_name);
+        ifaddr->family = family;
+        if (family == AF_PACKET) {
+            const struct sockaddr_ll *ll_addr = (const struct sockaddr_ll *) ifa->ifa_addr;
+            if (ll_addr->sll_halen == sizeof(ifaddr->hw_addr)) {
+                memcpy(ifaddr->hw_addr, ll_addr->sll_addr, sizeof(ifaddr->hw_addr));
+            }
+        } else if (family == AF_INET) {
+            const struct sockaddr_in *in_addr = (const struct sockaddr_in *) ifa->ifa_addr;
+            memcpy(ifaddr->addr, &in_addr->sin_addr, sizeof(struct in_addr));
+            ifaddr->prefix_len = get_prefix_len(ifa->ifa_netmask);
+        } else {
+            const struct sockaddr_in6 *in6_addr = (const struct sockaddr_in6 *) ifa->ifa_addr;
+            memcpy(ifaddr->addr, &in6_addr->sin6_addr, sizeof(struct in6_addr));
+            ifaddr->prefix_len = get_prefix_len(ifa->ifa_netmask);
+        }
+    }
+
+    if (sockfd >= 0) {
+        close(sockfd);
+    }
+    freeifaddrs(host_ifaddrs);
+    return count;
+}
@@ -1,9 +1,12 @@
+#include <sys/mman.h>
 #include <sys/stat.h>
 #include <sys/types.h>
 #include <fcntl.h>
+#include <limits.h>
 #include <poll.h>
 #include <unistd.h>
 #include <stdio.h>
+#include <string.h>
 #include "test.h"
 
 // ============================================================================
@@ -115,6 +118,124 @@ int test_dev_arandom() {
     return 0;
 }
 
+// ============================================================================
+// Test cases for /dev/shm, /dev/fd and /dev/tty
+// ============================================================================
+
+int test_dev_shm() {
+    const char *shm_name = "/test_dev_shm";
+    const char *msg = "Hello from /dev/shm";
+    char buf[64] = {0};
+
+    int fd = shm_open(shm_name, O_RDWR | O_CREAT | O_EXCL, 0666);
+    if (fd < 0) {
+        THROW_ERROR("failed to shm_open");
+    }
+    if (ftruncate(fd, getpagesize()) < 0) {
+        close(fd);
+        THROW_ERROR("failed to ftruncate the shared memory object");
+    }
+    char *addr = mmap(NULL, getpagesize(), PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    close(fd);
+    if (addr == MAP_FAILED) {
+        THROW_ERROR("failed to mmap the shared memory object");
+    }
+    strcpy(addr, msg);
+    munmap(addr, getpagesize());
+
+    // The shared memory object is a file in /dev/shm
+    if ((fd = open("/dev/shm/test_dev_shm", O_RDONLY)) < 0) {
+        THROW_ERROR("failed to open the shared memory object in /dev/shm");
+    }
+    if (read(fd, buf, sizeof(buf)) != sizeof(buf) || strcmp(buf, msg) != 0) {
+        close(fd);
+        THROW_ERROR("failed to read the shared memory object");
+    }
+    close(fd);
+
+    if (shm_unlink(shm_name) < 0) {
+        THROW_ERROR("failed to shm_unlink");
+    }
+    if (access("/dev/shm/test_dev_shm", F_OK) == 0) {
+        THROW_ERROR("the shared memory object is not removed");
+    }
+    return 0;
+}
+
+int test_dev_fd() {
+    char link_buf[PATH_MAX] = {0};
+    const char *expected_target = "/proc/self/fd";
+    const char *links[] = {"/dev/fd", "/dev/stdin", "/dev/stdout", "/dev/stderr"};
+    struct stat link_stats[ARRAY_SIZE(links)];
+
+    // Each link is a different inode
+    for (int i = 0; i < ARRAY_SIZE(links); i++) {
+        if (lstat(links[i], &link_stats[i]) < 0) {
+            THROW_ERROR("failed to lstat %s", links[i]);
+        }
+        for (int j = 0; j < i; j++) {
+            if (link_stats[i].st_ino == link_stats[j].st_ino) {
+                THROW_ERROR("%s and %s have the same inode number", links[i], links[j]);
+            }
+        }
+    }
+
+    int len = readlink("/dev/fd", link_buf, sizeof(link_buf));
+    if (len != strlen(expected_target) || strncmp(link_buf, expected_target, len) != 0) {
+        THROW_ERROR("/dev/fd does not link to /proc/self/fd");
+    }
+
+    // Open the same file through the fd in /dev/fd
+    const char *file_path = "/root/test_dev_fd.txt";
+    int fd = open(file_path, O_WRONLY | O_CREAT | O_TRUNC, 0666);
+    if (fd < 0) {
+        THROW_ERROR("failed to open a file to test");
+    }
+    char fd_path[32] = {0};
+    snprintf(fd_path, sizeof(fd_path), "/dev/fd/%d", fd);
+    int new_fd = open(fd_path, O_RDONLY);
+    if (new_fd < 0) {
+        close(fd);
+        THROW_ERROR("failed to open the fd in /dev/fd");
+    }
+    struct stat stat_buf, new_stat_buf;
+    if (fstat(fd, &stat_buf) < 0 || fstat(new_fd, &new_stat_buf) < 0) {
+        close(fd);
+        close(new_fd);
+        THROW_ERROR("failed to fstat the files");
+    }
+    close(fd);
+    close(new_fd);
+    if (stat_buf.st_ino != new_stat_buf.st_ino || stat_buf.st_dev != new_stat_buf.st_dev) {
+        THROW_ERROR("the fd in /dev/fd is not the same file");
+    }
+    if (unlink(file_path) < 0) {
+        THROW_ERROR("failed to unlink the file");
+    }
+    return 0;
+}
+
+int test_dev_tty() {
+    const char *msg = "Hello from /dev/tty\n";
+    struct stat stat_buf;
+
+    int fd = open("/dev/tty", O_WRONLY);
+    if (fd < 0) {
+        THROW_ERROR("failed to open /dev/tty");
+    }
+    if (fstat(fd, &stat_buf) < 0 || (stat_buf.st_mode & S_IFMT) != S_IFCHR) {
+        close(fd);
+        THROW_ERROR("/dev/tty is not a character device");
+    }
+    // The terminal of the LibOS is that of the host
+    if (write(fd, msg, strlen(msg)) != strlen(msg)) {
+        close(fd);
+        THROW_ERROR("failed to write to /dev/tty");
+    }
+    close(fd);
+    return 0;
+}
+
 // ============================================================================
 // Test suite
 // ============================================================================
@@ -127,6 +248,9 @@ static test_case_t test_cases[] = {
     TEST_CASE(test_dev_urandom_fstat),
     TEST_CASE(test_dev_urandom_poll),
     TEST_CASE(test_dev_arandom),
+    TEST_CASE(test_dev_shm),
+    TEST_CASE(test_dev_fd),
+    TEST_CASE(test_dev_tty),
 };
 
 int main() {
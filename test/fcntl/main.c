@@ -1,6 +1,10 @@
+#define _GNU_SOURCE
 #define _LARGEFILE64_SOURCE
 #include <errno.h>
 #include <fcntl.h>
+#include <pthread.h>
+#include <unistd.h>
+#include <sys/file.h>
 #include "test_fs.h"
 
 // ============================================================================
@@ -12,6 +16,8 @@
 #define TMPFILE_FLAG O_TMPFILE
 #endif
 
+#define TEST_FILE_PATH "/root/test_fcntl_file.txt"
+
 #define CREATION_FLAGS_MASK (O_CLOEXEC | O_CREAT| O_DIRECTORY | O_EXCL |    \
                              O_NOCTTY | O_NOFOLLOW | TMPFILE_FLAG | O_TRUNC)
 
@@ -74,6 +80,121 @@ static int __fcntl_getlk_and_setlk(int fd, int open_flags) {
     return 0;
 }
 
+static int __fcntl_ofd_setlk(int fd, int open_flags) {
+    int other_fd, ret;
+    struct flock fl = { F_WRLCK, SEEK_SET, 0, 10, 0 };
+
+    ret = fcntl(fd, F_OFD_SETLK, &fl);
+    if (ret < 0) {
+        THROW_ERROR("failed to call ofd setlk");
+    }
+
+    // The OFD locks of another open file description conflict with the lock
+    other_fd = open(TEST_FILE_PATH, O_RDWR);
+    if (other_fd < 0) {
+        THROW_ERROR("failed to open the file again");
+    }
+    fl.l_start = 5;
+    ret = fcntl(other_fd, F_OFD_SETLK, &fl);
+    if (ret == 0 || errno != EAGAIN) {
+        THROW_ERROR("the conflicting lock should fail with EAGAIN");
+    }
+    ret = fcntl(other_fd, F_OFD_GETLK, &fl);
+    if (ret < 0) {
+        THROW_ERROR("failed to call ofd getlk");
+    }
+    if (fl.l_type != F_WRLCK || fl.l_start != 0 || fl.l_len != 10 || fl.l_pid != -1) {
+        THROW_ERROR("failed to get the conflicting lock");
+    }
+
+    // A non-overlapping range can be locked
+    fl.l_type = F_WRLCK;
+    fl.l_start = 10;
+    ret = fcntl(other_fd, F_OFD_SETLK, &fl);
+    if (ret < 0) {
+        THROW_ERROR("failed to lock a non-overlapping range");
+    }
+    close(other_fd);
+    return 0;
+}
+
+static void *__ofd_setlkw_read(void *arg) {
+    int fd = *(int *)arg;
+    struct flock fl = { F_RDLCK, SEEK_SET, 0, 10, 0 };
+
+    if (fcntl(fd, F_OFD_SETLKW, &fl) < 0) {
+        return (void *)-1;
+    }
+    return NULL;
+}
+
+static int __fcntl_ofd_downgrade_wakes_waiters(int fd, int open_flags) {
+    int other_fd, ret;
+    pthread_t thread;
+    void *thread_ret;
+    struct flock fl = { F_WRLCK, SEEK_SET, 0, 10, 0 };
+
+    ret = fcntl(fd, F_OFD_SETLK, &fl);
+    if (ret < 0) {
+        THROW_ERROR("failed to call ofd setlk");
+    }
+    other_fd = open(TEST_FILE_PATH, O_RDWR);
+    if (other_fd < 0) {
+        THROW_ERROR("failed to open the file again");
+    }
+    // The reader waits for the exclusive lock to go
+    if (pthread_create(&thread, NULL, __ofd_setlkw_read, &other_fd) != 0) {
+        close(other_fd);
+        THROW_ERROR("failed to create the thread");
+    }
+    usleep(100 * 1000);
+
+    // Downgrading the lock to a shared one lets the reader in
+    fl.l_type = F_RDLCK;
+    ret = fcntl(fd, F_OFD_SETLK, &fl);
+    if (ret < 0) {
+        THROW_ERROR("failed to downgrade the lock");
+    }
+    if (pthread_join(thread, &thread_ret) != 0 || thread_ret != NULL) {
+        THROW_ERROR("the reader failed to get the lock");
+    }
+    close(other_fd);
+    return 0;
+}
+
+static int __flock(int fd, int open_flags) {
+    int other_fd, ret;
+
+    ret = flock(fd, LOCK_SH);
+    if (ret < 0) {
+        THROW_ERROR("failed to place a shared lock");
+    }
+    other_fd = open(TEST_FILE_PATH, O_RDONLY);
+    if (other_fd < 0) {
+        THROW_ERROR("failed to open the file again");
+    }
+    ret = flock(other_fd, LOCK_SH | LOCK_NB);
+    if (ret < 0) {
+        THROW_ERROR("failed to place another shared lock");
+    }
+    ret = flock(fd, LOCK_EX | LOCK_NB);
+    if (ret == 0 || errno != EWOULDBLOCK) {
+        THROW_ERROR("the exclusive lock should fail with EWOULDBLOCK");
+    }
+
+    // The lock of the file is released on close
+    close(other_fd);
+    ret = flock(fd, LOCK_EX | LOCK_NB);
+    if (ret < 0) {
+        THROW_ERROR("failed to place an exclusive lock");
+    }
+    ret = flock(fd, LOCK_UN);
+    if (ret < 0) {
+        THROW_ERROR("failed to remove the lock");
+    }
+    return 0;
+}
+
 static int __fcntl_dupfd(int fd, int open_flags) {
     if (fcntl(fd, F_DUPFD, 0) < 0) {
         THROW_ERROR("failed to duplicate the fd");
@@ -84,7 +205,7 @@ static int __fcntl_dupfd(int fd, int open_flags) {
 typedef int(*test_fcntl_func_t)(int fd, int open_flags);
 
 static int test_fcntl_framework(test_fcntl_func_t fn) {
-    const char *file_path = "/root/test_fcntl_file.txt";
+    const char *file_path = TEST_FILE_PATH;
     int open_flags = O_RDWR | O_CREAT | O_TRUNC | O_APPEND;
     int mode = 00666;
     int fd, ret;
@@ -117,6 +238,18 @@ static int test_getlk_and_setlk() {
     return test_fcntl_framework(__fcntl_getlk_and_setlk);
 }
 
+static int test_ofd_setlk() {
+    return test_fcntl_framework(__fcntl_ofd_setlk);
+}
+
+static int test_ofd_downgrade_wakes_waiters() {
+    return test_fcntl_framework(__fcntl_ofd_downgrade_wakes_waiters);
+}
+
+static int test_flock() {
+    return test_fcntl_framework(__flock);
+}
+
 static int test_fcntl_dupfd() {
     return test_fcntl_framework(__fcntl_dupfd);
 }
@@ -129,6 +262,9 @@ static test_case_t test_cases[] = {
     TEST_CASE(test_fcntl_getfl),
     TEST_CASE(test_fcntl_setfl),
     TEST_CASE(test_getlk_and_setlk),
+    TEST_CASE(test_ofd_setlk),
+    TEST_CASE(test_ofd_downgrade_wakes_waiters),
+    TEST_CASE(test_flock),
     TEST_CASE(test_fcntl_dupfd),
 };
 
@@ -0,0 +1,265 @@
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/types.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <string.h>
+#include <unistd.h>
+#include "test.h"
+
+// The mount policy of the test only allows the targets under /mnt
+#define MNT_DIR         "/mnt"
+#define TARGET_DIR      MNT_DIR "/test_mount_target"
+#define SOURCE_DIR      MNT_DIR "/test_mount_source"
+#define LINK_TO_ROOT    MNT_DIR "/test_mount_link_to_root"
+#define LINK_TO_MNT     MNT_DIR "/test_mount_link_to_mnt"
+
+// ============================================================================
+// Helper functions
+// ============================================================================
+
+static int create_dir(const char *path) {
+    if (mkdir(path, 00775) < 0 && errno != EEXIST) {
+        THROW_ERROR("failed to mkdir %s", path);
+    }
+    return 0;
+}
+
+static int create_file(const char *path) {
+    int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 00666);
+    if (fd < 0) {
+        return -1;
+    }
+    close(fd);
+    return 0;
+}
+
+static int check_errno(int ret, int expected_errno) {
+    if (ret >= 0) {
+        THROW_ERROR("the operation is expected to fail");
+    }
+    if (errno != expected_errno) {
+        THROW_ERROR("the errno is %d, but %d is expected", errno, expected_errno);
+    }
+    return 0;
+}
+
+static int setup() {
+    if (create_dir(MNT_DIR) < 0 || create_dir(TARGET_DIR) < 0 || create_dir(SOURCE_DIR) < 0) {
+        THROW_ERROR("failed to create the dirs to test");
+    }
+    if (symlink("/root", LINK_TO_ROOT) < 0 && errno != EEXIST) {
+        THROW_ERROR("failed to create the symlink to /root");
+    }
+    if (symlink(MNT_DIR, LINK_TO_MNT) < 0 && errno != EEXIST) {
+        THROW_ERROR("failed to create the symlink to " MNT_DIR);
+    }
+    return 0;
+}
+
+// ============================================================================
+// Test cases for mount and umount2
+// ============================================================================
+
+static int test_mount_and_umount() {
+    const char *file_path = TARGET_DIR "/test_mount_file";
+
+    if (mount("none", TARGET_DIR, "ramfs", 0, NULL) < 0) {
+        THROW_ERROR("failed to mount ramfs");
+    }
+    if (create_file(file_path) < 0) {
+        THROW_ERROR("failed to create a file in the mounted ramfs");
+    }
+    if (umount2(TARGET_DIR, 0) < 0) {
+        THROW_ERROR("failed to umount ramfs");
+    }
+    // The file goes away with the ramfs
+    if (check_errno(access(file_path, F_OK), ENOENT) < 0) {
+        THROW_ERROR("the file is still there after umount");
+    }
+    return 0;
+}
+
+static int test_mount_out_of_policy() {
+    if (check_errno(mount("none", "/root", "ramfs", 0, NULL), EPERM) < 0) {
+        THROW_ERROR("the target out of the policy is mounted on");
+    }
+    if (check_errno(mount("none", "/root/../mnt/../root", "ramfs", 0, NULL), EPERM) < 0) {
+        THROW_ERROR("the target out of the policy is mounted on");
+    }
+    return 0;
+}
+
+static int test_mount_through_symlink() {
+    // A final symlink is not followed
+    if (check_errno(mount("none", LINK_TO_ROOT, "ramfs", 0, NULL), ENOTDIR) < 0) {
+        THROW_ERROR("the symlink is followed");
+    }
+    // The policy applies to the canonical path of the target
+    if (check_errno(mount("none", LINK_TO_ROOT "/", "ramfs", 0, NULL), EPERM) < 0) {
+        THROW_ERROR("the symlink redirects the mount out of the policy");
+    }
+
+    // The mount is recorded with the canonical path of the target
+    if (mount("none", LINK_TO_MNT "/test_mount_target", "ramfs", 0, NULL) < 0) {
+        THROW_ERROR("failed to mount ramfs through a symlink");
+    }
+    if (umount2(TARGET_DIR, 0) < 0) {
+        THROW_ERROR("failed to umount ramfs by the canonical path");
+    }
+    return 0;
+}
+
+static int test_bind_mount() {
+    const char *source_file = SOURCE_DIR "/test_bind_mount_file";
+    const char *target_file = TARGET_DIR "/test_bind_mount_file";
+
+    if (create_file(source_file) < 0) {
+        THROW_ERROR("failed to create a file in the source");
+    }
+    if (mount(SOURCE_DIR, TARGET_DIR, NULL, MS_BIND, NULL) < 0) {
+        THROW_ERROR("failed to bind mount");
+    }
+    if (access(target_file, F_OK) < 0) {
+        THROW_ERROR("the file is not found in the target");
+    }
+    if (umount2(TARGET_DIR, 0) < 0) {
+        THROW_ERROR("failed to umount the bind mount");
+    }
+    if (unlink(source_file) < 0) {
+        THROW_ERROR("failed to unlink the file in the source");
+    }
+
+    // The policy applies to the source of a bind mount as well
+    if (check_errno(mount("/bin", TARGET_DIR, NULL, MS_BIND, NULL), EPERM) < 0) {
+        THROW_ERROR("the source out of the policy is bind mounted");
+    }
+    if (check_errno(mount(LINK_TO_ROOT, TARGET_DIR, NULL, MS_BIND, NULL), EPERM) < 0) {
+        THROW_ERROR("the symlink redirects the source out of the policy");
+    }
+    return 0;
+}
+
+static int test_read_only_mount() {
+    const char *file_path = TARGET_DIR "/test_read_only_file";
+    const char *new_file_path = TARGET_DIR "/test_read_only_new_file";
+    const char *dir_path = TARGET_DIR "/test_read_only_dir";
+
+    if (mount("none", TARGET_DIR, "ramfs", MS_RDONLY, NULL) < 0) {
+        THROW_ERROR("failed to mount read-only ramfs");
+    }
+    if (check_errno(create_file(file_path), EROFS) < 0) {
+        THROW_ERROR("a file is created on the read-only mount");
+    }
+    if (check_errno(mkdir(dir_path, 00775), EROFS) < 0) {
+        THROW_ERROR("a dir is created on the read-only mount");
+    }
+
+    // Create the files to test after remounting it read-write
+    if (mount(NULL, TARGET_DIR, NULL, MS_REMOUNT, NULL) < 0) {
+        THROW_ERROR("failed to remount read-write");
+    }
+    if (create_file(file_path) < 0 || mkdir(dir_path, 00775) < 0) {
+        THROW_ERROR("failed to create the files after remounting read-write");
+    }
+    if (mount(NULL, TARGET_DIR, NULL, MS_REMOUNT | MS_RDONLY, NULL) < 0) {
+        THROW_ERROR("failed to remount read-only");
+    }
+
+    if (check_errno(open(file_path, O_WRONLY), EROFS) < 0 ||
+            check_errno(unlink(file_path), EROFS) < 0 ||
+            check_errno(rmdir(dir_path), EROFS) < 0 ||
+            check_errno(rename(file_path, new_file_path), EROFS) < 0 ||
+            check_errno(link(file_path, new_file_path), EROFS) < 0 ||
+            check_errno(symlink(file_path, new_file_path), EROFS) < 0 ||
+            check_errno(truncate(file_path, 0), EROFS) < 0 ||
+            check_errno(chmod(file_path, 00644), EROFS) < 0 ||
+            check_errno(chown(file_path, 0, 0), EROFS) < 0 ||
+            check_errno(access(file_path, W_OK), EROFS) < 0) {
+        THROW_ERROR("the read-only mount is modified");
+    }
+    int fd = open(file_path, O_RDONLY);
+    if (fd < 0) {
+        THROW_ERROR("failed to open a file on the read-only mount for reading");
+    }
+    close(fd);
+
+    if (umount2(TARGET_DIR, 0) < 0) {
+        THROW_ERROR("failed to umount the read-only ramfs");
+    }
+    return 0;
+}
+
+static int test_remount_read_only_with_writers() {
+    const char *file_path = TARGET_DIR "/test_remount_file";
+
+    if (mount("none", TARGET_DIR, "ramfs", 0, NULL) < 0) {
+        THROW_ERROR("failed to mount ramfs");
+    }
+    int fd = open(file_path, O_WRONLY | O_CREAT, 00666);
+    if (fd < 0) {
+        THROW_ERROR("failed to create a file in the mounted ramfs");
+    }
+    // The file system has a file open for writing
+    if (check_errno(mount(NULL, TARGET_DIR, NULL, MS_REMOUNT | MS_RDONLY, NULL), EBUSY) < 0) {
+        close(fd);
+        THROW_ERROR("the file system is remounted read-only with a writer");
+    }
+    close(fd);
+    if (mount(NULL, TARGET_DIR, NULL, MS_REMOUNT | MS_RDONLY, NULL) < 0) {
+        THROW_ERROR("failed to remount read-only");
+    }
+
+    if (umount2(TARGET_DIR, 0) < 0) {
+        THROW_ERROR("failed to umount ramfs");
+    }
+    return 0;
+}
+
+static int test_umount_busy() {
+    const char *file_path = TARGET_DIR "/test_umount_busy_file";
+
+    if (mount("none", TARGET_DIR, "ramfs", 0, NULL) < 0) {
+        THROW_ERROR("failed to mount ramfs");
+    }
+    int fd = open(file_path, O_RDWR | O_CREAT, 00666);
+    if (fd < 0) {
+        THROW_ERROR("failed to create a file in the mounted ramfs");
+    }
+    if (check_errno(umount2(TARGET_DIR, 0), EBUSY) < 0) {
+        close(fd);
+        THROW_ERROR("the file system is unmounted with open files");
+    }
+    // A lazy unmount detaches it anyway, while the open file keeps working
+    if (umount2(TARGET_DIR, MNT_DETACH) < 0) {
+        close(fd);
+        THROW_ERROR("failed to umount lazily");
+    }
+    if (write(fd, "hello", 5) != 5) {
+        close(fd);
+        THROW_ERROR("failed to write the open file after umount");
+    }
+    close(fd);
+    return 0;
+}
+
+// ============================================================================
+// Test suite main
+// ============================================================================
+
+static test_case_t test_cases[] = {
+    TEST_CASE(test_mount_and_umount),
+    TEST_CASE(test_mount_out_of_policy),
+    TEST_CASE(test_mount_through_symlink),
+    TEST_CASE(test_bind_mount),
+    TEST_CASE(test_read_only_mount),
+    TEST_CASE(test_remount_read_only_with_writers),
+    TEST_CASE(test_umount_busy),
+};
+
+int main(int argc, const char *argv[]) {
+    if (setup() < 0) {
+        return -1;
+    }
+    return test_suite_run(test_cases, ARRAY_SIZE(test_cases));
+}
@@ -1,5 +1,6 @@
 #include <fcntl.h>
 #include <errno.h>
+#include <sys/stat.h>
 #include "test_fs.h"
 
 // ============================================================================
@@ -138,6 +139,36 @@ static int test_openat_with_dirfd() {
     return test_open_framework(__test_openat_with_dirfd);
 }
 
+static int test_open_with_trunc() {
+    const char *file_path = "/root/test_filesystem_open_trunc.txt";
+    const char *msg = "data to be truncated";
+    struct stat stat_buf;
+
+    int fd = open(file_path, O_WRONLY | O_CREAT | O_TRUNC, 00666);
+    if (fd < 0) {
+        THROW_ERROR("failed to create a file");
+    }
+    if (write(fd, msg, strlen(msg)) != strlen(msg)) {
+        close(fd);
+        THROW_ERROR("failed to write the file");
+    }
+    close(fd);
+
+    fd = open(file_path, O_WRONLY | O_TRUNC);
+    if (fd < 0) {
+        THROW_ERROR("failed to open the file with O_TRUNC");
+    }
+    if (fstat(fd, &stat_buf) < 0) {
+        close(fd);
+        THROW_ERROR("failed to stat the file");
+    }
+    close(fd);
+    if (stat_buf.st_size != 0) {
+        THROW_ERROR("the file is not truncated");
+    }
+    return remove_file(file_path);
+}
+
 // ============================================================================
 // Test suite main
 // ============================================================================
@@ -148,6 +179,7 @@ static test_case_t test_cases[] = {
     TEST_CASE(test_open_dir_with_write_flags),
     TEST_CASE(test_openat_with_abs_path),
     TEST_CASE(test_openat_with_dirfd),
+    TEST_CASE(test_open_with_trunc),
 };
 
 int main(int argc, const char *argv[]) {
@@ -7,6 +7,7 @@
 #include <sys/time.h>
 #include <fcntl.h>
 #include <poll.h>
+#include <signal.h>
 #include <unistd.h>
 #include <stdlib.h>
 #include <stdio.h>
@@ -311,6 +312,39 @@ int test_select_read_write() {
     return 0;
 }
 
+static volatile int sigpipe_count = 0;
+
+static void handle_sigpipe(int signum) {
+    sigpipe_count++;
+}
+
+int test_write_to_broken_pipe() {
+    int pipe_fds[2];
+    struct sigaction new_action = {0}, old_action;
+    new_action.sa_handler = handle_sigpipe;
+    if (sigaction(SIGPIPE, &new_action, &old_action) < 0) {
+        THROW_ERROR("failed to set the SIGPIPE handler");
+    }
+    if (pipe(pipe_fds) < 0) {
+        sigaction(SIGPIPE, &old_action, NULL);
+        THROW_ERROR("failed to create a pipe");
+    }
+    close(pipe_fds[0]);
+
+    sigpipe_count = 0;
+    int ret = write(pipe_fds[1], "a", 1);
+    int saved_errno = errno;
+    close(pipe_fds[1]);
+    sigaction(SIGPIPE, &old_action, NULL);
+    if (ret >= 0 || saved_errno != EPIPE) {
+        THROW_ERROR("writing to a broken pipe should fail with EPIPE");
+    }
+    if (sigpipe_count != 1) {
+        THROW_ERROR("SIGPIPE is not raised");
+    }
+    return 0;
+}
+
 // ============================================================================
 // Test suite
 // ============================================================================
@@ -325,6 +359,7 @@ static test_case_t test_cases[] = {
     TEST_CASE(test_poll_no_timeout),
     TEST_CASE(test_epoll_no_timeout),
     TEST_CASE(test_select_read_write),
+    TEST_CASE(test_write_to_broken_pipe),
 };
 
 int main(int argc, const char *argv[]) {
@@ -1,6 +1,9 @@
 #include <sys/types.h>
+#include <sys/socket.h>
+#include <dirent.h>
 #include <fcntl.h>
 #include <limits.h>
+#include <stdio.h>
 #include <stdlib.h>
 #include <errno.h>
 #include "test_fs.h"
@@ -10,6 +13,21 @@
 // ============================================================================
 
 const char **g_argv;
+extern char **environ;
+
+static ssize_t read_from_procfs(const char *proc_inode, char *buf, size_t buf_size) {
+    int fd = open(proc_inode, O_RDONLY);
+    if (fd < 0) {
+        THROW_ERROR("failed to open file: %s", proc_inode);
+    }
+    ssize_t len = read(fd, buf, buf_size - 1);
+    close(fd);
+    if (len < 0) {
+        THROW_ERROR("failed to read from %s", proc_inode);
+    }
+    buf[len] = '\0';
+    return len;
+}
 
 static int test_readlink_from_procfs(const char *proc_inode, char *buf, int buf_size,
                                      const char *expected_target) {
@@ -68,19 +86,209 @@ static int test_readlink_from_proc_self_cwd() {
 
 static int test_read_from_proc_self_cmdline() {
     char absolute_path[PATH_MAX] = { 0 };
+    char cmdline[PATH_MAX] = { 0 };
     const char *proc_cmdline = "/proc/self/cmdline";
 
     int n = snprintf(absolute_path, sizeof(absolute_path), "/bin/%s", *g_argv);
     if (n < 0) {
         THROW_ERROR("failed to call snprintf");
     }
-    if (fs_check_file_content(proc_cmdline, absolute_path) < 0) {
+    // The arguments are separated and terminated by null bytes
+    ssize_t len = read_from_procfs(proc_cmdline, cmdline, sizeof(cmdline));
+    if (len != n + 1 || strcmp(cmdline, absolute_path) != 0) {
         THROW_ERROR("failed to check result in %s", proc_cmdline);
     }
 
     return 0;
 }
 
+static int test_read_from_proc_self_environ() {
+    char expected_environ[4096] = { 0 };
+    char proc_environ_buf[4096] = { 0 };
+    const char *proc_environ = "/proc/self/environ";
+
+    size_t expected_len = 0;
+    for (char **env = environ; *env != NULL; env++) {
+        size_t env_len = strlen(*env) + 1;
+        if (expected_len + env_len >= sizeof(expected_environ)) {
+            THROW_ERROR("the environment variables are too long");
+        }
+        memcpy(expected_environ + expected_len, *env, env_len);
+        expected_len += env_len;
+    }
+    ssize_t len = read_from_procfs(proc_environ, proc_environ_buf, sizeof(proc_environ_buf));
+    if (len != expected_len || memcmp(proc_environ_buf, expected_environ, len) != 0) {
+        THROW_ERROR("failed to check result in %s", proc_environ);
+    }
+
+    return 0;
+}
+
+static int test_read_from_proc_self_stat() {
+    char stat_buf[1024] = { 0 };
+    char comm_buf[64] = { 0 };
+    char comm[64] = { 0 };
+    char state;
+    int pid, ppid, pgrp, session;
+
+    if (read_from_procfs("/proc/self/comm", comm_buf, sizeof(comm_buf)) <= 0) {
+        THROW_ERROR("failed to read the comm");
+    }
+    comm_buf[strcspn(comm_buf, "\n")] = '\0';
+    if (read_from_procfs("/proc/self/stat", stat_buf, sizeof(stat_buf)) < 0) {
+        THROW_ERROR("failed to read the stat");
+    }
+    if (sscanf(stat_buf, "%d (%63[^)]) %c %d %d %d", &pid, comm, &state, &ppid, &pgrp,
+               &session) != 6) {
+        THROW_ERROR("failed to parse the stat");
+    }
+    if (pid != getpid() || strcmp(comm, comm_buf) != 0 || state != 'R' ||
+            ppid != getppid() || pgrp != getpgrp()) {
+        THROW_ERROR("the stat is not expected");
+    }
+    // Sessions are not supported
+    if (session != 0) {
+        THROW_ERROR("the session in the stat is not expected");
+    }
+
+    return 0;
+}
+
+static int test_read_from_proc_self_status() {
+    char status[1024] = { 0 };
+    char expected_pid[32] = { 0 };
+    char expected_ppid[32] = { 0 };
+
+    if (read_from_procfs("/proc/self/status", status, sizeof(status)) < 0) {
+        THROW_ERROR("failed to read the status");
+    }
+    snprintf(expected_pid, sizeof(expected_pid), "\nPid:\t%d\n", getpid());
+    snprintf(expected_ppid, sizeof(expected_ppid), "\nPPid:\t%d\n", getppid());
+    if (strncmp(status, "Name:\t", strlen("Name:\t")) != 0 ||
+            strstr(status, expected_pid) == NULL || strstr(status, expected_ppid) == NULL ||
+            strstr(status, "\nVmSize:\t") == NULL || strstr(status, "\nThreads:\t1\n") == NULL) {
+        THROW_ERROR("the status is not expected");
+    }
+
+    return 0;
+}
+
+static int test_read_from_proc_self_fd() {
+    char link_buf[PATH_MAX] = { 0 };
+    char proc_fd[64] = { 0 };
+    char fd_name[16] = { 0 };
+    const char *file_path = "/root/test_procfs_fd.txt";
+    int found = 0;
+
+    int fd = open(file_path, O_RDWR | O_CREAT | O_TRUNC, 00666);
+    if (fd < 0) {
+        THROW_ERROR("failed to open a file to test");
+    }
+    snprintf(proc_fd, sizeof(proc_fd), "/proc/self/fd/%d", fd);
+    if (test_readlink_from_procfs(proc_fd, link_buf, PATH_MAX, file_path) < 0) {
+        THROW_ERROR("failed to call test_readlink_from_procfs");
+    }
+
+    snprintf(fd_name, sizeof(fd_name), "%d", fd);
+    DIR *dir = opendir("/proc/self/fd");
+    if (dir == NULL) {
+        THROW_ERROR("failed to open /proc/self/fd");
+    }
+    struct dirent *entry;
+    while ((entry = readdir(dir)) != NULL) {
+        if (strcmp(entry->d_name, fd_name) == 0) {
+            found = 1;
+        }
+    }
+    closedir(dir);
+    if (!found) {
+        THROW_ERROR("failed to find the fd in /proc/self/fd");
+    }
+
+    close(fd);
+    if (unlink(file_path) < 0) {
+        THROW_ERROR("failed to unlink the file");
+    }
+    if (access(proc_fd, F_OK) == 0 || errno != ENOENT) {
+        THROW_ERROR("the closed fd is still in /proc/self/fd");
+    }
+
+    return 0;
+}
+
+static int readlink_of_fd(int fd, char *buf, int buf_size) {
+    char proc_fd[64] = { 0 };
+    snprintf(proc_fd, sizeof(proc_fd), "/proc/self/fd/%d", fd);
+    int n = readlink(proc_fd, buf, buf_size - 1);
+    if (n < 0) {
+        THROW_ERROR("failed to readlink from %s", proc_fd);
+    }
+    buf[n] = '\0';
+    return 0;
+}
+
+static int test_readlink_from_proc_self_fd_of_pipes_and_sockets() {
+    char links[6][64] = { 0 };
+    int pipe_fds[2][2];
+    int socket_fds[2];
+
+    if (pipe(pipe_fds[0]) < 0 || pipe(pipe_fds[1]) < 0) {
+        THROW_ERROR("failed to create pipes");
+    }
+    if (socketpair(AF_UNIX, SOCK_STREAM, 0, socket_fds) < 0) {
+        THROW_ERROR("failed to create a socket pair");
+    }
+    int fds[6] = { pipe_fds[0][0], pipe_fds[0][1], pipe_fds[1][0], pipe_fds[1][1], socket_fds[0],
+                   socket_fds[1]
+                 };
+    for (int i = 0; i < 6; i++) {
+        if (readlink_of_fd(fds[i], links[i], sizeof(links[i])) < 0) {
+            THROW_ERROR("failed to readlink the fd");
+        }
+    }
+
+    for (int i = 0; i < 4; i++) {
+        if (strncmp(links[i], "pipe:[", strlen("pipe:[")) != 0) {
+            THROW_ERROR("the link of a pipe is wrong: %s", links[i]);
+        }
+    }
+    // Both ends of a pipe share the ID, while different pipes do not
+    if (strcmp(links[0], links[1]) != 0 || strcmp(links[2], links[3]) != 0 ||
+            strcmp(links[0], links[2]) == 0) {
+        THROW_ERROR("the links of pipes are wrong");
+    }
+    for (int i = 4; i < 6; i++) {
+        if (strncmp(links[i], "socket:[", strlen("socket:[")) != 0) {
+            THROW_ERROR("the link of a socket is wrong: %s", links[i]);
+        }
+    }
+    if (strcmp(links[4], links[5]) == 0) {
+        THROW_ERROR("different sockets have the same link");
+    }
+
+    for (int i = 0; i < 6; i++) {
+        close(fds[i]);
+    }
+    return 0;
+}
+
+static int test_read_from_proc_uptime() {
+    char uptime_buf[128] = { 0 };
+    double uptime, idle_time;
+
+    if (read_from_procfs("/proc/uptime", uptime_buf, sizeof(uptime_buf)) < 0) {
+        THROW_ERROR("failed to read the uptime");
+    }
+    if (sscanf(uptime_buf, "%lf %lf", &uptime, &idle_time) != 2) {
+        THROW_ERROR("failed to parse the uptime");
+    }
+    if (uptime <= 0 || idle_time < 0) {
+        THROW_ERROR("the uptime is not expected");
+    }
+
+    return 0;
+}
+
 static int test_read_from_proc_meminfo() {
     char meminfo[1024] = { 0 };
     const char *proc_meminfo = "/proc/meminfo";
@@ -127,6 +335,12 @@ static test_case_t test_cases[] = {
     TEST_CASE(test_readlink_from_proc_self_exe),
     TEST_CASE(test_readlink_from_proc_self_cwd),
     TEST_CASE(test_read_from_proc_self_cmdline),
+    TEST_CASE(test_read_from_proc_self_environ),
+    TEST_CASE(test_read_from_proc_self_stat),
+    TEST_CASE(test_read_from_proc_self_status),
+    TEST_CASE(test_read_from_proc_self_fd),
+    TEST_CASE(test_readlink_from_proc_self_fd_of_pipes_and_sockets),
+    TEST_CASE(test_read_from_proc_uptime),
     TEST_CASE(test_read_from_proc_meminfo),
     TEST_CASE(test_read_from_proc_cpuinfo),
 };
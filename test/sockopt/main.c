@@ -0,0 +1,272 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <poll.h>
+#include <string.h>
+#include <unistd.h>
+#include <arpa/inet.h>
+#include <netinet/in.h>
+#include <netinet/tcp.h>
+#include <sys/socket.h>
+
+#include "test.h"
+
+#define LISTEN_PORT     8810
+// No socket listens on this port
+#define CLOSED_PORT     8811
+
+// ============================================================================
+// Helper functions
+// ============================================================================
+
+static void init_loopback_addr(struct sockaddr_in *addr, int port) {
+    memset(addr, 0, sizeof(*addr));
+    addr->sin_family = AF_INET;
+    addr->sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+    addr->sin_port = htons(port);
+}
+
+static int create_listener(int port) {
+    struct sockaddr_in addr;
+    int reuse = 1;
+
+    int listen_fd = socket(AF_INET, SOCK_STREAM, 0);
+    if (listen_fd < 0) {
+        THROW_ERROR("failed to create socket");
+    }
+    if (setsockopt(listen_fd, SOL_SOCKET, SO_REUSEADDR, &reuse, sizeof(reuse)) < 0) {
+        close(listen_fd);
+        THROW_ERROR("failed to set SO_REUSEADDR");
+    }
+    init_loopback_addr(&addr, port);
+    if (bind(listen_fd, (struct sockaddr *)&addr, sizeof(addr)) < 0) {
+        close(listen_fd);
+        THROW_ERROR("failed to bind");
+    }
+    if (listen(listen_fd, 5) < 0) {
+        close(listen_fd);
+        THROW_ERROR("failed to listen");
+    }
+    return listen_fd;
+}
+
+// Connect a pair of sockets over the loopback interface
+static int connect_pair(int *client_fd, int *server_fd) {
+    struct sockaddr_in addr;
+
+    int listen_fd = create_listener(LISTEN_PORT);
+    if (listen_fd < 0) {
+        return -1;
+    }
+    *client_fd = socket(AF_INET, SOCK_STREAM, 0);
+    if (*client_fd < 0) {
+        close(listen_fd);
+        THROW_ERROR("failed to create socket");
+    }
+    init_loopback_addr(&addr, LISTEN_PORT);
+    if (connect(*client_fd, (struct sockaddr *)&addr, sizeof(addr)) < 0) {
+        close_files(2, listen_fd, *client_fd);
+        THROW_ERROR("failed to connect");
+    }
+    *server_fd = accept(listen_fd, NULL, NULL);
+    close(listen_fd);
+    if (*server_fd < 0) {
+        close(*client_fd);
+        THROW_ERROR("failed to accept");
+    }
+    return 0;
+}
+
+static int get_int_opt(int fd, int level, int optname, int *val) {
+    socklen_t len = sizeof(*val);
+    if (getsockopt(fd, level, optname, val, &len) < 0) {
+        THROW_ERROR("failed to call getsockopt");
+    }
+    if (len != sizeof(*val)) {
+        THROW_ERROR("the length of the option is wrong");
+    }
+    return 0;
+}
+
+// ============================================================================
+// Test cases for socket options
+// ============================================================================
+
+static int __test_buf_size(int optname) {
+    int val = 32 * 1024;
+    int actual_val;
+    int ret = 0;
+
+    int fd = socket(AF_INET, SOCK_STREAM, 0);
+    if (fd < 0) {
+        THROW_ERROR("failed to create socket");
+    }
+    if (setsockopt(fd, SOL_SOCKET, optname, &val, sizeof(val)) < 0) {
+        close(fd);
+        THROW_ERROR("failed to set the buffer size");
+    }
+    if (get_int_opt(fd, SOL_SOCKET, optname, &actual_val) < 0) {
+        close(fd);
+        return -1;
+    }
+    // Just like Linux, the size is doubled for bookkeeping overhead
+    if (actual_val != val * 2) {
+        ret = -1;
+    }
+    close(fd);
+    if (ret < 0) {
+        THROW_ERROR("the buffer size is not resized");
+    }
+    return 0;
+}
+
+static int test_rcvbuf() {
+    return __test_buf_size(SO_RCVBUF);
+}
+
+static int test_sndbuf() {
+    return __test_buf_size(SO_SNDBUF);
+}
+
+static int test_so_error() {
+    struct sockaddr_in addr;
+    struct pollfd pfd;
+    int err;
+
+    int fd = socket(AF_INET, SOCK_STREAM | SOCK_NONBLOCK, 0);
+    if (fd < 0) {
+        THROW_ERROR("failed to create socket");
+    }
+    if (get_int_opt(fd, SOL_SOCKET, SO_ERROR, &err) < 0 || err != 0) {
+        close(fd);
+        THROW_ERROR("a new socket should have no error");
+    }
+
+    init_loopback_addr(&addr, CLOSED_PORT);
+    if (connect(fd, (struct sockaddr *)&addr, sizeof(addr)) == 0) {
+        close(fd);
+        THROW_ERROR("connect should fail");
+    }
+    if (errno == EINPROGRESS) {
+        pfd.fd = fd;
+        pfd.events = POLLOUT;
+        if (poll(&pfd, 1, 5000) != 1) {
+            close(fd);
+            THROW_ERROR("the failed connect is not reported by poll");
+        }
+        if (get_int_opt(fd, SOL_SOCKET, SO_ERROR, &err) < 0 || err != ECONNREFUSED) {
+            close(fd);
+            THROW_ERROR("SO_ERROR should be ECONNREFUSED");
+        }
+    } else if (errno != ECONNREFUSED) {
+        close(fd);
+        THROW_ERROR("connect should be refused");
+    }
+
+    // The error is cleared once read
+    if (get_int_opt(fd, SOL_SOCKET, SO_ERROR, &err) < 0 || err != 0) {
+        close(fd);
+        THROW_ERROR("the error should be cleared");
+    }
+    close(fd);
+    return 0;
+}
+
+static int test_so_linger() {
+    struct linger linger = { .l_onoff = 1, .l_linger = 5 };
+    struct linger actual_linger;
+    socklen_t len = sizeof(actual_linger);
+
+    int fd = socket(AF_INET, SOCK_STREAM, 0);
+    if (fd < 0) {
+        THROW_ERROR("failed to create socket");
+    }
+    if (setsockopt(fd, SOL_SOCKET, SO_LINGER, &linger, sizeof(linger)) < 0) {
+        close(fd);
+        THROW_ERROR("failed to set SO_LINGER");
+    }
+    if (getsockopt(fd, SOL_SOCKET, SO_LINGER, &actual_linger, &len) < 0) {
+        close(fd);
+        THROW_ERROR("failed to get SO_LINGER");
+    }
+    close(fd);
+    if (len != sizeof(actual_linger) || actual_linger.l_onoff == 0 ||
+            actual_linger.l_linger != linger.l_linger) {
+        THROW_ERROR("SO_LINGER is not set");
+    }
+    return 0;
+}
+
+static int test_tcp_nodelay() {
+    int client_fd, server_fd;
+    int on = 1, val;
+
+    if (connect_pair(&client_fd, &server_fd) < 0) {
+        return -1;
+    }
+    if (get_int_opt(client_fd, IPPROTO_TCP, TCP_NODELAY, &val) < 0 || val != 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("TCP_NODELAY should be off by default");
+    }
+    if (setsockopt(client_fd, IPPROTO_TCP, TCP_NODELAY, &on, sizeof(on)) < 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to set TCP_NODELAY");
+    }
+    if (get_int_opt(client_fd, IPPROTO_TCP, TCP_NODELAY, &val) < 0 || val == 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("TCP_NODELAY is not set");
+    }
+    close_files(2, client_fd, server_fd);
+    return 0;
+}
+
+static int test_shutdown_wr() {
+    const char msg[] = "after shutdown";
+    char buf[sizeof(msg)];
+    int client_fd, server_fd;
+
+    if (connect_pair(&client_fd, &server_fd) < 0) {
+        return -1;
+    }
+    if (shutdown(client_fd, SHUT_WR) < 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to shutdown");
+    }
+    // The peer sees EOF
+    if (read(server_fd, buf, sizeof(buf)) != 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("the peer should see EOF");
+    }
+    // Writing to the shut down direction fails
+    if (send(client_fd, msg, sizeof(msg), MSG_NOSIGNAL) >= 0 || errno != EPIPE) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("writing after SHUT_WR should fail with EPIPE");
+    }
+    // The other direction still works
+    if (write(server_fd, msg, sizeof(msg)) != sizeof(msg)) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to write to the half-closed socket");
+    }
+    if (read(client_fd, buf, sizeof(buf)) != sizeof(msg) || strcmp(buf, msg) != 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to read from the half-closed socket");
+    }
+    close_files(2, client_fd, server_fd);
+    return 0;
+}
+
+// ============================================================================
+// Test suite
+// ============================================================================
+
+static test_case_t test_cases[] = {
+    TEST_CASE(test_rcvbuf),
+    TEST_CASE(test_sndbuf),
+    TEST_CASE(test_so_error),
+    TEST_CASE(test_so_linger),
+    TEST_CASE(test_tcp_nodelay),
+    TEST_CASE(test_shutdown_wr),
+};
+
+int main() {
+    return test_suite_run(test_cases, ARRAY_SIZE(test_cases));
+}
@@ -0,0 +1,416 @@
+#include <errno.h>
+#include <string.h>
+#include <time.h>
+#include <unistd.h>
+#include <arpa/inet.h>
+#include <netinet/in.h>
+#include <netinet/tcp.h>
+#include <sys/socket.h>
+#include <sys/time.h>
+
+#include "test.h"
+
+#define LISTEN_PORT     8820
+#define ECHO_MSG        "echo msg for tcp_loopback test"
+// The timeout of SO_RCVTIMEO and SO_SNDTIMEO in milliseconds
+#define TIMEOUT_MS      100
+
+// ============================================================================
+// Helper functions
+// ============================================================================
+
+static void init_loopback_addr(struct sockaddr_in *addr, int port) {
+    memset(addr, 0, sizeof(*addr));
+    addr->sin_family = AF_INET;
+    addr->sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+    addr->sin_port = htons(port);
+}
+
+static int create_listener_with(int port, int backlog, int optname) {
+    struct sockaddr_in addr;
+    int reuse = 1;
+
+    int listen_fd = socket(AF_INET, SOCK_STREAM, 0);
+    if (listen_fd < 0) {
+        THROW_ERROR("failed to create socket");
+    }
+    if (setsockopt(listen_fd, SOL_SOCKET, optname, &reuse, sizeof(reuse)) < 0) {
+        close(listen_fd);
+        THROW_ERROR("failed to set SO_REUSEADDR or SO_REUSEPORT");
+    }
+    init_loopback_addr(&addr, port);
+    if (bind(listen_fd, (struct sockaddr *)&addr, sizeof(addr)) < 0) {
+        close(listen_fd);
+        THROW_ERROR("failed to bind");
+    }
+    if (listen(listen_fd, backlog) < 0) {
+        close(listen_fd);
+        THROW_ERROR("failed to listen");
+    }
+    return listen_fd;
+}
+
+static int create_listener(int port, int backlog) {
+    return create_listener_with(port, backlog, SO_REUSEADDR);
+}
+
+static int connect_to(int port) {
+    struct sockaddr_in addr;
+
+    int fd = socket(AF_INET, SOCK_STREAM, 0);
+    if (fd < 0) {
+        THROW_ERROR("failed to create socket");
+    }
+    init_loopback_addr(&addr, port);
+    if (connect(fd, (struct sockaddr *)&addr, sizeof(addr)) < 0) {
+        close(fd);
+        THROW_ERROR("failed to connect");
+    }
+    return fd;
+}
+
+// Connect a pair of sockets over the loopback interface
+static int connect_pair(int *client_fd, int *server_fd) {
+    int listen_fd = create_listener(LISTEN_PORT, 5);
+    if (listen_fd < 0) {
+        return -1;
+    }
+    *client_fd = connect_to(LISTEN_PORT);
+    if (*client_fd < 0) {
+        close(listen_fd);
+        return -1;
+    }
+    *server_fd = accept(listen_fd, NULL, NULL);
+    close(listen_fd);
+    if (*server_fd < 0) {
+        close(*client_fd);
+        THROW_ERROR("failed to accept");
+    }
+    return 0;
+}
+
+static int set_timeout(int fd, int optname, int timeout_ms) {
+    struct timeval tv = {
+        .tv_sec = timeout_ms / 1000,
+        .tv_usec = (timeout_ms % 1000) * 1000,
+    };
+    return setsockopt(fd, SOL_SOCKET, optname, &tv, sizeof(tv));
+}
+
+static long elapsed_ms(struct timespec *start) {
+    struct timespec end;
+    clock_gettime(CLOCK_MONOTONIC, &end);
+    return (end.tv_sec - start->tv_sec) * 1000 +
+           (end.tv_nsec - start->tv_nsec) / 1000000;
+}
+
+// ============================================================================
+// Test cases
+// ============================================================================
+
+static int test_echo() {
+    int client_fd, server_fd;
+    char buf[sizeof(ECHO_MSG)];
+
+    if (connect_pair(&client_fd, &server_fd) < 0) {
+        return -1;
+    }
+    if (write(client_fd, ECHO_MSG, sizeof(ECHO_MSG)) != sizeof(ECHO_MSG)) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to write");
+    }
+    if (read(server_fd, buf, sizeof(buf)) != sizeof(buf) ||
+            strcmp(buf, ECHO_MSG) != 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to read the message");
+    }
+    if (write(server_fd, buf, sizeof(buf)) != sizeof(buf)) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to echo");
+    }
+    memset(buf, 0, sizeof(buf));
+    if (read(client_fd, buf, sizeof(buf)) != sizeof(buf) ||
+            strcmp(buf, ECHO_MSG) != 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to read the echoed message");
+    }
+
+    // Closing one end gives EOF to the other end
+    close(client_fd);
+    if (read(server_fd, buf, sizeof(buf)) != 0) {
+        close(server_fd);
+        THROW_ERROR("failed to read EOF");
+    }
+    close(server_fd);
+    return 0;
+}
+
+static int test_addrs() {
+    int client_fd, server_fd;
+    struct sockaddr_in client_addr, peer_addr;
+    socklen_t len;
+
+    if (connect_pair(&client_fd, &server_fd) < 0) {
+        return -1;
+    }
+    len = sizeof(client_addr);
+    if (getsockname(client_fd, (struct sockaddr *)&client_addr, &len) < 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to getsockname");
+    }
+    len = sizeof(peer_addr);
+    if (getpeername(server_fd, (struct sockaddr *)&peer_addr, &len) < 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to getpeername");
+    }
+    close_files(2, client_fd, server_fd);
+
+    if (client_addr.sin_port == 0 || client_addr.sin_port != peer_addr.sin_port ||
+            client_addr.sin_addr.s_addr != peer_addr.sin_addr.s_addr) {
+        THROW_ERROR("the addresses of the two ends mismatch");
+    }
+    return 0;
+}
+
+// The accepted socket of an in-enclave connection takes the common options
+static int test_accepted_sockopts() {
+    int client_fd, server_fd;
+    int nodelay = 1;
+
+    if (connect_pair(&client_fd, &server_fd) < 0) {
+        return -1;
+    }
+    if (setsockopt(server_fd, IPPROTO_TCP, TCP_NODELAY, &nodelay, sizeof(nodelay)) < 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to set TCP_NODELAY");
+    }
+    close_files(2, client_fd, server_fd);
+    return 0;
+}
+
+static int test_recv_timeout() {
+    int client_fd, server_fd;
+    char buf[16];
+    struct timespec start;
+
+    if (connect_pair(&client_fd, &server_fd) < 0) {
+        return -1;
+    }
+    if (set_timeout(server_fd, SO_RCVTIMEO, TIMEOUT_MS) < 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to set SO_RCVTIMEO");
+    }
+
+    clock_gettime(CLOCK_MONOTONIC, &start);
+    ssize_t ret = recv(server_fd, buf, sizeof(buf), 0);
+    int saved_errno = errno;
+    long elapsed = elapsed_ms(&start);
+    close_files(2, client_fd, server_fd);
+
+    if (ret >= 0 || saved_errno != EAGAIN) {
+        THROW_ERROR("recv should time out with EAGAIN");
+    }
+    if (elapsed < TIMEOUT_MS) {
+        THROW_ERROR("recv timed out too early");
+    }
+    return 0;
+}
+
+static int test_send_timeout() {
+    int client_fd, server_fd;
+    char buf[4096];
+    struct timespec start;
+
+    if (connect_pair(&client_fd, &server_fd) < 0) {
+        return -1;
+    }
+    if (set_timeout(client_fd, SO_SNDTIMEO, TIMEOUT_MS) < 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to set SO_SNDTIMEO");
+    }
+
+    // Send until the buffer is full as the peer never receives
+    memset(buf, 'a', sizeof(buf));
+    clock_gettime(CLOCK_MONOTONIC, &start);
+    ssize_t ret;
+    int saved_errno;
+    do {
+        ret = send(client_fd, buf, sizeof(buf), 0);
+        saved_errno = errno;
+    } while (ret == sizeof(buf));
+    long elapsed = elapsed_ms(&start);
+    close_files(2, client_fd, server_fd);
+
+    // A partially sent buffer is reported as is
+    if (ret < 0 && saved_errno != EAGAIN) {
+        THROW_ERROR("send should time out with EAGAIN");
+    }
+    if (elapsed < TIMEOUT_MS) {
+        THROW_ERROR("send timed out too early");
+    }
+    return 0;
+}
+
+static int test_accept_timeout() {
+    struct timespec start;
+    int listen_fd = create_listener(LISTEN_PORT, 5);
+    if (listen_fd < 0) {
+        return -1;
+    }
+    if (set_timeout(listen_fd, SO_RCVTIMEO, TIMEOUT_MS) < 0) {
+        close(listen_fd);
+        THROW_ERROR("failed to set SO_RCVTIMEO");
+    }
+
+    clock_gettime(CLOCK_MONOTONIC, &start);
+    int ret = accept(listen_fd, NULL, NULL);
+    int saved_errno = errno;
+    long elapsed = elapsed_ms(&start);
+    close(listen_fd);
+
+    if (ret >= 0 || saved_errno != EAGAIN) {
+        THROW_ERROR("accept should time out with EAGAIN");
+    }
+    if (elapsed < TIMEOUT_MS) {
+        THROW_ERROR("accept timed out too early");
+    }
+    return 0;
+}
+
+// A harmless control message, e.g., SO_TIMESTAMP, is ignored by a stream socket
+static int test_sendmsg_with_timestamp_cmsg() {
+    int client_fd, server_fd;
+    char buf[sizeof(ECHO_MSG)];
+    char control[CMSG_SPACE(sizeof(struct timeval))];
+    struct iovec iov = {
+        .iov_base = ECHO_MSG,
+        .iov_len = sizeof(ECHO_MSG),
+    };
+    struct msghdr msg = {
+        .msg_iov = &iov,
+        .msg_iovlen = 1,
+        .msg_control = control,
+        .msg_controllen = sizeof(control),
+    };
+
+    memset(control, 0, sizeof(control));
+    struct cmsghdr *cmsg = CMSG_FIRSTHDR(&msg);
+    cmsg->cmsg_level = SOL_SOCKET;
+    cmsg->cmsg_type = SO_TIMESTAMP;
+    cmsg->cmsg_len = CMSG_LEN(sizeof(struct timeval));
+
+    if (connect_pair(&client_fd, &server_fd) < 0) {
+        return -1;
+    }
+    if (sendmsg(client_fd, &msg, 0) != sizeof(ECHO_MSG)) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to sendmsg with a timestamp control message");
+    }
+    if (read(server_fd, buf, sizeof(buf)) != sizeof(buf) ||
+            strcmp(buf, ECHO_MSG) != 0) {
+        close_files(2, client_fd, server_fd);
+        THROW_ERROR("failed to read the message");
+    }
+    close_files(2, client_fd, server_fd);
+    return 0;
+}
+
+static int test_backlog() {
+    // Just like Linux, a backlog of 1 allows 2 pending connections
+    const int backlog = 1;
+    const int num_conns = backlog + 1;
+    int client_fds[num_conns];
+    int listen_fd = create_listener(LISTEN_PORT, backlog);
+    if (listen_fd < 0) {
+        return -1;
+    }
+
+    for (int i = 0; i < num_conns; i++) {
+        client_fds[i] = connect_to(LISTEN_PORT);
+        if (client_fds[i] < 0) {
+            for (int j = 0; j < i; j++) {
+                close(client_fds[j]);
+            }
+            close(listen_fd);
+            THROW_ERROR("failed to connect within the backlog");
+        }
+    }
+    for (int i = 0; i < num_conns; i++) {
+        int server_fd = accept(listen_fd, NULL, NULL);
+        if (server_fd < 0) {
+            for (int j = 0; j < num_conns; j++) {
+                close(client_fds[j]);
+            }
+            close(listen_fd);
+            THROW_ERROR("failed to accept");
+        }
+        close(server_fd);
+    }
+
+    for (int i = 0; i < num_conns; i++) {
+        close(client_fds[i]);
+    }
+    close(listen_fd);
+    return 0;
+}
+
+// The connections to a SO_REUSEPORT group are spread among its listeners
+static int test_reuse_port_group() {
+    int listen_fds[2], client_fds[2];
+
+    listen_fds[0] = create_listener_with(LISTEN_PORT, 5, SO_REUSEPORT);
+    if (listen_fds[0] < 0) {
+        return -1;
+    }
+    listen_fds[1] = create_listener_with(LISTEN_PORT, 5, SO_REUSEPORT);
+    if (listen_fds[1] < 0) {
+        close(listen_fds[0]);
+        return -1;
+    }
+    client_fds[0] = connect_to(LISTEN_PORT);
+    if (client_fds[0] < 0) {
+        close_files(2, listen_fds[0], listen_fds[1]);
+        return -1;
+    }
+    client_fds[1] = connect_to(LISTEN_PORT);
+    if (client_fds[1] < 0) {
+        close_files(3, listen_fds[0], listen_fds[1], client_fds[0]);
+        return -1;
+    }
+
+    // Each listener gets one of the two connections
+    for (int i = 0; i < 2; i++) {
+        if (set_timeout(listen_fds[i], SO_RCVTIMEO, TIMEOUT_MS) < 0) {
+            close_files(4, listen_fds[0], listen_fds[1], client_fds[0], client_fds[1]);
+            THROW_ERROR("failed to set SO_RCVTIMEO");
+        }
+        int server_fd = accept(listen_fds[i], NULL, NULL);
+        if (server_fd < 0) {
+            close_files(4, listen_fds[0], listen_fds[1], client_fds[0], client_fds[1]);
+            THROW_ERROR("the connections are not spread among the listeners");
+        }
+        close(server_fd);
+    }
+    close_files(4, listen_fds[0], listen_fds[1], client_fds[0], client_fds[1]);
+    return 0;
+}
+
+// ============================================================================
+// Test suite main
+// ============================================================================
+
+static test_case_t test_cases[] = {
+    TEST_CASE(test_echo),
+    TEST_CASE(test_addrs),
+    TEST_CASE(test_accepted_sockopts),
+    TEST_CASE(test_recv_timeout),
+    TEST_CASE(test_send_timeout),
+    TEST_CASE(test_accept_timeout),
+    TEST_CASE(test_sendmsg_with_timestamp_cmsg),
+    TEST_CASE(test_backlog),
+    TEST_CASE(test_reuse_port_group),
+};
+
+int main(int argc, const char *argv[]) {
+    return test_suite_run(test_cases, ARRAY_SIZE(test_cases));
+}
@@ -1,7 +1,9 @@
+#define _GNU_SOURCE
 #include <sys/syscall.h>
 #include <sys/wait.h>
 #include <sys/socket.h>
 #include <sys/un.h>
+#include <sys/time.h>
 #include <poll.h>
 #include <unistd.h>
 #include <stdlib.h>
@@ -9,6 +11,7 @@
 #include <spawn.h>
 #include <string.h>
 #include <spawn.h>
+#include <errno.h>
 
 #include "test.h"
 
@@ -245,12 +248,159 @@ int test_getname() {
     return 0;
 }
 
+int test_connect_path(const char *sock_path) {
+    int sockets[2];
+    unlink(sock_path);
+    if (create_connected_sockets(sockets, (char *)sock_path) < 0) {
+        THROW_ERROR("failed to create connected sockets on %s", sock_path);
+    }
+
+    int ret = verify_connection(sockets[0], sockets[1]);
+    close(sockets[0]);
+    close(sockets[1]);
+    unlink(sock_path);
+    if (ret < 0) {
+        THROW_ERROR("failed to verify the connection on %s", sock_path);
+    }
+    return 0;
+}
+
+int test_connect_trusted_path() {
+    return test_connect_path("/tmp/unix_socket_trusted_path");
+}
+
+int test_connect_host_path() {
+    // A socket file under a HostFS mount is backed by a host socket
+    return test_connect_path("/host/unix_socket_host_path");
+}
+
+int expect_connect_refused(struct sockaddr_un *addr, socklen_t addr_len) {
+    int sock = socket(AF_UNIX, SOCK_STREAM, 0);
+    if (sock == -1) {
+        THROW_ERROR("failed to create a unix socket");
+    }
+
+    int ret = connect(sock, (struct sockaddr *)addr, addr_len);
+    int saved_errno = errno;
+    close(sock);
+    if (ret == 0 || (saved_errno != ECONNREFUSED && saved_errno != ENOENT)) {
+        THROW_ERROR("connecting to an unbound address should fail");
+    }
+    return 0;
+}
+
+int test_connect_unbound() {
+    struct sockaddr_un addr;
+    memset(&addr, 0, sizeof(struct sockaddr_un));
+    addr.sun_family = AF_UNIX;
+
+    // No socket is bound to the path, which is not under a HostFS mount
+    const char *path = "/tmp/unix_socket_unbound_path";
+    strcpy(addr.sun_path, path);
+    socklen_t addr_len = strlen(path) + sizeof(addr.sun_family);
+    if (expect_connect_refused(&addr, addr_len) < 0) {
+        THROW_ERROR("unexpected result of connecting to an unbound path");
+    }
+
+    // Abstract addresses must not be resolved by the host
+    const char *name = "unix_socket_unbound_abstract";
+    memset(addr.sun_path, 0, sizeof(addr.sun_path));
+    strcpy(addr.sun_path + 1, name);
+    addr_len = 1 + strlen(name) + sizeof(addr.sun_family);
+    if (expect_connect_refused(&addr, addr_len) < 0) {
+        THROW_ERROR("unexpected result of connecting to an unbound abstract address");
+    }
+    return 0;
+}
+
+int test_dgram_recv_timeout() {
+    int socks[2];
+    char buf[sizeof(ECHO_MSG)];
+    struct timeval timeout = {
+        .tv_sec = 0,
+        .tv_usec = 100 * 1000,
+    };
+
+    if (socketpair(AF_UNIX, SOCK_DGRAM, 0, socks) < 0) {
+        THROW_ERROR("socketpair failed");
+    }
+    if (setsockopt(socks[1], SOL_SOCKET, SO_RCVTIMEO, &timeout, sizeof(timeout)) < 0) {
+        close_files(2, socks[0], socks[1]);
+        THROW_ERROR("failed to set SO_RCVTIMEO");
+    }
+
+    int ret = recv(socks[1], buf, sizeof(buf), 0);
+    int saved_errno = errno;
+    close_files(2, socks[0], socks[1]);
+    if (ret >= 0 || saved_errno != EAGAIN) {
+        THROW_ERROR("recv should time out with EAGAIN");
+    }
+    return 0;
+}
+
+// Just like Linux, a process can send its own credentials
+int test_send_credentials() {
+    int socks[2];
+    char buf[sizeof(ECHO_MSG)];
+    char control[CMSG_SPACE(sizeof(struct ucred))];
+    struct iovec iov = {
+        .iov_base = ECHO_MSG,
+        .iov_len = sizeof(ECHO_MSG),
+    };
+    struct msghdr msg = {
+        .msg_iov = &iov,
+        .msg_iovlen = 1,
+        .msg_control = control,
+        .msg_controllen = sizeof(control),
+    };
+    struct ucred cred = {
+        .pid = getpid(),
+        .uid = getuid(),
+        .gid = getgid(),
+    };
+
+    memset(control, 0, sizeof(control));
+    struct cmsghdr *cmsg = CMSG_FIRSTHDR(&msg);
+    cmsg->cmsg_level = SOL_SOCKET;
+    cmsg->cmsg_type = SCM_CREDENTIALS;
+    cmsg->cmsg_len = CMSG_LEN(sizeof(cred));
+    memcpy(CMSG_DATA(cmsg), &cred, sizeof(cred));
+
+    if (socketpair(AF_UNIX, SOCK_DGRAM, 0, socks) < 0) {
+        THROW_ERROR("socketpair failed");
+    }
+    if (sendmsg(socks[0], &msg, 0) != sizeof(ECHO_MSG)) {
+        close_files(2, socks[0], socks[1]);
+        THROW_ERROR("failed to send the credentials");
+    }
+    if (recv(socks[1], buf, sizeof(buf), 0) != sizeof(buf) || strcmp(buf, ECHO_MSG) != 0) {
+        close_files(2, socks[0], socks[1]);
+        THROW_ERROR("failed to receive the message");
+    }
+
+    // The credentials of another process cannot be sent
+    cred.pid = getpid() + 1;
+    memcpy(CMSG_DATA(cmsg), &cred, sizeof(cred));
+    int ret = sendmsg(socks[0], &msg, 0);
+    int saved_errno = errno;
+    close_files(2, socks[0], socks[1]);
+    if (ret >= 0 || saved_errno != EPERM) {
+        THROW_ERROR("sending forged credentials should fail with EPERM");
+    }
+    return 0;
+}
+
 static test_case_t test_cases[] = {
     TEST_CASE(test_unix_socket_inter_process),
     TEST_CASE(test_socketpair_inter_process),
     TEST_CASE(test_multiple_socketpairs),
     TEST_CASE(test_poll),
     TEST_CASE(test_getname),
+    TEST_CASE(test_connect_trusted_path),
+    TEST_CASE(test_connect_host_path),
+    TEST_CASE(test_connect_unbound),
+    TEST_CASE(test_dgram_recv_timeout),
+    TEST_CASE(test_send_credentials),
 };
 
 int main(int argc, const char *argv[]) {
@@ -79,10 +79,7 @@ fn main() {
     );
 
     let occlum_conf_root_fs_mac = matches.value_of("fs_mac").unwrap();
-    debug!(
-        "Occlum config root FS MAC {:?}",
-        occlum_conf_root_fs_mac
-    );
+    debug!("Occlum config root FS MAC {:?}", occlum_conf_root_fs_mac);
 
     let enclave_config_file_path = matches.value_of("sdk_xml").unwrap();
     debug!(
@@ -172,11 +169,16 @@ fn main() {
         },
         entry_points: occlum_config.entry_points,
         env: occlum_config.env,
-        mount: gen_mount_config(occlum_conf_root_fs_mac.to_string()),
+        mount: gen_mount_config(occlum_conf_root_fs_mac.to_string(), &occlum_config.mount),
+        mount_policy: occlum_config.mount_policy,
+        network: occlum_config.network,
     };
     let internal_occlum_json_str =
         serde_json::to_string_pretty(&internal_occlum_json_config).unwrap();
-    debug!("The internal Occlum.json config:\n{:?}", internal_occlum_json_str);
+    debug!(
+        "The internal Occlum.json config:\n{:?}",
+        internal_occlum_json_str
+    );
 
     // Update the output file
     let mut enclave_config_file = File::create(enclave_config_file_path)
@@ -223,7 +225,10 @@ fn parse_memory_size(mem_str: &str) -> Result<usize, &str> {
     Ok(mem_val * unit_factor)
 }
 
-fn gen_mount_config(occlum_conf_root_fs_mac: String) -> serde_json::Value {
+fn gen_mount_config(
+    occlum_conf_root_fs_mac: String,
+    occlum_conf_mount: &serde_json::Value,
+) -> serde_json::Value {
     let mut internal_mount_config: serde_json::Value = json!({
         "mount": [
             {
@@ -288,8 +293,19 @@ fn gen_mount_config(occlum_conf_root_fs_mac: String) -> serde_json::Value {
     *internal_mount_config
         .pointer_mut("/mount/3/source")
         .unwrap() = serde_json::Value::String(tmp_run_source_path);
+    // The devices of DevFS can be configured by the user
+    if let Some(devfs_options) = occlum_conf_mount
+        .as_array()
+        .and_then(|mounts| mounts.iter().find(|m| m["type"] == "devfs"))
+        .and_then(|devfs| devfs.get("options"))
+    {
+        internal_mount_config["mount"][4]["options"] = devfs_options.to_owned();
+    }
 
-    debug!("internal Occlum.json mount config:\n{:?}", internal_mount_config);
+    debug!(
+        "internal Occlum.json mount config:\n{:?}",
+        internal_mount_config
+    );
 
     internal_mount_config["mount"].to_owned()
 }
@@ -302,6 +318,10 @@ struct OcclumConfiguration {
     env: serde_json::Value,
     metadata: OcclumMetadata,
     mount: serde_json::Value,
+    #[serde(default)]
+    mount_policy: serde_json::Value,
+    #[serde(default)]
+    network: serde_json::Value,
 }
 
 #[derive(Debug, PartialEq, Deserialize)]
@@ -361,4 +381,8 @@ struct InternalOcclumJson {
     entry_points: serde_json::Value,
     env: serde_json::Value,
     mount: serde_json::Value,
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    mount_policy: serde_json::Value,
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    network: serde_json::Value,
 }